@@ -0,0 +1,243 @@
+//! The summary shown before generating: how long the text is by several
+//! measures, how long it will take to say and what it will cost, and where
+//! it will be split into requests. Pure and cheap enough to run as the user
+//! types; the math is the same as `TTSService::estimate`'s.
+
+use crate::models::MODELS;
+use crate::settings::MIN_CHUNK_SIZE;
+use crate::tts::{self, estimate_cost, SINGLE_REQUEST_LIMIT, VALID_MODEL_IDS};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextAnalysis {
+    /// Unicode characters, which is what the API bills
+    pub characters: usize,
+    pub words: usize,
+    /// As the chunker splits them (see `tts::sentences`)
+    pub sentences: usize,
+    /// Requests the text takes at the current chunk size
+    pub chunks: usize,
+    pub estimated_duration_secs: f64,
+    /// For the model asked about
+    pub estimated_cost: f64,
+    /// For every model, in `models::MODELS` order
+    pub costs: Vec<ModelCost>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelCost {
+    pub model: &'static str,
+    pub estimated_cost: f64,
+}
+
+pub fn analyze(text: &str, model: &str, chunk_size: usize) -> Result<TextAnalysis, String> {
+    if !VALID_MODEL_IDS.contains(&model) {
+        return Err(format!("Invalid model: {}", model));
+    }
+    let characters = text.chars().count();
+    Ok(TextAnalysis {
+        characters,
+        words: count_words(text),
+        sentences: tts::sentences(text).iter().filter(|sentence| !sentence.trim().is_empty()).count(),
+        chunks: tts::chunk_count(text, chunk_size),
+        estimated_duration_secs: tts::estimate_duration_secs(characters),
+        estimated_cost: estimate_cost(characters as i64, model),
+        costs: MODELS
+            .iter()
+            .map(|info| ModelCost { model: info.id, estimated_cost: estimate_cost(characters as i64, info.id) })
+            .collect(),
+    })
+}
+
+/// Characters shown from each end of a chunk in its preview
+const PREVIEW_EDGE: usize = 40;
+
+/// One request's worth of text, as `preview_chunks` shows it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkPreview {
+    pub index: usize,
+    /// In characters from the start of the text, `end` not included
+    pub start: usize,
+    pub end: usize,
+    pub characters: usize,
+    /// The first and last `PREVIEW_EDGE` characters
+    pub head: String,
+    pub tail: String,
+}
+
+/// What to preview the split with, instead of the settings
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreviewOptions {
+    pub chunk_size: Option<usize>,
+}
+
+/// Where generating `text` would split it, chunk by chunk: the same
+/// `tts::request_texts` generation sends, so nothing here can disagree
+/// with what is paid for
+pub fn preview_chunks(text: &str, chunk_size: usize) -> Result<Vec<ChunkPreview>, String> {
+    if !(MIN_CHUNK_SIZE..=SINGLE_REQUEST_LIMIT).contains(&chunk_size) {
+        return Err(format!("Chunk size must be between {} and {}, got {}", MIN_CHUNK_SIZE, SINGLE_REQUEST_LIMIT, chunk_size));
+    }
+    if text.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut start = 0;
+    let previews = tts::request_texts(text, chunk_size)
+        .iter()
+        .enumerate()
+        .map(|(index, chunk)| {
+            let characters = chunk.chars().count();
+            let tail: Vec<char> = chunk.chars().rev().take(PREVIEW_EDGE).collect();
+            let preview = ChunkPreview {
+                index,
+                start,
+                end: start + characters,
+                characters,
+                head: chunk.chars().take(PREVIEW_EDGE).collect(),
+                tail: tail.into_iter().rev().collect(),
+            };
+            start += characters;
+            preview
+        })
+        .collect();
+    Ok(previews)
+}
+
+/// Runs of text between spaces, except that Chinese and Japanese, written
+/// without spaces, count a word per character. Punctuation on its own
+/// isn't a word.
+fn count_words(text: &str) -> usize {
+    let mut words = 0;
+    let mut in_word = false;
+    for c in text.chars() {
+        if is_cjk(c) {
+            words += 1;
+            in_word = false;
+        } else if c.is_whitespace() || c.is_ascii_punctuation() || is_cjk_punctuation(c) {
+            in_word = false;
+        } else if !in_word {
+            words += 1;
+            in_word = true;
+        }
+    }
+    words
+}
+
+/// Kana and CJK ideographs. Korean puts spaces between words, so Hangul
+/// counts like any other letter.
+fn is_cjk(c: char) -> bool {
+    matches!(c, '\u{3040}'..='\u{30FF}' | '\u{3400}'..='\u{4DBF}' | '\u{4E00}'..='\u{9FFF}' | '\u{F900}'..='\u{FAFF}')
+}
+
+/// "。", "、", brackets and full-width "！", "？" and friends
+fn is_cjk_punctuation(c: char) -> bool {
+    matches!(c, '\u{3000}'..='\u{303F}' | '\u{FF01}'..='\u{FF0F}' | '\u{FF1A}'..='\u{FF20}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tts::{TTSService, CHUNK_SIZE};
+
+    fn analyze(text: &str) -> TextAnalysis {
+        super::analyze(text, "tts-1", CHUNK_SIZE).unwrap()
+    }
+
+    #[test]
+    fn test_ascii() {
+        let analysis = analyze("Hello there. How are you? Fine, thanks!");
+        assert_eq!(analysis.characters, 39);
+        assert_eq!(analysis.words, 7);
+        assert_eq!(analysis.sentences, 3);
+        assert_eq!(analysis.chunks, 1);
+
+        let empty = analyze("   ");
+        assert_eq!((empty.words, empty.sentences, empty.chunks), (0, 0, 0));
+    }
+
+    #[test]
+    fn test_cjk_counts_characters_not_bytes() {
+        let text = "今日は晴れです。明日は雨でしょう。";
+        assert_eq!(text.len(), 51);
+        let analysis = analyze(text);
+        assert_eq!(analysis.characters, 17);
+        assert_eq!(analysis.words, 15);
+        assert_eq!(analysis.sentences, 2);
+        assert_eq!(analysis.estimated_cost, estimate_cost(17, "tts-1"));
+
+        // Korean is spaced like English
+        assert_eq!(analyze("안녕하세요 반갑습니다").words, 2);
+    }
+
+    #[test]
+    fn test_emoji() {
+        let analysis = analyze("Great job 👍 see you soon 🎉! Bye.");
+        assert_eq!(analysis.characters, 32);
+        assert_eq!(analysis.words, 8);
+        assert_eq!(analysis.sentences, 2);
+    }
+
+    #[test]
+    fn test_matches_the_service_estimate() {
+        let text = "A sentence that goes on for a while. ".repeat(300);
+        let analysis = analyze(&text);
+        let estimate = TTSService::estimate(&text);
+        // ASCII, so bytes and characters agree
+        assert_eq!(analysis.characters, estimate.character_count);
+        assert_eq!(analysis.chunks, estimate.chunk_count);
+        assert!(analysis.chunks > 1);
+        assert_eq!(analysis.sentences, 300);
+        assert_eq!(analysis.estimated_duration_secs, estimate.estimated_duration_secs);
+        assert_eq!(analysis.estimated_cost, estimate.estimated_cost_tts_1);
+
+        let costs: Vec<&str> = analysis.costs.iter().map(|cost| cost.model).collect();
+        assert_eq!(costs, VALID_MODEL_IDS);
+        assert_eq!(analysis.costs[1].estimated_cost, estimate.estimated_cost_tts_1_hd);
+
+        assert!(super::analyze("text", "tts-2", CHUNK_SIZE).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_preview_matches_the_requests_sent() {
+        let text = "Première phrase, un peu longue. Second one!\n".repeat(200);
+        let chunk_size = 1000;
+        let previews = preview_chunks(&text, chunk_size).unwrap();
+        assert!(previews.len() > 1);
+
+        let sent = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = sent.clone();
+        let mut server = mockito::Server::new_async().await;
+        let _mock = server
+            .mock("POST", "/v1/audio/speech")
+            .with_body_from_request(move |request| {
+                let body: serde_json::Value = serde_json::from_slice(request.body().unwrap()).unwrap();
+                recorded.lock().unwrap().push(body["input"].as_str().unwrap().to_string());
+                b"audio".to_vec()
+            })
+            .create_async()
+            .await;
+        let service = TTSService::new("sk-test", &server.url());
+        let settings = crate::settings::Settings { chunk_size, ..crate::settings::Settings::from_config(&crate::config::Config::default()) };
+        service.apply_settings(&settings);
+        service.generate_speech_chunked(&text, "nova", "tts-1").await.unwrap();
+
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent.len(), previews.len());
+        let characters: Vec<char> = text.chars().collect();
+        for (preview, input) in previews.iter().zip(sent.iter()) {
+            assert_eq!(characters[preview.start..preview.end].iter().collect::<String>(), *input);
+            assert_eq!(preview.characters, input.chars().count());
+            assert!(input.starts_with(&preview.head) && input.ends_with(&preview.tail));
+            assert_eq!(preview.head.chars().count(), PREVIEW_EDGE);
+        }
+        assert_eq!(previews.last().unwrap().end, characters.len());
+
+        assert_eq!(preview_chunks("Short.", chunk_size).unwrap().len(), 1);
+        assert!(preview_chunks("  ", chunk_size).unwrap().is_empty());
+        assert!(preview_chunks(&text, 10).is_err());
+    }
+}
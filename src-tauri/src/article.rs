@@ -0,0 +1,495 @@
+//! Articles from the web: download a page and keep its main text, the way
+//! reader modes do. Pages are fetched over http(s) only, size-capped, and by
+//! default never from localhost or private networks, redirects included.
+
+use crate::extract;
+use crate::tts::{GenerationEstimate, TTSService};
+use scraper::{ElementRef, Html, Node, Selector};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::Duration;
+use url::{Host, Url};
+
+/// Much shorter than speech requests; a page that takes longer isn't coming
+pub const FETCH_TIMEOUT: Duration = Duration::from_secs(20);
+pub const MAX_PAGE_BYTES: usize = 5 * 1024 * 1024;
+pub const MAX_REDIRECTS: usize = 5;
+/// Less text than this means we found navigation or a paywall, not an article
+const MIN_ARTICLE_CHARS: usize = 200;
+/// Paragraphs shorter than this are captions and bylines, not body text
+const MIN_PARAGRAPH_CHARS: usize = 25;
+
+/// Elements that never hold the article
+const BOILERPLATE_TAGS: &[&str] = &[
+    "aside", "button", "figure", "footer", "form", "header", "iframe", "nav", "noscript", "script", "style", "svg",
+];
+/// class/id fragments of page furniture around the article
+const BOILERPLATE_NAMES: &[&str] = &[
+    "banner", "breadcrumb", "comment", "cookie", "footer", "menu", "modal", "navbar", "newsletter", "popup",
+    "promo", "related", "share", "sidebar", "social", "subscribe", "advert",
+];
+const BOILERPLATE_ROLES: &[&str] = &["banner", "complementary", "contentinfo", "navigation", "search"];
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Article {
+    /// Where the page ended up after redirects
+    pub url: String,
+    pub title: Option<String>,
+    pub byline: Option<String>,
+    pub text: String,
+    pub estimate: GenerationEstimate,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArticleError {
+    InvalidUrl(String),
+    /// Local or private address, or a scheme other than http(s)
+    Blocked(String),
+    NotHtml(String),
+    TooLarge,
+    Http(u16),
+    Network(String),
+    /// The page had no article-sized text: paywalled, or built by JavaScript
+    NoArticle,
+}
+
+impl std::fmt::Display for ArticleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArticleError::InvalidUrl(e) => write!(f, "Invalid URL: {}", e),
+            ArticleError::Blocked(reason) => write!(f, "Refusing to fetch {}", reason),
+            ArticleError::NotHtml(content_type) => write!(
+                f,
+                "That link is {} rather than a web page; download it and open the file instead",
+                content_type
+            ),
+            ArticleError::TooLarge => write!(f, "Page is larger than {} MB", MAX_PAGE_BYTES / (1024 * 1024)),
+            ArticleError::Http(status) => write!(f, "The site responded with HTTP {}", status),
+            ArticleError::Network(e) => write!(f, "Failed to fetch page: {}", e),
+            ArticleError::NoArticle => write!(
+                f,
+                "No article text found; the page may be paywalled or need JavaScript. Copy the text instead"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ArticleError {}
+
+/// Fetches pages for `fetch_article`. It has its own client because the
+/// redirect checks are a client-wide policy, and the speech client has to
+/// keep talking to whatever base URL it was given, local ones included.
+pub struct ArticleFetcher {
+    client: reqwest::Client,
+    allow_private: bool,
+}
+
+impl ArticleFetcher {
+    /// `allow_private` lifts the localhost/private network block
+    pub fn new(allow_private: bool) -> Self {
+        let policy = reqwest::redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                return attempt.error(ArticleError::Network(format!("more than {} redirects", MAX_REDIRECTS)));
+            }
+            match check_url(attempt.url(), allow_private) {
+                Ok(()) => attempt.follow(),
+                Err(e) => attempt.error(e),
+            }
+        });
+        let client = reqwest::Client::builder()
+            .timeout(FETCH_TIMEOUT)
+            .redirect(policy)
+            .user_agent(concat!("tts-player/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .unwrap();
+        Self { client, allow_private }
+    }
+
+    pub async fn fetch(&self, url: &str) -> Result<Article, ArticleError> {
+        let url = Url::parse(url.trim()).map_err(|e| ArticleError::InvalidUrl(e.to_string()))?;
+        check_url(&url, self.allow_private)?;
+        if !self.allow_private {
+            check_resolved(&url).await?;
+        }
+
+        let mut response = self.client.get(url).send().await.map_err(request_error)?;
+        if !response.status().is_success() {
+            return Err(ArticleError::Http(response.status().as_u16()));
+        }
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("text/html")
+            .to_ascii_lowercase();
+        let mime = content_type.split(';').next().unwrap_or_default().trim();
+        if mime != "text/html" && mime != "application/xhtml+xml" {
+            return Err(ArticleError::NotHtml(mime.to_string()));
+        }
+        if response.content_length().is_some_and(|len| len as usize > MAX_PAGE_BYTES) {
+            return Err(ArticleError::TooLarge);
+        }
+
+        let final_url = response.url().to_string();
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(request_error)? {
+            if body.len() + chunk.len() > MAX_PAGE_BYTES {
+                return Err(ArticleError::TooLarge);
+            }
+            body.extend_from_slice(&chunk);
+        }
+
+        parse_article(&decode(&body, &content_type), &final_url)
+    }
+}
+
+/// Only http(s), and unless `allow_private`, no localhost or private,
+/// link-local or otherwise internal IP literals
+pub fn check_url(url: &Url, allow_private: bool) -> Result<(), ArticleError> {
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(ArticleError::Blocked(format!("{}: URLs (only http and https links)", url.scheme())));
+    }
+    let blocked = |host: &str| Err(ArticleError::Blocked(format!("{} (local and private addresses are off limits)", host)));
+    match url.host() {
+        None => Err(ArticleError::InvalidUrl("missing host".to_string())),
+        Some(_) if allow_private => Ok(()),
+        Some(Host::Domain(domain)) => {
+            let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+            if domain == "localhost" || domain.ends_with(".localhost") {
+                blocked(&domain)
+            } else {
+                Ok(())
+            }
+        }
+        Some(Host::Ipv4(ip)) if is_private(IpAddr::V4(ip)) => blocked(&ip.to_string()),
+        Some(Host::Ipv6(ip)) if is_private(IpAddr::V6(ip)) => blocked(&ip.to_string()),
+        Some(_) => Ok(()),
+    }
+}
+
+/// Catch names that resolve to internal addresses. Redirect hops are only
+/// checked by `check_url`, since the redirect policy can't do lookups.
+async fn check_resolved(url: &Url) -> Result<(), ArticleError> {
+    let Some(Host::Domain(domain)) = url.host() else {
+        return Ok(());
+    };
+    let port = url.port_or_known_default().unwrap_or(80);
+    let addrs = tokio::net::lookup_host((domain, port))
+        .await
+        .map_err(|e| ArticleError::Network(format!("{}: {}", domain, e)))?;
+    for addr in addrs {
+        if is_private(addr.ip()) {
+            return Err(ArticleError::Blocked(format!("{} (it resolves to a private address)", domain)));
+        }
+    }
+    Ok(())
+}
+
+fn is_private(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                // Carrier-grade NAT
+                || (a == 100 && (64..128).contains(&b))
+        }
+        IpAddr::V6(ip) => {
+            if let Some(v4) = ip.to_ipv4_mapped() {
+                return is_private(IpAddr::V4(v4));
+            }
+            let first = ip.segments()[0];
+            ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // Unique local (fc00::/7) and link-local (fe80::/10)
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80
+        }
+    }
+}
+
+/// Surface our own redirect refusals rather than reqwest's wrapper
+fn request_error(e: reqwest::Error) -> ArticleError {
+    let mut source = std::error::Error::source(&e);
+    while let Some(inner) = source {
+        if let Some(refusal) = inner.downcast_ref::<ArticleError>() {
+            return refusal.clone();
+        }
+        source = inner.source();
+    }
+    ArticleError::Network(e.to_string())
+}
+
+/// Decode with the charset from the header, then a `<meta charset>`, then UTF-8
+fn decode(body: &[u8], content_type: &str) -> String {
+    let header_label = content_type.split(';').find_map(|param| param.trim().strip_prefix("charset="));
+    let head = String::from_utf8_lossy(&body[..body.len().min(2048)]).to_ascii_lowercase();
+    let meta_label = head.find("charset=").map(|at| {
+        head[at + 8..]
+            .trim_start_matches(['"', '\''])
+            .split(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+            .next()
+            .unwrap_or_default()
+            .to_string()
+    });
+    let encoding = header_label
+        .or(meta_label.as_deref())
+        .and_then(|label| encoding_rs::Encoding::for_label(label.trim_matches('"').as_bytes()))
+        .unwrap_or(encoding_rs::UTF_8);
+    encoding.decode(body).0.into_owned()
+}
+
+/// Pick the main text out of `html`: paragraphs are scored by length and
+/// commas, their containers collect the scores, and the best container's
+/// text is kept, minus anything that looks like page furniture.
+pub fn parse_article(html: &str, url: &str) -> Result<Article, ArticleError> {
+    let doc = Html::parse_document(html);
+
+    let mut scores = HashMap::new();
+    for paragraph in doc.select(&selector("p, pre")) {
+        if in_boilerplate(paragraph) {
+            continue;
+        }
+        let text = normalized_text(paragraph);
+        let length = text.chars().count();
+        if length < MIN_PARAGRAPH_CHARS {
+            continue;
+        }
+        let score = 1.0 + text.matches(',').count() as f64 + (length as f64 / 100.0).min(3.0);
+        let mut ancestors = paragraph.ancestors().filter_map(ElementRef::wrap);
+        if let Some(parent) = ancestors.next() {
+            *scores.entry(parent.id()).or_insert(0.0) += score;
+        }
+        if let Some(grandparent) = ancestors.next() {
+            *scores.entry(grandparent.id()).or_insert(0.0) += score / 2.0;
+        }
+    }
+
+    let best = scores
+        .iter()
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .and_then(|(&id, _)| doc.tree.get(id))
+        .and_then(ElementRef::wrap)
+        .ok_or(ArticleError::NoArticle)?;
+
+    let mut paragraphs = Vec::new();
+    let mut current = String::new();
+    collect_text(best, &mut paragraphs, &mut current);
+    flush(&mut paragraphs, &mut current);
+    let text = paragraphs.join("\n\n");
+    if text.chars().count() < MIN_ARTICLE_CHARS {
+        return Err(ArticleError::NoArticle);
+    }
+
+    let meta = |attribute: &str, name: &str| {
+        doc.select(&selector(&format!("meta[{}=\"{}\"]", attribute, name)))
+            .find_map(|meta| meta.value().attr("content"))
+            .map(|content| content.split_whitespace().collect::<Vec<_>>().join(" "))
+            .filter(|content| !content.is_empty())
+    };
+    let first_text = |css: &str| {
+        doc.select(&selector(css))
+            .map(normalized_text)
+            .find(|text| !text.is_empty() && text.chars().count() <= 200)
+    };
+    let title = meta("property", "og:title").or_else(|| first_text("h1")).or_else(|| first_text("title"));
+    let byline = meta("name", "author").or_else(|| first_text("[rel=\"author\"], .byline, .author"));
+
+    let estimate = TTSService::estimate(&text);
+    Ok(Article { url: url.to_string(), title, byline, text, estimate })
+}
+
+fn selector(css: &str) -> Selector {
+    Selector::parse(css).expect("selectors are hard-coded")
+}
+
+fn normalized_text(element: ElementRef) -> String {
+    element.text().collect::<Vec<_>>().join(" ").split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn is_boilerplate(element: ElementRef) -> bool {
+    let value = element.value();
+    if BOILERPLATE_TAGS.contains(&value.name()) {
+        return true;
+    }
+    if value.attr("role").is_some_and(|role| BOILERPLATE_ROLES.contains(&role)) {
+        return true;
+    }
+    let names = format!("{} {}", value.attr("class").unwrap_or_default(), value.attr("id").unwrap_or_default())
+        .to_ascii_lowercase();
+    BOILERPLATE_NAMES.iter().any(|name| names.contains(name))
+        || names.split(|c: char| !c.is_ascii_alphanumeric()).any(|token| matches!(token, "ad" | "ads" | "nav"))
+}
+
+fn in_boilerplate(element: ElementRef) -> bool {
+    std::iter::once(element)
+        .chain(element.ancestors().filter_map(ElementRef::wrap))
+        .any(is_boilerplate)
+}
+
+fn collect_text(element: ElementRef, paragraphs: &mut Vec<String>, current: &mut String) {
+    for child in element.children() {
+        match child.value() {
+            Node::Text(text) => current.push_str(text),
+            Node::Element(_) => {
+                let child = ElementRef::wrap(child).expect("element node");
+                if is_boilerplate(child) {
+                    continue;
+                }
+                let name = child.value().name();
+                if name == "br" {
+                    current.push(' ');
+                    continue;
+                }
+                let block = extract::BLOCK_TAGS.contains(&name);
+                if block {
+                    flush(paragraphs, current);
+                }
+                collect_text(child, paragraphs, current);
+                if block {
+                    flush(paragraphs, current);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn flush(paragraphs: &mut Vec<String>, current: &mut String) {
+    let paragraph = current.split_whitespace().collect::<Vec<_>>().join(" ");
+    if !paragraph.is_empty() {
+        paragraphs.push(paragraph);
+    }
+    current.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ARTICLE: &str = include_str!("../tests/fixtures/article.html");
+
+    #[test]
+    fn test_extracts_main_text() {
+        let article = parse_article(ARTICLE, "https://example.com/lighthouses").unwrap();
+        assert_eq!(article.title.as_deref(), Some("Why Lighthouses Still Matter"));
+        assert_eq!(article.byline.as_deref(), Some("Jane Harbor"));
+        assert_eq!(
+            article.text,
+            "Satellite navigation has made most ships independent of the shore, yet the lights keep turning, \
+             night after night, along every rocky coast.\n\n\
+             A backup that never fails\n\n\
+             When electronics fail, as they sometimes do in storms, a captain still looks for a familiar flash \
+             on the horizon — and finds it.\n\n\
+             Keepers are mostly gone, but volunteers, historians, and local councils maintain the towers, \
+             repaint the walls, and replace the bulbs."
+        );
+        assert_eq!(article.estimate.character_count, article.text.len());
+    }
+
+    #[test]
+    fn test_no_article() {
+        let html = "<html><body><nav><p>Home, News, Opinion, Sport, Weather and more links</p></nav>\
+                    <div class=\"paywall\"><p>Subscribe to keep reading this story today.</p></div></body></html>";
+        assert_eq!(parse_article(html, "https://example.com").unwrap_err(), ArticleError::NoArticle);
+        assert_eq!(parse_article("", "https://example.com").unwrap_err(), ArticleError::NoArticle);
+    }
+
+    #[test]
+    fn test_url_checks() {
+        let check = |url: &str| check_url(&Url::parse(url).unwrap(), false);
+        assert!(check("https://example.com/story").is_ok());
+        assert!(check("http://93.184.216.34/").is_ok());
+        for url in [
+            "file:///etc/passwd",
+            "ftp://example.com/",
+            "http://localhost:8080/",
+            "http://api.localhost/",
+            "http://127.0.0.1/",
+            "http://10.0.0.8/",
+            "http://192.168.1.1/",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://100.64.0.1/",
+            "http://0.0.0.0/",
+            "http://[::1]/",
+            "http://[fd00::1]/",
+            "http://[::ffff:127.0.0.1]/",
+        ] {
+            assert!(matches!(check(url), Err(ArticleError::Blocked(_))), "{} should be blocked", url);
+        }
+        assert!(check_url(&Url::parse("http://127.0.0.1/").unwrap(), true).is_ok());
+        assert!(check_url(&Url::parse("file:///etc/passwd").unwrap(), true).is_err());
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode("caf\u{e9}".as_bytes(), "text/html"), "café");
+        assert_eq!(decode(b"caf\xe9", "text/html; charset=ISO-8859-1"), "café");
+        assert_eq!(decode(b"<meta charset=\"windows-1252\">caf\xe9", "text/html"), "<meta charset=\"windows-1252\">café");
+    }
+
+    #[tokio::test]
+    async fn test_fetch() {
+        let mut server = mockito::Server::new_async().await;
+        let page = server
+            .mock("GET", "/story")
+            .with_header("content-type", "text/html; charset=utf-8")
+            .with_body(ARTICLE)
+            .create_async()
+            .await;
+        let moved = server
+            .mock("GET", "/old")
+            .with_status(301)
+            .with_header("location", "/story")
+            .create_async()
+            .await;
+        let loop_mock = server
+            .mock("GET", "/loop")
+            .with_status(302)
+            .with_header("location", "/loop")
+            .expect_at_least(1)
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/report.pdf")
+            .with_header("content-type", "application/pdf")
+            .with_body("%PDF-1.5")
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/huge")
+            .with_header("content-type", "text/html")
+            .with_body(vec![b'a'; MAX_PAGE_BYTES + 1])
+            .create_async()
+            .await;
+        server.mock("GET", "/gone").with_status(404).create_async().await;
+
+        let fetcher = ArticleFetcher::new(true);
+        let article = fetcher.fetch(&format!("{}/old", server.url())).await.unwrap();
+        assert_eq!(article.url, format!("{}/story", server.url()));
+        assert_eq!(article.title.as_deref(), Some("Why Lighthouses Still Matter"));
+        page.assert_async().await;
+        moved.assert_async().await;
+
+        let url = |path: &str| format!("{}{}", server.url(), path);
+        let err = fetcher.fetch(&url("/report.pdf")).await.unwrap_err();
+        assert_eq!(err, ArticleError::NotHtml("application/pdf".to_string()));
+        assert_eq!(fetcher.fetch(&url("/huge")).await.unwrap_err(), ArticleError::TooLarge);
+        assert_eq!(fetcher.fetch(&url("/gone")).await.unwrap_err(), ArticleError::Http(404));
+        let err = fetcher.fetch(&url("/loop")).await.unwrap_err();
+        assert!(matches!(err, ArticleError::Network(e) if e.contains("redirects")));
+        loop_mock.assert_async().await;
+
+        // The mock server is on localhost, which is off limits by default
+        let strict = ArticleFetcher::new(false);
+        let err = strict.fetch(&format!("{}/story", server.url())).await.unwrap_err();
+        assert!(matches!(err, ArticleError::Blocked(_)));
+    }
+}
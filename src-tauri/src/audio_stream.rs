@@ -0,0 +1,213 @@
+//! The `ttsaudio://<id>` scheme: serves generated audio from `FileManager`
+//! with HTTP range support so the audio element can start and seek in long
+//! files without reading them whole. This module builds plain responses;
+//! main.rs registers the scheme with Tauri.
+
+use crate::file_manager::FileManager;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+pub const SCHEME: &str = "ttsaudio";
+
+/// Most bytes sent for one range request; the player asks again for the rest
+pub const MAX_RANGE_BYTES: u64 = 1024 * 1024;
+
+/// What a `Range` header asks of a file of a given length
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeRequest {
+    /// No header, or one we don't serve (several ranges, another unit, garbage)
+    Full,
+    /// Inclusive byte positions, already clamped to the file
+    Partial { start: u64, end: u64 },
+    /// Starts past the end, or an empty file
+    Unsatisfiable,
+}
+
+pub fn parse_range(header: Option<&str>, len: u64) -> RangeRequest {
+    let Some(spec) = header.and_then(|h| h.trim().strip_prefix("bytes=")) else {
+        return RangeRequest::Full;
+    };
+    if spec.contains(',') {
+        return RangeRequest::Full;
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return RangeRequest::Full;
+    };
+
+    match (start.trim(), end.trim()) {
+        // bytes=-500: the last 500 bytes
+        ("", suffix) => match suffix.parse::<u64>() {
+            Ok(0) => RangeRequest::Unsatisfiable,
+            Ok(_) if len == 0 => RangeRequest::Unsatisfiable,
+            Ok(n) => RangeRequest::Partial { start: len.saturating_sub(n), end: len - 1 },
+            Err(_) => RangeRequest::Full,
+        },
+        (start, end) => {
+            let Ok(start) = start.parse::<u64>() else {
+                return RangeRequest::Full;
+            };
+            let end = match end {
+                "" => None,
+                end => match end.parse::<u64>() {
+                    Ok(end) if end >= start => Some(end),
+                    _ => return RangeRequest::Full,
+                },
+            };
+            if start >= len {
+                return RangeRequest::Unsatisfiable;
+            }
+            RangeRequest::Partial { start, end: end.map_or(len - 1, |end| end.min(len - 1)) }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct StreamResponse {
+    pub status: u16,
+    pub headers: Vec<(&'static str, String)>,
+    pub body: Vec<u8>,
+}
+
+impl StreamResponse {
+    fn status(status: u16) -> Self {
+        Self { status, headers: Vec::new(), body: Vec::new() }
+    }
+
+    /// For a file that isn't what was written (see `integrity`); 409 so the
+    /// window can tell it from one that is gone
+    pub fn corrupted() -> Self {
+        Self::status(409)
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+    }
+}
+
+/// The id in a request path; `/abc` on every platform once Tauri has mapped
+/// `ttsaudio://localhost/abc` or `http://ttsaudio.localhost/abc`
+pub fn id_from_path(path: &str) -> &str {
+    path.trim_start_matches('/').split(['/', '?', '#']).next().unwrap_or("")
+}
+
+/// Answer a request for `id`. Reads only the requested range from disk.
+pub fn respond(files: &FileManager, id: &str, range: Option<&str>) -> StreamResponse {
+    let Some(path) = files.find(id) else {
+        return StreamResponse::status(404);
+    };
+    match read_range(&path, range) {
+        Ok(response) => response,
+        Err(e) => {
+            tracing::warn!("Failed to stream {:?}: {}", path, e);
+            StreamResponse::status(500)
+        }
+    }
+}
+
+fn read_range(path: &Path, range: Option<&str>) -> std::io::Result<StreamResponse> {
+    let mut file = std::fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    let format = path.extension().and_then(|ext| ext.to_str()).unwrap_or("mp3");
+
+    let mut response = StreamResponse::status(200);
+    response.headers.push(("Content-Type", crate::config::mime_type(format).to_string()));
+    response.headers.push(("Accept-Ranges", "bytes".to_string()));
+
+    match parse_range(range, len) {
+        RangeRequest::Full => {
+            file.read_to_end(&mut response.body)?;
+        }
+        RangeRequest::Partial { start, end } => {
+            let end = end.min(start + MAX_RANGE_BYTES - 1);
+            response.status = 206;
+            response.headers.push(("Content-Range", format!("bytes {}-{}/{}", start, end, len)));
+            file.seek(SeekFrom::Start(start))?;
+            response.body = vec![0; (end - start + 1) as usize];
+            file.read_exact(&mut response.body)?;
+        }
+        RangeRequest::Unsatisfiable => {
+            response.status = 416;
+            response.headers.push(("Content-Range", format!("bytes */{}", len)));
+        }
+    }
+    response.headers.push(("Content-Length", response.body.len().to_string()));
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_range() {
+        use RangeRequest::*;
+        assert_eq!(parse_range(None, 100), Full);
+        assert_eq!(parse_range(Some("bytes=0-9"), 100), Partial { start: 0, end: 9 });
+        assert_eq!(parse_range(Some("bytes=90-"), 100), Partial { start: 90, end: 99 });
+        assert_eq!(parse_range(Some("bytes=99-99"), 100), Partial { start: 99, end: 99 });
+        // Suffix ranges, including one longer than the file
+        assert_eq!(parse_range(Some("bytes=-10"), 100), Partial { start: 90, end: 99 });
+        assert_eq!(parse_range(Some("bytes=-500"), 100), Partial { start: 0, end: 99 });
+        assert_eq!(parse_range(Some("bytes=-0"), 100), Unsatisfiable);
+        // Ends past EOF are clamped, starts past EOF aren't satisfiable
+        assert_eq!(parse_range(Some("bytes=50-1000"), 100), Partial { start: 50, end: 99 });
+        assert_eq!(parse_range(Some("bytes=100-"), 100), Unsatisfiable);
+        assert_eq!(parse_range(Some("bytes=0-"), 0), Unsatisfiable);
+        assert_eq!(parse_range(Some("bytes=-5"), 0), Unsatisfiable);
+        // Anything we don't understand falls back to the whole file
+        assert_eq!(parse_range(Some("bytes=9-0"), 100), Full);
+        assert_eq!(parse_range(Some("bytes=0-1,5-6"), 100), Full);
+        assert_eq!(parse_range(Some("items=0-1"), 100), Full);
+        assert_eq!(parse_range(Some("bytes=abc"), 100), Full);
+    }
+
+    #[test]
+    fn test_id_from_path() {
+        assert_eq!(id_from_path("/abc-123"), "abc-123");
+        assert_eq!(id_from_path("/abc-123?t=1"), "abc-123");
+        assert_eq!(id_from_path(""), "");
+    }
+
+    #[tokio::test]
+    async fn test_respond() {
+        let dir = TempDir::new().unwrap();
+        let files = FileManager::with_dir(dir.path());
+        let audio: Vec<u8> = (0..=255).collect();
+        files.save_audio_as("job-1", &audio, "wav").await.unwrap();
+
+        let full = respond(&files, "job-1", None);
+        assert_eq!(full.status, 200);
+        assert_eq!(full.body, audio);
+        assert_eq!(full.header("content-type"), Some("audio/wav"));
+
+        let partial = respond(&files, "job-1", Some("bytes=-6"));
+        assert_eq!(partial.status, 206);
+        assert_eq!(partial.body, vec![250, 251, 252, 253, 254, 255]);
+        assert_eq!(partial.header("Content-Range"), Some("bytes 250-255/256"));
+        assert_eq!(partial.header("Content-Length"), Some("6"));
+
+        let past_end = respond(&files, "job-1", Some("bytes=256-"));
+        assert_eq!(past_end.status, 416);
+        assert_eq!(past_end.header("Content-Range"), Some("bytes */256"));
+
+        assert_eq!(respond(&files, "job-2", None).status, 404);
+        assert_eq!(respond(&files, "../job-1", None).status, 404);
+    }
+
+    #[tokio::test]
+    async fn test_large_ranges_are_capped() {
+        let dir = TempDir::new().unwrap();
+        let files = FileManager::with_dir(dir.path());
+        let len = MAX_RANGE_BYTES + 10;
+        files.save_audio_as("big", &vec![7; len as usize], "mp3").await.unwrap();
+
+        let response = respond(&files, "big", Some("bytes=0-"));
+        assert_eq!(response.status, 206);
+        assert_eq!(response.body.len() as u64, MAX_RANGE_BYTES);
+        assert_eq!(
+            response.header("Content-Range"),
+            Some(format!("bytes 0-{}/{}", MAX_RANGE_BYTES - 1, len).as_str())
+        );
+    }
+}
@@ -0,0 +1,288 @@
+//! Synthesize a directory of text files, one audio file per input.
+
+use crate::documents;
+use crate::eta::Estimator;
+use crate::report::{escape_control_chars, format_duration};
+use crate::tts::{estimate_cost, SpeechBackend};
+use futures::stream::{self, StreamExt};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+pub const DEFAULT_CONCURRENCY: usize = 4;
+const DEFAULT_GLOB: &str = "*.txt";
+
+#[derive(Debug, Clone)]
+pub struct BatchOptions {
+    pub input_dir: PathBuf,
+    pub output_dir: PathBuf,
+    pub recursive: bool,
+    /// Matched against the path relative to `input_dir`; defaults to `*.txt`
+    pub glob: Option<String>,
+    pub voice: String,
+    pub model: String,
+    /// Output extension, one of `config::SUPPORTED_FORMATS`
+    pub format: String,
+    pub concurrency: usize,
+    /// Larger files fail (see `documents::read_text`)
+    pub max_input_bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum FileOutcome {
+    Succeeded { characters: usize },
+    Skipped,
+    Failed { error: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileResult {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    #[serde(flatten)]
+    pub outcome: FileOutcome,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BatchSummary {
+    pub succeeded: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    pub total_characters: usize,
+    pub estimated_cost: f64,
+    pub files: Vec<FileResult>,
+}
+
+impl BatchSummary {
+    fn from_results(files: Vec<FileResult>, model: &str) -> Self {
+        let mut summary = BatchSummary::default();
+        for file in &files {
+            match &file.outcome {
+                FileOutcome::Succeeded { characters } => {
+                    summary.succeeded += 1;
+                    summary.total_characters += characters;
+                }
+                FileOutcome::Skipped => summary.skipped += 1,
+                FileOutcome::Failed { .. } => summary.failed += 1,
+            }
+        }
+        summary.estimated_cost = estimate_cost(summary.total_characters as i64, model);
+        summary.files = files;
+        summary
+    }
+}
+
+/// Input files under `input_dir` matching the glob, sorted for stable output
+pub fn collect_inputs(options: &BatchOptions) -> Result<Vec<PathBuf>, String> {
+    let pattern = glob::Pattern::new(options.glob.as_deref().unwrap_or(DEFAULT_GLOB))
+        .map_err(|e| format!("Invalid glob pattern: {}", e))?;
+
+    let match_options = glob::MatchOptions { require_literal_separator: true, ..Default::default() };
+    let matches = |path: &Path| pattern.matches_path_with(path, match_options);
+
+    let mut inputs = Vec::new();
+    let mut pending = vec![options.input_dir.clone()];
+    while let Some(dir) = pending.pop() {
+        let entries = std::fs::read_dir(&dir)
+            .map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+        for entry in entries {
+            let path = entry.map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?.path();
+            if path.is_dir() {
+                if options.recursive {
+                    pending.push(path);
+                }
+                continue;
+            }
+            let relative = path.strip_prefix(&options.input_dir).unwrap_or(&path);
+            // Plain patterns like `*.txt` should match at any depth
+            if matches(relative) || relative.file_name().is_some_and(|name| matches(Path::new(name))) {
+                inputs.push(path);
+            }
+        }
+    }
+
+    inputs.sort();
+    Ok(inputs)
+}
+
+/// `<input_dir>/a/b.txt` -> `<output_dir>/a/b.<format>`
+pub fn output_path_for(input: &Path, options: &BatchOptions) -> PathBuf {
+    let relative = input.strip_prefix(&options.input_dir).unwrap_or(input);
+    options.output_dir.join(relative).with_extension(&options.format)
+}
+
+/// Synthesize every input with at most `options.concurrency` requests in
+/// flight. A failed file is recorded and the rest of the run continues.
+pub async fn run_batch<B: SpeechBackend>(backend: &B, options: &BatchOptions) -> Result<BatchSummary, String> {
+    let inputs = collect_inputs(options)?;
+    tracing::info!("{} file(s) in {}", inputs.len(), options.input_dir.display());
+
+    let total = inputs.len();
+    let mut eta = Estimator::new(total, Instant::now());
+    let mut done = 0;
+    let mut results = stream::iter(inputs)
+        .map(|input| process_file(backend, options, input))
+        .buffer_unordered(options.concurrency.max(1))
+        .inspect(|result| {
+            done += 1;
+            if result.outcome == FileOutcome::Skipped {
+                eta.skipped();
+                return;
+            }
+            let now = Instant::now();
+            eta.finished(now);
+            let timing = eta.timing(now);
+            tracing::info!(
+                "{} of {} file(s) done in {}, about {} left",
+                done,
+                total,
+                format_duration(timing.elapsed_ms as f64 / 1000.0),
+                format_duration(timing.eta_ms.unwrap_or_default() as f64 / 1000.0)
+            );
+        })
+        .collect::<Vec<_>>()
+        .await;
+
+    results.sort_by(|a, b| a.input.cmp(&b.input));
+    Ok(BatchSummary::from_results(results, &options.model))
+}
+
+async fn process_file<B: SpeechBackend>(backend: &B, options: &BatchOptions, input: PathBuf) -> FileResult {
+    let output = output_path_for(&input, options);
+    let outcome = if output.exists() {
+        FileOutcome::Skipped
+    } else {
+        match synthesize_file(backend, options, &input, &output).await {
+            Ok(characters) => FileOutcome::Succeeded { characters },
+            Err(error) => {
+                tracing::warn!("{}: {}", input.display(), escape_control_chars(&error));
+                FileOutcome::Failed { error }
+            }
+        }
+    };
+    FileResult { input, output, outcome }
+}
+
+async fn synthesize_file<B: SpeechBackend>(
+    backend: &B,
+    options: &BatchOptions,
+    input: &Path,
+    output: &Path,
+) -> Result<usize, String> {
+    let text = documents::read_text(input, options.max_input_bytes)?.text;
+    if text.trim().is_empty() {
+        return Err("File is empty".to_string());
+    }
+
+    let audio_data = backend
+        .synthesize(&text, &options.voice, &options.model)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if let Some(parent) = output.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    std::fs::write(output, &audio_data).map_err(|e| format!("Failed to write {}: {}", output.display(), e))?;
+    Ok(text.chars().count())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::FakeBackend;
+    use std::sync::atomic::Ordering;
+    use tempfile::TempDir;
+
+    fn write(dir: &Path, relative: &str, contents: &str) {
+        let path = dir.join(relative);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+
+    fn options(input: &TempDir, output: &TempDir) -> BatchOptions {
+        BatchOptions {
+            input_dir: input.path().to_path_buf(),
+            output_dir: output.path().to_path_buf(),
+            recursive: false,
+            glob: None,
+            voice: "nova".to_string(),
+            model: "tts-1".to_string(),
+            format: "mp3".to_string(),
+            concurrency: 2,
+            max_input_bytes: 1024,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batch_summary_with_failure_and_skip() {
+        let input = TempDir::new().unwrap();
+        let output = TempDir::new().unwrap();
+        write(input.path(), "a.txt", "First article");
+        write(input.path(), "b.txt", "This one should FAIL");
+        write(input.path(), "c.txt", "Already done");
+        write(input.path(), "notes.md", "Not a text file");
+        write(output.path(), "c.mp3", "existing");
+
+        let backend = FakeBackend::default();
+        let summary = run_batch(&backend, &options(&input, &output)).await.unwrap();
+
+        assert_eq!((summary.succeeded, summary.failed, summary.skipped), (1, 1, 1));
+        assert_eq!(summary.total_characters, "First article".len());
+        assert_eq!(backend.calls.lock().unwrap().len(), 2);
+        assert_eq!(std::fs::read_to_string(output.path().join("a.mp3")).unwrap(), "First article");
+        assert_eq!(std::fs::read_to_string(output.path().join("c.mp3")).unwrap(), "existing");
+        assert!(!output.path().join("b.mp3").exists());
+        assert!(matches!(summary.files[1].outcome, FileOutcome::Failed { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_oversized_file_fails_without_a_request() {
+        let input = TempDir::new().unwrap();
+        let output = TempDir::new().unwrap();
+        write(input.path(), "long.txt", &"Words. ".repeat(200));
+
+        let backend = FakeBackend::default();
+        let summary = run_batch(&backend, &options(&input, &output)).await.unwrap();
+
+        assert_eq!(summary.failed, 1);
+        assert_eq!(backend.calls.lock().unwrap().len(), 0);
+        let FileOutcome::Failed { error } = &summary.files[0].outcome else { panic!("{:?}", summary.files[0]) };
+        assert!(error.contains("1024 bytes"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_recursive_mirrors_tree_and_respects_glob() {
+        let input = TempDir::new().unwrap();
+        let output = TempDir::new().unwrap();
+        write(input.path(), "top.txt", "Top");
+        write(input.path(), "2024/jan/one.txt", "One");
+        write(input.path(), "2024/jan/draft-two.txt", "Draft");
+
+        let mut opts = options(&input, &output);
+        assert_eq!(collect_inputs(&opts).unwrap().len(), 1);
+
+        opts.recursive = true;
+        opts.glob = Some("2024/**/[!d]*.txt".to_string());
+        let summary = run_batch(&FakeBackend::default(), &opts).await.unwrap();
+
+        assert_eq!(summary.succeeded, 1);
+        assert!(output.path().join("2024/jan/one.mp3").exists());
+        assert!(!output.path().join("top.mp3").exists());
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_is_bounded() {
+        let input = TempDir::new().unwrap();
+        let output = TempDir::new().unwrap();
+        for i in 0..8 {
+            write(input.path(), &format!("{}.txt", i), "Text");
+        }
+
+        let backend = FakeBackend::default();
+        let summary = run_batch(&backend, &options(&input, &output)).await.unwrap();
+
+        assert_eq!(summary.succeeded, 8);
+        assert!(backend.max_in_flight.load(Ordering::SeqCst) <= 2);
+    }
+}
@@ -0,0 +1,170 @@
+//! Chapter markers for long text joined by FFmpeg, so players that support
+//! them (ID3 CHAP frames in MP3, chapter atoms in M4A) can skip through it.
+//! A chapter starts at each heading of the text; text without any gets one
+//! per request it was split into. Timestamps come from the length of each
+//! piece of audio, a heading inside a piece being placed by how far into
+//! its text it is.
+
+use std::time::Duration;
+
+/// Longest line taken as a heading when it isn't marked with `#`
+const MAX_HEADING_CHARS: usize = 80;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chapter {
+    pub title: String,
+    pub start_ms: u64,
+    pub end_ms: u64,
+}
+
+/// Headings in `text` by their offset in characters: Markdown `#` lines,
+/// and short lines standing alone between blank lines (or at the start),
+/// not ending like a sentence, e.g. "Chapter One"
+pub fn headings(text: &str) -> Vec<(usize, String)> {
+    let lines: Vec<&str> = text.split('\n').collect();
+    let mut offset = 0;
+    let mut found = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        let trimmed = line.trim();
+        let marked = trimmed.trim_start_matches('#');
+        let hashes = trimmed.len() - marked.len();
+        let title = if (1..=6).contains(&hashes) && marked.starts_with(' ') {
+            Some(marked.trim())
+        } else if hashes == 0 {
+            let alone = (i == 0 || lines[i - 1].trim().is_empty())
+                && lines.get(i + 1).is_some_and(|next| next.trim().is_empty());
+            let short = trimmed.chars().count() <= MAX_HEADING_CHARS;
+            let ends_sentence = trimmed
+                .trim_end_matches(['"', '\'', '”', '’', '»', ')'])
+                .ends_with(['.', ',', ';', ':', '!', '?', '…', '。', '！', '？']);
+            (alone && short && !ends_sentence).then_some(trimmed)
+        } else {
+            None
+        };
+        if let Some(title) = title.filter(|title| !title.is_empty()) {
+            found.push((offset, title.to_string()));
+        }
+        offset += line.chars().count() + 1;
+    }
+    found
+}
+
+/// Chapters of audio joined from `pieces`: the text of each request and
+/// how long its audio plays
+pub fn from_pieces(pieces: &[(&str, Duration)]) -> Vec<Chapter> {
+    let mut starts: Vec<(u64, String)> = Vec::new();
+    let mut elapsed = Duration::ZERO;
+    for (text, duration) in pieces {
+        let characters = text.chars().count().max(1);
+        for (offset, title) in headings(text) {
+            let into = duration.mul_f64(offset as f64 / characters as f64);
+            starts.push(((elapsed + into).as_millis() as u64, title));
+        }
+        elapsed += *duration;
+    }
+    let total_ms = elapsed.as_millis() as u64;
+
+    if starts.is_empty() {
+        // No headings: one chapter per request
+        let mut start = Duration::ZERO;
+        for (i, (_, duration)) in pieces.iter().enumerate() {
+            starts.push((start.as_millis() as u64, format!("Part {}", i + 1)));
+            start += *duration;
+        }
+    } else if starts[0].0 > 0 {
+        // Whatever comes before the first heading
+        starts.insert(0, (0, "Introduction".to_string()));
+    }
+    // Headings with nothing between them start one chapter
+    starts.dedup_by(|later, earlier| later.0 == earlier.0);
+
+    let ends: Vec<u64> = starts.iter().skip(1).map(|(start, _)| *start).chain([total_ms]).collect();
+    starts
+        .into_iter()
+        .zip(ends)
+        .map(|((start_ms, title), end_ms)| Chapter { title, start_ms, end_ms })
+        .collect()
+}
+
+/// `chapters` as an FFmpeg metadata file, to be given as a second input
+/// with `-map_chapters`
+pub fn ffmetadata(chapters: &[Chapter]) -> String {
+    let mut metadata = String::from(";FFMETADATA1\n");
+    for chapter in chapters {
+        metadata.push_str(&format!(
+            "\n[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle={}\n",
+            chapter.start_ms,
+            chapter.end_ms,
+            escape(&chapter.title)
+        ));
+    }
+    metadata
+}
+
+/// `=`, `;`, `#`, `\` and newlines are special in a metadata file
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_headings() {
+        let text = "# The Start\nIt begins.\n\nChapter Two\n\nIt goes on: \"What now?\"\n\nA line on its own.\n\n## Notes";
+        assert_eq!(
+            headings(text),
+            [(0, "The Start".to_string()), (24, "Chapter Two".to_string()), (82, "Notes".to_string())]
+        );
+        assert!(headings("#hashtag\n\nplain prose that runs on without a break").is_empty());
+    }
+
+    #[test]
+    fn test_one_chapter_per_request_without_headings() {
+        let pieces = [("First part of it.", secs(40)), ("Second part.", secs(35)), ("Last.", secs(5))];
+        let chapters = from_pieces(&pieces);
+        let expected = [("Part 1", 0, 40_000), ("Part 2", 40_000, 75_000), ("Part 3", 75_000, 80_000)];
+        let actual: Vec<_> = chapters.iter().map(|c| (c.title.as_str(), c.start_ms, c.end_ms)).collect();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_chapters_start_at_headings() {
+        // The second heading is halfway through the second piece's text
+        let second = format!("{}.\n\nTwo\n\n{}.", "a".repeat(47), "b".repeat(44));
+        assert_eq!(headings(&second), [(50, "Two".to_string())]);
+        let pieces = [("Preface text.\n\n# One\n\nA story.", secs(10)), (second.as_str(), secs(60)), ("The end.", secs(4))];
+        let chapters = from_pieces(&pieces);
+        // "# One" is 15 of the first piece's 30 characters in
+        let expected = [("Introduction", 0, 5_000), ("One", 5_000, 40_000), ("Two", 40_000, 74_000)];
+        let actual: Vec<_> = chapters.iter().map(|c| (c.title.as_str(), c.start_ms, c.end_ms)).collect();
+        assert_eq!(actual, expected);
+        assert_eq!(chapters.last().unwrap().end_ms, pieces.iter().map(|(_, d)| d.as_millis() as u64).sum::<u64>());
+    }
+
+    #[test]
+    fn test_metadata_file() {
+        let chapters = [
+            Chapter { title: "One; or, the=start".to_string(), start_ms: 0, end_ms: 61_500 },
+            Chapter { title: "Two".to_string(), start_ms: 61_500, end_ms: 90_000 },
+        ];
+        assert_eq!(
+            ffmetadata(&chapters),
+            ";FFMETADATA1\n\
+             \n[CHAPTER]\nTIMEBASE=1/1000\nSTART=0\nEND=61500\ntitle=One\\; or, the\\=start\n\
+             \n[CHAPTER]\nTIMEBASE=1/1000\nSTART=61500\nEND=90000\ntitle=Two\n"
+        );
+    }
+}
@@ -1,63 +1,574 @@
+use clap::builder::{PossibleValue, StringValueParser, TypedValueParser};
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
 use serde::{Deserialize, Serialize};
+use crate::config::{Config, SUPPORTED_FORMATS};
+use crate::output_format;
+use crate::{documents, i18n};
+use crate::tts::{VALID_MODEL_IDS, VALID_VOICE_IDS};
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Normalized result of parsing the command line. Subcommands and the legacy
+/// top-level flags both end up here so downstream code has one shape to read.
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct CliArgs {
     pub text: Option<String>,
     pub voice: Option<String>,
+    pub file: Option<String>,
+    pub clipboard: bool,
+    pub model: Option<String>,
+    pub speed: Option<f32>,
+    pub format: Option<String>,
+    pub output: Option<String>,
+    pub config: Option<String>,
+    pub no_gui: bool,
+    pub estimate: bool,
+    pub stats: bool,
+    pub history: bool,
+    pub voices: bool,
+    pub limit: Option<i32>,
+    pub days: Option<i32>,
+    pub json: bool,
+    pub completions: Option<CompletionShell>,
+    /// Input directory for `batch`; `output` is then the output directory
+    pub batch_dir: Option<String>,
+    pub recursive: bool,
+    pub glob: Option<String>,
+    pub jobs: Option<usize>,
+    /// `tts-player://` link the OS launched us with
+    pub deep_link: Option<String>,
+    pub verbose: u8,
+    pub quiet: bool,
+    pub play: bool,
+    pub device: Option<String>,
+    pub devices: bool,
+    /// `watch`: speak `file` again each time it's saved
+    pub watch: bool,
 }
 
-pub fn parse_cli_args(args: Vec<String>) -> Result<CliArgs, String> {
-    let mut cli_args = CliArgs {
-        text: None,
-        voice: None,
-    };
-    
-    let mut i = 1; // Skip program name
-    while i < args.len() {
-        match args[i].as_str() {
-            "--text" | "-t" => {
-                if i + 1 < args.len() {
-                    cli_args.text = Some(args[i + 1].clone());
-                    i += 2;
-                } else {
-                    return Err("Missing value for --text argument".to_string());
-                }
-            }
-            "--voice" | "-v" => {
-                if i + 1 < args.len() {
-                    cli_args.voice = Some(args[i + 1].clone());
-                    i += 2;
-                } else {
-                    return Err("Missing value for --voice argument".to_string());
-                }
-            }
-            "--help" | "-h" => {
-                return Err(format_help());
-            }
-            _ => {
-                return Err(format!("Unknown argument: {}", args[i]));
-            }
+/// Shells we can generate completion scripts for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum CompletionShell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+}
+
+impl From<CompletionShell> for clap_complete::Shell {
+    fn from(shell: CompletionShell) -> Self {
+        match shell {
+            CompletionShell::Bash => clap_complete::Shell::Bash,
+            CompletionShell::Zsh => clap_complete::Shell::Zsh,
+            CompletionShell::Fish => clap_complete::Shell::Fish,
+            CompletionShell::Powershell => clap_complete::Shell::PowerShell,
+        }
+    }
+}
+
+/// Where an invocation should be handled once its arguments are parsed.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Dispatch {
+    /// Start the Tauri window as usual.
+    Gui,
+    /// Run the generation directly and exit without creating a window.
+    Headless,
+}
+
+/// CLI-only invocations (`--no-gui`, report flags, `--clipboard`, or anything
+/// that asks for an output file) never need the webview, so they skip
+/// `tauri::Builder::run`.
+pub fn dispatch(args: &CliArgs) -> Dispatch {
+    if args.no_gui
+        || args.output.is_some()
+        || args.estimate
+        || args.stats
+        || args.history
+        || args.voices
+        || args.completions.is_some()
+        || args.batch_dir.is_some()
+        || args.play
+        || args.devices
+        || args.watch
+        || args.clipboard
+    {
+        Dispatch::Headless
+    } else {
+        Dispatch::Gui
+    }
+}
+
+/// Text handed to the window at startup (`tts-player --text "hello"`).
+/// The frontend takes it once via the `take_launch_request` command.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LaunchRequest {
+    pub text: String,
+    pub voice_id: String,
+    /// False when the invocation had a problem the user should see first
+    pub auto_generate: bool,
+    /// The job already generating this text; the window follows it instead
+    /// of starting its own
+    #[serde(default)]
+    pub job_id: Option<String>,
+    /// Something the user should know about the launch, shown in the window
+    #[serde(default)]
+    pub notice: Option<String>,
+}
+
+/// Translate GUI-mode arguments into the request the window starts with.
+/// `config` already has `--voice` merged in. `Ok(None)` means no text was
+/// given and the window falls back to the clipboard as before.
+pub fn launch_request(args: &CliArgs, config: &Config) -> Result<Option<LaunchRequest>, String> {
+    let mut notice = None;
+    let text = match (&args.text, &args.file) {
+        (Some(text), _) => text.clone(),
+        (None, Some(file)) => {
+            let decoded = documents::read_text(std::path::Path::new(file), config.max_input_bytes)
+                .map_err(|e| format!("{}: {}", file, e))?;
+            let warnings: Vec<String> = decoded.warnings().iter().map(|warning| warning.translate(i18n::locale())).collect();
+            notice = (!warnings.is_empty()).then(|| warnings.join(" "));
+            decoded.text
         }
+        (None, None) => return Ok(None),
+    };
+    if text.trim().is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(LaunchRequest {
+        text,
+        voice_id: config.voice.clone(),
+        auto_generate: true,
+        job_id: None,
+        notice,
+    }))
+}
+
+/// A window launch with a `--voice` that doesn't exist still opens and
+/// speaks, with the saved voice; take the bad name out before the config is
+/// loaded (which would reject it) and return it for a notice
+pub fn take_invalid_voice(args: &mut CliArgs) -> Option<String> {
+    if args.voice.as_deref().is_some_and(|voice| !VALID_VOICE_IDS.contains(&voice)) {
+        return args.voice.take();
     }
-    
-    Ok(cli_args)
+    None
 }
 
-fn format_help() -> String {
-    r#"TTS Player - Text-to-Speech Audio Player
+/// What a second window launch hands the running instance before exiting.
+/// The single-instance plugin delivers only its raw arguments; this is what
+/// they mean to the instance that's already up.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum ForwardedLaunch {
+    /// Text for the window, as if it had been started with it
+    Text { request: LaunchRequest },
+    DeepLink { link: String },
+    /// Nothing to hand over; just bring the window forward
+    Focus,
+}
+
+/// Parse the arguments of a second launch the way `main` parses its own.
+/// A relative `--file` is read from the second launch's `cwd`; `--voice`
+/// wins over `saved_voice`, as on a fresh launch.
+pub fn forwarded_launch(
+    argv: Vec<String>,
+    cwd: &std::path::Path,
+    saved_voice: &str,
+    configured: bool,
+) -> Result<ForwardedLaunch, String> {
+    let mut args = parse_cli_args(argv)?;
+    if let Some(link) = args.deep_link.take() {
+        return Ok(ForwardedLaunch::DeepLink { link });
+    }
+    if let Some(voice) = args.voice.as_deref().filter(|voice| !VALID_VOICE_IDS.contains(voice)) {
+        return Err(format!("Invalid voice ID: {}", voice));
+    }
+    if let Some(file) = &args.file {
+        args.file = Some(cwd.join(file).to_string_lossy().into_owned());
+    }
+
+    let request = launch_request(&args, &Config::default())?.map(|request| LaunchRequest {
+        voice_id: args.voice.clone().unwrap_or_else(|| saved_voice.to_string()),
+        auto_generate: configured,
+        ..request
+    });
+    Ok(match request {
+        Some(request) => ForwardedLaunch::Text { request },
+        None => ForwardedLaunch::Focus,
+    })
+}
+
+const HELP_TEMPLATE: &str = "\
+{name} {version} - {about}
 
 USAGE:
-    tts-player [OPTIONS]
+    {usage}
 
-OPTIONS:
-    -t, --text <TEXT>     Text to convert to speech
-    -v, --voice <VOICE>   Voice ID to use (rachel, adam, bella)
-    -h, --help           Print help information
+{all-args}{after-help}";
 
+const EXAMPLES: &str = "\
 EXAMPLES:
-    tts-player --text "Hello world"
-    tts-player -t "Hello world" -v rachel
-"#.to_string()
+    tts-player --text \"Hello world\"
+    tts-player -t \"Hello world\" -v nova
+    tts-player speak -f notes.txt -o notes.mp3
+    tts-player speak --clipboard -o clip.mp3
+    tts-player -t \"build finished\" --play
+    tts-player estimate --file book.txt
+    tts-player batch ./articles --out ./audio --voice nova
+    tts-player watch notes.txt --voice nova --play
+    tts-player stats --days 7
+    tts-player history --limit 10 --json
+    tts-player completions bash > /etc/bash_completion.d/tts-player
+
+EXIT CODES:
+    0   success
+    2   usage error (bad arguments, missing text, invalid config)
+    3   authentication failed
+    4   rate limited
+    5   network error
+    10  any other failure";
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "tts-player",
+    version,
+    about = "TTS Player - Text-to-Speech Audio Player",
+    help_template = HELP_TEMPLATE,
+    after_help = EXAMPLES
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Read defaults from this config file instead of the standard location
+    #[arg(long, global = true, value_name = "PATH")]
+    config: Option<String>,
+
+    /// Log more: once for progress, twice for HTTP details (-v is --voice)
+    #[arg(long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Print only the result or the error
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    #[command(flatten)]
+    source: SourceArgs,
+
+    #[command(flatten)]
+    speech: SpeechArgs,
+
+    /// Write the audio to PATH without opening the window
+    #[arg(short, long, value_name = "PATH")]
+    output: Option<String>,
+
+    /// Run without opening the window
+    #[arg(long)]
+    no_gui: bool,
+
+    #[command(flatten)]
+    playback: PlaybackArgs,
+
+    /// On Linux and Windows a `tts-player://` link arrives as an argument
+    #[arg(hide = true, value_name = "URL")]
+    deep_link: Option<String>,
+
+    // Pre-subcommand spellings, kept so existing scripts keep working
+    #[arg(long, hide = true)]
+    estimate: bool,
+    #[arg(long, hide = true)]
+    stats: bool,
+    #[arg(long, hide = true)]
+    history: bool,
+    #[arg(long, hide = true, value_parser = clap::value_parser!(i32).range(1..))]
+    limit: Option<i32>,
+    #[arg(long, hide = true, value_parser = clap::value_parser!(i32).range(1..))]
+    days: Option<i32>,
+    #[arg(long, hide = true)]
+    json: bool,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Generate speech and write it to a file without opening the window
+    Speak {
+        #[command(flatten)]
+        source: SourceArgs,
+        #[command(flatten)]
+        speech: SpeechArgs,
+        /// Where to write the audio (defaults to ./tts-<timestamp>.<format>)
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<String>,
+        #[command(flatten)]
+        playback: PlaybackArgs,
+        #[command(flatten)]
+        format: FormatArgs,
+    },
+    /// Generate audio for every text file in a directory
+    Batch {
+        /// Directory containing the text files
+        #[arg(value_name = "DIR")]
+        input: String,
+        /// Directory to write the audio files to
+        #[arg(short, long = "out", alias = "output", value_name = "DIR")]
+        out: String,
+        #[command(flatten)]
+        speech: SpeechArgs,
+        /// Descend into subdirectories
+        #[arg(short, long)]
+        recursive: bool,
+        /// Only process files matching PATTERN (default: *.txt)
+        #[arg(long, value_name = "PATTERN")]
+        glob: Option<String>,
+        /// Number of files to generate at once
+        #[arg(short, long, value_parser = clap::value_parser!(u16).range(1..=16))]
+        jobs: Option<u16>,
+    },
+    /// Speak a file again whenever it's saved (Ctrl-C to stop)
+    Watch {
+        /// File to watch
+        #[arg(value_name = "PATH")]
+        file: String,
+        #[command(flatten)]
+        speech: SpeechArgs,
+        /// Write each generation to PATH, replacing the previous one
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<String>,
+        #[command(flatten)]
+        playback: PlaybackArgs,
+    },
+    /// Print characters, chunks, duration and cost without generating
+    Estimate {
+        #[command(flatten)]
+        source: SourceArgs,
+        #[command(flatten)]
+        format: FormatArgs,
+    },
+    /// Print usage statistics
+    Stats {
+        /// Number of days to cover
+        #[arg(long, default_value_t = 30, value_parser = clap::value_parser!(i32).range(1..))]
+        days: i32,
+        #[command(flatten)]
+        format: FormatArgs,
+    },
+    /// List recent generations
+    History {
+        /// Number of entries to show
+        #[arg(long, default_value_t = 20, value_parser = clap::value_parser!(i32).range(1..))]
+        limit: i32,
+        /// Only show entries from the last DAYS days
+        #[arg(long, value_parser = clap::value_parser!(i32).range(1..))]
+        days: Option<i32>,
+        #[command(flatten)]
+        format: FormatArgs,
+    },
+    /// List the available voices
+    Voices {
+        #[command(flatten)]
+        format: FormatArgs,
+    },
+    /// List the audio output devices usable with --device
+    Devices,
+    /// Print a shell completion script to stdout
+    Completions {
+        #[arg(value_enum)]
+        shell: CompletionShell,
+    },
+}
+
+#[derive(Debug, Args)]
+struct SourceArgs {
+    /// Text to convert to speech
+    #[arg(short, long, conflicts_with = "file")]
+    text: Option<String>,
+
+    /// Read the text from a file
+    #[arg(short, long, value_name = "PATH")]
+    file: Option<String>,
+
+    /// Read the text from the clipboard (used when --text/--file are absent)
+    #[arg(long)]
+    clipboard: bool,
+}
+
+#[derive(Debug, Args)]
+struct SpeechArgs {
+    /// Voice ID to use
+    #[arg(short, long, value_parser = VoiceParser)]
+    voice: Option<String>,
+
+    /// Model to use
+    #[arg(short, long, value_parser = clap::builder::PossibleValuesParser::new(VALID_MODEL_IDS))]
+    model: Option<String>,
+
+    /// Speaking speed, 0.25 to 4.0
+    #[arg(long)]
+    speed: Option<f32>,
+
+    /// Audio format; "auto" for one the built-in player can play
+    #[arg(long, value_parser = clap::builder::PossibleValuesParser::new(SUPPORTED_FORMATS.iter().copied().chain([output_format::AUTO])))]
+    format: Option<String>,
+}
+
+#[derive(Debug, Args)]
+struct PlaybackArgs {
+    /// Play the audio without opening the window (saved only if --output is given)
+    #[arg(long)]
+    play: bool,
+
+    /// Output device for --play (see `tts-player devices`)
+    #[arg(long, value_name = "NAME", requires = "play")]
+    device: Option<String>,
+}
+
+#[derive(Debug, Args)]
+struct FormatArgs {
+    /// Print machine-readable JSON instead of text
+    #[arg(long)]
+    json: bool,
+}
+
+/// Accepts any voice ID (the config layer validates it) but advertises the
+/// known ones so help text and shell completions can offer them.
+#[derive(Clone)]
+struct VoiceParser;
+
+impl TypedValueParser for VoiceParser {
+    type Value = String;
+
+    fn parse_ref(
+        &self,
+        cmd: &clap::Command,
+        arg: Option<&clap::Arg>,
+        value: &std::ffi::OsStr,
+    ) -> Result<String, clap::Error> {
+        StringValueParser::new().parse_ref(cmd, arg, value)
+    }
+
+    fn possible_values(&self) -> Option<Box<dyn Iterator<Item = PossibleValue> + '_>> {
+        Some(Box::new(VALID_VOICE_IDS.iter().map(|voice| PossibleValue::new(*voice))))
+    }
+}
+
+impl From<Cli> for CliArgs {
+    fn from(cli: Cli) -> Self {
+        let (config, verbose, quiet) = (cli.config, cli.verbose, cli.quiet);
+        let args = match cli.command {
+            None => CliArgs {
+                text: cli.source.text,
+                file: cli.source.file,
+                clipboard: cli.source.clipboard,
+                voice: cli.speech.voice,
+                model: cli.speech.model,
+                speed: cli.speech.speed,
+                format: cli.speech.format,
+                output: cli.output,
+                no_gui: cli.no_gui,
+                play: cli.playback.play,
+                device: cli.playback.device,
+                estimate: cli.estimate,
+                stats: cli.stats,
+                history: cli.history,
+                limit: cli.limit,
+                days: cli.days,
+                json: cli.json,
+                deep_link: cli.deep_link,
+                ..Default::default()
+            },
+            Some(Command::Speak { source, speech, output, playback, format }) => CliArgs {
+                text: source.text,
+                file: source.file,
+                clipboard: source.clipboard,
+                voice: speech.voice,
+                model: speech.model,
+                speed: speech.speed,
+                format: speech.format,
+                output,
+                no_gui: true,
+                play: playback.play,
+                device: playback.device,
+                json: format.json,
+                ..Default::default()
+            },
+            Some(Command::Batch { input, out, speech, recursive, glob, jobs }) => CliArgs {
+                batch_dir: Some(input),
+                output: Some(out),
+                voice: speech.voice,
+                model: speech.model,
+                speed: speech.speed,
+                format: speech.format,
+                recursive,
+                glob,
+                jobs: jobs.map(usize::from),
+                ..Default::default()
+            },
+            Some(Command::Watch { file, speech, output, playback }) => CliArgs {
+                watch: true,
+                file: Some(file),
+                voice: speech.voice,
+                model: speech.model,
+                speed: speech.speed,
+                format: speech.format,
+                output,
+                play: playback.play,
+                device: playback.device,
+                ..Default::default()
+            },
+            Some(Command::Estimate { source, format }) => CliArgs {
+                text: source.text,
+                file: source.file,
+                clipboard: source.clipboard,
+                estimate: true,
+                json: format.json,
+                ..Default::default()
+            },
+            Some(Command::Stats { days, format }) => CliArgs {
+                stats: true,
+                days: Some(days),
+                json: format.json,
+                ..Default::default()
+            },
+            Some(Command::History { limit, days, format }) => CliArgs {
+                history: true,
+                limit: Some(limit),
+                days,
+                json: format.json,
+                ..Default::default()
+            },
+            Some(Command::Voices { format }) => CliArgs {
+                voices: true,
+                json: format.json,
+                ..Default::default()
+            },
+            Some(Command::Devices) => CliArgs {
+                devices: true,
+                ..Default::default()
+            },
+            Some(Command::Completions { shell }) => CliArgs {
+                completions: Some(shell),
+                ..Default::default()
+            },
+        };
+        CliArgs { config, verbose, quiet, ..args }
+    }
+}
+
+/// Parse `args` (including the program name). `--help`/`--version` and
+/// usage errors come back as `clap::Error`, whose `exit()` prints to the
+/// right stream and uses exit code 0 or 2 respectively.
+pub fn try_parse_cli_args(args: Vec<String>) -> Result<CliArgs, clap::Error> {
+    Cli::try_parse_from(args).map(CliArgs::from)
+}
+
+pub fn parse_cli_args(args: Vec<String>) -> Result<CliArgs, String> {
+    try_parse_cli_args(args).map_err(|e| e.render().to_string())
+}
+
+pub fn write_completions(shell: CompletionShell, out: &mut dyn std::io::Write) {
+    let mut command = Cli::command();
+    let name = command.get_name().to_string();
+    clap_complete::generate(clap_complete::Shell::from(shell), &mut command, name, out);
 }
 
 #[cfg(test)]
@@ -122,4 +633,428 @@ mod tests {
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("USAGE"));
     }
+
+    #[test]
+    fn test_flag_not_taken_as_value() {
+        // `--voice` must not be swallowed as the text
+        let args = vec![
+            "app".to_string(),
+            "--text".to_string(),
+            "--voice".to_string(),
+            "nova".to_string(),
+        ];
+        let err = try_parse_cli_args(args).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::InvalidValue);
+    }
+
+    #[test]
+    fn test_text_and_file_conflict() {
+        let args = vec![
+            "app".to_string(),
+            "-t".to_string(),
+            "Hello".to_string(),
+            "-f".to_string(),
+            "notes.txt".to_string(),
+        ];
+        assert!(parse_cli_args(args).is_err());
+    }
+
+    #[test]
+    fn test_unknown_model_rejected() {
+        let args = vec!["app".to_string(), "-t".to_string(), "Hi".to_string(), "-m".to_string(), "tts-2".to_string()];
+        assert!(parse_cli_args(args).is_err());
+    }
+
+    #[test]
+    fn test_version_flag() {
+        let args = vec!["app".to_string(), "--version".to_string()];
+        let err = try_parse_cli_args(args).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::DisplayVersion);
+    }
+
+    #[test]
+    fn test_config_flag_with_and_without_subcommand() {
+        let args = vec!["app".to_string(), "--config".to_string(), "my.toml".to_string(), "stats".to_string()];
+        let parsed = parse_cli_args(args).unwrap();
+        assert_eq!(parsed.config, Some("my.toml".to_string()));
+        assert!(parsed.stats);
+
+        let args = vec![
+            "app".to_string(),
+            "-t".to_string(),
+            "Hi".to_string(),
+            "--speed".to_string(),
+            "1.25".to_string(),
+            "--format".to_string(),
+            "opus".to_string(),
+            "--config".to_string(),
+            "my.toml".to_string(),
+        ];
+        let parsed = parse_cli_args(args).unwrap();
+        assert_eq!(parsed.config, Some("my.toml".to_string()));
+        assert_eq!(parsed.speed, Some(1.25));
+        assert_eq!(parsed.format, Some("opus".to_string()));
+    }
+
+    #[test]
+    fn test_clipboard_flag() {
+        let args = vec!["app".to_string(), "estimate".to_string(), "--clipboard".to_string()];
+        let parsed = parse_cli_args(args).unwrap();
+        assert!(parsed.clipboard);
+        assert!(parsed.estimate);
+
+        // Composes with the other sources rather than conflicting
+        let args = vec!["app".to_string(), "-t".to_string(), "Hi".to_string(), "--clipboard".to_string(), "--no-gui".to_string()];
+        let parsed = parse_cli_args(args).unwrap();
+        assert!(parsed.clipboard);
+        assert_eq!(parsed.text, Some("Hi".to_string()));
+    }
+
+    #[test]
+    fn test_speak_subcommand() {
+        let args = vec![
+            "app".to_string(),
+            "speak".to_string(),
+            "-t".to_string(),
+            "Hello".to_string(),
+            "-v".to_string(),
+            "nova".to_string(),
+            "-o".to_string(),
+            "hello.mp3".to_string(),
+        ];
+        let parsed = parse_cli_args(args).unwrap();
+        assert_eq!(parsed.text, Some("Hello".to_string()));
+        assert_eq!(parsed.voice, Some("nova".to_string()));
+        assert_eq!(parsed.output, Some("hello.mp3".to_string()));
+        assert_eq!(dispatch(&parsed), Dispatch::Headless);
+        assert!(!parsed.json);
+
+        let args = vec!["app".to_string(), "speak".to_string(), "-t".to_string(), "Hi".to_string(), "--json".to_string()];
+        assert!(parse_cli_args(args).unwrap().json);
+    }
+
+    #[test]
+    fn test_report_subcommands() {
+        let parsed = parse_cli_args(vec!["app".to_string(), "stats".to_string()]).unwrap();
+        assert!(parsed.stats);
+        assert_eq!(parsed.days, Some(30));
+
+        let args = vec!["app".to_string(), "history".to_string(), "--limit".to_string(), "5".to_string(), "--json".to_string()];
+        let parsed = parse_cli_args(args).unwrap();
+        assert!(parsed.history);
+        assert_eq!(parsed.limit, Some(5));
+        assert!(parsed.json);
+
+        let args = vec!["app".to_string(), "estimate".to_string(), "-f".to_string(), "book.txt".to_string()];
+        let parsed = parse_cli_args(args).unwrap();
+        assert!(parsed.estimate);
+        assert_eq!(parsed.file, Some("book.txt".to_string()));
+
+        let parsed = parse_cli_args(vec!["app".to_string(), "voices".to_string()]).unwrap();
+        assert!(parsed.voices);
+        assert_eq!(dispatch(&parsed), Dispatch::Headless);
+    }
+
+    #[test]
+    fn test_batch_subcommand() {
+        let args = vec![
+            "app".to_string(),
+            "batch".to_string(),
+            "./articles".to_string(),
+            "--out".to_string(),
+            "./audio".to_string(),
+            "--voice".to_string(),
+            "nova".to_string(),
+            "-r".to_string(),
+            "--glob".to_string(),
+            "*.md".to_string(),
+        ];
+        let parsed = parse_cli_args(args).unwrap();
+        assert_eq!(parsed.batch_dir, Some("./articles".to_string()));
+        assert_eq!(parsed.output, Some("./audio".to_string()));
+        assert_eq!(parsed.voice, Some("nova".to_string()));
+        assert!(parsed.recursive);
+        assert_eq!(parsed.glob, Some("*.md".to_string()));
+        assert_eq!(dispatch(&parsed), Dispatch::Headless);
+
+        // The output directory is required
+        let args = vec!["app".to_string(), "batch".to_string(), "./articles".to_string()];
+        assert!(parse_cli_args(args).is_err());
+    }
+
+    #[test]
+    fn test_verbosity_flags() {
+        let args = vec!["app".to_string(), "speak".to_string(), "-t".to_string(), "Hi".to_string(), "--verbose".to_string(), "--verbose".to_string()];
+        let parsed = parse_cli_args(args).unwrap();
+        assert_eq!(parsed.verbose, 2);
+        assert!(!parsed.quiet);
+
+        let parsed = parse_cli_args(vec!["app".to_string(), "-q".to_string(), "stats".to_string()]).unwrap();
+        assert!(parsed.quiet);
+
+        let args = vec!["app".to_string(), "--quiet".to_string(), "--verbose".to_string()];
+        assert!(parse_cli_args(args).is_err());
+    }
+
+    #[test]
+    fn test_play_flags() {
+        let args = vec!["app".to_string(), "-t".to_string(), "build finished".to_string(), "--play".to_string()];
+        let parsed = parse_cli_args(args).unwrap();
+        assert!(parsed.play);
+        assert_eq!(parsed.output, None);
+        assert_eq!(dispatch(&parsed), Dispatch::Headless);
+
+        let args = vec![
+            "app".to_string(),
+            "speak".to_string(),
+            "-t".to_string(),
+            "Hi".to_string(),
+            "--play".to_string(),
+            "--device".to_string(),
+            "USB Audio".to_string(),
+            "-o".to_string(),
+            "hi.mp3".to_string(),
+        ];
+        let parsed = parse_cli_args(args).unwrap();
+        assert!(parsed.play);
+        assert_eq!(parsed.device, Some("USB Audio".to_string()));
+        assert_eq!(parsed.output, Some("hi.mp3".to_string()));
+
+        // --device only makes sense with --play
+        let args = vec!["app".to_string(), "-t".to_string(), "Hi".to_string(), "--device".to_string(), "x".to_string()];
+        assert!(parse_cli_args(args).is_err());
+
+        let parsed = parse_cli_args(vec!["app".to_string(), "devices".to_string()]).unwrap();
+        assert!(parsed.devices);
+        assert_eq!(dispatch(&parsed), Dispatch::Headless);
+    }
+
+    #[test]
+    fn test_watch_subcommand() {
+        let args = vec![
+            "app".to_string(),
+            "watch".to_string(),
+            "notes.txt".to_string(),
+            "--voice".to_string(),
+            "nova".to_string(),
+            "--play".to_string(),
+        ];
+        let parsed = parse_cli_args(args).unwrap();
+        assert!(parsed.watch);
+        assert_eq!(parsed.file, Some("notes.txt".to_string()));
+        assert_eq!(parsed.voice, Some("nova".to_string()));
+        assert!(parsed.play);
+        assert_eq!(dispatch(&parsed), Dispatch::Headless);
+
+        // The file is required
+        assert!(parse_cli_args(vec!["app".to_string(), "watch".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_forwarded_launch() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "From a file").unwrap();
+        let argv = |args: &[&str]| std::iter::once("app").chain(args.iter().copied()).map(str::to_string).collect();
+
+        let forwarded = forwarded_launch(argv(&["-t", "Hello", "-v", "onyx"]), dir.path(), "nova", true).unwrap();
+        let request = LaunchRequest {
+            text: "Hello".to_string(),
+            voice_id: "onyx".to_string(),
+            auto_generate: true,
+            job_id: None,
+            notice: None,
+        };
+        assert_eq!(forwarded, ForwardedLaunch::Text { request });
+
+        // Relative to the second launch's directory, with the saved voice
+        let forwarded = forwarded_launch(argv(&["--file", "notes.txt"]), dir.path(), "nova", false).unwrap();
+        let ForwardedLaunch::Text { request } = forwarded else {
+            panic!("expected text");
+        };
+        assert_eq!((request.text.as_str(), request.voice_id.as_str()), ("From a file", "nova"));
+        assert!(!request.auto_generate);
+
+        let link = "tts-player://speak?text=Hi";
+        assert_eq!(
+            forwarded_launch(argv(&[link]), dir.path(), "nova", true).unwrap(),
+            ForwardedLaunch::DeepLink { link: link.to_string() }
+        );
+        assert_eq!(forwarded_launch(argv(&[]), dir.path(), "nova", true).unwrap(), ForwardedLaunch::Focus);
+        assert!(forwarded_launch(argv(&["-t", "Hi", "-v", "rachel"]), dir.path(), "nova", true).is_err());
+        assert!(forwarded_launch(argv(&["--file", "missing.txt"]), dir.path(), "nova", true).is_err());
+    }
+
+    #[test]
+    fn test_take_invalid_voice() {
+        let mut args = CliArgs { voice: Some("rachel".to_string()), ..Default::default() };
+        assert_eq!(take_invalid_voice(&mut args), Some("rachel".to_string()));
+        assert_eq!(args.voice, None);
+
+        let mut args = CliArgs { voice: Some("onyx".to_string()), ..Default::default() };
+        assert_eq!(take_invalid_voice(&mut args), None);
+        assert_eq!(args.voice.as_deref(), Some("onyx"));
+        assert_eq!(take_invalid_voice(&mut CliArgs::default()), None);
+    }
+
+    #[test]
+    fn test_forwarded_launch_serialization() {
+        let request = LaunchRequest {
+            text: "Hi".to_string(),
+            voice_id: "nova".to_string(),
+            auto_generate: true,
+            job_id: Some("job".to_string()),
+            notice: None,
+        };
+        let forwarded = ForwardedLaunch::Text { request };
+        let json = serde_json::to_value(&forwarded).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "kind": "text",
+                "request": { "text": "Hi", "voiceId": "nova", "autoGenerate": true, "jobId": "job", "notice": null },
+            })
+        );
+        assert_eq!(serde_json::from_value::<ForwardedLaunch>(json).unwrap(), forwarded);
+        let focus = serde_json::to_string(&ForwardedLaunch::Focus).unwrap();
+        assert_eq!(focus, r#"{"kind":"focus"}"#);
+    }
+
+    #[test]
+    fn test_deep_link_argument() {
+        let args = vec!["app".to_string(), "tts-player://speak?text=Hi".to_string()];
+        let parsed = parse_cli_args(args).unwrap();
+        assert_eq!(parsed.deep_link, Some("tts-player://speak?text=Hi".to_string()));
+        assert_eq!(dispatch(&parsed), Dispatch::Gui);
+
+        // Subcommand names still win over the positional
+        let parsed = parse_cli_args(vec!["app".to_string(), "voices".to_string()]).unwrap();
+        assert!(parsed.voices);
+        assert_eq!(parsed.deep_link, None);
+    }
+
+    #[test]
+    fn test_completions_subcommand() {
+        let args = vec!["app".to_string(), "completions".to_string(), "zsh".to_string()];
+        let parsed = parse_cli_args(args).unwrap();
+        assert_eq!(parsed.completions, Some(CompletionShell::Zsh));
+        assert_eq!(dispatch(&parsed), Dispatch::Headless);
+
+        let args = vec!["app".to_string(), "completions".to_string(), "tcsh".to_string()];
+        let err = try_parse_cli_args(args).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::InvalidValue);
+        assert!(err.render().to_string().contains("powershell"));
+    }
+
+    #[test]
+    fn test_bash_completions_script() {
+        let mut script = Vec::new();
+        write_completions(CompletionShell::Bash, &mut script);
+        let script = String::from_utf8(script).unwrap();
+
+        for subcommand in ["speak", "estimate", "stats", "history", "voices", "completions"] {
+            assert!(script.contains(subcommand), "missing {}", subcommand);
+        }
+        // Value completion for --voice and --model
+        assert!(script.contains("alloy ash coral echo fable onyx nova sage shimmer"));
+        assert!(script.contains("tts-1 tts-1-hd"));
+    }
+
+    #[test]
+    fn test_output_and_no_gui_flags() {
+        let args = vec![
+            "app".to_string(),
+            "-f".to_string(),
+            "notes.txt".to_string(),
+            "-o".to_string(),
+            "notes.mp3".to_string(),
+            "--no-gui".to_string(),
+        ];
+        let parsed = parse_cli_args(args).unwrap();
+        assert_eq!(parsed.file, Some("notes.txt".to_string()));
+        assert_eq!(parsed.output, Some("notes.mp3".to_string()));
+        assert!(parsed.no_gui);
+    }
+
+    #[test]
+    fn test_dispatch_defaults_to_gui() {
+        let args = vec!["app".to_string(), "--text".to_string(), "Hello".to_string()];
+        let parsed = parse_cli_args(args).unwrap();
+        assert_eq!(dispatch(&parsed), Dispatch::Gui);
+        assert_eq!(dispatch(&CliArgs::default()), Dispatch::Gui);
+    }
+
+    #[test]
+    fn test_dispatch_headless() {
+        let no_gui = CliArgs { no_gui: true, ..Default::default() };
+        assert_eq!(dispatch(&no_gui), Dispatch::Headless);
+
+        let with_output = CliArgs {
+            text: Some("Hello".to_string()),
+            output: Some("hello.mp3".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(dispatch(&with_output), Dispatch::Headless);
+
+        let stats = CliArgs { stats: true, ..Default::default() };
+        assert_eq!(dispatch(&stats), Dispatch::Headless);
+    }
+
+    #[test]
+    fn test_dispatch_clipboard_headless() {
+        // The window can't take its text from the flag, so it speaks from the CLI
+        let args = vec!["app".to_string(), "--clipboard".to_string()];
+        let parsed = parse_cli_args(args).unwrap();
+        assert_eq!(dispatch(&parsed), Dispatch::Headless);
+
+        let args = vec!["app".to_string(), "speak".to_string(), "--clipboard".to_string()];
+        assert_eq!(dispatch(&parse_cli_args(args).unwrap()), Dispatch::Headless);
+    }
+
+    #[test]
+    fn test_estimate_flag() {
+        let args = vec![
+            "app".to_string(),
+            "--file".to_string(),
+            "book.txt".to_string(),
+            "--estimate".to_string(),
+        ];
+        let parsed = parse_cli_args(args).unwrap();
+        assert!(parsed.estimate);
+        assert_eq!(dispatch(&parsed), Dispatch::Headless);
+    }
+
+    #[test]
+    fn test_history_flags() {
+        let args = vec![
+            "app".to_string(),
+            "--history".to_string(),
+            "--limit".to_string(),
+            "5".to_string(),
+            "--days".to_string(),
+            "3".to_string(),
+        ];
+        let parsed = parse_cli_args(args).unwrap();
+        assert!(parsed.history);
+        assert_eq!(parsed.limit, Some(5));
+        assert_eq!(parsed.days, Some(3));
+        assert_eq!(dispatch(&parsed), Dispatch::Headless);
+    }
+
+    #[test]
+    fn test_stats_flags() {
+        let args = vec![
+            "app".to_string(),
+            "--stats".to_string(),
+            "--days".to_string(),
+            "7".to_string(),
+            "--json".to_string(),
+        ];
+        let parsed = parse_cli_args(args).unwrap();
+        assert!(parsed.stats);
+        assert_eq!(parsed.days, Some(7));
+        assert!(parsed.json);
+
+        let args = vec!["app".to_string(), "--stats".to_string(), "--days".to_string(), "-3".to_string()];
+        assert!(parse_cli_args(args).is_err());
+    }
 }
\ No newline at end of file
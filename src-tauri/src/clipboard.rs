@@ -0,0 +1,121 @@
+use crate::extract;
+use serde::Serialize;
+
+/// Source of clipboard text for the headless path. The GUI reads the
+/// clipboard through the Tauri plugin; this exists so the CLI can too, and
+/// so the source-selection logic can be tested without a display server.
+pub trait ClipboardSource {
+    /// `Ok(None)` when the clipboard holds nothing or something other than text.
+    fn read_text(&mut self) -> Result<Option<String>, String>;
+
+    /// The HTML a browser or word processor puts beside the plain text;
+    /// `Ok(None)` when there is none
+    fn read_html(&mut self) -> Result<Option<String>, String> {
+        Ok(None)
+    }
+}
+
+/// Which of the clipboard's versions of the text was read
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClipboardFlavor {
+    Html,
+    Text,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClipboardText {
+    pub text: String,
+    pub flavor: ClipboardFlavor,
+}
+
+/// The clipboard's text, from its HTML when there is any: copied from a
+/// browser, the plain version runs paragraphs, list items and headings
+/// together, and the pauses between them are lost. `Ok(None)` like
+/// `read_text`.
+pub fn read(clipboard: &mut dyn ClipboardSource) -> Result<Option<ClipboardText>, String> {
+    match clipboard.read_html() {
+        Ok(Some(html)) => {
+            let text = extract::html_to_text(&html);
+            if !text.is_empty() {
+                return Ok(Some(ClipboardText { text, flavor: ClipboardFlavor::Html }));
+            }
+        }
+        Ok(None) => {}
+        Err(e) => tracing::debug!("Reading the clipboard's text instead of its HTML: {}", e),
+    }
+    let text = clipboard.read_text()?;
+    Ok(text.map(|text| ClipboardText { text, flavor: ClipboardFlavor::Text }))
+}
+
+pub struct SystemClipboard;
+
+impl ClipboardSource for SystemClipboard {
+    fn read_text(&mut self) -> Result<Option<String>, String> {
+        let mut clipboard = arboard::Clipboard::new()
+            .map_err(|e| format!("Failed to open clipboard: {}", e))?;
+        match clipboard.get_text() {
+            Ok(text) => Ok(Some(text)),
+            Err(arboard::Error::ContentNotAvailable) => Ok(None),
+            Err(e) => Err(format!("Failed to read clipboard: {}", e)),
+        }
+    }
+
+    fn read_html(&mut self) -> Result<Option<String>, String> {
+        read_system_html()
+    }
+}
+
+/// The Tauri plugin only reads plain text, so the GUI gets HTML here too
+pub fn read_system_html() -> Result<Option<String>, String> {
+    let mut clipboard = arboard::Clipboard::new().map_err(|e| format!("Failed to open clipboard: {}", e))?;
+    match clipboard.get().html() {
+        Ok(html) => Ok(Some(html)),
+        Err(arboard::Error::ContentNotAvailable) => Ok(None),
+        Err(e) => Err(format!("Failed to read clipboard HTML: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FakeClipboard {
+        text: Option<String>,
+        html: Result<Option<String>, String>,
+    }
+
+    impl ClipboardSource for FakeClipboard {
+        fn read_text(&mut self) -> Result<Option<String>, String> {
+            Ok(self.text.clone())
+        }
+
+        fn read_html(&mut self) -> Result<Option<String>, String> {
+            self.html.clone()
+        }
+    }
+
+    #[test]
+    fn test_prefers_html() {
+        let mut copied = FakeClipboard {
+            text: Some("Title Intro One Two".to_string()),
+            html: Ok(Some("<!--StartFragment--><h2>Title</h2><p>Intro</p><ul><li>One</li><li>Two</li></ul>".to_string())),
+        };
+        let read = read(&mut copied).unwrap().unwrap();
+        assert_eq!(read, ClipboardText { text: "Title\n\nIntro\n\nOne\n\nTwo".to_string(), flavor: ClipboardFlavor::Html });
+        assert_eq!(serde_json::to_value(&read).unwrap()["flavor"], "html");
+    }
+
+    #[test]
+    fn test_falls_back_to_text() {
+        let text = |html| {
+            let mut clipboard = FakeClipboard { text: Some("Plain".to_string()), html };
+            read(&mut clipboard).unwrap().unwrap()
+        };
+        for html in [Ok(None), Ok(Some("<img src=\"chart.png\">".to_string())), Err("no HTML".to_string())] {
+            assert_eq!(text(html), ClipboardText { text: "Plain".to_string(), flavor: ClipboardFlavor::Text });
+        }
+        assert_eq!(read(&mut FakeClipboard { text: None, html: Ok(None) }).unwrap(), None);
+    }
+}
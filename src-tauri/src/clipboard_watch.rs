@@ -0,0 +1,200 @@
+//! Clipboard watch mode: while it is on, text copied anywhere is read
+//! aloud. `main` runs the polling loop; this decides which clipboard
+//! contents are new, settled and worth speaking.
+
+use crate::clipboard::ClipboardSource;
+use crate::watch::{self, Debouncer};
+use std::time::{Duration, Instant};
+
+pub const POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// Selections are often copied a few times over while being adjusted
+pub const DEBOUNCE: Duration = Duration::from_millis(800);
+/// Shorter copies are usually a word or number being pasted somewhere
+pub const MIN_CHARS: usize = 3;
+/// Longer copies are usually whole documents, better generated on purpose
+pub const MAX_CHARS: usize = 20_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LengthFilter {
+    pub min_chars: usize,
+    pub max_chars: usize,
+}
+
+impl Default for LengthFilter {
+    fn default() -> Self {
+        Self { min_chars: MIN_CHARS, max_chars: MAX_CHARS }
+    }
+}
+
+impl LengthFilter {
+    pub fn accepts(&self, text: &str) -> bool {
+        (self.min_chars..=self.max_chars).contains(&text.chars().count())
+    }
+}
+
+#[derive(Debug)]
+pub struct ClipboardWatcher {
+    filter: LengthFilter,
+    debouncer: Debouncer,
+    /// The clipboard at the last poll, to notice when it changes
+    current: Option<u64>,
+    /// Text waiting for the clipboard to settle
+    pending: Option<String>,
+    /// The last text spoken, so copying it again says nothing
+    last_spoken: Option<u64>,
+}
+
+impl ClipboardWatcher {
+    pub fn new(filter: LengthFilter, debounce: Duration) -> Self {
+        Self { filter, debouncer: Debouncer::new(debounce), current: None, pending: None, last_spoken: None }
+    }
+
+    /// Treat what was on the clipboard when watching started as spoken;
+    /// only what is copied afterwards is new
+    pub fn baseline(&mut self, text: Option<&str>) {
+        let text = text.map(str::trim).filter(|text| !text.is_empty());
+        self.current = text.map(watch::hash);
+        self.last_spoken = self.current;
+    }
+
+    /// Read the clipboard at `now` and return the text to speak, once a
+    /// change has stayed put for the debounce period
+    pub fn poll(&mut self, clipboard: &mut dyn ClipboardSource, now: Instant) -> Option<String> {
+        match clipboard.read_text() {
+            Ok(text) => self.observe(text.as_deref(), now),
+            // Counts as unchanged; the next poll tries again
+            Err(e) => tracing::debug!("{}", e),
+        }
+        self.take_ready(now)
+    }
+
+    fn observe(&mut self, text: Option<&str>, now: Instant) {
+        let text = text.map(str::trim).filter(|text| !text.is_empty());
+        let hash = text.map(watch::hash);
+        if hash == self.current {
+            return;
+        }
+        self.current = hash;
+        self.pending = text.map(str::to_string);
+        self.debouncer.event(now);
+    }
+
+    fn take_ready(&mut self, now: Instant) -> Option<String> {
+        if !self.debouncer.take_ready(now) {
+            return None;
+        }
+        let text = self.pending.take()?;
+        let hash = watch::hash(&text);
+        if self.last_spoken == Some(hash) || !self.filter.accepts(&text) {
+            return None;
+        }
+        self.last_spoken = Some(hash);
+        Some(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hands out the scripted values in order, then keeps returning the last
+    struct ScriptedClipboard(Vec<Option<&'static str>>);
+
+    impl ClipboardSource for ScriptedClipboard {
+        fn read_text(&mut self) -> Result<Option<String>, String> {
+            let value = if self.0.len() > 1 { self.0.remove(0) } else { self.0[0] };
+            Ok(value.map(str::to_string))
+        }
+    }
+
+    /// Poll every 500ms through `script`, returning what was spoken
+    fn run(watcher: &mut ClipboardWatcher, script: &[Option<&'static str>]) -> Vec<String> {
+        let start = Instant::now();
+        let mut clipboard = ScriptedClipboard(script.to_vec());
+        (0..script.len() as u64)
+            .filter_map(|tick| watcher.poll(&mut clipboard, start + POLL_INTERVAL * tick as u32))
+            .collect()
+    }
+
+    fn watcher() -> ClipboardWatcher {
+        ClipboardWatcher::new(LengthFilter { min_chars: 3, max_chars: 40 }, Duration::from_millis(800))
+    }
+
+    #[test]
+    fn test_speaks_new_text_once_settled() {
+        let mut watcher = watcher();
+        watcher.baseline(Some("Already there"));
+        let spoken = run(
+            &mut watcher,
+            &[
+                Some("Already there"),
+                // Adjusting a selection: only the final copy is spoken
+                Some("The quick"),
+                Some("The quick brown fox"),
+                Some("The quick brown fox"),
+                Some("The quick brown fox"),
+                Some("The quick brown fox"),
+            ],
+        );
+        assert_eq!(spoken, ["The quick brown fox"]);
+    }
+
+    #[test]
+    fn test_repeats_are_not_spoken_again() {
+        let mut watcher = watcher();
+        let spoken = run(
+            &mut watcher,
+            &[
+                Some("First passage"),
+                Some("First passage"),
+                Some("First passage"),
+                // Copied again after something else, and with extra whitespace
+                None,
+                None,
+                None,
+                Some("  First passage\n"),
+                Some("First passage"),
+                Some("First passage"),
+                Some("Second passage"),
+                Some("Second passage"),
+                Some("Second passage"),
+            ],
+        );
+        assert_eq!(spoken, ["First passage", "Second passage"]);
+    }
+
+    #[test]
+    fn test_length_filter() {
+        let mut watcher = watcher();
+        let spoken = run(
+            &mut watcher,
+            &[
+                Some("42"),
+                Some("42"),
+                Some("42"),
+                Some("This one is far too long to be read out by the watcher"),
+                Some("This one is far too long to be read out by the watcher"),
+                Some("This one is far too long to be read out by the watcher"),
+                Some("Just right"),
+                Some("Just right"),
+                Some("Just right"),
+            ],
+        );
+        assert_eq!(spoken, ["Just right"]);
+        assert!(LengthFilter::default().accepts("Hey"));
+        assert!(!LengthFilter::default().accepts("Hi"));
+    }
+
+    #[test]
+    fn test_unreadable_clipboard_counts_as_unchanged() {
+        struct Failing;
+        impl ClipboardSource for Failing {
+            fn read_text(&mut self) -> Result<Option<String>, String> {
+                Err("no display".to_string())
+            }
+        }
+        let mut watcher = watcher();
+        watcher.baseline(Some("Already there"));
+        assert_eq!(watcher.poll(&mut Failing, Instant::now() + Duration::from_secs(5)), None);
+    }
+}
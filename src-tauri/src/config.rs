@@ -0,0 +1,383 @@
+//! Persistent defaults loaded from `config.toml` in the platform config
+//! directory (`~/.config/tts-player/config.toml` on Linux), or from the file
+//! given with `--config <path>`.
+//!
+//! Values are merged in this order, highest precedence first:
+//!
+//! 1. CLI flags (`--voice`, `--model`, `--speed`, `--format`)
+//! 2. Environment variables: `TTS_PLAYER_VOICE`, `TTS_PLAYER_MODEL`,
+//!    `TTS_PLAYER_SPEED`, `TTS_PLAYER_FORMAT`, `TTS_PLAYER_DATA_DIR`,
+//!    `TTS_PLAYER_FFMPEG`, `TTS_PLAYER_TESSERACT`, `OPENAI_BASE_URL`,
+//!    `OPENAI_ORG_ID`, `OPENAI_PROJECT`
+//! 3. The config file
+//! 4. Built-in defaults
+//!
+//! Unknown keys in the file are reported as warnings and otherwise ignored.
+
+use crate::cli::CliArgs;
+use crate::output_format;
+use crate::tts::{VALID_MODEL_IDS, VALID_VOICE_IDS};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Output formats accepted by the speech endpoint
+pub const SUPPORTED_FORMATS: &[&str] = &["mp3", "opus", "aac", "flac", "wav", "pcm"];
+
+const KNOWN_KEYS: &[&str] = &[
+    "voice", "model", "speed", "format", "data_dir", "ffmpeg_path", "api_base_url", "organization", "project",
+    "max_input_mb", "tesseract_path",
+];
+
+/// Text files, and text pulled out of documents, are refused past this
+/// unless `max_input_mb` says otherwise
+pub const DEFAULT_MAX_INPUT_MB: u64 = 5;
+/// The most `max_input_mb` can be set to
+pub const MAX_INPUT_MB: u64 = 100;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Config {
+    pub voice: String,
+    pub model: String,
+    pub speed: f32,
+    pub format: String,
+    pub data_dir: PathBuf,
+    pub ffmpeg_path: String,
+    pub api_base_url: String,
+    /// Sent as `OpenAI-Organization`, for a key in several organizations
+    pub organization: Option<String>,
+    /// Sent as `OpenAI-Project`
+    pub project: Option<String>,
+    /// Largest text file read, or text extracted from a document (see `documents`)
+    pub max_input_bytes: u64,
+    /// For reading text in images (see `ocr`)
+    pub tesseract_path: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            voice: "nova".to_string(),
+            model: "tts-1-hd".to_string(),
+            speed: 1.0,
+            format: "mp3".to_string(),
+            data_dir: crate::paths::default_data_dir(),
+            ffmpeg_path: "ffmpeg".to_string(),
+            api_base_url: "https://api.openai.com".to_string(),
+            organization: None,
+            project: None,
+            max_input_bytes: DEFAULT_MAX_INPUT_MB * 1024 * 1024,
+            tesseract_path: "tesseract".to_string(),
+        }
+    }
+}
+
+/// The config file as written on disk; every key is optional.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ConfigFile {
+    pub voice: Option<String>,
+    pub model: Option<String>,
+    pub speed: Option<f32>,
+    pub format: Option<String>,
+    pub data_dir: Option<PathBuf>,
+    pub ffmpeg_path: Option<String>,
+    pub api_base_url: Option<String>,
+    pub organization: Option<String>,
+    pub project: Option<String>,
+    pub max_input_mb: Option<u64>,
+    pub tesseract_path: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read { path: PathBuf, message: String },
+    Parse { path: PathBuf, message: String },
+    Invalid(String),
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Read { path, message } => {
+                write!(f, "Failed to read config file {}: {}", path.display(), message)
+            }
+            ConfigError::Parse { path, message } => {
+                write!(f, "Invalid config file {}: {}", path.display(), message)
+            }
+            ConfigError::Invalid(msg) => write!(f, "Invalid configuration: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl ConfigFile {
+    /// Parse file contents, returning warnings for keys we don't recognise
+    pub fn parse(contents: &str, path: &Path) -> Result<(Self, Vec<String>), ConfigError> {
+        let table: toml::Table = contents.parse().map_err(|e: toml::de::Error| ConfigError::Parse {
+            path: path.to_path_buf(),
+            message: e.to_string(),
+        })?;
+
+        let warnings = table
+            .keys()
+            .filter(|key| !KNOWN_KEYS.contains(&key.as_str()))
+            .map(|key| format!("unknown key '{}' in {}", key, path.display()))
+            .collect();
+
+        let file = table.try_into().map_err(|e: toml::de::Error| ConfigError::Parse {
+            path: path.to_path_buf(),
+            message: e.to_string(),
+        })?;
+
+        Ok((file, warnings))
+    }
+
+    pub fn read(path: &Path) -> Result<(Self, Vec<String>), ConfigError> {
+        let contents = std::fs::read_to_string(path).map_err(|e| ConfigError::Read {
+            path: path.to_path_buf(),
+            message: e.to_string(),
+        })?;
+        Self::parse(&contents, path)
+    }
+}
+
+impl Config {
+    pub fn default_path() -> Option<PathBuf> {
+        crate::paths::config_file()
+    }
+
+    /// Load the config for this process: the `--config` file (which must
+    /// exist) or the default file (if present), merged with env and CLI.
+    pub fn load(cli: &CliArgs) -> Result<Self, ConfigError> {
+        let (file, warnings) = match &cli.config {
+            Some(path) => ConfigFile::read(Path::new(path))?,
+            None => match Self::default_path() {
+                Some(path) if path.exists() => ConfigFile::read(&path)?,
+                _ => (ConfigFile::default(), Vec::new()),
+            },
+        };
+
+        for warning in warnings {
+            tracing::warn!("{}", warning);
+        }
+
+        Self::resolve(file, |key| std::env::var(key).ok(), cli)
+    }
+
+    pub fn resolve(
+        file: ConfigFile,
+        env: impl Fn(&str) -> Option<String>,
+        cli: &CliArgs,
+    ) -> Result<Self, ConfigError> {
+        let defaults = Config::default();
+
+        let env_speed = match env("TTS_PLAYER_SPEED") {
+            Some(value) => Some(value.parse::<f32>().map_err(|_| {
+                ConfigError::Invalid(format!("TTS_PLAYER_SPEED must be a number, got '{}'", value))
+            })?),
+            None => None,
+        };
+
+        let config = Config {
+            voice: cli.voice.clone()
+                .or_else(|| env("TTS_PLAYER_VOICE"))
+                .or(file.voice)
+                .unwrap_or(defaults.voice),
+            model: cli.model.clone()
+                .or_else(|| env("TTS_PLAYER_MODEL"))
+                .or(file.model)
+                .unwrap_or(defaults.model),
+            speed: cli.speed
+                .or(env_speed)
+                .or(file.speed)
+                .unwrap_or(defaults.speed),
+            format: cli.format.clone()
+                .or_else(|| env("TTS_PLAYER_FORMAT"))
+                .or(file.format)
+                .unwrap_or(defaults.format),
+            data_dir: env("TTS_PLAYER_DATA_DIR")
+                .map(PathBuf::from)
+                .or(file.data_dir)
+                .unwrap_or(defaults.data_dir),
+            ffmpeg_path: env("TTS_PLAYER_FFMPEG")
+                .or(file.ffmpeg_path)
+                .unwrap_or(defaults.ffmpeg_path),
+            api_base_url: env("OPENAI_BASE_URL")
+                .or(file.api_base_url)
+                .unwrap_or(defaults.api_base_url),
+            organization: env("OPENAI_ORG_ID").or(file.organization).filter(|id| !id.trim().is_empty()),
+            project: env("OPENAI_PROJECT").or(file.project).filter(|id| !id.trim().is_empty()),
+            max_input_bytes: file.max_input_mb.map(|mb| mb.saturating_mul(1024 * 1024)).unwrap_or(defaults.max_input_bytes),
+            tesseract_path: env("TTS_PLAYER_TESSERACT")
+                .or(file.tesseract_path)
+                .unwrap_or(defaults.tesseract_path),
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if !VALID_VOICE_IDS.contains(&self.voice.as_str()) {
+            return Err(ConfigError::Invalid(format!("unknown voice '{}'", self.voice)));
+        }
+        if !VALID_MODEL_IDS.contains(&self.model.as_str()) {
+            return Err(ConfigError::Invalid(format!("unknown model '{}'", self.model)));
+        }
+        if !(0.25..=4.0).contains(&self.speed) {
+            return Err(ConfigError::Invalid(format!("speed must be between 0.25 and 4.0, got {}", self.speed)));
+        }
+        if !SUPPORTED_FORMATS.contains(&self.format.as_str()) && self.format != output_format::AUTO {
+            return Err(ConfigError::Invalid(format!("unsupported format '{}'", self.format)));
+        }
+        if !(1..=MAX_INPUT_MB * 1024 * 1024).contains(&self.max_input_bytes) {
+            return Err(ConfigError::Invalid(format!("max_input_mb must be between 1 and {}", MAX_INPUT_MB)));
+        }
+        for (name, id) in [("organization", &self.organization), ("project", &self.project)] {
+            if let Some(id) = id {
+                validate_account_id(name, id).map_err(ConfigError::Invalid)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn database_path(&self) -> PathBuf {
+        crate::paths::database(&self.data_dir)
+    }
+}
+
+/// An organization or project ID goes into a header as it is, so it can't
+/// hold whitespace or anything else a header can't
+pub fn validate_account_id(name: &str, id: &str) -> Result<(), String> {
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_graphic()) {
+        return Err(format!("{} ID '{}' must be printable ASCII without spaces", name, id));
+    }
+    Ok(())
+}
+
+/// MIME type for audio in one of the `SUPPORTED_FORMATS`
+pub fn mime_type(format: &str) -> &'static str {
+    match format {
+        "opus" => "audio/ogg",
+        "aac" => "audio/aac",
+        "flac" => "audio/flac",
+        "wav" => "audio/wav",
+        "pcm" => "audio/L16",
+        _ => "audio/mpeg",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn env_from(pairs: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let map: HashMap<String, String> = pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |key| map.get(key).cloned()
+    }
+
+    fn parse(contents: &str) -> Result<(ConfigFile, Vec<String>), ConfigError> {
+        ConfigFile::parse(contents, Path::new("config.toml"))
+    }
+
+    #[test]
+    fn test_defaults_without_file_env_or_cli() {
+        let config = Config::resolve(ConfigFile::default(), env_from(&[]), &CliArgs::default()).unwrap();
+        assert_eq!(config, Config::default());
+    }
+
+    #[test]
+    fn test_precedence_cli_over_env_over_file() {
+        let (file, _) = parse("voice = \"onyx\"\nmodel = \"tts-1\"\nspeed = 1.5\nformat = \"opus\"\n").unwrap();
+        let env = env_from(&[("TTS_PLAYER_VOICE", "echo"), ("TTS_PLAYER_SPEED", "2.0")]);
+        let cli = CliArgs { voice: Some("shimmer".to_string()), ..Default::default() };
+
+        let config = Config::resolve(file, env, &cli).unwrap();
+        assert_eq!(config.voice, "shimmer"); // CLI
+        assert_eq!(config.speed, 2.0); // env
+        assert_eq!(config.model, "tts-1"); // file
+        assert_eq!(config.format, "opus"); // file
+        assert_eq!(config.api_base_url, "https://api.openai.com"); // default
+    }
+
+    #[test]
+    fn test_env_overrides_file_paths() {
+        let (file, _) =
+            parse("data_dir = \"/from/file\"\nffmpeg_path = \"/opt/ffmpeg\"\ntesseract_path = \"/opt/tesseract\"\n").unwrap();
+        let env = env_from(&[("TTS_PLAYER_DATA_DIR", "/from/env"), ("OPENAI_BASE_URL", "http://localhost:8080")]);
+
+        let config = Config::resolve(file, env, &CliArgs::default()).unwrap();
+        assert_eq!(config.data_dir, PathBuf::from("/from/env"));
+        assert_eq!(config.database_path(), PathBuf::from("/from/env/tts_usage.db"));
+        assert_eq!(config.ffmpeg_path, "/opt/ffmpeg");
+        assert_eq!(config.tesseract_path, "/opt/tesseract");
+        let env = env_from(&[("TTS_PLAYER_TESSERACT", "/usr/local/bin/tesseract")]);
+        assert_eq!(Config::resolve(ConfigFile::default(), env, &CliArgs::default()).unwrap().tesseract_path, "/usr/local/bin/tesseract");
+        assert_eq!(config.api_base_url, "http://localhost:8080");
+    }
+
+    #[test]
+    fn test_organization_and_project() {
+        let (file, warnings) = parse("organization = \"org-file\"\nproject = \"proj_file\"\n").unwrap();
+        assert!(warnings.is_empty());
+        let env = env_from(&[("OPENAI_ORG_ID", "org-env")]);
+        let config = Config::resolve(file, env, &CliArgs::default()).unwrap();
+        assert_eq!(config.organization.as_deref(), Some("org-env"));
+        assert_eq!(config.project.as_deref(), Some("proj_file"));
+
+        // Set but empty is the same as unset
+        let config = Config::resolve(ConfigFile::default(), env_from(&[("OPENAI_PROJECT", "")]), &CliArgs::default()).unwrap();
+        assert_eq!(config.project, None);
+
+        let env = env_from(&[("OPENAI_ORG_ID", "org 1")]);
+        assert!(Config::resolve(ConfigFile::default(), env, &CliArgs::default()).is_err());
+    }
+
+    #[test]
+    fn test_max_input_size() {
+        assert_eq!(Config::default().max_input_bytes, 5 * 1024 * 1024);
+        let (file, warnings) = parse("max_input_mb = 20\n").unwrap();
+        assert!(warnings.is_empty());
+        let config = Config::resolve(file, env_from(&[]), &CliArgs::default()).unwrap();
+        assert_eq!(config.max_input_bytes, 20 * 1024 * 1024);
+
+        for invalid in ["max_input_mb = 0", "max_input_mb = 101"] {
+            let (file, _) = parse(invalid).unwrap();
+            let err = Config::resolve(file, env_from(&[]), &CliArgs::default()).unwrap_err();
+            assert!(err.to_string().contains("max_input_mb"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_unknown_keys_warn() {
+        let (file, warnings) = parse("voice = \"nova\"\nvolume = 11\n").unwrap();
+        assert_eq!(file.voice.as_deref(), Some("nova"));
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("volume"));
+    }
+
+    #[test]
+    fn test_malformed_file_is_readable_error() {
+        let err = parse("voice = \"nova\nmodel = ").unwrap_err();
+        let message = err.to_string();
+        assert!(message.starts_with("Invalid config file config.toml"), "{}", message);
+
+        let err = parse("speed = \"fast\"").unwrap_err();
+        assert!(err.to_string().contains("speed"), "{}", err);
+    }
+
+    #[test]
+    fn test_invalid_values_rejected() {
+        let (file, _) = parse("voice = \"rachel\"").unwrap();
+        assert!(Config::resolve(file, env_from(&[]), &CliArgs::default()).is_err());
+
+        let env = env_from(&[("TTS_PLAYER_SPEED", "fast")]);
+        let err = Config::resolve(ConfigFile::default(), env, &CliArgs::default()).unwrap_err();
+        assert!(err.to_string().contains("TTS_PLAYER_SPEED"));
+    }
+}
@@ -1,8 +1,45 @@
-use sqlx::{sqlite::SqlitePool, Row};
+use sqlx::{sqlite::SqlitePool, QueryBuilder, Row, Sqlite, SqliteConnection};
 use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use anyhow::Result;
-// use std::path::PathBuf; // Unused import
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use crate::integrity::AudioChecksum;
+use crate::latency::{self, ModelLatency};
+use crate::usage_import::{self, CsvMapping, ImportReport};
+
+/// Where a generation was requested from, stored in `usage_records.source`
+pub const SOURCE_APP: &str = "app";
+pub const SOURCE_CLI: &str = "cli";
+pub const SOURCE_DEEPLINK: &str = "deeplink";
+pub const SOURCE_CLIPBOARD: &str = "clipboard";
+pub const SOURCE_CLIPBOARD_WATCH: &str = "clipboard-watch";
+pub const SOURCE_PROJECT: &str = "project";
+/// Text read from an image (see `ocr`)
+pub const SOURCE_OCR: &str = "ocr";
+/// Brought in from another tool's CSV (see `usage_import`)
+pub const SOURCE_IMPORT: &str = "import";
+/// Every source above, to read one back from a saved job
+pub const SOURCES: &[&str] = &[
+    SOURCE_APP,
+    SOURCE_CLI,
+    SOURCE_DEEPLINK,
+    SOURCE_CLIPBOARD,
+    SOURCE_CLIPBOARD_WATCH,
+    SOURCE_PROJECT,
+    SOURCE_OCR,
+    SOURCE_IMPORT,
+];
+
+/// Profile of usage from before profiles, and while none is chosen
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Kept in `PRAGMA user_version` by `migrate`; bump when the tables change
+pub const SCHEMA_VERSION: i64 = 1;
+
+fn default_profile() -> String {
+    DEFAULT_PROFILE.to_string()
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct UsageRecord {
@@ -14,6 +51,52 @@ pub struct UsageRecord {
     pub model_id: String,
     pub success: bool,
     pub error_message: Option<String>,
+    pub source: String,
+    /// The record this one generated again (see `regenerate`)
+    #[serde(default)]
+    pub regenerated_from: Option<i64>,
+    /// The model asked for when the budget switched the request to a
+    /// cheaper one (see `spend::check_budget`)
+    #[serde(default)]
+    pub downgraded_from: Option<String>,
+    /// Which API key paid for it, without the key (see `keychain::fingerprint`)
+    #[serde(default)]
+    pub key_fingerprint: Option<String>,
+    /// Version of the app that made the request; `None` for records from
+    /// before it was kept, or not made by the app
+    #[serde(default)]
+    pub app_version: Option<String>,
+    /// Which `Settings::profile` the usage counts towards
+    #[serde(default = "default_profile")]
+    pub profile: String,
+    /// From sending the first request to having all the audio; `None` for
+    /// records from before it was kept, or generations not timed
+    #[serde(default)]
+    pub latency_ms: Option<i64>,
+    /// Requests the text was split into
+    #[serde(default)]
+    pub chunk_count: Option<i32>,
+}
+
+/// Which usage records to list; a field left out matches every record
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UsageFilter {
+    /// Only the last this many days
+    pub days: Option<i32>,
+    pub voice_id: Option<String>,
+    pub model_id: Option<String>,
+    pub success: Option<bool>,
+    pub source: Option<String>,
+    pub key_fingerprint: Option<String>,
+    pub profile: Option<String>,
+}
+
+/// The newest records matching a `UsageFilter`, with how many match in all
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsagePage {
+    pub records: Vec<UsageRecord>,
+    pub total: i64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -34,6 +117,9 @@ pub struct UsageStats {
     pub failed_requests: i64,
     pub most_used_voice: String,
     pub daily_usage: Vec<DailyUsage>,
+    /// Of successful generations that were timed
+    #[serde(default)]
+    pub latency_by_model: Vec<ModelLatency>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -43,23 +129,118 @@ pub struct DailyUsage {
     pub request_count: i64,
 }
 
+/// Requests made by one version of the app and how many of them failed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VersionFailures {
+    /// `None` for records from before versions were kept
+    pub app_version: Option<String>,
+    pub request_count: i64,
+    pub failed_count: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelUsage {
+    pub model_id: String,
+    pub character_count: i64,
+    pub request_count: i64,
+}
+
+/// Requests with one voice, for the picker (see `voices::usage_summary`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VoiceUsage {
+    pub voice_id: String,
+    pub request_count: i64,
+    pub character_count: i64,
+    pub last_used: DateTime<Utc>,
+}
+
+/// A generation saved while offline, waiting for the connection to come
+/// back (see `offline`). The lowest `position` goes first.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, sqlx::FromRow)]
+pub struct PendingJob {
+    pub id: i64,
+    pub position: i64,
+    pub text: String,
+    pub voice_id: String,
+    pub model_id: String,
+    pub source: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A job kept until it finishes, with the files of the chunks done so far
+/// by their index (see `recovery`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct SavedJob<T> {
+    pub id: String,
+    pub definition: T,
+    pub chunks: BTreeMap<usize, PathBuf>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A finished generation kept to play again, its audio in `path` (see
+/// `recent`)
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct KeptGeneration {
+    pub id: String,
+    pub title: Option<String>,
+    pub text: String,
+    pub voice_id: String,
+    pub model: String,
+    pub format: String,
+    pub path: String,
+    pub size: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A reading list generated and exported as one (see `projects`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Project {
+    pub id: i64,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// One document in a project. Voice and model fall back to the settings
+/// when `None`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ProjectItem {
+    pub id: i64,
+    pub project_id: i64,
+    pub position: i64,
+    pub title: String,
+    pub text: String,
+    pub voice_id: Option<String>,
+    pub model_id: Option<String>,
+}
+
+#[derive(Clone)]
 pub struct Database {
     pool: SqlitePool,
 }
 
 impl Database {
     pub async fn new() -> Result<Self> {
+        Self::open(&Self::default_path()).await
+    }
+
+    /// Location of the usage database shared by the app and the CLI
+    pub fn default_path() -> PathBuf {
+        crate::paths::database(&crate::paths::default_data_dir())
+    }
+
+    pub async fn open(db_path: &Path) -> Result<Self> {
         // Create database file in app data directory  
-        let app_dir = dirs::home_dir()
-            .unwrap_or_else(|| std::env::temp_dir())
-            .join(".tts-player");
-        
-        std::fs::create_dir_all(&app_dir)?;
-        let db_path = app_dir.join("tts_usage.db");
+        if let Some(app_dir) = db_path.parent() {
+            crate::permissions::create_private_dir(app_dir)?;
+        }
         
         // Use proper SQLite URL with create flag
         let database_url = format!("sqlite://{}?mode=rwc", db_path.display());
         let pool = SqlitePool::connect(&database_url).await?;
+        // It holds a preview of everything read aloud
+        if let Err(e) = crate::permissions::restrict_file(db_path) {
+            tracing::warn!("Failed to restrict access to {}: {}", db_path.display(), e);
+        }
         
         let database = Self { pool };
         database.migrate().await?;
@@ -103,6 +284,153 @@ impl Database {
         .execute(&self.pool)
         .await?;
 
+        // Added after the first release; older databases get the columns with
+        // existing rows attributed to the app and everything else unknown
+        self.add_column("usage_records", "source", "TEXT NOT NULL DEFAULT 'app'").await?;
+        self.add_column("usage_records", "regenerated_from", "INTEGER REFERENCES usage_records(id)").await?;
+        self.add_column("usage_records", "downgraded_from", "TEXT").await?;
+        self.add_column("usage_records", "key_fingerprint", "TEXT").await?;
+        self.add_column("usage_records", "app_version", "TEXT").await?;
+        self.add_column("usage_records", "profile", "TEXT NOT NULL DEFAULT 'default'").await?;
+        self.add_column("usage_records", "latency_ms", "INTEGER").await?;
+        self.add_column("usage_records", "chunk_count", "INTEGER").await?;
+
+        // Preferences from the window, one JSON value per key (see `settings`)
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS settings (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Generations waiting for the connection to come back (see `offline`)
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS pending_jobs (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                position INTEGER NOT NULL,
+                text TEXT NOT NULL,
+                voice_id TEXT NOT NULL,
+                model_id TEXT NOT NULL,
+                source TEXT NOT NULL,
+                created_at DATETIME NOT NULL
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // What the backend keeps across restarts that isn't a preference,
+        // such as a paused job queue
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS app_state (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Jobs not finished yet and which of their chunks are done, so they
+        // can be picked up again after a restart (see `recovery`)
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS saved_jobs (
+                id TEXT PRIMARY KEY,
+                definition TEXT NOT NULL,
+                created_at DATETIME NOT NULL
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS saved_job_chunks (
+                job_id TEXT NOT NULL REFERENCES saved_jobs(id) ON DELETE CASCADE,
+                chunk INTEGER NOT NULL,
+                path TEXT NOT NULL,
+                PRIMARY KEY (job_id, chunk)
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // The last few finished generations, to play again (see `recent`)
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS kept_generations (
+                id TEXT PRIMARY KEY,
+                title TEXT,
+                text TEXT NOT NULL,
+                voice_id TEXT NOT NULL,
+                model TEXT NOT NULL,
+                format TEXT NOT NULL,
+                path TEXT NOT NULL,
+                size INTEGER NOT NULL,
+                created_at DATETIME NOT NULL
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Reading lists and their documents (see `projects`)
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS projects (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL,
+                created_at DATETIME NOT NULL
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS project_items (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                project_id INTEGER NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+                position INTEGER NOT NULL,
+                title TEXT NOT NULL,
+                text TEXT NOT NULL,
+                voice_id TEXT,
+                model_id TEXT
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_project_items_project ON project_items(project_id)")
+            .execute(&self.pool)
+            .await?;
+
+        // What each saved audio file held when it was written (see `integrity`)
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS saved_audio (
+                path TEXT PRIMARY KEY,
+                size INTEGER NOT NULL,
+                sha256 TEXT NOT NULL,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
         // Create indexes for performance
         sqlx::query("CREATE INDEX IF NOT EXISTS idx_usage_timestamp ON usage_records(timestamp)")
             .execute(&self.pool)
@@ -112,113 +440,277 @@ impl Database {
             .execute(&self.pool)
             .await?;
 
+        // Totals per day, profile, voice and model, so stats don't aggregate
+        // every record each time; kept up to date by `record_usage`. One from
+        // before profiles is dropped and rebuilt below.
+        if !self.has_column("daily_summary", "profile").await? {
+            sqlx::query("DROP TABLE IF EXISTS daily_summary")
+                .execute(&self.pool)
+                .await?;
+        }
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS daily_summary (
+                date TEXT NOT NULL,
+                profile TEXT NOT NULL,
+                voice_id TEXT NOT NULL,
+                model_id TEXT NOT NULL,
+                request_count INTEGER NOT NULL,
+                successful_count INTEGER NOT NULL,
+                character_count INTEGER NOT NULL,
+                PRIMARY KEY (date, profile, voice_id, model_id)
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Fills it in for records from before it existed
+        self.verify_daily_summary().await?;
+
+        sqlx::query(&format!("PRAGMA user_version = {}", SCHEMA_VERSION))
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// The `SCHEMA_VERSION` the tables were last brought up to
+    pub async fn schema_version(&self) -> Result<i64> {
+        Ok(sqlx::query_scalar("PRAGMA user_version").fetch_one(&self.pool).await?)
+    }
+
+    /// Fails unless a write would succeed: a row is written and rolled back
+    pub async fn check_writable(&self) -> Result<()> {
+        let mut transaction = self.pool.begin().await?;
+        sqlx::query("INSERT OR REPLACE INTO settings (key, value) VALUES ('health_check', 'null')")
+            .execute(&mut *transaction)
+            .await?;
+        transaction.rollback().await?;
+        Ok(())
+    }
+
+    /// Add `column` to `table` unless an earlier run already has; rows
+    /// already there get the default in `definition`, or NULL
+    async fn add_column(&self, table: &str, column: &str, definition: &str) -> Result<()> {
+        if !self.has_column(table, column).await? {
+            sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))
+                .execute(&self.pool)
+                .await?;
+        }
         Ok(())
     }
 
+    /// Whether `table` has `column`; false when there's no `table`
+    async fn has_column(&self, table: &str, column: &str) -> Result<bool> {
+        let exists = sqlx::query_scalar("SELECT COUNT(*) > 0 FROM pragma_table_info(?) WHERE name = ?")
+            .bind(table)
+            .bind(column)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(exists)
+    }
+
     pub async fn record_usage(&self, record: &UsageRecord) -> Result<i64> {
-        let id = sqlx::query(
+        let mut transaction = self.pool.begin().await?;
+        let id = insert_usage(&mut transaction, record).await?;
+        transaction.commit().await?;
+        Ok(id)
+    }
+
+    /// Add the rows of the CSV at `path` to the usage history, with the
+    /// source `SOURCE_IMPORT`. All or nothing: while any row is invalid the
+    /// report lists them and nothing is written. `dry_run` only checks.
+    pub async fn import_usage_csv(&self, path: &Path, mapping: &CsvMapping, dry_run: bool) -> Result<ImportReport> {
+        let file = std::fs::File::open(path)?;
+        let (records, errors) = usage_import::parse(file, mapping)?;
+        if dry_run || !errors.is_empty() {
+            let imported = if errors.is_empty() { records.len() } else { 0 };
+            return Ok(ImportReport { dry_run, imported, errors });
+        }
+
+        let mut transaction = self.pool.begin().await?;
+        for record in &records {
+            insert_usage(&mut transaction, record).await?;
+        }
+        transaction.commit().await?;
+        Ok(ImportReport { dry_run, imported: records.len(), errors })
+    }
+
+    /// Rebuild `daily_summary` if it no longer adds up to the records, e.g.
+    /// after rows were written by something other than `record_usage`.
+    /// Returns whether it was rebuilt.
+    pub async fn verify_daily_summary(&self) -> Result<bool> {
+        // Requests, successes, characters, and the requests weighted by day
+        // so one counted on the wrong day shows up too
+        let records: (i64, i64, i64, i64) = sqlx::query_as(
             r#"
-            INSERT INTO usage_records (timestamp, text, character_count, voice_id, model_id, success, error_message)
-            VALUES (?, ?, ?, ?, ?, ?, ?)
+            SELECT
+                COUNT(*),
+                COALESCE(SUM(CASE WHEN success THEN 1 ELSE 0 END), 0),
+                COALESCE(SUM(character_count), 0),
+                COALESCE(SUM(CAST(strftime('%Y%m%d', timestamp) AS INTEGER)), 0)
+            FROM usage_records
+            WHERE date(timestamp) IS NOT NULL
             "#
         )
-        .bind(&record.timestamp)
-        .bind(&record.text)
-        .bind(record.character_count)
-        .bind(&record.voice_id)
-        .bind(&record.model_id)
-        .bind(record.success)
-        .bind(&record.error_message)
-        .execute(&self.pool)
-        .await?
-        .last_insert_rowid();
+        .fetch_one(&self.pool)
+        .await?;
+        let summary: (i64, i64, i64, i64) = sqlx::query_as(
+            r#"
+            SELECT
+                COALESCE(SUM(request_count), 0),
+                COALESCE(SUM(successful_count), 0),
+                COALESCE(SUM(character_count), 0),
+                COALESCE(SUM(CAST(strftime('%Y%m%d', date) AS INTEGER) * request_count), 0)
+            FROM daily_summary
+            "#
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        if records == summary {
+            return Ok(false);
+        }
+        tracing::info!("Usage summary out of date, rebuilding");
+        self.rebuild_daily_summary().await?;
+        Ok(true)
+    }
 
-        Ok(id)
+    /// Recompute `daily_summary` from every record
+    pub async fn rebuild_daily_summary(&self) -> Result<()> {
+        let mut transaction = self.pool.begin().await?;
+        sqlx::query("DELETE FROM daily_summary")
+            .execute(&mut *transaction)
+            .await?;
+        sqlx::query(
+            r#"
+            INSERT INTO daily_summary (date, profile, voice_id, model_id, request_count, successful_count, character_count)
+            SELECT
+                date(timestamp),
+                profile,
+                voice_id,
+                model_id,
+                COUNT(*),
+                SUM(CASE WHEN success THEN 1 ELSE 0 END),
+                SUM(character_count)
+            FROM usage_records
+            WHERE date(timestamp) IS NOT NULL
+            GROUP BY date(timestamp), profile, voice_id, model_id
+            "#
+        )
+        .execute(&mut *transaction)
+        .await?;
+        transaction.commit().await?;
+        Ok(())
     }
 
     pub async fn get_usage_records(&self, limit: i32, days: Option<i32>) -> Result<Vec<UsageRecord>> {
-        let query = match days {
-            Some(days) => {
-                sqlx::query_as::<_, UsageRecord>(
-                    r#"
-                    SELECT * FROM usage_records 
-                    WHERE timestamp > datetime('now', '-' || ? || ' days')
-                    ORDER BY timestamp DESC 
-                    LIMIT ?
-                    "#
-                )
-                .bind(days)
-                .bind(limit)
-            }
-            None => {
-                sqlx::query_as::<_, UsageRecord>(
-                    r#"
-                    SELECT * FROM usage_records 
-                    ORDER BY timestamp DESC 
-                    LIMIT ?
-                    "#
-                )
-                .bind(limit)
-            }
-        };
+        self.select_usage_records(&UsageFilter { days, ..UsageFilter::default() }, limit).await
+    }
 
-        let records = query.fetch_all(&self.pool).await?;
+    /// Up to `limit` of the newest records matching `filter`, and the
+    /// number that match
+    pub async fn get_usage_page(&self, filter: &UsageFilter, limit: i32) -> Result<UsagePage> {
+        let mut count = QueryBuilder::new("SELECT COUNT(*) FROM usage_records");
+        push_usage_filter(&mut count, filter);
+        let total: i64 = count.build_query_scalar().fetch_one(&self.pool).await?;
+        let records = self.select_usage_records(filter, limit).await?;
+        Ok(UsagePage { records, total })
+    }
+
+    async fn select_usage_records(&self, filter: &UsageFilter, limit: i32) -> Result<Vec<UsageRecord>> {
+        let mut query = QueryBuilder::new("SELECT * FROM usage_records");
+        push_usage_filter(&mut query, filter);
+        query.push(" ORDER BY timestamp DESC LIMIT ").push_bind(limit);
+        let records = query.build_query_as::<UsageRecord>().fetch_all(&self.pool).await?;
         Ok(records)
     }
 
-    pub async fn get_usage_stats(&self, days: i32) -> Result<UsageStats> {
+    pub async fn get_usage_record(&self, id: i64) -> Result<Option<UsageRecord>> {
+        let record = sqlx::query_as::<_, UsageRecord>("SELECT * FROM usage_records WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(record)
+    }
+
+    /// Characters generated in the last `days` days with the key whose
+    /// fingerprint is `key_fingerprint`
+    pub async fn characters_by_key(&self, key_fingerprint: &str, days: i32) -> Result<i64> {
+        let characters: Option<i64> = sqlx::query_scalar(
+            r#"
+            SELECT SUM(character_count)
+            FROM usage_records
+            WHERE key_fingerprint = ? AND timestamp > datetime('now', '-' || ? || ' days')
+            "#
+        )
+        .bind(key_fingerprint)
+        .bind(days)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(characters.unwrap_or(0))
+    }
+
+    /// Totals for the last `days` days, counted in whole (UTC) days with
+    /// today the last of them, in `profile` or all of them
+    pub async fn get_usage_stats(&self, days: i32, profile: Option<&str>) -> Result<UsageStats> {
         // Total stats
-        let total_row = sqlx::query(
+        let total_row = sqlx::query(&format!(
             r#"
+            {}
             SELECT 
-                COUNT(*) as total_requests,
+                COALESCE(SUM(request_count), 0) as total_requests,
                 SUM(character_count) as total_characters,
-                SUM(CASE WHEN success THEN 1 ELSE 0 END) as successful_requests,
-                SUM(CASE WHEN NOT success THEN 1 ELSE 0 END) as failed_requests
-            FROM usage_records 
-            WHERE timestamp > datetime('now', '-' || ? || ' days')
-            "#
-        )
+                COALESCE(SUM(successful_count), 0) as successful_requests
+            FROM recent_days
+            "#,
+            RECENT_DAYS
+        ))
         .bind(days)
+        .bind(profile)
         .fetch_one(&self.pool)
         .await?;
 
         let total_requests: i64 = total_row.get("total_requests");
         let total_characters: i64 = total_row.get::<Option<i64>, _>("total_characters").unwrap_or(0);
         let successful_requests: i64 = total_row.get("successful_requests");
-        let failed_requests: i64 = total_row.get("failed_requests");
+        let failed_requests = total_requests - successful_requests;
 
         // Most used voice
-        let most_used_voice = sqlx::query(
+        let most_used_voice = sqlx::query(&format!(
             r#"
-            SELECT voice_id, COUNT(*) as usage_count 
-            FROM usage_records 
-            WHERE timestamp > datetime('now', '-' || ? || ' days')
+            {}
+            SELECT voice_id, SUM(request_count) as usage_count 
+            FROM recent_days 
             GROUP BY voice_id 
             ORDER BY usage_count DESC 
             LIMIT 1
-            "#
-        )
+            "#,
+            RECENT_DAYS
+        ))
         .bind(days)
+        .bind(profile)
         .fetch_optional(&self.pool)
         .await?
         .map(|row| row.get::<String, _>("voice_id"))
         .unwrap_or_else(|| "rachel".to_string());
 
         // Daily usage
-        let daily_usage_rows = sqlx::query(
+        let daily_usage_rows = sqlx::query(&format!(
             r#"
+            {}
             SELECT 
-                date(timestamp) as date,
+                date,
                 SUM(character_count) as character_count,
-                COUNT(*) as request_count
-            FROM usage_records 
-            WHERE timestamp > datetime('now', '-' || ? || ' days')
-            GROUP BY date(timestamp)
+                SUM(request_count) as request_count
+            FROM recent_days 
+            GROUP BY date
             ORDER BY date DESC
-            "#
-        )
+            "#,
+            RECENT_DAYS
+        ))
         .bind(days)
+        .bind(profile)
         .fetch_all(&self.pool)
         .await?;
 
@@ -231,6 +723,21 @@ impl Database {
             })
             .collect();
 
+        // Latency, worked out here as SQLite has no percentiles
+        let latencies: Vec<(String, i64)> = sqlx::query_as(
+            r#"
+            SELECT model_id, latency_ms
+            FROM usage_records
+            WHERE success AND latency_ms IS NOT NULL
+              AND timestamp >= date('now', '-' || (?1 - 1) || ' days')
+              AND (?2 IS NULL OR profile = ?2)
+            "#
+        )
+        .bind(days)
+        .bind(profile)
+        .fetch_all(&self.pool)
+        .await?;
+
         Ok(UsageStats {
             total_requests,
             total_characters,
@@ -238,44 +745,236 @@ impl Database {
             failed_requests,
             most_used_voice,
             daily_usage,
+            latency_by_model: latency::by_model(latencies),
         })
     }
 
-    pub async fn cache_user_info(&self, user_info: &UserInfo) -> Result<()> {
-        sqlx::query(
+    /// Requests per voice over the same days as `get_usage_stats`, the most
+    /// used first. Voice ids are as recorded, including ones since retired.
+    pub async fn get_voice_usage(&self, days: i32) -> Result<Vec<VoiceUsage>> {
+        let rows = sqlx::query(
             r#"
-            INSERT OR REPLACE INTO user_info_cache 
-            (id, subscription_tier, character_limit, character_used, characters_remaining, last_updated, reset_date)
-            VALUES (1, ?, ?, ?, ?, ?, ?)
+            SELECT
+                voice_id,
+                COUNT(*) as request_count,
+                SUM(character_count) as character_count,
+                MAX(timestamp) as last_used
+            FROM usage_records
+            WHERE timestamp >= date('now', '-' || (? - 1) || ' days')
+            GROUP BY voice_id
+            ORDER BY request_count DESC, last_used DESC
             "#
         )
-        .bind(&user_info.subscription_tier)
-        .bind(user_info.character_limit)
-        .bind(user_info.character_used)
-        .bind(user_info.characters_remaining)
-        .bind(&user_info.last_updated)
-        .bind(&user_info.reset_date)
-        .execute(&self.pool)
+        .bind(days)
+        .fetch_all(&self.pool)
         .await?;
 
-        Ok(())
+        Ok(rows
+            .into_iter()
+            .map(|row| VoiceUsage {
+                voice_id: row.get("voice_id"),
+                request_count: row.get("request_count"),
+                character_count: row.get::<Option<i64>, _>("character_count").unwrap_or(0),
+                last_used: row.get("last_used"),
+            })
+            .collect())
     }
 
-    pub async fn get_cached_user_info(&self) -> Result<Option<UserInfo>> {
-        let row = sqlx::query(
+    /// Generations of the last `days` days that took at least
+    /// `threshold_ms`, slowest first
+    pub async fn get_slow_requests(&self, threshold_ms: i64, limit: i32, days: i32) -> Result<Vec<UsageRecord>> {
+        let records = sqlx::query_as::<_, UsageRecord>(
             r#"
-            SELECT subscription_tier, character_limit, character_used, characters_remaining, last_updated, reset_date
-            FROM user_info_cache 
-            WHERE id = 1
+            SELECT * FROM usage_records
+            WHERE latency_ms >= ? AND timestamp > datetime('now', '-' || ? || ' days')
+            ORDER BY latency_ms DESC
+            LIMIT ?
             "#
         )
-        .fetch_optional(&self.pool)
+        .bind(threshold_ms)
+        .bind(days)
+        .bind(limit)
+        .fetch_all(&self.pool)
         .await?;
+        Ok(records)
+    }
 
-        match row {
-            Some(row) => Ok(Some(UserInfo {
-                subscription_tier: row.get("subscription_tier"),
-                character_limit: row.get("character_limit"),
+    /// Requests per model over the same days as `get_usage_stats`
+    pub async fn get_model_usage(&self, days: i32, profile: Option<&str>) -> Result<Vec<ModelUsage>> {
+        let rows = sqlx::query(&format!(
+            r#"
+            {}
+            SELECT 
+                model_id,
+                SUM(character_count) as character_count,
+                SUM(request_count) as request_count
+            FROM recent_days 
+            GROUP BY model_id
+            ORDER BY character_count DESC
+            "#,
+            RECENT_DAYS
+        ))
+        .bind(days)
+        .bind(profile)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| ModelUsage {
+                model_id: row.get("model_id"),
+                character_count: row.get::<Option<i64>, _>("character_count").unwrap_or(0),
+                request_count: row.get("request_count"),
+            })
+            .collect())
+    }
+
+    /// Successful requests per model with timestamps in `[start, end)`; an
+    /// open end is unbounded. Failed requests aren't billed, so aren't counted.
+    /// Only `profile`'s, when there is one.
+    pub async fn get_model_usage_between(
+        &self,
+        start: Option<DateTime<Utc>>,
+        end: Option<DateTime<Utc>>,
+        profile: Option<&str>,
+    ) -> Result<Vec<ModelUsage>> {
+        // Rows are stored both as RFC 3339 and as SQLite's own format; datetime()
+        // brings each side to the latter so they compare correctly
+        let format = |time: DateTime<Utc>| time.format("%Y-%m-%d %H:%M:%S").to_string();
+        let rows = sqlx::query(
+            r#"
+            SELECT
+                model_id,
+                SUM(character_count) as character_count,
+                COUNT(*) as request_count
+            FROM usage_records
+            WHERE success
+              AND (?1 IS NULL OR datetime(timestamp) >= datetime(?1))
+              AND (?2 IS NULL OR datetime(timestamp) < datetime(?2))
+              AND (?3 IS NULL OR profile = ?3)
+            GROUP BY model_id
+            ORDER BY character_count DESC
+            "#
+        )
+        .bind(start.map(format))
+        .bind(end.map(format))
+        .bind(profile)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| ModelUsage {
+                model_id: row.get("model_id"),
+                character_count: row.get::<Option<i64>, _>("character_count").unwrap_or(0),
+                request_count: row.get("request_count"),
+            })
+            .collect())
+    }
+
+    /// `get_model_usage_between` over the same days as `get_usage_stats`:
+    /// what those days cost, with failed requests left out
+    pub async fn get_billed_model_usage(&self, days: i32, profile: Option<&str>) -> Result<Vec<ModelUsage>> {
+        let first_day = Utc::now().date_naive() - chrono::Duration::days(days as i64 - 1);
+        self.get_model_usage_between(first_day.and_hms_opt(0, 0, 0).map(|start| start.and_utc()), None, profile).await
+    }
+
+    /// Requests and failures in the last `days` days by app version, the
+    /// most failures first, to tell whether a release broke something
+    pub async fn get_failures_by_version(&self, days: i32) -> Result<Vec<VersionFailures>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT
+                app_version,
+                COUNT(*) as request_count,
+                SUM(CASE WHEN success THEN 0 ELSE 1 END) as failed_count
+            FROM usage_records
+            WHERE timestamp > datetime('now', '-' || ? || ' days')
+            GROUP BY app_version
+            ORDER BY failed_count DESC, app_version DESC
+            "#
+        )
+        .bind(days)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| VersionFailures {
+                app_version: row.get("app_version"),
+                request_count: row.get("request_count"),
+                failed_count: row.get("failed_count"),
+            })
+            .collect())
+    }
+
+    /// Every profile with usage recorded
+    pub async fn get_profiles(&self) -> Result<Vec<String>> {
+        let profiles = sqlx::query_scalar("SELECT DISTINCT profile FROM daily_summary ORDER BY profile")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(profiles)
+    }
+
+    /// Every record with a timestamp in `[start, end)`, oldest first
+    pub async fn get_usage_records_between(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        profile: Option<&str>,
+    ) -> Result<Vec<UsageRecord>> {
+        let format = |time: DateTime<Utc>| time.format("%Y-%m-%d %H:%M:%S").to_string();
+        let records = sqlx::query_as::<_, UsageRecord>(
+            r#"
+            SELECT * FROM usage_records
+            WHERE datetime(timestamp) >= datetime(?1) AND datetime(timestamp) < datetime(?2)
+              AND (?3 IS NULL OR profile = ?3)
+            ORDER BY timestamp
+            "#
+        )
+        .bind(format(start))
+        .bind(format(end))
+        .bind(profile)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
+    pub async fn cache_user_info(&self, user_info: &UserInfo) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT OR REPLACE INTO user_info_cache 
+            (id, subscription_tier, character_limit, character_used, characters_remaining, last_updated, reset_date)
+            VALUES (1, ?, ?, ?, ?, ?, ?)
+            "#
+        )
+        .bind(&user_info.subscription_tier)
+        .bind(user_info.character_limit)
+        .bind(user_info.character_used)
+        .bind(user_info.characters_remaining)
+        .bind(&user_info.last_updated)
+        .bind(&user_info.reset_date)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_cached_user_info(&self) -> Result<Option<UserInfo>> {
+        let row = sqlx::query(
+            r#"
+            SELECT subscription_tier, character_limit, character_used, characters_remaining, last_updated, reset_date
+            FROM user_info_cache 
+            WHERE id = 1
+            "#
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        match row {
+            Some(row) => Ok(Some(UserInfo {
+                subscription_tier: row.get("subscription_tier"),
+                character_limit: row.get("character_limit"),
                 character_used: row.get("character_used"),
                 characters_remaining: row.get("characters_remaining"),
                 last_updated: row.get("last_updated"),
@@ -285,6 +984,454 @@ impl Database {
         }
     }
 
+    pub async fn get_setting<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        let value: Option<String> = sqlx::query_scalar("SELECT value FROM settings WHERE key = ?")
+            .bind(key)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        match value {
+            Some(value) => Ok(Some(serde_json::from_str(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn set_setting<T: Serialize + ?Sized>(&self, key: &str, value: &T) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO settings (key, value, updated_at) VALUES (?, ?, CURRENT_TIMESTAMP)
+            ON CONFLICT(key) DO UPDATE SET value = excluded.value, updated_at = excluded.updated_at
+            "#
+        )
+        .bind(key)
+        .bind(serde_json::to_string(value)?)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_state<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        let value: Option<String> = sqlx::query_scalar("SELECT value FROM app_state WHERE key = ?")
+            .bind(key)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(value.map(|value| serde_json::from_str(&value)).transpose()?)
+    }
+
+    pub async fn set_state<T: Serialize + ?Sized>(&self, key: &str, value: &T) -> Result<()> {
+        sqlx::query("INSERT INTO app_state (key, value) VALUES (?, ?) ON CONFLICT(key) DO UPDATE SET value = excluded.value")
+            .bind(key)
+            .bind(serde_json::to_string(value)?)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Every saved setting, for overlaying onto the defaults
+    pub async fn get_all_settings(&self) -> Result<Vec<(String, serde_json::Value)>> {
+        let rows = sqlx::query("SELECT key, value FROM settings ORDER BY key")
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                let value: String = row.get("value");
+                Ok((row.get("key"), serde_json::from_str(&value)?))
+            })
+            .collect()
+    }
+
+    /// Save a generation for later, after everything already waiting
+    pub async fn add_pending_job(&self, text: &str, voice_id: &str, model_id: &str, source: &str) -> Result<i64> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO pending_jobs (position, text, voice_id, model_id, source, created_at)
+            VALUES ((SELECT COALESCE(MAX(position), 0) + 1 FROM pending_jobs), ?, ?, ?, ?, ?)
+            "#
+        )
+        .bind(text)
+        .bind(voice_id)
+        .bind(model_id)
+        .bind(source)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.last_insert_rowid())
+    }
+
+    /// Waiting generations in the order they will run
+    pub async fn get_pending_jobs(&self) -> Result<Vec<PendingJob>> {
+        let jobs = sqlx::query_as::<_, PendingJob>("SELECT * FROM pending_jobs ORDER BY position, id")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(jobs)
+    }
+
+    pub async fn next_pending_job(&self) -> Result<Option<PendingJob>> {
+        let job = sqlx::query_as::<_, PendingJob>("SELECT * FROM pending_jobs ORDER BY position, id LIMIT 1")
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(job)
+    }
+
+    /// Whether there was such a job
+    pub async fn delete_pending_job(&self, id: i64) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM pending_jobs WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Run the waiting generations in the order of `ids`, which must name
+    /// each of them exactly once
+    pub async fn reorder_pending_jobs(&self, ids: &[i64]) -> Result<()> {
+        let mut transaction = self.pool.begin().await?;
+        let current: Vec<i64> = sqlx::query_scalar("SELECT id FROM pending_jobs")
+            .fetch_all(&mut *transaction)
+            .await?;
+        if !is_permutation(&current, ids) {
+            anyhow::bail!("The new order must list every pending job once");
+        }
+
+        for (position, id) in ids.iter().enumerate() {
+            sqlx::query("UPDATE pending_jobs SET position = ? WHERE id = ?")
+                .bind(position as i64 + 1)
+                .bind(id)
+                .execute(&mut *transaction)
+                .await?;
+        }
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    /// Keep a job until `delete_saved_job`; saving it again changes nothing
+    pub async fn save_job<T: Serialize>(&self, id: &str, definition: &T) -> Result<()> {
+        sqlx::query("INSERT INTO saved_jobs (id, definition, created_at) VALUES (?, ?, ?) ON CONFLICT(id) DO NOTHING")
+            .bind(id)
+            .bind(serde_json::to_string(definition)?)
+            .bind(Utc::now())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Record that chunk `chunk` of saved job `id` is done, its audio in `path`
+    pub async fn save_job_chunk(&self, id: &str, chunk: usize, path: &Path) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO saved_job_chunks (job_id, chunk, path) VALUES (?, ?, ?) \
+             ON CONFLICT(job_id, chunk) DO UPDATE SET path = excluded.path"
+        )
+        .bind(id)
+        .bind(chunk as i64)
+        .bind(path.to_string_lossy())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Every saved job, oldest first
+    pub async fn get_saved_jobs<T: DeserializeOwned>(&self) -> Result<Vec<SavedJob<T>>> {
+        let rows = sqlx::query("SELECT id, definition, created_at FROM saved_jobs ORDER BY created_at, id")
+            .fetch_all(&self.pool)
+            .await?;
+        let chunks = sqlx::query("SELECT job_id, chunk, path FROM saved_job_chunks")
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut jobs = rows
+            .into_iter()
+            .map(|row| {
+                let definition: String = row.get("definition");
+                Ok(SavedJob {
+                    id: row.get("id"),
+                    definition: serde_json::from_str(&definition)?,
+                    chunks: BTreeMap::new(),
+                    created_at: row.get("created_at"),
+                })
+            })
+            .collect::<Result<Vec<SavedJob<T>>>>()?;
+        for chunk in chunks {
+            let job_id: String = chunk.get("job_id");
+            if let Some(job) = jobs.iter_mut().find(|job| job.id == job_id) {
+                let path: String = chunk.get("path");
+                job.chunks.insert(chunk.get::<i64, _>("chunk") as usize, PathBuf::from(path));
+            }
+        }
+        Ok(jobs)
+    }
+
+    /// Whether there was such a job; its chunks go with it
+    pub async fn delete_saved_job(&self, id: &str) -> Result<bool> {
+        let mut transaction = self.pool.begin().await?;
+        sqlx::query("DELETE FROM saved_job_chunks WHERE job_id = ?")
+            .bind(id)
+            .execute(&mut *transaction)
+            .await?;
+        let result = sqlx::query("DELETE FROM saved_jobs WHERE id = ?")
+            .bind(id)
+            .execute(&mut *transaction)
+            .await?;
+        transaction.commit().await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Keep `generation`, replacing one with the same id
+    pub async fn keep_generation(&self, generation: &KeptGeneration) -> Result<()> {
+        sqlx::query(
+            "INSERT OR REPLACE INTO kept_generations (id, title, text, voice_id, model, format, path, size, created_at) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(&generation.id)
+        .bind(&generation.title)
+        .bind(&generation.text)
+        .bind(&generation.voice_id)
+        .bind(&generation.model)
+        .bind(&generation.format)
+        .bind(&generation.path)
+        .bind(generation.size)
+        .bind(generation.created_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Up to `limit` kept generations, newest first
+    pub async fn get_kept_generations(&self, limit: usize) -> Result<Vec<KeptGeneration>> {
+        let generations = sqlx::query_as::<_, KeptGeneration>(
+            "SELECT * FROM kept_generations ORDER BY created_at DESC, rowid DESC LIMIT ?"
+        )
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(generations)
+    }
+
+    pub async fn get_kept_generation(&self, id: &str) -> Result<Option<KeptGeneration>> {
+        let generation = sqlx::query_as::<_, KeptGeneration>("SELECT * FROM kept_generations WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(generation)
+    }
+
+    /// Forget all but the newest `keep` generations. The ones forgotten,
+    /// whose files are the caller's to delete.
+    pub async fn forget_kept_generations_beyond(&self, keep: usize) -> Result<Vec<KeptGeneration>> {
+        let mut transaction = self.pool.begin().await?;
+        let forgotten = sqlx::query_as::<_, KeptGeneration>(
+            "SELECT * FROM kept_generations ORDER BY created_at DESC, rowid DESC LIMIT -1 OFFSET ?"
+        )
+        .bind(keep as i64)
+        .fetch_all(&mut *transaction)
+        .await?;
+        for generation in &forgotten {
+            sqlx::query("DELETE FROM kept_generations WHERE id = ?")
+                .bind(&generation.id)
+                .execute(&mut *transaction)
+                .await?;
+        }
+        transaction.commit().await?;
+        Ok(forgotten)
+    }
+
+    /// Whether there was such a generation
+    pub async fn forget_kept_generation(&self, id: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM kept_generations WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn create_project(&self, name: &str) -> Result<i64> {
+        let result = sqlx::query("INSERT INTO projects (name, created_at) VALUES (?, ?)")
+            .bind(name)
+            .bind(Utc::now())
+            .execute(&self.pool)
+            .await?;
+        Ok(result.last_insert_rowid())
+    }
+
+    pub async fn get_projects(&self) -> Result<Vec<Project>> {
+        let projects = sqlx::query_as::<_, Project>("SELECT * FROM projects ORDER BY created_at, id")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(projects)
+    }
+
+    pub async fn get_project(&self, id: i64) -> Result<Option<Project>> {
+        let project = sqlx::query_as::<_, Project>("SELECT * FROM projects WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(project)
+    }
+
+    /// Whether there was such a project
+    pub async fn rename_project(&self, id: i64, name: &str) -> Result<bool> {
+        let result = sqlx::query("UPDATE projects SET name = ? WHERE id = ?")
+            .bind(name)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Delete the project and its items; whether there was such a project
+    pub async fn delete_project(&self, id: i64) -> Result<bool> {
+        let mut transaction = self.pool.begin().await?;
+        sqlx::query("DELETE FROM project_items WHERE project_id = ?")
+            .bind(id)
+            .execute(&mut *transaction)
+            .await?;
+        let result = sqlx::query("DELETE FROM projects WHERE id = ?")
+            .bind(id)
+            .execute(&mut *transaction)
+            .await?;
+        transaction.commit().await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Add an item after the project's others
+    pub async fn add_project_item(
+        &self,
+        project_id: i64,
+        title: &str,
+        text: &str,
+        voice_id: Option<&str>,
+        model_id: Option<&str>,
+    ) -> Result<i64> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO project_items (project_id, position, title, text, voice_id, model_id)
+            VALUES (?, (SELECT COALESCE(MAX(position), 0) + 1 FROM project_items WHERE project_id = ?), ?, ?, ?, ?)
+            "#
+        )
+        .bind(project_id)
+        .bind(project_id)
+        .bind(title)
+        .bind(text)
+        .bind(voice_id)
+        .bind(model_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.last_insert_rowid())
+    }
+
+    /// Save an item's title, text and overrides; its place stays put.
+    /// Whether there was such an item.
+    pub async fn update_project_item(&self, item: &ProjectItem) -> Result<bool> {
+        let result = sqlx::query("UPDATE project_items SET title = ?, text = ?, voice_id = ?, model_id = ? WHERE id = ?")
+            .bind(&item.title)
+            .bind(&item.text)
+            .bind(&item.voice_id)
+            .bind(&item.model_id)
+            .bind(item.id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Whether there was such an item
+    pub async fn delete_project_item(&self, id: i64) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM project_items WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// The project's items in reading order
+    pub async fn get_project_items(&self, project_id: i64) -> Result<Vec<ProjectItem>> {
+        let items = sqlx::query_as::<_, ProjectItem>(
+            "SELECT * FROM project_items WHERE project_id = ? ORDER BY position, id"
+        )
+        .bind(project_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(items)
+    }
+
+    /// Put the project's items in the order of `ids`, which must name each
+    /// of them exactly once
+    pub async fn reorder_project_items(&self, project_id: i64, ids: &[i64]) -> Result<()> {
+        let mut transaction = self.pool.begin().await?;
+        let current: Vec<i64> = sqlx::query_scalar("SELECT id FROM project_items WHERE project_id = ?")
+            .bind(project_id)
+            .fetch_all(&mut *transaction)
+            .await?;
+        if !is_permutation(&current, ids) {
+            anyhow::bail!("The new order must list every item of the project once");
+        }
+
+        for (position, id) in ids.iter().enumerate() {
+            sqlx::query("UPDATE project_items SET position = ? WHERE id = ?")
+                .bind(position as i64 + 1)
+                .bind(id)
+                .execute(&mut *transaction)
+                .await?;
+        }
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    /// Remember what the file at `path` was written with, replacing what
+    /// an earlier file there was
+    pub async fn record_checksum(&self, path: &Path, checksum: &AudioChecksum) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO saved_audio (path, size, sha256, created_at)
+            VALUES (?, ?, ?, CURRENT_TIMESTAMP)
+            ON CONFLICT (path) DO UPDATE SET
+                size = excluded.size,
+                sha256 = excluded.sha256,
+                created_at = excluded.created_at
+            "#
+        )
+        .bind(path.to_string_lossy())
+        .bind(checksum.size as i64)
+        .bind(&checksum.sha256)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get_checksum(&self, path: &Path) -> Result<Option<AudioChecksum>> {
+        let row: Option<(i64, String)> = sqlx::query_as("SELECT size, sha256 FROM saved_audio WHERE path = ?")
+            .bind(path.to_string_lossy())
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(|(size, sha256)| AudioChecksum { size: size as u64, sha256 }))
+    }
+
+    /// Every file with a checksum, by path
+    pub async fn get_checksums(&self) -> Result<Vec<(PathBuf, AudioChecksum)>> {
+        let rows: Vec<(String, i64, String)> = sqlx::query_as("SELECT path, size, sha256 FROM saved_audio ORDER BY path")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows
+            .into_iter()
+            .map(|(path, size, sha256)| (PathBuf::from(path), AudioChecksum { size: size as u64, sha256 }))
+            .collect())
+    }
+
+    /// Forget the checksums of `path`, and with `recursive` of every file
+    /// under it
+    pub async fn forget_checksums(&self, path: &Path, recursive: bool) -> Result<u64> {
+        let path = path.to_string_lossy();
+        let within = format!("{}{}", path.trim_end_matches(std::path::MAIN_SEPARATOR), std::path::MAIN_SEPARATOR);
+        let result = sqlx::query("DELETE FROM saved_audio WHERE path = ?1 OR (?2 AND substr(path, 1, length(?3)) = ?3)")
+            .bind(&*path)
+            .bind(recursive)
+            .bind(within)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
     pub async fn cleanup_old_records(&self, days: i32) -> Result<u64> {
         let result = sqlx::query(
             r#"
@@ -295,11 +1442,118 @@ impl Database {
         .bind(days)
         .execute(&self.pool)
         .await?;
+        if result.rows_affected() > 0 {
+            self.rebuild_daily_summary().await?;
+        }
 
         Ok(result.rows_affected())
     }
 }
 
+/// `recent_days`: totals per day, voice and model for the last `?1` days,
+/// in the profile `?2` or every profile when it's NULL. Days before today
+/// come from `daily_summary`; today is still changing, so comes from the
+/// records, found through the timestamp index.
+const RECENT_DAYS: &str = r#"
+    WITH recent_days AS (
+        SELECT date, voice_id, model_id, request_count, successful_count, character_count
+        FROM daily_summary
+        WHERE date > date('now', '-' || ?1 || ' days') AND date < date('now')
+          AND (?2 IS NULL OR profile = ?2)
+        UNION ALL
+        SELECT
+            date(timestamp),
+            voice_id,
+            model_id,
+            COUNT(*),
+            SUM(CASE WHEN success THEN 1 ELSE 0 END),
+            SUM(character_count)
+        FROM usage_records
+        WHERE timestamp >= date('now') AND (?2 IS NULL OR profile = ?2)
+        GROUP BY date(timestamp), voice_id, model_id
+    )
+"#;
+
+/// Insert `record` and count it in `daily_summary`
+async fn insert_usage(connection: &mut SqliteConnection, record: &UsageRecord) -> Result<i64> {
+    let id = sqlx::query(
+        r#"
+        INSERT INTO usage_records (timestamp, text, character_count, voice_id, model_id, success, error_message, source, regenerated_from, downgraded_from, key_fingerprint, app_version, profile, latency_ms, chunk_count)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#
+    )
+    .bind(&record.timestamp)
+    .bind(&record.text)
+    .bind(record.character_count)
+    .bind(&record.voice_id)
+    .bind(&record.model_id)
+    .bind(record.success)
+    .bind(&record.error_message)
+    .bind(&record.source)
+    .bind(record.regenerated_from)
+    .bind(&record.downgraded_from)
+    .bind(&record.key_fingerprint)
+    .bind(&record.app_version)
+    .bind(&record.profile)
+    .bind(record.latency_ms)
+    .bind(record.chunk_count)
+    .execute(&mut *connection)
+    .await?
+    .last_insert_rowid();
+
+    sqlx::query(
+        r#"
+        INSERT INTO daily_summary (date, profile, voice_id, model_id, request_count, successful_count, character_count)
+        VALUES (date(?), ?, ?, ?, 1, ?, ?)
+        ON CONFLICT (date, profile, voice_id, model_id) DO UPDATE SET
+            request_count = request_count + 1,
+            successful_count = successful_count + excluded.successful_count,
+            character_count = character_count + excluded.character_count
+        "#
+    )
+    .bind(record.timestamp)
+    .bind(&record.profile)
+    .bind(&record.voice_id)
+    .bind(&record.model_id)
+    .bind(record.success as i64)
+    .bind(record.character_count)
+    .execute(&mut *connection)
+    .await?;
+
+    Ok(id)
+}
+
+/// Whether `requested` names each of `current` exactly once
+fn is_permutation(current: &[i64], requested: &[i64]) -> bool {
+    let mut current = current.to_vec();
+    let mut requested = requested.to_vec();
+    current.sort_unstable();
+    requested.sort_unstable();
+    current == requested
+}
+
+/// `WHERE` for `filter`, every value bound as a parameter
+fn push_usage_filter<'a>(query: &mut QueryBuilder<'a, Sqlite>, filter: &'a UsageFilter) {
+    query.push(" WHERE 1 = 1");
+    if let Some(days) = filter.days {
+        query.push(" AND timestamp > datetime('now', '-' || ").push_bind(days).push(" || ' days')");
+    }
+    for (column, value) in [
+        ("voice_id", &filter.voice_id),
+        ("model_id", &filter.model_id),
+        ("source", &filter.source),
+        ("key_fingerprint", &filter.key_fingerprint),
+        ("profile", &filter.profile),
+    ] {
+        if let Some(value) = value {
+            query.push(format_args!(" AND {} = ", column)).push_bind(value.as_str());
+        }
+    }
+    if let Some(success) = filter.success {
+        query.push(" AND success = ").push_bind(success);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -318,6 +1572,14 @@ mod tests {
             model_id: "eleven_multilingual_v2".to_string(),
             success: true,
             error_message: None,
+            source: SOURCE_APP.to_string(),
+            regenerated_from: None,
+            downgraded_from: None,
+            key_fingerprint: None,
+            app_version: None,
+            profile: DEFAULT_PROFILE.to_string(),
+            latency_ms: None,
+            chunk_count: None,
         };
 
         let id = db.record_usage(&record).await.unwrap();
@@ -344,14 +1606,539 @@ mod tests {
                 model_id: "eleven_multilingual_v2".to_string(),
                 success: i != 2, // Make one fail
                 error_message: if i == 2 { Some("Test error".to_string()) } else { None },
+                source: SOURCE_APP.to_string(),
+                regenerated_from: None,
+                downgraded_from: None,
+                key_fingerprint: None,
+                app_version: None,
+                profile: DEFAULT_PROFILE.to_string(),
+                latency_ms: None,
+                chunk_count: None,
             };
             db.record_usage(&record).await.unwrap();
         }
 
-        let stats = db.get_usage_stats(7).await.unwrap();
+        let stats = db.get_usage_stats(7, None).await.unwrap();
         assert_eq!(stats.total_requests, 5);
         assert_eq!(stats.successful_requests, 4);
         assert_eq!(stats.failed_requests, 1);
         assert_eq!(stats.most_used_voice, "rachel"); // 3 uses vs 2 for adam
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_voice_usage() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = Database::open(&dir.path().join("usage.db")).await.unwrap();
+        let now = Utc::now();
+        // From before the switch to OpenAI, and from before these days
+        let history = [
+            ("rachel", 40, now - chrono::Duration::hours(3)),
+            ("nova", 10, now - chrono::Duration::hours(2)),
+            ("nova", 12, now - chrono::Duration::hours(1)),
+            ("onyx", 5, now),
+            ("onyx", 500, now - chrono::Duration::days(60)),
+        ];
+        for (voice_id, character_count, timestamp) in history {
+            db.record_usage(&UsageRecord {
+                id: None,
+                timestamp,
+                text: "x".repeat(character_count as usize),
+                character_count,
+                voice_id: voice_id.to_string(),
+                model_id: "tts-1".to_string(),
+                success: true,
+                error_message: None,
+                source: SOURCE_APP.to_string(),
+                regenerated_from: None,
+                downgraded_from: None,
+                key_fingerprint: None,
+                app_version: None,
+                profile: DEFAULT_PROFILE.to_string(),
+                latency_ms: None,
+                chunk_count: None,
+            })
+            .await
+            .unwrap();
+        }
+
+        let usage = db.get_voice_usage(30).await.unwrap();
+        let counts: Vec<(&str, i64, i64)> = usage.iter().map(|voice| (voice.voice_id.as_str(), voice.request_count, voice.character_count)).collect();
+        assert_eq!(counts, [("nova", 2, 22), ("onyx", 1, 5), ("rachel", 1, 40)]);
+        assert_eq!(usage[0].last_used.timestamp(), (now - chrono::Duration::hours(1)).timestamp());
+        assert_eq!(db.get_voice_usage(90).await.unwrap()[0].voice_id, "onyx");
+    }
+
+    #[tokio::test]
+    async fn test_source_column_added_to_old_database() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("old.db");
+
+        // Schema as shipped before the source column existed
+        let pool = SqlitePool::connect(&format!("sqlite://{}?mode=rwc", path.display())).await.unwrap();
+        sqlx::query(
+            "CREATE TABLE usage_records (id INTEGER PRIMARY KEY AUTOINCREMENT, timestamp DATETIME DEFAULT CURRENT_TIMESTAMP, \
+             text TEXT NOT NULL, character_count INTEGER NOT NULL, voice_id TEXT NOT NULL, model_id TEXT NOT NULL, \
+             success BOOLEAN NOT NULL, error_message TEXT)"
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query("INSERT INTO usage_records (text, character_count, voice_id, model_id, success) VALUES ('Old', 3, 'nova', 'tts-1', 1)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        pool.close().await;
+
+        let db = Database::open(&path).await.unwrap();
+        db.record_usage(&UsageRecord {
+            id: None,
+            timestamp: Utc::now(),
+            text: "New".to_string(),
+            character_count: 3,
+            voice_id: "nova".to_string(),
+            model_id: "tts-1".to_string(),
+            success: true,
+            error_message: None,
+            source: SOURCE_DEEPLINK.to_string(),
+            regenerated_from: None,
+            downgraded_from: None,
+            key_fingerprint: None,
+            app_version: None,
+            profile: DEFAULT_PROFILE.to_string(),
+            latency_ms: None,
+            chunk_count: None,
+        })
+        .await
+        .unwrap();
+
+        let mut sources: Vec<String> = db.get_usage_records(10, None).await.unwrap().into_iter().map(|r| r.source).collect();
+        sources.sort();
+        assert_eq!(sources, vec!["app", "deeplink"]);
+
+        // Reopening must not try to add the column again
+        Database::open(&path).await.unwrap();
+
+        let old = db.get_usage_records(10, None).await.unwrap().into_iter().find(|r| r.text == "Old").unwrap();
+        assert_eq!(old.regenerated_from, None);
+        assert_eq!(old.downgraded_from, None);
+        assert_eq!(old.key_fingerprint, None);
+        assert_eq!(old.app_version, None);
+        assert_eq!(old.profile, DEFAULT_PROFILE);
+    }
+
+    #[tokio::test]
+    async fn test_app_state_survives_reopening() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("usage.db");
+        let db = Database::open(&path).await.unwrap();
+        assert_eq!(db.get_state::<bool>("queue_paused").await.unwrap(), None);
+        db.set_state("queue_paused", &true).await.unwrap();
+        drop(db);
+
+        let db = Database::open(&path).await.unwrap();
+        assert_eq!(db.get_state::<bool>("queue_paused").await.unwrap(), Some(true));
+        db.set_state("queue_paused", &false).await.unwrap();
+        assert_eq!(db.get_state::<bool>("queue_paused").await.unwrap(), Some(false));
+        // Not a preference, so not among the settings
+        assert!(db.get_all_settings().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_saved_jobs_keep_their_chunks() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = Database::open(&dir.path().join("usage.db")).await.unwrap();
+        db.save_job("a", &"first").await.unwrap();
+        db.save_job("b", &"second").await.unwrap();
+        db.save_job_chunk("a", 1, Path::new("/chunks/a/1")).await.unwrap();
+        db.save_job_chunk("a", 0, Path::new("/chunks/a/0")).await.unwrap();
+        // Saving again keeps the definition and the chunks done
+        db.save_job("a", &"changed").await.unwrap();
+
+        let jobs = db.get_saved_jobs::<String>().await.unwrap();
+        assert_eq!(jobs.iter().map(|job| (job.id.as_str(), job.definition.as_str())).collect::<Vec<_>>(), [("a", "first"), ("b", "second")]);
+        assert_eq!(jobs[0].chunks.keys().copied().collect::<Vec<_>>(), [0, 1]);
+        assert_eq!(jobs[0].chunks[&1], PathBuf::from("/chunks/a/1"));
+        assert!(jobs[1].chunks.is_empty());
+
+        assert!(db.delete_saved_job("a").await.unwrap());
+        assert!(!db.delete_saved_job("a").await.unwrap());
+        let left: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM saved_job_chunks").fetch_one(&db.pool).await.unwrap();
+        assert_eq!(left, 0);
+        assert_eq!(db.get_saved_jobs::<String>().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_app_version_round_trips() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = Database::open(&dir.path().join("usage.db")).await.unwrap();
+        for (version, success) in [(Some("1.4.0"), true), (Some("1.5.0"), false), (Some("1.5.0"), false), (Some("1.5.0"), true), (None, false)] {
+            db.record_usage(&UsageRecord {
+                id: None,
+                timestamp: Utc::now(),
+                text: "Hello".to_string(),
+                character_count: 5,
+                voice_id: "nova".to_string(),
+                model_id: "tts-1".to_string(),
+                success,
+                error_message: (!success).then(|| "HTTP 500".to_string()),
+                source: SOURCE_APP.to_string(),
+                regenerated_from: None,
+                downgraded_from: None,
+                key_fingerprint: None,
+                app_version: version.map(str::to_string),
+                profile: DEFAULT_PROFILE.to_string(),
+                latency_ms: None,
+                chunk_count: None,
+            })
+            .await
+            .unwrap();
+        }
+
+        let records = db.get_usage_records(10, None).await.unwrap();
+        let mut versions: Vec<_> = records.iter().map(|r| r.app_version.as_deref()).collect();
+        versions.sort();
+        assert_eq!(versions, [None, Some("1.4.0"), Some("1.5.0"), Some("1.5.0"), Some("1.5.0")]);
+
+        let by_version = db.get_failures_by_version(7).await.unwrap();
+        let failures = |version: Option<&str>| {
+            let row = by_version.iter().find(|row| row.app_version.as_deref() == version).unwrap();
+            (row.request_count, row.failed_count)
+        };
+        assert_eq!(by_version[0].app_version.as_deref(), Some("1.5.0"));
+        assert_eq!(failures(Some("1.5.0")), (3, 2));
+        assert_eq!(failures(Some("1.4.0")), (1, 0));
+        assert_eq!(failures(None), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn test_usage_filter() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = Database::open(&dir.path().join("usage.db")).await.unwrap();
+        let rows = [
+            ("nova", "tts-1", true, SOURCE_APP),
+            ("nova", "tts-1-hd", true, SOURCE_APP),
+            ("nova", "tts-1", false, SOURCE_APP),
+            ("onyx", "tts-1", true, SOURCE_APP),
+            ("nova", "tts-1", true, SOURCE_CLI),
+            ("nova", "tts-1", true, SOURCE_APP),
+        ];
+        for (i, (voice_id, model_id, success, source)) in rows.into_iter().enumerate() {
+            db.record_usage(&UsageRecord {
+                id: None,
+                timestamp: Utc::now() - chrono::Duration::seconds(i as i64),
+                text: format!("Record {}", i),
+                character_count: 8,
+                voice_id: voice_id.to_string(),
+                model_id: model_id.to_string(),
+                success,
+                error_message: None,
+                source: source.to_string(),
+                regenerated_from: None,
+                downgraded_from: None,
+                key_fingerprint: None,
+                app_version: None,
+                profile: DEFAULT_PROFILE.to_string(),
+                latency_ms: None,
+                chunk_count: None,
+            })
+            .await
+            .unwrap();
+        }
+
+        let filter = UsageFilter {
+            days: Some(1),
+            voice_id: Some("nova".to_string()),
+            model_id: Some("tts-1".to_string()),
+            success: Some(true),
+            source: Some(SOURCE_APP.to_string()),
+            ..UsageFilter::default()
+        };
+        let page = db.get_usage_page(&filter, 1).await.unwrap();
+        assert_eq!(page.total, 2);
+        // Newest first, cut to the limit
+        assert_eq!(page.records.iter().map(|r| r.text.as_str()).collect::<Vec<_>>(), ["Record 0"]);
+
+        let failed = UsageFilter { success: Some(false), ..UsageFilter::default() };
+        assert_eq!(db.get_usage_page(&failed, 10).await.unwrap().records[0].text, "Record 2");
+        assert_eq!(db.get_usage_page(&UsageFilter::default(), 10).await.unwrap().total, 6);
+
+        // Input shaped like SQL is only ever compared as a value
+        for injection in ["nova' OR '1'='1", "nova\"; DROP TABLE usage_records; --", "' OR 1=1 --"] {
+            let filter = UsageFilter { voice_id: Some(injection.to_string()), ..UsageFilter::default() };
+            let page = db.get_usage_page(&filter, 10).await.unwrap();
+            assert_eq!((page.total, page.records.len()), (0, 0), "{}", injection);
+        }
+        assert_eq!(db.get_usage_records(10, None).await.unwrap().len(), 6);
+    }
+
+    #[tokio::test]
+    async fn test_usage_by_profile() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("usage.db");
+        let db = Database::open(&path).await.unwrap();
+        for (profile, model_id, characters, days_ago) in [
+            ("work", "tts-1-hd", 500, 0),
+            ("work", "tts-1-hd", 300, 3),
+            (DEFAULT_PROFILE, "tts-1", 40, 0),
+            (DEFAULT_PROFILE, "tts-1", 60, 1),
+        ] {
+            db.record_usage(&UsageRecord {
+                id: None,
+                timestamp: Utc::now() - chrono::Duration::days(days_ago),
+                text: "Seeded".to_string(),
+                character_count: characters,
+                voice_id: "nova".to_string(),
+                model_id: model_id.to_string(),
+                success: true,
+                error_message: None,
+                source: SOURCE_APP.to_string(),
+                regenerated_from: None,
+                downgraded_from: None,
+                key_fingerprint: None,
+                app_version: None,
+                profile: profile.to_string(),
+                latency_ms: None,
+                chunk_count: None,
+            })
+            .await
+            .unwrap();
+        }
+
+        let work = UsageFilter { profile: Some("work".to_string()), ..UsageFilter::default() };
+        let page = db.get_usage_page(&work, 10).await.unwrap();
+        assert_eq!(page.total, 2);
+        assert!(page.records.iter().all(|r| r.profile == "work"));
+        assert_eq!(db.get_profiles().await.unwrap(), [DEFAULT_PROFILE, "work"]);
+
+        // A summary from before profiles is rebuilt with them
+        sqlx::query("DROP TABLE daily_summary").execute(&db.pool).await.unwrap();
+        sqlx::query("CREATE TABLE daily_summary (date TEXT NOT NULL, voice_id TEXT NOT NULL, model_id TEXT NOT NULL, request_count INTEGER NOT NULL, successful_count INTEGER NOT NULL, character_count INTEGER NOT NULL, PRIMARY KEY (date, voice_id, model_id))")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        let db = Database::open(&path).await.unwrap();
+
+        let stats = db.get_usage_stats(7, Some("work")).await.unwrap();
+        assert_eq!((stats.total_requests, stats.total_characters), (2, 800));
+        let stats = db.get_usage_stats(7, Some(DEFAULT_PROFILE)).await.unwrap();
+        assert_eq!((stats.total_requests, stats.total_characters), (2, 100));
+        assert_eq!(db.get_usage_stats(7, None).await.unwrap().total_characters, 900);
+        let models = db.get_model_usage(7, Some("work")).await.unwrap();
+        assert_eq!(models.iter().map(|m| m.model_id.as_str()).collect::<Vec<_>>(), ["tts-1-hd"]);
+        assert!(db.get_usage_stats(7, Some("nobody")).await.unwrap().daily_usage.is_empty());
+    }
+
+    /// `get_usage_stats` and `get_model_usage` worked out from the records
+    /// alone: totals, busiest voice, (date, characters, requests) per day
+    /// and (model, characters, requests) per model
+    async fn aggregate_directly(db: &Database, days: i32) -> ((i64, i64, i64), String, Vec<(String, i64, i64)>, Vec<(String, i64, i64)>) {
+        const WINDOW: &str = "date(timestamp) > date('now', '-' || ? || ' days')";
+        let totals = sqlx::query_as(&format!(
+            "SELECT COUNT(*), COALESCE(SUM(character_count), 0), COALESCE(SUM(success), 0) FROM usage_records WHERE {}",
+            WINDOW
+        ))
+        .bind(days)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+        let voice = sqlx::query_scalar(&format!(
+            "SELECT voice_id FROM usage_records WHERE {} GROUP BY voice_id ORDER BY COUNT(*) DESC LIMIT 1",
+            WINDOW
+        ))
+        .bind(days)
+        .fetch_optional(&db.pool)
+        .await
+        .unwrap()
+        .unwrap_or_else(|| "rachel".to_string());
+        let daily = sqlx::query_as(&format!(
+            "SELECT date(timestamp), SUM(character_count), COUNT(*) FROM usage_records WHERE {} GROUP BY 1 ORDER BY 1 DESC",
+            WINDOW
+        ))
+        .bind(days)
+        .fetch_all(&db.pool)
+        .await
+        .unwrap();
+        let models = sqlx::query_as(&format!(
+            "SELECT model_id, SUM(character_count), COUNT(*) FROM usage_records WHERE {} GROUP BY 1 ORDER BY 1",
+            WINDOW
+        ))
+        .bind(days)
+        .fetch_all(&db.pool)
+        .await
+        .unwrap();
+        (totals, voice, daily, models)
+    }
+
+    async fn assert_stats_match_records(db: &Database) {
+        for days in [1, 3, 7, 30, 365] {
+            let (totals, voice, daily, models) = aggregate_directly(db, days).await;
+            let stats = db.get_usage_stats(days, None).await.unwrap();
+            assert_eq!((stats.total_requests, stats.total_characters, stats.successful_requests), totals, "{} days", days);
+            assert_eq!(stats.failed_requests, totals.0 - totals.2);
+            assert_eq!(stats.most_used_voice, voice, "{} days", days);
+            let stats_daily: Vec<_> = stats.daily_usage.into_iter().map(|d| (d.date, d.character_count, d.request_count)).collect();
+            assert_eq!(stats_daily, daily, "{} days", days);
+            let mut stats_models: Vec<_> = db
+                .get_model_usage(days, None)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|m| (m.model_id, m.character_count, m.request_count))
+                .collect();
+            stats_models.sort();
+            assert_eq!(stats_models, models, "{} days", days);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_latency_percentiles_and_slow_requests() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = Database::open(&dir.path().join("usage.db")).await.unwrap();
+        let mut seeded = vec![
+            ("tts-1-hd", Some(42_000), true, 10, "Long chapter"),
+            ("tts-1-hd", Some(3000), true, 0, "Paragraph"),
+            ("tts-1", None, true, 0, "Before latency was kept"),
+            ("tts-1", Some(95_000), false, 0, "Timed out"),
+            ("tts-1", Some(61_000), true, 1, "Slow"),
+        ];
+        seeded.extend((1..=9).map(|i| ("tts-1", Some(i * 100), true, 0, "Quick")));
+        for (model_id, latency_ms, success, days_ago, text) in seeded {
+            db.record_usage(&UsageRecord {
+                id: None,
+                timestamp: Utc::now() - chrono::Duration::days(days_ago),
+                text: text.to_string(),
+                character_count: 100,
+                voice_id: "nova".to_string(),
+                model_id: model_id.to_string(),
+                success,
+                error_message: None,
+                source: SOURCE_APP.to_string(),
+                regenerated_from: None,
+                downgraded_from: None,
+                key_fingerprint: None,
+                app_version: None,
+                profile: DEFAULT_PROFILE.to_string(),
+                latency_ms,
+                chunk_count: latency_ms.map(|_| 1),
+            })
+            .await
+            .unwrap();
+        }
+
+        // Failures and untimed records are left out of the percentiles
+        let stats = db.get_usage_stats(7, None).await.unwrap();
+        assert_eq!(
+            stats.latency_by_model,
+            [
+                ModelLatency { model_id: "tts-1".to_string(), request_count: 10, p50_ms: 500, p90_ms: 900, p99_ms: 61_000 },
+                ModelLatency { model_id: "tts-1-hd".to_string(), request_count: 1, p50_ms: 3000, p90_ms: 3000, p99_ms: 3000 },
+            ]
+        );
+
+        let slow = db.get_slow_requests(10_000, 10, 30).await.unwrap();
+        let slow: Vec<_> = slow.iter().map(|r| (r.text.as_str(), r.latency_ms.unwrap())).collect();
+        assert_eq!(slow, [("Timed out", 95_000), ("Slow", 61_000), ("Long chapter", 42_000)]);
+        assert_eq!(db.get_slow_requests(10_000, 1, 7).await.unwrap()[0].text, "Timed out");
+        assert_eq!(db.get_slow_requests(10_000, 10, 7).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_stats_from_daily_summary_match_the_records() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("usage.db");
+        let db = Database::open(&path).await.unwrap();
+        for i in 0..60i64 {
+            let success = i % 7 != 3;
+            db.record_usage(&UsageRecord {
+                id: None,
+                timestamp: Utc::now() - chrono::Duration::hours(i * 11),
+                text: "Seeded".to_string(),
+                character_count: 100 + i as i32,
+                voice_id: ["nova", "nova", "alloy", "echo"][i as usize % 4].to_string(),
+                model_id: ["tts-1", "tts-1-hd"][i as usize % 2].to_string(),
+                success,
+                error_message: (!success).then(|| "HTTP 500".to_string()),
+                source: SOURCE_APP.to_string(),
+                regenerated_from: None,
+                downgraded_from: None,
+                key_fingerprint: None,
+                app_version: None,
+                profile: DEFAULT_PROFILE.to_string(),
+                latency_ms: None,
+                chunk_count: None,
+            })
+            .await
+            .unwrap();
+        }
+        let today = Utc::now().format("%Y-%m-%d").to_string();
+        assert_eq!(db.get_usage_stats(1, None).await.unwrap().daily_usage[0].date, today);
+        assert_stats_match_records(&db).await;
+        assert!(!db.verify_daily_summary().await.unwrap());
+
+        // Rows written behind its back are picked up when the database is
+        // next opened, and a summary that's wrong is rebuilt
+        sqlx::query("INSERT INTO usage_records (timestamp, text, character_count, voice_id, model_id, success) VALUES (datetime('now', '-2 days'), 'Raw', 5000, 'onyx', 'tts-1', 1)")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        let db = Database::open(&path).await.unwrap();
+        assert!(!db.verify_daily_summary().await.unwrap());
+        assert_stats_match_records(&db).await;
+
+        sqlx::query("UPDATE daily_summary SET date = date(date, '-1 day') WHERE voice_id = 'onyx'")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        assert!(db.verify_daily_summary().await.unwrap());
+        assert_stats_match_records(&db).await;
+
+        assert!(db.cleanup_old_records(10).await.unwrap() > 0);
+        assert!(!db.verify_daily_summary().await.unwrap());
+        assert_stats_match_records(&db).await;
+    }
+
+    #[tokio::test]
+    async fn test_import_usage_csv() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = Database::open(&dir.path().join("usage.db")).await.unwrap();
+        let mapping = CsvMapping {
+            timestamp: "Date".to_string(),
+            characters: "Chars".to_string(),
+            voice: "Voice".to_string(),
+            model: "Model".to_string(),
+            success: "Status".to_string(),
+            text: Some("Text".to_string()),
+        };
+
+        // The fixture has malformed rows, so nothing goes in, dry run or not
+        let export = include_str!("../tests/fixtures/usage-export.csv");
+        let malformed = dir.path().join("malformed.csv");
+        std::fs::write(&malformed, export).unwrap();
+        for dry_run in [true, false] {
+            let report = db.import_usage_csv(&malformed, &mapping, dry_run).await.unwrap();
+            assert_eq!((report.dry_run, report.imported), (dry_run, 0));
+            let lines: Vec<_> = report.errors.iter().map(|e| e.line).collect();
+            assert_eq!(lines, [5, 7, 8, 10]);
+        }
+        assert!(db.get_usage_records(10, None).await.unwrap().is_empty());
+
+        // Without them the rest goes in, once the dry run says it would
+        let fixed: Vec<_> = export.lines().enumerate().filter(|(i, _)| ![4, 6, 7, 9].contains(i)).map(|(_, line)| line).collect();
+        let fixed_path = dir.path().join("fixed.csv");
+        std::fs::write(&fixed_path, fixed.join("\n")).unwrap();
+        let report = db.import_usage_csv(&fixed_path, &mapping, true).await.unwrap();
+        assert_eq!((report.imported, report.errors.len()), (5, 0));
+        assert!(db.get_usage_records(10, None).await.unwrap().is_empty());
+
+        let report = db.import_usage_csv(&fixed_path, &mapping, false).await.unwrap();
+        assert_eq!((report.dry_run, report.imported), (false, 5));
+        let records = db.get_usage_records(10, None).await.unwrap();
+        assert_eq!(records.len(), 5);
+        assert!(records.iter().all(|r| r.source == SOURCE_IMPORT));
+        assert_eq!(records.iter().map(|r| r.character_count as i64).sum::<i64>(), 1200 + 800 + 450 + 2000 + 640);
+        assert!(!db.verify_daily_summary().await.unwrap());
+
+        let missing = db.import_usage_csv(&dir.path().join("missing.csv"), &mapping, true).await;
+        assert!(missing.is_err());
+    }
+}
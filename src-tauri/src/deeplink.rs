@@ -0,0 +1,155 @@
+//! `tts-player://speak?text=...&voice=nova` links from other apps and
+//! bookmarklets. Parsing lives here so it can be tested without Tauri.
+
+use crate::tts::{VALID_MODEL_IDS, VALID_VOICE_IDS};
+use reqwest::Url;
+use serde::Serialize;
+
+pub const SCHEME: &str = "tts-player";
+/// Longer links are rejected before parsing
+pub const MAX_URL_LENGTH: usize = 64 * 1024;
+/// Roughly ten minutes of speech; anything longer belongs in a file
+pub const MAX_TEXT_CHARS: usize = 10_000;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SpeakRequest {
+    pub text: String,
+    pub voice: Option<String>,
+    pub model: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DeepLinkError {
+    TooLong(usize),
+    Malformed(String),
+    UnknownAction(String),
+    MissingText,
+    TextTooLong(usize),
+    InvalidVoice(String),
+    InvalidModel(String),
+}
+
+impl std::fmt::Display for DeepLinkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeepLinkError::TooLong(len) => {
+                write!(f, "Link is too long ({} bytes, limit is {})", len, MAX_URL_LENGTH)
+            }
+            DeepLinkError::Malformed(msg) => write!(f, "Invalid link: {}", msg),
+            DeepLinkError::UnknownAction(action) => write!(f, "Unknown link action '{}'", action),
+            DeepLinkError::MissingText => write!(f, "Link has no text to speak"),
+            DeepLinkError::TextTooLong(chars) => {
+                write!(f, "Text is too long for a link ({} characters, limit is {})", chars, MAX_TEXT_CHARS)
+            }
+            DeepLinkError::InvalidVoice(voice) => write!(f, "Invalid voice ID: {}", voice),
+            DeepLinkError::InvalidModel(model) => write!(f, "Invalid model: {}", model),
+        }
+    }
+}
+
+impl std::error::Error for DeepLinkError {}
+
+/// Parse and validate a deep link. Query values are percent-decoded and
+/// `+` is read as a space, as browsers encode form values.
+pub fn parse_deep_link(link: &str) -> Result<SpeakRequest, DeepLinkError> {
+    if link.len() > MAX_URL_LENGTH {
+        return Err(DeepLinkError::TooLong(link.len()));
+    }
+
+    let url = Url::parse(link).map_err(|e| DeepLinkError::Malformed(e.to_string()))?;
+    if url.scheme() != SCHEME {
+        return Err(DeepLinkError::Malformed(format!("expected a {}:// link", SCHEME)));
+    }
+
+    // `tts-player://speak?...` puts the action in the host; accept `tts-player:speak?...` too
+    let action = url.host_str().unwrap_or_else(|| url.path()).trim_matches('/');
+    if action != "speak" {
+        return Err(DeepLinkError::UnknownAction(action.to_string()));
+    }
+
+    let mut text = None;
+    let mut voice = None;
+    let mut model = None;
+    for (key, value) in url.query_pairs() {
+        match key.as_ref() {
+            "text" => text = Some(value.into_owned()),
+            "voice" => voice = Some(value.into_owned()),
+            "model" => model = Some(value.into_owned()),
+            _ => {}
+        }
+    }
+
+    let text = text.filter(|t| !t.trim().is_empty()).ok_or(DeepLinkError::MissingText)?;
+    let chars = text.chars().count();
+    if chars > MAX_TEXT_CHARS {
+        return Err(DeepLinkError::TextTooLong(chars));
+    }
+    if let Some(voice) = &voice {
+        if !VALID_VOICE_IDS.contains(&voice.as_str()) {
+            return Err(DeepLinkError::InvalidVoice(voice.clone()));
+        }
+    }
+    if let Some(model) = &model {
+        if !VALID_MODEL_IDS.contains(&model.as_str()) {
+            return Err(DeepLinkError::InvalidModel(model.clone()));
+        }
+    }
+
+    Ok(SpeakRequest { text, voice, model })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_speak_link() {
+        let request = parse_deep_link("tts-player://speak?text=Hello%20world%21&voice=nova").unwrap();
+        assert_eq!(request.text, "Hello world!");
+        assert_eq!(request.voice.as_deref(), Some("nova"));
+        assert_eq!(request.model, None);
+
+        // Form-style encoding and non-ASCII
+        let request = parse_deep_link("tts-player://speak?text=caf%C3%A9+au+lait&model=tts-1").unwrap();
+        assert_eq!(request.text, "café au lait");
+        assert_eq!(request.model.as_deref(), Some("tts-1"));
+
+        let request = parse_deep_link("tts-player:speak?text=Hi").unwrap();
+        assert_eq!(request.text, "Hi");
+    }
+
+    #[test]
+    fn test_malformed_links() {
+        assert!(matches!(parse_deep_link("not a url"), Err(DeepLinkError::Malformed(_))));
+        assert!(matches!(parse_deep_link("https://speak?text=Hi"), Err(DeepLinkError::Malformed(_))));
+        assert_eq!(
+            parse_deep_link("tts-player://delete?text=Hi"),
+            Err(DeepLinkError::UnknownAction("delete".to_string()))
+        );
+        assert_eq!(parse_deep_link("tts-player://speak"), Err(DeepLinkError::MissingText));
+        assert_eq!(parse_deep_link("tts-player://speak?text=%20%20"), Err(DeepLinkError::MissingText));
+    }
+
+    #[test]
+    fn test_voice_and_model_validated() {
+        assert_eq!(
+            parse_deep_link("tts-player://speak?text=Hi&voice=rachel"),
+            Err(DeepLinkError::InvalidVoice("rachel".to_string()))
+        );
+        assert_eq!(
+            parse_deep_link("tts-player://speak?text=Hi&model=tts-2"),
+            Err(DeepLinkError::InvalidModel("tts-2".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_length_limits() {
+        let link = format!("tts-player://speak?text={}", "a".repeat(MAX_URL_LENGTH));
+        let err = parse_deep_link(&link).unwrap_err();
+        assert!(matches!(err, DeepLinkError::TooLong(_)));
+        assert!(err.to_string().contains("too long"));
+
+        let link = format!("tts-player://speak?text={}", "a".repeat(MAX_TEXT_CHARS + 1));
+        assert_eq!(parse_deep_link(&link), Err(DeepLinkError::TextTooLong(MAX_TEXT_CHARS + 1)));
+    }
+}
@@ -0,0 +1,379 @@
+//! Documents handed to the app as files (dropped on the window): which
+//! extractor reads each one, judged by extension, and how large a file
+//! is accepted before anything is read.
+//!
+//! Text is held to `Config::max_input_bytes` (`max_input_mb` in
+//! config.toml): text files by their size, other documents by the text
+//! that comes out of them. Past that it would cost more to speak than
+//! anyone means to spend.
+
+use crate::encoding::{self, Decoded};
+use crate::warnings::Warning;
+use crate::{docx, epub, markdown, pdf, subtitles};
+use serde::Serialize;
+use std::io::Read;
+use std::path::Path;
+
+/// Containers are mostly images and fonts, so they get more room than the
+/// text in them
+pub const MAX_DOCUMENT_BYTES: u64 = 100 * 1024 * 1024;
+
+/// Everything `DocumentKind::from_path` recognizes, for file dialog filters
+pub const EXTENSIONS: &[&str] = &["txt", "text", "md", "markdown", "pdf", "docx", "epub", "srt", "vtt"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentKind {
+    PlainText,
+    Markdown,
+    Pdf,
+    Docx,
+    Epub,
+    Subtitles,
+}
+
+impl DocumentKind {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "txt" | "text" => Some(DocumentKind::PlainText),
+            "md" | "markdown" => Some(DocumentKind::Markdown),
+            "pdf" => Some(DocumentKind::Pdf),
+            "docx" => Some(DocumentKind::Docx),
+            "epub" => Some(DocumentKind::Epub),
+            "srt" | "vtt" => Some(DocumentKind::Subtitles),
+            _ => None,
+        }
+    }
+
+    /// The largest file of this kind read, with text held to `max_text`
+    pub fn max_bytes(self, max_text: u64) -> u64 {
+        match self {
+            DocumentKind::PlainText | DocumentKind::Markdown | DocumentKind::Subtitles => max_text,
+            DocumentKind::Pdf | DocumentKind::Docx | DocumentKind::Epub => MAX_DOCUMENT_BYTES,
+        }
+    }
+}
+
+/// Payload of the `text:loaded` event
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadedText {
+    pub text: String,
+    /// File name, without the directory
+    pub source: String,
+    pub character_count: usize,
+    /// About how the file was read, e.g. a text file that wasn't UTF-8
+    pub warnings: Vec<Warning>,
+}
+
+/// Payload of the `text:load_error` event
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadFailure {
+    pub source: String,
+    pub reason: String,
+}
+
+/// Read the text of the document at `path` with the extractor its extension
+/// calls for, refusing more than `max_text` bytes of text. Blocking; PDFs
+/// and books can take a while.
+pub fn load(path: &Path, max_text: u64) -> Result<LoadedText, LoadFailure> {
+    let source = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let failure = |reason: String| LoadFailure { source: source.clone(), reason };
+
+    let kind = DocumentKind::from_path(path).ok_or_else(|| {
+        failure(match path.extension() {
+            Some(extension) => format!(".{} files can't be read aloud", extension.to_string_lossy()),
+            None => "Files without an extension can't be read aloud".to_string(),
+        })
+    })?;
+    let size = std::fs::metadata(path).map_err(|e| failure(format!("Failed to read file: {}", e)))?.len();
+    check_size(kind.max_bytes(max_text), size).map_err(failure)?;
+
+    let mut warnings = Vec::new();
+    let text = match kind {
+        DocumentKind::PlainText => read_text(path, max_text).map(|decoded| {
+            warnings = decoded.warnings().into_iter().map(Warning::from).collect();
+            decoded.text
+        }),
+        DocumentKind::Markdown => markdown::extract_text(path).map(|note| note.text).map_err(|e| e.to_string()),
+        DocumentKind::Pdf => pdf::extract_text(path, None, None, true).map(|pdf| pdf.text).map_err(|e| e.to_string()),
+        DocumentKind::Docx => docx::extract_text(path, false).map(|doc| doc.text).map_err(|e| e.to_string()),
+        DocumentKind::Epub => epub::list_chapters(path)
+            .and_then(|book| {
+                let all: Vec<usize> = book.chapters.iter().map(|chapter| chapter.index).collect();
+                epub::extract_chapters(path, &all)
+            })
+            .map(|book| book.text)
+            .map_err(|e| e.to_string()),
+        DocumentKind::Subtitles => subtitles::extract_text(path).map(|subs| subs.text).map_err(|e| e.to_string()),
+    }
+    .map_err(failure)?;
+    if text.len() as u64 > max_text {
+        return Err(failure(format!(
+            "The document has {:.1} MB of text; the limit is {}",
+            megabytes(text.len() as u64),
+            limit_text(max_text)
+        )));
+    }
+
+    let character_count = text.chars().count();
+    Ok(LoadedText { text, source, character_count, warnings })
+}
+
+/// Load what the user picked in an open dialog; `None` if they cancelled.
+/// Picking the file is the permission to read it, wherever it is.
+pub fn open_selected(selection: Option<&Path>, max_text: u64) -> Result<Option<LoadedText>, LoadFailure> {
+    selection.map(|path| load(path, max_text)).transpose()
+}
+
+/// Read a text file another app handed over through `temp_dir`, and delete
+/// it afterwards if `delete_after`. Anything outside `temp_dir` is refused
+/// and left alone: a path that fails the check may not be ours to delete.
+pub fn read_handoff_file(temp_dir: &Path, path: &Path, delete_after: bool, max_text: u64) -> Result<LoadedText, String> {
+    if !crate::file_manager::is_file_within(temp_dir, path) {
+        tracing::warn!("Refused to read {} outside {}", path.display(), temp_dir.display());
+        return Err("Access denied: only files in the temporary directory can be read".to_string());
+    }
+    let decoded = read_text(path, max_text)?;
+    if delete_after {
+        if let Err(e) = std::fs::remove_file(path) {
+            tracing::warn!("Failed to remove handed-over file {}: {}", path.display(), e);
+        }
+    }
+    Ok(LoadedText {
+        character_count: decoded.text.chars().count(),
+        source: path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default(),
+        warnings: decoded.warnings().into_iter().map(Warning::from).collect(),
+        text: decoded.text,
+    })
+}
+
+/// Read a text file of up to `limit` bytes in whatever encoding it was
+/// saved (see `encoding`). The size is checked before reading, and the
+/// read stops past the limit anyway, for a file that grew meanwhile or was
+/// never a regular file (a pipe, /dev/zero).
+pub fn read_text(path: &Path, limit: u64) -> Result<Decoded, String> {
+    let file = std::fs::File::open(path).map_err(|e| format!("Failed to read file: {}", e))?;
+    let size = file.metadata().map_err(|e| format!("Failed to read file: {}", e))?.len();
+    check_size(limit, size)?;
+
+    let mut bytes = Vec::with_capacity(size as usize);
+    file.take(limit + 1).read_to_end(&mut bytes).map_err(|e| format!("Failed to read file: {}", e))?;
+    if bytes.len() as u64 > limit {
+        return Err(format!("File is over {}, the limit for this kind of file", limit_text(limit)));
+    }
+    encoding::decode(&bytes)
+}
+
+fn check_size(limit: u64, size: u64) -> Result<(), String> {
+    if size > limit {
+        return Err(format!(
+            "File is {:.1} MB; the limit for this kind of file is {}",
+            megabytes(size),
+            limit_text(limit)
+        ));
+    }
+    Ok(())
+}
+
+fn megabytes(bytes: u64) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
+
+/// Limits are whole megabytes, except in tests
+fn limit_text(limit: u64) -> String {
+    if limit.is_multiple_of(1024 * 1024) {
+        format!("{} MB", limit / (1024 * 1024))
+    } else {
+        format!("{} bytes", limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const MAX_TEXT: u64 = crate::config::DEFAULT_MAX_INPUT_MB * 1024 * 1024;
+
+    #[test]
+    fn test_kind_by_extension() {
+        let kind = |name: &str| DocumentKind::from_path(Path::new(name));
+        assert_eq!(kind("notes.txt"), Some(DocumentKind::PlainText));
+        assert_eq!(kind("/vault/Plan.MD"), Some(DocumentKind::Markdown));
+        assert_eq!(kind("report.pdf"), Some(DocumentKind::Pdf));
+        assert_eq!(kind("update.docx"), Some(DocumentKind::Docx));
+        assert_eq!(kind("book.epub"), Some(DocumentKind::Epub));
+        assert_eq!(kind("film.en.srt"), Some(DocumentKind::Subtitles));
+        assert_eq!(kind("captions.vtt"), Some(DocumentKind::Subtitles));
+        assert_eq!(kind("photo.png"), None);
+        assert_eq!(kind("README"), None);
+        assert_eq!(kind("old.doc"), None);
+    }
+
+    #[test]
+    fn test_dialog_extensions_are_all_readable() {
+        for extension in EXTENSIONS {
+            let path = format!("file.{}", extension);
+            assert!(DocumentKind::from_path(Path::new(&path)).is_some(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_size_limits() {
+        let limit = |kind: DocumentKind| kind.max_bytes(MAX_TEXT);
+        assert!(check_size(limit(DocumentKind::PlainText), MAX_TEXT).is_ok());
+        assert!(check_size(limit(DocumentKind::PlainText), MAX_TEXT + 1).is_err());
+        assert!(check_size(limit(DocumentKind::Pdf), MAX_TEXT + 1).is_ok());
+        assert!(check_size(limit(DocumentKind::Epub), MAX_DOCUMENT_BYTES + 1).is_err());
+
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("huge.txt");
+        let file = std::fs::File::create(&path).unwrap();
+        file.set_len(12 * 1024 * 1024 + 300 * 1024).unwrap();
+        let failure = load(&path, MAX_TEXT).unwrap_err();
+        assert_eq!(failure.source, "huge.txt");
+        assert_eq!(failure.reason, "File is 12.3 MB; the limit for this kind of file is 5 MB");
+        // A larger setting lets it through to the encoding check
+        assert!(load(&path, 20 * 1024 * 1024).unwrap_err().reason.contains("binary"));
+    }
+
+    #[test]
+    fn test_read_stops_at_the_limit() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("notes.txt");
+        std::fs::write(&path, "Twenty-four bytes long.\n").unwrap();
+        assert_eq!(read_text(&path, 24).unwrap().text, "Twenty-four bytes long.\n");
+        assert_eq!(read_text(&path, 23).unwrap_err(), "File is 0.0 MB; the limit for this kind of file is 23 bytes");
+
+        // No size up front, so only the bounded read catches it
+        #[cfg(unix)]
+        {
+            let endless = read_text(Path::new("/dev/zero"), 1024).unwrap_err();
+            assert_eq!(endless, "File is over 1024 bytes, the limit for this kind of file");
+        }
+    }
+
+    #[test]
+    fn test_extracted_text_is_held_to_the_limit() {
+        let temp_dir = TempDir::new().unwrap();
+        let book = temp_dir.path().join("lighthouse.epub");
+        std::fs::write(&book, include_bytes!("../tests/fixtures/lighthouse.epub")).unwrap();
+        let length = load(&book, MAX_TEXT).unwrap().text.len() as u64;
+
+        let failure = load(&book, length - 1).unwrap_err();
+        assert!(failure.reason.starts_with("The document has 0.0 MB of text; the limit is"), "{}", failure.reason);
+    }
+
+    #[test]
+    fn test_load_dispatches_to_extractor() {
+        let temp_dir = TempDir::new().unwrap();
+        let note = temp_dir.path().join("note.md");
+        std::fs::write(&note, "# Title\n\nSome *emphasis* and `code`.\n\n```\nskipped\n```\n").unwrap();
+        let loaded = load(&note, MAX_TEXT).unwrap();
+        assert_eq!(loaded.text, "Title\n\nSome emphasis and code.");
+        assert_eq!(loaded.source, "note.md");
+        assert_eq!(loaded.character_count, 30);
+
+        let subtitles = temp_dir.path().join("interview.SRT");
+        std::fs::write(&subtitles, include_str!("../tests/fixtures/interview.srt")).unwrap();
+        assert!(load(&subtitles, MAX_TEXT).unwrap().text.starts_with("Where were you last night?"));
+
+        let image = temp_dir.path().join("photo.png");
+        std::fs::write(&image, [0u8; 4]).unwrap();
+        assert_eq!(load(&image, MAX_TEXT).unwrap_err().reason, ".png files can't be read aloud");
+    }
+
+    #[test]
+    fn test_plain_text_in_other_encodings() {
+        let temp_dir = TempDir::new().unwrap();
+        let exported = temp_dir.path().join("exported.txt");
+        std::fs::write(&exported, include_bytes!("../tests/fixtures/windows-1252.txt")).unwrap();
+        let loaded = load(&exported, MAX_TEXT).unwrap();
+        assert!(loaded.text.starts_with("Café crème"));
+        assert_eq!(loaded.warnings.len(), 1);
+        assert_eq!(loaded.warnings[0].data["encoding"], "windows-1252");
+
+        let notepad = temp_dir.path().join("notepad.txt");
+        std::fs::write(&notepad, include_bytes!("../tests/fixtures/utf16le-bom.txt")).unwrap();
+        let loaded = load(&notepad, MAX_TEXT).unwrap();
+        assert!(loaded.text.starts_with("Grüße aus Köln."));
+        assert!(loaded.warnings.is_empty());
+
+        let renamed = temp_dir.path().join("book.txt");
+        std::fs::write(&renamed, include_bytes!("../tests/fixtures/lighthouse.epub")).unwrap();
+        assert!(load(&renamed, MAX_TEXT).unwrap_err().reason.contains("binary"));
+    }
+
+    #[test]
+    fn test_open_selected() {
+        assert!(open_selected(None, MAX_TEXT).unwrap().is_none());
+
+        let temp_dir = TempDir::new().unwrap();
+        let picked = temp_dir.path().join("chosen.txt");
+        std::fs::write(&picked, "Read me.").unwrap();
+        let opened = open_selected(Some(&picked), MAX_TEXT).unwrap().unwrap();
+        assert_eq!((opened.text.as_str(), opened.source.as_str()), ("Read me.", "chosen.txt"));
+
+        std::fs::File::create(&picked).unwrap().set_len(MAX_TEXT + 1).unwrap();
+        assert!(open_selected(Some(&picked), MAX_TEXT).is_err());
+    }
+
+    /// A real file in the real temp directory, named the way the OS hands it
+    /// out (on macOS under /var, which is really /private/var; on Windows
+    /// often through an 8.3 name such as RUNNER~1)
+    #[test]
+    fn test_handoff_file_in_system_temp_dir() {
+        let temp_dir = std::env::temp_dir();
+        let handoff = tempfile::Builder::new().suffix(".txt").tempfile_in(&temp_dir).unwrap();
+        std::fs::write(handoff.path(), "Handed over.").unwrap();
+
+        assert_eq!(read_handoff_file(&temp_dir, handoff.path(), false, MAX_TEXT).unwrap().text, "Handed over.");
+        let canonical = handoff.path().canonicalize().unwrap();
+        assert_eq!(read_handoff_file(&temp_dir, &canonical, false, MAX_TEXT).unwrap().text, "Handed over.");
+        let canonical_dir = temp_dir.canonicalize().unwrap();
+        assert_eq!(read_handoff_file(&canonical_dir, handoff.path(), false, MAX_TEXT).unwrap().text, "Handed over.");
+
+        #[cfg(target_os = "macos")]
+        {
+            let spelled = |path: &Path| path.to_string_lossy().replacen("/private/var/", "/var/", 1);
+            let unresolved = std::path::PathBuf::from(spelled(&canonical));
+            assert!(unresolved.starts_with("/var"));
+            assert_eq!(read_handoff_file(&canonical_dir, &unresolved, false, MAX_TEXT).unwrap().text, "Handed over.");
+        }
+
+        #[cfg(windows)]
+        {
+            let upper = std::path::PathBuf::from(canonical.to_string_lossy().to_uppercase());
+            assert_eq!(read_handoff_file(&temp_dir, &upper, false, MAX_TEXT).unwrap().text, "Handed over.");
+        }
+
+        let path = handoff.path().to_path_buf();
+        read_handoff_file(&temp_dir, &path, true, MAX_TEXT).unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_handoff_refuses_outside_files() {
+        let handoff_dir = TempDir::new().unwrap();
+        let elsewhere = TempDir::new().unwrap();
+        let outside = elsewhere.path().join("private.txt");
+        std::fs::write(&outside, "Keep out.").unwrap();
+
+        let escaped = handoff_dir.path().join("..").join(elsewhere.path().file_name().unwrap()).join("private.txt");
+        for path in [&outside, &escaped] {
+            let err = read_handoff_file(handoff_dir.path(), path, true, MAX_TEXT).unwrap_err();
+            assert!(err.starts_with("Access denied"), "{}", err);
+        }
+        assert!(outside.exists(), "a refused file is never deleted");
+
+        #[cfg(unix)]
+        {
+            let link = handoff_dir.path().join("link.txt");
+            std::os::unix::fs::symlink(&outside, &link).unwrap();
+            assert!(read_handoff_file(handoff_dir.path(), &link, true, MAX_TEXT).is_err());
+            assert!(outside.exists());
+        }
+    }
+}
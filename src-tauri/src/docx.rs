@@ -0,0 +1,255 @@
+//! Word documents (.docx): paragraphs from `word/document.xml` with tracked
+//! changes accepted, table rows read cell by cell, and optionally the
+//! headers, footers and notes that live in parts of their own.
+
+use crate::extract::{self, ExtractError};
+use crate::tts::{GenerationEstimate, TTSService};
+use serde::Serialize;
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+/// Refuse parts that inflate past this; no real document comes close
+const MAX_ENTRY_BYTES: u64 = 32 * 1024 * 1024;
+
+const WORD_NAMESPACE: &str = "http://schemas.openxmlformats.org/wordprocessingml/2006/main";
+
+/// Start of an OLE compound file, which is how Word stores password-protected
+/// documents (and the old .doc format)
+const COMPOUND_FILE_MAGIC: &[u8] = &[0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1];
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocxText {
+    pub text: String,
+    /// Length of `text` in characters (not bytes)
+    pub character_count: usize,
+    pub estimate: GenerationEstimate,
+}
+
+/// Read the document at `path`. Headers, footers, footnotes and endnotes are
+/// left out unless `include_extras` is set.
+pub fn extract_text(path: &Path, include_extras: bool) -> Result<DocxText, ExtractError> {
+    let bytes = std::fs::read(path).map_err(|e| ExtractError::Io(e.to_string()))?;
+    extract_text_from_bytes(bytes, include_extras)
+}
+
+pub fn extract_text_from_bytes(bytes: Vec<u8>, include_extras: bool) -> Result<DocxText, ExtractError> {
+    if bytes.starts_with(COMPOUND_FILE_MAGIC) {
+        return Err(if contains_utf16(&bytes, "EncryptedPackage") {
+            ExtractError::Encrypted
+        } else {
+            ExtractError::Unreadable("this is an old-style .doc file; save it as .docx and try again".to_string())
+        });
+    }
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes))
+        .map_err(|e| ExtractError::Unreadable(format!("not a DOCX file: {}", e)))?;
+
+    let mut paragraphs = Vec::new();
+    if include_extras {
+        // The same header is often repeated for first, odd and even pages
+        let mut headers = Vec::new();
+        for part in parts_named(&archive, "header") {
+            for paragraph in read_part(&mut archive, &part)? {
+                if !headers.contains(&paragraph) {
+                    headers.push(paragraph);
+                }
+            }
+        }
+        paragraphs.extend(headers);
+    }
+    paragraphs.extend(read_part(&mut archive, "word/document.xml")?);
+    if include_extras {
+        for part in ["word/footnotes.xml", "word/endnotes.xml"] {
+            if archive.by_name(part).is_ok() {
+                paragraphs.extend(read_part(&mut archive, part)?);
+            }
+        }
+        let mut footers = Vec::new();
+        for part in parts_named(&archive, "footer") {
+            for paragraph in read_part(&mut archive, &part)? {
+                if !footers.contains(&paragraph) {
+                    footers.push(paragraph);
+                }
+            }
+        }
+        paragraphs.extend(footers);
+    }
+
+    // Each part is capped, but a package can hold any number of them
+    extract::check_length(paragraphs.iter().map(|paragraph| paragraph.len() + 2).sum())?;
+    let text = paragraphs.join("\n\n");
+    if text.is_empty() {
+        return Err(ExtractError::NoText);
+    }
+    let character_count = text.chars().count();
+    let estimate = TTSService::estimate(&text);
+    Ok(DocxText { text, character_count, estimate })
+}
+
+/// `word/<kind>1.xml`, `word/<kind>2.xml`, … in order
+fn parts_named(archive: &zip::ZipArchive<Cursor<Vec<u8>>>, kind: &str) -> Vec<String> {
+    let prefix = format!("word/{}", kind);
+    let mut parts: Vec<_> = archive
+        .file_names()
+        .filter(|name| {
+            name.strip_prefix(&prefix)
+                .and_then(|rest| rest.strip_suffix(".xml"))
+                .is_some_and(|number| number.chars().all(|c| c.is_ascii_digit()))
+        })
+        .map(str::to_string)
+        .collect();
+    parts.sort_by_key(|name| (name.len(), name.clone()));
+    parts
+}
+
+/// The non-empty paragraphs of one part of the package
+fn read_part(archive: &mut zip::ZipArchive<Cursor<Vec<u8>>>, name: &str) -> Result<Vec<String>, ExtractError> {
+    let entry = archive
+        .by_name(name)
+        .map_err(|_| ExtractError::Unreadable(format!("missing {}", name)))?;
+    if entry.size() > MAX_ENTRY_BYTES {
+        return Err(ExtractError::Unreadable(format!("{} is too large", name)));
+    }
+    let mut bytes = Vec::with_capacity(entry.size() as usize);
+    entry
+        .take(MAX_ENTRY_BYTES)
+        .read_to_end(&mut bytes)
+        .map_err(|e| ExtractError::Unreadable(format!("{}: {}", name, e)))?;
+    let xml = String::from_utf8_lossy(&bytes);
+    let doc = roxmltree::Document::parse(&xml).map_err(|e| ExtractError::Unreadable(format!("{}: {}", name, e)))?;
+
+    let mut paragraphs = Vec::new();
+    block_text(doc.root_element(), &mut paragraphs);
+    Ok(paragraphs)
+}
+
+fn is_word(node: roxmltree::Node, name: &str) -> bool {
+    node.is_element() && node.tag_name().name() == name && node.tag_name().namespace() == Some(WORD_NAMESPACE)
+}
+
+/// Paragraphs and table rows under `node`, one string each
+fn block_text(node: roxmltree::Node, paragraphs: &mut Vec<String>) {
+    for child in node.children().filter(|child| child.is_element()) {
+        if is_word(child, "p") {
+            push_paragraph(paragraphs, run_text(child));
+        } else if is_word(child, "tbl") {
+            for row in child.children().filter(|row| is_word(*row, "tr")) {
+                let cells: Vec<_> = row
+                    .children()
+                    .filter(|cell| is_word(*cell, "tc"))
+                    .map(|cell| {
+                        let mut cell_paragraphs = Vec::new();
+                        block_text(cell, &mut cell_paragraphs);
+                        cell_paragraphs.join(" ")
+                    })
+                    .filter(|cell| !cell.is_empty())
+                    .collect();
+                push_paragraph(paragraphs, cells.join(", "));
+            }
+        } else if is_deleted(child) || is_separator_note(child) {
+            continue;
+        } else {
+            // Content controls, inserted blocks, notes and the like
+            block_text(child, paragraphs);
+        }
+    }
+}
+
+fn push_paragraph(paragraphs: &mut Vec<String>, text: String) {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if !text.is_empty() {
+        paragraphs.push(text);
+    }
+}
+
+/// Text of a paragraph as it reads with all tracked changes accepted
+fn run_text(paragraph: roxmltree::Node) -> String {
+    let mut text = String::new();
+    collect_runs(paragraph, &mut text);
+    text
+}
+
+fn collect_runs(node: roxmltree::Node, text: &mut String) {
+    for child in node.children().filter(|child| child.is_element()) {
+        // Other namespaces hold drawings and the fallbacks for them
+        if child.tag_name().namespace() != Some(WORD_NAMESPACE) || is_deleted(child) {
+            continue;
+        }
+        match child.tag_name().name() {
+            "t" => text.push_str(child.text().unwrap_or_default()),
+            "tab" | "br" | "cr" => text.push(' '),
+            "noBreakHyphen" => text.push('-'),
+            // Formatting and field codes such as PAGE
+            "pPr" | "rPr" | "instrText" => {}
+            _ => collect_runs(child, text),
+        }
+    }
+}
+
+/// Tracked deletions, and text tracked as moved elsewhere
+fn is_deleted(node: roxmltree::Node) -> bool {
+    is_word(node, "del") || is_word(node, "moveFrom")
+}
+
+/// The rules Word draws above footnotes are stored as notes of their own
+fn is_separator_note(node: roxmltree::Node) -> bool {
+    (is_word(node, "footnote") || is_word(node, "endnote"))
+        && node.attribute((WORD_NAMESPACE, "type")).is_some_and(|kind| kind != "normal")
+}
+
+fn contains_utf16(bytes: &[u8], needle: &str) -> bool {
+    let needle: Vec<u8> = needle.encode_utf16().flat_map(u16::to_le_bytes).collect();
+    bytes.windows(needle.len()).any(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REPORT: &[u8] = include_bytes!("../tests/fixtures/quarterly-update.docx");
+    const PROTECTED: &[u8] = include_bytes!("../tests/fixtures/protected.docx");
+
+    #[test]
+    fn test_body_with_tables_and_tracked_changes() {
+        let extracted = extract_text_from_bytes(REPORT.to_vec(), false).unwrap();
+        assert_eq!(
+            extracted.text,
+            "Quarterly update\n\n\
+             Sales grew faster than forecast.\n\n\
+             Region, Growth\n\n\
+             North, 12%\n\n\
+             South, 8%\n\n\
+             Next steps: hire two-three people and open the depot."
+        );
+        assert_eq!(extracted.character_count, extracted.text.chars().count());
+        assert!(!extracted.text.contains("we feared"));
+        assert!(!extracted.text.contains("Moved away"));
+        assert!(!extracted.text.contains("PAGE"));
+    }
+
+    #[test]
+    fn test_headers_footers_and_notes_on_request() {
+        let extracted = extract_text_from_bytes(REPORT.to_vec(), true).unwrap();
+        assert!(extracted.text.starts_with("Acme Ltd. Confidential\n\nQuarterly update"));
+        assert!(extracted.text.ends_with("open the depot.\n\nUnaudited figures.\n\nPrepared by Finance"));
+        assert_eq!(extracted.text.matches("Confidential").count(), 1);
+    }
+
+    #[test]
+    fn test_distinct_errors() {
+        assert_eq!(extract_text_from_bytes(PROTECTED.to_vec(), false).unwrap_err(), ExtractError::Encrypted);
+        assert!(matches!(
+            extract_text_from_bytes(b"PK\x03\x04 truncated".to_vec(), false),
+            Err(ExtractError::Unreadable(_))
+        ));
+        assert!(matches!(
+            extract_text(Path::new("/nonexistent/file.docx"), false),
+            Err(ExtractError::Io(_))
+        ));
+
+        let mut legacy = COMPOUND_FILE_MAGIC.to_vec();
+        legacy.resize(1024, 0);
+        let err = extract_text_from_bytes(legacy, false).unwrap_err();
+        assert!(err.to_string().contains(".doc file"));
+    }
+}
@@ -0,0 +1,163 @@
+//! Text files that aren't UTF-8. Notepad and most Windows exports write
+//! UTF-16 with a BOM or plain Windows-1252, and `read_to_string` refuses
+//! both. A BOM settles it; without one, valid UTF-8 is taken as such, NUL
+//! bytes in every other position mean UTF-16, and anything else is read as
+//! Windows-1252, the usual legacy encoding for the languages the voices
+//! speak. Files with NUL bytes or control characters left over are binary.
+
+use crate::i18n::Message;
+use crate::warnings;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+
+/// How much of the file the UTF-16 and binary checks look at
+const SNIFF_BYTES: usize = 4096;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decoded {
+    pub text: String,
+    /// e.g. "UTF-8", "UTF-16LE", "windows-1252"
+    pub encoding: &'static str,
+    /// Without a BOM to say so, the encoding is a guess
+    pub guessed: bool,
+    /// Bytes that weren't valid in `encoding`, each now U+FFFD
+    pub replaced: usize,
+}
+
+impl Decoded {
+    /// What the user should hear about: a guessed encoding other than
+    /// UTF-8, and characters lost in decoding
+    pub fn warnings(&self) -> Vec<Message> {
+        let mut found = Vec::new();
+        if self.guessed && self.encoding != UTF_8.name() {
+            found.push(warnings::reencoded(self.encoding));
+        }
+        if self.replaced > 0 {
+            found.push(warnings::replaced(self.replaced));
+        }
+        found
+    }
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Decoded, String> {
+    if let Some((encoding, bom_length)) = Encoding::for_bom(bytes) {
+        return Ok(decode_as(encoding, &bytes[bom_length..], false));
+    }
+    if let Some(encoding) = sniff_utf16(bytes) {
+        return Ok(decode_as(encoding, bytes, true));
+    }
+
+    let sample = &bytes[..bytes.len().min(SNIFF_BYTES)];
+    let controls = sample.iter().filter(|&&byte| byte < 0x20 && !b"\t\n\x0c\r".contains(&byte)).count();
+    if sample.contains(&0) || controls * 10 > sample.len() {
+        return Err("The file doesn't contain text (it looks like binary data)".to_string());
+    }
+
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        return Ok(Decoded { text: text.to_string(), encoding: UTF_8.name(), guessed: false, replaced: 0 });
+    }
+    // UTF-8 with a few damaged bytes still has more real multi-byte
+    // characters than damage, where Windows-1252 text has next to none
+    let lossy = String::from_utf8_lossy(bytes);
+    let (replaced, multibyte) = lossy.chars().filter(|c| !c.is_ascii()).fold((0, 0), |(replaced, multibyte), c| {
+        if c == char::REPLACEMENT_CHARACTER {
+            (replaced + 1, multibyte)
+        } else {
+            (replaced, multibyte + 1)
+        }
+    });
+    if multibyte > replaced {
+        return Ok(Decoded { text: lossy.into_owned(), encoding: UTF_8.name(), guessed: true, replaced });
+    }
+    Ok(decode_as(WINDOWS_1252, bytes, true))
+}
+
+fn decode_as(encoding: &'static Encoding, bytes: &[u8], guessed: bool) -> Decoded {
+    let (text, had_errors) = encoding.decode_without_bom_handling(bytes);
+    // U+FFFD already in the file counts too; it can't be told apart
+    let replaced = if had_errors { text.matches(char::REPLACEMENT_CHARACTER).count() } else { 0 };
+    Decoded { text: text.into_owned(), encoding: encoding.name(), guessed, replaced }
+}
+
+/// UTF-16 without a BOM: mostly Latin text has a NUL in the high byte of
+/// nearly every character and almost nowhere else
+fn sniff_utf16(bytes: &[u8]) -> Option<&'static Encoding> {
+    let sample = &bytes[..bytes.len().min(SNIFF_BYTES) & !1];
+    let pairs = sample.len() / 2;
+    if pairs < 2 {
+        return None;
+    }
+    let (even, odd) = sample.chunks_exact(2).fold((0, 0), |(even, odd), pair| {
+        (even + usize::from(pair[0] == 0), odd + usize::from(pair[1] == 0))
+    });
+    match (even * 10 / pairs, odd * 10 / pairs) {
+        (0, high) if high >= 5 => Some(UTF_16LE),
+        (high, 0) if high >= 5 => Some(UTF_16BE),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UTF16LE_BOM: &[u8] = include_bytes!("../tests/fixtures/utf16le-bom.txt");
+    const WINDOWS_1252_TEXT: &[u8] = include_bytes!("../tests/fixtures/windows-1252.txt");
+    const BINARY: &[u8] = include_bytes!("../tests/fixtures/lighthouse.epub");
+
+    #[test]
+    fn test_utf16_with_bom() {
+        let decoded = decode(UTF16LE_BOM).unwrap();
+        assert_eq!(decoded.encoding, "UTF-16LE");
+        assert_eq!(decoded.text, "Grüße aus Köln.\r\nBis bald!\r\n");
+        assert!(!decoded.guessed);
+        assert!(decoded.warnings().is_empty());
+    }
+
+    #[test]
+    fn test_windows_1252_with_accents() {
+        let decoded = decode(WINDOWS_1252_TEXT).unwrap();
+        assert_eq!(decoded.encoding, "windows-1252");
+        assert_eq!(decoded.text, "Café crème, naïve façade – 12 €.\r\n");
+        assert!(decoded.guessed);
+        let codes: Vec<&str> = decoded.warnings().iter().map(|message| message.key).collect();
+        assert_eq!(codes, ["warning.reencoded"]);
+    }
+
+    #[test]
+    fn test_binary_is_refused() {
+        let err = decode(BINARY).unwrap_err();
+        assert!(err.contains("binary"), "{}", err);
+        assert!(decode(&[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, 0, 0, 0, 0x0d]).is_err());
+    }
+
+    #[test]
+    fn test_utf8_and_bomless_utf16() {
+        let plain = decode("Plain text, with ünïcode.".as_bytes()).unwrap();
+        assert_eq!((plain.encoding, plain.guessed, plain.replaced), ("UTF-8", false, 0));
+        assert!(decode(b"").unwrap().text.is_empty());
+
+        let with_bom = decode("\u{feff}Marked.".as_bytes()).unwrap();
+        assert_eq!(with_bom.text, "Marked.");
+
+        let le: Vec<u8> = "Hello there".encode_utf16().flat_map(|unit| unit.to_le_bytes()).collect();
+        let be: Vec<u8> = "Hello there".encode_utf16().flat_map(|unit| unit.to_be_bytes()).collect();
+        assert_eq!(decode(&le).unwrap().text, "Hello there");
+        assert_eq!(decode(&be).unwrap().encoding, "UTF-16BE");
+        assert_eq!(decode(&le).unwrap().warnings()[0].key, "warning.reencoded");
+    }
+
+    #[test]
+    fn test_damaged_utf8_counts_replacements() {
+        let mut bytes = "Über die Brücke, über den Fluss".as_bytes().to_vec();
+        bytes.insert(5, 0xff);
+        let decoded = decode(&bytes).unwrap();
+        assert_eq!(decoded.encoding, "UTF-8");
+        assert_eq!(decoded.replaced, 1);
+        assert_eq!(decoded.text, "Über\u{fffd} die Brücke, über den Fluss");
+        let codes: Vec<&str> = decoded.warnings().iter().map(|message| message.key).collect();
+        assert_eq!(codes, ["warning.replaced"]);
+
+        let truncated = decode(&[0xff, 0xfe, b'A', 0, b'B']).unwrap();
+        assert_eq!((truncated.text.as_str(), truncated.replaced), ("A\u{fffd}", 1));
+    }
+}
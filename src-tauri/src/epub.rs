@@ -0,0 +1,421 @@
+//! Books in EPUB form: the spine read as a list of chapters, titled from the
+//! table of contents, each converted to plain paragraphs with
+//! `extract::html_to_text`. Only DRM-free books can be read.
+
+use crate::extract::{self, ExtractError};
+use crate::tts::{GenerationEstimate, TTSService};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+/// Refuse entries that inflate past this; no chapter is anywhere near it
+const MAX_ENTRY_BYTES: u64 = 32 * 1024 * 1024;
+
+/// Encryption that only scrambles embedded fonts and leaves the text readable
+const FONT_OBFUSCATION: &[&str] = &["http://www.idpf.org/2008/embedding", "http://ns.adobe.com/pdf/enc#RC"];
+
+const OPS_NAMESPACE: &str = "http://www.idpf.org/2007/ops";
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EpubBook {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub chapters: Vec<EpubChapter>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EpubChapter {
+    /// Position in `EpubBook::chapters`, used to select chapters
+    pub index: usize,
+    pub title: String,
+    pub estimate: GenerationEstimate,
+}
+
+/// Text of the selected chapters, in reading order
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EpubText {
+    pub text: String,
+    pub chapters: Vec<ChapterMark>,
+    pub estimate: GenerationEstimate,
+}
+
+/// Where a chapter begins in `EpubText::text`, for chapter markers
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChapterMark {
+    pub index: usize,
+    pub title: String,
+    /// Offset in characters (not bytes)
+    pub start: usize,
+}
+
+/// List the chapters of the book at `path`
+pub fn list_chapters(path: &Path) -> Result<EpubBook, ExtractError> {
+    Epub::open(path)?.book()
+}
+
+/// Extract the chapters at `indices` (from `list_chapters`), in book order
+pub fn extract_chapters(path: &Path, indices: &[usize]) -> Result<EpubText, ExtractError> {
+    Epub::open(path)?.text_of(indices)
+}
+
+struct SpineItem {
+    /// Path inside the archive
+    path: String,
+    toc_title: Option<String>,
+}
+
+struct Epub {
+    archive: zip::ZipArchive<Cursor<Vec<u8>>>,
+    title: Option<String>,
+    author: Option<String>,
+    spine: Vec<SpineItem>,
+}
+
+impl Epub {
+    fn open(path: &Path) -> Result<Self, ExtractError> {
+        let bytes = std::fs::read(path).map_err(|e| ExtractError::Io(e.to_string()))?;
+        Self::from_bytes(bytes)
+    }
+
+    fn from_bytes(bytes: Vec<u8>) -> Result<Self, ExtractError> {
+        let archive = zip::ZipArchive::new(Cursor::new(bytes))
+            .map_err(|e| ExtractError::Unreadable(format!("not an EPUB archive: {}", e)))?;
+        let mut epub = Self { archive, title: None, author: None, spine: Vec::new() };
+        epub.check_drm()?;
+
+        let container = epub.read_entry("META-INF/container.xml")?;
+        let opf_path = parse_xml(&container, |doc| {
+            doc.descendants()
+                .find(|node| node.has_tag_name("rootfile"))
+                .and_then(|node| node.attribute("full-path"))
+                .map(str::to_string)
+        })?
+        .ok_or_else(|| ExtractError::Unreadable("container.xml names no package file".to_string()))?;
+        let opf = epub.read_entry(&opf_path)?;
+        epub.load_package(&opf, &opf_path)?;
+        Ok(epub)
+    }
+
+    fn check_drm(&mut self) -> Result<(), ExtractError> {
+        // Adobe ADEPT
+        if self.archive.by_name("META-INF/rights.xml").is_ok() {
+            return Err(ExtractError::Drm);
+        }
+        if self.archive.by_name("META-INF/encryption.xml").is_err() {
+            return Ok(());
+        }
+        let encryption = self.read_entry("META-INF/encryption.xml")?;
+        let encrypts_content = parse_xml(&encryption, |doc| {
+            doc.descendants()
+                .filter(|node| node.has_tag_name("EncryptionMethod"))
+                .any(|node| !FONT_OBFUSCATION.contains(&node.attribute("Algorithm").unwrap_or_default()))
+        })?;
+        if encrypts_content {
+            return Err(ExtractError::Drm);
+        }
+        Ok(())
+    }
+
+    /// Metadata, the manifest and the spine, with titles from the table of
+    /// contents: the EPUB 3 nav document, or the EPUB 2 NCX
+    fn load_package(&mut self, opf: &str, opf_path: &str) -> Result<(), ExtractError> {
+        struct Package {
+            title: Option<String>,
+            author: Option<String>,
+            spine: Vec<String>,
+            nav: Option<String>,
+            ncx: Option<String>,
+        }
+
+        let package = parse_xml(opf, |doc| {
+            let text_of = |name: &str| {
+                doc.descendants()
+                    .find(|node| node.has_tag_name(name))
+                    .and_then(|node| node.text())
+                    .map(|text| text.trim().to_string())
+                    .filter(|text| !text.is_empty())
+            };
+            let mut manifest = HashMap::new();
+            let mut nav = None;
+            for item in doc.descendants().filter(|node| node.has_tag_name("item")) {
+                let (Some(id), Some(href)) = (item.attribute("id"), item.attribute("href")) else {
+                    continue;
+                };
+                let href = resolve_href(opf_path, href);
+                if item.attribute("properties").is_some_and(|props| props.split_whitespace().any(|p| p == "nav")) {
+                    nav = Some(href.clone());
+                }
+                manifest.insert(id, href);
+            }
+            let spine_node = doc.descendants().find(|node| node.has_tag_name("spine"));
+            let spine = spine_node
+                .iter()
+                .flat_map(|spine| spine.children().filter(|node| node.has_tag_name("itemref")))
+                .filter(|itemref| itemref.attribute("linear") != Some("no"))
+                .filter_map(|itemref| manifest.get(itemref.attribute("idref")?).cloned())
+                .collect();
+            let ncx = spine_node.and_then(|spine| spine.attribute("toc")).and_then(|id| manifest.get(id).cloned());
+            Package { title: text_of("title"), author: text_of("creator"), spine, nav, ncx }
+        })?;
+
+        let toc = if let Some(nav) = &package.nav {
+            self.nav_titles(nav)?
+        } else if let Some(ncx) = &package.ncx {
+            self.ncx_titles(ncx)?
+        } else {
+            HashMap::new()
+        };
+
+        self.title = package.title;
+        self.author = package.author;
+        self.spine = package
+            .spine
+            .into_iter()
+            .map(|path| SpineItem { toc_title: toc.get(&path).cloned(), path })
+            .collect();
+        Ok(())
+    }
+
+    fn nav_titles(&mut self, nav_path: &str) -> Result<HashMap<String, String>, ExtractError> {
+        let nav = self.read_entry(nav_path)?;
+        parse_xml(&nav, |doc| {
+            let navs: Vec<_> = doc.descendants().filter(|node| node.has_tag_name("nav")).collect();
+            let toc = navs
+                .iter()
+                .find(|node| node.attribute((OPS_NAMESPACE, "type")) == Some("toc"))
+                .or(navs.first());
+            let mut titles = HashMap::new();
+            for link in toc.iter().flat_map(|toc| toc.descendants().filter(|node| node.has_tag_name("a"))) {
+                if let Some(href) = link.attribute("href") {
+                    let title = node_text(link);
+                    if !title.is_empty() {
+                        titles.entry(resolve_href(nav_path, href)).or_insert(title);
+                    }
+                }
+            }
+            titles
+        })
+    }
+
+    fn ncx_titles(&mut self, ncx_path: &str) -> Result<HashMap<String, String>, ExtractError> {
+        let ncx = self.read_entry(ncx_path)?;
+        parse_xml(&ncx, |doc| {
+            let mut titles = HashMap::new();
+            for point in doc.descendants().filter(|node| node.has_tag_name("navPoint")) {
+                let label = point.children().find(|node| node.has_tag_name("navLabel")).map(node_text);
+                let src = point
+                    .children()
+                    .find(|node| node.has_tag_name("content"))
+                    .and_then(|node| node.attribute("src"));
+                if let (Some(label), Some(src)) = (label, src) {
+                    if !label.is_empty() {
+                        titles.entry(resolve_href(ncx_path, src)).or_insert(label);
+                    }
+                }
+            }
+            titles
+        })
+    }
+
+    fn read_entry(&mut self, name: &str) -> Result<String, ExtractError> {
+        let entry = self
+            .archive
+            .by_name(name)
+            .map_err(|_| ExtractError::Unreadable(format!("missing {}", name)))?;
+        if entry.size() > MAX_ENTRY_BYTES {
+            return Err(ExtractError::Unreadable(format!("{} is too large", name)));
+        }
+        let mut bytes = Vec::with_capacity(entry.size() as usize);
+        entry
+            .take(MAX_ENTRY_BYTES)
+            .read_to_end(&mut bytes)
+            .map_err(|e| ExtractError::Unreadable(format!("{}: {}", name, e)))?;
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    /// Title and text of spine item `index`
+    fn chapter(&mut self, index: usize) -> Result<(String, String), ExtractError> {
+        let path = self.spine[index].path.clone();
+        let html = self.read_entry(&path)?;
+        let title = self.spine[index]
+            .toc_title
+            .clone()
+            .or_else(|| first_heading(&html))
+            .unwrap_or_else(|| format!("Chapter {}", index + 1));
+        Ok((title, extract::html_to_text(&html)))
+    }
+
+    fn book(mut self) -> Result<EpubBook, ExtractError> {
+        let mut chapters = Vec::with_capacity(self.spine.len());
+        for index in 0..self.spine.len() {
+            let (title, text) = self.chapter(index)?;
+            chapters.push(EpubChapter { index, title, estimate: TTSService::estimate(&text) });
+        }
+        if chapters.iter().all(|chapter| chapter.estimate.character_count == 0) {
+            return Err(ExtractError::NoText);
+        }
+        Ok(EpubBook { title: self.title, author: self.author, chapters })
+    }
+
+    fn text_of(mut self, indices: &[usize]) -> Result<EpubText, ExtractError> {
+        let mut indices = indices.to_vec();
+        indices.sort_unstable();
+        indices.dedup();
+        if let Some(&bad) = indices.iter().find(|&&index| index >= self.spine.len()) {
+            return Err(ExtractError::Unreadable(format!(
+                "chapter {} doesn't exist; the book has {}",
+                bad,
+                self.spine.len()
+            )));
+        }
+
+        let mut text = String::new();
+        let mut chapters = Vec::with_capacity(indices.len());
+        for index in indices {
+            let (title, chapter_text) = self.chapter(index)?;
+            if chapter_text.is_empty() {
+                continue;
+            }
+            if !text.is_empty() {
+                text.push_str("\n\n");
+            }
+            chapters.push(ChapterMark { index, title, start: text.chars().count() });
+            text.push_str(&chapter_text);
+            extract::check_length(text.len())?;
+        }
+        if text.is_empty() {
+            return Err(ExtractError::NoText);
+        }
+        let estimate = TTSService::estimate(&text);
+        Ok(EpubText { text, chapters, estimate })
+    }
+}
+
+fn parse_xml<T>(xml: &str, read: impl FnOnce(&roxmltree::Document) -> T) -> Result<T, ExtractError> {
+    let options = roxmltree::ParsingOptions { allow_dtd: true, ..Default::default() };
+    let doc = roxmltree::Document::parse_with_options(xml, options)
+        .map_err(|e| ExtractError::Unreadable(format!("invalid XML: {}", e)))?;
+    Ok(read(&doc))
+}
+
+fn node_text(node: roxmltree::Node) -> String {
+    let text: String = node.descendants().filter(|n| n.is_text()).filter_map(|n| n.text()).collect();
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn first_heading(html: &str) -> Option<String> {
+    let lower = html.to_ascii_lowercase();
+    let start = (1..=6).filter_map(|level| lower.find(&format!("<h{}", level))).min()?;
+    let end = lower[start..].find("</h").map_or(html.len(), |end| start + end);
+    let heading = extract::html_to_text(&html[start..end]);
+    (!heading.is_empty()).then_some(heading)
+}
+
+/// An href from the document at `base` as a path inside the archive:
+/// relative to `base`'s folder, fragment dropped, `%20` and `..` resolved
+fn resolve_href(base: &str, href: &str) -> String {
+    let href = href.split('#').next().unwrap_or_default();
+    let mut parts: Vec<String> = match base.rsplit_once('/') {
+        Some((dir, _)) => dir.split('/').map(str::to_string).collect(),
+        None => Vec::new(),
+    };
+    for part in percent_decode(href).split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part.to_string()),
+        }
+    }
+    parts.join("/")
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOOK: &[u8] = include_bytes!("../tests/fixtures/lighthouse.epub");
+    const DRM_BOOK: &[u8] = include_bytes!("../tests/fixtures/lighthouse-drm.epub");
+
+    fn book() -> Epub {
+        Epub::from_bytes(BOOK.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_chapter_enumeration() {
+        let book = book().book().unwrap();
+        assert_eq!(book.title.as_deref(), Some("The Lighthouse Keeper"));
+        assert_eq!(book.author.as_deref(), Some("A. N. Author"));
+
+        // The non-linear cover is left out; untitled chapters use their heading
+        let titles: Vec<_> = book.chapters.iter().map(|chapter| chapter.title.as_str()).collect();
+        assert_eq!(titles, ["I. The Storm", "II. The Ship", "Homecoming"]);
+        assert_eq!(book.chapters[2].index, 2);
+        assert!(book.chapters.iter().all(|chapter| chapter.estimate.character_count > 0));
+        assert!(book.chapters[0].estimate.estimated_cost_tts_1 > 0.0);
+    }
+
+    #[test]
+    fn test_selected_chapters_are_clean() {
+        let text = book().text_of(&[2, 0]).unwrap();
+        assert_eq!(
+            text.text,
+            "The Storm\n\n\
+             The wind rose at dusk and did not fall again until morning.\n\n\
+             Mara kept the lamp lit — as her mother had & her grandmother before.\n\n\
+             Homecoming\n\n\
+             The crew came ashore in the afternoon."
+        );
+        let second_start = text.text.find("Homecoming").unwrap();
+        assert_eq!(
+            text.chapters,
+            vec![
+                ChapterMark { index: 0, title: "I. The Storm".to_string(), start: 0 },
+                ChapterMark { index: 2, title: "Homecoming".to_string(), start: text.text[..second_start].chars().count() },
+            ]
+        );
+        assert!(!text.text.contains('<'));
+        assert!(!text.text.contains("Chapter 1"));
+
+        assert!(book().text_of(&[3]).is_err());
+    }
+
+    #[test]
+    fn test_drm_and_invalid_files() {
+        assert_eq!(Epub::from_bytes(DRM_BOOK.to_vec()).err(), Some(ExtractError::Drm));
+        assert!(matches!(Epub::from_bytes(b"plain text".to_vec()), Err(ExtractError::Unreadable(_))));
+    }
+
+    #[test]
+    fn test_resolve_href() {
+        assert_eq!(resolve_href("OEBPS/content.opf", "text/ch%201.xhtml#p4"), "OEBPS/text/ch 1.xhtml");
+        assert_eq!(resolve_href("OEBPS/text/nav.xhtml", "../ch1.xhtml"), "OEBPS/ch1.xhtml");
+        assert_eq!(resolve_href("content.opf", "ch1.xhtml"), "ch1.xhtml");
+    }
+}
@@ -0,0 +1,136 @@
+//! How much longer a run of requests will take, from how quickly the
+//! finished ones came in. It counts completions over time rather than
+//! timing each request, so it holds just as well when several requests
+//! run at once (see `batch`) as when they run one after another.
+
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Completions the pace is taken over, so the estimate follows a run that
+/// speeds up or slows down instead of averaging over all of it
+const WINDOW: usize = 8;
+
+/// Where a run stands, sent with each progress event
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Timing {
+    pub elapsed_ms: u64,
+    /// Unknown until something has finished
+    pub eta_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Estimator {
+    remaining: usize,
+    started: Instant,
+    /// The start, then the latest completions, at most `WINDOW` intervals
+    recent: VecDeque<Instant>,
+}
+
+impl Estimator {
+    pub fn new(total: usize, started: Instant) -> Self {
+        Self { remaining: total, started, recent: VecDeque::from([started]) }
+    }
+
+    /// One finished, successfully or not, at `at`
+    pub fn finished(&mut self, at: Instant) {
+        self.remaining = self.remaining.saturating_sub(1);
+        self.recent.push_back(at);
+        if self.recent.len() > WINDOW + 1 {
+            self.recent.pop_front();
+        }
+    }
+
+    /// One that took no time, e.g. reused from a cache; it would make the
+    /// rest look quicker than they will be
+    pub fn skipped(&mut self) {
+        self.remaining = self.remaining.saturating_sub(1);
+    }
+
+    /// Average time between completions over the window
+    fn pace(&self) -> Option<Duration> {
+        let intervals = self.recent.len() - 1;
+        let span = *self.recent.back()? - *self.recent.front()?;
+        (intervals > 0).then(|| span / intervals as u32)
+    }
+
+    pub fn timing(&self, now: Instant) -> Timing {
+        let eta = match (self.remaining, self.pace()) {
+            (0, _) => Some(Duration::ZERO),
+            (remaining, Some(pace)) => {
+                // Time already spent towards the next one counts
+                let since_last = now.saturating_duration_since(*self.recent.back().expect("never empty"));
+                Some((pace * remaining as u32).saturating_sub(since_last))
+            }
+            (_, None) => None,
+        };
+        Timing {
+            elapsed_ms: now.saturating_duration_since(self.started).as_millis() as u64,
+            eta_ms: eta.map(|eta| eta.as_millis() as u64),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn test_one_at_a_time() {
+        let start = Instant::now();
+        let mut estimator = Estimator::new(10, start);
+        assert_eq!(estimator.timing(start + ms(500)), Timing { elapsed_ms: 500, eta_ms: None });
+
+        for second in 1..=3 {
+            estimator.finished(start + ms(1000 * second));
+        }
+        assert_eq!(estimator.timing(start + ms(3000)), Timing { elapsed_ms: 3000, eta_ms: Some(7000) });
+        // Partway into the next one
+        assert_eq!(estimator.timing(start + ms(3400)).eta_ms, Some(6600));
+        // Overdue: it can't go below nothing
+        assert_eq!(estimator.timing(start + ms(60_000)).eta_ms, Some(0));
+    }
+
+    #[test]
+    fn test_parallel_completions_measure_throughput() {
+        // Four at a time, each taking about 2 s, finishing in bursts
+        let start = Instant::now();
+        let mut estimator = Estimator::new(12, start);
+        for burst in 1..=2u64 {
+            for offset in 0..4 {
+                estimator.finished(start + ms(2000 * burst + 10 * offset));
+            }
+        }
+        // 8 in 4.03 s is about 0.5 s each for the 4 left, not 4 x 2 s
+        let eta = estimator.timing(start + ms(4030)).eta_ms.unwrap();
+        assert!((1900..=2100).contains(&eta), "{}", eta);
+    }
+
+    #[test]
+    fn test_follows_a_slowdown_and_ignores_skips() {
+        let start = Instant::now();
+        let mut estimator = Estimator::new(30, start);
+        let mut at = start;
+        for _ in 0..10 {
+            at += ms(100);
+            estimator.finished(at);
+        }
+        for _ in 0..WINDOW {
+            at += ms(1000);
+            estimator.finished(at);
+        }
+        estimator.skipped();
+        // 11 left at the recent pace of 1 s, not the run's average
+        assert_eq!(estimator.timing(at).eta_ms, Some(11_000));
+
+        for _ in 0..11 {
+            estimator.skipped();
+        }
+        assert_eq!(estimator.timing(at).eta_ms, Some(0));
+    }
+}
@@ -0,0 +1,462 @@
+//! Text pulled out of documents for reading aloud, and the layout cleanup
+//! they all need: lines that were only wrapped for the page rejoined into
+//! paragraphs, words hyphenated across lines put back together, running
+//! headers and footers dropped, and HTML reduced to its paragraphs.
+
+use crate::tts::{GenerationEstimate, TTSService};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Extractors stop once they have this much text, so a small archive that
+/// unpacks to gigabytes can't take all the memory. It's the most
+/// `max_input_mb` allows; `documents` holds text to that setting afterwards.
+pub const MAX_TEXT_BYTES: usize = crate::config::MAX_INPUT_MB as usize * 1024 * 1024;
+
+/// A document's text plus what the window needs to show before generating
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtractedText {
+    pub text: String,
+    /// Pages in the whole document, not just the ones extracted
+    pub page_count: usize,
+    /// 1-based, inclusive
+    pub first_page: usize,
+    pub last_page: usize,
+    pub estimate: GenerationEstimate,
+}
+
+impl ExtractedText {
+    pub fn new(text: String, page_count: usize, first_page: usize, last_page: usize) -> Self {
+        let estimate = TTSService::estimate(&text);
+        Self { text, page_count, first_page, last_page, estimate }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExtractError {
+    Io(String),
+    /// Needs a password we don't have
+    Encrypted,
+    /// Has pages but no text layer, e.g. a scan
+    NoText,
+    /// A book locked to a store's reader app
+    Drm,
+    InvalidPageRange { first: usize, last: usize, page_count: usize },
+    /// More text than `MAX_TEXT_BYTES`; extraction stopped there
+    TooLong { limit: usize },
+    Unreadable(String),
+}
+
+impl std::fmt::Display for ExtractError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExtractError::Io(e) => write!(f, "Failed to read file: {}", e),
+            ExtractError::Encrypted => write!(
+                f,
+                "This document is password-protected; save an unprotected copy (e.g. print to PDF) and try again"
+            ),
+            ExtractError::NoText => write!(
+                f,
+                "No text found; the document may be scanned images, which need OCR before they can be read aloud"
+            ),
+            ExtractError::Drm => write!(
+                f,
+                "This book is DRM-protected; only DRM-free EPUBs can be read aloud"
+            ),
+            ExtractError::InvalidPageRange { first, last, page_count } => write!(
+                f,
+                "Pages {}-{} are outside the document, which has {} page{}",
+                first,
+                last,
+                page_count,
+                if *page_count == 1 { "" } else { "s" }
+            ),
+            ExtractError::TooLong { limit } => write!(
+                f,
+                "The document has more than {} MB of text; choose fewer pages or chapters",
+                limit / (1024 * 1024)
+            ),
+            ExtractError::Unreadable(e) => write!(f, "Couldn't read the document: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ExtractError {}
+
+/// For extractors, as the text grows: `length` bytes so far
+pub fn check_length(length: usize) -> Result<(), ExtractError> {
+    if length > MAX_TEXT_BYTES {
+        return Err(ExtractError::TooLong { limit: MAX_TEXT_BYTES });
+    }
+    Ok(())
+}
+
+/// Resolve an optional 1-based page range against `page_count`
+pub fn page_range(first: Option<usize>, last: Option<usize>, page_count: usize) -> Result<(usize, usize), ExtractError> {
+    let first_page = first.unwrap_or(1);
+    let last_page = last.unwrap_or(page_count);
+    if first_page == 0 || first_page > last_page || last_page > page_count {
+        return Err(ExtractError::InvalidPageRange { first: first_page, last: last_page, page_count });
+    }
+    Ok((first_page, last_page))
+}
+
+/// Drop lines that open or close most pages, like a running title or a page
+/// number. Digits are ignored when comparing so "Page 3" matches "Page 4".
+/// Needs at least three pages to tell a header from a coincidence.
+pub fn strip_running_lines(pages: &mut [String]) {
+    if pages.len() < 3 {
+        return;
+    }
+    let key = |line: &str| -> String {
+        line.trim().chars().map(|c| if c.is_ascii_digit() { '#' } else { c }).collect()
+    };
+    let edges = |page: &str| -> (Option<String>, Option<String>) {
+        let mut lines = page.lines().filter(|line| !line.trim().is_empty());
+        let first = lines.next().map(key);
+        let last = lines.next_back().map(key);
+        (first, last)
+    };
+
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for page in pages.iter() {
+        let (first, last) = edges(page);
+        for line in [first, last].into_iter().flatten() {
+            *counts.entry(line).or_default() += 1;
+        }
+    }
+    let threshold = pages.len().div_ceil(2).max(2);
+    let repeated = |line: &str| counts.get(&key(line)).is_some_and(|&count| count >= threshold);
+
+    for page in pages.iter_mut() {
+        let mut lines: Vec<&str> = page.lines().collect();
+        if let Some(first) = lines.iter().position(|line| !line.trim().is_empty()) {
+            if repeated(lines[first]) {
+                lines.remove(first);
+            }
+        }
+        if let Some(last) = lines.iter().rposition(|line| !line.trim().is_empty()) {
+            if repeated(lines[last]) {
+                lines.remove(last);
+            }
+        }
+        *page = lines.join("\n");
+    }
+}
+
+/// Join hard-wrapped lines into paragraphs (blank lines separate them) and
+/// undo hyphenation at line ends: "carry-\ning" becomes "carrying", while
+/// "Anglo-\nSaxon" keeps its hyphen since the next part is capitalized.
+/// List items ("- ", "* ", "1. ") stay on lines of their own.
+pub fn reflow(text: &str) -> String {
+    let mut paragraphs: Vec<Vec<String>> = Vec::new();
+    let mut current: Vec<String> = Vec::new();
+
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() {
+            if !current.is_empty() {
+                paragraphs.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+        match current.last_mut() {
+            Some(last) if !is_list_item(line) => {
+                if ends_with_hyphenated_word(last) {
+                    if line.starts_with(char::is_lowercase) {
+                        last.pop();
+                    }
+                } else {
+                    last.push(' ');
+                }
+                last.push_str(line);
+            }
+            _ => current.push(line.to_string()),
+        }
+    }
+    if !current.is_empty() {
+        paragraphs.push(current);
+    }
+
+    paragraphs
+        .into_iter()
+        .map(|lines| {
+            lines.iter().map(|line| line.split_whitespace().collect::<Vec<_>>().join(" ")).collect::<Vec<_>>().join("\n")
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// "- milk", "* eggs" or "12. Stir"
+fn is_list_item(line: &str) -> bool {
+    if line.starts_with("- ") || line.starts_with("* ") {
+        return true;
+    }
+    let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    digits > 0 && line[digits..].starts_with(". ")
+}
+
+/// "carry-" but not "list -"
+fn ends_with_hyphenated_word(line: &str) -> bool {
+    let mut chars = line.chars().rev();
+    chars.next() == Some('-') && chars.next().is_some_and(char::is_alphabetic)
+}
+
+/// Elements that start a new paragraph
+pub const BLOCK_TAGS: &[&str] = &[
+    "address", "article", "aside", "blockquote", "dd", "div", "dl", "dt", "figcaption", "figure", "footer",
+    "h1", "h2", "h3", "h4", "h5", "h6", "header", "hr", "li", "main", "nav", "ol", "p", "pre", "section",
+    "table", "td", "th", "tr", "ul",
+];
+/// Elements whose content is never read
+const SKIPPED_TAGS: &[&str] = &["head", "script", "style", "svg", "template", "noscript"];
+
+/// Plain text of an HTML or XHTML fragment, one paragraph per block
+/// element (headings and list items included) separated by blank lines.
+/// Two or more `<br>` in a row also end a paragraph, as in mail and
+/// pages laid out without `<p>`; a single one is only a space. Tolerates
+/// the malformed markup real pages have; it never fails, only skips.
+pub fn html_to_text(html: &str) -> String {
+    let mut paragraphs: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut skipping: Option<String> = None;
+    // `<br>` since the last text
+    let mut breaks = 0;
+    let mut rest = html;
+
+    let mut end_paragraph = |current: &mut String| {
+        let paragraph = current.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !paragraph.is_empty() {
+            paragraphs.push(paragraph);
+        }
+        current.clear();
+    };
+
+    while let Some(open) = rest.find('<') {
+        if skipping.is_none() {
+            let text = decode_entities(&rest[..open]);
+            if !text.trim().is_empty() {
+                breaks = 0;
+            }
+            current.push_str(&text);
+        }
+        rest = &rest[open..];
+
+        // Comments, CDATA and doctypes
+        if let Some(after) = rest.strip_prefix("<!--") {
+            rest = after.find("-->").map_or("", |end| &after[end + 3..]);
+            continue;
+        }
+        let Some(close) = rest.find('>') else {
+            rest = "";
+            break;
+        };
+        let tag = &rest[1..close];
+        rest = &rest[close + 1..];
+        if tag.starts_with('!') || tag.starts_with('?') {
+            continue;
+        }
+
+        let closing = tag.starts_with('/');
+        let name: String = tag
+            .trim_start_matches('/')
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric() || *c == ':')
+            .collect::<String>()
+            .to_ascii_lowercase();
+        // Drop a namespace prefix like `xhtml:p`
+        let name = name.rsplit(':').next().unwrap_or_default().to_string();
+
+        if let Some(skipped) = &skipping {
+            if closing && *skipped == name {
+                skipping = None;
+            }
+            continue;
+        }
+        if SKIPPED_TAGS.contains(&name.as_str()) {
+            if !closing && !tag.ends_with('/') {
+                skipping = Some(name);
+            }
+            continue;
+        }
+        if name == "br" {
+            breaks += 1;
+            if breaks == 2 {
+                end_paragraph(&mut current);
+            } else {
+                current.push('\n');
+            }
+        } else if BLOCK_TAGS.contains(&name.as_str()) {
+            end_paragraph(&mut current);
+        }
+    }
+    if skipping.is_none() {
+        current.push_str(&decode_entities(rest));
+    }
+    end_paragraph(&mut current);
+
+    paragraphs.join("\n\n")
+}
+
+/// Decode character references; unknown named entities are left as written
+pub fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let decoded = rest[1..].find(';').filter(|&end| end <= 10).and_then(|end| {
+            let entity = &rest[1..end + 1];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some(' '),
+                "ndash" => Some('–'),
+                "mdash" => Some('—'),
+                "hellip" => Some('…'),
+                "lsquo" => Some('‘'),
+                "rsquo" => Some('’'),
+                "ldquo" => Some('“'),
+                "rdquo" => Some('”'),
+                "copy" => Some('©'),
+                _ => {
+                    let number = entity.strip_prefix('#')?;
+                    let code = match number.strip_prefix(['x', 'X']) {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                        None => number.parse().ok()?,
+                    };
+                    char::from_u32(code)
+                }
+            }?;
+            Some((c, end + 2))
+        });
+        match decoded {
+            Some((c, len)) => {
+                out.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_length_limit() {
+        assert!(check_length(MAX_TEXT_BYTES).is_ok());
+        let err = check_length(MAX_TEXT_BYTES + 1).unwrap_err();
+        assert_eq!(err, ExtractError::TooLong { limit: MAX_TEXT_BYTES });
+        assert!(err.to_string().starts_with("The document has more than 100 MB of text"), "{}", err);
+    }
+
+    #[test]
+    fn test_reflow() {
+        let text = "The expedition set out before dawn, carry-\ning enough water for three days\n  and a map.\n\n\nBy noon it was hot.\nAnglo-\nSaxon names stay.";
+        assert_eq!(
+            reflow(text),
+            "The expedition set out before dawn, carrying enough water for three days and a map.\n\n\
+             By noon it was hot. Anglo-Saxon names stay."
+        );
+        assert_eq!(reflow("  \n\n "), "");
+        assert_eq!(reflow("A list -\nitem"), "A list - item");
+    }
+
+    #[test]
+    fn test_reflow_keeps_list_items() {
+        // As copied out of a PDF viewer: a break every line or so
+        let text = "To make the dough you will need the\nfollowing, at room temperature:\n- 500 g of flour,\n  sifted twice\n* 2 eggs\n\n1. Mix everything in a\nlarge bowl.\n2. Knead for 10\nminutes. Rest it\n30 minutes.";
+        assert_eq!(
+            reflow(text),
+            "To make the dough you will need the following, at room temperature:\n\
+             - 500 g of flour, sifted twice\n\
+             * 2 eggs\n\n\
+             1. Mix everything in a large bowl.\n\
+             2. Knead for 10 minutes. Rest it 30 minutes."
+        );
+    }
+
+    #[test]
+    fn test_strip_running_lines() {
+        let mut pages: Vec<String> = (1..=4)
+            .map(|n| format!("Annual Report\n\nBody of page {} goes here.\n\nPage {}", n, n))
+            .collect();
+        pages[2] = "Annual Report\nA chart-only page\nPage 3".to_string();
+        strip_running_lines(&mut pages);
+        assert_eq!(pages[0].trim(), "Body of page 1 goes here.");
+        assert_eq!(pages[2].trim(), "A chart-only page");
+
+        // Two pages aren't enough to call anything a header
+        let mut pages = vec!["Title\nOne".to_string(), "Title\nTwo".to_string()];
+        strip_running_lines(&mut pages);
+        assert!(pages[0].starts_with("Title"));
+    }
+
+    #[test]
+    fn test_html_to_text() {
+        let html = r#"<?xml version="1.0"?><!DOCTYPE html>
+            <html><head><title>Ignored</title><style>p { color: red }</style></head>
+            <body><h1>Chapter&nbsp;One</h1>
+            <p>It was a <em>dark</em> and
+               stormy night &mdash; &#8220;truly&#x201D; &amp; properly.</p>
+            <!-- <p>commented out</p> -->
+            <div><p>Nested</p>tail text</div><script>alert("no")</script>
+            <p>Fish &chips; 3 &lt; 4<br/>next line</p></body></html>"#;
+        assert_eq!(
+            html_to_text(html),
+            "Chapter One\n\n\
+             It was a dark and stormy night — “truly” & properly.\n\n\
+             Nested\n\n\
+             tail text\n\n\
+             Fish &chips; 3 < 4 next line"
+        );
+        assert_eq!(html_to_text("plain <b>bold"), "plain bold");
+        assert_eq!(html_to_text("<p>unterminated <a href="), "unterminated");
+    }
+
+    #[test]
+    fn test_html_to_text_nested_lists() {
+        let html = "<h2>Packing</h2><ul>\n  <li>Clothes\n    <ul><li>Socks</li><li>A <b>warm</b> coat</li></ul>\n  </li>\n  \
+                    <li>Tickets<ol><li>Outbound</li><li>Return<ul><li>Seat 12A</li></ul></li></ol></li>\n</ul><p>Done.</p>";
+        assert_eq!(
+            html_to_text(html),
+            "Packing\n\nClothes\n\nSocks\n\nA warm coat\n\nTickets\n\nOutbound\n\nReturn\n\nSeat 12A\n\nDone."
+        );
+    }
+
+    #[test]
+    fn test_html_to_text_br_soup() {
+        let html = "<div>Dear team,<br><br>The launch moved.<BR>New date:\n Friday.<br/> <br>\n<br>Thanks<br></div>\
+                    <span>Sent from my phone</span><br><br><br>";
+        assert_eq!(
+            html_to_text(html),
+            "Dear team,\n\nThe launch moved. New date: Friday.\n\nThanks\n\nSent from my phone"
+        );
+        assert_eq!(html_to_text("<br><br>"), "");
+    }
+
+    #[test]
+    fn test_page_range() {
+        assert_eq!(page_range(None, None, 10), Ok((1, 10)));
+        assert_eq!(page_range(Some(3), None, 10), Ok((3, 10)));
+        assert_eq!(page_range(None, Some(2), 10), Ok((1, 2)));
+        assert!(page_range(Some(0), None, 10).is_err());
+        assert!(page_range(Some(5), Some(4), 10).is_err());
+        assert_eq!(
+            page_range(Some(2), Some(11), 10),
+            Err(ExtractError::InvalidPageRange { first: 2, last: 11, page_count: 10 })
+        );
+    }
+}
@@ -1,75 +1,541 @@
+//! Generated audio handed to the window as files in a scratch directory,
+//! which the webview streams by id through `ttsaudio://` (see `audio_stream`)
+//! instead of pushing megabytes of base64 through IPC. With a database,
+//! what each file was written with is kept so a truncated one is caught
+//! before it plays (see `integrity`).
+
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 use tokio::fs;
 use uuid::Uuid;
-use anyhow::Result;
+use anyhow::{bail, Result};
+use serde::Serialize;
+use crate::database::Database;
+use crate::integrity::{self, AudioChecksum, AudioFile, AudioStatus};
+use crate::permissions;
+use crate::resolve::Resolved;
+use crate::silence::Gaps;
+use crate::spend::Downgrade;
+use crate::warnings::Warning;
+
+/// Clips up to this size may still be returned inline as a data URL
+pub const MAX_INLINE_BYTES: usize = 256 * 1024;
+
+/// What the generate commands return: a file to play, or for tiny clips
+/// that asked for it, the audio itself as a data URL.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GeneratedAudio {
+    /// Name to stream it by: `ttsaudio://<id>` (see `audio_stream`)
+    pub id: String,
+    /// Absolute path to hand back to `release_audio`; `None` when returned inline
+    pub path: Option<PathBuf>,
+    pub data_url: Option<String>,
+    /// Size of the audio in bytes
+    pub size: usize,
+    /// What it is encoded as, e.g. "opus" when the format setting is "auto"
+    /// (see `output_format`)
+    pub format: String,
+    /// Decoded length when the format says, otherwise an estimate from the text
+    pub duration_secs: f64,
+    /// Shared with an identical generation that was already running, so
+    /// it cost nothing extra (see `inflight`)
+    pub coalesced: bool,
+    /// Generated with a cheaper model than asked for because the month is
+    /// near its budget, for the window to say so
+    pub downgraded: bool,
+    pub downgrade_reason: Option<String>,
+    /// When long text couldn't be joined for want of FFmpeg: every piece in
+    /// order, to play one after another, this audio being the first
+    /// (see `tts::WithoutFfmpeg`)
+    pub playlist: Vec<GeneratedAudio>,
+    /// What happened along the way that didn't stop it (see `warnings`)
+    pub warnings: Vec<Warning>,
+    /// The voice and model it was made with and where each came from;
+    /// `None` for audio that wasn't newly generated, e.g. a speed change
+    pub resolved: Option<Resolved>,
+    /// The pauses added between sentences and paragraphs, for audio from a
+    /// job (see `silence::Gaps`)
+    pub gaps: Option<Gaps>,
+}
+
+impl GeneratedAudio {
+    /// Marked with `downgrade`, if the request had one
+    pub fn downgraded(self, downgrade: Option<&Downgrade>) -> Self {
+        Self {
+            downgraded: downgrade.is_some(),
+            downgrade_reason: downgrade.map(|downgrade| downgrade.reason.clone()),
+            ..self
+        }
+    }
+}
+
+/// Why audio couldn't be written, for the window to tell apart
+#[derive(Debug)]
+pub enum StorageError {
+    /// A file is where the audio directory, or one above it, should be
+    NotADirectory(PathBuf),
+    /// A new file's name was already taken, and so was the one tried next
+    NameCollision(PathBuf),
+    InvalidId(String),
+    Io(PathBuf, std::io::Error),
+}
+
+impl std::fmt::Display for StorageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StorageError::NotADirectory(path) => write!(
+                f,
+                "{} is a file, so audio can't be saved in a folder there. Move or delete it",
+                path.display()
+            ),
+            StorageError::NameCollision(path) => write!(f, "Couldn't find a free name for new audio, last tried {}", path.display()),
+            StorageError::InvalidId(id) => write!(f, "Invalid audio id: {}", id),
+            StorageError::Io(path, e) => write!(f, "Failed to write {}: {}", path.display(), e),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
 
 pub struct FileManager {
     temp_dir: PathBuf,
+    /// Where checksums are kept; without one nothing can be verified
+    database: Option<Database>,
+    /// Names for new files
+    names: Box<dyn Fn() -> String + Send + Sync>,
+    /// Where audio was exported to while the app runs
+    exports: std::sync::Mutex<Vec<PathBuf>>,
 }
 
 impl FileManager {
     pub fn new() -> Self {
-        let temp_dir = std::env::temp_dir().join("tts-player");
-        Self { temp_dir }
+        Self::with_dir(crate::paths::audio_dir())
     }
 
-    pub async fn create_temp_audio_file(&self, audio_data: &[u8]) -> Result<String> {
-        // Ensure temp directory exists
-        fs::create_dir_all(&self.temp_dir).await?;
-        
-        // Generate unique filename
-        let filename = format!("{}.mp3", Uuid::new_v4());
-        let file_path = self.temp_dir.join(filename);
-        
-        // Write audio data to file
-        fs::write(&file_path, audio_data).await?;
-        
-        // Return absolute path as string
+    pub fn with_dir(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            temp_dir: dir.into(),
+            database: None,
+            names: Box::new(|| Uuid::new_v4().to_string()),
+            exports: Default::default(),
+        }
+    }
+
+    /// Name new files with `names` instead of random UUIDs
+    pub fn with_names(self, names: impl Fn() -> String + Send + Sync + 'static) -> Self {
+        Self { names: Box::new(names), ..self }
+    }
+
+    /// Keep the checksum of everything written in `database`
+    pub fn with_database(self, database: Option<Database>) -> Self {
+        Self { database, ..self }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.temp_dir
+    }
+
+    /// Remember that audio was exported to `path`, a file or a directory,
+    /// so it can be shown in the file manager (see `reveal`)
+    pub fn remember_export(&self, path: &Path) {
+        let mut exports = self.exports.lock().unwrap();
+        if !exports.iter().any(|known| known == path) {
+            exports.push(path.to_path_buf());
+        }
+    }
+
+    /// Where audio can be found: this directory and the export locations
+    pub fn locations(&self) -> Vec<PathBuf> {
+        let exports = self.exports.lock().unwrap();
+        std::iter::once(self.temp_dir.clone()).chain(exports.iter().cloned()).collect()
+    }
+
+    pub async fn create_temp_audio_file(&self, audio_data: &[u8]) -> Result<String, StorageError> {
+        let file_path = self.save_audio(audio_data, "mp3").await?;
         Ok(file_path.to_string_lossy().to_string())
     }
+
+    /// Write `audio_data` to a new uniquely named file and return its path.
+    /// An existing file is never overwritten; if the name is taken, one
+    /// other name is tried.
+    pub async fn save_audio(&self, audio_data: &[u8], format: &str) -> Result<PathBuf, StorageError> {
+        self.ensure_dir().await?;
+        let file_path = match write_new(self.new_path(format)?, audio_data).await {
+            Err(StorageError::NameCollision(taken)) => {
+                tracing::warn!("{} already exists; trying another name", taken.display());
+                write_new(self.new_path(format)?, audio_data).await?
+            }
+            written => written?,
+        };
+        self.record(&file_path, &AudioChecksum::of(audio_data)).await;
+        Ok(file_path)
+    }
+
+    fn new_path(&self, format: &str) -> Result<PathBuf, StorageError> {
+        let id = (self.names)();
+        if !is_valid_id(&id) {
+            return Err(StorageError::InvalidId(id));
+        }
+        Ok(self.temp_dir.join(format!("{}.{}", id, format)))
+    }
+
+    /// Write `audio_data` as `<id>.<format>` so `find` can look it up by id
+    pub async fn save_audio_as(&self, id: &str, audio_data: &[u8], format: &str) -> Result<PathBuf, StorageError> {
+        if !is_valid_id(id) {
+            return Err(StorageError::InvalidId(id.to_string()));
+        }
+        self.ensure_dir().await?;
+        let file_path = self.temp_dir.join(format!("{}.{}", id, format));
+        fs::write(&file_path, audio_data).await.map_err(|e| StorageError::Io(file_path.clone(), e))?;
+        self.record(&file_path, &AudioChecksum::of(audio_data)).await;
+        Ok(file_path)
+    }
+
+    /// Create the directory unless it exists. Another process creating it
+    /// at the same moment is fine; a file in the way is reported as such.
+    pub async fn ensure_dir(&self) -> Result<(), StorageError> {
+        ensure_dir(&self.temp_dir).await
+    }
+
+    /// Keep the checksum of a file written some other way, e.g. by ffmpeg
+    pub async fn track(&self, path: &Path) -> Result<()> {
+        if self.database.is_some() {
+            let checksum = AudioChecksum::of_file(path).await?;
+            self.record(path, &checksum).await;
+        }
+        Ok(())
+    }
+
+    /// Save `<id>.<format>` as `write` writes it to the path it's given,
+    /// e.g. by running ffmpeg, and `track` it. Written under another name
+    /// first so `find` never sees half a file; removed if `write` fails.
+    pub async fn write_via_partial<F, Fut, E>(&self, id: &str, format: &str, write: F) -> Result<PathBuf, E>
+    where
+        F: FnOnce(PathBuf) -> Fut,
+        Fut: std::future::Future<Output = Result<(), E>>,
+        E: From<StorageError>,
+    {
+        if !is_valid_id(id) {
+            return Err(StorageError::InvalidId(id.to_string()).into());
+        }
+        self.ensure_dir().await?;
+        let partial = self.temp_dir.join(format!("{}.partial-{}.{}", id, Uuid::new_v4(), format));
+        if let Err(e) = write(partial.clone()).await {
+            let _ = fs::remove_file(&partial).await;
+            return Err(e);
+        }
+        let path = self.temp_dir.join(format!("{}.{}", id, format));
+        fs::rename(&partial, &path).await.map_err(|e| StorageError::Io(path.clone(), e))?;
+        self.track(&path).await.map_err(|e| StorageError::Io(path.clone(), std::io::Error::other(e)))?;
+        Ok(path)
+    }
+
+    async fn record(&self, path: &Path, checksum: &AudioChecksum) {
+        let Some(database) = &self.database else {
+            return;
+        };
+        // The audio is there either way; it just can't be verified later
+        if let Err(e) = database.record_checksum(path, checksum).await {
+            tracing::warn!("Failed to record the checksum of {}: {}", path.display(), e);
+        }
+    }
+
+    /// Whether the file at `path` is what was written; see `integrity::check`
+    pub async fn check(&self, path: &Path, thorough: bool) -> AudioStatus {
+        let expected = match &self.database {
+            Some(database) => database.get_checksum(path).await.unwrap_or_else(|e| {
+                tracing::warn!("Failed to look up the checksum of {}: {}", path.display(), e);
+                None
+            }),
+            None => None,
+        };
+        integrity::check(path, expected.as_ref(), thorough).await
+    }
+
+    /// `find`, failing for a file that isn't what was written rather than
+    /// letting it play cut short
+    pub async fn find_verified(&self, id: &str) -> Result<PathBuf> {
+        let Some(path) = self.find(id) else {
+            bail!("Unknown audio: {}", id);
+        };
+        if self.check(&path, true).await == AudioStatus::Corrupted {
+            bail!("Audio {} is corrupted: it isn't what was generated. Generate it again.", id);
+        }
+        Ok(path)
+    }
+
+    /// The files in the directory with a quick check of each (their length)
+    pub async fn list(&self) -> Result<Vec<AudioFile>> {
+        let mut files = Vec::new();
+        for path in self.files().await? {
+            let status = self.check(&path, false).await;
+            files.push(audio_file(path, status).await);
+        }
+        Ok(files)
+    }
+
+    /// Read every file with a checksum, wherever it is, and every file in
+    /// the directory, comparing each with what was written
+    pub async fn verify_library(&self) -> Result<Vec<AudioFile>> {
+        let recorded = match &self.database {
+            Some(database) => database.get_checksums().await?,
+            None => Vec::new(),
+        };
+        let mut files = Vec::with_capacity(recorded.len());
+        for (path, checksum) in &recorded {
+            let status = integrity::check(path, Some(checksum), true).await;
+            files.push(audio_file(path.clone(), status).await);
+        }
+        for path in self.files().await? {
+            if !recorded.iter().any(|(recorded, _)| *recorded == path) {
+                files.push(audio_file(path, AudioStatus::Unverified).await);
+            }
+        }
+        let corrupted = files.iter().filter(|file| file.status == AudioStatus::Corrupted).count();
+        tracing::info!("Verified {} audio files: {} corrupted", files.len(), corrupted);
+        Ok(files)
+    }
+
+    /// Finished files in the directory, by name; partial ones are left out
+    async fn files(&self) -> Result<Vec<PathBuf>> {
+        if !self.temp_dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut paths = Vec::new();
+        let mut entries = fs::read_dir(&self.temp_dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.is_file() && is_valid_id(&integrity::id_of(&path)) {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        Ok(paths)
+    }
+
+    /// The file saved under `id`, whatever its format
+    pub fn find(&self, id: &str) -> Option<PathBuf> {
+        if !is_valid_id(id) {
+            return None;
+        }
+        std::fs::read_dir(&self.temp_dir)
+            .ok()?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .find(|path| path.is_file() && path.file_stem().and_then(|stem| stem.to_str()) == Some(id))
+    }
+
+    /// Whether `path` is an existing file inside this directory, after
+    /// resolving symlinks and `..`
+    pub fn is_in_scope(&self, path: &Path) -> bool {
+        is_file_within(&self.temp_dir, path)
+    }
+
+    /// Delete a file this manager handed out; anything else is refused
+    pub async fn remove(&self, path: &Path) -> Result<()> {
+        if !self.is_in_scope(path) {
+            bail!("Not a generated audio file: {}", path.display());
+        }
+        fs::remove_file(path).await?;
+        if let Some(database) = &self.database {
+            database.forget_checksums(path, false).await?;
+        }
+        Ok(())
+    }
+
+    /// Remove every file in the directory, e.g. ones left by a previous run
+    pub async fn cleanup(&self) -> Result<()> {
+        if !self.temp_dir.exists() {
+            return Ok(());
+        }
+        let mut entries = fs::read_dir(&self.temp_dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.is_file() {
+                if let Err(e) = fs::remove_file(&path).await {
+                    tracing::warn!("Failed to remove temp file {:?}: {}", path, e);
+                }
+            }
+        }
+        if let Some(database) = &self.database {
+            database.forget_checksums(&self.temp_dir, true).await?;
+        }
+        Ok(())
+    }
+
+    /// Package finished audio for the window. It is written to a file unless
+    /// `inline` was asked for and the clip is at most `MAX_INLINE_BYTES`.
+    pub async fn deliver(
+        &self,
+        id: &str,
+        audio_data: Vec<u8>,
+        format: &str,
+        inline: bool,
+        estimated_secs: f64,
+    ) -> Result<GeneratedAudio, StorageError> {
+        let size = audio_data.len();
+        if inline && size <= MAX_INLINE_BYTES {
+            let duration = crate::playback::decode(audio_data.clone()).ok().and_then(|decoded| {
+                use rodio::Source;
+                decoded.total_duration()
+            });
+            use base64::{Engine, engine::general_purpose};
+            let data_url = format!(
+                "data:{};base64,{}",
+                crate::config::mime_type(format),
+                general_purpose::STANDARD.encode(&audio_data)
+            );
+            return Ok(GeneratedAudio {
+                id: id.to_string(),
+                path: None,
+                data_url: Some(data_url),
+                size,
+                format: format.to_string(),
+                duration_secs: duration.map_or(estimated_secs, |d| d.as_secs_f64()),
+                coalesced: false,
+                downgraded: false,
+                downgrade_reason: None,
+                playlist: Vec::new(),
+                warnings: Vec::new(),
+                resolved: None,
+                gaps: None,
+            });
+        }
+
+        let path = self.save_audio_as(id, &audio_data, format).await?;
+        drop(audio_data);
+        Ok(describe(id, path, size, estimated_secs).await)
+    }
+
+    /// A file already saved under `id`, packaged as `deliver` would
+    pub async fn saved(&self, id: &str, estimated_secs: f64) -> Option<GeneratedAudio> {
+        let path = self.find(id)?;
+        let size = fs::metadata(&path).await.ok()?.len() as usize;
+        Some(describe(id, path, size, estimated_secs).await)
+    }
 }
 
-impl Drop for FileManager {
-    fn drop(&mut self) {
-        // In a real implementation, we'd queue cleanup for the background
-        // For now, files will be cleaned up by the OS or manual cleanup
+async fn describe(id: &str, path: PathBuf, size: usize, estimated_secs: f64) -> GeneratedAudio {
+    let probe = path.clone();
+    let duration = tokio::task::spawn_blocking(move || file_duration(&probe)).await.ok().flatten();
+    let format = path.extension().and_then(|ext| ext.to_str()).unwrap_or_default().to_string();
+    GeneratedAudio {
+        id: id.to_string(),
+        path: Some(path),
+        data_url: None,
+        size,
+        format,
+        duration_secs: duration.map_or(estimated_secs, |d| d.as_secs_f64()),
+        coalesced: false,
+        downgraded: false,
+        downgrade_reason: None,
+        playlist: Vec::new(),
+        warnings: Vec::new(),
+        resolved: None,
+        gaps: None,
     }
 }
 
+/// `FileManager::ensure_dir` for any directory, which only its owner can
+/// open (see `permissions`)
+async fn ensure_dir(dir: &Path) -> Result<(), StorageError> {
+    let created = match permissions::create_private_dir(dir) {
+        Ok(()) => Ok(()),
+        // Made by someone else between looking and creating
+        Err(_) if dir.is_dir() => permissions::restrict_dir(dir).map(|_| ()),
+        Err(e) => match dir.ancestors().find(|path| path.exists() && !path.is_dir()) {
+            Some(file) => return Err(StorageError::NotADirectory(file.to_path_buf())),
+            None => Err(e),
+        },
+    };
+    created.map_err(|e| StorageError::Io(dir.to_path_buf(), e))
+}
+
+/// Write a file that mustn't exist yet; a taken name is a `NameCollision`
+async fn write_new(path: PathBuf, audio_data: &[u8]) -> Result<PathBuf, StorageError> {
+    use tokio::io::AsyncWriteExt;
+    let mut file = match fs::OpenOptions::new().write(true).create_new(true).open(&path).await {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => return Err(StorageError::NameCollision(path)),
+        Err(e) => return Err(StorageError::Io(path, e)),
+    };
+    if let Err(e) = async { file.write_all(audio_data).await?; file.flush().await }.await {
+        drop(file);
+        let _ = fs::remove_file(&path).await;
+        return Err(StorageError::Io(path, e));
+    }
+    Ok(path)
+}
+
+async fn audio_file(path: PathBuf, status: AudioStatus) -> AudioFile {
+    let size = fs::metadata(&path).await.ok().map(|metadata| metadata.len());
+    AudioFile { id: integrity::id_of(&path), path, size, status }
+}
+
+impl Default for FileManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Whether `path` is an existing file somewhere under `dir`. Both sides are
+/// canonicalized, so it doesn't matter who spelled them how: macOS's
+/// `/var` → `/private/var` link and Windows 8.3 names like `RUNNER~1`
+/// resolve the same way on each side.
+pub fn is_file_within(dir: &Path, path: &Path) -> bool {
+    let (Ok(dir), Ok(path)) = (dir.canonicalize(), path.canonicalize()) else {
+        return false;
+    };
+    let (inside, base) = (comparable(&path), comparable(&dir));
+    inside.starts_with(&base) && inside != base && path.is_file()
+}
+
+/// Windows paths compare case-insensitively and canonicalize to the `\\?\`
+/// form, which some callers strip; use neither for the comparison
+#[cfg(windows)]
+fn comparable(path: &Path) -> PathBuf {
+    let path = path.to_string_lossy();
+    let path = path.strip_prefix(r"\\?\").unwrap_or(&path);
+    PathBuf::from(path.to_lowercase())
+}
+
+#[cfg(not(windows))]
+fn comparable(path: &Path) -> PathBuf {
+    path.to_path_buf()
+}
+
+/// Ids become file names, so keep them to what a UUID uses
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= 64 && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+/// Length of an audio file from its headers, when the format records it
+pub fn file_duration(path: &Path) -> Option<Duration> {
+    use rodio::Source;
+    let file = std::fs::File::open(path).ok()?;
+    rodio::Decoder::new(std::io::BufReader::new(file)).ok()?.total_duration()
+}
+
 pub async fn create_temp_audio_file(path: &Path, audio_data: &[u8]) -> Result<()> {
     // Ensure parent directory exists
     if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent).await?;
+        ensure_dir(parent).await?;
     }
-    
+
     // Write audio data
     fs::write(path, audio_data).await?;
-    
+
     Ok(())
 }
 
 pub async fn cleanup_temp_files() -> Result<()> {
-    let temp_dir = std::env::temp_dir().join("tts-player");
-    
-    if temp_dir.exists() {
-        // Remove all files in temp directory
-        let mut entries = fs::read_dir(&temp_dir).await?;
-        while let Some(entry) = entries.next_entry().await? {
-            let path = entry.path();
-            if path.is_file() {
-                if let Err(e) = fs::remove_file(&path).await {
-                    eprintln!("Failed to remove temp file {:?}: {}", path, e);
-                }
-            }
-        }
-    }
-    
-    Ok(())
+    FileManager::new().cleanup().await
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::silence::silent_wav;
     use tempfile::TempDir;
 
     #[tokio::test]
@@ -108,4 +574,199 @@ mod tests {
         // Should succeed because we create parent directories
         assert!(result.is_ok());
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_delivered_file_exists_in_scope() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = FileManager::with_dir(temp_dir.path().join("audio"));
+
+        let audio = manager.deliver("wav-clip", silent_wav(2000), "wav", false, 99.0).await.unwrap();
+        let path = audio.path.clone().unwrap();
+        assert!(path.exists());
+        assert!(manager.is_in_scope(&path));
+        assert_eq!(audio.size, fs::metadata(&path).await.unwrap().len() as usize);
+        assert_eq!(audio.duration_secs, 2.0);
+        assert!(audio.data_url.is_none());
+        assert_eq!(manager.find("wav-clip"), Some(path.clone()));
+        assert_eq!(manager.find("../wav-clip"), None);
+
+        let saved = manager.saved("wav-clip", 99.0).await.unwrap();
+        assert_eq!((saved.path, saved.size, saved.duration_secs), (Some(path.clone()), audio.size, 2.0));
+        assert!(manager.saved("other-clip", 99.0).await.is_none());
+
+        // Headerless formats fall back to the estimate
+        let audio = manager.deliver("mp3-clip", vec![0; 10], "mp3", false, 3.5).await.unwrap();
+        assert_eq!(audio.duration_secs, 3.5);
+
+        manager.remove(&path).await.unwrap();
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_scope_rejects_outside_paths() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = FileManager::with_dir(temp_dir.path().join("audio"));
+        let inside = manager.save_audio(&[1, 2, 3], "mp3").await.unwrap();
+
+        let outside = temp_dir.path().join("secret.txt");
+        fs::write(&outside, "keep me").await.unwrap();
+        let escaped = manager.dir().join("..").join("secret.txt");
+
+        assert!(manager.is_in_scope(&inside));
+        assert!(!manager.is_in_scope(&outside));
+        assert!(!manager.is_in_scope(&escaped));
+        assert!(!manager.is_in_scope(manager.dir()));
+        assert!(!manager.is_in_scope(&manager.dir().join("missing.mp3")));
+        assert!(manager.remove(&escaped).await.is_err());
+        assert!(outside.exists());
+
+        manager.cleanup().await.unwrap();
+        assert!(!inside.exists());
+    }
+
+    #[tokio::test]
+    async fn test_inline_only_for_small_clips() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = FileManager::with_dir(temp_dir.path());
+
+        let audio = manager.deliver("tiny", vec![1, 2, 3], "mp3", true, 1.0).await.unwrap();
+        assert!(audio.path.is_none());
+        assert_eq!(audio.data_url.as_deref(), Some("data:audio/mpeg;base64,AQID"));
+
+        let audio = manager.deliver("large", vec![0; MAX_INLINE_BYTES + 1], "mp3", true, 1.0).await.unwrap();
+        assert!(audio.data_url.is_none());
+        assert!(audio.path.unwrap().exists());
+    }
+
+    #[tokio::test]
+    async fn test_tampered_audio_is_reported_corrupted() {
+        let temp_dir = TempDir::new().unwrap();
+        let database = Database::open(&temp_dir.path().join("usage.db")).await.unwrap();
+        let manager = FileManager::with_dir(temp_dir.path().join("audio")).with_database(Some(database.clone()));
+
+        let intact = manager.save_audio_as("intact", &silent_wav(100), "wav").await.unwrap();
+        let truncated = manager.save_audio_as("truncated", &silent_wav(100), "wav").await.unwrap();
+        let flipped = manager.save_audio_as("flipped", &silent_wav(100), "wav").await.unwrap();
+        std::fs::write(&truncated, &silent_wav(100)[..700]).unwrap();
+        let mut audio = silent_wav(100);
+        audio[500] = 1;
+        std::fs::write(&flipped, &audio).unwrap();
+        // From before checksums were kept
+        std::fs::write(manager.dir().join("older.mp3"), [1, 2, 3]).unwrap();
+
+        let statuses = |files: Vec<AudioFile>| files.into_iter().map(|file| (file.id, file.status)).collect::<Vec<_>>();
+        assert_eq!(
+            statuses(manager.list().await.unwrap()),
+            [
+                ("flipped".to_string(), AudioStatus::Ok),
+                ("intact".to_string(), AudioStatus::Ok),
+                ("older".to_string(), AudioStatus::Unverified),
+                ("truncated".to_string(), AudioStatus::Corrupted),
+            ]
+        );
+        assert_eq!(manager.find_verified("intact").await.unwrap(), intact);
+        assert!(manager.find_verified("flipped").await.unwrap_err().to_string().contains("corrupted"));
+        assert!(manager.find_verified("truncated").await.is_err());
+
+        // Project audio elsewhere is verified too once tracked
+        let project_audio = temp_dir.path().join("item-1.wav");
+        std::fs::write(&project_audio, silent_wav(10)).unwrap();
+        manager.track(&project_audio).await.unwrap();
+        std::fs::remove_file(&project_audio).unwrap();
+
+        let mut verified = statuses(manager.verify_library().await.unwrap());
+        verified.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            verified,
+            [
+                ("flipped".to_string(), AudioStatus::Corrupted),
+                ("intact".to_string(), AudioStatus::Ok),
+                ("item-1".to_string(), AudioStatus::Missing),
+                ("older".to_string(), AudioStatus::Unverified),
+                ("truncated".to_string(), AudioStatus::Corrupted),
+            ]
+        );
+
+        manager.remove(&intact).await.unwrap();
+        assert_eq!(database.get_checksum(&intact).await.unwrap(), None);
+        manager.cleanup().await.unwrap();
+        assert_eq!(database.get_checksums().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_file_where_the_directory_should_be() {
+        let temp_dir = TempDir::new().unwrap();
+        let blocking = temp_dir.path().join("tts-player");
+        fs::write(&blocking, "not a directory").await.unwrap();
+
+        let manager = FileManager::with_dir(&blocking);
+        let error = manager.save_audio(&[1, 2, 3], "mp3").await.unwrap_err();
+        assert!(matches!(&error, StorageError::NotADirectory(path) if *path == blocking), "{:?}", error);
+        assert!(error.to_string().contains("is a file"), "{}", error);
+
+        // Further down, the file above is the one named
+        let manager = FileManager::with_dir(blocking.join("audio"));
+        let error = manager.save_audio_as("clip", &[1, 2, 3], "mp3").await.unwrap_err();
+        assert!(matches!(&error, StorageError::NotADirectory(path) if *path == blocking), "{:?}", error);
+        assert_eq!(fs::read_to_string(&blocking).await.unwrap(), "not a directory");
+    }
+
+    #[tokio::test]
+    async fn test_taken_name_is_retried_once() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let temp_dir = TempDir::new().unwrap();
+        let taken = temp_dir.path().join("taken.mp3");
+        fs::write(&taken, "earlier audio").await.unwrap();
+
+        let calls = AtomicUsize::new(0);
+        let manager = FileManager::with_dir(temp_dir.path())
+            .with_names(move || if calls.fetch_add(1, Ordering::SeqCst) == 0 { "taken" } else { "fresh" }.to_string());
+        let path = manager.save_audio(&[1, 2, 3], "mp3").await.unwrap();
+        assert_eq!(path, temp_dir.path().join("fresh.mp3"));
+        assert_eq!(fs::read_to_string(&taken).await.unwrap(), "earlier audio");
+
+        let manager = FileManager::with_dir(temp_dir.path()).with_names(|| "taken".to_string());
+        let error = manager.save_audio(&[1, 2, 3], "mp3").await.unwrap_err();
+        assert!(matches!(&error, StorageError::NameCollision(path) if *path == taken), "{:?}", error);
+        assert_eq!(fs::read_to_string(&taken).await.unwrap(), "earlier audio");
+    }
+
+    #[tokio::test]
+    async fn test_written_via_partial_or_not_at_all() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = FileManager::with_dir(temp_dir.path());
+        let failed = manager
+            .write_via_partial("converted", "wav", |partial| async move {
+                fs::write(&partial, "half a file").await?;
+                Err::<(), anyhow::Error>(anyhow::anyhow!("ffmpeg failed"))
+            })
+            .await;
+        assert_eq!(failed.unwrap_err().to_string(), "ffmpeg failed");
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+
+        let files = &manager;
+        let path = manager
+            .write_via_partial("converted", "wav", |partial| async move {
+                fs::write(&partial, "whole file").await?;
+                assert_eq!(files.find("converted"), None);
+                Ok::<(), anyhow::Error>(())
+            })
+            .await
+            .unwrap();
+        assert_eq!(path, temp_dir.path().join("converted.wav"));
+        assert_eq!(manager.find("converted"), Some(path.clone()));
+        assert_eq!(fs::read_to_string(&path).await.unwrap(), "whole file");
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_saves_create_the_directory_once() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().join("a").join("b");
+        let managers: Vec<_> = (0..8).map(|_| FileManager::with_dir(&dir)).collect();
+        let saves = managers.iter().map(|manager| manager.save_audio(&[1, 2, 3], "mp3"));
+        let saved = futures::future::join_all(saves).await;
+        assert!(saved.iter().all(Result::is_ok), "{:?}", saved);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 8);
+    }
+}
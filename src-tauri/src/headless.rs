@@ -0,0 +1,667 @@
+use crate::batch::{self, BatchOptions};
+use crate::cli::CliArgs;
+use crate::clipboard::{self, ClipboardSource, SystemClipboard};
+use crate::config::Config;
+use crate::database::{Database, SOURCE_CLI};
+use crate::documents;
+use crate::keychain::ApiKeys;
+use crate::report::{self, StatsReport};
+use crate::tts::{estimate_cost, GenerationEstimate, SpeechBackend, TTSError, TTSService, VALID_VOICE_IDS};
+use crate::output_format;
+use crate::playback::{self, PlaybackEnd, PLAYABLE_FORMATS};
+use crate::watch::{self, ContentTracker, Debouncer};
+use notify::Watcher;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+pub use crate::tts::{EXIT_AUTH, EXIT_NETWORK, EXIT_OTHER, EXIT_RATE_LIMIT, EXIT_SUCCESS, EXIT_USAGE};
+
+const DEFAULT_STATS_DAYS: i32 = 30;
+const DEFAULT_HISTORY_LIMIT: i32 = 20;
+
+#[derive(Debug)]
+pub enum HeadlessError {
+    /// The invocation itself was wrong (missing text, bad voice, ...)
+    Usage(String),
+    /// The API request failed; the exit code depends on why
+    Tts(TTSError),
+    /// Database, file IO or anything else
+    Failed(String),
+}
+
+impl std::fmt::Display for HeadlessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HeadlessError::Usage(msg) => write!(f, "{}", msg),
+            HeadlessError::Tts(e) => write!(f, "Failed to generate speech: {}", e),
+            HeadlessError::Failed(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl HeadlessError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            HeadlessError::Usage(_) => EXIT_USAGE,
+            HeadlessError::Tts(e) => e.exit_code(),
+            HeadlessError::Failed(_) => EXIT_OTHER,
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            HeadlessError::Usage(_) => "usage",
+            HeadlessError::Tts(e) => e.kind(),
+            HeadlessError::Failed(_) => "other",
+        }
+    }
+
+    /// The object printed on stdout in place of a result under `--json`
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "status": "error",
+            "error": {
+                "kind": self.kind(),
+                "message": self.to_string(),
+                "exit_code": self.exit_code(),
+            }
+        })
+    }
+}
+
+/// What `speak --json` prints on success
+#[derive(Debug, Clone, Serialize)]
+pub struct SpeakResult {
+    pub status: &'static str,
+    /// None when the audio was only played
+    pub output: Option<PathBuf>,
+    pub bytes: usize,
+    pub characters: usize,
+    pub chunks: usize,
+    pub estimated_duration_secs: f64,
+    pub estimated_cost: f64,
+    pub voice: String,
+    pub model: String,
+    pub format: String,
+}
+
+/// Run a CLI-only invocation to completion without starting Tauri.
+/// Returns the process exit code; all diagnostics go to stderr.
+pub async fn run(args: CliArgs, config: &Config) -> i32 {
+    // Everything from here on writes or plays a definite format
+    let config = &Config { format: output_format::for_cli(&config.format).to_string(), ..config.clone() };
+    let result = if let Some(shell) = args.completions {
+        crate::cli::write_completions(shell, &mut std::io::stdout());
+        Ok(())
+    } else if args.batch_dir.is_some() {
+        run_batch(&args, config).await
+    } else if args.watch {
+        run_watch(&args, config).await
+    } else if args.estimate {
+        print_estimate(&args, config)
+    } else if args.stats {
+        print_stats(&args, config).await
+    } else if args.history {
+        print_history(&args, config).await
+    } else if args.voices {
+        print_voices(&args)
+    } else if args.devices {
+        print_devices()
+    } else {
+        speak_and_play(&args, config).await
+    };
+
+    match result {
+        Ok(()) => EXIT_SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            if args.json {
+                println!("{}", e.to_json());
+            }
+            e.exit_code()
+        }
+    }
+}
+
+async fn speak_and_play(args: &CliArgs, config: &Config) -> Result<(), HeadlessError> {
+    let (result, audio_data) = speak(args, config).await?;
+    print_speak_result(args, &result)?;
+    if args.play {
+        play_audio(args, audio_data).await?;
+    }
+    Ok(())
+}
+
+fn print_speak_result(args: &CliArgs, result: &SpeakResult) -> Result<(), HeadlessError> {
+    if args.json {
+        let json = serde_json::to_string_pretty(result)
+            .map_err(|e| HeadlessError::Failed(e.to_string()))?;
+        println!("{}", json);
+    } else if let Some(output) = &result.output {
+        println!("{}", output.display());
+    }
+    Ok(())
+}
+
+/// Play on the chosen device until the end or Ctrl-C
+async fn play_audio(args: &CliArgs, audio_data: Vec<u8>) -> Result<(), HeadlessError> {
+    let stop = std::sync::Arc::new(AtomicBool::new(false));
+    let ctrl_c = {
+        let stop = stop.clone();
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                stop.store(true, Ordering::SeqCst);
+            }
+        })
+    };
+
+    let device = args.device.clone();
+    let result = tokio::task::spawn_blocking(move || playback::play(device.as_deref(), audio_data, &stop))
+        .await
+        .map_err(|e| HeadlessError::Failed(format!("Playback failed: {}", e)))?;
+    ctrl_c.abort();
+
+    match result.map_err(HeadlessError::Failed)? {
+        PlaybackEnd::Finished => Ok(()),
+        PlaybackEnd::Interrupted => {
+            tracing::info!("Playback interrupted");
+            Ok(())
+        }
+    }
+}
+
+async fn speak(args: &CliArgs, config: &Config) -> Result<(SpeakResult, Vec<u8>), HeadlessError> {
+    let text = read_input_text(args, config, &mut SystemClipboard)?;
+    check_playable(args, config)?;
+    let tts_service = cli_service(config).await?;
+    speak_with(&tts_service, args, config, &text).await
+}
+
+fn check_playable(args: &CliArgs, config: &Config) -> Result<(), HeadlessError> {
+    if args.play && !PLAYABLE_FORMATS.contains(&config.format.as_str()) {
+        return Err(HeadlessError::Usage(format!(
+            "--play supports {}, not {}",
+            PLAYABLE_FORMATS.join(", "),
+            config.format
+        )));
+    }
+    Ok(())
+}
+
+async fn cli_service(config: &Config) -> Result<TTSService, HeadlessError> {
+    let (api_key, _) = ApiKeys::from_env().resolve_or_warn().ok_or_else(|| {
+        HeadlessError::Usage("No API key: set OPENAI_API_KEY or save one in the app".to_string())
+    })?;
+    let tts_service = TTSService::from_config(Some(&api_key), config)
+        .await
+        .map_err(|e| HeadlessError::Failed(e.to_string()))?
+        .with_usage_source(SOURCE_CLI);
+    Ok(tts_service)
+}
+
+/// Generate `text` with `backend` and write it out (unless it's only being
+/// played). Voice and model come from `config`, which already has the CLI
+/// flags merged in. Returns the audio too, for `--play`.
+async fn speak_with<B: SpeechBackend>(
+    backend: &B,
+    args: &CliArgs,
+    config: &Config,
+    text: &str,
+) -> Result<(SpeakResult, Vec<u8>), HeadlessError> {
+    if text.trim().is_empty() {
+        return Err(HeadlessError::Usage("Text cannot be empty".to_string()));
+    }
+
+    tracing::info!("Generating speech for {} characters", text.len());
+    let audio_data = backend.synthesize(text, &config.voice, &config.model).await
+        .map_err(HeadlessError::Tts)?;
+
+    let output = if args.play && args.output.is_none() {
+        None
+    } else {
+        let output_path = output_path(args, &config.format);
+        if let Some(parent) = output_path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| HeadlessError::Failed(format!("Failed to create {}: {}", parent.display(), e)))?;
+        }
+        std::fs::write(&output_path, &audio_data)
+            .map_err(|e| HeadlessError::Failed(format!("Failed to write {}: {}", output_path.display(), e)))?;
+        Some(output_path)
+    };
+
+    let estimate = TTSService::estimate(text);
+    let result = SpeakResult {
+        status: "ok",
+        output,
+        bytes: audio_data.len(),
+        characters: estimate.character_count,
+        chunks: estimate.chunk_count,
+        estimated_duration_secs: estimate.estimated_duration_secs,
+        estimated_cost: estimate_cost(estimate.character_count as i64, &config.model),
+        voice: config.voice.clone(),
+        model: config.model.clone(),
+        format: config.format.clone(),
+    };
+    Ok((result, audio_data))
+}
+
+async fn run_batch(args: &CliArgs, config: &Config) -> Result<(), HeadlessError> {
+    let options = BatchOptions {
+        input_dir: PathBuf::from(args.batch_dir.as_deref().unwrap_or(".")),
+        output_dir: PathBuf::from(args.output.as_deref().unwrap_or(".")),
+        recursive: args.recursive,
+        glob: args.glob.clone(),
+        voice: config.voice.clone(),
+        model: config.model.clone(),
+        format: config.format.clone(),
+        concurrency: args.jobs.unwrap_or(batch::DEFAULT_CONCURRENCY),
+        max_input_bytes: config.max_input_bytes,
+    };
+    if !options.input_dir.is_dir() {
+        return Err(HeadlessError::Usage(format!("Not a directory: {}", options.input_dir.display())));
+    }
+
+    let tts_service = cli_service(config).await?;
+    let summary = batch::run_batch(&tts_service, &options).await
+        .map_err(HeadlessError::Usage)?;
+    if args.json {
+        let json = serde_json::to_string_pretty(&summary)
+            .map_err(|e| HeadlessError::Failed(e.to_string()))?;
+        println!("{}", json);
+    } else {
+        print!("{}", report::format_batch_summary(&summary));
+    }
+
+    if summary.failed > 0 {
+        return Err(HeadlessError::Failed(format!("{} file(s) failed", summary.failed)));
+    }
+    Ok(())
+}
+
+/// Speak `args.file` whenever it's saved, until Ctrl-C. A failed
+/// generation is reported and the watch carries on.
+async fn run_watch(args: &CliArgs, config: &Config) -> Result<(), HeadlessError> {
+    let path = PathBuf::from(args.file.as_deref().unwrap_or_default());
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_os_string())
+        .ok_or_else(|| HeadlessError::Usage(format!("Not a file: {}", path.display())))?;
+    let initial = read_file(&path, config.max_input_bytes)?;
+    check_playable(args, config)?;
+    let tts_service = cli_service(config).await?;
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event| {
+        let _ = tx.send(event);
+    })
+    .map_err(|e| HeadlessError::Failed(format!("Failed to watch {}: {}", path.display(), e)))?;
+    // Watch the directory: editors that save by renaming a temp file over
+    // the original would otherwise leave us watching a deleted file
+    let dir = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    watcher
+        .watch(dir, notify::RecursiveMode::NonRecursive)
+        .map_err(|e| HeadlessError::Failed(format!("Failed to watch {}: {}", path.display(), e)))?;
+
+    let mut tracker = ContentTracker::default();
+    tracker.baseline(&initial);
+    let mut debouncer = Debouncer::new(watch::DEBOUNCE);
+    if !args.quiet {
+        eprintln!("Watching {} (Ctrl-C to stop)", path.display());
+    }
+
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    loop {
+        let deadline = debouncer.deadline();
+        tokio::select! {
+            _ = &mut ctrl_c => break,
+            event = rx.recv() => match event {
+                Some(Ok(event)) => {
+                    let event: notify::Event = event;
+                    if event.paths.iter().any(|p| p.file_name() == Some(file_name.as_os_str())) {
+                        debouncer.event(Instant::now());
+                    }
+                }
+                Some(Err(e)) => tracing::warn!("Watch error: {}", e),
+                None => break,
+            },
+            _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now).into()), if deadline.is_some() => {
+                if !debouncer.take_ready(Instant::now()) {
+                    continue;
+                }
+                let text = match documents::read_text(&path, config.max_input_bytes) {
+                    Ok(decoded) => tracker.next_text(&decoded.text),
+                    Err(e) => {
+                        // Mid-save or deleted; the next event will tell
+                        tracing::info!("Skipping change to {}: {}", path.display(), e);
+                        None
+                    }
+                };
+                let Some(text) = text else {
+                    tracing::info!("{} unchanged", path.display());
+                    continue;
+                };
+                tokio::select! {
+                    _ = &mut ctrl_c => break,
+                    result = speak_change(&tts_service, args, config, &text) => {
+                        if let Err(e) = result {
+                            eprintln!("Error: {}", e);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn speak_change(tts_service: &TTSService, args: &CliArgs, config: &Config, text: &str) -> Result<(), HeadlessError> {
+    let (result, audio_data) = speak_with(tts_service, args, config, text).await?;
+    print_speak_result(args, &result)?;
+    if args.play {
+        play_audio(args, audio_data).await?;
+    }
+    Ok(())
+}
+
+/// Dry run: no HTTP client, no database, nothing written
+fn print_estimate(args: &CliArgs, config: &Config) -> Result<(), HeadlessError> {
+    let estimate = estimate_input(args, config)?;
+    if args.json {
+        let json = serde_json::to_string_pretty(&estimate)
+            .map_err(|e| HeadlessError::Failed(e.to_string()))?;
+        println!("{}", json);
+    } else {
+        print!("{}", report::format_estimate(&estimate));
+    }
+    Ok(())
+}
+
+fn estimate_input(args: &CliArgs, config: &Config) -> Result<GenerationEstimate, HeadlessError> {
+    let text = read_input_text(args, config, &mut SystemClipboard)?;
+    Ok(TTSService::estimate(&text))
+}
+
+async fn print_stats(args: &CliArgs, config: &Config) -> Result<(), HeadlessError> {
+    let days = args.days.unwrap_or(DEFAULT_STATS_DAYS);
+
+    // Same database file the app uses, so the numbers match the UI
+    let database = Database::open(&config.database_path()).await
+        .map_err(|e| HeadlessError::Failed(format!("Database error: {}", e)))?;
+    let stats = database.get_usage_stats(days, None).await
+        .map_err(|e| HeadlessError::Failed(format!("Database error: {}", e)))?;
+    // Failed requests aren't billed, so they don't count towards the cost
+    let model_usage = database.get_billed_model_usage(days, None).await
+        .map_err(|e| HeadlessError::Failed(format!("Database error: {}", e)))?;
+
+    let report = StatsReport::new(days, stats, &model_usage);
+    if args.json {
+        let json = serde_json::to_string_pretty(&report)
+            .map_err(|e| HeadlessError::Failed(e.to_string()))?;
+        println!("{}", json);
+    } else {
+        print!("{}", report::format_stats(&report));
+    }
+    Ok(())
+}
+
+async fn print_history(args: &CliArgs, config: &Config) -> Result<(), HeadlessError> {
+    let limit = args.limit.unwrap_or(DEFAULT_HISTORY_LIMIT);
+
+    let database = Database::open(&config.database_path()).await
+        .map_err(|e| HeadlessError::Failed(format!("Database error: {}", e)))?;
+    let records = database.get_usage_records(limit, args.days).await
+        .map_err(|e| HeadlessError::Failed(format!("Database error: {}", e)))?;
+
+    if args.json {
+        let json = serde_json::to_string_pretty(&report::history_json(&records))
+            .map_err(|e| HeadlessError::Failed(e.to_string()))?;
+        println!("{}", json);
+    } else {
+        print!("{}", report::format_history(&records));
+    }
+    Ok(())
+}
+
+fn print_devices() -> Result<(), HeadlessError> {
+    for device in playback::output_devices().map_err(HeadlessError::Failed)? {
+        println!("{}", device);
+    }
+    Ok(())
+}
+
+fn print_voices(args: &CliArgs) -> Result<(), HeadlessError> {
+    if args.json {
+        let json = serde_json::to_string_pretty(VALID_VOICE_IDS)
+            .map_err(|e| HeadlessError::Failed(e.to_string()))?;
+        println!("{}", json);
+    } else {
+        for voice_id in VALID_VOICE_IDS {
+            println!("{}", voice_id);
+        }
+    }
+    Ok(())
+}
+
+/// Pick the text source: --text, then --file, then --clipboard
+fn read_input_text(args: &CliArgs, config: &Config, clipboard: &mut dyn ClipboardSource) -> Result<String, HeadlessError> {
+    if let Some(text) = &args.text {
+        return Ok(text.clone());
+    }
+    if let Some(file) = &args.file {
+        return read_file(Path::new(file), config.max_input_bytes);
+    }
+    if args.clipboard {
+        return match clipboard::read(clipboard).map_err(HeadlessError::Failed)?.map(|copied| copied.text) {
+            Some(text) if !text.trim().is_empty() => Ok(text),
+            Some(_) => Err(HeadlessError::Usage("Clipboard is empty".to_string())),
+            None => Err(HeadlessError::Usage("Clipboard does not contain text".to_string())),
+        };
+    }
+    Err(HeadlessError::Usage("No text given (use --text, --file or --clipboard)".to_string()))
+}
+
+/// Read a text file in whatever encoding it was saved, saying so on stderr
+/// when that was a guess or lost characters
+fn read_file(path: &Path, max_bytes: u64) -> Result<String, HeadlessError> {
+    let decoded =
+        documents::read_text(path, max_bytes).map_err(|e| HeadlessError::Usage(format!("{}: {}", path.display(), e)))?;
+    for warning in decoded.warnings() {
+        eprintln!("Warning: {}", warning.text);
+    }
+    Ok(decoded.text)
+}
+
+fn output_path(args: &CliArgs, format: &str) -> PathBuf {
+    match &args.output {
+        Some(path) => PathBuf::from(path),
+        None => PathBuf::from(format!("tts-{}.{}", chrono::Local::now().format("%Y%m%d-%H%M%S"), format)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::FakeBackend;
+
+    struct FakeClipboard {
+        contents: Option<String>,
+        reads: usize,
+    }
+
+    impl FakeClipboard {
+        fn with(contents: Option<&str>) -> Self {
+            Self { contents: contents.map(str::to_string), reads: 0 }
+        }
+    }
+
+    impl ClipboardSource for FakeClipboard {
+        fn read_text(&mut self) -> Result<Option<String>, String> {
+            self.reads += 1;
+            Ok(self.contents.clone())
+        }
+    }
+
+    #[test]
+    fn test_missing_text_is_usage_error() {
+        let mut clipboard = FakeClipboard::with(Some("ignored"));
+        let err = read_input_text(&CliArgs { no_gui: true, ..Default::default() }, &Config::default(), &mut clipboard).unwrap_err();
+        assert_eq!(err.exit_code(), EXIT_USAGE);
+        assert_eq!(clipboard.reads, 0);
+    }
+
+    #[test]
+    fn test_text_preferred_over_file() {
+        let args = CliArgs {
+            text: Some("Hello".to_string()),
+            file: Some("/does/not/exist.txt".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(read_input_text(&args, &Config::default(), &mut FakeClipboard::with(None)).unwrap(), "Hello");
+    }
+
+    #[test]
+    fn test_clipboard_has_lowest_precedence() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let file = dir.path().join("notes.txt");
+        std::fs::write(&file, "From file").unwrap();
+
+        let mut clipboard = FakeClipboard::with(Some("From clipboard"));
+        let args = CliArgs {
+            text: Some("From text".to_string()),
+            clipboard: true,
+            ..Default::default()
+        };
+        assert_eq!(read_input_text(&args, &Config::default(), &mut clipboard).unwrap(), "From text");
+
+        let args = CliArgs {
+            file: Some(file.to_string_lossy().to_string()),
+            clipboard: true,
+            ..Default::default()
+        };
+        assert_eq!(read_input_text(&args, &Config::default(), &mut clipboard).unwrap(), "From file");
+        assert_eq!(clipboard.reads, 0);
+        let small = Config { max_input_bytes: 4, ..Config::default() };
+        let err = read_input_text(&args, &small, &mut clipboard).unwrap_err();
+        assert!(err.to_string().contains("the limit for this kind of file is 4 bytes"), "{}", err);
+        assert_eq!(err.exit_code(), EXIT_USAGE);
+
+        let args = CliArgs { clipboard: true, ..Default::default() };
+        assert_eq!(read_input_text(&args, &Config::default(), &mut clipboard).unwrap(), "From clipboard");
+        assert_eq!(clipboard.reads, 1);
+    }
+
+    #[test]
+    fn test_clipboard_empty_or_not_text() {
+        let args = CliArgs { clipboard: true, ..Default::default() };
+
+        let err = read_input_text(&args, &Config::default(), &mut FakeClipboard::with(Some("  \n"))).unwrap_err();
+        assert!(err.to_string().contains("empty"));
+
+        let err = read_input_text(&args, &Config::default(), &mut FakeClipboard::with(None)).unwrap_err();
+        assert!(err.to_string().contains("does not contain text"));
+        assert_eq!(err.exit_code(), EXIT_USAGE);
+    }
+
+    #[tokio::test]
+    async fn test_estimate_makes_no_requests() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", mockito::Matcher::Any)
+            .expect(0)
+            .create_async()
+            .await;
+        let dir = tempfile::TempDir::new().unwrap();
+        let config = Config { data_dir: dir.path().to_path_buf(), api_base_url: server.url(), ..Config::default() };
+
+        let args = CliArgs {
+            text: Some("Sentence one. ".repeat(600)),
+            estimate: true,
+            ..Default::default()
+        };
+        let estimate = estimate_input(&args, &config).unwrap();
+        assert_eq!(estimate.character_count, 8400);
+        assert_eq!(estimate.chunk_count, 3);
+
+        // The whole command, pointed at the server, neither calls it nor
+        // opens the usage database
+        assert_eq!(run(args, &config).await, EXIT_SUCCESS);
+        mock.assert_async().await;
+        assert!(!config.database_path().exists());
+    }
+
+    #[tokio::test]
+    async fn test_speak_json_schema() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let output = dir.path().join("out").join("hello.opus");
+        let args = CliArgs {
+            output: Some(output.to_string_lossy().to_string()),
+            json: true,
+            ..Default::default()
+        };
+        let config = Config { format: "opus".to_string(), model: "tts-1".to_string(), ..Config::default() };
+
+        let (result, _) = speak_with(&FakeBackend::default(), &args, &config, "Hello there").await.unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), b"Hello there");
+
+        let json = serde_json::to_value(&result).unwrap();
+        let keys: Vec<&str> = json.as_object().unwrap().keys().map(String::as_str).collect();
+        assert_eq!(
+            keys,
+            vec!["bytes", "characters", "chunks", "estimated_cost", "estimated_duration_secs", "format", "model", "output", "status", "voice"]
+        );
+        assert_eq!(json["status"], "ok");
+        assert_eq!(json["output"], output.to_string_lossy().as_ref());
+        assert_eq!(json["characters"], 11);
+        assert_eq!(json["chunks"], 1);
+        assert_eq!(json["format"], "opus");
+        assert!(json["estimated_cost"].as_f64().unwrap() > 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_auth_failure_exit_code() {
+        let backend = FakeBackend::scripted(vec![Err(TTSError::Authentication("Incorrect API key".to_string()))]);
+        let args = CliArgs { json: true, ..Default::default() };
+
+        let err = speak_with(&backend, &args, &Config::default(), "Hello").await.unwrap_err();
+        assert_eq!(err.exit_code(), EXIT_AUTH);
+
+        let json = err.to_json();
+        assert_eq!(json["status"], "error");
+        assert_eq!(json["error"]["kind"], "auth");
+        assert_eq!(json["error"]["exit_code"], 3);
+        assert!(json["error"]["message"].as_str().unwrap().contains("Incorrect API key"));
+
+        let backend = FakeBackend::scripted(vec![Err(TTSError::RateLimit(Some(20)))]);
+        let err = speak_with(&backend, &args, &Config::default(), "Hello").await.unwrap_err();
+        assert_eq!(err.exit_code(), EXIT_RATE_LIMIT);
+    }
+
+    #[tokio::test]
+    async fn test_play_without_output_writes_nothing() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let args = CliArgs { play: true, ..Default::default() };
+
+        let cwd_files = std::fs::read_dir(".").unwrap().count();
+        let (result, audio) = speak_with(&FakeBackend::default(), &args, &Config::default(), "Hi").await.unwrap();
+        assert_eq!(result.output, None);
+        assert_eq!(audio, b"Hi");
+        assert_eq!(std::fs::read_dir(".").unwrap().count(), cwd_files);
+
+        // --play with --output saves and plays
+        let output = dir.path().join("both.mp3");
+        let args = CliArgs { play: true, output: Some(output.to_string_lossy().to_string()), ..Default::default() };
+        let (result, _) = speak_with(&FakeBackend::default(), &args, &Config::default(), "Hi").await.unwrap();
+        assert_eq!(result.output, Some(output.clone()));
+        assert!(output.exists());
+    }
+
+    #[test]
+    fn test_explicit_output_path() {
+        let args = CliArgs { output: Some("out/hello.mp3".to_string()), ..Default::default() };
+        assert_eq!(output_path(&args, "mp3"), PathBuf::from("out/hello.mp3"));
+        assert!(output_path(&CliArgs::default(), "opus").to_string_lossy().ends_with(".opus"));
+    }
+}
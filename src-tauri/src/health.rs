@@ -0,0 +1,423 @@
+//! Diagnostics for the settings screen: why generation might fail, checked
+//! piece by piece without changing anything. Each probe has its own
+//! timeout and they run at the same time, so the whole check takes a few
+//! seconds at most even with the network down.
+
+use crate::database::Database;
+use crate::tts::{TTSError, TTSService};
+use serde::Serialize;
+use std::future::Future;
+use std::path::Path;
+use std::time::Duration;
+
+/// How long one probe may take
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Less free space than this is worth a warning; an hour of mp3 is ~60 MB
+pub const LOW_SPACE_BYTES: u64 = 200 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Ok,
+    /// Works, but something may go wrong later (e.g. no FFmpeg for long text)
+    Warning,
+    Failed,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthItem {
+    /// e.g. "apiKey"
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+    /// What to do about it, unless it's fine
+    pub hint: Option<String>,
+}
+
+impl HealthItem {
+    fn ok(name: &str, detail: impl Into<String>) -> Self {
+        Self { name: name.to_string(), status: CheckStatus::Ok, detail: detail.into(), hint: None }
+    }
+
+    fn warning(name: &str, detail: impl Into<String>, hint: &str) -> Self {
+        Self { status: CheckStatus::Warning, hint: Some(hint.to_string()), ..Self::ok(name, detail) }
+    }
+
+    fn failed(name: &str, detail: impl Into<String>, hint: &str) -> Self {
+        Self { status: CheckStatus::Failed, hint: Some(hint.to_string()), ..Self::ok(name, detail) }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthReport {
+    /// The worst status of any item
+    pub status: CheckStatus,
+    pub items: Vec<HealthItem>,
+}
+
+impl HealthReport {
+    pub fn new(items: Vec<HealthItem>) -> Self {
+        let status = if items.iter().any(|item| item.status == CheckStatus::Failed) {
+            CheckStatus::Failed
+        } else if items.iter().any(|item| item.status == CheckStatus::Warning) {
+            CheckStatus::Warning
+        } else {
+            CheckStatus::Ok
+        };
+        Self { status, items }
+    }
+}
+
+/// `probe`, or a failure once `timeout` has passed without an answer
+pub async fn within(name: &str, timeout: Duration, probe: impl Future<Output = HealthItem>) -> HealthItem {
+    tokio::time::timeout(timeout, probe).await.unwrap_or_else(|_| {
+        HealthItem::failed(
+            name,
+            format!("No answer within {} s", timeout.as_secs_f64()),
+            "Something is stuck; try again, and check the network and disk if it keeps happening",
+        )
+    })
+}
+
+/// Whether there is a key and the API accepts it
+pub async fn api_key(service: &TTSService) -> HealthItem {
+    const NAME: &str = "apiKey";
+    if !service.is_configured() {
+        return HealthItem::failed(NAME, "No API key is set", "Add your OpenAI API key in Settings");
+    }
+    match service.validate_api_key().await {
+        Ok(()) => HealthItem::ok(NAME, "The API key is valid"),
+        Err(TTSError::RateLimit(_)) => HealthItem::warning(
+            NAME,
+            "The API key is valid, but it is being rate limited",
+            "Wait a little before generating more, or check your usage limits on the OpenAI dashboard",
+        ),
+        Err(e @ TTSError::Authentication(_)) => HealthItem::failed(
+            NAME,
+            e.to_string(),
+            "Check the key, and the organization and project if you set them, on the OpenAI dashboard",
+        ),
+        Err(e) => HealthItem::warning(
+            NAME,
+            format!("Couldn't check the API key: {}", e),
+            "See the connectivity check below",
+        ),
+    }
+}
+
+/// Which FFmpeg is used and its version. Only long text and conversions
+/// need it, so a missing one is a warning.
+pub async fn ffmpeg(ffmpeg_path: &str) -> HealthItem {
+    const NAME: &str = "ffmpeg";
+    let source = if ffmpeg_path == "ffmpeg" { "on the PATH".to_string() } else { format!("at {}", ffmpeg_path) };
+    let output = tokio::process::Command::new(ffmpeg_path).arg("-version").kill_on_drop(true).output().await;
+    match output {
+        Ok(output) if output.status.success() => {
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let version = stdout
+                .lines()
+                .next()
+                .and_then(|line| line.strip_prefix("ffmpeg version "))
+                .and_then(|rest| rest.split_whitespace().next())
+                .unwrap_or("of unknown version");
+            HealthItem::ok(NAME, format!("FFmpeg {} {}", version, source))
+        }
+        Ok(output) => HealthItem::warning(
+            NAME,
+            format!("FFmpeg {} exits with {}", source, output.status),
+            "Reinstall FFmpeg, or set ffmpeg_path in config.toml to a working one",
+        ),
+        Err(e) => HealthItem::warning(
+            NAME,
+            format!("No FFmpeg {} ({})", source, e),
+            "Install FFmpeg to generate text over 4000 characters and convert audio, or set ffmpeg_path in \
+             config.toml (or TTS_PLAYER_FFMPEG) to where it is",
+        ),
+    }
+}
+
+/// Which Tesseract reads text in images, and its version. Only images need
+/// it, so a missing one is a warning.
+pub async fn tesseract(tesseract_path: &str) -> HealthItem {
+    const NAME: &str = "tesseract";
+    let source =
+        if tesseract_path == "tesseract" { "on the PATH".to_string() } else { format!("at {}", tesseract_path) };
+    let output = tokio::process::Command::new(tesseract_path).arg("--version").kill_on_drop(true).output().await;
+    match output {
+        Ok(output) if output.status.success() => {
+            // Older versions print it to stderr
+            let printed = [output.stdout, output.stderr].concat();
+            let printed = String::from_utf8_lossy(&printed);
+            let version = printed
+                .lines()
+                .find_map(|line| line.strip_prefix("tesseract "))
+                .and_then(|rest| rest.split_whitespace().next())
+                .unwrap_or("of unknown version");
+            HealthItem::ok(NAME, format!("Tesseract {} {}", version, source))
+        }
+        Ok(output) => HealthItem::warning(
+            NAME,
+            format!("Tesseract {} exits with {}", source, output.status),
+            "Reinstall Tesseract, or set tesseract_path in config.toml to a working one",
+        ),
+        Err(e) => HealthItem::warning(
+            NAME,
+            format!("No Tesseract {} ({})", source, e),
+            "Install Tesseract to read text in images, or set tesseract_path in config.toml (or \
+             TTS_PLAYER_TESSERACT) to where it is",
+        ),
+    }
+}
+
+/// Whether the usage database is open and takes writes
+pub async fn database(database: Option<&Database>) -> HealthItem {
+    const NAME: &str = "database";
+    let Some(database) = database else {
+        return HealthItem::failed(
+            NAME,
+            "The database couldn't be opened, so usage and settings aren't saved",
+            "Check that the data directory exists and is writable, then restart the app",
+        );
+    };
+    let version = match database.schema_version().await {
+        Ok(version) => version,
+        Err(e) => {
+            return HealthItem::failed(NAME, format!("The database can't be read: {}", e), "Restart the app")
+        }
+    };
+    match database.check_writable().await {
+        Ok(()) => HealthItem::ok(NAME, format!("Open and writable, schema version {}", version)),
+        Err(e) => HealthItem::failed(
+            NAME,
+            format!("The database can't be written: {}", e),
+            "Check that the disk isn't full and that the database file and its directory are writable",
+        ),
+    }
+}
+
+/// Whether a file can be written in `dir`, and how much space is left. The
+/// directory is created if it doesn't exist yet, as it would be on first use.
+pub async fn storage(name: &str, dir: &Path) -> HealthItem {
+    let dir = dir.to_path_buf();
+    let owned_name = name.to_string();
+    tokio::task::spawn_blocking(move || {
+        let name = owned_name;
+        let not_writable = |e: std::io::Error| {
+            HealthItem::failed(
+                &name,
+                format!("{} isn't writable: {}", dir.display(), e),
+                "Check the directory's permissions, or move it elsewhere in config.toml",
+            )
+        };
+        if let Err(e) = crate::permissions::create_private_dir(&dir) {
+            return not_writable(e);
+        }
+        let probe = dir.join(format!(".health-check-{}", uuid::Uuid::new_v4()));
+        if let Err(e) = std::fs::write(&probe, b"") {
+            return not_writable(e);
+        }
+        let _ = std::fs::remove_file(&probe);
+        match fs2::available_space(&dir) {
+            Ok(free) if free < LOW_SPACE_BYTES => HealthItem::warning(
+                &name,
+                format!("{} has only {} MB free", dir.display(), free / (1024 * 1024)),
+                "Free up disk space; generated audio may not fit",
+            ),
+            Ok(free) => HealthItem::ok(&name, format!("{} is writable, {} MB free", dir.display(), free / (1024 * 1024))),
+            Err(e) => HealthItem::ok(&name, format!("{} is writable (free space unknown: {})", dir.display(), e)),
+        }
+    })
+    .await
+    .unwrap_or_else(|e| HealthItem::failed(name, format!("The check crashed: {}", e), "Try again"))
+}
+
+/// Whether the configured API server answers
+pub async fn connectivity(service: &TTSService, base_url: &str) -> HealthItem {
+    const NAME: &str = "connectivity";
+    if service.is_reachable().await {
+        HealthItem::ok(NAME, format!("{} answers", base_url))
+    } else {
+        HealthItem::failed(
+            NAME,
+            format!("{} can't be reached", base_url),
+            "Check the internet connection, a proxy or firewall, and api_base_url in config.toml",
+        )
+    }
+}
+
+/// Every probe at once, each given `timeout`. `dirs` are the storage
+/// directories by name.
+pub async fn run(
+    service: &TTSService,
+    base_url: &str,
+    ffmpeg_path: &str,
+    tesseract_path: &str,
+    dirs: &[(&str, &Path)],
+    timeout: Duration,
+) -> HealthReport {
+    let storage = futures::future::join_all(dirs.iter().map(|(name, dir)| within(name, timeout, storage(name, dir))));
+    let (api_key, ffmpeg, tesseract, database, connectivity, storage) = tokio::join!(
+        within("apiKey", timeout, api_key(service)),
+        within("ffmpeg", timeout, ffmpeg(ffmpeg_path)),
+        within("tesseract", timeout, tesseract(tesseract_path)),
+        within("database", timeout, database(service.database())),
+        within("connectivity", timeout, connectivity(service, base_url)),
+        storage,
+    );
+    let mut items = vec![api_key, ffmpeg, tesseract, database];
+    items.extend(storage);
+    items.push(connectivity);
+    HealthReport::new(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Nothing listens here, so connections are refused at once
+    const UNREACHABLE: &str = "http://127.0.0.1:1";
+
+    #[tokio::test]
+    async fn test_api_key_probe() {
+        let mut server = mockito::Server::new_async().await;
+        let service = TTSService::new("", &server.url());
+        assert_eq!(api_key(&service).await.status, CheckStatus::Failed);
+
+        let valid = server.mock("GET", "/v1/models").with_status(200).with_body("{\"data\":[]}").create_async().await;
+        let service = TTSService::new("sk-test", &server.url());
+        assert_eq!(api_key(&service).await.status, CheckStatus::Ok);
+        valid.remove_async().await;
+
+        server
+            .mock("GET", "/v1/models")
+            .with_status(401)
+            .with_body("{\"error\":{\"message\":\"Incorrect API key provided\"}}")
+            .create_async()
+            .await;
+        let item = api_key(&service).await;
+        assert_eq!(item.status, CheckStatus::Failed);
+        assert!(item.detail.contains("Incorrect API key"), "{}", item.detail);
+        assert!(item.hint.is_some());
+
+        let offline = api_key(&TTSService::new("sk-test", UNREACHABLE)).await;
+        assert_eq!(offline.status, CheckStatus::Warning);
+    }
+
+    #[tokio::test]
+    async fn test_ffmpeg_probe() {
+        let missing = ffmpeg("/nonexistent/ffmpeg").await;
+        assert_eq!(missing.status, CheckStatus::Warning);
+        assert!(missing.detail.starts_with("No FFmpeg at /nonexistent/ffmpeg"), "{}", missing.detail);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let dir = tempfile::TempDir::new().unwrap();
+            let fake = dir.path().join("ffmpeg");
+            std::fs::write(&fake, "#!/bin/sh\necho 'ffmpeg version 7.1-test Copyright (c) 2000-2024'\n").unwrap();
+            std::fs::set_permissions(&fake, std::fs::Permissions::from_mode(0o755)).unwrap();
+            let found = ffmpeg(fake.to_str().unwrap()).await;
+            assert_eq!(found.status, CheckStatus::Ok);
+            assert_eq!(found.detail, format!("FFmpeg 7.1-test at {}", fake.display()));
+
+            std::fs::write(&fake, "#!/bin/sh\nexit 3\n").unwrap();
+            assert_eq!(ffmpeg(fake.to_str().unwrap()).await.status, CheckStatus::Warning);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tesseract_probe() {
+        let missing = tesseract("/nonexistent/tesseract").await;
+        assert_eq!(missing.status, CheckStatus::Warning);
+        assert!(missing.detail.starts_with("No Tesseract at /nonexistent/tesseract"), "{}", missing.detail);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let dir = tempfile::TempDir::new().unwrap();
+            let fake = dir.path().join("tesseract");
+            std::fs::write(&fake, "#!/bin/sh\necho 'tesseract 5.3.4-test' >&2\necho ' leptonica-1.84.1' >&2\n").unwrap();
+            std::fs::set_permissions(&fake, std::fs::Permissions::from_mode(0o755)).unwrap();
+            let found = tesseract(fake.to_str().unwrap()).await;
+            assert_eq!(found.status, CheckStatus::Ok);
+            assert_eq!(found.detail, format!("Tesseract 5.3.4-test at {}", fake.display()));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_database_probe() {
+        assert_eq!(database(None).await.status, CheckStatus::Failed);
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = Database::open(&dir.path().join("tts_usage.db")).await.unwrap();
+        let item = database(Some(&db)).await;
+        assert_eq!(item, HealthItem::ok("database", format!("Open and writable, schema version {}", crate::database::SCHEMA_VERSION)));
+        // The probe's row was rolled back
+        assert!(db.get_all_settings().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_storage_probe() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let item = storage("audioDir", &dir.path().join("audio")).await;
+        assert_ne!(item.status, CheckStatus::Failed, "{:?}", item);
+        assert!(dir.path().join("audio").is_dir());
+        assert_eq!(std::fs::read_dir(dir.path().join("audio")).unwrap().count(), 0, "probe file left behind");
+
+        let blocking = dir.path().join("file");
+        std::fs::write(&blocking, "").unwrap();
+        let item = storage("audioDir", &blocking.join("audio")).await;
+        assert_eq!(item.status, CheckStatus::Failed);
+        assert!(item.detail.contains("isn't writable"), "{}", item.detail);
+    }
+
+    #[tokio::test]
+    async fn test_connectivity_probe() {
+        let mut server = mockito::Server::new_async().await;
+        server.mock("HEAD", "/").with_status(404).create_async().await;
+        let service = TTSService::new("", &server.url());
+        assert_eq!(connectivity(&service, &server.url()).await.status, CheckStatus::Ok);
+
+        let item = connectivity(&TTSService::new("", UNREACHABLE), UNREACHABLE).await;
+        assert_eq!(item.status, CheckStatus::Failed);
+        assert_eq!(item.detail, format!("{} can't be reached", UNREACHABLE));
+    }
+
+    #[tokio::test]
+    async fn test_a_stuck_probe_times_out() {
+        let stuck = async {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            HealthItem::ok("database", "never")
+        };
+        let item = within("database", Duration::from_millis(20), stuck).await;
+        assert_eq!(item.status, CheckStatus::Failed);
+        assert_eq!(item.detail, "No answer within 0.02 s");
+    }
+
+    #[tokio::test]
+    async fn test_report() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let audio_dir = dir.path().join("audio");
+        let service = TTSService::new("", UNREACHABLE);
+        let report = run(
+            &service,
+            UNREACHABLE,
+            "/nonexistent/ffmpeg",
+            "/nonexistent/tesseract",
+            &[("audioDir", &audio_dir)],
+            PROBE_TIMEOUT,
+        )
+        .await;
+        let names: Vec<_> = report.items.iter().map(|item| item.name.as_str()).collect();
+        assert_eq!(names, ["apiKey", "ffmpeg", "tesseract", "database", "audioDir", "connectivity"]);
+        assert_eq!(report.status, CheckStatus::Failed);
+        let statuses: Vec<_> = report.items.iter().map(|item| item.status).collect();
+        assert_eq!(
+            statuses[..4],
+            [CheckStatus::Failed, CheckStatus::Warning, CheckStatus::Warning, CheckStatus::Failed]
+        );
+    }
+}
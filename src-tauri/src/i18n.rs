@@ -0,0 +1,427 @@
+//! Messages the backend shows the user, translated. An error carries a
+//! stable key and its parameters beside the English text, so the window can
+//! translate it with its own strings or show the text from here as is.
+//! Log lines stay English.
+
+use crate::file_manager::StorageError;
+use crate::ocr::OcrError;
+use crate::transcode::{FfmpegMissing, TranscodeError};
+use crate::tts::TTSError;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::sync::RwLock;
+
+pub const DEFAULT_LOCALE: &str = "en";
+/// Locales with bundled translations; English is the messages' own text
+pub const LOCALES: &[&str] = &[DEFAULT_LOCALE, "de"];
+
+/// Locale of the messages from here on, set with `set_locale`
+static CURRENT: RwLock<&str> = RwLock::new(DEFAULT_LOCALE);
+
+/// A bundled locale for `locale`: "de-AT" and "de_DE.UTF-8" get German,
+/// anything without translations English
+pub fn resolve(locale: &str) -> &'static str {
+    let language = locale.split(['-', '_', '.']).next().unwrap_or_default().to_ascii_lowercase();
+    LOCALES.iter().copied().find(|supported| *supported == language).unwrap_or(DEFAULT_LOCALE)
+}
+
+/// Translate messages into `locale` (see `resolve`) from now on. The
+/// locale actually used.
+pub fn set_locale(locale: &str) -> &'static str {
+    let resolved = resolve(locale);
+    *CURRENT.write().unwrap() = resolved;
+    resolved
+}
+
+pub fn locale() -> &'static str {
+    *CURRENT.read().unwrap()
+}
+
+/// Something to tell the user: a stable key, what to fill into its
+/// translation, and the English text
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub key: &'static str,
+    pub params: Map<String, Value>,
+    pub text: String,
+}
+
+impl Message {
+    pub fn new(key: &'static str, text: impl Into<String>) -> Self {
+        Self { key, params: Map::new(), text: text.into() }
+    }
+
+    pub fn with(mut self, name: &str, value: impl Into<Value>) -> Self {
+        self.params.insert(name.to_string(), value.into());
+        self
+    }
+
+    /// The message in `locale`, or the English text where it has no
+    /// translation
+    pub fn translate(&self, locale: &str) -> String {
+        let Some(template) = translations(resolve(locale)).iter().find(|(key, _)| *key == self.key).map(|(_, t)| *t)
+        else {
+            return self.text.clone();
+        };
+        self.params.iter().fold(template.to_string(), |text, (name, value)| {
+            let value = match value {
+                Value::String(value) => value.clone(),
+                value => value.to_string(),
+            };
+            text.replace(&format!("{{{}}}", name), &value)
+        })
+    }
+}
+
+/// Whatever went wrong, as text
+pub const GENERIC: &str = "error.generic";
+
+/// For a voice that isn't in `VALID_VOICE_IDS`
+pub fn invalid_voice(voice_id: &str) -> Message {
+    Message::new("error.invalid_voice", format!("Invalid voice ID: {}", voice_id)).with("voice", voice_id)
+}
+
+/// For a model that isn't in `VALID_MODEL_IDS`
+pub fn invalid_model(model: &str) -> Message {
+    Message::new("error.invalid_model", format!("Invalid model: {}", model)).with("model", model)
+}
+
+/// For replaying a kept generation whose audio file has gone (see `recent`)
+pub fn replay_missing() -> Message {
+    Message::new("error.replay_missing", "The audio of this generation is gone; it can only be generated again")
+}
+
+impl TTSError {
+    pub fn message(&self) -> Message {
+        let text = self.to_string();
+        match self {
+            TTSError::NotConfigured => Message::new("error.not_configured", text),
+            TTSError::Authentication(detail) => Message::new("error.authentication", text).with("detail", detail.as_str()),
+            TTSError::RateLimit(Some(seconds)) => Message::new("error.rate_limited", text).with("seconds", *seconds),
+            TTSError::RateLimit(None) => Message::new("error.rate_limited_no_wait", text),
+            TTSError::ValidationError(detail) => Message::new("error.validation", text).with("detail", detail.as_str()),
+            TTSError::NetworkError(detail) if self.is_offline() => {
+                Message::new("error.offline", text).with("detail", detail.as_str())
+            }
+            TTSError::NetworkError(detail) => Message::new("error.network", text).with("detail", detail.as_str()),
+            TTSError::UnknownError(detail) => Message::new("error.unknown", text).with("detail", detail.as_str()),
+            TTSError::FfmpegMissing(missing) => missing.message(),
+        }
+    }
+}
+
+impl StorageError {
+    pub fn message(&self) -> Message {
+        let text = self.to_string();
+        match self {
+            StorageError::NotADirectory(path) => {
+                Message::new("error.audio_dir_not_a_directory", text).with("path", path.display().to_string())
+            }
+            StorageError::NameCollision(path) => {
+                Message::new("error.audio_name_collision", text).with("path", path.display().to_string())
+            }
+            StorageError::InvalidId(id) => Message::new("error.invalid_audio_id", text).with("id", id.as_str()),
+            StorageError::Io(path, e) => Message::new("error.audio_write", text)
+                .with("path", path.display().to_string())
+                .with("detail", e.to_string()),
+        }
+    }
+}
+
+impl FfmpegMissing {
+    pub fn message(&self) -> Message {
+        Message::new("error.ffmpeg_missing", self.to_string()).with("path", self.0.as_str())
+    }
+}
+
+impl TranscodeError {
+    pub fn message(&self) -> Message {
+        let text = self.to_string();
+        match self {
+            TranscodeError::FfmpegMissing(missing) => missing.message(),
+            TranscodeError::Unsupported(detail) => {
+                Message::new("error.transcode_unsupported", text).with("detail", detail.as_str())
+            }
+            TranscodeError::Failed(detail) => Message::new("error.transcode_failed", text).with("detail", detail.as_str()),
+        }
+    }
+}
+
+impl OcrError {
+    pub fn message(&self) -> Message {
+        let text = self.to_string();
+        match self {
+            OcrError::TesseractMissing(path) => {
+                Message::new("error.ocr_tesseract_missing", text).with("path", path.as_str())
+            }
+            OcrError::Failed(detail) => Message::new("error.ocr_failed", text).with("detail", detail.as_str()),
+            OcrError::NoText => Message::new("error.ocr_no_text", text),
+        }
+    }
+}
+
+/// What a failed command sends the window
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandError {
+    pub key: &'static str,
+    pub params: Map<String, Value>,
+    /// In the locale from `set_locale`
+    pub message: String,
+    /// In English
+    pub fallback: String,
+    /// Correlation id of the log lines about the failure (see `recent_logs`)
+    pub reference: Option<String>,
+}
+
+impl From<Message> for CommandError {
+    fn from(message: Message) -> Self {
+        Self {
+            key: message.key,
+            message: message.translate(locale()),
+            params: message.params,
+            fallback: message.text,
+            reference: None,
+        }
+    }
+}
+
+impl From<TTSError> for CommandError {
+    fn from(error: TTSError) -> Self {
+        error.message().into()
+    }
+}
+
+impl From<StorageError> for CommandError {
+    fn from(error: StorageError) -> Self {
+        error.message().into()
+    }
+}
+
+impl From<TranscodeError> for CommandError {
+    fn from(error: TranscodeError) -> Self {
+        error.message().into()
+    }
+}
+
+impl From<OcrError> for CommandError {
+    fn from(error: OcrError) -> Self {
+        error.message().into()
+    }
+}
+
+impl From<String> for CommandError {
+    fn from(error: String) -> Self {
+        Message::new(GENERIC, error.clone()).with("detail", error).into()
+    }
+}
+
+impl From<&str> for CommandError {
+    fn from(error: &str) -> Self {
+        error.to_string().into()
+    }
+}
+
+impl std::fmt::Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// For a generation refused because this month's spend reached `budget`
+pub fn budget_reached(budget: f64) -> Message {
+    Message::new("error.budget_reached", format!("This month's budget of ${:.2} has been reached", budget))
+        .with("budget", format!("{:.2}", budget))
+}
+
+/// Templates by key, with `{name}` for each parameter
+fn translations(locale: &str) -> &'static [(&'static str, &'static str)] {
+    match locale {
+        "de" => GERMAN,
+        _ => &[],
+    }
+}
+
+const GERMAN: &[(&str, &str)] = &[
+    (GENERIC, "{detail}"),
+    ("error.not_configured", "Es ist kein OpenAI-API-Schlüssel eingerichtet"),
+    ("error.authentication", "Anmeldung fehlgeschlagen: {detail}"),
+    ("error.rate_limited", "Zu viele Anfragen. Bitte in {seconds} Sekunden erneut versuchen"),
+    ("error.rate_limited_no_wait", "Zu viele Anfragen. Bitte später erneut versuchen"),
+    ("error.validation", "Ungültige Eingabe: {detail}"),
+    ("error.offline", "Der Server ist nicht erreichbar. Bitte die Internetverbindung prüfen"),
+    ("error.network", "Netzwerkfehler: {detail}"),
+    ("error.unknown", "Unbekannter Fehler: {detail}"),
+    ("error.invalid_voice", "Unbekannte Stimme: {voice}"),
+    ("error.invalid_model", "Unbekanntes Modell: {model}"),
+    (
+        "error.ffmpeg_missing",
+        "FFmpeg wird zum Zusammenfügen langer Texte und zum Umwandeln oder Kürzen von Audio benötigt, aber \"{path}\" \
+         ließ sich nicht ausführen. Bitte FFmpeg installieren oder ffmpeg_path in config.toml (bzw. \
+         TTS_PLAYER_FFMPEG) darauf setzen",
+    ),
+    ("error.budget_reached", "Das Monatsbudget von {budget} $ ist aufgebraucht"),
+    (
+        "error.audio_dir_not_a_directory",
+        "{path} ist eine Datei, daher kann dort kein Ordner für Audio angelegt werden. Bitte verschieben oder löschen",
+    ),
+    ("error.audio_name_collision", "Für neues Audio war kein freier Name zu finden, zuletzt versucht: {path}"),
+    ("error.invalid_audio_id", "Ungültige Audio-ID: {id}"),
+    ("error.audio_write", "{path} konnte nicht geschrieben werden: {detail}"),
+    ("error.transcode_unsupported", "Diese Umwandlung ist nicht möglich: {detail}"),
+    ("error.transcode_failed", "FFmpeg ist fehlgeschlagen: {detail}"),
+    (
+        "error.ocr_tesseract_missing",
+        "Zum Lesen von Text in Bildern wird Tesseract benötigt, aber \"{path}\" ließ sich nicht ausführen. Bitte \
+         Tesseract installieren oder tesseract_path in config.toml (bzw. TTS_PLAYER_TESSERACT) darauf setzen",
+    ),
+    ("error.ocr_failed", "Tesseract ist fehlgeschlagen: {detail}"),
+    ("error.ocr_no_text", "Im Bild wurde kein Text gefunden"),
+    ("error.replay_missing", "Das Audio dieser Erzeugung ist nicht mehr vorhanden; es kann nur neu erzeugt werden"),
+    ("warning.retried", "Eine Anfrage ist fehlgeschlagen ({error}) und wurde wiederholt (Versuch {attempt})"),
+    ("warning.shared", "Derselbe Text wurde bereits erzeugt, daher wurde dessen Audio wiederverwendet"),
+    ("warning.cached", "Dies wurde bereits erzeugt, daher wurde das gespeicherte Audio verwendet"),
+    ("warning.downgraded", "Das Monatsbudget ist fast erreicht, daher wurde {to} statt {from} verwendet"),
+    (
+        "warning.playlist",
+        "FFmpeg ließ sich nicht ausführen, daher besteht das Audio aus {pieces} Teilen, die nacheinander abgespielt werden",
+    ),
+    ("warning.pauses_dropped", "FFmpeg ließ sich nicht ausführen, daher wurde der Text ohne Pausen vorgelesen"),
+    ("warning.silence_trimmed", "Am Anfang wurden {start_ms} ms und am Ende {end_ms} ms Stille entfernt"),
+    (
+        "warning.reencoded",
+        "Die Datei war nicht als UTF-8 gespeichert und wurde als {encoding} gelesen; bitte prüfen, ob Umlaute und \
+         Akzente stimmen",
+    ),
+    ("warning.replaced", "{count} Zeichen der Datei ließen sich nicht lesen und fehlen"),
+    (
+        "warning.low_confidence",
+        "Der Text wurde mit {confidence} % Sicherheit aus einem Bild gelesen; bitte auf Fehler prüfen",
+    ),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tts::UNREACHABLE;
+
+    fn every_error() -> Vec<TTSError> {
+        vec![
+            TTSError::NotConfigured,
+            TTSError::Authentication("Invalid API key".to_string()),
+            TTSError::RateLimit(Some(60)),
+            TTSError::RateLimit(None),
+            TTSError::ValidationError("Text cannot be empty".to_string()),
+            TTSError::NetworkError(format!("{}: connection refused", UNREACHABLE)),
+            TTSError::NetworkError("Failed to read response".to_string()),
+            TTSError::UnknownError("HTTP 500".to_string()),
+            TTSError::FfmpegMissing(FfmpegMissing("ffmpeg".to_string())),
+        ]
+    }
+
+    #[test]
+    fn test_every_error_has_a_translated_key() {
+        for error in every_error() {
+            let message = error.message();
+            assert!(message.key.starts_with("error."), "{}", message.key);
+            assert_eq!(message.text, error.to_string());
+            assert_eq!(message.translate("en"), message.text);
+
+            let german = message.translate("de");
+            assert!(GERMAN.iter().any(|(key, _)| *key == message.key), "no German for {}", message.key);
+            assert!(!german.contains('{'), "unfilled parameter in {:?}", german);
+        }
+
+        let rate_limited = TTSError::RateLimit(Some(60)).message();
+        assert_eq!(rate_limited.key, "error.rate_limited");
+        assert_eq!(rate_limited.params["seconds"], 60);
+        assert_eq!(rate_limited.translate("de"), "Zu viele Anfragen. Bitte in 60 Sekunden erneut versuchen");
+    }
+
+    #[test]
+    fn test_storage_errors_have_translated_keys() {
+        let path = std::path::PathBuf::from("/tmp/tts-player");
+        let errors = [
+            StorageError::NotADirectory(path.clone()),
+            StorageError::NameCollision(path.join("a.mp3")),
+            StorageError::InvalidId("../a".to_string()),
+            StorageError::Io(path.clone(), std::io::Error::from(std::io::ErrorKind::PermissionDenied)),
+        ];
+        for error in errors {
+            let message = error.message();
+            assert_eq!(message.text, error.to_string());
+            let german = message.translate("de");
+            assert!(GERMAN.iter().any(|(key, _)| *key == message.key), "no German for {}", message.key);
+            assert!(!german.contains('{'), "unfilled parameter in {:?}", german);
+        }
+
+        let error = CommandError::from(StorageError::NotADirectory(path));
+        assert_eq!(error.key, "error.audio_dir_not_a_directory");
+        assert_eq!(error.params["path"], "/tmp/tts-player");
+    }
+
+    #[test]
+    fn test_transcode_errors_have_translated_keys() {
+        let errors = [
+            TranscodeError::FfmpegMissing(FfmpegMissing("ffmpeg".to_string())),
+            TranscodeError::Unsupported("Can't convert to \"aiff\"".to_string()),
+            TranscodeError::Failed("Unknown encoder 'libopus'".to_string()),
+        ];
+        for error in errors {
+            let message = error.message();
+            assert_eq!(message.text, error.to_string());
+            assert!(GERMAN.iter().any(|(key, _)| *key == message.key), "no German for {}", message.key);
+            assert!(!message.translate("de").contains('{'), "unfilled parameter in {:?}", message.translate("de"));
+        }
+        // The same error as for long text, whichever needed FFmpeg
+        let error = CommandError::from(TranscodeError::FfmpegMissing(FfmpegMissing("/opt/ffmpeg".to_string())));
+        assert_eq!(error.key, "error.ffmpeg_missing");
+        assert_eq!(error.params["path"], "/opt/ffmpeg");
+    }
+
+    #[test]
+    fn test_ocr_errors_have_translated_keys() {
+        let errors = [
+            OcrError::TesseractMissing("tesseract".to_string()),
+            OcrError::Failed("Error in pixReadStream".to_string()),
+            OcrError::NoText,
+        ];
+        for error in errors {
+            let message = error.message();
+            assert_eq!(message.text, error.to_string());
+            assert!(GERMAN.iter().any(|(key, _)| *key == message.key), "no German for {}", message.key);
+            assert!(!message.translate("de").contains('{'), "unfilled parameter in {:?}", message.translate("de"));
+        }
+        let error = CommandError::from(OcrError::TesseractMissing("/opt/tesseract".to_string()));
+        assert_eq!(error.key, "error.ocr_tesseract_missing");
+        assert_eq!(error.params["path"], "/opt/tesseract");
+    }
+
+    #[test]
+    fn test_unknown_locales_fall_back_to_english() {
+        assert_eq!(resolve("de-AT"), "de");
+        assert_eq!(resolve("de_DE.UTF-8"), "de");
+        assert_eq!(resolve("DE"), "de");
+        for locale in ["fr", "", "x-klingon", "-"] {
+            assert_eq!(resolve(locale), DEFAULT_LOCALE, "{:?}", locale);
+        }
+
+        let message = TTSError::NotConfigured.message();
+        assert_eq!(message.translate("fr-CA"), "OpenAI API key is not configured");
+        // A key with no translation keeps its English text
+        assert_eq!(Message::new("error.new_thing", "Something new").translate("de"), "Something new");
+    }
+
+    #[test]
+    fn test_command_errors_keep_key_and_fallback() {
+        let error = CommandError::from(TTSError::RateLimit(Some(5)));
+        let json = serde_json::to_value(&error).unwrap();
+        assert_eq!(json["key"], "error.rate_limited");
+        assert_eq!(json["params"]["seconds"], 5);
+        assert_eq!(json["fallback"], "Rate limit exceeded. Retry after 5 seconds");
+        assert_eq!(json["reference"], Value::Null);
+
+        let error = CommandError::from("Unknown audio: abc");
+        assert_eq!((error.key, error.fallback.as_str()), (GENERIC, "Unknown audio: abc"));
+    }
+}
@@ -0,0 +1,135 @@
+//! Identical generations running at the same time, e.g. from a double
+//! click, share one request: the first runs it and the others wait for
+//! its result instead of paying for the same audio twice.
+
+use crate::tts::TTSError;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+use tokio::sync::watch;
+
+type Outcome = Result<Vec<u8>, TTSError>;
+
+/// Requests running now by the hash of what they generate
+#[derive(Default)]
+pub struct InFlight {
+    requests: Mutex<HashMap<u64, watch::Receiver<Option<Outcome>>>>,
+}
+
+/// Takes a request off the registry however it ends, including when the
+/// future running it is dropped
+struct Registered<'a> {
+    requests: &'a Mutex<HashMap<u64, watch::Receiver<Option<Outcome>>>>,
+    key: u64,
+}
+
+impl Drop for Registered<'_> {
+    fn drop(&mut self) {
+        self.requests.lock().unwrap().remove(&self.key);
+    }
+}
+
+impl InFlight {
+    /// Run `request` unless one with the same `key` is already running, in
+    /// which case wait for that one's outcome instead. Whether it waited.
+    /// If the running request is cancelled, a waiting caller runs its own.
+    pub async fn run<F>(&self, key: u64, request: impl FnOnce() -> F) -> (Outcome, bool)
+    where
+        F: Future<Output = Outcome>,
+    {
+        let mut request = Some(request);
+        loop {
+            // Checked and claimed under one lock so two callers can't both run it
+            let claimed = {
+                let mut requests = self.requests.lock().unwrap();
+                match requests.get(&key) {
+                    Some(running) => Err(running.clone()),
+                    None => {
+                        let (sender, receiver) = watch::channel(None);
+                        requests.insert(key, receiver);
+                        Ok(sender)
+                    }
+                }
+            };
+            let mut running = match claimed {
+                Err(running) => running,
+                Ok(sender) => {
+                    // Declared after `sender` so it drops first: a waiting
+                    // caller that finds the request gone must not see it again
+                    let _registered = Registered { requests: &self.requests, key };
+                    let request = request.take().expect("a request only runs once");
+                    let outcome = request().await;
+                    sender.send_replace(Some(outcome.clone()));
+                    return (outcome, false);
+                }
+            };
+            let outcome = running.wait_for(Option::is_some).await.map(|outcome| outcome.clone());
+            match outcome {
+                Ok(outcome) => return (outcome.expect("waited for an outcome"), true),
+                Err(_) => tracing::debug!("Shared request was cancelled; running it again"),
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.requests.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_failures_are_shared_and_cleared() {
+        let in_flight = InFlight::default();
+        let calls = AtomicUsize::new(0);
+        let failing = || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            Err(TTSError::RateLimit(Some(3)))
+        };
+
+        let ((first, first_waited), (second, second_waited)) =
+            tokio::join!(in_flight.run(7, failing), in_flight.run(7, failing));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(!first_waited && second_waited);
+        assert!(matches!(first, Err(TTSError::RateLimit(Some(3)))));
+        assert!(matches!(second, Err(TTSError::RateLimit(Some(3)))));
+        assert!(in_flight.is_empty());
+
+        // Nothing is remembered once it has finished
+        let (_, waited) = in_flight.run(7, failing).await;
+        assert!(!waited);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_request_is_run_by_a_waiting_caller() {
+        let in_flight = InFlight::default();
+        let calls = AtomicUsize::new(0);
+        let slow = || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            Ok(b"audio".to_vec())
+        };
+
+        let first = in_flight.run(1, slow);
+        let second = in_flight.run(1, slow);
+        // Start the first, then give up on it once the second is waiting
+        let cancelled = tokio::time::timeout(Duration::from_millis(5), first);
+        let (cancelled, (outcome, waited)) = tokio::join!(cancelled, second);
+        assert!(cancelled.is_err());
+
+        assert_eq!(outcome.unwrap(), b"audio");
+        assert!(!waited);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert!(in_flight.is_empty());
+    }
+}
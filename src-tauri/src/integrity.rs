@@ -0,0 +1,135 @@
+//! Whether saved audio is still what was written. A generation can succeed
+//! and still leave a truncated file behind (a disk hiccup, a crash during
+//! the write), which otherwise only shows when playback cuts off. The
+//! SHA-256 and length of the audio are stored when it is written (see
+//! `Database::record_checksum`) and compared when it is played or exported.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+/// What a file held when it was written
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AudioChecksum {
+    pub size: u64,
+    /// Lowercase hex
+    pub sha256: String,
+}
+
+impl AudioChecksum {
+    pub fn of(audio: &[u8]) -> Self {
+        Self { size: audio.len() as u64, sha256: hex(&Sha256::digest(audio)) }
+    }
+
+    /// The checksum of the file at `path` as it is now, read in pieces
+    pub async fn of_file(path: &Path) -> std::io::Result<Self> {
+        let path = path.to_path_buf();
+        tokio::task::spawn_blocking(move || {
+            let mut hasher = Sha256::new();
+            let size = std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+            Ok(Self { size, sha256: hex(&hasher.finalize()) })
+        })
+        .await?
+    }
+}
+
+fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioStatus {
+    /// Matches what was written
+    Ok,
+    /// Not what was written: a different length, or with `thorough`, content
+    Corrupted,
+    /// Recorded, but the file is gone
+    Missing,
+    /// Written before checksums were kept, so there's nothing to compare
+    Unverified,
+}
+
+/// A saved file and how it checked out
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AudioFile {
+    /// The name it is streamed by (see `FileManager::find`)
+    pub id: String,
+    pub path: PathBuf,
+    /// Bytes on disk; `None` when missing
+    pub size: Option<u64>,
+    pub status: AudioStatus,
+}
+
+/// Compare the file at `path` with `expected`. Only the length is checked
+/// unless `thorough`, which reads the whole file for its hash.
+pub async fn check(path: &Path, expected: Option<&AudioChecksum>, thorough: bool) -> AudioStatus {
+    let Ok(metadata) = tokio::fs::metadata(path).await else {
+        return AudioStatus::Missing;
+    };
+    let Some(expected) = expected else {
+        return AudioStatus::Unverified;
+    };
+    if metadata.len() != expected.size {
+        tracing::warn!("{} is {} bytes; {} were written", path.display(), metadata.len(), expected.size);
+        return AudioStatus::Corrupted;
+    }
+    if !thorough {
+        return AudioStatus::Ok;
+    }
+    match AudioChecksum::of_file(path).await {
+        Ok(actual) if actual == *expected => AudioStatus::Ok,
+        Ok(_) => {
+            tracing::warn!("{} doesn't match the SHA-256 it was written with", path.display());
+            AudioStatus::Corrupted
+        }
+        Err(e) => {
+            tracing::warn!("Failed to read {}: {}", path.display(), e);
+            AudioStatus::Corrupted
+        }
+    }
+}
+
+/// The id of the file at `path`, as `AudioFile::id`
+pub fn id_of(path: &Path) -> String {
+    path.file_stem().and_then(|stem| stem.to_str()).unwrap_or_default().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checksum_of_audio() {
+        let checksum = AudioChecksum::of(b"abc");
+        assert_eq!(checksum.size, 3);
+        assert_eq!(checksum.sha256, "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    }
+
+    #[tokio::test]
+    async fn test_tampered_files_are_detected() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("clip.mp3");
+        let audio = vec![7u8; 4096];
+        std::fs::write(&path, &audio).unwrap();
+        let written = AudioChecksum::of(&audio);
+        assert_eq!(AudioChecksum::of_file(&path).await.unwrap(), written);
+        assert_eq!(check(&path, Some(&written), true).await, AudioStatus::Ok);
+        assert_eq!(check(&path, None, true).await, AudioStatus::Unverified);
+
+        // Same length, one byte changed: only the hash tells
+        let mut flipped = audio.clone();
+        flipped[100] ^= 1;
+        std::fs::write(&path, &flipped).unwrap();
+        assert_eq!(check(&path, Some(&written), false).await, AudioStatus::Ok);
+        assert_eq!(check(&path, Some(&written), true).await, AudioStatus::Corrupted);
+
+        // Cut short
+        std::fs::write(&path, &audio[..1000]).unwrap();
+        assert_eq!(check(&path, Some(&written), false).await, AudioStatus::Corrupted);
+
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(check(&path, Some(&written), true).await, AudioStatus::Missing);
+    }
+}
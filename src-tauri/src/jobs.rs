@@ -0,0 +1,1439 @@
+//! Generation requests from the window run as jobs, so rapid clicks don't
+//! race each other and every result can be matched to its request. Jobs
+//! start in the order they were enqueued, at most `concurrency` at a time,
+//! and none start while the queue is paused. With a `JobStore` they are
+//! saved chunk by chunk and can be recovered after a restart (see
+//! `recovery`).
+
+use crate::recovery::{JobDefinition, JobStore, RecoverableJob};
+use crate::resolve::Resolved;
+use crate::settings::Settings;
+use crate::silence::Gaps;
+use crate::spend::Downgrade;
+use crate::throttle::{self, ThrottleStatus};
+use crate::tts::{SpeechBackend, TTSError, VALID_VOICE_IDS};
+use crate::warnings::{self, Warning};
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{watch, OwnedSemaphorePermit, Semaphore};
+use tracing::Instrument;
+
+pub const DEFAULT_JOB_CONCURRENCY: usize = 2;
+/// Most jobs the `max_concurrent_jobs` setting lets run at once; they all
+/// share one rate limit, so more only queue up at the API
+pub const MAX_JOB_CONCURRENCY: usize = 4;
+/// How long a finished job's result is kept if nobody takes it
+pub const RESULT_TTL: Duration = Duration::from_secs(10 * 60);
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// `Database::set_state` key under which a paused queue stays paused
+/// across restarts
+pub const PAUSED_STATE_KEY: &str = "queue_paused";
+
+pub type JobId = String;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerationOptions {
+    pub text: String,
+    /// Left out by the window for the default (see `resolve`)
+    #[serde(default)]
+    pub voice_id: String,
+    /// Defaults to the model in the settings
+    pub model: Option<String>,
+    /// What the audio is, such as a chapter name, for notifications
+    #[serde(default)]
+    pub title: Option<String>,
+    /// Usage source for jobs started by something other than the window
+    /// (which can't set it); `None` for the backend's own
+    #[serde(skip)]
+    pub source: Option<&'static str>,
+    /// Keep the request for later if there's no connection; `None` follows
+    /// the `queue_when_offline` setting (see `offline`)
+    #[serde(default)]
+    pub queue_if_offline: Option<bool>,
+    /// Set when the budget moved the request to a cheaper model, which is
+    /// then `model` (see `spend::check_budget`)
+    #[serde(skip)]
+    pub downgrade: Option<Downgrade>,
+    /// Where `voice_id` and `model` came from, when the command resolved them
+    #[serde(skip)]
+    pub resolved: Option<Resolved>,
+    /// Silence after each sentence and paragraph; `None` follows the
+    /// settings (see `silence::Gaps`)
+    #[serde(default)]
+    pub sentence_gap_ms: Option<u64>,
+    #[serde(default)]
+    pub paragraph_gap_ms: Option<u64>,
+    /// The generation this is one part of, such as a document split into
+    /// sections (see `sections`)
+    #[serde(skip)]
+    pub parent_id: Option<JobId>,
+    /// Cut the dead air from the start and end of the finished audio (see
+    /// `trim`); what was cut is in the warnings
+    #[serde(default)]
+    pub trim_silence: bool,
+}
+
+impl GenerationOptions {
+    /// The window's request as it is queued: with the voice and model it
+    /// `resolved` to, or the `model` the budget moved it to, and the pauses
+    /// it leaves out taken from `settings`. What else it asked for is kept.
+    pub fn queued(self, resolved: Resolved, model: String, downgrade: Option<Downgrade>, settings: &Settings) -> Self {
+        Self {
+            voice_id: resolved.voice_id.clone(),
+            model: Some(model),
+            downgrade,
+            resolved: Some(resolved),
+            sentence_gap_ms: Some(self.sentence_gap_ms.unwrap_or(settings.sentence_gap_ms)),
+            paragraph_gap_ms: Some(self.paragraph_gap_ms.unwrap_or(settings.paragraph_gap_ms)),
+            parent_id: None,
+            ..self
+        }
+    }
+
+    /// The gaps asked for; a side left to the settings has none
+    pub fn gaps(&self) -> Gaps {
+        Gaps { sentence_ms: self.sentence_gap_ms.unwrap_or(0), paragraph_ms: self.paragraph_gap_ms.unwrap_or(0) }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "state", rename_all = "lowercase")]
+pub enum JobState {
+    Queued,
+    Running { progress: f32 },
+    /// The audio is waiting for `take_result`
+    Done { bytes: usize },
+    Failed {
+        error: String,
+        /// `TTSError::kind` of the failure
+        kind: String,
+    },
+    Cancelled,
+    /// There was no connection; the request waits as pending job `pending_id`
+    #[serde(rename_all = "camelCase")]
+    Deferred { pending_id: i64 },
+    /// Failed for a network error `attempt` times so far and tries again at
+    /// `retry_at` (see `RetryPolicy`)
+    #[serde(rename_all = "camelCase")]
+    Parked { attempt: u32, retry_at: DateTime<Utc>, error: String },
+}
+
+impl JobState {
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            JobState::Done { .. } | JobState::Failed { .. } | JobState::Cancelled | JobState::Deferred { .. }
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobInfo {
+    pub id: JobId,
+    /// Enqueue order, for sorting a queue view
+    pub sequence: u64,
+    #[serde(flatten)]
+    pub state: JobState,
+    pub voice_id: String,
+    pub model: String,
+    pub characters: usize,
+    pub title: Option<String>,
+    /// Why `model` isn't the one asked for, if it isn't
+    pub downgrade: Option<Downgrade>,
+    /// Where the voice and model came from (see `resolve`)
+    pub resolved: Option<Resolved>,
+    /// Shared by the jobs of one split generation
+    pub parent_id: Option<JobId>,
+    /// Raised while it ran (see `warnings`)
+    pub warnings: Vec<Warning>,
+    /// The pauses added to the text
+    pub gaps: Gaps,
+    /// Set while it is running but held back by the rate limit, or pausing
+    /// before a retry (see `throttle`)
+    pub waiting: Option<ThrottleStatus>,
+    /// The queue is paused, so a queued job won't start until it's resumed
+    pub queue_paused: bool,
+    pub created_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+/// A job as `JobManager::generation_status` reports it. The listener's
+/// events say what changed as it happens, but a window that reloads
+/// mid-job misses them; this is always current, for it to poll instead.
+/// Once `result` is set the audio waits for `take_result` until it is
+/// taken or expires, whether or not anyone heard the job finish.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerationStatus {
+    #[serde(flatten)]
+    pub job: JobInfo,
+    /// Requests done out of those the text is sent as
+    pub chunks_done: usize,
+    pub chunks: usize,
+    /// Set once it is done and the audio hasn't been taken
+    pub result: Option<ResultHandle>,
+}
+
+/// Finished audio waiting to be taken
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResultHandle {
+    /// To take it by
+    pub job_id: JobId,
+    pub bytes: usize,
+    /// When it is forgotten if nobody takes it
+    pub expires_at: DateTime<Utc>,
+}
+
+/// The queue as a whole, for pausing and resuming it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueStatus {
+    pub paused: bool,
+    /// Waiting to start, parked jobs included
+    pub queued: usize,
+    pub running: usize,
+    /// Most jobs that run at once
+    pub concurrency: usize,
+}
+
+/// Called after every state change; the app forwards these as events
+pub type JobListener = Arc<dyn Fn(&JobInfo) + Send + Sync>;
+
+/// Offered each failed job with its model; resolves to the pending job id
+/// if it kept the request for later (see `offline`)
+pub type OfflineQueue = Arc<dyn Fn(&GenerationOptions, &str, &TTSError) -> BoxFuture<'static, Option<i64>> + Send + Sync>;
+
+/// Handed each job that succeeds, with what it was asked and its audio,
+/// before it is done; the app keeps the last few to replay (see `recent`)
+pub type FinishedListener = Arc<dyn Fn(&JobInfo, &GenerationOptions, &[u8]) -> BoxFuture<'static, ()> + Send + Sync>;
+
+/// How jobs that fail for a network error, such as a dropped Wi-Fi
+/// connection, are tried again: parked, then run again after a delay that
+/// doubles each time, until `max_attempts` retries or `max_age` after the
+/// job was enqueued. What still fails goes to the offline queue if there
+/// is one, like any other failure.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub max_age: Duration,
+    /// Before the first retry
+    pub base_delay: Duration,
+}
+
+/// Wait before the first retry of a job that failed for a network error
+pub const RETRY_BASE_DELAY: Duration = Duration::from_secs(5);
+
+/// Longest wait between two attempts, however many there were
+pub const MAX_RETRY_DELAY: Duration = Duration::from_secs(5 * 60);
+
+impl RetryPolicy {
+    /// How long to wait before retry `attempt` (from 1) after `error`, for
+    /// a job enqueued `age` ago; `None` to give up
+    pub fn delay(&self, error: &TTSError, attempt: u32, age: Duration) -> Option<Duration> {
+        if !matches!(error, TTSError::NetworkError(_)) || attempt > self.max_attempts {
+            return None;
+        }
+        let delay = self.base_delay.saturating_mul(2u32.saturating_pow(attempt - 1)).min(MAX_RETRY_DELAY);
+        (age + delay <= self.max_age).then_some(delay)
+    }
+}
+
+/// Asked after each failure, so the policy follows the settings; `None`
+/// when failures aren't retried
+pub type RetryPolicySource = Arc<dyn Fn() -> Option<RetryPolicy> + Send + Sync>;
+
+struct Job {
+    info: JobInfo,
+    audio: Option<Vec<u8>>,
+    /// Requests it is sent as, and how many of them are done
+    chunks: usize,
+    chunks_done: usize,
+    finished_at: Option<Instant>,
+    abort: Option<tokio::task::AbortHandle>,
+}
+
+/// How many jobs may run at once, and how many permits are still to be
+/// taken out of circulation after the limit was lowered while they were in use
+struct Concurrency {
+    limit: usize,
+    excess: usize,
+}
+
+/// A job's turn to run. Dropped, even by an aborted task, it goes to the
+/// next job, unless the limit has been lowered since and it's one too many.
+struct Slot<B> {
+    permit: Option<OwnedSemaphorePermit>,
+    shared: Arc<Shared<B>>,
+}
+
+impl<B> Drop for Slot<B> {
+    fn drop(&mut self) {
+        let mut concurrency = self.shared.concurrency.lock().unwrap();
+        if concurrency.excess > 0 {
+            concurrency.excess -= 1;
+            if let Some(permit) = self.permit.take() {
+                permit.forget();
+            }
+        }
+    }
+}
+
+struct Shared<B> {
+    backend: Arc<B>,
+    permits: Arc<Semaphore>,
+    concurrency: Mutex<Concurrency>,
+    jobs: Mutex<HashMap<JobId, Job>>,
+    next_sequence: AtomicU64,
+    listener: Option<JobListener>,
+    offline_queue: Option<OfflineQueue>,
+    retry_policy: Option<RetryPolicySource>,
+    store: Option<Arc<JobStore>>,
+    on_finished: Option<FinishedListener>,
+    result_ttl: Duration,
+    paused: watch::Sender<bool>,
+}
+
+impl<B> Shared<B> {
+    fn queue_status(&self) -> QueueStatus {
+        let jobs = self.jobs.lock().unwrap();
+        let count = |wanted: fn(&JobState) -> bool| jobs.values().filter(|job| wanted(&job.info.state)).count();
+        QueueStatus {
+            paused: *self.paused.borrow(),
+            queued: count(|state| matches!(state, JobState::Queued | JobState::Parked { .. })),
+            running: count(|state| matches!(state, JobState::Running { .. })),
+            concurrency: self.concurrency.lock().unwrap().limit,
+        }
+    }
+
+    /// `job`'s info as the window sees it
+    fn info(&self, job: &Job) -> JobInfo {
+        JobInfo { queue_paused: *self.paused.borrow(), ..job.info.clone() }
+    }
+
+    /// Apply `change` to the job if it still exists and `allowed` accepts
+    /// its current state, then tell the listener. Returns whether it applied.
+    fn transition(&self, id: &str, allowed: impl Fn(&JobState) -> bool, change: impl FnOnce(&mut Job)) -> bool {
+        let info = {
+            let mut jobs = self.jobs.lock().unwrap();
+            match jobs.get_mut(id) {
+                Some(job) if allowed(&job.info.state) => {
+                    change(job);
+                    if job.info.state.is_finished() {
+                        job.info.finished_at = Some(Utc::now());
+                        job.finished_at = Some(Instant::now());
+                        job.abort = None;
+                    }
+                    self.info(job)
+                }
+                _ => return false,
+            }
+        };
+        if let Some(listener) = &self.listener {
+            listener(&info);
+        }
+        true
+    }
+}
+
+impl<B: SpeechBackend + Send + Sync> Shared<B> {
+    /// Job `id`'s audio, in one request; or with a store, one request per
+    /// chunk, each kept as it arrives and those in `done` skipped
+    async fn synthesize(
+        &self,
+        id: &str,
+        options: &GenerationOptions,
+        model: &str,
+        chunks: &[String],
+        done: &mut BTreeMap<usize, Vec<u8>>,
+    ) -> Result<Vec<u8>, TTSError> {
+        let requested_model = options.downgrade.as_ref().map(|downgrade| downgrade.from.as_str());
+        let (source, voice_id, gaps) = (options.source, options.voice_id.as_str(), options.gaps());
+        let store = match &self.store {
+            Some(store) if chunks.len() > 1 => store,
+            _ => return self.backend.synthesize_paced(source, &options.text, voice_id, model, requested_model, gaps).await,
+        };
+        for (i, chunk) in chunks.iter().enumerate() {
+            if done.contains_key(&i) {
+                continue;
+            }
+            let audio = self.backend.synthesize_paced(source, chunk, voice_id, model, requested_model, gaps).await?;
+            if let Err(e) = store.save_chunk(id, i, &audio).await {
+                tracing::warn!("Failed to save chunk {} of {}: {}", i + 1, chunks.len(), e);
+            }
+            done.insert(i, audio);
+            let progress = done.len() as f32 / chunks.len() as f32;
+            self.transition(
+                id,
+                |state| matches!(state, JobState::Running { .. }),
+                |job| {
+                    job.info.state = JobState::Running { progress };
+                    job.chunks_done = done.len();
+                },
+            );
+        }
+        self.backend.join(chunks, done.values().cloned().collect())
+    }
+}
+
+/// Runs generation jobs in the background. The `with_*` methods set it up,
+/// and must be called before the first job is enqueued or it is cloned.
+pub struct JobManager<B> {
+    shared: Arc<Shared<B>>,
+}
+
+/// Another handle on the same jobs
+impl<B> Clone for JobManager<B> {
+    fn clone(&self) -> Self {
+        Self { shared: self.shared.clone() }
+    }
+}
+
+impl<B: SpeechBackend + Send + Sync + 'static> JobManager<B> {
+    pub fn new(backend: Arc<B>, concurrency: usize) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                backend,
+                permits: Arc::new(Semaphore::new(concurrency.max(1))),
+                concurrency: Mutex::new(Concurrency { limit: concurrency.max(1), excess: 0 }),
+                jobs: Mutex::new(HashMap::new()),
+                next_sequence: AtomicU64::new(1),
+                listener: None,
+                offline_queue: None,
+                retry_policy: None,
+                store: None,
+                on_finished: None,
+                result_ttl: RESULT_TTL,
+                paused: watch::Sender::new(false),
+            }),
+        }
+    }
+
+    /// Told of every job state change
+    pub fn with_listener(mut self, listener: JobListener) -> Self {
+        Arc::get_mut(&mut self.shared).expect("listener set after jobs started").listener = Some(listener);
+        self
+    }
+
+    /// Offered failed jobs to keep for later
+    pub fn with_offline_queue(mut self, offline_queue: OfflineQueue) -> Self {
+        Arc::get_mut(&mut self.shared).expect("offline queue set after jobs started").offline_queue = Some(offline_queue);
+        self
+    }
+
+    /// How jobs that fail for a network error are tried again
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicySource) -> Self {
+        Arc::get_mut(&mut self.shared).expect("retry policy set after jobs started").retry_policy = Some(retry_policy);
+        self
+    }
+
+    /// Where jobs and their finished chunks are saved, to resume after a restart
+    pub fn with_store(mut self, store: Arc<JobStore>) -> Self {
+        Arc::get_mut(&mut self.shared).expect("job store set after jobs started").store = Some(store);
+        self
+    }
+
+    /// Handed each job that succeeds, with its audio
+    pub fn with_finished_listener(mut self, on_finished: FinishedListener) -> Self {
+        Arc::get_mut(&mut self.shared).expect("finished listener set after jobs started").on_finished = Some(on_finished);
+        self
+    }
+
+    /// Start paused, as the queue was left when the app last quit
+    pub fn with_paused(self, paused: bool) -> Self {
+        self.shared.paused.send_replace(paused);
+        self
+    }
+
+    /// Start no more jobs until `resume`. Running jobs finish, and queued
+    /// ones keep their place and can still be cancelled. Returns whether
+    /// the queue was running.
+    pub fn pause(&self) -> bool {
+        let was_paused = self.shared.paused.send_replace(true);
+        if !was_paused {
+            tracing::info!("Job queue paused");
+        }
+        !was_paused
+    }
+
+    /// Start queued jobs again, in the order they were enqueued. Returns
+    /// whether the queue was paused.
+    pub fn resume(&self) -> bool {
+        let was_paused = self.shared.paused.send_replace(false);
+        if was_paused {
+            tracing::info!("Job queue resumed");
+        }
+        was_paused
+    }
+
+    pub fn queue_status(&self) -> QueueStatus {
+        self.shared.queue_status()
+    }
+
+    /// Let up to `concurrency` jobs run at once from now on. Raising it
+    /// starts queued jobs straight away; lowering it lets running jobs
+    /// finish and starts no more until fewer than the new limit are left.
+    pub fn set_concurrency(&self, concurrency: usize) {
+        let concurrency = concurrency.max(1);
+        let mut current = self.shared.concurrency.lock().unwrap();
+        if concurrency > current.limit {
+            // Permits still owed from lowering it come back first
+            let more = concurrency - current.limit;
+            let owed = more.min(current.excess);
+            current.excess -= owed;
+            self.shared.permits.add_permits(more - owed);
+        } else if concurrency < current.limit {
+            let fewer = current.limit - concurrency;
+            current.excess += fewer - self.shared.permits.forget_permits(fewer);
+        } else {
+            return;
+        }
+        tracing::info!("Up to {} job(s) now run at once (was {})", concurrency, current.limit);
+        current.limit = concurrency;
+    }
+
+    /// Queue a generation and return its id straight away. Invalid input is
+    /// rejected here rather than becoming a failed job.
+    pub fn enqueue(&self, options: GenerationOptions, default_model: &str) -> Result<JobId, String> {
+        if options.text.trim().is_empty() {
+            return Err("Text cannot be empty".to_string());
+        }
+        if !VALID_VOICE_IDS.contains(&options.voice_id.as_str()) {
+            return Err(format!("Invalid voice ID: {}", options.voice_id));
+        }
+        options.gaps().validate()?;
+        self.prune(Instant::now());
+
+        let model = options.model.clone().unwrap_or_else(|| default_model.to_string());
+        let chunks = match &self.shared.store {
+            Some(_) => self.shared.backend.request_texts(&options.text),
+            None => Vec::new(),
+        };
+        Ok(self.start(uuid::Uuid::new_v4().to_string(), options, model, chunks, BTreeMap::new()))
+    }
+
+    /// Run job `id`, saved first if there is a store. `chunks` are the
+    /// texts it's sent as with a store, of which those in `done` already
+    /// have their audio.
+    fn start(
+        &self,
+        id: JobId,
+        options: GenerationOptions,
+        model: String,
+        chunks: Vec<String>,
+        mut done: BTreeMap<usize, Vec<u8>>,
+    ) -> JobId {
+        let info = JobInfo {
+            id: id.clone(),
+            sequence: self.shared.next_sequence.fetch_add(1, Ordering::SeqCst),
+            state: JobState::Queued,
+            voice_id: options.voice_id.clone(),
+            model,
+            characters: options.text.chars().count(),
+            title: options.title.clone(),
+            downgrade: options.downgrade.clone(),
+            resolved: options.resolved.clone(),
+            parent_id: options.parent_id.clone(),
+            warnings: Vec::new(),
+            gaps: options.gaps(),
+            waiting: None,
+            queue_paused: *self.shared.paused.borrow(),
+            created_at: Utc::now(),
+            started_at: None,
+            finished_at: None,
+        };
+        let model = info.model.clone();
+        self.shared.jobs.lock().unwrap().insert(
+            id.clone(),
+            Job {
+                info: info.clone(),
+                audio: None,
+                chunks: chunks.len().max(1),
+                chunks_done: done.len(),
+                finished_at: None,
+                abort: None,
+            },
+        );
+        if let Some(listener) = &self.shared.listener {
+            listener(&info);
+        }
+
+        let shared = self.shared.clone();
+        let job_id = id.clone();
+        // Everything logged while the job runs carries its id
+        let span = tracing::info_span!("job", correlation_id = %id);
+        let run = async move {
+            if let Some(store) = &shared.store {
+                if let Err(e) = store.save(&job_id, &JobDefinition::new(&options, &model, chunks.clone())).await {
+                    tracing::warn!("Failed to save the job; it can't be recovered after a restart: {}", e);
+                }
+            }
+            let enqueued = Instant::now();
+            let waits = shared.clone();
+            let waiting_id = job_id.clone();
+            let on_wait: throttle::WaitListener = Arc::new(move |status| {
+                waits.transition(
+                    &waiting_id,
+                    |state| matches!(state, JobState::Running { .. }),
+                    |job| job.info.waiting = status.cloned(),
+                );
+            });
+            let mut warnings: Vec<Warning> = Vec::new();
+            let mut attempt = 0;
+            let result = loop {
+                // Semaphore permits are handed out first come, first served.
+                // A parked job gives its up while it waits.
+                let Ok(permit) = shared.permits.clone().acquire_owned().await else {
+                    return;
+                };
+                let slot = Slot { permit: Some(permit), shared: shared.clone() };
+                // Holding the permit keeps this job's place while paused
+                let _ = shared.paused.subscribe().wait_for(|paused| !paused).await;
+                // Recovered or parked, it may have chunks done already
+                let progress = if chunks.is_empty() { 0.0 } else { done.len() as f32 / chunks.len() as f32 };
+                let started = shared.transition(
+                    &job_id,
+                    |state| matches!(state, JobState::Queued | JobState::Parked { .. }),
+                    |job| {
+                        job.info.state = JobState::Running { progress };
+                        job.info.started_at.get_or_insert_with(Utc::now);
+                    },
+                );
+                if !started {
+                    return; // cancelled while queued or parked
+                }
+                let status = shared.queue_status();
+                tracing::info!("Job started; {} of at most {} running, {} queued", status.running, status.concurrency, status.queued);
+
+                let (result, raised) = warnings::collect(throttle::report_waits(on_wait.clone(), async {
+                    if let Some(downgrade) = options.downgrade.as_ref().filter(|_| attempt == 0) {
+                        warnings::warn(warnings::downgraded(downgrade));
+                    }
+                    match shared.synthesize(&job_id, &options, &model, &chunks, &mut done).await {
+                        Ok(audio) if options.trim_silence => Ok(shared.backend.trim_silence(audio).await),
+                        result => result,
+                    }
+                }))
+                .await;
+                warnings.extend(raised);
+                let Err(e) = &result else {
+                    break result;
+                };
+                let policy = shared.retry_policy.as_ref().and_then(|policy| policy());
+                let delay = policy.and_then(|policy| policy.delay(e, attempt + 1, enqueued.elapsed()));
+                let Some(delay) = delay else {
+                    break result;
+                };
+                attempt += 1;
+                tracing::info!("Attempt {} failed: {}; trying again in {} ms", attempt, e, delay.as_millis());
+                let error = e.to_string();
+                let parked = shared.transition(
+                    &job_id,
+                    |state| matches!(state, JobState::Running { .. }),
+                    |job| {
+                        let retry_at = Utc::now() + chrono::Duration::from_std(delay).unwrap_or_default();
+                        job.info.state = JobState::Parked { attempt, retry_at, error };
+                        job.info.waiting = None;
+                    },
+                );
+                if !parked {
+                    return;
+                }
+                drop(slot);
+                tokio::time::sleep(delay).await;
+            };
+            // It has an answer now, so there's nothing left to recover
+            if let Some(store) = &shared.store {
+                if let Err(e) = store.forget(&job_id).await {
+                    tracing::warn!("Failed to delete the saved job: {}", e);
+                }
+            }
+            if let (Ok(audio), Some(on_finished)) = (&result, &shared.on_finished) {
+                let info = shared.jobs.lock().unwrap().get(&job_id).map(|job| job.info.clone());
+                if let Some(info) = info {
+                    on_finished(&info, &options, audio).await;
+                }
+            }
+            if let Err(e) = &result {
+                tracing::warn!("Job failed: {}", e);
+                let deferred = match &shared.offline_queue {
+                    Some(offline_queue) => offline_queue(&options, &model, e).await,
+                    None => None,
+                };
+                if let Some(pending_id) = deferred {
+                    shared.transition(
+                        &job_id,
+                        |state| matches!(state, JobState::Running { .. }),
+                        |job| job.info.state = JobState::Deferred { pending_id },
+                    );
+                    return;
+                }
+            }
+            shared.transition(
+                &job_id,
+                |state| matches!(state, JobState::Running { .. }),
+                |job| {
+                    job.info.warnings = warnings;
+                    job.info.waiting = None;
+                    match result {
+                        Ok(audio) => {
+                            job.info.state = JobState::Done { bytes: audio.len() };
+                            job.audio = Some(audio);
+                            job.chunks_done = job.chunks;
+                        }
+                        Err(e) => job.info.state = JobState::Failed { error: e.to_string(), kind: e.kind().to_string() },
+                    }
+                },
+            );
+        };
+        let task = tokio::spawn(run.instrument(span));
+
+        if let Some(job) = self.shared.jobs.lock().unwrap().get_mut(&id) {
+            if !job.info.state.is_finished() {
+                job.abort = Some(task.abort_handle());
+            }
+        }
+        id
+    }
+
+    /// Saved jobs this manager isn't running, interrupted by the app
+    /// quitting or crashing, with how far they got
+    pub async fn recoverable(&self) -> Result<Vec<RecoverableJob>, String> {
+        let Some(store) = &self.shared.store else {
+            return Ok(Vec::new());
+        };
+        let saved = store.list().await.map_err(|e| format!("Failed to read saved jobs: {}", e))?;
+        let jobs = self.shared.jobs.lock().unwrap();
+        Ok(saved.into_iter().filter(|job| !jobs.contains_key(&job.id)).map(RecoverableJob::from).collect())
+    }
+
+    /// Queue interrupted job `id` again under the same id. Chunks done
+    /// before the restart are kept; it carries on from the first that isn't.
+    pub async fn recover(&self, id: &str) -> Result<JobId, String> {
+        let store = self.shared.store.as_ref().ok_or("Jobs aren't saved without a database")?;
+        let (definition, done) = store
+            .load(id)
+            .await
+            .map_err(|e| format!("Failed to read saved job {}: {}", id, e))?
+            .ok_or_else(|| format!("Unknown job: {}", id))?;
+        if self.shared.jobs.lock().unwrap().contains_key(id) {
+            return Err(format!("Job {} was not interrupted", id));
+        }
+        tracing::info!("Recovering job {} with {} of {} chunks done", id, done.len(), definition.chunks.len());
+        let options = definition.options();
+        Ok(self.start(id.to_string(), options, definition.model, definition.chunks, done))
+    }
+
+    /// Delete interrupted job `id` and its chunks instead of recovering it
+    pub async fn discard(&self, id: &str) -> Result<(), String> {
+        let store = self.shared.store.as_ref().ok_or("Jobs aren't saved without a database")?;
+        if self.shared.jobs.lock().unwrap().contains_key(id) {
+            return Err(format!("Job {} was not interrupted", id));
+        }
+        store.forget(id).await.map_err(|e| format!("Failed to delete saved job {}: {}", id, e))
+    }
+
+    pub fn status(&self, id: &str) -> Option<JobInfo> {
+        self.prune(Instant::now());
+        self.shared.jobs.lock().unwrap().get(id).map(|job| self.shared.info(job))
+    }
+
+    /// Every job still known, oldest first
+    pub fn list(&self) -> Vec<JobInfo> {
+        self.prune(Instant::now());
+        let mut jobs: Vec<JobInfo> = self.shared.jobs.lock().unwrap().values().map(|job| self.shared.info(job)).collect();
+        jobs.sort_by_key(|job| job.sequence);
+        jobs
+    }
+
+    /// Job `id` with how far it got and, once done, a handle to its
+    /// result (see `GenerationStatus`)
+    pub fn generation_status(&self, id: &str) -> Option<GenerationStatus> {
+        self.prune(Instant::now());
+        let jobs = self.shared.jobs.lock().unwrap();
+        let job = jobs.get(id)?;
+        let ttl = chrono::Duration::from_std(self.shared.result_ttl).unwrap_or(chrono::Duration::MAX);
+        let result = match (&job.audio, job.info.finished_at) {
+            (Some(audio), Some(finished_at)) => Some(ResultHandle {
+                job_id: id.to_string(),
+                bytes: audio.len(),
+                expires_at: finished_at.checked_add_signed(ttl).unwrap_or(DateTime::<Utc>::MAX_UTC),
+            }),
+            _ => None,
+        };
+        Some(GenerationStatus { job: self.shared.info(job), chunks_done: job.chunks_done, chunks: job.chunks, result })
+    }
+
+    /// Hand over a finished job's audio. This works once; the job is
+    /// forgotten afterwards.
+    pub fn take_result(&self, id: &str) -> Result<Vec<u8>, String> {
+        let mut jobs = self.shared.jobs.lock().unwrap();
+        finished(jobs.get(id), id)?;
+        let job = jobs.remove(id).expect("job looked up above");
+        Ok(job.audio.unwrap_or_default())
+    }
+
+    /// A copy of a finished job's audio, which stays until `forget` so it
+    /// can be asked for again if handing it over fails
+    pub fn result(&self, id: &str) -> Result<Vec<u8>, String> {
+        let jobs = self.shared.jobs.lock().unwrap();
+        Ok(finished(jobs.get(id), id)?.audio.clone().unwrap_or_default())
+    }
+
+    /// Forget a finished job and its audio; unfinished jobs are left alone
+    pub fn forget(&self, id: &str) -> bool {
+        let mut jobs = self.shared.jobs.lock().unwrap();
+        let finished = jobs.get(id).is_some_and(|job| job.info.state.is_finished());
+        finished && jobs.remove(id).is_some()
+    }
+
+    /// Wait for job `id` to finish, then take its result as `take_result` does
+    pub async fn wait(&self, id: &str) -> Result<Vec<u8>, String> {
+        loop {
+            match self.status(id) {
+                Some(job) if !job.state.is_finished() => tokio::time::sleep(WAIT_POLL_INTERVAL).await,
+                _ => return self.take_result(id),
+            }
+        }
+    }
+
+    /// Cancel a queued or running job; finished jobs are left alone
+    pub fn cancel(&self, id: &str) -> bool {
+        let abort = self.shared.jobs.lock().unwrap().get_mut(id).and_then(|job| job.abort.take());
+        let cancelled = self.shared.transition(
+            id,
+            |state| !state.is_finished(),
+            |job| job.info.state = JobState::Cancelled,
+        );
+        if cancelled {
+            if let Some(abort) = abort {
+                abort.abort();
+            }
+            if let Some(store) = &self.shared.store {
+                store.forget_later(id);
+            }
+        }
+        cancelled
+    }
+
+    /// Forget jobs that finished more than the TTL before `now`
+    fn prune(&self, now: Instant) {
+        let ttl = self.shared.result_ttl;
+        self.shared.jobs.lock().unwrap().retain(|_, job| match job.finished_at {
+            Some(finished_at) => now.duration_since(finished_at) < ttl,
+            None => true,
+        });
+    }
+}
+
+/// `job`, job `id`, if it is done; otherwise why its result can't be had
+fn finished<'a>(job: Option<&'a Job>, id: &str) -> Result<&'a Job, String> {
+    let job = job.ok_or_else(|| format!("Unknown job: {}", id))?;
+    match &job.info.state {
+        JobState::Done { .. } => Ok(job),
+        JobState::Failed { error, .. } => Err(error.clone()),
+        JobState::Cancelled => Err("Job was cancelled".to_string()),
+        JobState::Deferred { pending_id } => {
+            Err(format!("No connection; saved as pending job {} to generate later", pending_id))
+        }
+        JobState::Queued | JobState::Running { .. } | JobState::Parked { .. } => Err("Job has not finished".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tts::TTSError;
+
+    /// The shared fake (see `test_support::FakeBackend`) recording when each
+    /// text was being "sent", after any rate limit; "RETRY" warns of a
+    /// retry, "THROTTLE" pauses as before one, "LIMITED" gets a 429, "FLAKY"
+    /// loses the connection the first two times and "OFFLINE" every time.
+    /// Texts are sent in chunks split at "|", where "STALL" never answers if
+    /// `stalls`.
+    #[derive(Default)]
+    struct FakeBackend {
+        base: crate::test_support::FakeBackend,
+        spans: Mutex<Vec<(String, Instant, Instant)>>,
+        throttle: crate::throttle::Throttle,
+        stalls: bool,
+    }
+
+    impl FakeBackend {
+        fn calls(&self) -> Vec<String> {
+            self.base.calls.lock().unwrap().clone()
+        }
+
+        fn span(&self, text: &str) -> (Instant, Instant) {
+            let spans = self.spans.lock().unwrap();
+            let (_, start, end) = spans.iter().find(|(call, ..)| call == text).unwrap();
+            (*start, *end)
+        }
+    }
+
+    impl SpeechBackend for FakeBackend {
+        async fn synthesize(&self, text: &str, voice_id: &str, model: &str) -> Result<Vec<u8>, TTSError> {
+            if self.stalls && text.contains("STALL") {
+                self.base.calls.lock().unwrap().push(text.to_string());
+                std::future::pending::<()>().await;
+            }
+            let _ = self.throttle.ready().await;
+            let start = Instant::now();
+            if text.contains("LIMITED") {
+                self.throttle.rate_limited(Some(Duration::from_millis(100)));
+            }
+            let answer = self.base.synthesize(text, voice_id, model).await;
+            self.spans.lock().unwrap().push((text.to_string(), start, Instant::now()));
+            if text.contains("RETRY") {
+                warnings::warn(warnings::retried(2, "HTTP 500"));
+            }
+            if text.contains("THROTTLE") {
+                self.throttle.pause(Duration::from_millis(20)).await;
+            }
+            if text.contains("LIMITED") {
+                Err(TTSError::RateLimit(None))
+            } else if text.contains("FLAKY") && self.calls().iter().filter(|call| *call == text).count() <= 2 {
+                Err(TTSError::NetworkError("Failed to read response: connection reset".to_string()))
+            } else if text.contains("OFFLINE") {
+                Err(TTSError::NetworkError(format!("{}: connection refused", crate::tts::UNREACHABLE)))
+            } else {
+                answer
+            }
+        }
+
+        fn request_texts(&self, text: &str) -> Vec<String> {
+            text.split('|').map(str::to_string).collect()
+        }
+
+        async fn trim_silence(&self, audio: Vec<u8>) -> Vec<u8> {
+            let trimmed = String::from_utf8(audio).unwrap().trim().to_string();
+            warnings::warn(warnings::silence_trimmed(1, 1));
+            trimmed.into_bytes()
+        }
+    }
+
+    fn options(text: &str) -> GenerationOptions {
+        GenerationOptions {
+            text: text.to_string(),
+            voice_id: "nova".to_string(),
+            model: None,
+            title: None,
+            source: None,
+            queue_if_offline: None,
+            downgrade: None,
+            resolved: None,
+            sentence_gap_ms: None,
+            paragraph_gap_ms: None,
+            parent_id: None,
+            trim_silence: false,
+        }
+    }
+
+    type Events = Arc<Mutex<Vec<(JobId, JobState)>>>;
+
+    fn manager(concurrency: usize) -> (JobManager<FakeBackend>, Arc<FakeBackend>, Events) {
+        let backend = Arc::new(FakeBackend::default());
+        let events: Events = Arc::default();
+        let recorded = events.clone();
+        let manager = JobManager::new(backend.clone(), concurrency)
+            .with_listener(Arc::new(move |info: &JobInfo| {
+                recorded.lock().unwrap().push((info.id.clone(), info.state.clone()));
+            }));
+        (manager, backend, events)
+    }
+
+    async fn wait_until_finished(manager: &JobManager<FakeBackend>, ids: &[JobId]) {
+        for _ in 0..200 {
+            if ids.iter().all(|id| manager.status(id).is_some_and(|job| job.state.is_finished())) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        panic!("jobs did not finish");
+    }
+
+    #[tokio::test]
+    async fn test_three_jobs_run_in_order() {
+        let (manager, backend, events) = manager(1);
+        let ids: Vec<JobId> = ["first", "second FAIL", "third"]
+            .iter()
+            .map(|text| manager.enqueue(options(text), "tts-1").unwrap())
+            .collect();
+
+        let listed = manager.list();
+        assert_eq!(listed.iter().map(|job| &job.id).collect::<Vec<_>>(), ids.iter().collect::<Vec<_>>());
+        assert_eq!(listed[0].model, "tts-1");
+        assert_eq!(listed[0].gaps, Gaps::default());
+
+        wait_until_finished(&manager, &ids).await;
+        assert_eq!(backend.calls(), vec!["first", "second FAIL", "third"]);
+
+        let states_of = |id: &JobId| -> Vec<JobState> {
+            events.lock().unwrap().iter().filter(|(job, _)| job == id).map(|(_, state)| state.clone()).collect()
+        };
+        assert_eq!(
+            states_of(&ids[0]),
+            vec![JobState::Queued, JobState::Running { progress: 0.0 }, JobState::Done { bytes: 5 }]
+        );
+        assert!(matches!(states_of(&ids[1]).last(), Some(JobState::Failed { kind, .. }) if kind == "unknown"));
+        let first = manager.status(&ids[0]).unwrap();
+        assert!(first.created_at <= first.started_at.unwrap() && first.started_at <= first.finished_at);
+
+        // Results can be taken once
+        assert_eq!(manager.take_result(&ids[2]).unwrap(), b"third");
+        assert!(manager.take_result(&ids[2]).unwrap_err().contains("Unknown job"));
+        assert!(manager.status(&ids[2]).is_none());
+        assert_eq!(manager.take_result(&ids[1]).unwrap_err(), "Unknown error: HTTP 500");
+    }
+
+    #[tokio::test]
+    async fn test_warnings_are_kept_with_the_job() {
+        let (manager, _, _) = manager(2);
+        let downgrade = Downgrade { from: "tts-1-hd".to_string(), to: "tts-1".to_string(), reason: "Near the budget".to_string() };
+        let downgraded = GenerationOptions { downgrade: Some(downgrade.clone()), ..options("RETRY then done") };
+        let ids = [manager.enqueue(downgraded, "tts-1").unwrap(), manager.enqueue(options("plain"), "tts-1").unwrap()];
+        wait_until_finished(&manager, &ids).await;
+
+        let job = manager.status(&ids[0]).unwrap();
+        assert_eq!(job.downgrade, Some(downgrade));
+        let codes: Vec<&str> = job.warnings.iter().map(|warning| warning.code).collect();
+        assert_eq!(codes, ["warning.downgraded", "warning.retried"]);
+        assert!(manager.status(&ids[1]).unwrap().warnings.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_finished_listener_hears_successes_only() {
+        let finished: Arc<Mutex<Vec<(String, String, Vec<u8>)>>> = Arc::default();
+        let recorded = finished.clone();
+        let manager = JobManager::new(Arc::new(FakeBackend::default()), 2).with_finished_listener(Arc::new(
+            move |info: &JobInfo, options: &GenerationOptions, audio: &[u8]| {
+                recorded.lock().unwrap().push((info.id.clone(), options.text.clone(), audio.to_vec()));
+                Box::pin(async {})
+            },
+        ));
+        let ids = [manager.enqueue(options("kept"), "tts-1").unwrap(), manager.enqueue(options("FAIL"), "tts-1").unwrap()];
+        wait_until_finished(&manager, &ids).await;
+
+        assert_eq!(*finished.lock().unwrap(), [(ids[0].clone(), "kept".to_string(), b"kept".to_vec())]);
+    }
+
+    #[tokio::test]
+    async fn test_silence_is_trimmed_only_when_asked() {
+        let (manager, _, _) = manager(2);
+        let trimmed = GenerationOptions { trim_silence: true, ..options("  padded  ") };
+        let ids = [manager.enqueue(trimmed, "tts-1").unwrap(), manager.enqueue(options("  padded  "), "tts-1").unwrap()];
+        wait_until_finished(&manager, &ids).await;
+
+        let codes: Vec<&str> = manager.status(&ids[0]).unwrap().warnings.iter().map(|warning| warning.code).collect();
+        assert_eq!(codes, ["warning.silence_trimmed"]);
+        assert!(manager.status(&ids[1]).unwrap().warnings.is_empty());
+        assert_eq!(manager.take_result(&ids[0]).unwrap(), b"padded");
+        assert_eq!(manager.take_result(&ids[1]).unwrap(), b"  padded  ");
+    }
+
+    #[tokio::test]
+    async fn test_window_asking_to_trim_gets_trimmed_audio() {
+        let (manager, _, _) = manager(1);
+        let settings = Settings::from_config(&crate::config::Config::default());
+        let asked: GenerationOptions =
+            serde_json::from_value(serde_json::json!({ "text": "  padded  ", "trimSilence": true })).unwrap();
+        let resolved = crate::resolve::defaults(&settings);
+        let model = resolved.model.clone();
+        let id = manager.enqueue(asked.queued(resolved, model, None, &settings), "tts-1").unwrap();
+        wait_until_finished(&manager, std::slice::from_ref(&id)).await;
+
+        let codes: Vec<&str> = manager.status(&id).unwrap().warnings.iter().map(|warning| warning.code).collect();
+        assert_eq!(codes, ["warning.silence_trimmed"]);
+        assert_eq!(manager.take_result(&id).unwrap(), b"padded");
+    }
+
+    #[tokio::test]
+    async fn test_waits_on_the_throttle_are_reported() {
+        let waiting: Arc<Mutex<Vec<Option<ThrottleStatus>>>> = Arc::default();
+        let recorded = waiting.clone();
+        let manager = JobManager::new(Arc::new(FakeBackend::default()), 1)
+            .with_listener(Arc::new(move |info: &JobInfo| recorded.lock().unwrap().push(info.waiting.clone())));
+        let id = manager.enqueue(options("THROTTLE me"), "tts-1").unwrap();
+        wait_until_finished(&manager, std::slice::from_ref(&id)).await;
+
+        let waiting = waiting.lock().unwrap();
+        let reasons: Vec<_> = waiting.iter().map(|status| status.as_ref().map(|status| status.reason)).collect();
+        // queued, running, waiting, no longer waiting, done
+        assert_eq!(reasons, [None, None, Some(Some(crate::throttle::ThrottleReason::Retrying)), None, None]);
+        assert!(waiting[2].as_ref().unwrap().resume_at.is_some());
+        assert!(manager.status(&id).unwrap().waiting.is_none());
+    }
+
+    fn retrying(base_delay: Duration) -> (JobManager<FakeBackend>, Arc<FakeBackend>, Events) {
+        let (manager, backend, events) = manager(1);
+        let policy = RetryPolicy { max_attempts: 3, max_age: Duration::from_secs(60), base_delay };
+        (manager.with_retry_policy(Arc::new(move || Some(policy))), backend, events)
+    }
+
+    #[test]
+    fn test_retry_delays() {
+        let policy = RetryPolicy { max_attempts: 3, max_age: Duration::from_secs(60), base_delay: Duration::from_secs(5) };
+        let dropped = TTSError::NetworkError("Failed to read response: connection reset".to_string());
+        let delays: Vec<_> = (1..=4).map(|attempt| policy.delay(&dropped, attempt, Duration::ZERO)).collect();
+        assert_eq!(delays, [Some(Duration::from_secs(5)), Some(Duration::from_secs(10)), Some(Duration::from_secs(20)), None]);
+        // Not past the age limit
+        assert_eq!(policy.delay(&dropped, 2, Duration::from_secs(50)), Some(Duration::from_secs(10)));
+        assert_eq!(policy.delay(&dropped, 2, Duration::from_secs(51)), None);
+        let many = RetryPolicy { max_attempts: 20, max_age: Duration::from_secs(86400), ..policy };
+        assert_eq!(many.delay(&dropped, 20, Duration::ZERO), Some(MAX_RETRY_DELAY));
+        // Only network errors are worth another try
+        assert_eq!(policy.delay(&TTSError::RateLimit(Some(5)), 1, Duration::ZERO), None);
+        assert_eq!(policy.delay(&TTSError::UnknownError("HTTP 500".to_string()), 1, Duration::ZERO), None);
+    }
+
+    #[tokio::test]
+    async fn test_network_failures_are_parked_and_retried() {
+        // Without a policy the first failure is final
+        let (once, _, _) = manager(1);
+        let id = once.enqueue(options("FLAKY wifi"), "tts-1").unwrap();
+        wait_until_finished(&once, std::slice::from_ref(&id)).await;
+        assert!(matches!(once.status(&id).unwrap().state, JobState::Failed { kind, .. } if kind == "network"));
+
+        let (manager, backend, events) = retrying(Duration::from_millis(10));
+        let id = manager.enqueue(options("FLAKY wifi"), "tts-1").unwrap();
+        wait_until_finished(&manager, std::slice::from_ref(&id)).await;
+
+        assert_eq!(backend.calls().len(), 3);
+        let states: Vec<JobState> = events.lock().unwrap().iter().map(|(_, state)| state.clone()).collect();
+        assert_eq!(states.len(), 7, "{:?}", states);
+        for (i, attempt) in [(2, 1), (4, 2)] {
+            let JobState::Parked { attempt: parked, error, .. } = &states[i] else {
+                panic!("expected parked, got {:?}", states[i]);
+            };
+            assert_eq!(*parked, attempt);
+            assert!(error.contains("connection reset"), "{}", error);
+            assert_eq!(states[i + 1], JobState::Running { progress: 0.0 });
+        }
+        assert_eq!(states[6], JobState::Done { bytes: 10 });
+        assert_eq!(manager.take_result(&id).unwrap(), b"FLAKY wifi");
+
+    }
+
+    #[tokio::test]
+    async fn test_parked_jobs_can_be_cancelled() {
+        let (manager, backend, _) = retrying(Duration::from_secs(30));
+        let id = manager.enqueue(options("FLAKY wifi"), "tts-1").unwrap();
+        for _ in 0..200 {
+            if matches!(manager.status(&id).unwrap().state, JobState::Parked { .. }) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        let JobState::Parked { retry_at, .. } = manager.status(&id).unwrap().state else {
+            panic!("job was not parked");
+        };
+        assert!(retry_at > Utc::now() + chrono::Duration::seconds(25));
+        // It doesn't hold up the queue meanwhile
+        let other = manager.enqueue(options("next"), "tts-1").unwrap();
+        wait_until_finished(&manager, std::slice::from_ref(&other)).await;
+
+        assert!(manager.cancel(&id));
+        assert_eq!(manager.status(&id).unwrap().state, JobState::Cancelled);
+        assert_eq!(backend.calls(), ["FLAKY wifi", "next"]);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_queued_job() {
+        let (manager, backend, _) = manager(1);
+        let running = manager.enqueue(options("long"), "tts-1").unwrap();
+        let queued = manager.enqueue(options("never"), "tts-1").unwrap();
+
+        assert!(manager.cancel(&queued));
+        assert!(!manager.cancel(&queued));
+        wait_until_finished(&manager, &[running.clone(), queued.clone()]).await;
+
+        assert_eq!(manager.status(&queued).unwrap().state, JobState::Cancelled);
+        assert_eq!(backend.calls(), vec!["long"]);
+        assert_eq!(manager.take_result(&queued).unwrap_err(), "Job was cancelled");
+    }
+
+    async fn wait_for_state(manager: &JobManager<FakeBackend>, id: &str, wanted: fn(&JobState) -> bool) {
+        for _ in 0..200 {
+            if manager.status(id).is_some_and(|job| wanted(&job.state)) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        panic!("job {} never got there: {:?}", id, manager.status(id));
+    }
+
+    #[tokio::test]
+    async fn test_pause_lets_the_running_job_finish_and_holds_the_rest() {
+        let (manager, backend, events) = manager(1);
+        let ids: Vec<JobId> =
+            ["first", "second", "third"].iter().map(|text| manager.enqueue(options(text), "tts-1").unwrap()).collect();
+        wait_for_state(&manager, &ids[0], |state| matches!(state, JobState::Running { .. })).await;
+
+        assert!(manager.pause());
+        assert!(!manager.pause());
+        wait_until_finished(&manager, &ids[..1]).await;
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(backend.calls(), ["first"]);
+        let second = manager.status(&ids[1]).unwrap();
+        assert_eq!(second.state, JobState::Queued);
+        assert!(second.queue_paused);
+        assert_eq!(manager.queue_status(), QueueStatus { paused: true, queued: 2, running: 0, concurrency: 1 });
+
+        // Queued jobs can still be cancelled, and new ones wait too
+        assert!(manager.cancel(&ids[1]));
+        let fourth = manager.enqueue(options("fourth"), "tts-1").unwrap();
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert_eq!(manager.status(&fourth).unwrap().state, JobState::Queued);
+
+        assert!(manager.resume());
+        assert!(!manager.resume());
+        wait_until_finished(&manager, &[ids[2].clone(), fourth.clone()]).await;
+        assert_eq!(backend.calls(), ["first", "third", "fourth"]);
+        assert!(!manager.status(&fourth).unwrap().queue_paused);
+        assert_eq!(manager.queue_status(), QueueStatus { paused: false, queued: 0, running: 0, concurrency: 1 });
+
+        let second_states: Vec<JobState> =
+            events.lock().unwrap().iter().filter(|(id, _)| *id == ids[1]).map(|(_, state)| state.clone()).collect();
+        assert_eq!(second_states, [JobState::Queued, JobState::Cancelled]);
+    }
+
+    /// Most calls to the backend that overlapped
+    fn most_at_once(backend: &FakeBackend) -> usize {
+        let spans = backend.spans.lock().unwrap();
+        spans.iter().map(|(_, start, _)| spans.iter().filter(|(_, s, e)| s <= start && start < e).count()).max().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_one_versus_two() {
+        for concurrency in [1, 2] {
+            let (manager, backend, _) = manager(concurrency);
+            let ids: Vec<JobId> =
+                ["a", "b", "c", "d"].iter().map(|text| manager.enqueue(options(text), "tts-1").unwrap()).collect();
+            wait_for_state(&manager, &ids[0], |state| matches!(state, JobState::Running { .. })).await;
+            let status = manager.queue_status();
+            assert_eq!((status.running, status.queued, status.concurrency), (concurrency, 4 - concurrency, concurrency));
+
+            wait_until_finished(&manager, &ids).await;
+            assert_eq!(most_at_once(&backend), concurrency);
+            assert_eq!(backend.calls(), ["a", "b", "c", "d"]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_changes_apply_to_jobs_that_start_later() {
+        let (manager, backend, _) = manager(2);
+        let ids: Vec<JobId> =
+            ["a", "b", "c", "d"].iter().map(|text| manager.enqueue(options(text), "tts-1").unwrap()).collect();
+        wait_for_state(&manager, &ids[1], |state| matches!(state, JobState::Running { .. })).await;
+
+        // Both running jobs finish before the next one starts
+        manager.set_concurrency(1);
+        assert_eq!(manager.queue_status().concurrency, 1);
+        wait_until_finished(&manager, &ids).await;
+        let ((_, a_end), (_, b_end)) = (backend.span("a"), backend.span("b"));
+        let (c_start, c_end) = backend.span("c");
+        assert!(c_start >= a_end.max(b_end));
+        assert!(backend.span("d").0 >= c_end);
+
+        // Raising it starts queued jobs at once
+        manager.pause();
+        let more: Vec<JobId> =
+            ["e", "f", "g"].iter().map(|text| manager.enqueue(options(text), "tts-1").unwrap()).collect();
+        manager.set_concurrency(3);
+        manager.resume();
+        wait_until_finished(&manager, &more).await;
+        let starts: Vec<Instant> = ["e", "f", "g"].iter().map(|text| backend.span(text).0).collect();
+        let first_end = ["e", "f", "g"].iter().map(|text| backend.span(text).1).min().unwrap();
+        assert!(starts.iter().all(|start| *start < first_end), "e, f and g should overlap");
+    }
+
+    #[tokio::test]
+    async fn test_parallel_jobs_share_the_rate_limit() {
+        let (manager, backend, _) = manager(2);
+        let ids: Vec<JobId> =
+            ["LIMITED", "b", "c"].iter().map(|text| manager.enqueue(options(text), "tts-1").unwrap()).collect();
+        wait_until_finished(&manager, &ids).await;
+
+        // c started once the 429 had finished its job, and waited out the
+        // Retry-After rather than hitting the API straight away
+        let (limited_start, limited_end) = backend.span("LIMITED");
+        let (c_start, _) = backend.span("c");
+        assert!(c_start >= limited_end);
+        assert!(c_start.duration_since(limited_start) >= Duration::from_millis(90), "{:?}", c_start - limited_start);
+    }
+
+    #[tokio::test]
+    async fn test_starting_paused_resumes_in_order() {
+        let (manager, backend, _) = manager(2);
+        let manager = manager.with_paused(true);
+        let ids: Vec<JobId> =
+            ["a", "b", "c"].iter().map(|text| manager.enqueue(options(text), "tts-1").unwrap()).collect();
+        assert!(manager.status(&ids[0]).unwrap().queue_paused);
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(backend.calls().is_empty());
+
+        assert!(manager.resume());
+        wait_until_finished(&manager, &ids).await;
+        let calls = backend.calls();
+        assert_eq!(calls.last().unwrap(), "c");
+        assert_eq!(calls.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_options_rejected_up_front() {
+        let (manager, _, events) = manager(1);
+        assert!(manager.enqueue(options("  "), "tts-1").is_err());
+        let bad_voice = GenerationOptions { voice_id: "rachel".to_string(), ..options("Hi") };
+        assert!(manager.enqueue(bad_voice, "tts-1").is_err());
+        let long_gap = GenerationOptions { paragraph_gap_ms: Some(10_000), ..options("Hi") };
+        assert!(manager.enqueue(long_gap, "tts-1").unwrap_err().contains("Paragraph gap"));
+        assert!(manager.list().is_empty());
+        assert!(events.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_offline_failures_can_be_deferred() {
+        let backend = Arc::new(FakeBackend::default());
+        let offered = Arc::new(Mutex::new(Vec::new()));
+        let recorded = offered.clone();
+        let manager = JobManager::new(backend, 1).with_offline_queue(Arc::new(
+            move |options: &GenerationOptions, model: &str, error: &TTSError| -> BoxFuture<'static, Option<i64>> {
+                recorded.lock().unwrap().push((options.text.clone(), model.to_string()));
+                let queued = crate::offline::should_queue(error, options.queue_if_offline, true);
+                Box::pin(async move { queued.then_some(7) })
+            },
+        ));
+
+        let deferred = manager.enqueue(options("OFFLINE please"), "tts-1").unwrap();
+        let failed = manager.enqueue(options("FAIL please"), "tts-1").unwrap();
+        let declined =
+            manager.enqueue(GenerationOptions { queue_if_offline: Some(false), ..options("OFFLINE now") }, "tts-1").unwrap();
+        wait_until_finished(&manager, &[deferred.clone(), failed.clone(), declined.clone()]).await;
+
+        assert_eq!(manager.status(&deferred).unwrap().state, JobState::Deferred { pending_id: 7 });
+        assert!(matches!(manager.status(&failed).unwrap().state, JobState::Failed { .. }));
+        assert!(matches!(manager.status(&declined).unwrap().state, JobState::Failed { .. }));
+        assert_eq!(offered.lock().unwrap().len(), 3);
+        assert_eq!(
+            serde_json::to_value(JobState::Deferred { pending_id: 7 }).unwrap(),
+            serde_json::json!({ "state": "deferred", "pendingId": 7 })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_finished_jobs_expire() {
+        let (manager, _, _) = manager(2);
+        let id = manager.enqueue(options("Hello"), "tts-1").unwrap();
+        wait_until_finished(&manager, std::slice::from_ref(&id)).await;
+
+        manager.prune(Instant::now() + RESULT_TTL / 2);
+        assert!(manager.status(&id).is_some());
+        manager.prune(Instant::now() + RESULT_TTL);
+        assert!(manager.status(&id).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_result_is_there_to_poll_for_without_events() {
+        // Nobody listening, as after the window reloaded
+        let manager = JobManager::new(Arc::new(FakeBackend::default()), 1);
+        let id = manager.enqueue(options("Hello"), "tts-1").unwrap();
+        let status = manager.generation_status(&id).unwrap();
+        assert!(!status.job.state.is_finished());
+        assert_eq!((status.chunks_done, status.chunks, status.result), (0, 1, None));
+        wait_until_finished(&manager, std::slice::from_ref(&id)).await;
+
+        let status = manager.generation_status(&id).unwrap();
+        assert_eq!(status.job.state, JobState::Done { bytes: 5 });
+        assert_eq!((status.chunks_done, status.chunks), (1, 1));
+        let result = status.result.unwrap();
+        assert_eq!((result.job_id.as_str(), result.bytes), (id.as_str(), 5));
+        assert_eq!(result.expires_at, status.job.finished_at.unwrap() + RESULT_TTL);
+
+        // Copied until forgotten, so a failed hand-over can be tried again
+        assert_eq!(manager.result(&id).unwrap(), b"Hello");
+        assert_eq!(manager.result(&id).unwrap(), b"Hello");
+        assert!(manager.forget(&id));
+        assert!(manager.generation_status(&id).is_none());
+        assert_eq!(manager.result(&id).unwrap_err(), format!("Unknown job: {}", id));
+        assert!(!manager.forget(&id));
+    }
+
+    /// A manager saving its jobs under `dir`, as the app builds one at startup
+    async fn saving_manager(dir: &std::path::Path, backend: FakeBackend) -> (JobManager<FakeBackend>, Arc<FakeBackend>) {
+        let database = crate::database::Database::open(&dir.join("usage.db")).await.unwrap();
+        let store = JobStore::new(database, dir.join("job-chunks"), tokio::runtime::Handle::current());
+        let backend = Arc::new(backend);
+        (JobManager::new(backend.clone(), 1).with_store(Arc::new(store)), backend)
+    }
+
+    async fn wait_for_progress(manager: &JobManager<FakeBackend>, id: &str) {
+        while !matches!(manager.status(id).unwrap().state, JobState::Running { progress } if progress > 0.0) {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    }
+
+    #[test]
+    fn test_interrupted_job_is_recovered_after_a_restart() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let runtime = || tokio::runtime::Builder::new_multi_thread().enable_all().build().unwrap();
+
+        // The app goes away while the second of three chunks is generating
+        let before = runtime();
+        let id = before.block_on(async {
+            let (manager, _) = saving_manager(dir.path(), FakeBackend { stalls: true, ..FakeBackend::default() }).await;
+            let titled = GenerationOptions { title: Some("Chapter 1".to_string()), ..options("One.|Two STALL.|Three.") };
+            let id = manager.enqueue(titled, "tts-1").unwrap();
+            wait_for_progress(&manager, &id).await;
+            assert!(manager.recoverable().await.unwrap().is_empty(), "a running job isn't interrupted");
+            id
+        });
+        before.shutdown_background();
+
+        let after = runtime();
+        after.block_on(async {
+            let (manager, backend) = saving_manager(dir.path(), FakeBackend::default()).await;
+            let recoverable = manager.recoverable().await.unwrap();
+            assert_eq!(recoverable.len(), 1);
+            assert_eq!((recoverable[0].id.as_str(), recoverable[0].title.as_deref()), (id.as_str(), Some("Chapter 1")));
+            assert_eq!((recoverable[0].chunks, recoverable[0].completed_chunks), (3, 1));
+
+            assert_eq!(manager.recover(&id).await.unwrap(), id);
+            assert!(manager.recover(&id).await.unwrap_err().contains("not interrupted"));
+            assert_eq!(manager.wait(&id).await.unwrap(), b"One.Two STALL.Three.");
+            // The chunk done before the restart isn't generated again
+            assert_eq!(backend.calls(), ["Two STALL.", "Three."]);
+            assert!(manager.recoverable().await.unwrap().is_empty());
+            assert!(!dir.path().join("job-chunks").join(&id).exists());
+            assert!(manager.recover(&id).await.unwrap_err().contains("Unknown job"));
+        });
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_and_finished_jobs_are_not_recoverable() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let (manager, _) = saving_manager(dir.path(), FakeBackend { stalls: true, ..FakeBackend::default() }).await;
+        let stalled = manager.enqueue(options("One.|STALL"), "tts-1").unwrap();
+        let short = manager.enqueue(options("Short"), "tts-1").unwrap();
+        wait_for_progress(&manager, &stalled).await;
+        assert!(manager.cancel(&stalled));
+        assert_eq!(manager.wait(&short).await.unwrap(), b"Short");
+
+        let (restarted, _) = saving_manager(dir.path(), FakeBackend::default()).await;
+        for _ in 0..200 {
+            if restarted.recoverable().await.unwrap().is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert!(restarted.recoverable().await.unwrap().is_empty());
+        assert!(!dir.path().join("job-chunks").join(&stalled).exists());
+    }
+}
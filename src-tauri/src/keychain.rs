@@ -0,0 +1,278 @@
+//! Where the OpenAI API key comes from: `OPENAI_API_KEY` when it's set (CI,
+//! scripts), otherwise the OS keychain, where the window saves it. The key
+//! never leaves the backend; callers only see an `ApiKeyStatus`.
+
+use crate::tts::TTSService;
+use serde::Serialize;
+
+pub const API_KEY_ENV: &str = "OPENAI_API_KEY";
+const KEYCHAIN_SERVICE: &str = "tts-player";
+const KEYCHAIN_USER: &str = "openai-api-key";
+
+/// Persistent storage for the key; the OS keychain in the app, a fake in tests.
+pub trait KeyStore: Send + Sync {
+    fn get(&self) -> Result<Option<String>, String>;
+    fn set(&self, key: &str) -> Result<(), String>;
+    /// Succeeds when there was nothing to delete
+    fn delete(&self) -> Result<(), String>;
+}
+
+/// Keychain on macOS, Credential Manager on Windows, Secret Service on Linux
+pub struct Keychain;
+
+impl Keychain {
+    fn entry() -> Result<keyring::Entry, String> {
+        keyring::Entry::new(KEYCHAIN_SERVICE, KEYCHAIN_USER).map_err(|e| format!("Keychain unavailable: {}", e))
+    }
+}
+
+impl KeyStore for Keychain {
+    fn get(&self) -> Result<Option<String>, String> {
+        match Self::entry()?.get_password() {
+            Ok(key) => Ok(Some(key)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(format!("Failed to read API key from keychain: {}", e)),
+        }
+    }
+
+    fn set(&self, key: &str) -> Result<(), String> {
+        Self::entry()?
+            .set_password(key)
+            .map_err(|e| format!("Failed to save API key to keychain: {}", e))
+    }
+
+    fn delete(&self) -> Result<(), String> {
+        match Self::entry()?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(format!("Failed to remove API key from keychain: {}", e)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeySource {
+    Environment,
+    Keychain,
+}
+
+/// All the frontend ever learns about the key
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ApiKeyStatus {
+    pub configured: bool,
+    pub source: Option<KeySource>,
+    /// e.g. `sk-…3xYz`
+    pub masked: Option<String>,
+}
+
+/// Resolves the key with the environment taking precedence over the store.
+pub struct ApiKeys {
+    store: Box<dyn KeyStore>,
+    env_key: Option<String>,
+}
+
+impl ApiKeys {
+    /// The OS keychain, overridden by `OPENAI_API_KEY` if it's set
+    pub fn from_env() -> Self {
+        Self::new(Box::new(Keychain), std::env::var(API_KEY_ENV).ok())
+    }
+
+    pub fn new(store: Box<dyn KeyStore>, env_key: Option<String>) -> Self {
+        let env_key = env_key.map(|key| key.trim().to_string()).filter(|key| !key.is_empty());
+        Self { store, env_key }
+    }
+
+    /// The key requests should use, and where it came from
+    pub fn resolve(&self) -> Result<Option<(String, KeySource)>, String> {
+        if let Some(key) = &self.env_key {
+            return Ok(Some((key.clone(), KeySource::Environment)));
+        }
+        Ok(self.store.get()?.map(|key| (key, KeySource::Keychain)))
+    }
+
+    /// `resolve`, treating an unreadable keychain as having no key
+    pub fn resolve_or_warn(&self) -> Option<(String, KeySource)> {
+        self.resolve().unwrap_or_else(|e| {
+            tracing::warn!("{}", e);
+            None
+        })
+    }
+
+    pub fn status(&self) -> ApiKeyStatus {
+        match self.resolve_or_warn() {
+            Some((key, source)) => ApiKeyStatus { configured: true, source: Some(source), masked: Some(mask(&key)) },
+            None => ApiKeyStatus { configured: false, source: None, masked: None },
+        }
+    }
+
+    /// Save `key` to the store. While `OPENAI_API_KEY` is set it still wins.
+    pub fn set(&self, key: &str) -> Result<(), String> {
+        let key = key.trim();
+        if key.is_empty() {
+            return Err("API key cannot be empty".to_string());
+        }
+        if key.chars().any(char::is_whitespace) {
+            return Err("API key cannot contain spaces".to_string());
+        }
+        self.store.set(key)
+    }
+
+    pub fn clear(&self) -> Result<(), String> {
+        self.store.delete()
+    }
+
+    /// Point `service` at the current key after a change and report the result
+    pub fn reload(&self, service: &TTSService) -> ApiKeyStatus {
+        let resolved = self.resolve_or_warn();
+        service.set_api_key(resolved.as_ref().map(|(key, _)| key.as_str()));
+        tracing::info!(
+            "API key {}",
+            match resolved.map(|(_, source)| source) {
+                Some(KeySource::Environment) => "from environment",
+                Some(KeySource::Keychain) => "from keychain",
+                None => "not configured",
+            }
+        );
+        self.status()
+    }
+}
+
+/// A short tag telling keys apart in the usage history without storing any
+/// of the key: a hash (32-bit FNV-1a, stable across builds) of its last four
+/// characters
+pub fn fingerprint(key: &str) -> String {
+    let chars: Vec<char> = key.trim().chars().collect();
+    let last_four: String = chars[chars.len().saturating_sub(4)..].iter().collect();
+    let hash = last_four.bytes().fold(0x811c_9dc5_u32, |hash, byte| (hash ^ byte as u32).wrapping_mul(0x0100_0193));
+    format!("{:08x}", hash)
+}
+
+/// Enough of the key to recognize it: `sk-…` plus the last four characters
+pub fn mask(key: &str) -> String {
+    let chars: Vec<char> = key.chars().collect();
+    if chars.len() < 12 {
+        return "…".to_string();
+    }
+    let prefix = if key.starts_with("sk-") { "sk-" } else { "" };
+    let suffix: String = chars[chars.len() - 4..].iter().collect();
+    format!("{}…{}", prefix, suffix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// In-memory stand-in for the keychain; clones share the same slot
+    #[derive(Clone, Default)]
+    struct MemoryStore(Arc<Mutex<Option<String>>>);
+
+    impl KeyStore for MemoryStore {
+        fn get(&self) -> Result<Option<String>, String> {
+            Ok(self.0.lock().unwrap().clone())
+        }
+
+        fn set(&self, key: &str) -> Result<(), String> {
+            *self.0.lock().unwrap() = Some(key.to_string());
+            Ok(())
+        }
+
+        fn delete(&self) -> Result<(), String> {
+            *self.0.lock().unwrap() = None;
+            Ok(())
+        }
+    }
+
+    struct BrokenStore;
+
+    impl KeyStore for BrokenStore {
+        fn get(&self) -> Result<Option<String>, String> {
+            Err("Keychain unavailable: locked".to_string())
+        }
+
+        fn set(&self, _key: &str) -> Result<(), String> {
+            Err("Keychain unavailable: locked".to_string())
+        }
+
+        fn delete(&self) -> Result<(), String> {
+            Err("Keychain unavailable: locked".to_string())
+        }
+    }
+
+    const KEY: &str = "sk-proj-abcdefghijklmnop1234";
+
+    #[test]
+    fn test_environment_overrides_keychain() {
+        let store = MemoryStore::default();
+        store.set("sk-from-keychain-0000").unwrap();
+
+        let keys = ApiKeys::new(Box::new(store.clone()), Some("sk-from-env-9999".to_string()));
+        assert_eq!(keys.resolve().unwrap(), Some(("sk-from-env-9999".to_string(), KeySource::Environment)));
+
+        // An empty variable doesn't count as set
+        let keys = ApiKeys::new(Box::new(store.clone()), Some(" ".to_string()));
+        assert_eq!(keys.resolve().unwrap(), Some(("sk-from-keychain-0000".to_string(), KeySource::Keychain)));
+
+        let keys = ApiKeys::new(Box::new(MemoryStore::default()), None);
+        assert_eq!(keys.resolve().unwrap(), None);
+    }
+
+    #[test]
+    fn test_reload_on_change() {
+        let store = MemoryStore::default();
+        let keys = ApiKeys::new(Box::new(store.clone()), None);
+        let service = TTSService::new("", "http://localhost");
+        assert!(!keys.reload(&service).configured);
+        assert!(!service.is_configured());
+
+        keys.set(&format!("  {}\n", KEY)).unwrap();
+        assert_eq!(store.get().unwrap().as_deref(), Some(KEY));
+        let status = keys.reload(&service);
+        assert_eq!(status.source, Some(KeySource::Keychain));
+        assert!(service.is_configured());
+
+        keys.clear().unwrap();
+        assert!(!keys.reload(&service).configured);
+        assert!(!service.is_configured());
+    }
+
+    #[test]
+    fn test_status_never_contains_the_key() {
+        let store = MemoryStore::default();
+        let keys = ApiKeys::new(Box::new(store), None);
+        keys.set(KEY).unwrap();
+
+        let status = keys.status();
+        assert_eq!(status.masked.as_deref(), Some("sk-…1234"));
+        let json = serde_json::to_string(&status).unwrap();
+        assert!(!json.contains("abcdefgh"));
+        assert_eq!(mask("short"), "…");
+    }
+
+    #[test]
+    fn test_invalid_keys_and_broken_keychain() {
+        let keys = ApiKeys::new(Box::new(MemoryStore::default()), None);
+        assert!(keys.set("   ").is_err());
+        assert!(keys.set("sk-abc def").is_err());
+
+        let keys = ApiKeys::new(Box::new(BrokenStore), None);
+        assert!(keys.resolve().is_err());
+        assert!(!keys.status().configured);
+        assert!(keys.set(KEY).is_err());
+
+        // The environment still works without a keychain
+        let keys = ApiKeys::new(Box::new(BrokenStore), Some(KEY.to_string()));
+        assert_eq!(keys.status().source, Some(KeySource::Environment));
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let fingerprint = fingerprint(KEY);
+        assert_eq!(fingerprint.len(), 8);
+        assert!(fingerprint.chars().all(|c| c.is_ascii_hexdigit()));
+        assert!(!KEY.contains(&fingerprint));
+        // Only the last four characters count
+        assert_eq!(super::fingerprint(&format!("sk-other{}", &KEY[KEY.len() - 4..])), fingerprint);
+        assert_ne!(super::fingerprint("sk-personal-1111"), super::fingerprint("sk-personal-2222"));
+    }
+}
@@ -0,0 +1,163 @@
+//! How long generations take. The time from sending the first request to
+//! having all of the audio is kept with the usage record, with the number
+//! of requests it took, so the stats can show percentiles per model and
+//! the slowest generations next to their length.
+
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Most finished generations waiting to be recorded; older ones are
+/// forgotten, as nothing is going to record them
+const MAX_PENDING: usize = 32;
+
+/// How long one generation took and in how many requests
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Latency {
+    pub latency_ms: i64,
+    pub chunk_count: i32,
+}
+
+/// Generations that finished but aren't recorded yet, by what they
+/// generated. Usage is recorded after the audio is handed back, often by
+/// the caller, so the timing waits here for the record to pick it up.
+#[derive(Default)]
+pub struct PendingLatencies {
+    entries: Mutex<VecDeque<(u64, Latency)>>,
+}
+
+impl PendingLatencies {
+    fn key(text: &str, voice_id: &str, model: &str) -> u64 {
+        crate::watch::hash(&format!("{}\0{}\0{}", text, voice_id, model))
+    }
+
+    /// A generation started at `started` finished after `chunk_count` requests
+    pub fn finished(&self, text: &str, voice_id: &str, model: &str, started: Instant, chunk_count: usize) {
+        let latency = Latency {
+            latency_ms: started.elapsed().as_millis() as i64,
+            chunk_count: chunk_count as i32,
+        };
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == MAX_PENDING {
+            entries.pop_front();
+        }
+        entries.push_back((Self::key(text, voice_id, model), latency));
+    }
+
+    /// The latest generation of `text` with `voice_id` and `model`, if it
+    /// hasn't been taken already
+    pub fn take(&self, text: &str, voice_id: &str, model: &str) -> Option<Latency> {
+        let key = Self::key(text, voice_id, model);
+        let mut entries = self.entries.lock().unwrap();
+        let position = entries.iter().rposition(|(entry, _)| *entry == key)?;
+        entries.remove(position).map(|(_, latency)| latency)
+    }
+}
+
+/// Latency percentiles of one model's successful generations
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelLatency {
+    pub model_id: String,
+    pub request_count: i64,
+    pub p50_ms: i64,
+    pub p90_ms: i64,
+    pub p99_ms: i64,
+}
+
+/// The nearest-rank `p`th percentile of `sorted`: the smallest value that
+/// at least `p` percent of them are no greater than
+pub fn percentile(sorted: &[i64], p: f64) -> Option<i64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+/// `ModelLatency` for each model in `samples` of (model, latency in ms),
+/// the busiest model first
+pub fn by_model(samples: impl IntoIterator<Item = (String, i64)>) -> Vec<ModelLatency> {
+    let mut models: Vec<(String, Vec<i64>)> = Vec::new();
+    for (model_id, latency_ms) in samples {
+        match models.iter_mut().find(|(model, _)| *model == model_id) {
+            Some((_, latencies)) => latencies.push(latency_ms),
+            None => models.push((model_id, vec![latency_ms])),
+        }
+    }
+    let mut latencies: Vec<ModelLatency> = models
+        .into_iter()
+        .map(|(model_id, mut latencies)| {
+            latencies.sort_unstable();
+            let at = |p| percentile(&latencies, p).unwrap_or(0);
+            ModelLatency {
+                request_count: latencies.len() as i64,
+                p50_ms: at(50.0),
+                p90_ms: at(90.0),
+                p99_ms: at(99.0),
+                model_id,
+            }
+        })
+        .collect();
+    latencies.sort_by(|a, b| b.request_count.cmp(&a.request_count).then_with(|| a.model_id.cmp(&b.model_id)));
+    latencies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentiles() {
+        let hundred: Vec<i64> = (1..=100).map(|i| i * 10).collect();
+        assert_eq!(percentile(&hundred, 50.0), Some(500));
+        assert_eq!(percentile(&hundred, 90.0), Some(900));
+        assert_eq!(percentile(&hundred, 99.0), Some(990));
+        assert_eq!(percentile(&hundred, 100.0), Some(1000));
+        assert_eq!(percentile(&hundred, 0.0), Some(10));
+
+        // With few samples the high percentiles are the slowest one
+        let few = [800, 900, 1000, 1200, 90_000];
+        assert_eq!(percentile(&few, 50.0), Some(1000));
+        assert_eq!(percentile(&few, 90.0), Some(90_000));
+        assert_eq!(percentile(&few, 99.0), Some(90_000));
+        assert_eq!(percentile(&[], 50.0), None);
+    }
+
+    #[test]
+    fn test_by_model() {
+        // tts-1: 1..=20 seconds, shuffled; tts-1-hd: two requests
+        let mut samples: Vec<(String, i64)> = (1..=20).map(|i| ("tts-1".to_string(), (i * 7 % 20 + 1) * 1000)).collect();
+        samples.insert(3, ("tts-1-hd".to_string(), 4000));
+        samples.push(("tts-1-hd".to_string(), 60_000));
+
+        let latencies = by_model(samples);
+        assert_eq!(
+            latencies,
+            [
+                ModelLatency { model_id: "tts-1".to_string(), request_count: 20, p50_ms: 10_000, p90_ms: 18_000, p99_ms: 20_000 },
+                ModelLatency { model_id: "tts-1-hd".to_string(), request_count: 2, p50_ms: 4000, p90_ms: 60_000, p99_ms: 60_000 },
+            ]
+        );
+        assert!(by_model(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn test_pending_latencies() {
+        let pending = PendingLatencies::default();
+        let started = Instant::now();
+        pending.finished("Hello", "nova", "tts-1", started, 1);
+        pending.finished("Long text", "nova", "tts-1-hd", started, 3);
+
+        assert_eq!(pending.take("Hello", "nova", "tts-1-hd"), None);
+        assert_eq!(pending.take("Long text", "nova", "tts-1-hd").unwrap().chunk_count, 3);
+        assert_eq!(pending.take("Long text", "nova", "tts-1-hd"), None);
+
+        // Ones nothing records don't pile up
+        for i in 0..MAX_PENDING {
+            pending.finished(&format!("Text {}", i), "nova", "tts-1", started, 1);
+        }
+        assert_eq!(pending.take("Hello", "nova", "tts-1"), None);
+        assert!(pending.take("Text 0", "nova", "tts-1").is_some());
+    }
+}
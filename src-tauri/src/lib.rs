@@ -1,4 +1,68 @@
-// pub mod cli; // Unused - CLI args handled by Tauri
+pub mod analysis;
+pub mod article;
+pub mod audio_stream;
+pub mod batch;
+pub mod chapters;
+pub mod cli;
+pub mod clipboard;
+pub mod clipboard_watch;
+pub mod config;
+pub mod deeplink;
+pub mod docx;
+pub mod documents;
+pub mod encoding;
+pub mod epub;
+pub mod eta;
+pub mod extract;
 pub mod tts;
-// pub mod file_manager; // Unused - file operations handled inline
-pub mod database;
\ No newline at end of file
+pub mod file_manager;
+pub mod database;
+pub mod headless;
+pub mod health;
+pub mod inflight;
+pub mod integrity;
+pub mod i18n;
+pub mod jobs;
+pub mod keychain;
+pub mod latency;
+pub mod logging;
+pub mod markdown;
+pub mod models;
+pub mod notifications;
+pub mod ocr;
+pub mod offline;
+pub mod output_format;
+pub mod paths;
+pub mod pdf;
+pub mod permissions;
+pub mod playback;
+pub mod player;
+pub mod projects;
+pub mod recent;
+pub mod recent_logs;
+pub mod recovery;
+pub mod regenerate;
+pub mod relay;
+pub mod report;
+pub mod resolve;
+pub mod reveal;
+pub mod sections;
+pub mod segments;
+pub mod settings;
+pub mod shortcut;
+pub mod silence;
+pub mod snippets;
+pub mod speed;
+pub mod spend;
+pub mod subtitles;
+#[cfg(test)]
+pub(crate) mod test_support;
+pub mod throttle;
+pub mod transcode;
+pub mod tray;
+pub mod trim;
+pub mod typography;
+pub mod usage_import;
+pub mod voices;
+pub mod warnings;
+pub mod watch;
\ No newline at end of file
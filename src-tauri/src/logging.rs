@@ -0,0 +1,156 @@
+//! Log output for both the window and CLI runs, written to stderr so it
+//! never mixes with results on stdout. Every line is passed through
+//! `redact` on the way out, in case it quotes a header or an error body.
+
+use crate::recent_logs::RecentLogs;
+use std::io;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+/// How much the run should say, from `--quiet` and `--verbose` (repeatable)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Verbosity {
+    pub verbose: u8,
+    pub quiet: bool,
+}
+
+impl Verbosity {
+    /// Filter directive for this verbosity. Quiet silences logging entirely;
+    /// the final result and any error are printed directly, not logged.
+    pub fn filter(&self) -> &'static str {
+        if self.quiet {
+            return "off";
+        }
+        match self.verbose {
+            0 => "warn",
+            // Progress lines per chunk
+            1 => "warn,tts_player=info",
+            // HTTP status and timing
+            _ => "warn,tts_player=debug",
+        }
+    }
+
+    /// Whether interactive progress output should be drawn
+    pub fn show_progress(&self) -> bool {
+        !self.quiet
+    }
+}
+
+/// What the in-memory log keeps regardless of verbosity, enough to see
+/// what a failed request did
+pub const RECENT_FILTER: &str = "warn,tts_player=debug";
+
+/// What a secret is replaced with
+pub const REDACTED: &str = "[redacted]";
+/// Length from which an "sk-" token is taken for an OpenAI key
+const MIN_KEY_LEN: usize = 20;
+
+fn is_token_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_'
+}
+
+/// `text` with whatever is shaped like a secret blanked out: the token
+/// after "Bearer" and anything long starting "sk-". For a key of another
+/// shape, `TTSService::redact` also knows the configured one.
+pub fn redact(text: &str) -> String {
+    let mut redacted = String::with_capacity(text.len());
+    let mut after_bearer = false;
+    for piece in text.split_inclusive(|c: char| !is_token_char(c)) {
+        let token = piece.trim_end_matches(|c: char| !is_token_char(c));
+        if token.is_empty() {
+            after_bearer &= piece.trim().is_empty();
+            redacted.push_str(piece);
+            continue;
+        }
+        if after_bearer || (token.starts_with("sk-") && token.len() >= MIN_KEY_LEN) {
+            redacted.push_str(REDACTED);
+            redacted.push_str(&piece[token.len()..]);
+        } else {
+            redacted.push_str(piece);
+        }
+        after_bearer = token.eq_ignore_ascii_case("bearer");
+    }
+    redacted
+}
+
+/// Writes through `redact`. Each log line arrives in one write.
+struct RedactingWriter<W>(W);
+
+impl<W: io::Write> io::Write for RedactingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write_all(redact(&String::from_utf8_lossy(buf)).as_bytes())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// Install the global subscriber and return the recent lines it keeps for
+/// `get_recent_logs`. `RUST_LOG` applies only when no verbosity flag was
+/// given, and only to stderr.
+pub fn init(verbosity: Verbosity) -> RecentLogs {
+    let filter = match std::env::var("RUST_LOG") {
+        Ok(value) if verbosity == Verbosity::default() => value,
+        _ => verbosity.filter().to_string(),
+    };
+
+    let recent = RecentLogs::default();
+    let _ = tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(|| RedactingWriter(io::stderr()))
+                .without_time()
+                .with_filter(EnvFilter::new(filter)),
+        )
+        .with(recent.clone().with_filter(EnvFilter::new(RECENT_FILTER)))
+        .try_init();
+    recent
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flag_to_filter_mapping() {
+        assert_eq!(Verbosity::default().filter(), "warn");
+        assert_eq!(Verbosity { verbose: 1, quiet: false }.filter(), "warn,tts_player=info");
+        assert_eq!(Verbosity { verbose: 2, quiet: false }.filter(), "warn,tts_player=debug");
+        assert_eq!(Verbosity { verbose: 5, quiet: false }.filter(), "warn,tts_player=debug");
+        assert_eq!(Verbosity { verbose: 0, quiet: true }.filter(), "off");
+    }
+
+    #[test]
+    fn test_quiet_hides_progress() {
+        assert!(Verbosity::default().show_progress());
+        assert!(!Verbosity { verbose: 0, quiet: true }.show_progress());
+    }
+
+    #[test]
+    fn test_redact() {
+        let key = "sk-proj-Abc123_def456-ghi789";
+        assert_eq!(redact(&format!("Authorization: Bearer {}", key)), "Authorization: Bearer [redacted]");
+        assert_eq!(redact(&format!("GET https://proxy/v1?key={}&x=1", key)), "GET https://proxy/v1?key=[redacted]&x=1");
+        assert_eq!(redact("authorization: bearer abc.def"), "authorization: bearer [redacted].def");
+        assert_eq!(redact("Bearer [redacted]"), "Bearer [redacted]");
+        assert_eq!(redact("Incorrect API key provided: sk-abc***xyz."), "Incorrect API key provided: sk-abc***xyz.");
+        let plain = "Split text into 3 chunks (sk-learn, task-based)";
+        assert_eq!(redact(plain), plain);
+
+        let mut written = Vec::new();
+        io::Write::write_all(&mut RedactingWriter(&mut written), format!("sending {}\n", key).as_bytes()).unwrap();
+        assert_eq!(String::from_utf8(written).unwrap(), "sending [redacted]\n");
+    }
+
+    #[test]
+    fn test_filters_parse() {
+        for verbose in 0..3 {
+            let filter = Verbosity { verbose, quiet: false }.filter();
+            assert!(tracing_subscriber::EnvFilter::try_new(filter).is_ok(), "{}", filter);
+        }
+        assert!(tracing_subscriber::EnvFilter::try_new(RECENT_FILTER).is_ok());
+    }
+}
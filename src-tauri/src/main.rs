@@ -1,105 +1,1699 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-// mod cli; // Unused - CLI args handled by Tauri
+mod analysis;
+mod article;
+mod audio_stream;
+mod batch;
+mod chapters;
+mod cli;
+mod clipboard;
+mod clipboard_watch;
+mod config;
+mod deeplink;
+mod docx;
+mod documents;
+mod encoding;
+mod epub;
+mod eta;
+mod extract;
 mod tts;
-// mod file_manager; // Unused - file operations handled inline
+mod file_manager;
 mod database;
+mod headless;
+mod health;
+mod inflight;
+mod integrity;
+mod i18n;
+mod jobs;
+mod keychain;
+mod latency;
+mod logging;
+mod markdown;
+mod models;
+mod notifications;
+mod ocr;
+mod offline;
+mod output_format;
+mod paths;
+mod pdf;
+mod permissions;
+mod playback;
+mod player;
+mod projects;
+mod recent;
+mod recent_logs;
+mod recovery;
+mod regenerate;
+mod relay;
+mod report;
+mod resolve;
+mod reveal;
+mod sections;
+mod segments;
+mod settings;
+mod shortcut;
+mod silence;
+mod snippets;
+mod speed;
+mod spend;
+mod subtitles;
+#[cfg(test)]
+mod test_support;
+mod throttle;
+mod transcode;
+mod tray;
+mod trim;
+mod typography;
+mod usage_import;
+mod voices;
+mod warnings;
+mod watch;
 
-// use tauri::Manager; // Unused import
+use clipboard::ClipboardSource;
+use serde::Serialize;
+use spend::{apply_budget, BudgetedModel};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tauri::menu::{Menu, MenuItem, PredefinedMenuItem};
+use tauri::tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent};
+use tauri::{Emitter, Manager};
 use tauri_plugin_clipboard_manager::ClipboardExt;
+use tauri_plugin_deep_link::DeepLinkExt;
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, ShortcutState};
+use tauri_plugin_notification::NotificationExt;
 
+/// Startup text from the command line, handed out once
+struct LaunchState(std::sync::Mutex<Option<cli::LaunchRequest>>);
+
+const TRAY_ID: &str = "main";
+
+/// Whether a generation started from the tray is running, and the history
+/// entries its menu lists
+#[derive(Default)]
+struct TrayState {
+    generating: AtomicBool,
+    history: std::sync::Mutex<Vec<database::UsageRecord>>,
+}
+
+/// Payload of the `deep-link-speech` and `clipboard-speech` events: audio
+/// generated without the window asking, for it to show and play
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BackgroundSpeech {
+    text: String,
+    voice_id: String,
+    audio: file_manager::GeneratedAudio,
+}
+
+/// `generate_speech_with_model` with the model from the settings
+#[tauri::command]
+async fn generate_speech(
+    text: String,
+    voice_id: Option<String>,
+    inline: Option<bool>,
+    api_key: Option<String>,
+    tts_service: tauri::State<'_, tts::TTSService>,
+    files: tauri::State<'_, file_manager::FileManager>,
+    settings: tauri::State<'_, settings::SettingsStore>,
+) -> Result<file_manager::GeneratedAudio, i18n::CommandError> {
+    recent_logs::correlated_localized("generate_speech", with_warnings(async {
+        let tts_service = tts_service.for_request(api_key.as_deref());
+        let settings = settings.get();
+        let resolved = resolve::resolve(voice_id.as_deref(), None, &settings)?;
+        let model = apply_budget(&tts_service, &settings, &resolved.model, &text).await?;
+        synthesize_audio(&tts_service, &files, &text, &resolved, &model, database::SOURCE_APP, inline.unwrap_or(false)).await
+    }))
+    .await
+}
+
+/// Whatever of the voice and model isn't given comes from the settings
+/// (see `resolve`). `api_key` sends this one request with another account's
+/// key (see `TTSService::for_request`).
+// Tauri passes each argument on its own, so they can't be grouped
+#[allow(clippy::too_many_arguments)]
+#[tauri::command]
+async fn generate_speech_with_model(
+    text: String,
+    voice_id: Option<String>,
+    model: Option<String>,
+    inline: Option<bool>,
+    api_key: Option<String>,
+    tts_service: tauri::State<'_, tts::TTSService>,
+    files: tauri::State<'_, file_manager::FileManager>,
+    settings: tauri::State<'_, settings::SettingsStore>,
+) -> Result<file_manager::GeneratedAudio, i18n::CommandError> {
+    recent_logs::correlated_localized("generate_speech_with_model", with_warnings(async {
+        let tts_service = tts_service.for_request(api_key.as_deref());
+        let settings = settings.get();
+        let resolved = resolve::resolve(voice_id.as_deref(), model.as_deref(), &settings)?;
+        let model = apply_budget(&tts_service, &settings, &resolved.model, &text).await?;
+        synthesize_audio(&tts_service, &files, &text, &resolved, &model, database::SOURCE_APP, inline.unwrap_or(false)).await
+    }))
+    .await
+}
+
+/// The voice and model the window starts with (see `resolve`)
+#[tauri::command]
+fn resolve_defaults(settings: tauri::State<'_, settings::SettingsStore>) -> resolve::Resolved {
+    resolve::defaults(&settings.get())
+}
+
+/// Shared by the model command and deep links: validate, generate, track
+/// usage under `source` and hand back something the audio element can play.
+async fn synthesize_audio(
+    tts_service: &tts::TTSService,
+    files: &file_manager::FileManager,
+    text: &str,
+    resolved: &resolve::Resolved,
+    budgeted: &BudgetedModel,
+    source: &str,
+    inline: bool,
+) -> Result<file_manager::GeneratedAudio, i18n::CommandError> {
+    let text = &*tts_service.prepare(text);
+    let voice_id = resolved.voice_id.as_str();
+    let model = budgeted.model.as_str();
+    if let Some(downgrade) = &budgeted.downgrade {
+        warnings::warn(warnings::downgraded(downgrade));
+    }
+    // Validate inputs
+    tts_service.validate_text(text).await?;
+    if !tts_service.is_valid_voice(voice_id) {
+        return Err(i18n::invalid_voice(voice_id).into());
+    }
+    
+    // Long text FFmpeg isn't there to join comes back in pieces, if the
+    // settings say so, to play one after another
+    if tts_service.plays_as_playlist(text) {
+        let pieces = tts_service.generate_speech_chunked(text, voice_id, model).await?;
+        warnings::warn(warnings::playlist(pieces.len()));
+        spend::track_budgeted(tts_service, text, voice_id, budgeted, source).await;
+
+        let id = uuid::Uuid::new_v4().to_string();
+        let characters = text.chars().count() / pieces.len();
+        let mut playlist = Vec::with_capacity(pieces.len());
+        for (i, piece) in pieces.into_iter().enumerate() {
+            let piece_id = format!("{}-{}", id, i + 1);
+            playlist.push(deliver_audio(files, tts_service, &piece_id, piece, characters, inline).await?);
+        }
+        let first = playlist[0].clone();
+        let resolved = Some(resolved.clone());
+        return Ok(file_manager::GeneratedAudio { playlist, resolved, ..first.downgraded(budgeted.downgrade.as_ref()) });
+    }
+
+    // Generate speech with specific model, sharing an identical request
+    // that is already running
+    let (audio_data, coalesced) = tts_service.generate_speech_shared(text, voice_id, model).await;
+    let audio_data = audio_data?;
+    
+    // Track usage, unless the shared request already did
+    if !coalesced {
+        spend::track_budgeted(tts_service, text, voice_id, budgeted, source).await;
+    }
+    
+    let id = uuid::Uuid::new_v4().to_string();
+    let audio = deliver_audio(files, tts_service, &id, audio_data, text.chars().count(), inline).await?;
+    let resolved = Some(resolved.clone());
+    Ok(file_manager::GeneratedAudio { coalesced, resolved, ..audio.downgraded(budgeted.downgrade.as_ref()) })
+}
+
+/// Generate history record `record_id` again with any of its voice, model
+/// or speed changed. The new history entry links back to the original.
+#[tauri::command]
+async fn regenerate_from_record(
+    record_id: i64,
+    overrides: Option<regenerate::RegenerateOverrides>,
+    inline: Option<bool>,
+    tts_service: tauri::State<'_, tts::TTSService>,
+    files: tauri::State<'_, file_manager::FileManager>,
+    settings: tauri::State<'_, settings::SettingsStore>,
+) -> Result<file_manager::GeneratedAudio, i18n::CommandError> {
+    recent_logs::correlated_localized("regenerate_from_record", with_warnings(async {
+        let settings = settings.get();
+        let regenerated = regenerate::run(&tts_service, &settings, record_id, overrides.unwrap_or_default()).await?;
+        let id = uuid::Uuid::new_v4().to_string();
+        let characters = regenerated.regeneration.text.chars().count();
+        let audio = deliver_audio(&files, &tts_service, &id, regenerated.audio, characters, inline.unwrap_or(false)).await?;
+        let resolved = Some(regenerated.resolved);
+        Ok(file_manager::GeneratedAudio { resolved, ..audio.downgraded(regenerated.budgeted.downgrade.as_ref()) })
+    }))
+    .await
+}
+
+/// Generate `segments` with a pause after each, e.g. to keep the timing of
+/// subtitles from `extract_text_from_subtitles`
+#[tauri::command]
+async fn generate_speech_with_pauses(
+    segments: Vec<silence::Segment>,
+    voice_id: String,
+    model: String,
+    inline: Option<bool>,
+    tts_service: tauri::State<'_, tts::TTSService>,
+    files: tauri::State<'_, file_manager::FileManager>,
+    settings: tauri::State<'_, settings::SettingsStore>,
+) -> Result<file_manager::GeneratedAudio, i18n::CommandError> {
+    recent_logs::correlated_localized("generate_speech_with_pauses", with_warnings(async {
+        let settings = settings.get();
+        let (audio_data, budgeted) =
+            silence::generate_with_pauses(&tts_service, &settings, &segments, &voice_id, &model, database::SOURCE_APP).await?;
+
+        let text = segments.iter().map(|segment| segment.text.as_str()).collect::<Vec<_>>().join(" ");
+        let pauses_ms: u64 = segments.iter().map(|segment| segment.pause_after_ms.min(silence::MAX_PAUSE_MS)).sum();
+        let characters = text.chars().count() + (pauses_ms as f64 / 1000.0 * tts::CHARACTERS_PER_SECOND) as usize;
+        let id = uuid::Uuid::new_v4().to_string();
+        let audio = deliver_audio(&files, &tts_service, &id, audio_data, characters, inline.unwrap_or(false)).await?;
+        Ok(audio.downgraded(budgeted.downgrade.as_ref()))
+    }))
+    .await
+}
+
+/// `generation`'s audio with what it was warned about on the way (see
+/// `warnings`)
+async fn with_warnings<E>(
+    generation: impl std::future::Future<Output = Result<file_manager::GeneratedAudio, E>>,
+) -> Result<file_manager::GeneratedAudio, E> {
+    let (result, warnings) = warnings::collect(generation).await;
+    result.map(|audio| file_manager::GeneratedAudio { warnings, ..audio })
+}
+
+/// Write finished audio where `ttsaudio://<id>` can stream it. The file
+/// lives until the window releases it (`release_audio`) or the next launch.
+async fn deliver_audio(
+    files: &file_manager::FileManager,
+    tts_service: &tts::TTSService,
+    id: &str,
+    audio_data: Vec<u8>,
+    characters: usize,
+    inline: bool,
+) -> Result<file_manager::GeneratedAudio, i18n::CommandError> {
+    let estimated_secs = characters as f64 / tts::CHARACTERS_PER_SECOND;
+    Ok(files.deliver(id, audio_data, &tts_service.response_format(), inline, estimated_secs).await?)
+}
+
+/// Delete a generated audio file once the window has stopped playing it
+#[tauri::command]
+async fn release_audio(path: std::path::PathBuf, files: tauri::State<'_, file_manager::FileManager>) -> Result<(), String> {
+    files.remove(&path).await.map_err(|e| e.to_string())
+}
+
+/// Generated audio on disk, each with a quick check that it is still what
+/// was written
+#[tauri::command]
+async fn list_audio_files(files: tauri::State<'_, file_manager::FileManager>) -> Result<Vec<integrity::AudioFile>, String> {
+    files.list().await.map_err(|e| e.to_string())
+}
+
+/// Read all saved audio, project audio included, and compare it with the
+/// checksums it was written with
+#[tauri::command]
+async fn verify_library(files: tauri::State<'_, file_manager::FileManager>) -> Result<Vec<integrity::AudioFile>, String> {
+    files.verify_library().await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_user_info(
+    api_key: Option<String>,
+    tts_service: tauri::State<'_, tts::TTSService>,
+) -> Result<database::UserInfo, i18n::CommandError> {
+    recent_logs::correlated_localized("get_user_info", async {
+        // With a key of its own, only what that key paid for
+        Ok(match tts::key_override(api_key.as_deref()) {
+            Some(api_key) => tts_service.with_api_key(api_key).get_account_info().await?,
+            None => tts_service.get_user_info().await?,
+        })
+    })
+    .await
+}
+
+/// Voices for the picker, optionally only those `model` can use
+#[tauri::command]
+fn get_available_voices(
+    model: Option<String>,
+    files: tauri::State<'_, file_manager::FileManager>,
+) -> Result<Vec<voices::AvailableVoice>, String> {
+    if let Some(model) = model.as_deref().filter(|model| !tts::VALID_MODEL_IDS.contains(model)) {
+        return Err(format!("Invalid model: {}", model));
+    }
+    Ok(voices::for_model(model.as_deref())
+        .map(|info| voices::AvailableVoice { info, preview_cached: files.find(&voices::preview_id(info.id)).is_some() })
+        .collect())
+}
+
+/// Every voice with its requests, characters and last use over the last
+/// `days` days, the most used first, so the picker can float them to the
+/// top. Retired voices still in the history come last, flagged `legacy`.
+#[tauri::command]
+async fn get_voice_usage_summary(
+    days: i32,
+    tts_service: tauri::State<'_, tts::TTSService>,
+) -> Result<Vec<voices::VoiceUsageSummary>, String> {
+    let database = tts_service.database().ok_or("Database not available")?;
+    let usage = database.get_voice_usage(days).await.map_err(|e| e.to_string())?;
+    Ok(voices::usage_summary(&usage))
+}
+
+/// The voice to switch to for text in `language` (a BCP 47 tag such as
+/// "en-GB"), which is `voice_id` unless another has that accent natively
+#[tauri::command]
+fn voice_for_language(language: String, voice_id: String) -> Result<&'static voices::VoiceInfo, i18n::CommandError> {
+    let current = voices::find(&voice_id).ok_or_else(|| i18n::invalid_voice(&voice_id))?;
+    Ok(voices::for_language(language.trim(), current))
+}
+
+/// The speech models with their prices and limits
+#[tauri::command]
+fn get_available_models() -> Vec<models::ModelInfo> {
+    models::MODELS.to_vec()
+}
+
+/// Cost of what was generated in `period`, e.g. "month", split by model;
+/// `profile`'s only when given
+#[tauri::command]
+async fn get_total_spend(
+    period: spend::SpendPeriod,
+    profile: Option<String>,
+    tts_service: tauri::State<'_, tts::TTSService>,
+) -> Result<spend::SpendSummary, String> {
+    let database = tts_service.database().ok_or("Database not available")?;
+    spend::total_spend(database, &period, profile.as_deref()).await.map_err(|e| e.to_string())
+}
+
+/// Characters, requests and cost in each of the last `hours` local hours
+#[tauri::command]
+async fn get_hourly_usage(
+    hours: i32,
+    profile: Option<String>,
+    tts_service: tauri::State<'_, tts::TTSService>,
+) -> Result<Vec<spend::HourlyUsage>, String> {
+    let database = tts_service.database().ok_or("Database not available")?;
+    spend::hourly_usage(database, hours, profile.as_deref()).await.map_err(|e| e.to_string())
+}
+
+/// Requests and failures over the last `days` days by app version
+#[tauri::command]
+async fn get_failures_by_version(
+    days: i32,
+    tts_service: tauri::State<'_, tts::TTSService>,
+) -> Result<Vec<database::VersionFailures>, String> {
+    let database = tts_service.database().ok_or("Database not available")?;
+    database.get_failures_by_version(days).await.map_err(|e| e.to_string())
+}
+
+/// Bring another tool's usage history in from CSV; see `Database::import_usage_csv`
+#[tauri::command]
+async fn import_usage_csv(
+    path: String,
+    mapping: usage_import::CsvMapping,
+    dry_run: bool,
+    tts_service: tauri::State<'_, tts::TTSService>,
+) -> Result<usage_import::ImportReport, String> {
+    let database = tts_service.database().ok_or("Database not available")?;
+    database
+        .import_usage_csv(std::path::Path::new(&path), &mapping, dry_run)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_usage_stats(
+    days: i32,
+    profile: Option<String>,
+    tts_service: tauri::State<'_, tts::TTSService>,
+) -> Result<database::UsageStats, String> {
+    tts_service.get_usage_stats(days, profile.as_deref()).await.map_err(|e| e.to_string())
+}
+
+/// How far back `get_slow_requests` looks
+const SLOW_REQUEST_DAYS: i32 = 30;
+
+/// The slowest generations of the last `SLOW_REQUEST_DAYS` days that took
+/// at least `threshold_ms`, with their length and chunk count
+#[tauri::command]
+async fn get_slow_requests(
+    threshold_ms: i64,
+    limit: i32,
+    tts_service: tauri::State<'_, tts::TTSService>,
+) -> Result<Vec<database::UsageRecord>, String> {
+    let database = tts_service.database().ok_or("Database not available")?;
+    database.get_slow_requests(threshold_ms, limit, SLOW_REQUEST_DAYS).await.map_err(|e| e.to_string())
+}
+
+/// Profiles to choose from or filter by: the default one, those in the
+/// settings and any others with usage recorded
+#[tauri::command]
+async fn list_profiles(
+    settings: tauri::State<'_, settings::SettingsStore>,
+    tts_service: tauri::State<'_, tts::TTSService>,
+) -> Result<Vec<String>, String> {
+    let mut profiles = std::collections::BTreeSet::from([database::DEFAULT_PROFILE.to_string()]);
+    profiles.extend(settings.get().profiles.into_iter().map(|profile| profile.name));
+    if let Some(database) = tts_service.database() {
+        profiles.extend(database.get_profiles().await.map_err(|e| e.to_string())?);
+    }
+    Ok(profiles.into_iter().collect())
+}
+
+/// The newest `limit` generations matching `filter`, with how many match
+#[tauri::command]
+async fn get_usage_history(
+    limit: i32,
+    days: Option<i32>,
+    filter: Option<database::UsageFilter>,
+    tts_service: tauri::State<'_, tts::TTSService>,
+) -> Result<database::UsagePage, String> {
+    let database = tts_service.database().ok_or("Database not available")?;
+    let filter = filter.unwrap_or_default();
+    let filter = database::UsageFilter { days: days.or(filter.days), ..filter };
+    database.get_usage_page(&filter, limit).await.map_err(|e| e.to_string())
+}
+
+/// Lets the window show a "set your API key" state up front instead of
+/// waiting for a generation to fail
+#[tauri::command]
+fn get_service_status(tts_service: tauri::State<'_, tts::TTSService>) -> tts::ServiceStatus {
+    tts_service.status()
+}
+
+#[tauri::command]
+fn get_api_key_status(keys: tauri::State<'_, keychain::ApiKeys>) -> keychain::ApiKeyStatus {
+    keys.status()
+}
+
+/// Save the key to the OS keychain and start using it. The key is never
+/// sent back; the returned status only carries a masked form.
+#[tauri::command]
+fn set_api_key(
+    key: String,
+    keys: tauri::State<'_, keychain::ApiKeys>,
+    tts_service: tauri::State<'_, tts::TTSService>,
+) -> Result<keychain::ApiKeyStatus, String> {
+    keys.set(&key)?;
+    Ok(keys.reload(&tts_service))
+}
+
+/// Check the key, or `api_key` instead, with the organization and project
+/// from the settings, without generating anything
+#[tauri::command]
+async fn validate_api_key(
+    api_key: Option<String>,
+    tts_service: tauri::State<'_, tts::TTSService>,
+) -> Result<(), i18n::CommandError> {
+    recent_logs::correlated_localized("validate_api_key", async {
+        Ok(tts_service.for_request(api_key.as_deref()).validate_api_key().await?)
+    })
+    .await
+}
+
+/// Why generation might fail: the key, FFmpeg, Tesseract, the database,
+/// storage and the network, each checked without changing anything
+#[tauri::command]
+async fn health_check(
+    config: tauri::State<'_, config::Config>,
+    files: tauri::State<'_, file_manager::FileManager>,
+    tts_service: tauri::State<'_, tts::TTSService>,
+) -> Result<health::HealthReport, String> {
+    let library_dir = paths::library_dir(&config.data_dir);
+    let dirs = [
+        ("dataDir", config.data_dir.as_path()),
+        ("audioDir", files.dir()),
+        ("libraryDir", library_dir.as_path()),
+    ];
+    let report = health::run(
+        &tts_service,
+        &config.api_base_url,
+        &config.ffmpeg_path,
+        &config.tesseract_path,
+        &dirs,
+        health::PROBE_TIMEOUT,
+    )
+    .await;
+    for item in report.items.iter().filter(|item| item.status != health::CheckStatus::Ok) {
+        tracing::warn!("Health check {}: {}", item.name, item.detail);
+    }
+    Ok(report)
+}
+
+/// Whether requests are held back after a 429 and until when; changes
+/// arrive as `tts:throttle` events
+#[tauri::command]
+fn get_throttle_status(tts_service: tauri::State<'_, tts::TTSService>) -> throttle::ThrottleStatus {
+    tts_service.throttle().status()
+}
+
+#[tauri::command]
+fn clear_api_key(
+    keys: tauri::State<'_, keychain::ApiKeys>,
+    tts_service: tauri::State<'_, tts::TTSService>,
+) -> Result<keychain::ApiKeyStatus, String> {
+    keys.clear()?;
+    Ok(keys.reload(&tts_service))
+}
+
+type Jobs = jobs::JobManager<tts::TTSService>;
+
+/// Finished generations kept to replay, when there is a database
+type Recent = Option<Arc<recent::RecentGenerations>>;
+
+/// Queue a generation; progress arrives as `job:changed` events
+#[tauri::command]
+async fn enqueue_generation(
+    options: jobs::GenerationOptions,
+    jobs: tauri::State<'_, Jobs>,
+    settings: tauri::State<'_, settings::SettingsStore>,
+    tts_service: tauri::State<'_, tts::TTSService>,
+) -> Result<jobs::JobId, i18n::CommandError> {
+    let settings = settings.get();
+    let resolved = resolve::resolve(Some(options.voice_id.as_str()), options.model.as_deref(), &settings)?;
+    let budgeted = apply_budget(&tts_service, &settings, &resolved.model, &options.text).await?;
+    let options = options.queued(resolved, budgeted.model, budgeted.downgrade, &settings);
+    Ok(jobs.enqueue(options, &settings.model)?)
+}
+
+/// Queue several snippets at once, each as its own job. Progress arrives
+/// as `batch:item` events, then `batch:finished`; a snippet that can't be
+/// generated fails alone.
+#[tauri::command]
+async fn generate_batch_command(
+    items: Vec<snippets::Snippet>,
+    jobs: tauri::State<'_, Jobs>,
+    batches: tauri::State<'_, snippets::Batches>,
+    settings: tauri::State<'_, settings::SettingsStore>,
+    tts_service: tauri::State<'_, tts::TTSService>,
+) -> Result<snippets::BatchId, i18n::CommandError> {
+    let settings = settings.get();
+    let mut prepared = Vec::with_capacity(items.len());
+    for item in &items {
+        let options = match snippets::prepare(item, &settings) {
+            Ok(options) => options,
+            Err(e) => {
+                prepared.push(Err(e));
+                continue;
+            }
+        };
+        let model = options.model.clone().unwrap_or_else(|| settings.model.clone());
+        prepared.push(match apply_budget(&tts_service, &settings, &model, &options.text).await {
+            Ok(budgeted) => {
+                Ok(jobs::GenerationOptions { model: Some(budgeted.model), downgrade: budgeted.downgrade, ..options })
+            }
+            Err(e) => Err(e.message),
+        });
+    }
+    Ok(batches.start(&jobs, prepared, &settings.model)?)
+}
+
+/// Generate a document as a numbered file per section in the `destination`
+/// directory, with a manifest listing them. Each section is its own job,
+/// its `job:changed` events carrying the manifest's parent id; one section
+/// failing doesn't stop the others.
+#[tauri::command]
+async fn generate_sections(
+    request: sections::SectionRequest,
+    destination: std::path::PathBuf,
+    jobs: tauri::State<'_, Jobs>,
+    settings: tauri::State<'_, settings::SettingsStore>,
+    files: tauri::State<'_, file_manager::FileManager>,
+    tts_service: tauri::State<'_, tts::TTSService>,
+) -> Result<sections::SectionOutput, i18n::CommandError> {
+    recent_logs::correlated_localized("generate_sections", async {
+        let settings = settings.get();
+        let min_chars = request.min_section_chars.unwrap_or(sections::DEFAULT_MIN_CHARS);
+        let parts = sections::split(&request.text, &request.split_on, min_chars)?;
+        let resolved = resolve::resolve(request.voice_id.as_deref(), request.model.as_deref(), &settings)?;
+        // Budgeted as a whole so every section is read with the same model
+        let budgeted = apply_budget(&tts_service, &settings, &resolved.model, &request.text).await?;
+        let options = jobs::GenerationOptions {
+            text: String::new(),
+            voice_id: resolved.voice_id.clone(),
+            model: Some(budgeted.model),
+            title: None,
+            source: None,
+            // A section kept for later would never reach the destination
+            queue_if_offline: Some(false),
+            downgrade: budgeted.downgrade,
+            resolved: Some(resolved),
+            sentence_gap_ms: Some(settings.sentence_gap_ms),
+            paragraph_gap_ms: Some(settings.paragraph_gap_ms),
+            parent_id: None,
+            trim_silence: false,
+        };
+
+        files.remember_export(&destination);
+        let format = output_format::for_window(&settings.response_format);
+        let output = sections::generate(&jobs, parts, options, &settings.model, &destination, format).await?;
+        tracing::info!(
+            "Generated {} of {} sections to {}",
+            output.files.len(),
+            output.manifest.sections.len(),
+            destination.display()
+        );
+        Ok(output)
+    })
+    .await
+}
+
+/// Every item of a batch so far, with a playable path for each one done
+#[tauri::command]
+fn get_batch_result(
+    batch_id: String,
+    batches: tauri::State<'_, snippets::Batches>,
+) -> Result<snippets::BatchResult, String> {
+    batches.result(&batch_id).ok_or_else(|| format!("Unknown batch: {}", batch_id))
+}
+
+/// Start no new jobs until `resume_queue`; running ones finish and queued
+/// ones keep their place. It stays paused across restarts.
+#[tauri::command]
+async fn pause_queue(
+    app: tauri::AppHandle,
+    jobs: tauri::State<'_, Jobs>,
+    tts_service: tauri::State<'_, tts::TTSService>,
+) -> Result<jobs::QueueStatus, String> {
+    jobs.pause();
+    Ok(queue_changed(&app, &jobs, &tts_service).await)
+}
+
+#[tauri::command]
+async fn resume_queue(
+    app: tauri::AppHandle,
+    jobs: tauri::State<'_, Jobs>,
+    tts_service: tauri::State<'_, tts::TTSService>,
+) -> Result<jobs::QueueStatus, String> {
+    jobs.resume();
+    Ok(queue_changed(&app, &jobs, &tts_service).await)
+}
+
+#[tauri::command]
+fn get_queue_status(jobs: tauri::State<'_, Jobs>) -> jobs::QueueStatus {
+    jobs.queue_status()
+}
+
+/// Save whether the queue is paused and send it to the window as `queue:changed`
+async fn queue_changed(app: &tauri::AppHandle, jobs: &Jobs, tts_service: &tts::TTSService) -> jobs::QueueStatus {
+    let status = jobs.queue_status();
+    if let Some(database) = tts_service.database() {
+        if let Err(e) = database.set_state(jobs::PAUSED_STATE_KEY, &status.paused).await {
+            tracing::warn!("Failed to save the queue state: {}", e);
+        }
+    }
+    emit_to_window(app, "queue:changed", &status);
+    status
+}
+
+/// Jobs that were generating when the app last quit or crashed, with how
+/// far they got, to offer to finish them
+#[tauri::command]
+async fn list_recoverable_jobs(jobs: tauri::State<'_, Jobs>) -> Result<Vec<recovery::RecoverableJob>, String> {
+    jobs.recoverable().await
+}
+
+/// Queue an interrupted job again under its id; it carries on from the
+/// first chunk not done and reports as `job:changed` events like any other
+#[tauri::command]
+async fn recover_job(job_id: String, jobs: tauri::State<'_, Jobs>) -> Result<jobs::JobId, String> {
+    jobs.recover(&job_id).await
+}
+
+/// Delete an interrupted job and its chunks rather than finish it
+#[tauri::command]
+async fn discard_recoverable_job(job_id: String, jobs: tauri::State<'_, Jobs>) -> Result<(), String> {
+    jobs.discard(&job_id).await
+}
+
+#[tauri::command]
+fn get_job_status(job_id: String, jobs: tauri::State<'_, Jobs>) -> Result<jobs::JobInfo, String> {
+    jobs.status(&job_id).ok_or_else(|| format!("Unknown job: {}", job_id))
+}
+
+/// Where a job has got, from the job manager rather than its events, for a
+/// window that may have missed them; see `jobs::GenerationStatus`
+#[tauri::command]
+fn get_generation_status(job_id: String, jobs: tauri::State<'_, Jobs>) -> Result<jobs::GenerationStatus, String> {
+    jobs.generation_status(&job_id).ok_or_else(|| format!("Unknown job: {}", job_id))
+}
+
+#[tauri::command]
+fn list_jobs(jobs: tauri::State<'_, Jobs>) -> Vec<jobs::JobInfo> {
+    jobs.list()
+}
+
+/// Cancel a queued or running job, or stop sending a finished one's audio
+/// (see `send_job_result`)
+#[tauri::command]
+fn cancel_job(job_id: String, jobs: tauri::State<'_, Jobs>, deliveries: tauri::State<'_, segments::Deliveries>) -> bool {
+    jobs.cancel(&job_id) || deliveries.cancel(&job_id)
+}
+
+/// The finished job's audio (see `deliver_audio`); only works once per job,
+/// though a job whose audio couldn't be delivered keeps it to try again
+#[tauri::command]
+async fn take_job_result(
+    job_id: String,
+    inline: Option<bool>,
+    jobs: tauri::State<'_, Jobs>,
+    tts_service: tauri::State<'_, tts::TTSService>,
+    files: tauri::State<'_, file_manager::FileManager>,
+) -> Result<file_manager::GeneratedAudio, i18n::CommandError> {
+    recent_logs::correlated_localized("take_job_result", async {
+        let job = jobs.status(&job_id);
+        let characters = job.as_ref().map_or(0, |job| job.characters);
+        let audio_data = jobs.result(&job_id)?;
+        let audio = deliver_audio(&files, &tts_service, &job_id, audio_data, characters, inline.unwrap_or(false)).await?;
+        jobs.forget(&job_id);
+        let Some(job) = job else {
+            return Ok(audio);
+        };
+        let audio = audio.downgraded(job.downgrade.as_ref());
+        Ok(file_manager::GeneratedAudio { warnings: job.warnings, resolved: job.resolved, gaps: Some(job.gaps), ..audio })
+    })
+    .await
+}
+
+/// The finished job's audio as `audio:segment` events of base64 and then
+/// `audio:segments-done` with its checksum, for a webview where one large
+/// reply is too much for IPC (see `segments`). Resolves with the done event
+/// once everything is sent; `cancel_job` stops it part way, with
+/// `audio:segments-aborted`, and the audio stays to ask for again.
+#[tauri::command]
+async fn send_job_result(
+    job_id: String,
+    app: tauri::AppHandle,
+    jobs: tauri::State<'_, Jobs>,
+    deliveries: tauri::State<'_, segments::Deliveries>,
+    settings: tauri::State<'_, settings::SettingsStore>,
+    tts_service: tauri::State<'_, tts::TTSService>,
+) -> Result<segments::SegmentsDone, i18n::CommandError> {
+    recent_logs::correlated_localized("send_job_result", async {
+        let audio = jobs.result(&job_id)?;
+        let per_second = settings.get().segments_per_second;
+        let done = deliveries
+            .send(&job_id, &audio, &tts_service.response_format(), per_second, |event| {
+                // Straight out rather than through the relay: the window
+                // asked, so it's listening
+                let emitted = match event {
+                    segments::SegmentEvent::Segment(segment) => app.emit("audio:segment", segment),
+                    segments::SegmentEvent::Done(done) => app.emit("audio:segments-done", done),
+                    segments::SegmentEvent::Aborted { job_id } => app.emit("audio:segments-aborted", job_id),
+                };
+                if let Err(e) = emitted {
+                    tracing::warn!("Failed to emit audio segments: {}", e);
+                }
+            })
+            .await?;
+        jobs.forget(&job_id);
+        Ok(done)
+    })
+    .await
+}
+
+/// The generations kept to play again, newest first
+#[tauri::command]
+async fn get_recent_generations(
+    limit: Option<usize>,
+    recent: tauri::State<'_, Recent>,
+) -> Result<Vec<recent::RecentGeneration>, String> {
+    let recent = recent.inner().as_ref().ok_or("The database is unavailable")?;
+    recent.list(limit.unwrap_or(recent::MAX_KEEP as usize)).await.map_err(|e| e.to_string())
+}
+
+/// Play a kept generation again from its file, without paying for it. If
+/// the file has gone this fails with `error.replay_missing`, unless
+/// `regenerate` confirms generating it again as a job to follow.
+#[tauri::command]
+async fn replay_generation(
+    id: String,
+    regenerate: Option<bool>,
+    inline: Option<bool>,
+    recent: tauri::State<'_, Recent>,
+    jobs: tauri::State<'_, Jobs>,
+    settings: tauri::State<'_, settings::SettingsStore>,
+    files: tauri::State<'_, file_manager::FileManager>,
+    tts_service: tauri::State<'_, tts::TTSService>,
+) -> Result<recent::Replay, i18n::CommandError> {
+    recent_logs::correlated_localized("replay_generation", async {
+        let store = recent.inner().as_ref().ok_or("The database is unavailable")?;
+        let (kept, audio) =
+            store.load(&id).await.map_err(|e| e.to_string())?.ok_or_else(|| format!("Unknown generation: {}", id))?;
+        if let Some(audio) = audio {
+            let estimated_secs = kept.text.chars().count() as f64 / tts::CHARACTERS_PER_SECOND;
+            // Delivered as a copy under a new name, for the window to
+            // release after playing like any other
+            let copy = uuid::Uuid::new_v4().to_string();
+            let audio = files.deliver(&copy, audio, &kept.format, inline.unwrap_or(false), estimated_secs).await?;
+            return Ok(recent::Replay::Audio { audio: Box::new(audio) });
+        }
+        if !regenerate.unwrap_or(false) {
+            return Err(i18n::replay_missing().into());
+        }
+        let options = jobs::GenerationOptions {
+            text: kept.text,
+            voice_id: kept.voice_id,
+            model: Some(kept.model),
+            title: kept.title,
+            source: None,
+            queue_if_offline: None,
+            downgrade: None,
+            resolved: None,
+            sentence_gap_ms: None,
+            paragraph_gap_ms: None,
+            parent_id: None,
+            trim_silence: false,
+        };
+        let job_id = enqueue_generation(options, jobs, settings, tts_service).await?;
+        // The new job is kept in its place once it finishes
+        store.forget(&id).await.map_err(|e| e.to_string())?;
+        Ok(recent::Replay::Regenerating { job_id })
+    })
+    .await
+}
+
+/// Generations waiting for the connection, in the order they will run
+#[tauri::command]
+async fn list_pending_jobs(
+    tts_service: tauri::State<'_, tts::TTSService>,
+) -> Result<Vec<database::PendingJob>, String> {
+    let database = tts_service.database().ok_or("The database is unavailable")?;
+    database.get_pending_jobs().await.map_err(|e| e.to_string())
+}
+
+/// Run the waiting generations in the order of `ids`, which must list each once
+#[tauri::command]
+async fn reorder_pending_jobs(
+    ids: Vec<i64>,
+    app: tauri::AppHandle,
+    tts_service: tauri::State<'_, tts::TTSService>,
+) -> Result<Vec<database::PendingJob>, String> {
+    let database = tts_service.database().ok_or("The database is unavailable")?;
+    database.reorder_pending_jobs(&ids).await.map_err(|e| e.to_string())?;
+    pending_jobs_changed(&app, database).await
+}
+
+#[tauri::command]
+async fn delete_pending_job(
+    id: i64,
+    app: tauri::AppHandle,
+    tts_service: tauri::State<'_, tts::TTSService>,
+) -> Result<bool, String> {
+    let database = tts_service.database().ok_or("The database is unavailable")?;
+    let deleted = database.delete_pending_job(id).await.map_err(|e| e.to_string())?;
+    pending_jobs_changed(&app, database).await?;
+    Ok(deleted)
+}
+
+/// Send the waiting generations to the window as `pending:changed`
+async fn pending_jobs_changed(
+    app: &tauri::AppHandle,
+    database: &database::Database,
+) -> Result<Vec<database::PendingJob>, String> {
+    let pending = database.get_pending_jobs().await.map_err(|e| e.to_string())?;
+    emit_to_window(app, "pending:changed", &pending);
+    Ok(pending)
+}
+
+/// Payload of `pending:finished`: a waiting generation that has now run
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PendingJobFinished {
+    job: database::PendingJob,
+    audio: Option<file_manager::GeneratedAudio>,
+    error: Option<String>,
+}
+
+/// Payload of `batch:item`: one snippet of a batch changed
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BatchItemChanged<'a> {
+    batch_id: &'a str,
+    #[serde(flatten)]
+    item: &'a snippets::BatchItem,
+}
+
+/// Save each finished snippet of a batch as `take_job_result` would
+fn deliver_batch_audio(app: tauri::AppHandle) -> snippets::Deliver {
+    Arc::new(move |job_id, audio_data, characters| {
+        let app = app.clone();
+        Box::pin(async move {
+            let files = app.state::<file_manager::FileManager>();
+            let tts_service = app.state::<tts::TTSService>();
+            deliver_audio(&files, &tts_service, &job_id, audio_data, characters, false).await.map_err(|e| e.message)
+        })
+    })
+}
+
+/// Save jobs that fail for want of a connection as pending jobs, when the
+/// job or the `queue_when_offline` setting asks for it
+fn offline_queue(app: tauri::AppHandle, tts_service: tts::TTSService) -> jobs::OfflineQueue {
+    Arc::new(move |options: &jobs::GenerationOptions, model: &str, error: &tts::TTSError| {
+        let setting = app.state::<settings::SettingsStore>().get().queue_when_offline;
+        let database = tts_service
+            .database()
+            .filter(|_| offline::should_queue(error, options.queue_if_offline, setting))
+            .cloned();
+        save_pending_job(app.clone(), database, options.clone(), model.to_string())
+    })
+}
+
+/// Keep each job that succeeds to replay, as many as the
+/// `recent_generations` setting says
+fn keep_recent(
+    app: tauri::AppHandle,
+    recent: Arc<recent::RecentGenerations>,
+    tts_service: tts::TTSService,
+) -> jobs::FinishedListener {
+    Arc::new(move |job: &jobs::JobInfo, options: &jobs::GenerationOptions, audio: &[u8]| {
+        let keep = app.state::<settings::SettingsStore>().get().recent_generations as usize;
+        let (recent, job, text, format) = (recent.clone(), job.clone(), options.text.clone(), tts_service.response_format());
+        let audio = if keep > 0 { audio.to_vec() } else { Vec::new() };
+        Box::pin(async move {
+            if let Err(e) = recent.keep(&job, &text, &format, &audio, keep).await {
+                tracing::warn!("Failed to keep the generation to replay: {}", e);
+            }
+        })
+    })
+}
+
+/// Retry jobs that fail for a network error as the `network_retry` setting
+/// says, read afresh for each failure
+fn retry_policy(app: tauri::AppHandle) -> jobs::RetryPolicySource {
+    Arc::new(move || app.state::<settings::SettingsStore>().get().retry_policy())
+}
+
+fn save_pending_job(
+    app: tauri::AppHandle,
+    database: Option<database::Database>,
+    options: jobs::GenerationOptions,
+    model: String,
+) -> futures::future::BoxFuture<'static, Option<i64>> {
+    Box::pin(async move {
+        let database = database?;
+        let source = options.source.unwrap_or(database::SOURCE_APP);
+        match database.add_pending_job(&options.text, &options.voice_id, &model, source).await {
+            Ok(id) => {
+                tracing::info!("No connection; saved as pending job {}", id);
+                let _ = pending_jobs_changed(&app, &database).await;
+                Some(id)
+            }
+            Err(e) => {
+                tracing::warn!("Failed to save pending job: {}", e);
+                None
+            }
+        }
+    })
+}
+
+/// Every `offline::PROBE_INTERVAL`, if generations are waiting and the API
+/// answers, run them and send each result as `pending:finished`
+async fn flush_pending_jobs(app: &tauri::AppHandle) {
+    let tts_service = app.state::<tts::TTSService>().inner().clone();
+    let Some(database) = tts_service.database().cloned() else {
+        return;
+    };
+    let mut interval = tokio::time::interval(offline::PROBE_INTERVAL);
+    loop {
+        interval.tick().await;
+        match database.next_pending_job().await {
+            Ok(Some(_)) => {}
+            Ok(None) => continue,
+            Err(e) => {
+                tracing::warn!("Failed to read pending jobs: {}", e);
+                continue;
+            }
+        }
+        if !tts_service.is_reachable().await {
+            continue;
+        }
+
+        tracing::info!("Connection is back; running pending jobs");
+        let flushed = offline::flush(&tts_service, &database, |job, result| {
+            let (app, job) = (app.clone(), job.clone());
+            let result = result.map_err(|e| e.to_string());
+            tauri::async_runtime::spawn(async move {
+                let files = app.state::<file_manager::FileManager>();
+                let tts_service = app.state::<tts::TTSService>();
+                let id = uuid::Uuid::new_v4().to_string();
+                let characters = job.text.chars().count();
+                let delivered = match result {
+                    Ok(audio_data) => deliver_audio(&files, &tts_service, &id, audio_data, characters, false)
+                        .await
+                        .map_err(|e| e.message),
+                    Err(e) => Err(e),
+                };
+                let (audio, error) = match delivered {
+                    Ok(audio) => (Some(audio), None),
+                    Err(e) => (None, Some(e)),
+                };
+                emit_to_window(&app, "pending:finished", &PendingJobFinished { job, audio, error });
+            });
+        })
+        .await;
+        match flushed {
+            Ok(flushed) => tracing::info!(
+                "Pending jobs: {} done, {} failed{}",
+                flushed.completed,
+                flushed.failed,
+                if flushed.interrupted { ", offline again" } else { "" }
+            ),
+            Err(e) => tracing::warn!("Failed to run pending jobs: {}", e),
+        }
+        let _ = pending_jobs_changed(app, &database).await;
+        refresh_tray(app).await;
+    }
+}
+
+/// The latest log lines, only those from one command or job if
+/// `correlation_id` is given (the `ref` in an error, or a job id)
+#[tauri::command]
+fn get_recent_logs(
+    correlation_id: Option<String>,
+    logs: tauri::State<'_, recent_logs::RecentLogs>,
+    tts_service: tauri::State<'_, tts::TTSService>,
+) -> Vec<recent_logs::LogLine> {
+    logs.lines(correlation_id.as_deref())
+        .into_iter()
+        .map(|line| recent_logs::LogLine { message: tts_service.redact(&line.message), ..line })
+        .collect()
+}
+
+#[tauri::command]
+async fn create_project(name: String, tts_service: tauri::State<'_, tts::TTSService>) -> Result<i64, String> {
+    let database = tts_service.database().ok_or("The database is unavailable")?;
+    if name.trim().is_empty() {
+        return Err("Project name cannot be empty".to_string());
+    }
+    database.create_project(name.trim()).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn list_projects(tts_service: tauri::State<'_, tts::TTSService>) -> Result<Vec<database::Project>, String> {
+    let database = tts_service.database().ok_or("The database is unavailable")?;
+    database.get_projects().await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn rename_project(
+    project_id: i64,
+    name: String,
+    tts_service: tauri::State<'_, tts::TTSService>,
+) -> Result<bool, String> {
+    let database = tts_service.database().ok_or("The database is unavailable")?;
+    if name.trim().is_empty() {
+        return Err("Project name cannot be empty".to_string());
+    }
+    database.rename_project(project_id, name.trim()).await.map_err(|e| e.to_string())
+}
+
+/// Delete a project and its items; generated audio stays cached
+#[tauri::command]
+async fn delete_project(project_id: i64, tts_service: tauri::State<'_, tts::TTSService>) -> Result<bool, String> {
+    let database = tts_service.database().ok_or("The database is unavailable")?;
+    database.delete_project(project_id).await.map_err(|e| e.to_string())
+}
+
+/// A project's items in reading order
+#[tauri::command]
+async fn get_project_items(
+    project_id: i64,
+    tts_service: tauri::State<'_, tts::TTSService>,
+) -> Result<Vec<database::ProjectItem>, String> {
+    let database = tts_service.database().ok_or("The database is unavailable")?;
+    database.get_project_items(project_id).await.map_err(|e| e.to_string())
+}
+
+/// Add an item at the end of the project
+#[tauri::command]
+async fn add_project_item(
+    project_id: i64,
+    item: projects::ItemContent,
+    tts_service: tauri::State<'_, tts::TTSService>,
+) -> Result<i64, String> {
+    let database = tts_service.database().ok_or("The database is unavailable")?;
+    item.validate()?;
+    if database.get_project(project_id).await.map_err(|e| e.to_string())?.is_none() {
+        return Err(format!("Unknown project: {}", project_id));
+    }
+    database
+        .add_project_item(project_id, &item.title, &item.text, item.voice_id.as_deref(), item.model_id.as_deref())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn update_project_item(
+    item_id: i64,
+    item: projects::ItemContent,
+    tts_service: tauri::State<'_, tts::TTSService>,
+) -> Result<bool, String> {
+    let database = tts_service.database().ok_or("The database is unavailable")?;
+    item.validate()?;
+    let item = database::ProjectItem {
+        id: item_id,
+        // Not changed by an update
+        project_id: 0,
+        position: 0,
+        title: item.title,
+        text: item.text,
+        voice_id: item.voice_id,
+        model_id: item.model_id,
+    };
+    database.update_project_item(&item).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn delete_project_item(item_id: i64, tts_service: tauri::State<'_, tts::TTSService>) -> Result<bool, String> {
+    let database = tts_service.database().ok_or("The database is unavailable")?;
+    database.delete_project_item(item_id).await.map_err(|e| e.to_string())
+}
+
+/// Put the project's items in the order of `ids`, which must list each once
+#[tauri::command]
+async fn reorder_project_items(
+    project_id: i64,
+    ids: Vec<i64>,
+    tts_service: tauri::State<'_, tts::TTSService>,
+) -> Result<Vec<database::ProjectItem>, String> {
+    let database = tts_service.database().ok_or("The database is unavailable")?;
+    database.reorder_project_items(project_id, &ids).await.map_err(|e| e.to_string())?;
+    database.get_project_items(project_id).await.map_err(|e| e.to_string())
+}
+
+/// Project audio is kept across runs so exports don't generate it again
+fn project_cache(config: &config::Config) -> projects::ProjectCache {
+    projects::ProjectCache::new(paths::library_dir(&config.data_dir))
+}
+
+fn project_defaults(settings: &settings::Settings) -> projects::ItemDefaults {
+    let resolved = resolve::defaults(settings);
+    projects::ItemDefaults {
+        voice_id: resolved.voice_id,
+        model_id: resolved.model,
+        speed: settings.speed,
+        format: output_format::for_window(&settings.response_format).to_string(),
+    }
+}
+
+/// Generate every item of a project that has no audio yet, reporting each
+/// item as `project:progress`. One failing item doesn't stop the others.
+#[tauri::command]
+async fn generate_project(
+    project_id: i64,
+    app: tauri::AppHandle,
+    config: tauri::State<'_, config::Config>,
+    settings: tauri::State<'_, settings::SettingsStore>,
+    files: tauri::State<'_, file_manager::FileManager>,
+    tts_service: tauri::State<'_, tts::TTSService>,
+) -> Result<Vec<projects::ItemResult>, i18n::CommandError> {
+    recent_logs::correlated("generate_project", async {
+        let database = tts_service.database().ok_or("The database is unavailable")?;
+        let items = database.get_project_items(project_id).await.map_err(|e| e.to_string())?;
+        let settings = settings.get();
+        let defaults = project_defaults(&settings);
+        // The speed is part of what the cached audio is kept by
+        let service = tts_service.at_speed(defaults.speed).with_usage_source(database::SOURCE_PROJECT);
+        let backend = spend::WithinBudget::new(service, settings);
+        let results = projects::generate(
+            &backend,
+            &items,
+            &defaults,
+            &project_cache(&config),
+            |progress| emit_to_window(&app, "project:progress", progress),
+        )
+        .await;
+        for path in results.iter().filter(|result| !result.cached).filter_map(|result| result.path.as_ref()) {
+            if let Err(e) = files.track(path).await {
+                tracing::warn!("Failed to record the checksum of {}: {}", path.display(), e);
+            }
+        }
+        tracing::info!(
+            "Generated project {}: {} of {} items failed",
+            project_id,
+            results.iter().filter(|result| result.error.is_some()).count(),
+            results.len()
+        );
+        Ok(results)
+    })
+    .await
+}
+
+/// Export a generated project: as numbered files in the `destination`
+/// directory, or as the single `destination` file with a chapter per item.
+/// The files written, in reading order.
+#[tauri::command]
+async fn export_project(
+    project_id: i64,
+    mode: projects::ExportMode,
+    destination: std::path::PathBuf,
+    config: tauri::State<'_, config::Config>,
+    settings: tauri::State<'_, settings::SettingsStore>,
+    files: tauri::State<'_, file_manager::FileManager>,
+    tts_service: tauri::State<'_, tts::TTSService>,
+) -> Result<Vec<std::path::PathBuf>, i18n::CommandError> {
+    recent_logs::correlated("export_project", async {
+        let database = tts_service.database().ok_or("The database is unavailable")?;
+        let project = database
+            .get_project(project_id)
+            .await
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("Unknown project: {}", project_id))?;
+        let items = database.get_project_items(project_id).await.map_err(|e| e.to_string())?;
+        let defaults = project_defaults(&settings.get());
+        let parts = projects::parts(&items, &defaults, &project_cache(&config))?;
+        for part in &parts {
+            if files.check(&part.path, true).await == integrity::AudioStatus::Corrupted {
+                // Removed so generating the project again redoes it
+                let _ = tokio::fs::remove_file(&part.path).await;
+                database.forget_checksums(&part.path, false).await.map_err(|e| e.to_string())?;
+                return Err(format!("The audio of \"{}\" was corrupted; generate the project again", part.title));
+            }
+        }
+
+        files.remember_export(&destination);
+        match mode {
+            projects::ExportMode::Separate => {
+                tokio::fs::create_dir_all(&destination)
+                    .await
+                    .map_err(|e| format!("Failed to create {}: {}", destination.display(), e))?;
+                let mut written = Vec::with_capacity(parts.len());
+                for (index, part) in parts.iter().enumerate() {
+                    let path =
+                        destination.join(projects::numbered_name(index, parts.len(), &part.title, &defaults.format));
+                    tokio::fs::copy(&part.path, &path)
+                        .await
+                        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+                    written.push(path);
+                }
+                Ok(written)
+            }
+            projects::ExportMode::Combined => {
+                let probe = parts.clone();
+                let lengths = tokio::task::spawn_blocking(move || {
+                    probe
+                        .into_iter()
+                        .map(|part| {
+                            let duration = file_manager::file_duration(&part.path)
+                                .ok_or_else(|| format!("Could not read the length of {}", part.title))?;
+                            Ok::<_, String>((part.title, duration))
+                        })
+                        .collect::<Result<Vec<_>, String>>()
+                })
+                .await
+                .map_err(|e| e.to_string())??;
+                let metadata = tempfile::Builder::new()
+                    .prefix("project-chapters")
+                    .suffix(".txt")
+                    .tempfile()
+                    .map_err(|e| format!("Failed to write chapters: {}", e))?;
+                tokio::fs::write(metadata.path(), projects::ffmetadata(&project.name, &projects::chapters(&lengths)))
+                    .await
+                    .map_err(|e| format!("Failed to write chapters: {}", e))?;
+
+                let format = destination.extension().and_then(|ext| ext.to_str()).unwrap_or(&defaults.format);
+                let partial = destination.with_extension(format!("partial-{}.{}", uuid::Uuid::new_v4(), format));
+                let inputs: Vec<std::path::PathBuf> = parts.into_iter().map(|part| part.path).collect();
+                let output = tokio::process::Command::new(&config.ffmpeg_path)
+                    .args(projects::combined_args(&inputs, metadata.path(), &partial))
+                    .output()
+                    .await
+                    .map_err(|e| format!("Failed to run ffmpeg ({}): {}", config.ffmpeg_path, e))?;
+                if !output.status.success() {
+                    let _ = tokio::fs::remove_file(&partial).await;
+                    let stderr = String::from_utf8_lossy(&output.stderr);
+                    tracing::error!("FFmpeg failed with stderr: {}", stderr);
+                    return Err(format!("ffmpeg failed: {}", stderr.trim()));
+                }
+                tokio::fs::rename(&partial, &destination)
+                    .await
+                    .map_err(|e| format!("Failed to write {}: {}", destination.display(), e))?;
+                Ok(vec![destination])
+            }
+        }
+    })
+    .await
+}
+
+/// Show `path` in Finder, Explorer or (on Linux) its folder. Only paths in
+/// the data directory, the audio directory or where audio was exported to
+/// this session are shown.
+#[tauri::command]
+fn reveal_in_file_manager(
+    path: std::path::PathBuf,
+    config: tauri::State<'_, config::Config>,
+    files: tauri::State<'_, file_manager::FileManager>,
+) -> Result<(), String> {
+    let mut roots = files.locations();
+    roots.push(config.data_dir.clone());
+    reveal::reveal(&path, &roots, reveal::Platform::current(), &reveal::SystemLauncher).map_err(|e| e.to_string())
+}
+
+/// Where the database, audio and the rest are kept, whether each exists and
+/// its size, for support requests
+#[tauri::command]
+async fn get_app_paths(config: tauri::State<'_, config::Config>) -> Result<Vec<paths::AppPath>, String> {
+    let paths = paths::AppPaths::new(&config.data_dir);
+    tokio::task::spawn_blocking(move || paths.report()).await.map_err(|e| e.to_string())
+}
+
+/// Open the data directory in the system file manager
+#[tauri::command]
+fn open_app_data_dir(config: tauri::State<'_, config::Config>) -> Result<(), String> {
+    reveal::open_dir(&config.data_dir, reveal::Platform::current(), &reveal::SystemLauncher).map_err(|e| e.to_string())
+}
+
+/// Play through the app's own player rather than the window's `<audio>`,
+/// stopping whatever it was playing
 #[tauri::command]
-async fn generate_speech(text: String, voice_id: String) -> Result<String, String> {
-    let api_key = std::env::var("OPENAI_API_KEY")
-        .map_err(|_| "OPENAI_API_KEY environment variable not set".to_string())?;
-    
-    let tts_service = tts::TTSService::with_database(&api_key, "https://api.openai.com")
-        .await
-        .map_err(|e| e.to_string())?;
-    
-    // Validate inputs
-    tts_service.validate_text(&text).await?;
-    if !tts_service.is_valid_voice(&voice_id) {
-        return Err(format!("Invalid voice ID: {}", voice_id));
+async fn play_audio(
+    source: player::PlaySource,
+    jobs: tauri::State<'_, Jobs>,
+    files: tauri::State<'_, file_manager::FileManager>,
+    player: tauri::State<'_, player::Player>,
+) -> Result<player::PlaybackStatus, i18n::CommandError> {
+    recent_logs::correlated("play_audio", async {
+        let (label, audio) = match source {
+            player::PlaySource::Job { id } => (format!("job:{}", id), jobs.take_result(&id)?),
+            player::PlaySource::Audio { id } => {
+                let path = files.find_verified(&id).await.map_err(|e| e.to_string())?;
+                let audio = tokio::fs::read(&path).await.map_err(|e| format!("Failed to read audio: {}", e))?;
+                (format!("audio:{}", id), audio)
+            }
+            player::PlaySource::Path { path } => {
+                let audio = tokio::fs::read(&path).await.map_err(|e| format!("Failed to read {}: {}", path, e))?;
+                (path, audio)
+            }
+        };
+        player.play(label, audio)
+    })
+    .await
+}
+
+/// The audio `factor` times as fast (slower below 1) without generating it
+/// again. Keeping the pitch stretches it with ffmpeg's `atempo`; otherwise
+/// it is resampled and sounds higher or lower. Results are kept per source
+/// and factor until released.
+#[tauri::command]
+async fn transform_audio_speed(
+    source: player::PlaySource,
+    factor: f64,
+    preserve_pitch: bool,
+    config: tauri::State<'_, config::Config>,
+    files: tauri::State<'_, file_manager::FileManager>,
+) -> Result<file_manager::GeneratedAudio, i18n::CommandError> {
+    recent_logs::correlated_localized("transform_audio_speed", with_warnings(async {
+        speed::validate_factor(factor)?;
+        let input = match source {
+            player::PlaySource::Audio { id } => files.find(&id).ok_or_else(|| format!("Unknown audio: {}", id))?,
+            player::PlaySource::Path { path } => std::path::PathBuf::from(path),
+            player::PlaySource::Job { id } => {
+                return Err(format!("Job {} has no file yet; take its result first", id).into());
+            }
+        };
+        let probe = input.clone();
+        let (duration, sample_rate) =
+            tokio::task::spawn_blocking(move || (file_manager::file_duration(&probe), speed::sample_rate(&probe)))
+                .await
+                .map_err(|e| e.to_string())?;
+        let estimated_secs = duration.map_or(0.0, |duration| duration.as_secs_f64() / factor);
+
+        let id = speed::cached_id(&input, factor, preserve_pitch);
+        if let Some(audio) = files.saved(&id, estimated_secs).await {
+            warnings::warn(warnings::cached());
+            return Ok(audio);
+        }
+
+        let filter = speed::speed_filter(factor, preserve_pitch, sample_rate.unwrap_or(silence::SAMPLE_RATE))?;
+        let format = input.extension().and_then(|ext| ext.to_str()).unwrap_or("mp3").to_ascii_lowercase();
+        let (ffmpeg, input) = (&config.ffmpeg_path, &input);
+        files
+            .write_via_partial(&id, &format, |partial| async move {
+                let output = tokio::process::Command::new(ffmpeg)
+                    .args(speed::ffmpeg_args(input, &partial, &filter))
+                    .output()
+                    .await
+                    .map_err(|e| format!("Failed to run ffmpeg ({}): {}", ffmpeg, e))?;
+                if !output.status.success() {
+                    let stderr = String::from_utf8_lossy(&output.stderr);
+                    tracing::error!("FFmpeg failed with stderr: {}", stderr);
+                    return Err(i18n::CommandError::from(format!("ffmpeg failed: {}", stderr.trim())));
+                }
+                Ok(())
+            })
+            .await?;
+        Ok(files.saved(&id, estimated_secs).await.ok_or("Failed to save audio")?)
+    }))
+    .await
+}
+
+/// A copy of saved audio in `target_format` (wav, flac, mp3, ogg, opus or
+/// m4a), e.g. a WAV to edit, converted by ffmpeg rather than generated again
+#[tauri::command]
+async fn transcode_audio(
+    source: player::PlaySource,
+    target_format: String,
+    options: Option<transcode::TranscodeOptions>,
+    config: tauri::State<'_, config::Config>,
+    files: tauri::State<'_, file_manager::FileManager>,
+) -> Result<file_manager::GeneratedAudio, i18n::CommandError> {
+    recent_logs::correlated_localized("transcode_audio", async {
+        let format = transcode::TargetFormat::parse(&target_format)?;
+        let input = match source {
+            player::PlaySource::Audio { id } => files.find(&id).ok_or_else(|| format!("Unknown audio: {}", id))?,
+            player::PlaySource::Path { path } => std::path::PathBuf::from(path),
+            player::PlaySource::Job { id } => {
+                return Err(format!("Job {} has no file yet; take its result first", id).into());
+            }
+        };
+        let id = uuid::Uuid::new_v4().to_string();
+        let (ffmpeg, input_path, options) = (&config.ffmpeg_path, &input, options.unwrap_or_default());
+        let path = files
+            .write_via_partial(&id, format.extension(), |partial| async move {
+                let args = transcode::ffmpeg_args(input_path, &partial, format, &options)?;
+                transcode::run(ffmpeg, &args).await?;
+                Ok::<_, i18n::CommandError>(())
+            })
+            .await?;
+
+        let probe = input.clone();
+        let duration = tokio::task::spawn_blocking(move || file_manager::file_duration(&probe))
+            .await
+            .map_err(|e| e.to_string())?;
+        let estimated_secs = duration.map_or(0.0, |duration| duration.as_secs_f64());
+        tracing::info!("Converted {} to {}", input.display(), path.display());
+        Ok(files.saved(&id, estimated_secs).await.ok_or("Failed to save audio")?)
+    })
+    .await
+}
+
+#[tauri::command]
+fn pause(player: tauri::State<'_, player::Player>) -> player::PlaybackStatus {
+    player.pause()
+}
+
+#[tauri::command]
+fn resume(player: tauri::State<'_, player::Player>) -> player::PlaybackStatus {
+    player.resume()
+}
+
+#[tauri::command]
+fn stop(player: tauri::State<'_, player::Player>) -> player::PlaybackStatus {
+    player.stop()
+}
+
+#[tauri::command]
+fn seek(seconds: f64, player: tauri::State<'_, player::Player>) -> Result<player::PlaybackStatus, String> {
+    player.seek(seconds)
+}
+
+#[tauri::command]
+fn set_volume(volume: f32, player: tauri::State<'_, player::Player>) -> Result<player::PlaybackStatus, String> {
+    player.set_volume(volume)
+}
+
+#[tauri::command]
+fn get_playback_status(player: tauri::State<'_, player::Player>) -> player::PlaybackStatus {
+    player.status()
+}
+
+/// `playback:position` for the window's progress bar while something plays,
+/// and once more when it stops
+async fn report_playback_position(app: &tauri::AppHandle) {
+    let mut interval = tokio::time::interval(player::POSITION_INTERVAL);
+    let mut last_state = player::PlayerState::Stopped;
+    loop {
+        interval.tick().await;
+        let status = app.state::<player::Player>().status();
+        if status.state == player::PlayerState::Playing || status.state != last_state {
+            if let Err(e) = app.emit("playback:position", &status) {
+                tracing::warn!("Failed to emit playback position: {}", e);
+            }
+        }
+        last_state = status.state;
     }
-    
-    // Generate speech (handles chunking internally for long text)
-    eprintln!("Generating speech for {} characters", text.len());
-    let audio_data = tts_service.generate_speech(&text, &voice_id).await
-        .map_err(|e| format!("Failed to generate speech: {}", e))?;
-    
-    // Convert audio data to base64 data URL
-    use base64::{Engine, engine::general_purpose};
-    let base64_audio = general_purpose::STANDARD.encode(&audio_data);
-    let data_url = format!("data:audio/mpeg;base64,{}", base64_audio);
-    
-    Ok(data_url)
 }
 
 #[tauri::command]
-async fn generate_speech_with_model(text: String, voice_id: String, model: String) -> Result<String, String> {
-    let api_key = std::env::var("OPENAI_API_KEY")
-        .map_err(|_| "OPENAI_API_KEY environment variable not set".to_string())?;
-    
-    let tts_service = tts::TTSService::with_database(&api_key, "https://api.openai.com")
-        .await
-        .map_err(|e| e.to_string())?;
-    
-    // Validate inputs
-    tts_service.validate_text(&text).await?;
-    if !tts_service.is_valid_voice(&voice_id) {
-        return Err(format!("Invalid voice ID: {}", voice_id));
+fn get_settings(settings: tauri::State<'_, settings::SettingsStore>) -> settings::Settings {
+    settings.get()
+}
+
+/// Merge `patch` into the saved settings (see `Settings::patched`) and tell
+/// every window through `settings:changed`
+#[tauri::command]
+async fn update_settings(
+    patch: serde_json::Value,
+    app: tauri::AppHandle,
+    settings: tauri::State<'_, settings::SettingsStore>,
+    tts_service: tauri::State<'_, tts::TTSService>,
+) -> Result<settings::Settings, String> {
+    let old = settings.get();
+    let updated = settings.update(&patch).await?;
+    settings_changed(&app, &tts_service, &old, &updated);
+    Ok(updated)
+}
+
+/// Bind the speak-clipboard hotkey to `shortcut`, or unbind it with `None`.
+/// The old binding stays if the new one is reserved or another app holds it.
+#[tauri::command]
+async fn set_speak_shortcut(
+    shortcut: Option<String>,
+    app: tauri::AppHandle,
+    settings: tauri::State<'_, settings::SettingsStore>,
+    tts_service: tauri::State<'_, tts::TTSService>,
+) -> Result<settings::Settings, String> {
+    let shortcut = shortcut.as_deref().map(shortcut::validate).transpose()?;
+    let old = settings.get();
+    rebind_speak_shortcut(&app, old.speak_shortcut.as_deref(), shortcut.as_deref())?;
+    let updated = settings.update(&serde_json::json!({ "speak_shortcut": shortcut })).await?;
+    settings_changed(&app, &tts_service, &old, &updated);
+    Ok(updated)
+}
+
+/// Swap the registered hotkey from `old` to `new`, putting `old` back if
+/// `new` can't be registered
+fn rebind_speak_shortcut(app: &tauri::AppHandle, old: Option<&str>, new: Option<&str>) -> Result<(), String> {
+    if old == new {
+        return Ok(());
+    }
+    let global = app.global_shortcut();
+    if let Some(old) = old {
+        if let Err(e) = global.unregister(old) {
+            tracing::warn!("Failed to unregister {}: {}", old, e);
+        }
+    }
+    let Some(new) = new else {
+        return Ok(());
+    };
+    if let Err(e) = global.register(new) {
+        if let Some(old) = old {
+            if let Err(e) = global.register(old) {
+                tracing::warn!("Failed to restore {}: {}", old, e);
+            }
+        }
+        return Err(format!("{} couldn't be registered; another app may be using it ({})", new, e));
+    }
+    Ok(())
+}
+
+/// Apply settings that were just saved and tell every window through `settings:changed`
+fn settings_changed(
+    app: &tauri::AppHandle,
+    tts_service: &tts::TTSService,
+    old: &settings::Settings,
+    new: &settings::Settings,
+) {
+    tts_service.apply_settings(new);
+    i18n::set_locale(&new.locale);
+    // Saved through `update_settings` or an import rather than `set_speak_shortcut`
+    if let Err(e) = rebind_speak_shortcut(app, old.speak_shortcut.as_deref(), new.speak_shortcut.as_deref()) {
+        tracing::warn!("{}", e);
+        notify(app, "Shortcut not set", &e);
+    }
+    if old.clipboard_watch != new.clipboard_watch {
+        set_clipboard_watching(app, new.clipboard_watch);
+    }
+    if old.max_concurrent_jobs != new.max_concurrent_jobs {
+        let jobs = app.state::<Jobs>();
+        jobs.set_concurrency(new.max_concurrent_jobs);
+        emit_to_window(app, "queue:changed", &jobs.queue_status());
+    }
+    if let Err(e) = app.emit("settings:changed", new) {
+        tracing::warn!("Failed to emit settings change: {}", e);
     }
-    
-    // Generate speech with specific model
-    let audio_data = tts_service.generate_speech_with_model(&text, &voice_id, &model).await?;
-    
-    // Track usage
-    let _ = tts_service.track_usage(&text, &voice_id, &model, true, None).await;
-    
-    // Convert audio data to base64 data URL that the HTML audio player can use directly
-    use base64::{Engine, engine::general_purpose};
-    let base64_audio = general_purpose::STANDARD.encode(&audio_data);
-    let data_url = format!("data:audio/mpeg;base64,{}", base64_audio);
-    
-    Ok(data_url)
 }
 
+/// Turn clipboard watch mode on or off. It stays that way across restarts.
 #[tauri::command]
-async fn get_user_info() -> Result<database::UserInfo, String> {
-    let api_key = std::env::var("OPENAI_API_KEY")
-        .map_err(|_| "OPENAI_API_KEY environment variable not set".to_string())?;
-    
-    let tts_service = tts::TTSService::with_database(&api_key, "https://api.openai.com")
-        .await
-        .map_err(|e| e.to_string())?;
-    
-    tts_service.get_user_info().await.map_err(|e| e.to_string())
+async fn set_clipboard_watch(
+    enabled: bool,
+    app: tauri::AppHandle,
+    settings: tauri::State<'_, settings::SettingsStore>,
+    tts_service: tauri::State<'_, tts::TTSService>,
+) -> Result<settings::Settings, String> {
+    let old = settings.get();
+    let updated = settings.update(&serde_json::json!({ "clipboard_watch": enabled })).await?;
+    settings_changed(&app, &tts_service, &old, &updated);
+    Ok(updated)
 }
 
+/// Show backend messages in `locale` ("de", "de-AT", ...), or in English
+/// where there are no translations for it. It stays that way across restarts.
 #[tauri::command]
-async fn get_usage_stats(days: i32) -> Result<database::UsageStats, String> {
-    let api_key = std::env::var("OPENAI_API_KEY")
-        .map_err(|_| "OPENAI_API_KEY environment variable not set".to_string())?;
-    
-    let tts_service = tts::TTSService::with_database(&api_key, "https://api.openai.com")
-        .await
-        .map_err(|e| e.to_string())?;
-    
-    tts_service.get_usage_stats(days).await.map_err(|e| e.to_string())
+async fn set_locale(
+    locale: String,
+    app: tauri::AppHandle,
+    settings: tauri::State<'_, settings::SettingsStore>,
+    tts_service: tauri::State<'_, tts::TTSService>,
+) -> Result<settings::Settings, String> {
+    let old = settings.get();
+    let updated = settings.update(&serde_json::json!({ "locale": i18n::resolve(&locale) })).await?;
+    settings_changed(&app, &tts_service, &old, &updated);
+    Ok(updated)
 }
 
+/// Write the settings to `path` for `import_settings` on another machine
 #[tauri::command]
-async fn get_usage_history(limit: i32, days: Option<i32>) -> Result<Vec<database::UsageRecord>, String> {
-    let api_key = std::env::var("OPENAI_API_KEY")
-        .map_err(|_| "OPENAI_API_KEY environment variable not set".to_string())?;
-    
-    let tts_service = tts::TTSService::with_database(&api_key, "https://api.openai.com")
-        .await
-        .map_err(|e| e.to_string())?;
-    
-    tts_service.get_usage_history(limit, days).await.map_err(|e| e.to_string())
+fn export_settings(path: std::path::PathBuf, settings: tauri::State<'_, settings::SettingsStore>) -> Result<(), String> {
+    settings.export().write(&path)
+}
+
+/// Apply settings written by `export_settings`; with `dry_run`, only report
+/// what would change
+#[tauri::command]
+async fn import_settings(
+    path: std::path::PathBuf,
+    strategy: Option<settings::MergeStrategy>,
+    dry_run: Option<bool>,
+    app: tauri::AppHandle,
+    settings: tauri::State<'_, settings::SettingsStore>,
+    tts_service: tauri::State<'_, tts::TTSService>,
+) -> Result<settings::ImportReport, i18n::CommandError> {
+    recent_logs::correlated("import_settings", async {
+        let bundle = settings::SettingsBundle::read(&path)?;
+        let dry_run = dry_run.unwrap_or(false);
+        let old = settings.get();
+        let report = settings.import(&bundle, strategy.unwrap_or_default(), dry_run).await?;
+        if !dry_run && !report.changes.is_empty() {
+            settings_changed(&app, &tts_service, &old, &report.settings);
+        }
+        Ok(report)
+    })
+    .await
+}
+
+/// The window's listeners are registered; send it what happened while it
+/// was loading
+#[tauri::command]
+fn frontend_ready(app: tauri::AppHandle, relay: tauri::State<'_, relay::EventRelay>) {
+    let replayed = relay.ready(&|event, payload| {
+        if let Err(e) = app.emit(event, payload) {
+            tracing::warn!("Failed to emit {}: {}", event, e);
+        }
+    });
+    if replayed > 0 {
+        tracing::debug!("Replayed {} events from before the window loaded", replayed);
+    }
+}
+
+#[tauri::command]
+fn take_launch_request(launch: tauri::State<'_, LaunchState>) -> Option<cli::LaunchRequest> {
+    launch.0.lock().unwrap().take()
 }
 
 #[tauri::command]
@@ -107,51 +1701,1154 @@ fn count_characters(text: String) -> i32 {
     text.len() as i32
 }
 
+/// Length, duration and cost of `text` for the summary before generating;
+/// no network or database, so it can run as the user types
 #[tauri::command]
-async fn read_clipboard(app_handle: tauri::AppHandle) -> Result<String, String> {
-    // Use Tauri's clipboard API
-    app_handle
-        .clipboard()
-        .read_text()
-        .map_err(|e| format!("Failed to read clipboard: {}", e))
+fn analyze_text(
+    text: String,
+    model: String,
+    settings: tauri::State<'_, settings::SettingsStore>,
+) -> Result<analysis::TextAnalysis, String> {
+    let settings = settings.get();
+    let text = settings.preprocessing.apply(&text);
+    analysis::analyze(&text, &model, settings.chunk_size)
 }
 
+/// Where generating `text` would split it into requests, at the chunk size
+/// in `options` or the settings'; sends and records nothing. Positions are
+/// in the text as sent, after `Preprocessing::apply`.
 #[tauri::command]
-async fn read_text_file(file_path: String) -> Result<String, String> {
-    use std::fs;
-    use std::path::Path;
-    
-    // Read the file
-    match fs::read_to_string(&file_path) {
-        Ok(content) => {
-            eprintln!("Read {} characters from file", content.len());
-            Ok(content)
+fn preview_chunks(
+    text: String,
+    options: Option<analysis::PreviewOptions>,
+    settings: tauri::State<'_, settings::SettingsStore>,
+) -> Result<Vec<analysis::ChunkPreview>, String> {
+    let settings = settings.get();
+    let chunk_size = options.and_then(|options| options.chunk_size).unwrap_or(settings.chunk_size);
+    let text = settings.preprocessing.apply(&text);
+    analysis::preview_chunks(&text, chunk_size)
+}
+
+/// The clipboard's text, converted from its HTML when a browser put some there
+#[tauri::command]
+async fn read_clipboard(app_handle: tauri::AppHandle) -> Result<clipboard::ClipboardText, String> {
+    clipboard::read(&mut PluginClipboard(&app_handle))?.ok_or_else(|| "The clipboard has no text".to_string())
+}
+
+/// Payload of `read_image_text`
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ImageText {
+    #[serde(flatten)]
+    ocr: ocr::OcrText,
+    /// The generation started with `speak`
+    job_id: Option<jobs::JobId>,
+}
+
+/// Read the text in the image file at `path`, or in the clipboard's image
+/// without one. With `speak` the text is also queued for generation, as
+/// `ocr` usage; otherwise the window shows it to check first.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn read_image_text(
+    path: Option<std::path::PathBuf>,
+    speak: Option<bool>,
+    voice_id: Option<String>,
+    app: tauri::AppHandle,
+    config: tauri::State<'_, config::Config>,
+    jobs: tauri::State<'_, Jobs>,
+    settings: tauri::State<'_, settings::SettingsStore>,
+    tts_service: tauri::State<'_, tts::TTSService>,
+) -> Result<ImageText, i18n::CommandError> {
+    recent_logs::correlated("read_image_text", async {
+        let ocr = match path {
+            Some(path) => ocr::recognize(&config.tesseract_path, &path).await?,
+            None => {
+                let image = app.clipboard().read_image().map_err(|e| {
+                    tracing::debug!("No image on the clipboard: {}", e);
+                    "The clipboard has no image".to_string()
+                })?;
+                ocr::recognize_pixels(&config.tesseract_path, image.rgba(), image.width(), image.height()).await?
+            }
+        };
+        let job_id = if speak.unwrap_or(false) {
+            let options = jobs::GenerationOptions {
+                text: ocr.text.clone(),
+                voice_id: voice_id.unwrap_or_default(),
+                model: None,
+                title: None,
+                source: Some(database::SOURCE_OCR),
+                queue_if_offline: None,
+                downgrade: None,
+                resolved: None,
+                sentence_gap_ms: None,
+                paragraph_gap_ms: None,
+                parent_id: None,
+                trim_silence: false,
+            };
+            Some(enqueue_generation(options, jobs, settings, tts_service).await?)
+        } else {
+            None
+        };
+        Ok(ImageText { ocr, job_id })
+    })
+    .await
+}
+
+/// Read a text file another app left in the temp directory for us, such as
+/// the Raycast handoff. Picked documents go through `open_text_file` instead.
+#[tauri::command]
+async fn read_text_file(
+    file_path: String,
+    delete_after: Option<bool>,
+    config: tauri::State<'_, config::Config>,
+) -> Result<documents::LoadedText, i18n::CommandError> {
+    recent_logs::correlated("read_text_file", async {
+        let path = std::path::PathBuf::from(file_path);
+        let max_text = config.max_input_bytes;
+        let loaded = tokio::task::spawn_blocking(move || {
+            documents::read_handoff_file(&paths::handoff_dir(), &path, delete_after.unwrap_or(false), max_text)
+        })
+        .await
+        .map_err(|e| e.to_string())??;
+        tracing::debug!("Read {} characters from file", loaded.character_count);
+        Ok(loaded)
+    })
+    .await
+}
+
+/// Let the user pick a document and return its text; `None` if they cancel.
+/// Unlike `read_text_file`, any location is allowed: the pick is the consent.
+#[tauri::command]
+async fn open_text_file(app: tauri::AppHandle) -> Result<Option<documents::LoadedText>, i18n::CommandError> {
+    recent_logs::correlated("open_text_file", async {
+        use tauri_plugin_dialog::DialogExt;
+
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+        app.dialog()
+            .file()
+            .add_filter("Documents", documents::EXTENSIONS)
+            .pick_file(move |file| {
+                let _ = sender.send(file);
+            });
+        let path = match receiver.await.map_err(|e| e.to_string())? {
+            Some(file) => Some(file.into_path().map_err(|e| e.to_string())?),
+            None => None,
+        };
+        let max_text = app.state::<config::Config>().max_input_bytes;
+        tokio::task::spawn_blocking(move || documents::open_selected(path.as_deref(), max_text))
+            .await
+            .map_err(|e| e.to_string())?
+            .map_err(|failure| failure.reason)
+    })
+    .await
+}
+
+/// Text of a PDF, optionally just some pages, with a cost estimate
+#[tauri::command]
+async fn extract_text_from_pdf(
+    path: std::path::PathBuf,
+    first_page: Option<usize>,
+    last_page: Option<usize>,
+    reflow: Option<bool>,
+) -> Result<extract::ExtractedText, i18n::CommandError> {
+    recent_logs::correlated("extract_text_from_pdf", async {
+        // PDF text is hard-wrapped, so it is reflowed unless asked not to be
+        let reflow = reflow.unwrap_or(true);
+        tokio::task::spawn_blocking(move || pdf::extract_text(&path, first_page, last_page, reflow))
+            .await
+            .map_err(|e| e.to_string())?
+            .map_err(|e| e.to_string())
+    })
+    .await
+}
+
+/// Text of a Word document; headers, footers and notes only if asked for
+#[tauri::command]
+async fn extract_text_from_docx(path: std::path::PathBuf, include_extras: Option<bool>) -> Result<docx::DocxText, i18n::CommandError> {
+    recent_logs::correlated("extract_text_from_docx", async {
+        tokio::task::spawn_blocking(move || docx::extract_text(&path, include_extras.unwrap_or(false)))
+            .await
+            .map_err(|e| e.to_string())?
+            .map_err(|e| e.to_string())
+    })
+    .await
+}
+
+/// A book's chapters with their titles and cost estimates
+#[tauri::command]
+async fn extract_text_from_epub(path: std::path::PathBuf) -> Result<epub::EpubBook, i18n::CommandError> {
+    recent_logs::correlated("extract_text_from_epub", async {
+        tokio::task::spawn_blocking(move || epub::list_chapters(&path))
+            .await
+            .map_err(|e| e.to_string())?
+            .map_err(|e| e.to_string())
+    })
+    .await
+}
+
+/// Text of the chosen chapters (indices from `extract_text_from_epub`)
+#[tauri::command]
+async fn extract_epub_chapters(path: std::path::PathBuf, chapters: Vec<usize>) -> Result<epub::EpubText, i18n::CommandError> {
+    recent_logs::correlated("extract_epub_chapters", async {
+        tokio::task::spawn_blocking(move || epub::extract_chapters(&path, &chapters))
+            .await
+            .map_err(|e| e.to_string())?
+            .map_err(|e| e.to_string())
+    })
+    .await
+}
+
+/// A Markdown note as speakable text, with its headings to start from
+#[tauri::command]
+async fn extract_text_from_markdown(path: std::path::PathBuf) -> Result<markdown::MarkdownText, i18n::CommandError> {
+    recent_logs::correlated("extract_text_from_markdown", async {
+        tokio::task::spawn_blocking(move || markdown::extract_text(&path))
+            .await
+            .map_err(|e| e.to_string())?
+            .map_err(|e| e.to_string())
+    })
+    .await
+}
+
+/// Narration text from an SRT or WebVTT file, with the gaps between cues
+#[tauri::command]
+async fn extract_text_from_subtitles(path: std::path::PathBuf) -> Result<subtitles::SubtitleText, i18n::CommandError> {
+    recent_logs::correlated("extract_text_from_subtitles", async {
+        tokio::task::spawn_blocking(move || subtitles::extract_text(&path))
+            .await
+            .map_err(|e| e.to_string())?
+            .map_err(|e| e.to_string())
+    })
+    .await
+}
+
+/// Extract each dropped file in turn, reporting it as `text:loaded` or
+/// `text:load_error`; images are read with OCR
+async fn load_dropped_files(app: &tauri::AppHandle, paths: Vec<std::path::PathBuf>) {
+    for path in paths {
+        tracing::info!("Loading dropped file {}", path.display());
+        let config = app.state::<config::Config>();
+        let loaded = if ocr::is_image(&path) {
+            let source = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+            Ok(ocr::recognize(&config.tesseract_path, &path)
+                .await
+                .map(|ocr| ocr.into_loaded(source.clone()))
+                .map_err(|e| documents::LoadFailure { source, reason: e.message().translate(i18n::locale()) }))
+        } else {
+            let max_text = config.max_input_bytes;
+            tokio::task::spawn_blocking(move || documents::load(&path, max_text)).await
+        };
+        let emitted = match loaded {
+            Ok(Ok(text)) => app.emit("text:loaded", text),
+            Ok(Err(failure)) => app.emit("text:load_error", failure),
+            Err(e) => app.emit(
+                "text:load_error",
+                documents::LoadFailure { source: String::new(), reason: e.to_string() },
+            ),
+        };
+        if let Err(e) = emitted {
+            tracing::warn!("Failed to emit file load result: {}", e);
         }
-        Err(e) => Err(format!("Failed to read file: {}", e))
     }
 }
 
+/// Download a web page and keep its article text
+#[tauri::command]
+async fn fetch_article(url: String, settings: tauri::State<'_, settings::SettingsStore>) -> Result<article::Article, i18n::CommandError> {
+    recent_logs::correlated("fetch_article", async {
+        let fetcher = article::ArticleFetcher::new(settings.get().allow_private_urls);
+        fetcher.fetch(&url).await.map_err(|e| e.to_string())
+    })
+    .await
+}
+
 #[tokio::main]
 async fn main() {
+    let mut cli_args = match cli::try_parse_cli_args(std::env::args().collect()) {
+        Ok(args) => args,
+        // Prints help/version to stdout (exit 0) or the usage error to stderr (exit 2)
+        Err(e) => e.exit(),
+    };
+
+    let dispatch = cli::dispatch(&cli_args);
+    let logs = logging::init(logging::Verbosity {
+        // The window has no console to clutter, so keep progress logs there
+        verbose: if dispatch == cli::Dispatch::Gui { cli_args.verbose.max(1) } else { cli_args.verbose },
+        quiet: cli_args.quiet,
+    });
+    // The window still opens for a mistyped voice, which the config would reject
+    let invalid_voice = if dispatch == cli::Dispatch::Gui { cli::take_invalid_voice(&mut cli_args) } else { None };
+    let (config, config_error) = match config::Config::load(&cli_args) {
+        Ok(config) => (config, None),
+        Err(e) if dispatch == cli::Dispatch::Headless => {
+            eprintln!("Error: {}", e);
+            std::process::exit(headless::EXIT_USAGE);
+        }
+        Err(e) => {
+            // Don't refuse to open the window over a bad config file or flag,
+            // but tell the user instead of quietly using the defaults
+            tracing::warn!("{}; using defaults", e);
+            (config::Config::default(), Some(e.to_string()))
+        }
+    };
+
+    match dispatch {
+        cli::Dispatch::Headless => {
+            let code = headless::run(cli_args, &config).await;
+            std::process::exit(code);
+        }
+        cli::Dispatch::Gui => {
+            let mut errors: Vec<String> = config_error.into_iter().collect();
+            let keys = keychain::ApiKeys::from_env();
+            let tts_service = build_service(&config, &keys, &mut errors).await;
+            let settings = settings::SettingsStore::load(
+                tts_service.database().cloned(),
+                settings::Settings::from_config(&config),
+            )
+            .await;
+            tts_service.apply_settings(&settings.get());
+            i18n::set_locale(&settings.get().locale);
+            let files = file_manager::FileManager::new().with_database(tts_service.database().cloned());
+            errors.extend(permissions::repair(&[&config.data_dir, files.dir()], &[&config.database_path()]));
+            let queue_paused = match tts_service.database() {
+                Some(database) => database.get_state(jobs::PAUSED_STATE_KEY).await.unwrap_or_else(|e| {
+                    tracing::warn!("Failed to read the queue state: {}", e);
+                    None
+                }),
+                None => None,
+            };
+            if queue_paused == Some(true) {
+                tracing::info!("The job queue was left paused; nothing starts until it's resumed");
+            }
+            let job_store = tts_service.database().map(|database| {
+                let dir = paths::job_chunks_dir(&config.data_dir);
+                Arc::new(recovery::JobStore::new(database.clone(), dir, tokio::runtime::Handle::current()))
+            });
+            if let Some(job_store) = &job_store {
+                let days = settings.get().keep_interrupted_jobs_days;
+                if let Err(e) = job_store.collect_garbage(std::time::Duration::from_secs(u64::from(days) * 24 * 60 * 60)).await {
+                    tracing::warn!("Failed to delete old interrupted jobs: {}", e);
+                }
+            }
+            let startup = Startup {
+                errors,
+                invalid_voice,
+                logs,
+                queue_paused: queue_paused.unwrap_or(false),
+                job_store,
+            };
+            run_gui(config, tts_service, files, keys, settings, cli_args, startup)
+        }
+    }
+}
+
+/// The one service the window's commands share. It is built here rather
+/// than in `setup()` because opening the database is async and `setup()`
+/// runs on this runtime's thread, where blocking on it would panic.
+async fn build_service(config: &config::Config, keys: &keychain::ApiKeys, startup_errors: &mut Vec<String>) -> tts::TTSService {
+    let api_key = keys.resolve_or_warn().map(|(key, _)| key);
+    if api_key.is_none() {
+        tracing::warn!("No API key configured; generation is disabled until one is set");
+    }
+
+    match tts::TTSService::from_config(api_key.as_deref(), config).await {
+        Ok(service) => service,
+        Err(e) => {
+            // Generation still works; usage tracking and stats don't
+            tracing::warn!("{}", e);
+            startup_errors.push(e.to_string());
+            tts::TTSService::from_config_without_database(api_key.as_deref(), config)
+        }
+    }
+}
+
+/// Start generating the command line's text now rather than once the
+/// window has loaded; the window follows the job by `LaunchRequest::job_id`.
+/// The budget is checked first, so a window that takes the request before
+/// then generates it itself, as it would any other text.
+async fn start_launch_job(app: &tauri::AppHandle) {
+    let request = app.state::<LaunchState>().0.lock().unwrap().clone();
+    let Some(request) = request.filter(|request| request.auto_generate) else {
+        return;
+    };
+    let settings = app.state::<settings::SettingsStore>().get();
+    let resolved = resolve::resolve(Some(request.voice_id.as_str()), None, &settings).ok();
+    let model = resolved.as_ref().map_or(settings.model.as_str(), |resolved| resolved.model.as_str());
+    let budgeted = apply_budget(&app.state::<tts::TTSService>(), &settings, model, &request.text).await;
+
+    let launch = app.state::<LaunchState>();
+    let mut launch = launch.0.lock().unwrap();
+    let Some(request) = launch.as_mut().filter(|request| request.auto_generate && request.job_id.is_none()) else {
+        return;
+    };
+    let enqueued = budgeted.map_err(|e| e.message).and_then(|budgeted| {
+        let options = jobs::GenerationOptions {
+            text: request.text.clone(),
+            voice_id: request.voice_id.clone(),
+            model: Some(budgeted.model),
+            title: None,
+            source: Some(database::SOURCE_CLI),
+            queue_if_offline: None,
+            downgrade: budgeted.downgrade,
+            resolved,
+            sentence_gap_ms: Some(settings.sentence_gap_ms),
+            paragraph_gap_ms: Some(settings.paragraph_gap_ms),
+            parent_id: None,
+            trim_silence: false,
+        };
+        app.state::<Jobs>().enqueue(options, &settings.model)
+    });
+    match enqueued {
+        Ok(job_id) => request.job_id = Some(job_id),
+        Err(e) => {
+            // Leave the text in the window to fix and generate by hand
+            tracing::warn!("Failed to start launch generation: {}", e);
+            request.auto_generate = false;
+            notify(app, "Speech generation failed", &e);
+        }
+    }
+}
+
+/// Bring the window forward and speak the linked text. Bad links end up as
+/// a notification; nothing here may panic since links come from anywhere.
+fn handle_deep_link(app: &tauri::AppHandle, link: &str) {
+    let request = match deeplink::parse_deep_link(link) {
+        Ok(request) => request,
+        Err(e) => {
+            tracing::warn!("Rejected deep link: {}", e);
+            notify(app, "Couldn't open link", &e.to_string());
+            return;
+        }
+    };
+
+    show_main_window(app);
+
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let settings = app.state::<settings::SettingsStore>().get();
+        let tts_service = app.state::<tts::TTSService>().inner().clone();
+        let files = app.state::<file_manager::FileManager>();
+        let resolved = match resolve::resolve(request.voice.as_deref(), request.model.as_deref(), &settings) {
+            Ok(resolved) => resolved,
+            Err(e) => {
+                notify(&app, "Couldn't open link", &e.translate(i18n::locale()));
+                return;
+            }
+        };
+        let generated = match apply_budget(&tts_service, &settings, &resolved.model, &request.text).await {
+            Ok(model) => {
+                with_warnings(synthesize_audio(&tts_service, &files, &request.text, &resolved, &model, database::SOURCE_DEEPLINK, false))
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+
+        match generated {
+            Ok(audio) => {
+                let payload = BackgroundSpeech { text: request.text, voice_id: resolved.voice_id, audio };
+                emit_to_window(&app, "deep-link-speech", payload);
+            }
+            Err(e) => {
+                tracing::error!("Deep link generation failed: {}", e.fallback);
+                notify(&app, "Speech generation failed", &e.message);
+            }
+        }
+    });
+}
+
+fn show_main_window(app: &tauri::AppHandle) {
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.unminimize();
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
+}
+
+/// Left-clicking the tray icon hides the window when it's in front and
+/// brings it back otherwise
+fn toggle_main_window(app: &tauri::AppHandle) {
+    let Some(window) = app.get_webview_window("main") else {
+        return;
+    };
+    let in_front = window.is_visible().unwrap_or(false) && window.is_focused().unwrap_or(false);
+    if in_front {
+        let _ = window.hide();
+    } else {
+        show_main_window(app);
+    }
+}
+
+/// The plugin's clipboard, for code written against `ClipboardSource`
+struct PluginClipboard<'a>(&'a tauri::AppHandle);
+
+impl ClipboardSource for PluginClipboard<'_> {
+    fn read_text(&mut self) -> Result<Option<String>, String> {
+        // The plugin reports an empty or non-text clipboard as an error
+        Ok(self.0.clipboard().read_text().ok())
+    }
+
+    fn read_html(&mut self) -> Result<Option<String>, String> {
+        clipboard::read_system_html()
+    }
+}
+
+struct AppTray<'a>(&'a tauri::AppHandle);
+
+impl tray::TrayHost for AppTray<'_> {
+    fn speak(&mut self, text: String) {
+        speak_clipboard_text(self.0, text);
+    }
+
+    fn stop(&mut self) {
+        self.0.state::<player::Player>().stop();
+        if let Err(e) = self.0.emit("playback:stop", ()) {
+            tracing::warn!("Failed to emit playback stop: {}", e);
+        }
+    }
+
+    fn open_history(&mut self, id: i64) {
+        let state = self.0.state::<TrayState>();
+        let history = state.history.lock().unwrap();
+        let text = history.iter().find(|record| record.id == Some(id)).map(|record| record.text.clone());
+        drop(history);
+        let Some(text) = text else {
+            return;
+        };
+        show_main_window(self.0);
+        let loaded = documents::LoadedText {
+            character_count: text.chars().count(),
+            text,
+            source: "History".to_string(),
+            warnings: Vec::new(),
+        };
+        if let Err(e) = self.0.emit("text:loaded", loaded) {
+            tracing::warn!("Failed to emit history text: {}", e);
+        }
+    }
+
+    fn quit(&mut self) {
+        self.0.exit(0);
+    }
+
+    fn notify(&mut self, title: &str, body: &str) {
+        notify(self.0, title, body);
+    }
+}
+
+fn handle_tray_menu(app: &tauri::AppHandle, id: &str) {
+    if let Some(action) = tray::TrayAction::from_id(id) {
+        tray::dispatch(action, &mut PluginClipboard(app), &mut AppTray(app));
+    }
+}
+
+/// Generate clipboard text with the saved voice and model and hand it to
+/// the window to play. One at a time; the menu shows "Generating…" meanwhile.
+fn speak_clipboard_text(app: &tauri::AppHandle, text: String) {
+    if app.state::<TrayState>().generating.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        refresh_tray(&app).await;
+        let settings = app.state::<settings::SettingsStore>().get();
+        let tts_service = app.state::<tts::TTSService>().inner().clone();
+        let files = app.state::<file_manager::FileManager>();
+
+        let resolved = resolve::defaults(&settings);
+        let generated = match apply_budget(&tts_service, &settings, &resolved.model, &text).await {
+            Ok(model) => {
+                with_warnings(synthesize_audio(&tts_service, &files, &text, &resolved, &model, database::SOURCE_CLIPBOARD, false))
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+
+        match generated {
+            Ok(audio) => {
+                let payload = BackgroundSpeech { text, voice_id: resolved.voice_id, audio };
+                emit_to_window(&app, "clipboard-speech", payload);
+            }
+            Err(e) => {
+                tracing::error!("Clipboard generation failed: {}", e.fallback);
+                notify(&app, "Speech generation failed", &e.message);
+            }
+        }
+        app.state::<TrayState>().generating.store(false, Ordering::SeqCst);
+        refresh_tray(&app).await;
+    });
+}
+
+/// The speak-clipboard hotkey: queue the clipboard text and play it on the
+/// default output device, or stop whatever an earlier press started
+fn handle_speak_shortcut(app: &tauri::AppHandle) {
+    let resolved = resolve::defaults(&app.state::<settings::SettingsStore>().get());
+    let jobs = app.state::<Jobs>();
+    let speaker = app.state::<shortcut::ClipboardSpeaker>();
+    match speaker.on_press(&mut PluginClipboard(app), &jobs, &resolved.voice_id, &resolved.model) {
+        Ok(shortcut::PressOutcome::Enqueued(id)) => {
+            let characters = jobs.status(&id).map(|job| job.characters).unwrap_or_default();
+            notify(app, "Speaking clipboard", &format!("{} characters", characters));
+            let app = app.clone();
+            tauri::async_runtime::spawn(async move { play_clipboard_job(&app, &id).await });
+        }
+        Ok(shortcut::PressOutcome::Stopped) => {}
+        Ok(shortcut::PressOutcome::NothingToSpeak) => notify(app, "Nothing to speak", "The clipboard has no text"),
+        Err(e) => notify(app, "Speech generation failed", &e),
+    }
+}
+
+/// The clipboard watch loop while it runs. Aborting it is what stops it, so
+/// nothing reads the clipboard while watch mode is off.
+#[derive(Default)]
+struct ClipboardWatchState(std::sync::Mutex<Option<tauri::async_runtime::JoinHandle<()>>>);
+
+/// How often a watcher paused by the budget checks whether it may resume
+const BUDGET_RECHECK: std::time::Duration = std::time::Duration::from_secs(60);
+
+fn set_clipboard_watching(app: &tauri::AppHandle, enabled: bool) {
+    let state = app.state::<ClipboardWatchState>();
+    let mut task = state.0.lock().unwrap();
+    match (enabled, task.take()) {
+        (true, Some(running)) => *task = Some(running),
+        (true, None) => {
+            let app = app.clone();
+            *task = Some(tauri::async_runtime::spawn(async move { watch_clipboard(&app).await }));
+        }
+        (false, Some(running)) => running.abort(),
+        (false, None) => {}
+    }
+}
+
+/// Speak each new, settled clipboard text, replacing whatever the clipboard
+/// was saying. Once this month's budget is reached the watcher stops reading
+/// until the budget allows it again.
+async fn watch_clipboard(app: &tauri::AppHandle) {
+    let mut watcher = clipboard_watch::ClipboardWatcher::new(Default::default(), clipboard_watch::DEBOUNCE);
+    watcher.baseline(PluginClipboard(app).read_text().ok().flatten().as_deref());
+    let mut interval = tokio::time::interval(clipboard_watch::POLL_INTERVAL);
+    let mut paused = false;
+    loop {
+        if paused {
+            tokio::time::sleep(BUDGET_RECHECK).await;
+            if budget_reached(app).await {
+                continue;
+            }
+            paused = false;
+            // What was copied while paused isn't read out afterwards
+            watcher.baseline(PluginClipboard(app).read_text().ok().flatten().as_deref());
+            notify(app, "Clipboard watch resumed", "Copied text will be read aloud again");
+        }
+        interval.tick().await;
+        let Some(text) = watcher.poll(&mut PluginClipboard(app), std::time::Instant::now()) else {
+            continue;
+        };
+        if budget_reached(app).await {
+            paused = true;
+            notify(app, "Clipboard watch paused", "This month's budget has been reached");
+            continue;
+        }
+
+        let resolved = resolve::defaults(&app.state::<settings::SettingsStore>().get());
+        let jobs = app.state::<Jobs>();
+        let speaker = app.state::<shortcut::ClipboardSpeaker>();
+        match speaker.replace(text, &jobs, &resolved.voice_id, &resolved.model, database::SOURCE_CLIPBOARD_WATCH) {
+            Ok(id) => {
+                let app = app.clone();
+                tauri::async_runtime::spawn(async move { play_clipboard_job(&app, &id).await });
+            }
+            Err(e) => tracing::warn!("Clipboard watch couldn't queue text: {}", e),
+        }
+    }
+}
+
+/// Whether the active profile's spend this month has reached its budget.
+/// Without usage tracking there is nothing to compare, so it never has.
+async fn budget_reached(app: &tauri::AppHandle) -> bool {
+    let tts_service = app.state::<tts::TTSService>().inner().clone();
+    let Some(database) = tts_service.database() else {
+        return false;
+    };
+    let settings = app.state::<settings::SettingsStore>().get();
+    spend::budget_reached(database, settings.profile_budget(), &settings.profile).await.unwrap_or_else(|e| {
+        tracing::warn!("Failed to check the budget: {}", e);
+        false
+    })
+}
+
+fn notify_job_finished(app: &tauri::AppHandle, job: &jobs::JobInfo) {
+    let settings = app.state::<settings::SettingsStore>().get();
+    let focused = app
+        .get_webview_window("main")
+        .is_some_and(|window| window.is_visible().unwrap_or(false) && window.is_focused().unwrap_or(false));
+    // Clicking a desktop notification brings the app forward, where the
+    // window has already picked up the result
+    let notification = notifications::for_job(job, settings.notify_on_completion, settings.notify_after_secs, focused);
+    if let Some(notification) = notification {
+        notify(app, &notification.title, &notification.body);
+    }
+}
+
+async fn play_clipboard_job(app: &tauri::AppHandle, id: &str) {
+    let jobs = app.state::<Jobs>();
+    let speaker = app.state::<shortcut::ClipboardSpeaker>();
+    let audio = match jobs.wait(id).await {
+        Ok(audio) => audio,
+        Err(e) => {
+            speaker.finish(id, None);
+            // A second press cancels on purpose; that's not worth a notification
+            if !jobs.status(id).is_some_and(|job| job.state == jobs::JobState::Cancelled) {
+                notify(app, "Speech generation failed", &e);
+            }
+            return;
+        }
+    };
+    let Some(stop) = speaker.start_playing(id) else {
+        return;
+    };
+    let flag = stop.clone();
+    let played = tokio::task::spawn_blocking(move || playback::play(None, audio, &flag))
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|result| result);
+    speaker.finish(id, Some(&stop));
+    if let Err(e) = played {
+        tracing::warn!("Clipboard playback failed: {}", e);
+        notify(app, "Couldn't play audio", &e);
+    }
+}
+
+fn build_tray_menu(app: &tauri::AppHandle, entries: &[tray::TrayEntry]) -> tauri::Result<Menu<tauri::Wry>> {
+    let menu = Menu::new(app)?;
+    for entry in entries {
+        match entry {
+            tray::TrayEntry::Item { id, label, enabled } => {
+                menu.append(&MenuItem::with_id(app, id.as_str(), label, *enabled, None::<&str>)?)?
+            }
+            tray::TrayEntry::Separator => menu.append(&PredefinedMenuItem::separator(app)?)?,
+        }
+    }
+    Ok(menu)
+}
+
+/// Rebuild the tray menu from the current generating state and history
+async fn refresh_tray(app: &tauri::AppHandle) {
+    let tts_service = app.state::<tts::TTSService>().inner().clone();
+    // Failed generations are skipped by the menu, so fetch a few extra
+    let history = tts_service.get_usage_history(tray::HISTORY_ITEMS as i32 * 2, None).await.unwrap_or_default();
+    let state = app.state::<TrayState>();
+    let entries = tray::menu_entries(state.generating.load(Ordering::SeqCst), &history);
+    *state.history.lock().unwrap() = history;
+
+    let Some(tray_icon) = app.tray_by_id(TRAY_ID) else {
+        return;
+    };
+    match build_tray_menu(app, &entries) {
+        Ok(menu) => {
+            if let Err(e) = tray_icon.set_menu(Some(menu)) {
+                tracing::warn!("Failed to update tray menu: {}", e);
+            }
+        }
+        Err(e) => tracing::warn!("Failed to build tray menu: {}", e),
+    }
+}
+
+/// Another launch of the app found this one running and handed over its
+/// arguments; act on them as a fresh launch would have
+fn handle_second_launch(app: &tauri::AppHandle, argv: Vec<String>, cwd: String) {
+    show_main_window(app);
+    let saved_voice = app.state::<settings::SettingsStore>().get().voice;
+    let configured = app.try_state::<tts::TTSService>().is_some_and(|service| service.is_configured());
+    match cli::forwarded_launch(argv, std::path::Path::new(&cwd), &saved_voice, configured) {
+        Ok(cli::ForwardedLaunch::Text { request }) => {
+            *app.state::<LaunchState>().0.lock().unwrap() = Some(request);
+            let app = app.clone();
+            tauri::async_runtime::spawn(async move {
+                start_launch_job(&app).await;
+                // The window takes it with `take_launch_request`, as at startup
+                let request = app.state::<LaunchState>().0.lock().unwrap().clone();
+                if let Some(request) = request {
+                    emit_to_window(&app, "launch-request", cli::ForwardedLaunch::Text { request });
+                }
+            });
+        }
+        Ok(cli::ForwardedLaunch::DeepLink { link }) => handle_deep_link(app, &link),
+        Ok(cli::ForwardedLaunch::Focus) => {}
+        Err(e) => {
+            tracing::warn!("Ignoring forwarded launch: {}", e);
+            notify(app, "TTS Player", &e);
+        }
+    }
+}
+
+/// Emit to the window through the relay, so nothing sent while it is still
+/// loading is missed
+fn emit_to_window<S: Serialize>(app: &tauri::AppHandle, event: &str, payload: S) {
+    app.state::<relay::EventRelay>().send(event, &payload, &|event, payload| {
+        if let Err(e) = app.emit(event, payload) {
+            tracing::warn!("Failed to emit {}: {}", event, e);
+        }
+    });
+}
+
+fn notify(app: &tauri::AppHandle, title: &str, body: &str) {
+    if let Err(e) = app.notification().builder().title(title).body(body).show() {
+        tracing::warn!("Failed to show notification: {}", e);
+    }
+}
+
+/// What happened before the window opened, for it to report
+struct Startup {
+    errors: Vec<String>,
+    /// A `--voice` that doesn't exist; the saved voice is used instead
+    invalid_voice: Option<String>,
+    /// Kept since logging started, for `get_recent_logs`
+    logs: recent_logs::RecentLogs,
+    /// The job queue was paused when the app last quit
+    queue_paused: bool,
+    /// Where jobs are saved to be recovered, when there is a database
+    job_store: Option<Arc<recovery::JobStore>>,
+}
+
+fn run_gui(
+    config: config::Config,
+    tts_service: tts::TTSService,
+    files: file_manager::FileManager,
+    keys: keychain::ApiKeys,
+    settings: settings::SettingsStore,
+    cli_args: cli::CliArgs,
+    startup: Startup,
+) {
+    let Startup { errors: mut startup_errors, invalid_voice, logs, queue_paused, job_store } = startup;
+    let launch = match cli::launch_request(&cli_args, &config) {
+        Ok(launch) => launch,
+        Err(e) => {
+            tracing::warn!("{}", e);
+            startup_errors.push(e);
+            None
+        }
+    };
+    let saved_voice = settings.get().voice;
+    let voice_notice = invalid_voice.map(|voice| {
+        tracing::warn!("Unknown voice {}; using {}", voice, saved_voice);
+        format!("Unknown voice '{}'; using {} instead", voice, saved_voice)
+    });
+    // Prefill but don't spend credits on a request the user didn't quite ask for
+    let launch = launch.map(|request| {
+        // The voice notice first, then any about how --file was read
+        let notice = match (voice_notice.clone(), request.notice.clone()) {
+            (Some(voice), Some(file)) => Some(format!("{} {}", voice, file)),
+            (voice, file) => voice.or(file),
+        };
+        cli::LaunchRequest {
+            // --voice wins; otherwise the saved voice rather than the config file's
+            voice_id: cli_args.voice.clone().unwrap_or_else(|| saved_voice.clone()),
+            auto_generate: startup_errors.is_empty() && tts_service.is_configured(),
+            notice,
+            ..request
+        }
+    });
+    // With no text to speak, the window has nowhere to show it
+    if launch.is_none() {
+        startup_errors.extend(voice_notice);
+    }
+    let launch_link = cli_args.deep_link;
+
     tauri::Builder::default()
-        .plugin(tauri_plugin_cli::init())
+        .manage(config)
+        .manage(files)
+        .manage(keys)
+        .manage(settings)
+        .manage(LaunchState(std::sync::Mutex::new(launch)))
+        .manage(relay::EventRelay::default())
+        .manage(segments::Deliveries::default())
+        .manage(logs)
+        .manage(TrayState::default())
+        .manage(shortcut::ClipboardSpeaker::default())
+        .manage(ClipboardWatchState::default())
+        .manage(player::Player::new(player::default_output()))
+        // First, so a second launch hands over its arguments and exits
+        // before any other plugin starts
+        .plugin(tauri_plugin_single_instance::init(|app, argv, cwd| handle_second_launch(app, argv, cwd)))
         .plugin(tauri_plugin_clipboard_manager::init())
+        .plugin(tauri_plugin_deep_link::init())
+        .plugin(tauri_plugin_dialog::init())
+        .plugin(
+            tauri_plugin_global_shortcut::Builder::new()
+                .with_handler(|app, _shortcut, event| {
+                    // Only the speak-clipboard hotkey is ever registered
+                    if event.state() == ShortcutState::Pressed {
+                        handle_speak_shortcut(app);
+                    }
+                })
+                .build(),
+        )
+        .plugin(tauri_plugin_notification::init())
+        .register_asynchronous_uri_scheme_protocol(audio_stream::SCHEME, |ctx, request, responder| {
+            let app = ctx.app_handle().clone();
+            tauri::async_runtime::spawn_blocking(move || {
+                let files = app.state::<file_manager::FileManager>();
+                let id = audio_stream::id_from_path(request.uri().path());
+                let range = request.headers().get("range").and_then(|value| value.to_str().ok());
+                // Only the length, as this runs for every range the player asks for
+                let corrupted = files.find(id).is_some_and(|path| {
+                    tauri::async_runtime::block_on(files.check(&path, false)) == integrity::AudioStatus::Corrupted
+                });
+                let streamed = if corrupted {
+                    audio_stream::StreamResponse::corrupted()
+                } else {
+                    audio_stream::respond(&files, id, range)
+                };
+
+                let mut response = tauri::http::Response::builder().status(streamed.status);
+                for (name, value) in streamed.headers {
+                    response = response.header(name, value);
+                }
+                let response = response.body(streamed.body).unwrap_or_else(|e| {
+                    tracing::error!("Failed to build audio response: {}", e);
+                    tauri::http::Response::builder().status(500).body(Vec::new()).unwrap()
+                });
+                responder.respond(response);
+            });
+        })
         .invoke_handler(tauri::generate_handler![
             generate_speech,
             generate_speech_with_model,
             get_user_info,
             get_usage_stats,
+            get_slow_requests,
+            list_profiles,
+            get_total_spend,
+            get_hourly_usage,
+            get_failures_by_version,
+            import_usage_csv,
+            get_available_voices,
+            resolve_defaults,
+            voice_for_language,
+            get_voice_usage_summary,
+            get_available_models,
             get_usage_history,
+            get_service_status,
+            get_api_key_status,
+            set_api_key,
+            validate_api_key,
+            health_check,
+            get_throttle_status,
+            clear_api_key,
+            get_settings,
+            update_settings,
+            set_speak_shortcut,
+            set_clipboard_watch,
+            set_locale,
+            export_settings,
+            import_settings,
+            enqueue_generation,
+            generate_batch_command,
+            generate_sections,
+            get_batch_result,
+            pause_queue,
+            resume_queue,
+            get_queue_status,
+            list_recoverable_jobs,
+            recover_job,
+            discard_recoverable_job,
+            get_job_status,
+            get_generation_status,
+            list_jobs,
+            cancel_job,
+            take_job_result,
+            send_job_result,
+            get_recent_generations,
+            replay_generation,
+            get_recent_logs,
+            list_pending_jobs,
+            reorder_pending_jobs,
+            delete_pending_job,
+            create_project,
+            list_projects,
+            rename_project,
+            delete_project,
+            get_project_items,
+            add_project_item,
+            update_project_item,
+            delete_project_item,
+            reorder_project_items,
+            generate_project,
+            export_project,
+            play_audio,
+            pause,
+            resume,
+            stop,
+            seek,
+            set_volume,
+            get_playback_status,
+            transform_audio_speed,
+            transcode_audio,
+            reveal_in_file_manager,
+            get_app_paths,
+            open_app_data_dir,
+            release_audio,
+            list_audio_files,
+            verify_library,
+            take_launch_request,
+            frontend_ready,
             count_characters,
+            analyze_text,
+            preview_chunks,
             read_text_file,
+            read_image_text,
+            open_text_file,
+            extract_text_from_pdf,
+            extract_text_from_docx,
+            extract_text_from_epub,
+            extract_epub_chapters,
+            extract_text_from_markdown,
+            extract_text_from_subtitles,
+            generate_speech_with_pauses,
+            regenerate_from_record,
+            fetch_article,
             read_clipboard
         ])
-        .setup(|app| {
-            // Setup cleanup on app exit
-            let _app_handle = app.handle().clone(); // Keep for potential future use
-            
+        .setup(move |app| {
+            let app_handle = app.handle().clone();
+            tts_service.throttle().set_listener(Arc::new(move |status: &throttle::ThrottleStatus| {
+                emit_to_window(&app_handle, "tts:throttle", status);
+            }));
+            let app_handle = app.handle().clone();
+            let concurrency = app.state::<settings::SettingsStore>().get().max_concurrent_jobs;
+            let jobs = Jobs::new(Arc::new(tts_service.clone()), concurrency).with_listener(Arc::new(
+                move |job: &jobs::JobInfo| {
+                    emit_to_window(&app_handle, "job:changed", job);
+                    if job.state.is_finished() {
+                        notify_job_finished(&app_handle, job);
+                        // A finished generation is new tray history
+                        let app_handle = app_handle.clone();
+                        tauri::async_runtime::spawn(async move { refresh_tray(&app_handle).await });
+                    }
+                },
+            ))
+            .with_offline_queue(offline_queue(app.handle().clone(), tts_service.clone()))
+            .with_retry_policy(retry_policy(app.handle().clone()))
+            .with_paused(queue_paused);
+            let jobs = match job_store {
+                Some(job_store) => jobs.with_store(job_store),
+                None => jobs,
+            };
+            let recent: Recent = tts_service.database().map(|database| {
+                let dir = paths::recent_dir(&app.state::<config::Config>().data_dir);
+                Arc::new(recent::RecentGenerations::new(database.clone(), dir))
+            });
+            let jobs = match &recent {
+                Some(recent) => {
+                    jobs.with_finished_listener(keep_recent(app.handle().clone(), recent.clone(), tts_service.clone()))
+                }
+                None => jobs,
+            };
+            let app_handle = app.handle().clone();
+            let batches = snippets::Batches::new(deliver_batch_audio(app.handle().clone())).with_listener(Arc::new(
+                move |event: &snippets::BatchEvent| match event {
+                    snippets::BatchEvent::Item { batch_id, item } => {
+                        emit_to_window(&app_handle, "batch:item", &BatchItemChanged { batch_id, item })
+                    }
+                    snippets::BatchEvent::Finished(result) => emit_to_window(&app_handle, "batch:finished", result),
+                },
+            ));
+            app.manage(tts_service);
+            app.manage(jobs);
+            app.manage(recent);
+            app.manage(batches);
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move { flush_pending_jobs(&app_handle).await });
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move { start_launch_job(&app_handle).await });
+            if app.state::<settings::SettingsStore>().get().clipboard_watch {
+                set_clipboard_watching(app.handle(), true);
+            }
+
+            // Audio from a previous run has nothing left to play it. Only
+            // now is it certain no other instance is using the directory.
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                if let Err(e) = app_handle.state::<file_manager::FileManager>().cleanup().await {
+                    tracing::warn!("Failed to clean up old audio files: {}", e);
+                }
+            });
+
             #[cfg(target_os = "macos")]
             app.set_activation_policy(tauri::ActivationPolicy::Regular);
+
+            // macOS registers the scheme from the bundle; elsewhere do it at runtime
+            #[cfg(any(target_os = "linux", all(debug_assertions, windows)))]
+            {
+                if let Err(e) = app.deep_link().register_all() {
+                    tracing::warn!("Failed to register deep link scheme: {}", e);
+                }
+            }
+
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move { report_playback_position(&app_handle).await });
+
+            let tray_menu = build_tray_menu(app.handle(), &tray::menu_entries(false, &[]))?;
+            let mut tray_icon = TrayIconBuilder::with_id(TRAY_ID)
+                .tooltip("TTS Player")
+                .menu(&tray_menu)
+                .show_menu_on_left_click(false)
+                .on_menu_event(|app, event| handle_tray_menu(app, event.id().as_ref()))
+                .on_tray_icon_event(|tray_icon, event| {
+                    if let TrayIconEvent::Click { button: MouseButton::Left, button_state: MouseButtonState::Up, .. } = event {
+                        toggle_main_window(tray_icon.app_handle());
+                    }
+                });
+            if let Some(icon) = app.default_window_icon() {
+                tray_icon = tray_icon.icon(icon.clone());
+            }
+            tray_icon.build(app)?;
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move { refresh_tray(&app_handle).await });
+
+            if let Some(hotkey) = app.state::<settings::SettingsStore>().get().speak_shortcut {
+                if let Err(e) = app.global_shortcut().register(hotkey.as_str()) {
+                    tracing::warn!("Failed to register {}: {}", hotkey, e);
+                    let message = format!("{} couldn't be registered; another app may be using it", hotkey);
+                    notify(app.handle(), "Shortcut not set", &message);
+                }
+            }
+
+            if let Some(window) = app.get_webview_window("main") {
+                let app_handle = app.handle().clone();
+                window.on_window_event(move |event| match event {
+                    // Files dropped on the window are loaded one after another
+                    tauri::WindowEvent::DragDrop(tauri::DragDropEvent::Drop { paths, .. }) => {
+                        let app_handle = app_handle.clone();
+                        let paths = paths.clone();
+                        tauri::async_runtime::spawn(async move { load_dropped_files(&app_handle, paths).await });
+                    }
+                    tauri::WindowEvent::CloseRequested { api, .. } => {
+                        if app_handle.state::<settings::SettingsStore>().get().close_to_tray {
+                            api.prevent_close();
+                            if let Some(window) = app_handle.get_webview_window("main") {
+                                let _ = window.hide();
+                            }
+                        }
+                    }
+                    _ => {}
+                });
+            }
+
+            let app_handle = app.handle().clone();
+            app.deep_link().on_open_url(move |event| {
+                for url in event.urls() {
+                    handle_deep_link(&app_handle, url.as_str());
+                }
+            });
+
+            for error in &startup_errors {
+                notify(app.handle(), "TTS Player", error);
+            }
+
+            if let Some(link) = &launch_link {
+                handle_deep_link(app.handle(), link);
+            }
             
             Ok(())
         })
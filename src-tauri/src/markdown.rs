@@ -0,0 +1,198 @@
+//! Markdown notes read aloud: formatting dropped, code blocks skipped,
+//! links reduced to their text and front matter ignored, with an outline of
+//! the headings so reading can start at a section.
+
+use crate::extract::ExtractError;
+use crate::tts::{GenerationEstimate, TTSService};
+use pulldown_cmark::{Event, LinkType, Options, Parser, Tag, TagEnd};
+use serde::Serialize;
+use std::path::Path;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MarkdownText {
+    pub text: String,
+    pub outline: Vec<Heading>,
+    pub estimate: GenerationEstimate,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Heading {
+    /// 1 for `#`, up to 6
+    pub level: u8,
+    pub title: String,
+    /// Offset in characters (not bytes) into `MarkdownText::text`
+    pub start: usize,
+}
+
+pub fn extract_text(path: &Path) -> Result<MarkdownText, ExtractError> {
+    let markdown = std::fs::read_to_string(path).map_err(|e| ExtractError::Io(e.to_string()))?;
+    let text = to_speech_text(&markdown);
+    if text.text.is_empty() {
+        return Err(ExtractError::NoText);
+    }
+    Ok(text)
+}
+
+/// What's worth hearing in `markdown`: paragraphs, list items, headings and
+/// table rows (cells separated by commas), one per paragraph.
+pub fn to_speech_text(markdown: &str) -> MarkdownText {
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_WIKILINKS
+        | Options::ENABLE_YAML_STYLE_METADATA_BLOCKS;
+
+    let mut builder = TextBuilder::default();
+    // Depth inside elements whose text isn't read: code blocks, images,
+    // front matter, footnote definitions and bare URLs
+    let mut skipping = 0usize;
+    let mut heading: Option<u8> = None;
+    // Whether each open link is a bare URL, whose text is skipped
+    let mut links: Vec<bool> = Vec::new();
+
+    for event in Parser::new_ext(markdown, options) {
+        match event {
+            Event::Start(tag) => match tag {
+                Tag::CodeBlock(_) | Tag::Image { .. } | Tag::MetadataBlock(_) | Tag::FootnoteDefinition(_) => {
+                    skipping += 1
+                }
+                Tag::Link { link_type, dest_url, .. } => {
+                    // A bare URL is read as just its host
+                    let bare_url = link_type == LinkType::Autolink;
+                    links.push(bare_url);
+                    if bare_url {
+                        if skipping == 0 {
+                            let host = url::Url::parse(&dest_url).ok().and_then(|url| url.host_str().map(str::to_string));
+                            builder.push(host.as_deref().unwrap_or(""));
+                        }
+                        skipping += 1;
+                    }
+                }
+                Tag::Heading { level, .. } => {
+                    builder.end_paragraph();
+                    heading = Some(level as u8);
+                }
+                Tag::Paragraph | Tag::Item | Tag::BlockQuote(_) | Tag::TableRow | Tag::TableHead => {
+                    builder.end_paragraph();
+                }
+                Tag::TableCell => builder.separate_cell(),
+                _ => {}
+            },
+            Event::End(tag) => match tag {
+                TagEnd::CodeBlock | TagEnd::Image | TagEnd::MetadataBlock(_) | TagEnd::FootnoteDefinition => {
+                    skipping = skipping.saturating_sub(1)
+                }
+                TagEnd::Link => skipping = skipping.saturating_sub(usize::from(links.pop() == Some(true))),
+                TagEnd::Heading(_) => {
+                    if let Some(level) = heading.take() {
+                        builder.end_heading(level);
+                    }
+                }
+                TagEnd::Paragraph | TagEnd::Item | TagEnd::BlockQuote(_) | TagEnd::TableRow | TagEnd::TableHead => {
+                    builder.end_paragraph();
+                }
+                _ => {}
+            },
+            Event::Text(text) | Event::Code(text) if skipping == 0 => builder.push(&text),
+            Event::SoftBreak | Event::HardBreak if skipping == 0 => builder.push(" "),
+            _ => {}
+        }
+    }
+    builder.end_paragraph();
+
+    let text = builder.paragraphs.join("\n\n");
+    let estimate = TTSService::estimate(&text);
+    MarkdownText { text, outline: builder.outline, estimate }
+}
+
+#[derive(Default)]
+struct TextBuilder {
+    paragraphs: Vec<String>,
+    current: String,
+    /// Characters in `paragraphs` once joined, separators included
+    length: usize,
+    outline: Vec<Heading>,
+}
+
+impl TextBuilder {
+    fn push(&mut self, text: &str) {
+        self.current.push_str(text);
+    }
+
+    fn separate_cell(&mut self) {
+        if !self.current.trim().is_empty() {
+            self.current.push_str(", ");
+        }
+    }
+
+    /// Finish the current paragraph; returns where it starts, if non-empty
+    fn end_paragraph(&mut self) -> Option<usize> {
+        let paragraph = self.current.split_whitespace().collect::<Vec<_>>().join(" ");
+        let paragraph = paragraph.trim_end_matches(',').to_string();
+        self.current.clear();
+        if paragraph.is_empty() {
+            return None;
+        }
+        if !self.paragraphs.is_empty() {
+            self.length += 2;
+        }
+        let start = self.length;
+        self.length += paragraph.chars().count();
+        self.paragraphs.push(paragraph);
+        Some(start)
+    }
+
+    fn end_heading(&mut self, level: u8) {
+        if let Some(start) = self.end_paragraph() {
+            let title = self.paragraphs.last().cloned().unwrap_or_default();
+            self.outline.push(Heading { level, title, start });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOTE: &str = include_str!("../tests/fixtures/vault-note.md");
+
+    #[test]
+    fn test_vault_note() {
+        let note = to_speech_text(NOTE);
+        assert_eq!(
+            note.text,
+            "Garden plan\n\n\
+             Spring planting starts after the last frost, usually mid-April. \
+             See the weather log and the seed catalogue.\n\n\
+             Beds\n\n\
+             Bed, Crop, Sown\n\n\
+             North, Peas, March\n\n\
+             South, Tomatoes, May\n\n\
+             Turn the compost\n\n\
+             Order seed potatoes\n\n\
+             Notes\n\n\
+             Ask about drip irrigation at the shop. Details in Irrigation and example.com.\n\n\
+             Plant early, harvest early."
+        );
+        for skipped in ["title:", "def water", "https://", "garden-sketch", "Overheard", "<br>", "[["] {
+            assert!(!note.text.contains(skipped), "{:?} should be skipped", skipped);
+        }
+
+        let outline: Vec<_> = note.outline.iter().map(|h| (h.level, h.title.as_str())).collect();
+        assert_eq!(outline, [(1, "Garden plan"), (2, "Beds"), (2, "Notes")]);
+        for heading in &note.outline {
+            let from_offset: String = note.text.chars().skip(heading.start).take(heading.title.chars().count()).collect();
+            assert_eq!(from_offset, heading.title);
+        }
+    }
+
+    #[test]
+    fn test_offsets_count_characters() {
+        let note = to_speech_text("Café crème — déjà vu.\n\n## Über\n\nText");
+        assert_eq!(note.outline, vec![Heading { level: 2, title: "Über".to_string(), start: 23 }]);
+        assert!(to_speech_text("---\ntags: [a]\n---\n").text.is_empty());
+    }
+}
@@ -0,0 +1,118 @@
+//! The speech models, with their prices and limits. Like `voices`, this is
+//! the single table: `tts::VALID_MODEL_IDS` and `tts::estimate_cost` both
+//! read it, so what's shown and what's estimated can't drift apart.
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelInfo {
+    pub id: &'static str,
+    pub name: &'static str,
+    /// USD per 1,000 input characters
+    pub price_per_1k_chars: f64,
+    /// Longest input the endpoint accepts in one request
+    pub max_input_chars: usize,
+    pub supports_speed: bool,
+    /// Whether the request can carry style instructions ("speak calmly")
+    pub supports_instructions: bool,
+    pub recommended: bool,
+}
+
+pub const MODELS: &[ModelInfo] = &[
+    ModelInfo {
+        id: "tts-1",
+        name: "Standard",
+        price_per_1k_chars: 0.015,
+        max_input_chars: 4096,
+        supports_speed: true,
+        supports_instructions: false,
+        recommended: false,
+    },
+    ModelInfo {
+        id: "tts-1-hd",
+        name: "HD",
+        price_per_1k_chars: 0.03,
+        max_input_chars: 4096,
+        supports_speed: true,
+        supports_instructions: false,
+        recommended: true,
+    },
+];
+
+/// The ids in `MODELS`, in the same order
+pub const MODEL_IDS: [&str; MODELS.len()] = {
+    let mut ids = [""; MODELS.len()];
+    let mut i = 0;
+    while i < MODELS.len() {
+        ids[i] = MODELS[i].id;
+        i += 1;
+    }
+    ids
+};
+
+pub fn find(id: &str) -> Option<&'static ModelInfo> {
+    MODELS.iter().find(|model| model.id == id)
+}
+
+/// The model used when nothing else says which
+pub fn recommended() -> &'static ModelInfo {
+    MODELS.iter().find(|model| model.recommended).unwrap_or(&MODELS[0])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tts::{estimate_cost, VALID_MODEL_IDS};
+    use std::path::Path;
+
+    #[test]
+    fn test_table_is_the_source() {
+        assert_eq!(VALID_MODEL_IDS, MODEL_IDS.as_slice());
+        assert_eq!(MODELS.iter().filter(|model| model.recommended).count(), 1);
+        for model in MODELS {
+            let expected = model.price_per_1k_chars * 1000.0;
+            assert!((estimate_cost(1_000_000, model.id) - expected).abs() < 1e-9, "{}", model.id);
+        }
+    }
+
+    #[test]
+    fn test_serialization() {
+        let json = serde_json::to_value(find("tts-1").unwrap()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "id": "tts-1",
+                "name": "Standard",
+                "pricePer1kChars": 0.015,
+                "maxInputChars": 4096,
+                "supportsSpeed": true,
+                "supportsInstructions": false,
+                "recommended": false,
+            })
+        );
+    }
+
+    /// Every model id written as a string literal in the sources must be in the table
+    #[test]
+    fn test_models_named_in_code_exist() {
+        let src = Path::new(file!()).parent().unwrap();
+        let mut checked = 0;
+        for entry in std::fs::read_dir(src).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|extension| extension != "rs") {
+                continue;
+            }
+            let source = std::fs::read_to_string(&path).unwrap();
+            // Words, since a literal can be a list such as shell completions
+            for word in source.split('"').skip(1).step_by(2).flat_map(str::split_whitespace) {
+                let looks_like_model = word.starts_with("tts-1") || (word.starts_with("gpt-") && word.contains("tts"));
+                if looks_like_model {
+                    assert!(find(word).is_some(), "{} names unknown model {:?}", path.display(), word);
+                    checked += 1;
+                }
+            }
+        }
+        assert!(checked > 0);
+    }
+}
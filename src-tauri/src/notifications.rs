@@ -0,0 +1,137 @@
+//! When a finished job is worth a desktop notification, and what it says.
+//! Quick generations the user is watching need none; long ones, or any
+//! that finish while the window is in the background, get one.
+
+use crate::jobs::{JobInfo, JobState};
+use crate::tts::CHARACTERS_PER_SECOND;
+
+/// Default for `Settings::notify_after_secs`
+pub const DEFAULT_NOTIFY_AFTER_SECS: u64 = 10;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    pub title: String,
+    pub body: String,
+}
+
+/// The notification for `job`, if it gets one: only once it is done,
+/// failed or deferred, with notifications `enabled`, and when it ran for at least
+/// `after_secs` or the window wasn't focused
+pub fn for_job(job: &JobInfo, enabled: bool, after_secs: u64, window_focused: bool) -> Option<Notification> {
+    if !enabled {
+        return None;
+    }
+    let took_long = match (job.started_at, job.finished_at) {
+        (Some(started), Some(finished)) => (finished - started).num_milliseconds() >= after_secs as i64 * 1000,
+        _ => false,
+    };
+    if !took_long && window_focused {
+        return None;
+    }
+
+    let name = job.title.as_deref().filter(|title| !title.trim().is_empty());
+    match &job.state {
+        JobState::Done { .. } => Some(Notification {
+            title: format!("{} ready", name.unwrap_or("Speech")),
+            body: format!("{} of audio", format_duration(job.characters as f64 / CHARACTERS_PER_SECOND)),
+        }),
+        JobState::Failed { error, kind } => Some(Notification {
+            title: match name {
+                Some(name) => format!("{} failed", name),
+                None => "Speech generation failed".to_string(),
+            },
+            body: format!("{}: {}", failure_category(kind), error),
+        }),
+        JobState::Deferred { .. } => Some(Notification {
+            title: format!("{} saved for later", name.unwrap_or("Speech")),
+            body: "No connection; it will be generated once you're back online".to_string(),
+        }),
+        JobState::Queued | JobState::Running { .. } | JobState::Parked { .. } | JobState::Cancelled => None,
+    }
+}
+
+/// `TTSError::kind` in words
+fn failure_category(kind: &str) -> &'static str {
+    match kind {
+        "not_configured" => "No API key",
+        "auth" => "Authentication",
+        "rate_limit" => "Rate limited",
+        "validation" => "Invalid request",
+        "network" => "Network",
+        _ => "Error",
+    }
+}
+
+/// "0:42", "18:42" or "1:02:05", rounded to the second
+fn format_duration(seconds: f64) -> String {
+    let total = seconds.round().max(0.0) as u64;
+    let (hours, minutes, seconds) = (total / 3600, total / 60 % 60, total % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, Utc};
+
+    fn job(state: JobState, seconds: i64, title: Option<&str>) -> JobInfo {
+        let started = Utc::now();
+        JobInfo {
+            id: "job".to_string(),
+            sequence: 1,
+            state,
+            voice_id: "nova".to_string(),
+            model: "tts-1".to_string(),
+            // 18:42 of speech
+            characters: 1122 * CHARACTERS_PER_SECOND as usize,
+            title: title.map(str::to_string),
+            downgrade: None,
+            resolved: None,
+            parent_id: None,
+            warnings: Vec::new(),
+            gaps: Default::default(),
+            waiting: None,
+            queue_paused: false,
+            created_at: started,
+            started_at: Some(started),
+            finished_at: Some(started + Duration::seconds(seconds)),
+        }
+    }
+
+    #[test]
+    fn test_when_to_notify() {
+        let done = |seconds| job(JobState::Done { bytes: 1 }, seconds, Some("Chapter 3"));
+        // Quick, and the user is looking at the window
+        assert_eq!(for_job(&done(3), true, 10, true), None);
+        // Quick but in the background, or long while watching
+        assert!(for_job(&done(3), true, 10, false).is_some());
+        assert!(for_job(&done(10), true, 10, true).is_some());
+        // Turned off
+        assert_eq!(for_job(&done(600), false, 10, false), None);
+
+        for state in [JobState::Queued, JobState::Running { progress: 0.5 }, JobState::Cancelled] {
+            assert_eq!(for_job(&job(state, 600, None), true, 10, false), None);
+        }
+    }
+
+    #[test]
+    fn test_messages() {
+        let done = for_job(&job(JobState::Done { bytes: 1 }, 60, Some("Chapter 3")), true, 10, false).unwrap();
+        assert_eq!(done, Notification { title: "Chapter 3 ready".to_string(), body: "18:42 of audio".to_string() });
+
+        let failed = JobState::Failed { error: "Rate limit exceeded".to_string(), kind: "rate_limit".to_string() };
+        let failed = for_job(&job(failed, 60, None), true, 10, false).unwrap();
+        assert_eq!(failed.title, "Speech generation failed");
+        assert_eq!(failed.body, "Rate limited: Rate limit exceeded");
+
+        let deferred = for_job(&job(JobState::Deferred { pending_id: 3 }, 1, Some("Chapter 4")), true, 10, false).unwrap();
+        assert_eq!(deferred.title, "Chapter 4 saved for later");
+
+        assert_eq!(format_duration(42.4), "0:42");
+        assert_eq!(format_duration(3725.0), "1:02:05");
+    }
+}
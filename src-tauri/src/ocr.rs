@@ -0,0 +1,246 @@
+//! Text in images, such as screenshots of a dialog or a photographed page,
+//! read with the `tesseract` command (`tesseract_path` in config.toml). It's
+//! optional like FFmpeg: without it only images can't be read, and
+//! `health::tesseract` says so.
+
+use crate::documents::LoadedText;
+use crate::extract;
+use crate::tts::{GenerationEstimate, TTSService};
+use crate::warnings::{self, Warning};
+use serde::Serialize;
+use std::io::Write;
+use std::path::Path;
+
+/// Image files dropped on the window that are read with OCR
+pub const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "tif", "tiff", "bmp", "gif", "webp"];
+
+/// Mean confidence below which the text is likely to have mistakes
+pub const LOW_CONFIDENCE: f32 = 60.0;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OcrText {
+    pub text: String,
+    /// Tesseract's average confidence in the words, 0 to 100 (see
+    /// `LOW_CONFIDENCE`)
+    pub confidence: f32,
+    pub estimate: GenerationEstimate,
+}
+
+impl OcrText {
+    /// As a document loaded from `source`, warning when the text is doubtful
+    pub fn into_loaded(self, source: String) -> LoadedText {
+        let mut loaded_warnings = Vec::new();
+        if self.confidence < LOW_CONFIDENCE {
+            loaded_warnings.push(Warning::from(warnings::low_confidence(self.confidence)));
+        }
+        LoadedText { character_count: self.text.chars().count(), text: self.text, source, warnings: loaded_warnings }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OcrError {
+    /// Tesseract couldn't be run from this path
+    TesseractMissing(String),
+    /// Tesseract ran and failed, with what it said
+    Failed(String),
+    /// It ran, but found no words
+    NoText,
+}
+
+impl std::fmt::Display for OcrError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OcrError::TesseractMissing(path) => write!(
+                f,
+                "Reading text from images needs Tesseract, but \"{}\" could not be run. Install Tesseract, or set \
+                 tesseract_path in config.toml (or TTS_PLAYER_TESSERACT) to where it is",
+                path
+            ),
+            OcrError::Failed(stderr) => write!(f, "tesseract failed: {}", stderr),
+            OcrError::NoText => write!(f, "No text was found in the image"),
+        }
+    }
+}
+
+impl std::error::Error for OcrError {}
+
+pub fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| IMAGE_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str()))
+}
+
+/// Read the text in the image file at `image`
+pub async fn recognize(tesseract: &str, image: &Path) -> Result<OcrText, OcrError> {
+    let output = tokio::process::Command::new(tesseract)
+        .arg(image)
+        .args(["stdout", "tsv"])
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| {
+            tracing::warn!("Failed to run tesseract ({}): {}", tesseract, e);
+            OcrError::TesseractMissing(tesseract.to_string())
+        })?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        tracing::error!("Tesseract failed with stderr: {}", stderr);
+        return Err(OcrError::Failed(stderr.trim().to_string()));
+    }
+    let (text, confidence) = parse_tsv(&String::from_utf8_lossy(&output.stdout)).ok_or(OcrError::NoText)?;
+    tracing::info!("Recognized {} characters at {:.0}% confidence", text.len(), confidence);
+    let estimate = TTSService::estimate(&text);
+    Ok(OcrText { text, confidence, estimate })
+}
+
+/// Read the text in an image given as RGBA pixels, e.g. from the clipboard
+pub async fn recognize_pixels(tesseract: &str, rgba: &[u8], width: u32, height: u32) -> Result<OcrText, OcrError> {
+    let mut file = tempfile::Builder::new()
+        .suffix(".ppm")
+        .tempfile()
+        .map_err(|e| OcrError::Failed(format!("Failed to write the image: {}", e)))?;
+    file.write_all(&to_ppm(rgba, width, height))
+        .map_err(|e| OcrError::Failed(format!("Failed to write the image: {}", e)))?;
+    recognize(tesseract, file.path()).await
+}
+
+/// Binary PPM, which Tesseract reads without a PNG encoder on our side;
+/// transparency is flattened onto white, as a screenshot would show it
+fn to_ppm(rgba: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let mut ppm = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+    ppm.reserve(rgba.len() / 4 * 3);
+    for pixel in rgba.chunks_exact(4) {
+        let alpha = u16::from(pixel[3]);
+        for &channel in &pixel[..3] {
+            ppm.push(((u16::from(channel) * alpha + 255 * (255 - alpha)) / 255) as u8);
+        }
+    }
+    ppm
+}
+
+/// Text and mean word confidence from `tesseract … tsv` output: words on a
+/// line joined by spaces, lines reflowed into their paragraphs (see
+/// `extract::reflow`), paragraphs separated by a blank line. `None` without
+/// any words.
+fn parse_tsv(tsv: &str) -> Option<(String, f32)> {
+    let mut text = String::new();
+    let mut last_line: Option<(&str, &str, &str, &str)> = None;
+    let mut confidences = Vec::new();
+    // level page block paragraph line word left top width height conf text
+    for row in tsv.lines().skip(1).map(|line| line.split('\t').collect::<Vec<_>>()) {
+        let [level, page, block, paragraph, line, _, _, _, _, _, confidence, word] = row[..] else {
+            continue;
+        };
+        let word = word.trim();
+        if level != "5" || word.is_empty() {
+            continue;
+        }
+        match last_line {
+            None => {}
+            Some((p, b, par, _)) if (p, b, par) != (page, block, paragraph) => text.push_str("\n\n"),
+            Some((.., l)) if l != line => text.push('\n'),
+            Some(_) => text.push(' '),
+        }
+        text.push_str(word);
+        last_line = Some((page, block, paragraph, line));
+        if let Ok(confidence) = confidence.parse::<f32>() {
+            if confidence >= 0.0 {
+                confidences.push(confidence);
+            }
+        }
+    }
+    if text.is_empty() {
+        return None;
+    }
+    let confidence = if confidences.is_empty() { 0.0 } else { confidences.iter().sum::<f32>() / confidences.len() as f32 };
+    Some((extract::reflow(&text), confidence))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "level\tpage_num\tblock_num\tpar_num\tline_num\tword_num\tleft\ttop\twidth\theight\tconf\ttext\n";
+
+    fn word(block: u32, paragraph: u32, line: u32, confidence: f32, text: &str) -> String {
+        format!("5\t1\t{}\t{}\t{}\t1\t0\t0\t10\t10\t{}\t{}\n", block, paragraph, line, confidence, text)
+    }
+
+    #[test]
+    fn test_parse_tsv() {
+        let tsv = [
+            HEADER.to_string(),
+            "1\t1\t0\t0\t0\t0\t0\t0\t640\t480\t-1\t\n".to_string(),
+            word(1, 1, 1, 96.0, "Error"),
+            word(1, 1, 1, 90.0, "saving"),
+            word(1, 1, 2, 94.0, "docu-"),
+            word(1, 1, 3, 80.0, "ment."),
+            "4\t1\t1\t1\t3\t0\t0\t0\t10\t10\t-1\t\n".to_string(),
+            word(2, 1, 1, 70.0, "Retry"),
+            word(2, 1, 1, 70.0, " "),
+        ]
+        .concat();
+        let (text, confidence) = parse_tsv(&tsv).unwrap();
+        assert_eq!(text, "Error saving document.\n\nRetry");
+        assert_eq!(confidence, 86.0);
+
+        assert_eq!(parse_tsv(HEADER), None);
+        assert_eq!(parse_tsv(""), None);
+    }
+
+    #[test]
+    fn test_low_confidence_warns() {
+        let ocr = |confidence| OcrText {
+            text: "Hello".to_string(),
+            confidence,
+            estimate: TTSService::estimate("Hello"),
+        };
+        let loaded = ocr(42.4).into_loaded("scan.png".to_string());
+        assert_eq!((loaded.source.as_str(), loaded.character_count), ("scan.png", 5));
+        assert_eq!(loaded.warnings.len(), 1);
+        assert_eq!(loaded.warnings[0].code, "warning.low_confidence");
+        assert_eq!(loaded.warnings[0].data["confidence"], 42);
+        assert!(ocr(91.0).into_loaded("scan.png".to_string()).warnings.is_empty());
+    }
+
+    #[test]
+    fn test_transparency_is_flattened_onto_white() {
+        let rgba = [0, 0, 0, 255, 0, 0, 0, 0, 200, 100, 0, 128];
+        let ppm = to_ppm(&rgba, 3, 1);
+        assert_eq!(&ppm[..11], b"P6\n3 1\n255\n");
+        assert_eq!(&ppm[11..], [0, 0, 0, 255, 255, 255, 227, 177, 127]);
+    }
+
+    #[test]
+    fn test_image_extensions() {
+        assert!(is_image(Path::new("Screenshot 2024-05-01.PNG")));
+        assert!(is_image(Path::new("page.jpeg")));
+        assert!(!is_image(Path::new("notes.txt")));
+        assert!(!is_image(Path::new("png")));
+    }
+
+    #[tokio::test]
+    async fn test_missing_tesseract() {
+        let err = recognize("/nonexistent/tesseract", Path::new("hello-world.png")).await.unwrap_err();
+        assert_eq!(err, OcrError::TesseractMissing("/nonexistent/tesseract".to_string()));
+        assert!(err.to_string().contains("tesseract_path"), "{}", err);
+    }
+
+    /// Needs Tesseract installed; skipped (passing) without it
+    #[tokio::test]
+    async fn test_recognizes_fixture() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let fixture = dir.path().join("hello-world.png");
+        std::fs::write(&fixture, include_bytes!("../tests/fixtures/hello-world.png")).unwrap();
+        let ocr = match recognize("tesseract", &fixture).await {
+            Err(OcrError::TesseractMissing(_)) => {
+                eprintln!("tesseract not installed; skipping");
+                return;
+            }
+            result => result.unwrap(),
+        };
+        assert_eq!(ocr.text.to_uppercase(), "HELLO WORLD", "{:?}", ocr.text);
+        assert!(ocr.confidence > 50.0, "{}", ocr.confidence);
+    }
+}
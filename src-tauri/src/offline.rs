@@ -0,0 +1,168 @@
+//! Generations that failed because there was no connection can wait in the
+//! `pending_jobs` table instead of being lost. `main` probes the API every
+//! so often and, once it answers, works through them in order.
+
+use crate::database::{Database, PendingJob};
+use crate::tts::{SpeechBackend, TTSError};
+use anyhow::Result;
+use std::time::Duration;
+
+/// How often to check whether the connection is back while jobs wait
+pub const PROBE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Whether a failed generation should be kept for later: only when the
+/// server couldn't be reached, and the request's own `flag` wins over the
+/// `queue_when_offline` setting
+pub fn should_queue(error: &TTSError, flag: Option<bool>, setting: bool) -> bool {
+    error.is_offline() && flag.unwrap_or(setting)
+}
+
+/// What became of a flush
+#[derive(Debug, Default, PartialEq)]
+pub struct Flushed {
+    pub completed: usize,
+    pub failed: usize,
+    /// The connection dropped again; the rest are still waiting
+    pub interrupted: bool,
+}
+
+/// Generate the waiting jobs one at a time in order, handing each result
+/// to `finished`. A job leaves the queue once it has an answer, even a
+/// failure, unless the connection went away again, which stops the flush.
+/// The queue is read afresh before each job, so reordering or deleting
+/// while it runs takes effect.
+pub async fn flush<B: SpeechBackend>(
+    backend: &B,
+    database: &Database,
+    mut finished: impl FnMut(&PendingJob, Result<Vec<u8>, TTSError>),
+) -> Result<Flushed> {
+    let mut flushed = Flushed::default();
+    while let Some(job) = database.next_pending_job().await? {
+        let result = backend.synthesize_as(&job.source, &job.text, &job.voice_id, &job.model_id).await;
+        if let Err(e) = &result {
+            if e.is_offline() {
+                tracing::info!("Still offline; {} stays queued", job.id);
+                flushed.interrupted = true;
+                break;
+            }
+            tracing::warn!("Pending job {} failed: {}", job.id, e);
+            flushed.failed += 1;
+        } else {
+            flushed.completed += 1;
+        }
+        database.delete_pending_job(job.id).await?;
+        finished(&job, result);
+    }
+    Ok(flushed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::FakeBackend;
+    use crate::tts::UNREACHABLE;
+
+    fn offline() -> TTSError {
+        TTSError::NetworkError(format!("{}: error sending request", UNREACHABLE))
+    }
+
+    async fn database(dir: &tempfile::TempDir, texts: &[&str]) -> Database {
+        let database = Database::open(&dir.path().join("pending.db")).await.unwrap();
+        for text in texts {
+            database.add_pending_job(text, "nova", "tts-1", "app").await.unwrap();
+        }
+        database
+    }
+
+    async fn waiting(database: &Database) -> Vec<String> {
+        database.get_pending_jobs().await.unwrap().into_iter().map(|job| job.text).collect()
+    }
+
+    #[test]
+    fn test_only_connectivity_failures_are_queued() {
+        assert!(should_queue(&offline(), None, true));
+        assert!(should_queue(&offline(), Some(true), false));
+        // The request's flag wins over the setting
+        assert!(!should_queue(&offline(), Some(false), true));
+        assert!(!should_queue(&offline(), None, false));
+
+        for error in [
+            TTSError::NetworkError("Failed to read response: connection reset".to_string()),
+            TTSError::RateLimit(Some(20)),
+            TTSError::Authentication("bad key".to_string()),
+            TTSError::NotConfigured,
+        ] {
+            assert!(!should_queue(&error, Some(true), true), "{}", error);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_server_counts_as_offline() {
+        // Nothing listens on the discard port
+        let service = crate::tts::TTSService::new("sk-test", "http://127.0.0.1:9");
+        let error = service.generate_speech_with_model("Hello", "nova", "tts-1").await.unwrap_err();
+        assert!(error.is_offline(), "{}", error);
+        assert!(!service.is_reachable().await);
+    }
+
+    #[tokio::test]
+    async fn test_flush_runs_in_queue_order() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let database = database(&dir, &["first", "second", "third"]).await;
+        let ids: Vec<i64> = database.get_pending_jobs().await.unwrap().iter().map(|job| job.id).collect();
+        database.reorder_pending_jobs(&[ids[2], ids[0], ids[1]]).await.unwrap();
+
+        let backend = FakeBackend::scripted(vec![
+            Ok(b"3".to_vec()),
+            Err(TTSError::ValidationError("too long".to_string())),
+            Ok(b"2".to_vec()),
+        ]);
+        let mut results = Vec::new();
+        let flushed = flush(&backend, &database, |job, result| results.push((job.text.clone(), result.is_ok())))
+            .await
+            .unwrap();
+
+        assert_eq!(*backend.calls.lock().unwrap(), ["third", "first", "second"]);
+        assert_eq!(
+            results,
+            [("third".to_string(), true), ("first".to_string(), false), ("second".to_string(), true)]
+        );
+        assert_eq!(flushed, Flushed { completed: 2, failed: 1, interrupted: false });
+        assert!(waiting(&database).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_flush_stops_when_offline_again() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let database = database(&dir, &["first", "second", "third"]).await;
+
+        let backend = FakeBackend::scripted(vec![Ok(b"1".to_vec()), Err(offline())]);
+        let mut finished = Vec::new();
+        let flushed = flush(&backend, &database, |job, _| finished.push(job.text.clone())).await.unwrap();
+
+        assert_eq!(finished, ["first"]);
+        assert!(flushed.interrupted);
+        // Still first in line for the next flush
+        assert_eq!(waiting(&database).await, ["second", "third"]);
+    }
+
+    #[tokio::test]
+    async fn test_reorder_must_name_every_job() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let database = database(&dir, &["first", "second"]).await;
+        let ids: Vec<i64> = database.get_pending_jobs().await.unwrap().iter().map(|job| job.id).collect();
+
+        assert!(database.reorder_pending_jobs(&[ids[1]]).await.is_err());
+        assert!(database.reorder_pending_jobs(&[ids[1], ids[1]]).await.is_err());
+        assert_eq!(waiting(&database).await, ["first", "second"]);
+
+        database.reorder_pending_jobs(&[ids[1], ids[0]]).await.unwrap();
+        assert_eq!(waiting(&database).await, ["second", "first"]);
+        // New jobs join the end of the line
+        database.add_pending_job("third", "nova", "tts-1", "app").await.unwrap();
+        assert_eq!(waiting(&database).await, ["second", "first", "third"]);
+        assert!(database.delete_pending_job(ids[0]).await.unwrap());
+        assert!(!database.delete_pending_job(ids[0]).await.unwrap());
+        assert_eq!(waiting(&database).await, ["second", "third"]);
+    }
+}
@@ -0,0 +1,92 @@
+//! The audio format to ask for when the settings say "auto". Opus is about
+//! a third the size of MP3 for speech and sounds the same, which matters
+//! for files on disk and clips sent inline over IPC, but not every player
+//! takes it: the webviews on Windows (WebView2) and Linux (WebKitGTK with
+//! GStreamer) do, WKWebView on macOS needs AAC or MP3, and the decoder
+//! behind the CLI's `--play` is built with MP3, WAV and FLAC only (see
+//! `playback::PLAYABLE_FORMATS`). A format set explicitly always wins.
+
+/// The `format` setting that leaves the choice to `choose`
+pub const AUTO: &str = "auto";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Platform {
+    Windows,
+    MacOs,
+    Linux,
+    Other,
+}
+
+impl Platform {
+    pub fn current() -> Self {
+        if cfg!(target_os = "windows") {
+            Platform::Windows
+        } else if cfg!(target_os = "macos") {
+            Platform::MacOs
+        } else if cfg!(target_os = "linux") {
+            Platform::Linux
+        } else {
+            Platform::Other
+        }
+    }
+}
+
+/// What will play the audio
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Player {
+    /// An `<audio>` element in the window
+    Webview,
+    /// The built-in decoder, for `--play` and the CLI's files
+    Decoder,
+}
+
+/// The format to generate in for the `format` setting `setting`
+pub fn choose(setting: &str, platform: Platform, player: Player) -> &str {
+    if setting != AUTO {
+        return setting;
+    }
+    match (player, platform) {
+        (Player::Webview, Platform::Windows | Platform::Linux) => "opus",
+        (Player::Webview, Platform::MacOs) => "aac",
+        (Player::Webview, Platform::Other) | (Player::Decoder, _) => "mp3",
+    }
+}
+
+/// `choose` for the window on this platform
+pub fn for_window(setting: &str) -> &str {
+    choose(setting, Platform::current(), Player::Webview)
+}
+
+/// `choose` for the CLI on this platform
+pub fn for_cli(setting: &str) -> &str {
+    choose(setting, Platform::current(), Player::Decoder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SUPPORTED_FORMATS;
+    use crate::playback::PLAYABLE_FORMATS;
+
+    const PLATFORMS: [Platform; 4] = [Platform::Windows, Platform::MacOs, Platform::Linux, Platform::Other];
+
+    #[test]
+    fn test_auto_per_platform_and_player() {
+        let webview: Vec<_> = PLATFORMS.iter().map(|&platform| choose(AUTO, platform, Player::Webview)).collect();
+        assert_eq!(webview, ["opus", "aac", "opus", "mp3"]);
+        for platform in PLATFORMS {
+            let format = choose(AUTO, platform, Player::Decoder);
+            assert!(PLAYABLE_FORMATS.contains(&format), "{:?}: {}", platform, format);
+        }
+    }
+
+    #[test]
+    fn test_explicit_format_always_wins() {
+        for &format in SUPPORTED_FORMATS {
+            for platform in PLATFORMS {
+                assert_eq!(choose(format, platform, Player::Webview), format);
+                assert_eq!(choose(format, platform, Player::Decoder), format);
+            }
+        }
+    }
+}
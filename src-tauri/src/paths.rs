@@ -0,0 +1,191 @@
+//! Where the app keeps its files, worked out in one place. Everything that
+//! lasts goes under the data directory (`~/.tts-player` unless `data_dir`
+//! says otherwise); generated audio and silence are scratch files, kept
+//! apart under the scratch root (see `scratch_dir`).
+
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Name of the usage database in the data directory
+pub const DATABASE_FILE: &str = "tts_usage.db";
+
+/// The data directory when neither config nor environment name one
+pub fn default_data_dir() -> PathBuf {
+    dirs::home_dir().unwrap_or_else(std::env::temp_dir).join(".tts-player")
+}
+
+pub fn database(data_dir: &Path) -> PathBuf {
+    data_dir.join(DATABASE_FILE)
+}
+
+/// Project audio, kept across runs so exports don't generate it again
+pub fn library_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("project-audio")
+}
+
+/// Audio of jobs still generating, kept until they finish so an
+/// interrupted one can pick up where it stopped (see `recovery`)
+pub fn job_chunks_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("job-chunks")
+}
+
+/// Audio of the last few generations, kept to play again after the
+/// window reloads (see `recent`)
+pub fn recent_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("recent-audio")
+}
+
+/// `config.toml` in the platform config directory
+pub fn config_file() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("tts-player").join("config.toml"))
+}
+
+/// Where the scratch directory `name` goes. On Windows that is under the
+/// per-user local app data, whose ACL already keeps other users out,
+/// rather than a temp directory (see `permissions`).
+pub fn scratch_dir(name: &str) -> PathBuf {
+    scratch_root().join(name)
+}
+
+#[cfg(windows)]
+fn scratch_root() -> PathBuf {
+    match dirs::data_local_dir() {
+        Some(local) => local.join("tts-player").join("temp"),
+        None => std::env::temp_dir(),
+    }
+}
+
+#[cfg(not(windows))]
+fn scratch_root() -> PathBuf {
+    std::env::temp_dir()
+}
+
+/// Generated audio, streamed over `ttsaudio://` (see `FileManager`)
+pub fn audio_dir() -> PathBuf {
+    scratch_dir("tts-player")
+}
+
+/// Silence between segments (see `SilenceCache`)
+pub fn silence_dir() -> PathBuf {
+    scratch_dir("tts-player-silence")
+}
+
+/// Where other apps hand text over to be read aloud; the system temp
+/// directory on every platform, since that's where they can write
+pub fn handoff_dir() -> PathBuf {
+    std::env::temp_dir()
+}
+
+/// Every location the app uses, for a data directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppPaths {
+    pub data_dir: PathBuf,
+    pub database: PathBuf,
+    pub config_file: Option<PathBuf>,
+    pub library_dir: PathBuf,
+    pub job_chunks_dir: PathBuf,
+    pub recent_dir: PathBuf,
+    pub audio_dir: PathBuf,
+    pub silence_dir: PathBuf,
+}
+
+impl AppPaths {
+    pub fn new(data_dir: &Path) -> Self {
+        Self {
+            data_dir: data_dir.to_path_buf(),
+            database: database(data_dir),
+            config_file: config_file(),
+            library_dir: library_dir(data_dir),
+            job_chunks_dir: job_chunks_dir(data_dir),
+            recent_dir: recent_dir(data_dir),
+            audio_dir: audio_dir(),
+            silence_dir: silence_dir(),
+        }
+    }
+
+    /// Each location, whether it exists and how much is in it. Reads the
+    /// directories through, so not for the async runtime's threads.
+    pub fn report(&self) -> Vec<AppPath> {
+        let mut locations = vec![("dataDir", &self.data_dir), ("database", &self.database)];
+        locations.extend(self.config_file.iter().map(|path| ("configFile", path)));
+        locations.extend([
+            ("libraryDir", &self.library_dir),
+            ("jobChunksDir", &self.job_chunks_dir),
+            ("recentDir", &self.recent_dir),
+            ("audioDir", &self.audio_dir),
+            ("silenceDir", &self.silence_dir),
+        ]);
+        locations
+            .into_iter()
+            .map(|(name, path)| AppPath { name, path: path.clone(), exists: path.exists(), size: size(path) })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppPath {
+    /// e.g. "database"
+    pub name: &'static str,
+    pub path: PathBuf,
+    pub exists: bool,
+    /// Bytes, everything inside counted for a directory; `None` when missing
+    pub size: Option<u64>,
+}
+
+/// Links aren't followed, so nothing is counted twice
+fn size(path: &Path) -> Option<u64> {
+    let metadata = path.symlink_metadata().ok()?;
+    if !metadata.is_dir() {
+        return Some(metadata.len());
+    }
+    let entries = std::fs::read_dir(path).ok()?;
+    Some(entries.filter_map(|entry| entry.ok()).filter_map(|entry| size(&entry.path())).sum())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_everything_agrees_on_the_locations() {
+        let config = crate::config::Config::default();
+        let paths = AppPaths::new(&config.data_dir);
+        assert_eq!(config.data_dir, default_data_dir());
+        assert_eq!(config.database_path(), paths.database);
+        assert_eq!(crate::database::Database::default_path(), paths.database);
+        assert_eq!(crate::config::Config::default_path(), paths.config_file);
+        assert_eq!(crate::file_manager::FileManager::new().dir(), paths.audio_dir);
+        assert!(paths.database.starts_with(&paths.data_dir));
+        assert!(paths.library_dir.starts_with(&paths.data_dir));
+        assert!(paths.job_chunks_dir.starts_with(&paths.data_dir));
+        assert!(paths.recent_dir.starts_with(&paths.data_dir));
+        assert_eq!(paths.audio_dir.parent(), paths.silence_dir.parent());
+    }
+
+    #[test]
+    fn test_report_sizes() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let data_dir = temp_dir.path().join(".tts-player");
+        let paths = AppPaths::new(&data_dir);
+        std::fs::create_dir_all(paths.library_dir.join("1")).unwrap();
+        std::fs::write(&paths.database, [0; 100]).unwrap();
+        std::fs::write(paths.library_dir.join("1").join("item.mp3"), [0; 20]).unwrap();
+
+        let report = paths.report();
+        let find = |name: &str| report.iter().find(|path| path.name == name).unwrap().clone();
+        assert_eq!(find("dataDir").size, Some(120));
+        assert_eq!(find("database"), AppPath { name: "database", path: paths.database.clone(), exists: true, size: Some(100) });
+        assert_eq!(find("libraryDir").size, Some(20));
+
+        let missing = AppPaths::new(&temp_dir.path().join("elsewhere")).report();
+        assert!(missing.iter().find(|path| path.name == "database").is_some_and(|path| !path.exists && path.size.is_none()));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_scratch_files_stay_in_the_user_profile() {
+        let local = PathBuf::from(std::env::var("LOCALAPPDATA").unwrap());
+        assert_eq!(scratch_dir("tts-player"), local.join("tts-player").join("temp").join("tts-player"));
+    }
+}
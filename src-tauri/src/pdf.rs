@@ -0,0 +1,122 @@
+//! Text from PDFs, page by page, through `pdf-extract` (pure Rust, no
+//! poppler). Layout cleanup is shared with the other formats in `extract`.
+
+use crate::extract::{self, ExtractError, ExtractedText};
+use pdf_extract::{Document, PlainTextOutput};
+use std::panic::AssertUnwindSafe;
+use std::path::Path;
+
+/// Extract pages `first_page..=last_page` (1-based; the whole document by
+/// default). With `reflow`, lines broken mid-paragraph are joined (see
+/// `extract::reflow`); without, each page keeps the layout's line breaks.
+pub fn extract_text(path: &Path, first_page: Option<usize>, last_page: Option<usize>, reflow: bool) -> Result<ExtractedText, ExtractError> {
+    let bytes = std::fs::read(path).map_err(|e| ExtractError::Io(e.to_string()))?;
+    extract_text_from_bytes(&bytes, first_page, last_page, reflow)
+}
+
+pub fn extract_text_from_bytes(
+    bytes: &[u8],
+    first_page: Option<usize>,
+    last_page: Option<usize>,
+    reflow: bool,
+) -> Result<ExtractedText, ExtractError> {
+    let mut doc = Document::load_mem(bytes).map_err(|e| ExtractError::Unreadable(e.to_string()))?;
+    // Many PDFs are "encrypted" only to set permissions and open without a password
+    if doc.is_encrypted() && doc.decrypt("").is_err() {
+        return Err(ExtractError::Encrypted);
+    }
+
+    let pages = doc.get_pages();
+    let (first_page, last_page) = extract::page_range(first_page, last_page, pages.len())?;
+    let mut texts = Vec::with_capacity(last_page - first_page + 1);
+    let mut length = 0;
+    for (&number, _) in pages.range(first_page as u32..=last_page as u32) {
+        let text = page_text(&doc, number)?;
+        length += text.len();
+        extract::check_length(length)?;
+        texts.push(text);
+    }
+
+    if texts.iter().all(|text| text.trim().is_empty()) {
+        return Err(ExtractError::NoText);
+    }
+    extract::strip_running_lines(&mut texts);
+    let text = texts
+        .iter()
+        .map(|page| if reflow { extract::reflow(page) } else { page.trim().to_string() })
+        .filter(|page| !page.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    Ok(ExtractedText::new(text, pages.len(), first_page, last_page))
+}
+
+fn page_text(doc: &Document, number: u32) -> Result<String, ExtractError> {
+    let mut text = String::new();
+    // pdf-extract panics on some malformed fonts rather than returning an error
+    let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut output = PlainTextOutput::new(&mut text);
+        pdf_extract::output_doc_page(doc, &mut output, number)
+    }));
+    match result {
+        Ok(Ok(())) => Ok(text),
+        Ok(Err(e)) => Err(ExtractError::Unreadable(format!("page {}: {}", number, e))),
+        Err(_) => Err(ExtractError::Unreadable(format!("page {} could not be parsed", number))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIELD_NOTES: &[u8] = include_bytes!("../tests/fixtures/field-notes.pdf");
+    const NO_TEXT: &[u8] = include_bytes!("../tests/fixtures/no-text.pdf");
+    const ENCRYPTED: &[u8] = include_bytes!("../tests/fixtures/encrypted.pdf");
+
+    #[test]
+    fn test_extracts_and_cleans_up_layout() {
+        let extracted = extract_text_from_bytes(FIELD_NOTES, None, None, true).unwrap();
+        assert_eq!(extracted.page_count, 3);
+        assert_eq!((extracted.first_page, extracted.last_page), (1, 3));
+        assert_eq!(
+            extracted.text,
+            "The expedition set out before dawn, carrying enough water for three days and a well-known map of the valley.\n\n\
+             By noon the path had narrowed to a ledge.\n\n\
+             They camped beside the river, where the current was slow enough to fill their bottles without wading in.\n\n\
+             On the third morning they reached the pass."
+        );
+        assert_eq!(extracted.estimate.character_count, extracted.text.len());
+    }
+
+    #[test]
+    fn test_layout_kept_without_reflow() {
+        let reflowed = extract_text_from_bytes(FIELD_NOTES, None, None, true).unwrap();
+        let raw = extract_text_from_bytes(FIELD_NOTES, None, None, false).unwrap();
+        assert_ne!(raw.text, reflowed.text);
+        assert!(raw.text.contains("carry-\n"), "{:?}", raw.text);
+        assert_eq!(extract::reflow(&raw.text), reflowed.text);
+    }
+
+    #[test]
+    fn test_page_range() {
+        let extracted = extract_text_from_bytes(FIELD_NOTES, Some(2), Some(2), true).unwrap();
+        assert_eq!(extracted.page_count, 3);
+        assert!(extracted.text.starts_with("Field Notes\n\nThey camped"));
+
+        let err = extract_text_from_bytes(FIELD_NOTES, Some(2), Some(9), true).unwrap_err();
+        assert_eq!(err, ExtractError::InvalidPageRange { first: 2, last: 9, page_count: 3 });
+    }
+
+    #[test]
+    fn test_distinct_errors() {
+        assert_eq!(extract_text_from_bytes(NO_TEXT, None, None, true).unwrap_err(), ExtractError::NoText);
+        assert_eq!(extract_text_from_bytes(ENCRYPTED, None, None, true).unwrap_err(), ExtractError::Encrypted);
+        assert!(matches!(
+            extract_text_from_bytes(b"not a pdf", None, None, true),
+            Err(ExtractError::Unreadable(_))
+        ));
+        assert!(matches!(
+            extract_text(Path::new("/nonexistent/file.pdf"), None, None, true),
+            Err(ExtractError::Io(_))
+        ));
+    }
+}
@@ -0,0 +1,149 @@
+//! Keeping the app's files to the user who made them. The usage database
+//! holds a preview of everything read aloud and the scratch directories the
+//! audio of it, so on unix directories are created 0700 and the database
+//! made 0600, whatever the umask. On Windows scratch files go under the
+//! per-user local app data instead (see `paths::scratch_dir`).
+
+use std::io;
+use std::path::Path;
+
+/// Owner only
+#[cfg(unix)]
+pub const DIR_MODE: u32 = 0o700;
+#[cfg(unix)]
+pub const FILE_MODE: u32 = 0o600;
+
+/// `create_dir_all`, creating what is missing owner-only and tightening
+/// `dir` itself if it already existed. Directories above it that existed
+/// already, like `/tmp`, are left alone.
+pub fn create_private_dir(dir: &Path) -> io::Result<()> {
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, DIR_MODE);
+    builder.create(dir)?;
+    restrict_dir(dir)?;
+    Ok(())
+}
+
+/// Make `dir` owner-only. Whether it had to be changed.
+pub fn restrict_dir(dir: &Path) -> io::Result<bool> {
+    #[cfg(unix)]
+    {
+        set_mode(dir, DIR_MODE)
+    }
+    #[cfg(not(unix))]
+    {
+        dir.metadata().map(|_| false)
+    }
+}
+
+/// Make `file` readable and writable by its owner only. Whether it had to
+/// be changed.
+pub fn restrict_file(file: &Path) -> io::Result<bool> {
+    #[cfg(unix)]
+    {
+        set_mode(file, FILE_MODE)
+    }
+    #[cfg(not(unix))]
+    {
+        file.metadata().map(|_| false)
+    }
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> io::Result<bool> {
+    use std::os::unix::fs::PermissionsExt;
+    if path.metadata()?.permissions().mode() & 0o777 == mode {
+        return Ok(false);
+    }
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    Ok(true)
+}
+
+/// Check the app's existing `dirs` and `files`, tightening any others can
+/// read. What couldn't be fixed, e.g. a directory another user owns; paths
+/// that don't exist yet are skipped.
+pub fn repair(dirs: &[&Path], files: &[&Path]) -> Vec<String> {
+    let dirs = dirs.iter().map(|path| (path, restrict_dir as fn(&Path) -> io::Result<bool>));
+    let files = files.iter().map(|path| (path, restrict_file as fn(&Path) -> io::Result<bool>));
+    let mut problems = Vec::new();
+    for (path, restrict) in dirs.chain(files).filter(|(path, _)| path.exists()) {
+        match restrict(path) {
+            Ok(true) => tracing::warn!("{} could be read by other users; now it can't", path.display()),
+            Ok(false) => {}
+            Err(e) => problems.push(format!("Couldn't restrict access to {}: {}", path.display(), e)),
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    fn mode(path: &Path) -> u32 {
+        use std::os::unix::fs::PermissionsExt;
+        path.metadata().unwrap().permissions().mode() & 0o777
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_private_dirs_and_files() {
+        use std::os::unix::fs::PermissionsExt;
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        std::fs::set_permissions(temp_dir.path(), std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let nested = temp_dir.path().join("data").join("audio");
+        create_private_dir(&nested).unwrap();
+        assert_eq!(mode(&nested), 0o700);
+        assert_eq!(mode(&temp_dir.path().join("data")), 0o700);
+        // What was there already is left as it was
+        assert_eq!(mode(temp_dir.path()), 0o755);
+
+        let open = temp_dir.path().join("open");
+        std::fs::create_dir(&open).unwrap();
+        std::fs::set_permissions(&open, std::fs::Permissions::from_mode(0o777)).unwrap();
+        create_private_dir(&open).unwrap();
+        assert_eq!(mode(&open), 0o700);
+
+        let file = nested.join("usage.db");
+        std::fs::write(&file, "").unwrap();
+        std::fs::set_permissions(&file, std::fs::Permissions::from_mode(0o644)).unwrap();
+        assert!(restrict_file(&file).unwrap());
+        assert_eq!(mode(&file), 0o600);
+        assert!(!restrict_file(&file).unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_repair_tightens_what_is_open() {
+        use std::os::unix::fs::PermissionsExt;
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dir = temp_dir.path().join("tts-player");
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let database = temp_dir.path().join("tts_usage.db");
+        std::fs::write(&database, "").unwrap();
+        std::fs::set_permissions(&database, std::fs::Permissions::from_mode(0o664)).unwrap();
+
+        let missing = temp_dir.path().join("not-yet");
+        assert!(repair(&[&dir, &missing], &[&database]).is_empty());
+        assert_eq!((mode(&dir), mode(&database)), (0o700, 0o600));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_database_and_audio_are_private() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let data_dir = temp_dir.path().join(".tts-player");
+        crate::database::Database::open(&data_dir.join("tts_usage.db")).await.unwrap();
+        assert_eq!(mode(&data_dir), 0o700);
+        assert_eq!(mode(&data_dir.join("tts_usage.db")), 0o600);
+
+        let files = crate::file_manager::FileManager::with_dir(temp_dir.path().join("tts-player"));
+        files.save_audio(&[1, 2, 3], "mp3").await.unwrap();
+        assert_eq!(mode(files.dir()), 0o700);
+    }
+}
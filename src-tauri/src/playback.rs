@@ -0,0 +1,212 @@
+//! Play generated audio through an output device from the CLI (`--play`).
+//! Decoding is always available; the device side needs the `playback`
+//! feature (on by default) and is kept behind `AudioSink` so the playback
+//! loop can be tested without audio hardware.
+
+use std::io::Cursor;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// Formats the decoder handles; the others have to be saved and played elsewhere
+pub const PLAYABLE_FORMATS: &[&str] = &["mp3", "wav", "flac"];
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+pub type DecodedAudio = rodio::Decoder<Cursor<Vec<u8>>>;
+
+/// Where decoded audio goes; a rodio `Sink` in the app, a fake in tests.
+pub trait AudioSink {
+    fn append(&mut self, source: DecodedAudio);
+    /// True once everything appended has been played
+    fn is_empty(&self) -> bool;
+    fn stop(&mut self);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaybackEnd {
+    Finished,
+    Interrupted,
+}
+
+pub fn decode(audio: Vec<u8>) -> Result<DecodedAudio, String> {
+    rodio::Decoder::new(Cursor::new(audio)).map_err(|e| format!("Failed to decode audio: {}", e))
+}
+
+/// Play `audio` on `sink`, blocking until it ends or `stop` is set
+/// (by the Ctrl-C handler), in which case playback is cut off cleanly.
+pub fn play_until_done(sink: &mut dyn AudioSink, audio: Vec<u8>, stop: &AtomicBool) -> Result<PlaybackEnd, String> {
+    sink.append(decode(audio)?);
+    loop {
+        if sink.is_empty() {
+            return Ok(PlaybackEnd::Finished);
+        }
+        if stop.load(Ordering::SeqCst) {
+            sink.stop();
+            return Ok(PlaybackEnd::Interrupted);
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+#[cfg(feature = "playback")]
+mod device {
+    use super::{AudioSink, DecodedAudio};
+    use rodio::cpal::traits::{DeviceTrait, HostTrait};
+
+    /// The default output device, or the one named `device`
+    pub struct RodioSink {
+        // Dropping the stream ends playback, so it lives as long as the sink
+        _stream: rodio::OutputStream,
+        sink: rodio::Sink,
+    }
+
+    impl RodioSink {
+        pub fn open(device: Option<&str>) -> Result<Self, String> {
+            let mut stream = match device {
+                None => rodio::OutputStreamBuilder::open_default_stream(),
+                Some(name) => {
+                    let device = find_device(name)?;
+                    rodio::OutputStreamBuilder::from_device(device).and_then(|builder| builder.open_stream())
+                }
+            }
+            .map_err(|e| format!("Failed to open audio output: {}", e))?;
+            stream.log_on_drop(false);
+
+            let sink = rodio::Sink::connect_new(stream.mixer());
+            Ok(Self { _stream: stream, sink })
+        }
+    }
+
+    impl AudioSink for RodioSink {
+        fn append(&mut self, source: DecodedAudio) {
+            self.sink.append(source);
+        }
+
+        fn is_empty(&self) -> bool {
+            self.sink.empty()
+        }
+
+        fn stop(&mut self) {
+            self.sink.stop();
+        }
+    }
+
+    pub fn output_devices() -> Result<Vec<String>, String> {
+        let devices = rodio::cpal::default_host()
+            .output_devices()
+            .map_err(|e| format!("Failed to list audio outputs: {}", e))?;
+        Ok(devices.filter_map(|device| device.name().ok()).collect())
+    }
+
+    fn find_device(name: &str) -> Result<rodio::cpal::Device, String> {
+        let devices = rodio::cpal::default_host()
+            .output_devices()
+            .map_err(|e| format!("Failed to list audio outputs: {}", e))?;
+        for device in devices {
+            if device.name().is_ok_and(|n| n == name) {
+                return Ok(device);
+            }
+        }
+        let available = output_devices().unwrap_or_default().join(", ");
+        Err(format!("Unknown output device '{}'. Available: {}", name, available))
+    }
+}
+
+#[cfg(feature = "playback")]
+pub use device::{output_devices, RodioSink};
+
+#[cfg(feature = "playback")]
+pub fn play(device: Option<&str>, audio: Vec<u8>, stop: &AtomicBool) -> Result<PlaybackEnd, String> {
+    let mut sink = RodioSink::open(device)?;
+    play_until_done(&mut sink, audio, stop)
+}
+
+#[cfg(not(feature = "playback"))]
+pub fn play(_device: Option<&str>, _audio: Vec<u8>, _stop: &AtomicBool) -> Result<PlaybackEnd, String> {
+    Err("This build has no audio playback support".to_string())
+}
+
+#[cfg(not(feature = "playback"))]
+pub fn output_devices() -> Result<Vec<String>, String> {
+    Err("This build has no audio playback support".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::Source;
+
+    /// A mono 16-bit WAV of `samples` silent samples at 8 kHz
+    fn silent_wav(samples: u32) -> Vec<u8> {
+        let data_len = samples * 2;
+        let mut wav = Vec::new();
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        wav.extend_from_slice(&1u16.to_le_bytes()); // mono
+        wav.extend_from_slice(&8000u32.to_le_bytes());
+        wav.extend_from_slice(&16000u32.to_le_bytes());
+        wav.extend_from_slice(&2u16.to_le_bytes());
+        wav.extend_from_slice(&16u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&data_len.to_le_bytes());
+        wav.resize(wav.len() + data_len as usize, 0);
+        wav
+    }
+
+    /// Pretends each poll plays one chunk; empties after `polls_to_finish`
+    struct FakeSink {
+        appended: Vec<Option<Duration>>,
+        polls_left: std::cell::Cell<u32>,
+        stopped: bool,
+    }
+
+    impl FakeSink {
+        fn new(polls_to_finish: u32) -> Self {
+            Self { appended: Vec::new(), polls_left: std::cell::Cell::new(polls_to_finish), stopped: false }
+        }
+    }
+
+    impl AudioSink for FakeSink {
+        fn append(&mut self, source: DecodedAudio) {
+            self.appended.push(source.total_duration());
+        }
+
+        fn is_empty(&self) -> bool {
+            let left = self.polls_left.get();
+            self.polls_left.set(left.saturating_sub(1));
+            left == 0 || self.stopped
+        }
+
+        fn stop(&mut self) {
+            self.stopped = true;
+        }
+    }
+
+    #[test]
+    fn test_plays_until_finished() {
+        let mut sink = FakeSink::new(2);
+        let end = play_until_done(&mut sink, silent_wav(8000), &AtomicBool::new(false)).unwrap();
+        assert_eq!(end, PlaybackEnd::Finished);
+        assert_eq!(sink.appended, vec![Some(Duration::from_secs(1))]);
+        assert!(!sink.stopped);
+    }
+
+    #[test]
+    fn test_stop_flag_interrupts() {
+        let mut sink = FakeSink::new(u32::MAX);
+        let end = play_until_done(&mut sink, silent_wav(800), &AtomicBool::new(true)).unwrap();
+        assert_eq!(end, PlaybackEnd::Interrupted);
+        assert!(sink.stopped);
+    }
+
+    #[test]
+    fn test_undecodable_audio_is_an_error() {
+        let mut sink = FakeSink::new(0);
+        let err = play_until_done(&mut sink, b"not audio".to_vec(), &AtomicBool::new(false)).unwrap_err();
+        assert!(err.starts_with("Failed to decode audio"));
+        assert!(sink.appended.is_empty());
+    }
+}
@@ -0,0 +1,453 @@
+//! The window's audio player. Audio played here keeps going while the
+//! window is hidden in the tray or busy, unlike the webview's `<audio>`
+//! element. One stream at a time: playing something stops what was playing.
+//! The device sits behind `Output`, so the player can be tested without one.
+
+use crate::playback::{decode, DecodedAudio};
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// How often the window is sent the position while something plays
+pub const POSITION_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Where the audio to play comes from
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum PlaySource {
+    /// A finished job whose audio hasn't been taken yet
+    Job { id: String },
+    /// Generated audio the window holds, by `GeneratedAudio::id`
+    Audio { id: String },
+    /// An audio file anywhere on disk
+    Path { path: String },
+}
+
+/// The audio device side; rodio in the app, a fake in tests
+pub trait Output: Send {
+    /// Play `audio` from the start, replacing whatever was loaded
+    fn start(&mut self, audio: DecodedAudio) -> Result<(), String>;
+    fn pause(&mut self);
+    fn resume(&mut self);
+    fn stop(&mut self);
+    fn seek(&mut self, position: Duration) -> Result<(), String>;
+    fn set_volume(&mut self, volume: f32);
+    fn position(&self) -> Duration;
+    /// True once the loaded audio has played to the end
+    fn is_finished(&self) -> bool;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlayerState {
+    Stopped,
+    Playing,
+    Paused,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaybackStatus {
+    pub state: PlayerState,
+    pub position_secs: f64,
+    /// `None` when the format doesn't record it
+    pub duration_secs: Option<f64>,
+    pub volume: f32,
+    /// What is loaded, for the window to match against its own audio
+    pub source: Option<String>,
+}
+
+struct Inner {
+    output: Box<dyn Output>,
+    state: PlayerState,
+    duration: Option<Duration>,
+    volume: f32,
+    source: Option<String>,
+}
+
+pub struct Player {
+    inner: Mutex<Inner>,
+}
+
+impl Player {
+    pub fn new(output: Box<dyn Output>) -> Self {
+        Self { inner: Mutex::new(Inner { output, state: PlayerState::Stopped, duration: None, volume: 1.0, source: None }) }
+    }
+
+    /// Stop what is playing and play `audio`, labelled `source`
+    pub fn play(&self, source: String, audio: Vec<u8>) -> Result<PlaybackStatus, String> {
+        let audio = decode(audio)?;
+        let duration = audio.total_duration();
+        let mut inner = self.inner.lock().unwrap();
+        inner.output.stop();
+        inner.state = PlayerState::Stopped;
+        inner.source = None;
+        inner.output.start(audio)?;
+        let volume = inner.volume;
+        inner.output.set_volume(volume);
+        inner.state = PlayerState::Playing;
+        inner.duration = duration;
+        inner.source = Some(source);
+        Ok(inner.status())
+    }
+
+    pub fn pause(&self) -> PlaybackStatus {
+        let mut inner = self.inner.lock().unwrap();
+        inner.check_finished();
+        if inner.state == PlayerState::Playing {
+            inner.output.pause();
+            inner.state = PlayerState::Paused;
+        }
+        inner.status()
+    }
+
+    pub fn resume(&self) -> PlaybackStatus {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.state == PlayerState::Paused {
+            inner.output.resume();
+            inner.state = PlayerState::Playing;
+        }
+        inner.status()
+    }
+
+    pub fn stop(&self) -> PlaybackStatus {
+        let mut inner = self.inner.lock().unwrap();
+        inner.output.stop();
+        inner.state = PlayerState::Stopped;
+        inner.source = None;
+        inner.duration = None;
+        inner.status()
+    }
+
+    /// Jump to `seconds` into what is loaded, clamped to its length
+    pub fn seek(&self, seconds: f64) -> Result<PlaybackStatus, String> {
+        if !seconds.is_finite() || seconds < 0.0 {
+            return Err(format!("Position must be zero or more seconds, got {}", seconds));
+        }
+        let mut inner = self.inner.lock().unwrap();
+        inner.check_finished();
+        if inner.state == PlayerState::Stopped {
+            return Err("Nothing is playing".to_string());
+        }
+        let mut position = Duration::from_secs_f64(seconds);
+        if let Some(duration) = inner.duration {
+            position = position.min(duration);
+        }
+        inner.output.seek(position)?;
+        Ok(inner.status())
+    }
+
+    /// From 0.0 (silent) to 1.0 (as generated); kept for what plays next
+    pub fn set_volume(&self, volume: f32) -> Result<PlaybackStatus, String> {
+        if !(0.0..=1.0).contains(&volume) {
+            return Err(format!("Volume must be between 0.0 and 1.0, got {}", volume));
+        }
+        let mut inner = self.inner.lock().unwrap();
+        inner.volume = volume;
+        inner.output.set_volume(volume);
+        Ok(inner.status())
+    }
+
+    pub fn status(&self) -> PlaybackStatus {
+        let mut inner = self.inner.lock().unwrap();
+        inner.check_finished();
+        inner.status()
+    }
+}
+
+impl Inner {
+    /// Audio that played to the end leaves the player stopped
+    fn check_finished(&mut self) {
+        if self.state == PlayerState::Playing && self.output.is_finished() {
+            self.output.stop();
+            self.state = PlayerState::Stopped;
+            self.source = None;
+            self.duration = None;
+        }
+    }
+
+    fn status(&self) -> PlaybackStatus {
+        let position = match self.state {
+            PlayerState::Stopped => Duration::ZERO,
+            PlayerState::Playing | PlayerState::Paused => self.output.position(),
+        };
+        PlaybackStatus {
+            state: self.state,
+            position_secs: position.as_secs_f64(),
+            duration_secs: self.duration.map(|duration| duration.as_secs_f64()),
+            volume: self.volume,
+            source: self.source.clone(),
+        }
+    }
+}
+
+#[cfg(feature = "playback")]
+mod device {
+    use super::{DecodedAudio, Output};
+    use std::time::Duration;
+
+    /// The default output device, opened on first use. `rodio::OutputStream`
+    /// can't move between threads everywhere, so it stays on a thread of its
+    /// own and only its mixer is kept here.
+    #[derive(Default)]
+    pub struct RodioOutput {
+        mixer: Option<rodio::mixer::Mixer>,
+        sink: Option<rodio::Sink>,
+    }
+
+    impl RodioOutput {
+        fn mixer(&mut self) -> Result<&rodio::mixer::Mixer, String> {
+            if self.mixer.is_none() {
+                self.mixer = Some(open_default_mixer()?);
+            }
+            Ok(self.mixer.as_ref().unwrap())
+        }
+    }
+
+    fn open_default_mixer() -> Result<rodio::mixer::Mixer, String> {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::Builder::new()
+            .name("audio-output".to_string())
+            .spawn(move || match rodio::OutputStreamBuilder::open_default_stream() {
+                Ok(mut stream) => {
+                    stream.log_on_drop(false);
+                    let _ = sender.send(Ok(stream.mixer().clone()));
+                    // Dropping the stream would close the device
+                    loop {
+                        std::thread::park();
+                    }
+                }
+                Err(e) => {
+                    let _ = sender.send(Err(format!("Failed to open audio output: {}", e)));
+                }
+            })
+            .map_err(|e| format!("Failed to start audio output: {}", e))?;
+        receiver.recv().map_err(|e| format!("Failed to open audio output: {}", e))?
+    }
+
+    impl Output for RodioOutput {
+        fn start(&mut self, audio: DecodedAudio) -> Result<(), String> {
+            let sink = rodio::Sink::connect_new(self.mixer()?);
+            sink.append(audio);
+            self.sink = Some(sink);
+            Ok(())
+        }
+
+        fn pause(&mut self) {
+            if let Some(sink) = &self.sink {
+                sink.pause();
+            }
+        }
+
+        fn resume(&mut self) {
+            if let Some(sink) = &self.sink {
+                sink.play();
+            }
+        }
+
+        fn stop(&mut self) {
+            if let Some(sink) = self.sink.take() {
+                sink.stop();
+            }
+        }
+
+        fn seek(&mut self, position: Duration) -> Result<(), String> {
+            match &self.sink {
+                Some(sink) => sink.try_seek(position).map_err(|e| format!("Failed to seek: {}", e)),
+                None => Ok(()),
+            }
+        }
+
+        fn set_volume(&mut self, volume: f32) {
+            if let Some(sink) = &self.sink {
+                sink.set_volume(volume);
+            }
+        }
+
+        fn position(&self) -> Duration {
+            self.sink.as_ref().map(|sink| sink.get_pos()).unwrap_or_default()
+        }
+
+        fn is_finished(&self) -> bool {
+            self.sink.as_ref().is_none_or(|sink| sink.empty())
+        }
+    }
+}
+
+#[cfg(feature = "playback")]
+pub fn default_output() -> Box<dyn Output> {
+    Box::new(device::RodioOutput::default())
+}
+
+/// Refuses to play, for builds without the `playback` feature
+#[cfg(not(feature = "playback"))]
+struct NoOutput;
+
+#[cfg(not(feature = "playback"))]
+impl Output for NoOutput {
+    fn start(&mut self, _audio: DecodedAudio) -> Result<(), String> {
+        Err("This build has no audio playback support".to_string())
+    }
+    fn pause(&mut self) {}
+    fn resume(&mut self) {}
+    fn stop(&mut self) {}
+    fn seek(&mut self, _position: Duration) -> Result<(), String> {
+        Ok(())
+    }
+    fn set_volume(&mut self, _volume: f32) {}
+    fn position(&self) -> Duration {
+        Duration::ZERO
+    }
+    fn is_finished(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(not(feature = "playback"))]
+pub fn default_output() -> Box<dyn Output> {
+    Box::new(NoOutput)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::silence::silent_wav;
+    use std::sync::Arc;
+
+    /// What the fake device was told, and a position the test moves along
+    #[derive(Debug, Default)]
+    struct Device {
+        calls: Vec<String>,
+        position: Duration,
+        finished: bool,
+    }
+
+    struct FakeOutput(Arc<Mutex<Device>>);
+
+    impl Output for FakeOutput {
+        fn start(&mut self, audio: DecodedAudio) -> Result<(), String> {
+            let mut device = self.0.lock().unwrap();
+            device.calls.push(format!("start {:?}", audio.total_duration()));
+            device.position = Duration::ZERO;
+            device.finished = false;
+            Ok(())
+        }
+        fn pause(&mut self) {
+            self.0.lock().unwrap().calls.push("pause".to_string());
+        }
+        fn resume(&mut self) {
+            self.0.lock().unwrap().calls.push("resume".to_string());
+        }
+        fn stop(&mut self) {
+            self.0.lock().unwrap().calls.push("stop".to_string());
+        }
+        fn seek(&mut self, position: Duration) -> Result<(), String> {
+            let mut device = self.0.lock().unwrap();
+            device.calls.push(format!("seek {:?}", position));
+            device.position = position;
+            Ok(())
+        }
+        fn set_volume(&mut self, volume: f32) {
+            self.0.lock().unwrap().calls.push(format!("volume {}", volume));
+        }
+        fn position(&self) -> Duration {
+            self.0.lock().unwrap().position
+        }
+        fn is_finished(&self) -> bool {
+            self.0.lock().unwrap().finished
+        }
+    }
+
+    fn player() -> (Player, Arc<Mutex<Device>>) {
+        let device = Arc::new(Mutex::new(Device::default()));
+        (Player::new(Box::new(FakeOutput(device.clone()))), device)
+    }
+
+    fn calls(device: &Mutex<Device>) -> Vec<String> {
+        std::mem::take(&mut device.lock().unwrap().calls)
+    }
+
+    #[test]
+    fn test_play_pause_resume_stop() {
+        let (player, device) = player();
+        assert_eq!(player.status().state, PlayerState::Stopped);
+
+        let status = player.play("job:1".to_string(), silent_wav(2_000)).unwrap();
+        assert_eq!(status.state, PlayerState::Playing);
+        assert_eq!(status.duration_secs, Some(2.0));
+        assert_eq!(status.source.as_deref(), Some("job:1"));
+        assert_eq!(calls(&device), ["stop", "start Some(2s)", "volume 1"]);
+
+        device.lock().unwrap().position = Duration::from_millis(500);
+        let status = player.pause();
+        assert_eq!((status.state, status.position_secs), (PlayerState::Paused, 0.5));
+        // Pausing twice, or resuming while playing, doesn't reach the device
+        player.pause();
+        assert_eq!(player.resume().state, PlayerState::Playing);
+        player.resume();
+        assert_eq!(calls(&device), ["pause", "resume"]);
+
+        let status = player.stop();
+        assert_eq!((status.state, status.position_secs, status.source), (PlayerState::Stopped, 0.0, None));
+        assert_eq!(player.resume().state, PlayerState::Stopped);
+    }
+
+    #[test]
+    fn test_playing_again_replaces_the_stream() {
+        let (player, device) = player();
+        player.play("a".to_string(), silent_wav(1_000)).unwrap();
+        player.set_volume(0.5).unwrap();
+        calls(&device);
+
+        let status = player.play("b".to_string(), silent_wav(2_000)).unwrap();
+        assert_eq!(status.source.as_deref(), Some("b"));
+        // The old stream stops first, and the volume carries over
+        assert_eq!(calls(&device), ["stop", "start Some(2s)", "volume 0.5"]);
+
+        assert!(player.play("c".to_string(), b"not audio".to_vec()).is_err());
+        assert_eq!(player.status().source.as_deref(), Some("b"), "a bad file leaves the old one playing");
+    }
+
+    #[test]
+    fn test_seek() {
+        let (player, device) = player();
+        assert_eq!(player.seek(1.0).unwrap_err(), "Nothing is playing");
+
+        player.play("a".to_string(), silent_wav(2_000)).unwrap();
+        calls(&device);
+        assert_eq!(player.seek(1.5).unwrap().position_secs, 1.5);
+        // Past the end lands on the end
+        assert_eq!(player.seek(60.0).unwrap().position_secs, 2.0);
+        assert!(player.seek(-1.0).is_err());
+        assert!(player.seek(f64::NAN).is_err());
+        assert_eq!(calls(&device), ["seek 1.5s", "seek 2s"]);
+    }
+
+    #[test]
+    fn test_volume_bounds() {
+        let (player, _device) = player();
+        assert_eq!(player.set_volume(0.25).unwrap().volume, 0.25);
+        assert!(player.set_volume(1.5).is_err());
+        assert!(player.set_volume(-0.1).is_err());
+        assert!(player.set_volume(f32::NAN).is_err());
+        assert_eq!(player.status().volume, 0.25);
+    }
+
+    #[test]
+    fn test_finishing_leaves_the_player_stopped() {
+        let (player, device) = player();
+        player.play("a".to_string(), silent_wav(1_000)).unwrap();
+        device.lock().unwrap().finished = true;
+        let status = player.status();
+        assert_eq!((status.state, status.source), (PlayerState::Stopped, None));
+    }
+
+    #[test]
+    fn test_source_deserialization() {
+        let source: PlaySource = serde_json::from_value(serde_json::json!({ "kind": "job", "id": "42" })).unwrap();
+        assert_eq!(source, PlaySource::Job { id: "42".to_string() });
+        let source: PlaySource = serde_json::from_value(serde_json::json!({ "kind": "path", "path": "/a.mp3" })).unwrap();
+        assert_eq!(source, PlaySource::Path { path: "/a.mp3".to_string() });
+    }
+}
@@ -0,0 +1,421 @@
+//! Projects: reading lists of several documents generated one after
+//! another and exported together, either as numbered files or as one file
+//! with a chapter per document. Each item's audio is cached by what it was
+//! generated from, so generating a project again only redoes what changed.
+
+use crate::database::ProjectItem;
+use crate::eta::{Estimator, Timing};
+use crate::tts::{SpeechBackend, VALID_MODEL_IDS, VALID_VOICE_IDS};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// What a new item or an edit to one carries from the window
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemContent {
+    pub title: String,
+    pub text: String,
+    #[serde(default)]
+    pub voice_id: Option<String>,
+    #[serde(default)]
+    pub model_id: Option<String>,
+}
+
+impl ItemContent {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.text.trim().is_empty() {
+            return Err("Item text cannot be empty".to_string());
+        }
+        if let Some(voice) = self.voice_id.as_deref().filter(|voice| !VALID_VOICE_IDS.contains(voice)) {
+            return Err(format!("Invalid voice ID: {}", voice));
+        }
+        if let Some(model) = self.model_id.as_deref().filter(|model| !VALID_MODEL_IDS.contains(model)) {
+            return Err(format!("Invalid model: {}", model));
+        }
+        Ok(())
+    }
+}
+
+/// What items without their own voice or model use, and what else the
+/// audio depends on
+#[derive(Debug, Clone, PartialEq)]
+pub struct ItemDefaults {
+    pub voice_id: String,
+    pub model_id: String,
+    pub speed: f32,
+    pub format: String,
+}
+
+/// Item audio by what it was generated from, shared by every project
+pub struct ProjectCache {
+    dir: PathBuf,
+}
+
+impl ProjectCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Where `item`'s audio is, or will be, kept
+    pub fn path_for(&self, item: &ProjectItem, defaults: &ItemDefaults) -> PathBuf {
+        let voice = item.voice_id.as_deref().unwrap_or(&defaults.voice_id);
+        let model = item.model_id.as_deref().unwrap_or(&defaults.model_id);
+        let key = format!("{}|{}|{}|{}", item.text, voice, model, defaults.speed);
+        self.dir.join(format!("item-{:016x}.{}", crate::watch::hash(&key), defaults.format))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "state", rename_all = "lowercase")]
+pub enum ItemState {
+    Generating,
+    /// Audio from an earlier run was reused
+    Cached,
+    Done,
+    Failed { error: String },
+}
+
+/// Sent as each item starts and finishes
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemProgress {
+    pub project_id: i64,
+    pub item_id: i64,
+    /// Place in the reading order, from 0
+    pub index: usize,
+    pub total: usize,
+    #[serde(flatten)]
+    pub state: ItemState,
+    /// For the whole project; reused items don't count towards the pace
+    #[serde(flatten)]
+    pub timing: Timing,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemResult {
+    pub item_id: i64,
+    pub title: String,
+    /// The audio, unless generating it failed
+    pub path: Option<PathBuf>,
+    pub error: Option<String>,
+    pub cached: bool,
+}
+
+/// Generate `items` one at a time in reading order, reusing cached audio.
+/// A failed item is recorded and the rest still run.
+pub async fn generate<B: SpeechBackend>(
+    backend: &B,
+    items: &[ProjectItem],
+    defaults: &ItemDefaults,
+    cache: &ProjectCache,
+    mut progress: impl FnMut(ItemProgress),
+) -> Vec<ItemResult> {
+    let items = in_order(items);
+    let total = items.len();
+    let mut results = Vec::with_capacity(total);
+    let mut eta = Estimator::new(total, Instant::now());
+    for (index, item) in items.into_iter().enumerate() {
+        let report = |state, eta: &Estimator| ItemProgress {
+            project_id: item.project_id,
+            item_id: item.id,
+            index,
+            total,
+            state,
+            timing: eta.timing(Instant::now()),
+        };
+        let path = cache.path_for(&item, defaults);
+        let mut result =
+            ItemResult { item_id: item.id, title: item.title.clone(), path: None, error: None, cached: false };
+
+        if path.is_file() {
+            eta.skipped();
+            progress(report(ItemState::Cached, &eta));
+            result.path = Some(path);
+            result.cached = true;
+            results.push(result);
+            continue;
+        }
+
+        progress(report(ItemState::Generating, &eta));
+        let outcome = generate_item(backend, &item, defaults, &path).await;
+        eta.finished(Instant::now());
+        match outcome {
+            Ok(()) => {
+                progress(report(ItemState::Done, &eta));
+                result.path = Some(path);
+            }
+            Err(error) => {
+                tracing::warn!("Project item {} failed: {}", item.id, error);
+                progress(report(ItemState::Failed { error: error.clone() }, &eta));
+                result.error = Some(error);
+            }
+        }
+        results.push(result);
+    }
+    results
+}
+
+async fn generate_item<B: SpeechBackend>(
+    backend: &B,
+    item: &ProjectItem,
+    defaults: &ItemDefaults,
+    path: &Path,
+) -> Result<(), String> {
+    let voice = item.voice_id.as_deref().unwrap_or(&defaults.voice_id);
+    let model = item.model_id.as_deref().unwrap_or(&defaults.model_id);
+    let audio = backend.synthesize(&item.text, voice, model).await.map_err(|e| e.to_string())?;
+
+    let dir = path.parent().expect("cache paths are inside the cache directory");
+    crate::permissions::create_private_dir(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    // Written under another name first so a half-written file is never reused
+    let partial = path.with_extension(format!("{}.partial", uuid::Uuid::new_v4()));
+    std::fs::write(&partial, &audio).map_err(|e| format!("Failed to save audio: {}", e))?;
+    std::fs::rename(&partial, path).map_err(|e| format!("Failed to save audio: {}", e))?;
+    Ok(())
+}
+
+fn in_order(items: &[ProjectItem]) -> Vec<ProjectItem> {
+    let mut items = items.to_vec();
+    items.sort_by_key(|item| (item.position, item.id));
+    items
+}
+
+/// An item's audio, ready to export
+#[derive(Debug, Clone, PartialEq)]
+pub struct Part {
+    pub title: String,
+    pub path: PathBuf,
+}
+
+/// Every item's cached audio in reading order, or which items still need
+/// generating
+pub fn parts(items: &[ProjectItem], defaults: &ItemDefaults, cache: &ProjectCache) -> Result<Vec<Part>, String> {
+    let items = in_order(items);
+    if items.is_empty() {
+        return Err("The project has no items".to_string());
+    }
+    let parts: Vec<Part> =
+        items.iter().map(|item| Part { title: item.title.clone(), path: cache.path_for(item, defaults) }).collect();
+    let missing: Vec<&str> =
+        parts.iter().filter(|part| !part.path.is_file()).map(|part| part.title.as_str()).collect();
+    if !missing.is_empty() {
+        return Err(format!("Generate the project first; no audio yet for: {}", missing.join(", ")));
+    }
+    Ok(parts)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportMode {
+    /// One file per item, numbered in reading order, in a directory
+    Separate,
+    /// A single file with a chapter per item
+    Combined,
+}
+
+/// "03 - Title.mp3", padded so the files sort in reading order
+pub fn numbered_name(index: usize, total: usize, title: &str, format: &str) -> String {
+    let width = total.to_string().len().max(2);
+    let title: String = title
+        .chars()
+        .map(|c| if c.is_control() || r#"/\:*?"<>|"#.contains(c) { '_' } else { c })
+        .take(80)
+        .collect();
+    let title = title.trim();
+    let title = if title.is_empty() { "Item" } else { title };
+    format!("{:0width$} - {}.{}", index + 1, title, format, width = width)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chapter {
+    pub title: String,
+    pub start_ms: u64,
+    pub end_ms: u64,
+}
+
+/// Back-to-back chapters for parts of the given lengths
+pub fn chapters(parts: &[(String, Duration)]) -> Vec<Chapter> {
+    let mut start_ms = 0;
+    parts
+        .iter()
+        .map(|(title, duration)| {
+            let end_ms = start_ms + duration.as_millis() as u64;
+            let chapter = Chapter { title: title.clone(), start_ms, end_ms };
+            start_ms = end_ms;
+            chapter
+        })
+        .collect()
+}
+
+/// ffmpeg's metadata file format, naming the file `title` and marking `chapters`
+pub fn ffmetadata(title: &str, chapters: &[Chapter]) -> String {
+    let mut metadata = format!(";FFMETADATA1\ntitle={}\n", escape_metadata(title));
+    for chapter in chapters {
+        metadata.push_str(&format!(
+            "\n[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle={}\n",
+            chapter.start_ms,
+            chapter.end_ms,
+            escape_metadata(&chapter.title)
+        ));
+    }
+    metadata
+}
+
+fn escape_metadata(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// ffmpeg arguments joining `inputs` into `output` with the title and
+/// chapters from the `metadata` file
+pub fn combined_args(inputs: &[PathBuf], metadata: &Path, output: &Path) -> Vec<String> {
+    let mut args = crate::silence::concat_args(inputs, output);
+    let filter_at = args.iter().position(|arg| arg == "-filter_complex").expect("concat arguments have a filter");
+    args.splice(filter_at..filter_at, ["-i".to_string(), metadata.to_string_lossy().into_owned()]);
+    // Before the trailing "-y <output>"
+    let output_at = args.len() - 2;
+    let metadata_input = inputs.len().to_string();
+    args.splice(
+        output_at..output_at,
+        ["-map_metadata".to_string(), metadata_input.clone(), "-map_chapters".to_string(), metadata_input],
+    );
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::FakeBackend;
+    use tempfile::TempDir;
+
+    /// Answers "<n>ms" with that much silence
+    fn backend() -> FakeBackend {
+        FakeBackend::with_audio(|text| crate::silence::silent_wav(text.trim_end_matches("ms").parse().unwrap_or(100)))
+    }
+
+    fn item(id: i64, position: i64, text: &str) -> ProjectItem {
+        ProjectItem {
+            id,
+            project_id: 1,
+            position,
+            title: format!("Article {}", id),
+            text: text.to_string(),
+            voice_id: None,
+            model_id: None,
+        }
+    }
+
+    fn defaults() -> ItemDefaults {
+        ItemDefaults { voice_id: "nova".to_string(), model_id: "tts-1".to_string(), speed: 1.0, format: "wav".to_string() }
+    }
+
+    #[tokio::test]
+    async fn test_items_generate_in_reading_order() {
+        let dir = TempDir::new().unwrap();
+        let cache = ProjectCache::new(dir.path());
+        let backend = backend();
+        let items = [item(1, 2, "200ms"), item(2, 3, "300ms"), item(3, 1, "100ms")];
+
+        let mut events = Vec::new();
+        let results = generate(&backend, &items, &defaults(), &cache, |progress| events.push(progress)).await;
+
+        assert_eq!(*backend.calls.lock().unwrap(), ["100ms", "200ms", "300ms"]);
+        assert_eq!(results.iter().map(|result| result.item_id).collect::<Vec<_>>(), [3, 1, 2]);
+        let timings: Vec<Timing> = events.iter().map(|event| event.timing).collect();
+        let states: Vec<(i64, usize, ItemState)> =
+            events.into_iter().map(|event| (event.item_id, event.index, event.state)).collect();
+        assert_eq!(
+            states,
+            [
+                (3, 0, ItemState::Generating),
+                (3, 0, ItemState::Done),
+                (1, 1, ItemState::Generating),
+                (1, 1, ItemState::Done),
+                (2, 2, ItemState::Generating),
+                (2, 2, ItemState::Done),
+            ]
+        );
+        assert_eq!(timings.first().unwrap().eta_ms, None);
+        assert!(timings[1].eta_ms.is_some());
+        assert_eq!(timings.last().unwrap().eta_ms, Some(0));
+        assert!(timings.windows(2).all(|pair| pair[0].elapsed_ms <= pair[1].elapsed_ms));
+
+        // A second run reuses everything; an edited item is generated again
+        let edited = [item(1, 2, "250ms"), item(2, 3, "300ms"), item(3, 1, "100ms")];
+        let results = generate(&backend, &edited, &defaults(), &cache, |_| {}).await;
+        assert_eq!(results.iter().map(|result| result.cached).collect::<Vec<_>>(), [true, false, true]);
+        assert_eq!(backend.calls.lock().unwrap().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_failed_item_does_not_stop_the_rest() {
+        let dir = TempDir::new().unwrap();
+        let cache = ProjectCache::new(dir.path());
+        let items = [item(1, 1, "100ms"), item(2, 2, "FAIL"), item(3, 3, "100ms")];
+
+        let mut failures = Vec::new();
+        let results = generate(&backend(), &items, &defaults(), &cache, |progress| {
+            if let ItemState::Failed { error } = progress.state {
+                failures.push((progress.item_id, error));
+            }
+        })
+        .await;
+
+        assert_eq!(failures, [(2, "Unknown error: HTTP 500".to_string())]);
+        assert!(results[0].path.as_ref().is_some_and(|path| path.is_file()));
+        assert_eq!(results[1].path, None);
+        assert!(results[2].path.as_ref().is_some_and(|path| path.is_file()));
+        // Nothing half-written is left to be mistaken for audio
+        assert!(!cache.path_for(&items[1], &defaults()).exists());
+
+        let error = parts(&items, &defaults(), &cache).unwrap_err();
+        assert!(error.contains("Article 2") && !error.contains("Article 1"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_combined_export_chapter_offsets() {
+        let dir = TempDir::new().unwrap();
+        let cache = ProjectCache::new(dir.path());
+        let items = [item(1, 1, "1500ms"), item(2, 2, "1000ms"), item(3, 3, "2250ms")];
+        generate(&backend(), &items, &defaults(), &cache, |_| {}).await;
+
+        let parts = parts(&items, &defaults(), &cache).unwrap();
+        let lengths: Vec<(String, Duration)> = parts
+            .iter()
+            .map(|part| (part.title.clone(), crate::file_manager::file_duration(&part.path).unwrap()))
+            .collect();
+        let chapters = chapters(&lengths);
+        let offsets: Vec<(u64, u64)> = chapters.iter().map(|chapter| (chapter.start_ms, chapter.end_ms)).collect();
+        assert_eq!(offsets, [(0, 1500), (1500, 2500), (2500, 4750)]);
+
+        let metadata = ffmetadata("Reading; list", &chapters);
+        assert!(metadata.starts_with(";FFMETADATA1\ntitle=Reading\\; list\n"), "{}", metadata);
+        assert!(metadata.contains("[CHAPTER]\nTIMEBASE=1/1000\nSTART=1500\nEND=2500\ntitle=Article 2\n"), "{}", metadata);
+    }
+
+    #[test]
+    fn test_combined_args_add_the_metadata_input() {
+        let inputs = [PathBuf::from("a.mp3"), PathBuf::from("b.mp3")];
+        let args = combined_args(&inputs, Path::new("meta.txt"), Path::new("out.mp3"));
+        let at = |arg: &str| args.iter().position(|a| a == arg).unwrap();
+        assert_eq!(args[at("meta.txt") - 1], "-i");
+        assert!(at("meta.txt") > at("b.mp3") && at("meta.txt") < at("-filter_complex"));
+        assert_eq!(args[at("-map_metadata") + 1], "2");
+        assert_eq!(args[at("-map_chapters") + 1], "2");
+        assert_eq!(args[args.len() - 2..], ["-y", "out.mp3"]);
+    }
+
+    #[test]
+    fn test_numbered_names() {
+        assert_eq!(numbered_name(2, 5, "Why: a/b", "mp3"), "03 - Why_ a_b.mp3");
+        assert_eq!(numbered_name(9, 120, "  ", "opus"), "010 - Item.opus");
+    }
+}
@@ -0,0 +1,220 @@
+//! The last few finished generations, kept so a reloaded window can play
+//! them again without paying for them twice. Each job's audio is copied
+//! here as it finishes, with a row in the database saying what it was;
+//! beyond the `recent_generations` setting the oldest go. One whose file
+//! has gone can only be generated again, which the window has to ask for.
+
+use crate::database::{Database, KeptGeneration};
+use crate::file_manager::GeneratedAudio;
+use crate::jobs::{JobId, JobInfo};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// How many generations are kept unless the settings say
+pub const DEFAULT_KEEP: u32 = 10;
+pub const MAX_KEEP: u32 = 100;
+
+/// Characters of the text shown to recognise a generation by
+const PREVIEW_CHARS: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecentGeneration {
+    pub id: JobId,
+    pub title: Option<String>,
+    /// The start of the text
+    pub preview: String,
+    pub characters: usize,
+    pub voice_id: String,
+    pub model: String,
+    pub format: String,
+    pub size: u64,
+    pub created_at: DateTime<Utc>,
+    /// The audio is still there to replay; otherwise it can only be
+    /// generated again
+    pub available: bool,
+}
+
+impl From<KeptGeneration> for RecentGeneration {
+    fn from(kept: KeptGeneration) -> Self {
+        Self {
+            available: Path::new(&kept.path).is_file(),
+            preview: kept.text.chars().take(PREVIEW_CHARS).collect(),
+            characters: kept.text.chars().count(),
+            id: kept.id,
+            title: kept.title,
+            voice_id: kept.voice_id,
+            model: kept.model,
+            format: kept.format,
+            size: kept.size.max(0) as u64,
+            created_at: kept.created_at,
+        }
+    }
+}
+
+/// What `replay_generation` did
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "state", rename_all = "lowercase")]
+pub enum Replay {
+    /// Played from the kept file
+    Audio { audio: Box<GeneratedAudio> },
+    /// The file was gone and it was asked to generate it again, as job
+    /// `job_id`
+    #[serde(rename_all = "camelCase")]
+    Regenerating { job_id: JobId },
+}
+
+/// Kept generations in the database, their audio as files in `dir`
+pub struct RecentGenerations {
+    database: Database,
+    dir: PathBuf,
+}
+
+impl RecentGenerations {
+    pub fn new(database: Database, dir: PathBuf) -> Self {
+        Self { database, dir }
+    }
+
+    /// Keep `audio`, finished job `job`'s from `text`, then forget all but
+    /// the newest `keep` generations; 0 keeps none
+    pub async fn keep(&self, job: &JobInfo, text: &str, format: &str, audio: &[u8], keep: usize) -> Result<()> {
+        if keep > 0 {
+            crate::permissions::create_private_dir(&self.dir)?;
+            let path = self.dir.join(format!("{}.{}", job.id, format));
+            tokio::fs::write(&path, audio).await?;
+            self.database
+                .keep_generation(&KeptGeneration {
+                    id: job.id.clone(),
+                    title: job.title.clone(),
+                    text: text.to_string(),
+                    voice_id: job.voice_id.clone(),
+                    model: job.model.clone(),
+                    format: format.to_string(),
+                    path: path.to_string_lossy().into_owned(),
+                    size: audio.len() as i64,
+                    created_at: Utc::now(),
+                })
+                .await?;
+        }
+        for forgotten in self.database.forget_kept_generations_beyond(keep).await? {
+            remove_file(Path::new(&forgotten.path)).await?;
+        }
+        Ok(())
+    }
+
+    /// Up to `limit` of them, newest first
+    pub async fn list(&self, limit: usize) -> Result<Vec<RecentGeneration>> {
+        Ok(self.database.get_kept_generations(limit).await?.into_iter().map(RecentGeneration::from).collect())
+    }
+
+    /// Generation `id` and its audio, which is `None` if the file has gone
+    pub async fn load(&self, id: &str) -> Result<Option<(KeptGeneration, Option<Vec<u8>>)>> {
+        let Some(kept) = self.database.get_kept_generation(id).await? else {
+            return Ok(None);
+        };
+        let audio = match tokio::fs::read(&kept.path).await {
+            Ok(audio) => Some(audio),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Some((kept, audio)))
+    }
+
+    /// Delete generation `id` and its file
+    pub async fn forget(&self, id: &str) -> Result<()> {
+        if let Some(kept) = self.database.get_kept_generation(id).await? {
+            self.database.forget_kept_generation(id).await?;
+            remove_file(Path::new(&kept.path)).await?;
+        }
+        Ok(())
+    }
+}
+
+async fn remove_file(path: &Path) -> Result<()> {
+    match tokio::fs::remove_file(path).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jobs::JobState;
+
+    fn job(id: &str) -> JobInfo {
+        JobInfo {
+            id: id.to_string(),
+            sequence: 1,
+            state: JobState::Done { bytes: 5 },
+            voice_id: "nova".to_string(),
+            model: "tts-1".to_string(),
+            characters: 5,
+            title: Some(format!("Title {}", id)),
+            downgrade: None,
+            resolved: None,
+            parent_id: None,
+            warnings: Vec::new(),
+            gaps: Default::default(),
+            waiting: None,
+            queue_paused: false,
+            created_at: Utc::now(),
+            started_at: None,
+            finished_at: None,
+        }
+    }
+
+    async fn recent(dir: &Path) -> RecentGenerations {
+        let database = Database::open(&dir.join("usage.db")).await.unwrap();
+        RecentGenerations::new(database, dir.join("recent-audio"))
+    }
+
+    #[tokio::test]
+    async fn test_kept_across_a_restart_up_to_the_limit() {
+        let dir = tempfile::TempDir::new().unwrap();
+        {
+            let recent = recent(dir.path()).await;
+            for id in ["a", "b", "c"] {
+                recent.keep(&job(id), &format!("Text of {}", id), "mp3", id.as_bytes(), 2).await.unwrap();
+            }
+        }
+
+        // Opened again, as after a restart
+        let recent = recent(dir.path()).await;
+        let listed = recent.list(10).await.unwrap();
+        assert_eq!(listed.iter().map(|generation| generation.id.as_str()).collect::<Vec<_>>(), ["c", "b"]);
+        assert_eq!(listed[0].title.as_deref(), Some("Title c"));
+        assert_eq!((listed[0].preview.as_str(), listed[0].characters, listed[0].size), ("Text of c", 9, 1));
+        assert!(listed.iter().all(|generation| generation.available));
+        assert_eq!(recent.list(1).await.unwrap().len(), 1);
+
+        let (kept, audio) = recent.load("b").await.unwrap().unwrap();
+        assert_eq!((kept.text.as_str(), audio.as_deref()), ("Text of b", Some(&b"b"[..])));
+        // The oldest went with its file
+        assert!(recent.load("a").await.unwrap().is_none());
+        assert!(!dir.path().join("recent-audio").join("a.mp3").exists());
+
+        // None kept forgets the rest
+        recent.keep(&job("d"), "Text of d", "mp3", b"d", 0).await.unwrap();
+        assert!(recent.list(10).await.unwrap().is_empty());
+        assert_eq!(std::fs::read_dir(dir.path().join("recent-audio")).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_missing_audio_is_reported_not_fatal() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let recent = recent(dir.path()).await;
+        recent.keep(&job("a"), "Text of a", "opus", b"a", 5).await.unwrap();
+        std::fs::remove_file(dir.path().join("recent-audio").join("a.opus")).unwrap();
+
+        assert!(!recent.list(10).await.unwrap()[0].available);
+        let (kept, audio) = recent.load("a").await.unwrap().unwrap();
+        assert_eq!((kept.voice_id.as_str(), kept.model.as_str(), audio), ("nova", "tts-1", None));
+
+        recent.forget("a").await.unwrap();
+        assert!(recent.load("a").await.unwrap().is_none());
+        recent.forget("a").await.unwrap();
+    }
+}
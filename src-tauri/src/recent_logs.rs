@@ -0,0 +1,246 @@
+//! The last few hundred log lines, kept in memory so a failure can be
+//! looked into from the window without running the app from a terminal.
+//! Each command invocation and job runs in a span carrying a correlation
+//! id, which every line logged inside it picks up and failed commands
+//! return as their error's `reference`.
+
+use crate::i18n::CommandError;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fmt::{self, Write};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tracing::field::{Field, Visit};
+use tracing::{span, Event, Instrument, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+pub const CAPACITY: usize = 500;
+/// The span field holding the correlation id
+pub const CORRELATION_FIELD: &str = "correlation_id";
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogLine {
+    pub timestamp: DateTime<Utc>,
+    pub level: String,
+    pub target: String,
+    /// The message followed by the event's other fields as `name=value`
+    pub message: String,
+    pub correlation_id: Option<String>,
+}
+
+/// A tracing layer keeping the latest `capacity` events. Clones share the
+/// same lines, so one can be installed and another read from.
+#[derive(Debug, Clone)]
+pub struct RecentLogs {
+    lines: Arc<Mutex<VecDeque<LogLine>>>,
+    capacity: usize,
+}
+
+impl Default for RecentLogs {
+    fn default() -> Self {
+        Self::new(CAPACITY)
+    }
+}
+
+impl RecentLogs {
+    pub fn new(capacity: usize) -> Self {
+        Self { lines: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))), capacity }
+    }
+
+    /// Oldest first, only those logged under `correlation_id` if given
+    pub fn lines(&self, correlation_id: Option<&str>) -> Vec<LogLine> {
+        self.lines
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|line| correlation_id.is_none() || line.correlation_id.as_deref() == correlation_id)
+            .cloned()
+            .collect()
+    }
+
+    fn push(&self, line: LogLine) {
+        let mut lines = self.lines.lock().unwrap();
+        while lines.len() >= self.capacity.max(1) {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+}
+
+/// Stored in a span's extensions when it was created with a correlation id
+struct CorrelationId(String);
+
+#[derive(Default)]
+struct FieldVisitor {
+    message: String,
+    fields: String,
+    correlation_id: Option<String>,
+}
+
+impl FieldVisitor {
+    fn line(self) -> String {
+        match (self.message.is_empty(), self.fields.is_empty()) {
+            (_, true) => self.message,
+            (true, false) => self.fields,
+            (false, false) => format!("{} {}", self.message, self.fields),
+        }
+    }
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.record_debug(field, &format_args!("{}", value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        match field.name() {
+            "message" => {
+                let _ = write!(self.message, "{:?}", value);
+            }
+            CORRELATION_FIELD => self.correlation_id = Some(format!("{:?}", value)),
+            name => {
+                if !self.fields.is_empty() {
+                    self.fields.push(' ');
+                }
+                let _ = write!(self.fields, "{}={:?}", name, value);
+            }
+        }
+    }
+}
+
+impl<S> Layer<S> for RecentLogs
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let mut visitor = FieldVisitor::default();
+        attrs.record(&mut visitor);
+        if let (Some(correlation_id), Some(span)) = (visitor.correlation_id, ctx.span(id)) {
+            span.extensions_mut().insert(CorrelationId(correlation_id));
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+        // The innermost span with an id wins
+        let correlation_id = visitor.correlation_id.take().or_else(|| {
+            ctx.event_scope(event)?
+                .find_map(|span| span.extensions().get::<CorrelationId>().map(|id| id.0.clone()))
+        });
+        let metadata = event.metadata();
+        self.push(LogLine {
+            timestamp: Utc::now(),
+            level: metadata.level().to_string(),
+            target: metadata.target().to_string(),
+            message: crate::logging::redact(&visitor.line()),
+            correlation_id,
+        });
+    }
+}
+
+/// A short id to tell one command's log lines from another's
+pub fn new_correlation_id() -> String {
+    uuid::Uuid::new_v4().simple().to_string()[..8].to_string()
+}
+
+/// Run the body of `command` under a fresh correlation id, which a failure
+/// carries as its `reference` so `get_recent_logs` can find what happened
+pub async fn correlated<T>(command: &str, future: impl Future<Output = Result<T, String>>) -> Result<T, CommandError> {
+    correlated_localized(command, async { future.await.map_err(CommandError::from) }).await
+}
+
+/// `correlated` for a body whose errors have their own message keys
+pub async fn correlated_localized<T>(
+    command: &str,
+    future: impl Future<Output = Result<T, CommandError>>,
+) -> Result<T, CommandError> {
+    let correlation_id = new_correlation_id();
+    let span = tracing::info_span!("command", command, correlation_id = %correlation_id);
+    future.instrument(span).await.map_err(|e| {
+        tracing::warn!(command, correlation_id = %correlation_id, "{}", e.fallback);
+        CommandError { reference: Some(correlation_id), ..e }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    fn capture(capacity: usize, log: impl FnOnce()) -> RecentLogs {
+        let logs = RecentLogs::new(capacity);
+        let subscriber = tracing_subscriber::registry().with(logs.clone());
+        tracing::subscriber::with_default(subscriber, log);
+        logs
+    }
+
+    fn messages(lines: &[LogLine]) -> Vec<&str> {
+        lines.iter().map(|line| line.message.as_str()).collect()
+    }
+
+    #[test]
+    fn test_keeps_only_the_latest_lines() {
+        let logs = capture(3, || {
+            for n in 0..5 {
+                tracing::info!("line {}", n);
+            }
+        });
+        let lines = logs.lines(None);
+        assert_eq!(messages(&lines), ["line 2", "line 3", "line 4"]);
+        assert_eq!(lines[0].level, "INFO");
+    }
+
+    #[test]
+    fn test_filters_by_correlation_id() {
+        let logs = capture(10, || {
+            tracing::info!("before");
+            tracing::info_span!("command", correlation_id = "aaaa1111").in_scope(|| {
+                tracing::info!("first");
+                // Nested spans without an id keep the outer one
+                tracing::debug_span!("chunk", index = 2).in_scope(|| tracing::warn!(status = 429, "retrying"));
+            });
+            tracing::info_span!("job", correlation_id = %"bbbb2222").in_scope(|| tracing::error!("second"));
+        });
+        assert_eq!(messages(&logs.lines(Some("aaaa1111"))), ["first", "retrying status=429"]);
+        assert_eq!(messages(&logs.lines(Some("bbbb2222"))), ["second"]);
+        assert_eq!(logs.lines(Some("cccc3333")), []);
+        assert_eq!(logs.lines(None).len(), 4);
+        assert_eq!(logs.lines(None)[0].correlation_id, None);
+    }
+
+    #[tokio::test]
+    async fn test_failed_commands_carry_their_reference() {
+        let logs = RecentLogs::new(10);
+        let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(logs.clone()));
+
+        let error = correlated("generate_speech", async {
+            tracing::info!("generating");
+            Err::<(), _>("Network error".to_string())
+        })
+        .await
+        .unwrap_err();
+        assert_eq!(error.fallback, "Network error");
+        let correlation_id = error.reference.as_deref().unwrap();
+        assert_eq!(correlation_id.len(), 8);
+
+        let lines = logs.lines(Some(correlation_id));
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].message, "generating");
+        assert!(lines[1].message.starts_with("Network error"), "{}", lines[1].message);
+
+        assert_eq!(correlated("count", async { Ok(3) }).await, Ok(3));
+
+        let error = correlated_localized("get_user_info", async {
+            Err::<(), _>(crate::tts::TTSError::RateLimit(Some(30)).into())
+        })
+        .await
+        .unwrap_err();
+        assert_eq!(error.key, "error.rate_limited");
+        assert!(error.reference.is_some());
+    }
+}
@@ -0,0 +1,309 @@
+//! Jobs outlive the app. Each is saved when it's enqueued and the audio of
+//! each chunk kept as it arrives, until the job has an answer. Whatever is
+//! still saved at startup was interrupted by quitting or a crash, and
+//! `JobManager::recover` runs it again from the first chunk not done. Saved
+//! jobs older than the `keep_interrupted_jobs_days` setting are deleted with
+//! their chunk files.
+
+use crate::database::{Database, SavedJob, SOURCES};
+use crate::jobs::{GenerationOptions, JobId};
+use crate::spend::Downgrade;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How long an interrupted job can be recovered unless the settings say
+pub const DEFAULT_KEEP_DAYS: u32 = 7;
+pub const MAX_KEEP_DAYS: u32 = 90;
+
+/// Characters of the text shown to recognise an interrupted job by
+const PREVIEW_CHARS: usize = 100;
+
+/// What a job was asked to generate, as saved
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobDefinition {
+    pub text: String,
+    pub voice_id: String,
+    pub model: String,
+    pub title: Option<String>,
+    pub source: Option<String>,
+    pub queue_if_offline: Option<bool>,
+    pub downgrade: Option<Downgrade>,
+    pub sentence_gap_ms: Option<u64>,
+    pub paragraph_gap_ms: Option<u64>,
+    #[serde(default)]
+    pub parent_id: Option<JobId>,
+    #[serde(default)]
+    pub trim_silence: bool,
+    /// The text of each request, split when the job was enqueued, so the
+    /// chunks done still line up if the chunk size changes before recovery
+    pub chunks: Vec<String>,
+}
+
+impl JobDefinition {
+    pub fn new(options: &GenerationOptions, model: &str, chunks: Vec<String>) -> Self {
+        Self {
+            text: options.text.clone(),
+            voice_id: options.voice_id.clone(),
+            model: model.to_string(),
+            title: options.title.clone(),
+            source: options.source.map(str::to_string),
+            queue_if_offline: options.queue_if_offline,
+            downgrade: options.downgrade.clone(),
+            sentence_gap_ms: options.sentence_gap_ms,
+            paragraph_gap_ms: options.paragraph_gap_ms,
+            parent_id: options.parent_id.clone(),
+            trim_silence: options.trim_silence,
+            chunks,
+        }
+    }
+
+    /// The options to run it again with; a source this version doesn't
+    /// know counts as the backend's own
+    pub fn options(&self) -> GenerationOptions {
+        let source = self.source.as_deref().and_then(|source| SOURCES.iter().find(|known| **known == source).copied());
+        GenerationOptions {
+            text: self.text.clone(),
+            voice_id: self.voice_id.clone(),
+            model: Some(self.model.clone()),
+            title: self.title.clone(),
+            source,
+            queue_if_offline: self.queue_if_offline,
+            downgrade: self.downgrade.clone(),
+            resolved: None,
+            sentence_gap_ms: self.sentence_gap_ms,
+            paragraph_gap_ms: self.paragraph_gap_ms,
+            parent_id: self.parent_id.clone(),
+            trim_silence: self.trim_silence,
+        }
+    }
+}
+
+/// A job interrupted before it finished, for offering to recover it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecoverableJob {
+    pub id: JobId,
+    pub title: Option<String>,
+    /// The start of the text
+    pub preview: String,
+    pub voice_id: String,
+    pub model: String,
+    pub characters: usize,
+    pub chunks: usize,
+    pub completed_chunks: usize,
+    /// `completed_chunks` out of `chunks`, 0 to 1
+    pub progress: f32,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<SavedJob<JobDefinition>> for RecoverableJob {
+    fn from(job: SavedJob<JobDefinition>) -> Self {
+        let definition = job.definition;
+        let chunks = definition.chunks.len().max(1);
+        let completed_chunks = job.chunks.len().min(chunks);
+        Self {
+            id: job.id,
+            title: definition.title,
+            preview: definition.text.chars().take(PREVIEW_CHARS).collect(),
+            voice_id: definition.voice_id,
+            model: definition.model,
+            characters: definition.text.chars().count(),
+            chunks,
+            completed_chunks,
+            progress: completed_chunks as f32 / chunks as f32,
+            created_at: job.created_at,
+        }
+    }
+}
+
+/// Saved jobs in the database, their chunks' audio in a directory of its
+/// own per job under `dir`
+pub struct JobStore {
+    database: Database,
+    dir: PathBuf,
+    runtime: tokio::runtime::Handle,
+}
+
+impl JobStore {
+    /// `forget_later` deletes jobs on `runtime`
+    pub fn new(database: Database, dir: PathBuf, runtime: tokio::runtime::Handle) -> Self {
+        Self { database, dir, runtime }
+    }
+
+    /// Keep job `id` until `forget`; saving it again changes nothing
+    pub async fn save(&self, id: &str, definition: &JobDefinition) -> Result<()> {
+        self.database.save_job(id, definition).await
+    }
+
+    /// Keep the audio of chunk `chunk` (from 0) of job `id`
+    pub async fn save_chunk(&self, id: &str, chunk: usize, audio: &[u8]) -> Result<()> {
+        let dir = self.dir.join(id);
+        tokio::fs::create_dir_all(&dir).await?;
+        let path = dir.join(format!("{}.chunk", chunk));
+        tokio::fs::write(&path, audio).await?;
+        self.database.save_job_chunk(id, chunk, &path).await
+    }
+
+    /// Every saved job, oldest first, including any still running
+    pub async fn list(&self) -> Result<Vec<SavedJob<JobDefinition>>> {
+        self.database.get_saved_jobs().await
+    }
+
+    /// Job `id` and the audio of its chunks done by index; a chunk whose
+    /// file has gone is generated again
+    pub async fn load(&self, id: &str) -> Result<Option<(JobDefinition, BTreeMap<usize, Vec<u8>>)>> {
+        let Some(job) = self.list().await?.into_iter().find(|job| job.id == id) else {
+            return Ok(None);
+        };
+        let mut done = BTreeMap::new();
+        for (chunk, path) in job.chunks.into_iter().filter(|(chunk, _)| *chunk < job.definition.chunks.len()) {
+            match tokio::fs::read(&path).await {
+                Ok(audio) => {
+                    done.insert(chunk, audio);
+                }
+                Err(e) => tracing::warn!("Chunk {} of job {} is lost ({}); generating it again", chunk + 1, id, e),
+            }
+        }
+        Ok(Some((job.definition, done)))
+    }
+
+    /// Delete job `id` and its chunk files
+    pub async fn forget(&self, id: &str) -> Result<()> {
+        forget(&self.database, &self.dir, id).await
+    }
+
+    /// `forget` without waiting for it, for callers that can't
+    pub fn forget_later(&self, id: &str) {
+        let (database, dir, id) = (self.database.clone(), self.dir.clone(), id.to_string());
+        self.runtime.spawn(async move {
+            if let Err(e) = forget(&database, &dir, &id).await {
+                tracing::warn!("Failed to delete saved job {}: {}", id, e);
+            }
+        });
+    }
+
+    /// Delete saved jobs created more than `max_age` ago, and chunk files
+    /// no saved job owns. Returns how many jobs were deleted.
+    pub async fn collect_garbage(&self, max_age: Duration) -> Result<usize> {
+        let cutoff = Utc::now() - chrono::Duration::from_std(max_age)?;
+        let saved = self.list().await?;
+        let mut deleted = 0;
+        for job in saved.iter().filter(|job| job.created_at < cutoff) {
+            self.forget(&job.id).await?;
+            deleted += 1;
+        }
+
+        let mut entries = match tokio::fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(deleted),
+            Err(e) => return Err(e.into()),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name();
+            if !saved.iter().any(|job| name == job.id.as_str()) {
+                tracing::debug!("Deleting chunks of unknown job {}", name.to_string_lossy());
+                remove_dir(&entry.path()).await?;
+            }
+        }
+        if deleted > 0 {
+            tracing::info!("Deleted {} interrupted job(s) older than {} days", deleted, max_age.as_secs() / 86_400);
+        }
+        Ok(deleted)
+    }
+}
+
+async fn forget(database: &Database, dir: &Path, id: &str) -> Result<()> {
+    database.delete_saved_job(id).await?;
+    remove_dir(&dir.join(id)).await
+}
+
+async fn remove_dir(dir: &Path) -> Result<()> {
+    match tokio::fs::remove_dir_all(dir).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn definition(chunks: &[&str]) -> JobDefinition {
+        JobDefinition {
+            text: chunks.join(" "),
+            voice_id: "nova".to_string(),
+            model: "tts-1".to_string(),
+            title: Some("Chapter 1".to_string()),
+            source: Some(crate::database::SOURCE_PROJECT.to_string()),
+            queue_if_offline: None,
+            downgrade: None,
+            sentence_gap_ms: Some(300),
+            paragraph_gap_ms: None,
+            parent_id: None,
+            trim_silence: false,
+            chunks: chunks.iter().map(|chunk| chunk.to_string()).collect(),
+        }
+    }
+
+    async fn store(dir: &Path) -> JobStore {
+        let database = Database::open(&dir.join("usage.db")).await.unwrap();
+        JobStore::new(database, dir.join("job-chunks"), tokio::runtime::Handle::current())
+    }
+
+    #[test]
+    fn test_definition_round_trips_to_options() {
+        let options = definition(&["One.", "Two."]).options();
+        assert_eq!(options.source, Some(crate::database::SOURCE_PROJECT));
+        assert_eq!((options.model.as_deref(), options.sentence_gap_ms), (Some("tts-1"), Some(300)));
+        assert_eq!(JobDefinition::new(&options, "tts-1", vec!["One.".to_string(), "Two.".to_string()]), definition(&["One.", "Two."]));
+
+        let unknown = JobDefinition { source: Some("from-the-future".to_string()), ..definition(&["One."]) };
+        assert_eq!(unknown.options().source, None);
+    }
+
+    #[tokio::test]
+    async fn test_load_skips_lost_chunks() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let store = store(dir.path()).await;
+        store.save("job", &definition(&["One.", "Two.", "Three."])).await.unwrap();
+        store.save_chunk("job", 0, b"one").await.unwrap();
+        store.save_chunk("job", 1, b"two").await.unwrap();
+        std::fs::remove_file(dir.path().join("job-chunks").join("job").join("1.chunk")).unwrap();
+
+        let (loaded, done) = store.load("job").await.unwrap().unwrap();
+        assert_eq!(loaded.chunks.len(), 3);
+        assert_eq!(done.into_iter().collect::<Vec<_>>(), [(0, b"one".to_vec())]);
+        assert!(store.load("other").await.unwrap().is_none());
+
+        let recoverable = RecoverableJob::from(store.list().await.unwrap().remove(0));
+        assert_eq!((recoverable.chunks, recoverable.completed_chunks, recoverable.characters), (3, 2, 16));
+        assert_eq!(recoverable.title.as_deref(), Some("Chapter 1"));
+    }
+
+    #[tokio::test]
+    async fn test_old_jobs_and_stray_chunks_are_collected() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let store = store(dir.path()).await;
+        store.save("old", &definition(&["Old."])).await.unwrap();
+        store.save_chunk("old", 0, b"old").await.unwrap();
+        std::fs::create_dir_all(dir.path().join("job-chunks").join("stray")).unwrap();
+
+        assert_eq!(store.collect_garbage(Duration::from_secs(3600)).await.unwrap(), 0);
+        assert!(dir.path().join("job-chunks").join("old").exists());
+        assert!(!dir.path().join("job-chunks").join("stray").exists());
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(store.collect_garbage(Duration::from_millis(5)).await.unwrap(), 1);
+        assert!(store.list().await.unwrap().is_empty());
+        assert!(!dir.path().join("job-chunks").join("old").exists());
+
+        // Nothing saved yet is fine too
+        let empty = tempfile::TempDir::new().unwrap();
+        assert_eq!(self::store(empty.path()).await.collect_garbage(Duration::ZERO).await.unwrap(), 0);
+    }
+}
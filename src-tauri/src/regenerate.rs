@@ -0,0 +1,209 @@
+//! Generating a history entry again, optionally with another voice, model
+//! or speed. Only short requests keep their whole text in the history;
+//! longer ones are stored as a preview and can't be regenerated.
+
+use crate::database::UsageRecord;
+use crate::i18n::CommandError;
+use crate::resolve::{self, Resolved};
+use crate::settings::Settings;
+use crate::spend::{apply_budget, BudgetedModel};
+use crate::tts::{TTSService, VALID_MODEL_IDS, VALID_VOICE_IDS};
+use crate::warnings;
+use serde::Deserialize;
+
+/// What to change from the original request; anything left out is reused
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RegenerateOverrides {
+    pub voice_id: Option<String>,
+    pub model: Option<String>,
+    pub speed: Option<f32>,
+}
+
+/// The request to make in place of history record `original_id`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Regeneration {
+    pub original_id: i64,
+    pub text: String,
+    pub voice_id: String,
+    pub model: String,
+    /// `None` keeps the service's current speed, which the history doesn't record
+    pub speed: Option<f32>,
+}
+
+/// Whether `record` holds the text that was sent rather than a preview of it
+pub fn has_full_text(record: &UsageRecord) -> bool {
+    record.text.len() == record.character_count as usize
+}
+
+pub fn plan(record: &UsageRecord, overrides: RegenerateOverrides) -> Result<Regeneration, String> {
+    let original_id = record.id.ok_or("History record has no id")?;
+    if !has_full_text(record) {
+        return Err(format!(
+            "History record {} only kept a preview of its {} characters, so it can't be generated again",
+            original_id, record.character_count
+        ));
+    }
+
+    let voice_id = overrides.voice_id.unwrap_or_else(|| record.voice_id.clone());
+    if !VALID_VOICE_IDS.contains(&voice_id.as_str()) {
+        return Err(format!("Unknown voice '{}'", voice_id));
+    }
+    let model = overrides.model.unwrap_or_else(|| record.model_id.clone());
+    if !VALID_MODEL_IDS.contains(&model.as_str()) {
+        return Err(format!("Unknown model '{}'", model));
+    }
+    if let Some(speed) = overrides.speed {
+        if !(0.25..=4.0).contains(&speed) {
+            return Err(format!("Speed must be between 0.25 and 4.0, got {}", speed));
+        }
+    }
+
+    Ok(Regeneration { original_id, text: record.text.clone(), voice_id, model, speed: overrides.speed })
+}
+
+/// A history record generated again, and what with
+pub struct Regenerated {
+    pub audio: Vec<u8>,
+    pub regeneration: Regeneration,
+    /// The voice and model asked for, either overridden or the record's own
+    pub resolved: Resolved,
+    pub budgeted: BudgetedModel,
+}
+
+/// Generate history record `record_id` again with `overrides`, within the
+/// budget like any other generation, and record it as a regeneration
+pub async fn run(
+    tts_service: &TTSService,
+    settings: &Settings,
+    record_id: i64,
+    overrides: RegenerateOverrides,
+) -> Result<Regenerated, CommandError> {
+    let database = tts_service.database().ok_or("History is unavailable")?;
+    let record = database
+        .get_usage_record(record_id)
+        .await
+        .map_err(|e| format!("Failed to read history: {}", e))?
+        .ok_or_else(|| format!("No history record {}", record_id))?;
+    let regeneration = plan(&record, overrides)?;
+    let resolved = resolve::resolve(Some(&regeneration.voice_id), Some(&regeneration.model), settings)?;
+    let service = match regeneration.speed {
+        Some(speed) => tts_service.at_speed(speed),
+        None => tts_service.clone(),
+    };
+    service.validate_text(&regeneration.text).await?;
+
+    let budgeted = apply_budget(&service, settings, &regeneration.model, &regeneration.text).await?;
+    if let Some(downgrade) = &budgeted.downgrade {
+        warnings::warn(warnings::downgraded(downgrade));
+    }
+    tracing::info!("Regenerating history record {} with {}/{}", record_id, regeneration.voice_id, budgeted.model);
+    let audio = service.generate_speech_with_model(&regeneration.text, &regeneration.voice_id, &budgeted.model).await?;
+    let downgraded_from = budgeted.downgrade.as_ref().map(|downgrade| downgrade.from.as_str());
+    let _ = service
+        .track_regeneration(record_id, &regeneration.text, &regeneration.voice_id, &budgeted.model, downgraded_from)
+        .await;
+    Ok(Regenerated { audio, regeneration, resolved, budgeted })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn record(text: &str, character_count: i32) -> UsageRecord {
+        UsageRecord {
+            id: Some(7),
+            timestamp: Utc::now(),
+            text: text.to_string(),
+            character_count,
+            voice_id: "alloy".to_string(),
+            model_id: "tts-1".to_string(),
+            success: true,
+            error_message: None,
+            source: "app".to_string(),
+            regenerated_from: None,
+            downgraded_from: None,
+            key_fingerprint: None,
+            app_version: None,
+            profile: crate::database::DEFAULT_PROFILE.to_string(),
+            latency_ms: None,
+            chunk_count: None,
+        }
+    }
+
+    #[test]
+    fn test_reuses_the_original_request() {
+        let regeneration = plan(&record("Hello there", 11), RegenerateOverrides::default()).unwrap();
+        assert_eq!(
+            regeneration,
+            Regeneration {
+                original_id: 7,
+                text: "Hello there".to_string(),
+                voice_id: "alloy".to_string(),
+                model: "tts-1".to_string(),
+                speed: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_applies_overrides() {
+        let overrides = RegenerateOverrides {
+            voice_id: Some("nova".to_string()),
+            model: Some("tts-1-hd".to_string()),
+            speed: Some(1.5),
+        };
+        let regeneration = plan(&record("Hello there", 11), overrides).unwrap();
+        assert_eq!(regeneration.text, "Hello there");
+        assert_eq!(regeneration.voice_id, "nova");
+        assert_eq!(regeneration.model, "tts-1-hd");
+        assert_eq!(regeneration.speed, Some(1.5));
+
+        // Overrides arrive from the window as camelCase JSON, any of them optional
+        let overrides: RegenerateOverrides = serde_json::from_str(r#"{"voiceId":"echo"}"#).unwrap();
+        let regeneration = plan(&record("Hello there", 11), overrides).unwrap();
+        assert_eq!((regeneration.voice_id.as_str(), regeneration.model.as_str()), ("echo", "tts-1"));
+    }
+
+    #[test]
+    fn test_rejects_invalid_overrides() {
+        let bad_voice = RegenerateOverrides { voice_id: Some("robot".to_string()), ..Default::default() };
+        assert!(plan(&record("Hello there", 11), bad_voice).unwrap_err().contains("robot"));
+        let bad_model = RegenerateOverrides { model: Some("tts-9".to_string()), ..Default::default() };
+        assert!(plan(&record("Hello there", 11), bad_model).is_err());
+        let bad_speed = RegenerateOverrides { speed: Some(5.0), ..Default::default() };
+        assert!(plan(&record("Hello there", 11), bad_speed).is_err());
+    }
+
+    #[test]
+    fn test_preview_only_records_are_refused() {
+        let preview = format!("{}...", "a".repeat(97));
+        let error = plan(&record(&preview, 250), RegenerateOverrides::default()).unwrap_err();
+        assert!(error.contains("preview"), "{}", error);
+        assert!(!has_full_text(&record(&preview, 250)));
+        assert!(has_full_text(&record(&"a".repeat(100), 100)));
+    }
+
+    #[tokio::test]
+    async fn test_says_what_it_was_generated_with() {
+        let mut server = mockito::Server::new_async().await;
+        server.mock("POST", "/v1/audio/speech").with_status(200).with_body("audio").create_async().await;
+        let dir = tempfile::TempDir::new().unwrap();
+        let config = crate::config::Config {
+            data_dir: dir.path().to_path_buf(),
+            api_base_url: server.url(),
+            ..crate::config::Config::default()
+        };
+        let service = TTSService::from_config(Some("sk-test"), &config).await.unwrap();
+        let settings = Settings::from_config(&config);
+        service.track_usage("Read this again", "alloy", "tts-1-hd", true, None).await.unwrap();
+        let original = service.database().unwrap().get_usage_records(1, None).await.unwrap()[0].id.unwrap();
+
+        let overrides = RegenerateOverrides { voice_id: Some("nova".to_string()), ..Default::default() };
+        let regenerated = run(&service, &settings, original, overrides).await.unwrap();
+        let resolved = regenerated.resolved;
+        assert_eq!((resolved.voice_id.as_str(), resolved.model.as_str()), ("nova", "tts-1-hd"));
+        assert_eq!((resolved.voice_from, resolved.model_from), (resolve::Origin::Request, resolve::Origin::Request));
+    }
+}
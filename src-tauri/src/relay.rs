@@ -0,0 +1,145 @@
+//! Events for the window that can fire before it is listening: a job
+//! enqueued at launch may finish, or a launch link produce audio, while the
+//! webview is still loading. Until the frontend calls `frontend_ready` they
+//! are kept, then replayed in order; after that they go straight out.
+
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// Past this many early events the oldest are dropped
+pub const MAX_BUFFERED: usize = 256;
+
+enum State {
+    Buffering(VecDeque<(String, Value)>),
+    Live,
+}
+
+pub struct EventRelay {
+    // Emitting happens under the lock, so a live event can't overtake the replay
+    state: Mutex<State>,
+}
+
+impl Default for EventRelay {
+    fn default() -> Self {
+        Self { state: Mutex::new(State::Buffering(VecDeque::new())) }
+    }
+}
+
+impl EventRelay {
+    /// Pass `event` to `emit` if the window is listening, or keep it until it is
+    pub fn send<S: Serialize>(&self, event: &str, payload: &S, emit: &dyn Fn(&str, &Value)) {
+        let payload = match serde_json::to_value(payload) {
+            Ok(payload) => payload,
+            Err(e) => {
+                tracing::warn!("Failed to serialize {} event: {}", event, e);
+                return;
+            }
+        };
+        let mut state = self.state.lock().unwrap();
+        match &mut *state {
+            State::Live => emit(event, &payload),
+            State::Buffering(buffered) => {
+                if buffered.len() == MAX_BUFFERED {
+                    buffered.pop_front();
+                }
+                buffered.push_back((event.to_string(), payload));
+            }
+        }
+    }
+
+    /// The window is listening: replay what it missed, oldest first, and
+    /// send everything after straight away. Returns how many were replayed;
+    /// a reloaded window calling this again gets nothing twice.
+    pub fn ready(&self, emit: &dyn Fn(&str, &Value)) -> usize {
+        let mut state = self.state.lock().unwrap();
+        let State::Buffering(buffered) = std::mem::replace(&mut *state, State::Live) else {
+            return 0;
+        };
+        for (event, payload) in &buffered {
+            emit(event, payload);
+        }
+        buffered.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    type Sent = Arc<Mutex<Vec<(String, Value)>>>;
+
+    fn recorder() -> (Sent, impl Fn(&str, &Value)) {
+        let sent: Sent = Arc::default();
+        let recorded = sent.clone();
+        (sent, move |event: &str, payload: &Value| recorded.lock().unwrap().push((event.to_string(), payload.clone())))
+    }
+
+    fn events(sent: &Sent) -> Vec<String> {
+        sent.lock().unwrap().iter().map(|(event, payload)| format!("{} {}", event, payload)).collect()
+    }
+
+    #[test]
+    fn test_early_events_are_replayed_in_order() {
+        let relay = EventRelay::default();
+        let (sent, emit) = recorder();
+        relay.send("job:changed", &json!({ "state": "queued" }), &emit);
+        relay.send("job:changed", &json!({ "state": "done" }), &emit);
+        assert!(sent.lock().unwrap().is_empty(), "nothing goes out before the window is ready");
+
+        assert_eq!(relay.ready(&emit), 2);
+        relay.send("deep-link-speech", &json!({ "text": "Hi" }), &emit);
+        assert_eq!(
+            events(&sent),
+            [
+                r#"job:changed {"state":"queued"}"#,
+                r#"job:changed {"state":"done"}"#,
+                r#"deep-link-speech {"text":"Hi"}"#
+            ]
+        );
+
+        // A reload doesn't replay again
+        assert_eq!(relay.ready(&emit), 0);
+        assert_eq!(sent.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_buffer_keeps_the_newest() {
+        let relay = EventRelay::default();
+        let (sent, emit) = recorder();
+        for i in 0..MAX_BUFFERED + 10 {
+            relay.send("tick", &i, &emit);
+        }
+        assert_eq!(relay.ready(&emit), MAX_BUFFERED);
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent[0].1, json!(10));
+        assert_eq!(sent.last().unwrap().1, json!(MAX_BUFFERED + 9));
+    }
+
+    #[test]
+    fn test_events_racing_ready_are_neither_lost_nor_reordered() {
+        let relay = Arc::new(EventRelay::default());
+        let (sent, emit) = recorder();
+        let emit = Arc::new(emit);
+        for i in 0..50 {
+            relay.send("n", &i, &*emit);
+        }
+
+        let sender = {
+            let (relay, emit) = (relay.clone(), emit.clone());
+            std::thread::spawn(move || {
+                for i in 50..200 {
+                    relay.send("n", &i, &*emit);
+                }
+            })
+        };
+        relay.ready(&*emit);
+        sender.join().unwrap();
+
+        let numbers: Vec<u64> = sent.lock().unwrap().iter().map(|(_, payload)| payload.as_u64().unwrap()).collect();
+        assert_eq!(numbers, (0..200).collect::<Vec<_>>());
+    }
+}
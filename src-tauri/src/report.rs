@@ -0,0 +1,353 @@
+use crate::batch::{BatchSummary, FileOutcome};
+use crate::database::{DailyUsage, ModelUsage, UsageRecord, UsageStats};
+use crate::tts::{estimate_cost, GenerationEstimate};
+use serde::Serialize;
+
+/// Preview column width, chosen so a history row fits in 120 columns
+const PREVIEW_WIDTH: usize = 48;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StatsReport {
+    pub days: i32,
+    pub total_requests: i64,
+    pub total_characters: i64,
+    pub successful_requests: i64,
+    pub failed_requests: i64,
+    pub success_rate: f64,
+    pub estimated_cost: f64,
+    pub daily_usage: Vec<DailyUsage>,
+}
+
+impl StatsReport {
+    /// The cost is worked out from `model_usage`, which should be the billed
+    /// usage of `Database::get_billed_model_usage`
+    pub fn new(days: i32, stats: UsageStats, model_usage: &[ModelUsage]) -> Self {
+        let success_rate = if stats.total_requests > 0 {
+            stats.successful_requests as f64 / stats.total_requests as f64 * 100.0
+        } else {
+            0.0
+        };
+        let estimated_cost = model_usage
+            .iter()
+            .map(|usage| estimate_cost(usage.character_count, &usage.model_id))
+            .sum();
+
+        Self {
+            days,
+            total_requests: stats.total_requests,
+            total_characters: stats.total_characters,
+            successful_requests: stats.successful_requests,
+            failed_requests: stats.failed_requests,
+            success_rate,
+            estimated_cost,
+            daily_usage: stats.daily_usage,
+        }
+    }
+}
+
+pub fn format_stats(report: &StatsReport) -> String {
+    let mut out = String::new();
+    out.push_str(&format!("Usage over the last {} days\n", report.days));
+    out.push_str(&format!("  Requests:       {}\n", group_thousands(report.total_requests)));
+    out.push_str(&format!("  Characters:     {}\n", group_thousands(report.total_characters)));
+    out.push_str(&format!("  Estimated cost: ${:.2}\n", report.estimated_cost));
+    out.push_str(&format!("  Success rate:   {:.1}%\n", report.success_rate));
+
+    if !report.daily_usage.is_empty() {
+        out.push('\n');
+        out.push_str(&format!("  {:<12} {:>10} {:>12}\n", "Date", "Requests", "Characters"));
+        for day in &report.daily_usage {
+            out.push_str(&format!(
+                "  {:<12} {:>10} {:>12}\n",
+                day.date,
+                group_thousands(day.request_count),
+                group_thousands(day.character_count)
+            ));
+        }
+    }
+
+    out
+}
+
+pub fn format_estimate(estimate: &GenerationEstimate) -> String {
+    let mut out = String::new();
+    out.push_str(&format!("  Characters:         {}\n", group_thousands(estimate.character_count as i64)));
+    out.push_str(&format!("  Chunks:             {}\n", estimate.chunk_count));
+    out.push_str(&format!("  Estimated duration: {}\n", format_duration(estimate.estimated_duration_secs)));
+    out.push_str(&format!("  Cost (tts-1):       ${:.2}\n", estimate.estimated_cost_tts_1));
+    out.push_str(&format!("  Cost (tts-1-hd):    ${:.2}\n", estimate.estimated_cost_tts_1_hd));
+    out
+}
+
+pub fn format_batch_summary(summary: &BatchSummary) -> String {
+    let mut out = String::new();
+    for file in &summary.files {
+        if let FileOutcome::Failed { error } = &file.outcome {
+            out.push_str(&format!("  failed  {}: {}\n", file.input.display(), escape_control_chars(error)));
+        }
+    }
+    out.push_str(&format!(
+        "Succeeded: {}  Failed: {}  Skipped: {}\n",
+        summary.succeeded, summary.failed, summary.skipped
+    ));
+    out.push_str(&format!("  Characters:     {}\n", group_thousands(summary.total_characters as i64)));
+    out.push_str(&format!("  Estimated cost: ${:.2}\n", summary.estimated_cost));
+    out
+}
+
+/// 3725.0 -> "1:02:05", 65.0 -> "1:05"
+pub fn format_duration(seconds: f64) -> String {
+    let total = seconds.round().max(0.0) as u64;
+    let (hours, minutes, secs) = (total / 3600, (total % 3600) / 60, total % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, secs)
+    } else {
+        format!("{}:{:02}", minutes, secs)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryEntry {
+    pub id: Option<i64>,
+    pub timestamp: String,
+    pub voice_id: String,
+    pub model_id: String,
+    pub character_count: i32,
+    pub success: bool,
+    pub error_message: Option<String>,
+    pub source: String,
+    pub app_version: Option<String>,
+    pub text: String,
+}
+
+pub fn history_json(records: &[UsageRecord]) -> Vec<HistoryEntry> {
+    records
+        .iter()
+        .map(|record| HistoryEntry {
+            id: record.id,
+            timestamp: record.timestamp.to_rfc3339(),
+            voice_id: record.voice_id.clone(),
+            model_id: record.model_id.clone(),
+            character_count: record.character_count,
+            success: record.success,
+            error_message: record.error_message.clone(),
+            source: record.source.clone(),
+            app_version: record.app_version.clone(),
+            text: record.text.clone(),
+        })
+        .collect()
+}
+
+pub fn format_history(records: &[UsageRecord]) -> String {
+    if records.is_empty() {
+        return "No generations recorded\n".to_string();
+    }
+
+    let mut out = format!(
+        "{:<19}  {:<8} {:<9} {:>7}  {:<2}  {}\n",
+        "Timestamp", "Voice", "Model", "Chars", "OK", "Text"
+    );
+    for record in records {
+        out.push_str(&format!(
+            "{:<19}  {:<8} {:<9} {:>7}  {:<2}  {}\n",
+            record.timestamp.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string(),
+            record.voice_id,
+            record.model_id,
+            record.character_count,
+            if record.success { "y" } else { "n" },
+            truncate_chars(&escape_control_chars(&record.text), PREVIEW_WIDTH)
+        ));
+    }
+    out
+}
+
+/// Escape anything that would move the cursor or break the table layout
+pub fn escape_control_chars(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Shorten to at most `max_chars` characters (not bytes), marking the cut with "…"
+pub fn truncate_chars(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let mut truncated: String = text.chars().take(max_chars.saturating_sub(1)).collect();
+    truncated.push('…');
+    truncated
+}
+
+/// 1234567 -> "1,234,567"
+pub fn group_thousands(value: i64) -> String {
+    let digits = value.unsigned_abs().to_string();
+    let mut grouped = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+    if value < 0 {
+        format!("-{}", grouped)
+    } else {
+        grouped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+use crate::database::{Database, UsageRecord};
+    use chrono::Utc;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(0.0), "0:00");
+        assert_eq!(format_duration(65.4), "1:05");
+        assert_eq!(format_duration(3725.0), "1:02:05");
+    }
+
+    #[test]
+    fn test_format_estimate() {
+        let estimate = crate::tts::TTSService::estimate(&"a".repeat(1500));
+        let text = format_estimate(&estimate);
+        assert!(text.contains("Characters:         1,500"));
+        assert!(text.contains("Chunks:             1"));
+        assert!(text.contains("Estimated duration: 1:40"));
+        assert!(text.contains("Cost (tts-1-hd):    $0.04"));
+    }
+
+    #[test]
+    fn test_group_thousands() {
+        assert_eq!(group_thousands(0), "0");
+        assert_eq!(group_thousands(999), "999");
+        assert_eq!(group_thousands(1000), "1,000");
+        assert_eq!(group_thousands(1234567), "1,234,567");
+        assert_eq!(group_thousands(-4200), "-4,200");
+    }
+
+    #[tokio::test]
+    async fn test_stats_report_from_seeded_database() {
+        let dir = TempDir::new().unwrap();
+        let db = Database::open(&dir.path().join("usage.db")).await.unwrap();
+
+        for (chars, model, success) in [(3000, "tts-1", true), (1500, "tts-1-hd", true), (500, "tts-1-hd", false)] {
+            db.record_usage(&UsageRecord {
+                id: None,
+                timestamp: Utc::now(),
+                text: "Seeded".to_string(),
+                character_count: chars,
+                voice_id: "nova".to_string(),
+                model_id: model.to_string(),
+                success,
+                error_message: None,
+                source: crate::database::SOURCE_APP.to_string(),
+                regenerated_from: None,
+                downgraded_from: None,
+                key_fingerprint: None,
+                app_version: None,
+                profile: crate::database::DEFAULT_PROFILE.to_string(),
+                latency_ms: None,
+                chunk_count: None,
+            })
+            .await
+            .unwrap();
+        }
+
+        let stats = db.get_usage_stats(30, None).await.unwrap();
+        let models = db.get_billed_model_usage(30, None).await.unwrap();
+        let report = StatsReport::new(30, stats, &models);
+
+        assert_eq!(report.total_requests, 3);
+        assert_eq!(report.total_characters, 5000);
+        assert!((report.success_rate - 66.666).abs() < 0.01);
+        // 3000 * $15/1M + 1500 * $30/1M; the failed request isn't billed
+        assert!((report.estimated_cost - 0.09).abs() < 1e-9);
+
+        let text = format_stats(&report);
+        assert!(text.contains("Requests:       3"));
+        assert!(text.contains("Characters:     5,000"));
+        assert!(text.contains("Estimated cost: $0.09"));
+        assert!(text.contains("Success rate:   66.7%"));
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["total_requests"], 3);
+        assert_eq!(json["daily_usage"].as_array().unwrap().len(), 1);
+    }
+
+    fn history_record(text: &str, success: bool) -> UsageRecord {
+        UsageRecord {
+            id: Some(7),
+            timestamp: Utc::now(),
+            text: text.to_string(),
+            character_count: text.chars().count() as i32,
+            voice_id: "nova".to_string(),
+            model_id: "tts-1-hd".to_string(),
+            success,
+            error_message: if success { None } else { Some("HTTP 500".to_string()) },
+            source: crate::database::SOURCE_APP.to_string(),
+            regenerated_from: None,
+            downgraded_from: None,
+            key_fingerprint: None,
+            app_version: None,
+            profile: crate::database::DEFAULT_PROFILE.to_string(),
+            latency_ms: None,
+            chunk_count: None,
+        }
+    }
+
+    #[test]
+    fn test_truncate_on_char_boundaries() {
+        assert_eq!(truncate_chars("short", 10), "short");
+        assert_eq!(truncate_chars("héllo wörld", 6), "héllo…");
+        assert_eq!(truncate_chars("你好世界你好世界", 4), "你好世…");
+    }
+
+    #[test]
+    fn test_control_characters_escaped() {
+        assert_eq!(escape_control_chars("a\nb\tc"), "a\\nb\\tc");
+        assert_eq!(escape_control_chars("bell\u{7}"), "bell\\u{7}");
+        assert_eq!(escape_control_chars("\u{1b}[31mred"), "\\u{1b}[31mred");
+    }
+
+    #[test]
+    fn test_history_columns_align() {
+        let records = vec![
+            history_record("First line\nsecond line", true),
+            history_record(&"long ".repeat(40), false),
+        ];
+        let text = format_history(&records);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("Timestamp"));
+        assert!(lines[1].contains("First line\\nsecond line"));
+        assert!(lines[2].ends_with('…'));
+
+        let text_column = lines[0].find("Text").unwrap();
+        for line in &lines[1..] {
+            assert!(line[..text_column].ends_with("  "));
+            assert!(!line[text_column..].starts_with(' '));
+        }
+    }
+
+    #[test]
+    fn test_history_json_shape() {
+        let json = serde_json::to_value(history_json(&[history_record("Hi", false)])).unwrap();
+        let entry = &json.as_array().unwrap()[0];
+        for key in ["id", "timestamp", "voice_id", "model_id", "character_count", "success", "error_message", "source", "app_version", "text"] {
+            assert!(entry.get(key).is_some(), "missing {}", key);
+        }
+        assert_eq!(entry["success"], false);
+        assert_eq!(entry["error_message"], "HTTP 500");
+    }
+}
@@ -0,0 +1,102 @@
+//! Which voice and model a generation uses, wherever it starts: the one it
+//! asked for, else the default in the settings, else the built-in one from
+//! `Config::default`. Validation comes after: a value asked for must be
+//! valid, while a settings default that isn't (say, a voice since removed)
+//! is passed over. There are no presets yet; they would come between the
+//! request and the settings.
+
+use crate::config::Config;
+use crate::i18n::{self, Message};
+use crate::settings::Settings;
+use crate::tts::{VALID_MODEL_IDS, VALID_VOICE_IDS};
+use serde::Serialize;
+
+/// Where a resolved value came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Origin {
+    Request,
+    Settings,
+    BuiltIn,
+}
+
+/// What a generation was made with, handed back for the window to show
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Resolved {
+    pub voice_id: String,
+    pub model: String,
+    pub voice_from: Origin,
+    pub model_from: Origin,
+}
+
+/// The voice and model for a generation that asked for `voice_id` and
+/// `model`; empty counts as not asking
+pub fn resolve(voice_id: Option<&str>, model: Option<&str>, settings: &Settings) -> Result<Resolved, Message> {
+    let built_in = Config::default();
+    let (voice_id, voice_from) =
+        pick("voice", voice_id, &settings.voice, &built_in.voice, VALID_VOICE_IDS).map_err(i18n::invalid_voice)?;
+    let (model, model_from) =
+        pick("model", model, &settings.model, &built_in.model, VALID_MODEL_IDS).map_err(i18n::invalid_model)?;
+    Ok(Resolved { voice_id, model, voice_from, model_from })
+}
+
+/// `resolve` for a generation that asks for nothing in particular
+pub fn defaults(settings: &Settings) -> Resolved {
+    resolve(None, None, settings).expect("only a requested value can be invalid")
+}
+
+/// The first of `requested`, `setting` and `built_in` to use, or the
+/// requested value if it isn't in `valid`
+fn pick<'a>(
+    what: &str,
+    requested: Option<&'a str>,
+    setting: &str,
+    built_in: &str,
+    valid: &[&str],
+) -> Result<(String, Origin), &'a str> {
+    if let Some(requested) = requested.map(str::trim).filter(|requested| !requested.is_empty()) {
+        return if valid.contains(&requested) { Ok((requested.to_string(), Origin::Request)) } else { Err(requested) };
+    }
+    if valid.contains(&setting) {
+        return Ok((setting.to_string(), Origin::Settings));
+    }
+    tracing::warn!("The default {} \"{}\" in the settings isn't valid; using {}", what, setting, built_in);
+    Ok((built_in.to_string(), Origin::BuiltIn))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(voice: &str, model: &str) -> Settings {
+        Settings { voice: voice.to_string(), model: model.to_string(), ..Settings::from_config(&Config::default()) }
+    }
+
+    #[test]
+    fn test_each_level_of_the_chain() {
+        let saved = settings("onyx", "tts-1");
+
+        let requested = resolve(Some("shimmer"), Some("tts-1-hd"), &saved).unwrap();
+        assert_eq!((requested.voice_id.as_str(), requested.voice_from), ("shimmer", Origin::Request));
+        assert_eq!((requested.model.as_str(), requested.model_from), ("tts-1-hd", Origin::Request));
+
+        let from_settings = resolve(Some(""), None, &saved).unwrap();
+        assert_eq!((from_settings.voice_id.as_str(), from_settings.voice_from), ("onyx", Origin::Settings));
+        assert_eq!((from_settings.model.as_str(), from_settings.model_from), ("tts-1", Origin::Settings));
+
+        // A stale default falls through to the built-in one
+        let built_in = Config::default();
+        let stale = defaults(&settings("rachel", "tts-2"));
+        assert_eq!((stale.voice_id, stale.voice_from), (built_in.voice, Origin::BuiltIn));
+        assert_eq!((stale.model, stale.model_from), (built_in.model, Origin::BuiltIn));
+    }
+
+    #[test]
+    fn test_invalid_request_is_refused() {
+        let saved = settings("onyx", "tts-1");
+        assert_eq!(resolve(Some("rachel"), None, &saved).unwrap_err().key, "error.invalid_voice");
+        let error = resolve(None, Some("tts-2"), &saved).unwrap_err();
+        assert_eq!((error.key, error.params["model"].as_str()), ("error.invalid_model", Some("tts-2")));
+    }
+}
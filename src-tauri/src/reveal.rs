@@ -0,0 +1,203 @@
+//! Showing a file in the system file manager: selected in Finder or
+//! Explorer, or its folder opened on Linux, where there is no common way to
+//! select it. Only files under the app's own directories or a location the
+//! user exported to can be shown, so the command can't be pointed at
+//! anything else on the machine.
+
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Platform {
+    MacOs,
+    Windows,
+    Linux,
+}
+
+impl Platform {
+    pub fn current() -> Self {
+        if cfg!(target_os = "macos") {
+            Platform::MacOs
+        } else if cfg!(windows) {
+            Platform::Windows
+        } else {
+            Platform::Linux
+        }
+    }
+}
+
+/// The program and arguments that show `path` on `platform`
+pub fn command(platform: Platform, path: &Path) -> (&'static str, Vec<OsString>) {
+    match platform {
+        Platform::MacOs => ("open", vec!["-R".into(), path.into()]),
+        Platform::Windows => {
+            // One argument; Explorer splits it at the comma itself
+            let mut select = OsString::from("/select,");
+            select.push(path);
+            ("explorer", vec![select])
+        }
+        Platform::Linux => ("xdg-open", vec![path.parent().unwrap_or(path).into()]),
+    }
+}
+
+/// The program and arguments that open the directory `dir`
+pub fn open_command(platform: Platform, dir: &Path) -> (&'static str, Vec<OsString>) {
+    let program = match platform {
+        Platform::MacOs => "open",
+        Platform::Windows => "explorer",
+        Platform::Linux => "xdg-open",
+    };
+    (program, vec![dir.into()])
+}
+
+/// Starts the file manager; a trait so tests can see what would be run
+pub trait Launcher {
+    fn launch(&self, program: &str, args: &[OsString]) -> io::Result<()>;
+}
+
+/// Runs it, without waiting for the file manager to close
+pub struct SystemLauncher;
+
+impl Launcher for SystemLauncher {
+    fn launch(&self, program: &str, args: &[OsString]) -> io::Result<()> {
+        let mut child = std::process::Command::new(program)
+            .args(args)
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .spawn()?;
+        // Reaped in the background so it doesn't linger as a zombie
+        std::thread::spawn(move || child.wait());
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum RevealError {
+    /// Nothing is there any more
+    Missing(PathBuf),
+    /// Not under the app's directories or an export location
+    NotAllowed(PathBuf),
+    Launch(&'static str, io::Error),
+}
+
+impl std::fmt::Display for RevealError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RevealError::Missing(path) => write!(f, "{} no longer exists", path.display()),
+            RevealError::NotAllowed(path) => {
+                write!(f, "{} isn't in the app's folders or one audio was exported to", path.display())
+            }
+            RevealError::Launch(program, e) => write!(f, "Failed to run {}: {}", program, e),
+        }
+    }
+}
+
+impl std::error::Error for RevealError {}
+
+/// `path` with links resolved, if it exists and is under one of `roots`.
+/// Roots that don't exist are skipped.
+pub fn allowed(path: &Path, roots: &[PathBuf]) -> Result<PathBuf, RevealError> {
+    let resolved = path.canonicalize().map_err(|_| RevealError::Missing(path.to_path_buf()))?;
+    let inside = roots
+        .iter()
+        .filter_map(|root| root.canonicalize().ok())
+        .any(|root| resolved.starts_with(root));
+    if !inside {
+        return Err(RevealError::NotAllowed(path.to_path_buf()));
+    }
+    Ok(resolved)
+}
+
+/// Show `path` in the file manager, if it is under one of `roots`
+pub fn reveal(path: &Path, roots: &[PathBuf], platform: Platform, launcher: &dyn Launcher) -> Result<(), RevealError> {
+    let resolved = allowed(path, roots)?;
+    let (program, args) = command(platform, &resolved);
+    tracing::debug!("Revealing {} with {}", resolved.display(), program);
+    launcher.launch(program, &args).map_err(|e| RevealError::Launch(program, e))
+}
+
+/// Open the app's own directory `dir`
+pub fn open_dir(dir: &Path, platform: Platform, launcher: &dyn Launcher) -> Result<(), RevealError> {
+    if !dir.is_dir() {
+        return Err(RevealError::Missing(dir.to_path_buf()));
+    }
+    let (program, args) = open_command(platform, dir);
+    launcher.launch(program, &args).map_err(|e| RevealError::Launch(program, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<(String, Vec<OsString>)>>);
+
+    impl Launcher for Recorder {
+        fn launch(&self, program: &str, args: &[OsString]) -> io::Result<()> {
+            self.0.lock().unwrap().push((program.to_string(), args.to_vec()));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_command_per_platform() {
+        let path = Path::new("/data/tts-player/clip.mp3");
+        assert_eq!(command(Platform::MacOs, path), ("open", vec!["-R".into(), path.into()]));
+        assert_eq!(command(Platform::Windows, path), ("explorer", vec!["/select,/data/tts-player/clip.mp3".into()]));
+        assert_eq!(command(Platform::Linux, path), ("xdg-open", vec!["/data/tts-player".into()]));
+
+        let dir = Path::new("/data/tts-player");
+        assert_eq!(open_command(Platform::MacOs, dir), ("open", vec![dir.into()]));
+        assert_eq!(open_command(Platform::Windows, dir), ("explorer", vec![dir.into()]));
+        assert_eq!(open_command(Platform::Linux, dir), ("xdg-open", vec![dir.into()]));
+    }
+
+    #[test]
+    fn test_only_known_locations_are_revealed() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let audio_dir = temp_dir.path().join("audio");
+        let export = temp_dir.path().join("book.m4a");
+        let elsewhere = temp_dir.path().join("elsewhere");
+        std::fs::create_dir_all(&audio_dir).unwrap();
+        std::fs::create_dir_all(&elsewhere).unwrap();
+        std::fs::write(audio_dir.join("clip.mp3"), "").unwrap();
+        std::fs::write(&export, "").unwrap();
+        std::fs::write(elsewhere.join("secret.txt"), "").unwrap();
+        let roots = [audio_dir.clone(), export.clone(), temp_dir.path().join("not-yet")];
+
+        let recorder = Recorder::default();
+        reveal(&audio_dir.join("clip.mp3"), &roots, Platform::MacOs, &recorder).unwrap();
+        reveal(&export, &roots, Platform::Linux, &recorder).unwrap();
+        let launched = recorder.0.lock().unwrap().clone();
+        let resolved = audio_dir.join("clip.mp3").canonicalize().unwrap();
+        assert_eq!(launched[0], ("open".to_string(), vec!["-R".into(), resolved.into()]));
+        assert_eq!(launched[1], ("xdg-open".to_string(), vec![temp_dir.path().canonicalize().unwrap().into()]));
+
+        let outside = reveal(&elsewhere.join("secret.txt"), &roots, Platform::MacOs, &recorder);
+        assert!(matches!(outside, Err(RevealError::NotAllowed(_))), "{:?}", outside);
+        // `..` doesn't get out either
+        let dotted = audio_dir.join("..").join("elsewhere").join("secret.txt");
+        assert!(matches!(allowed(&dotted, &roots), Err(RevealError::NotAllowed(_))));
+
+        std::fs::remove_file(audio_dir.join("clip.mp3")).unwrap();
+        let missing = reveal(&audio_dir.join("clip.mp3"), &roots, Platform::MacOs, &recorder).unwrap_err();
+        assert!(matches!(missing, RevealError::Missing(_)));
+        assert!(missing.to_string().ends_with("clip.mp3 no longer exists"), "{}", missing);
+        assert_eq!(recorder.0.lock().unwrap().len(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_links_out_of_a_known_location_are_refused() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let audio_dir = temp_dir.path().join("audio");
+        std::fs::create_dir(&audio_dir).unwrap();
+        std::fs::write(temp_dir.path().join("secret.txt"), "").unwrap();
+        std::os::unix::fs::symlink(temp_dir.path().join("secret.txt"), audio_dir.join("link.mp3")).unwrap();
+        let error = allowed(&audio_dir.join("link.mp3"), &[audio_dir]).unwrap_err();
+        assert!(matches!(error, RevealError::NotAllowed(_)), "{:?}", error);
+    }
+}
@@ -0,0 +1,347 @@
+//! Long documents generated as a file per section. The text is split at
+//! its `#` and `##` headings, or at lines holding nothing but a delimiter
+//! such as `***`, and a section too short to be worth a file of its own
+//! joins its neighbour. Each section is its own job, all sharing a parent
+//! id; the files are named by their place and heading, next to a
+//! `manifest.json` that lists them.
+
+use crate::jobs::{GenerationOptions, JobId, JobManager};
+use crate::markdown;
+use crate::tts::SpeechBackend;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Sections shorter than this, in characters, join a neighbour unless the
+/// request says otherwise
+pub const DEFAULT_MIN_CHARS: usize = 200;
+
+/// Most sections one document is split into
+pub const MAX_SECTIONS: usize = 200;
+
+pub const MANIFEST_NAME: &str = "manifest.json";
+
+/// Deepest heading that starts a section: `##`
+const MAX_LEVEL: u8 = 2;
+
+/// Characters of a heading kept in a file name, and of the first line of
+/// a section without one kept as its title
+const MAX_TITLE_CHARS: usize = 60;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "by", rename_all = "camelCase")]
+pub enum SplitOn {
+    /// `#` and `##` headings, the text being read as Markdown
+    Headings,
+    /// Lines holding nothing but `delimiter`, the text being read as it is
+    Delimiter { delimiter: String },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SectionRequest {
+    pub text: String,
+    pub split_on: SplitOn,
+    /// `DEFAULT_MIN_CHARS` when left out
+    #[serde(default)]
+    pub min_section_chars: Option<usize>,
+    /// The defaults when left out (see `resolve`)
+    #[serde(default)]
+    pub voice_id: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section {
+    pub title: String,
+    pub text: String,
+}
+
+impl Section {
+    fn untitled(text: &str) -> Self {
+        let first_line = text.lines().map(str::trim).find(|line| !line.is_empty()).unwrap_or_default();
+        let title = first_line.trim_start_matches('#').trim().chars().take(MAX_TITLE_CHARS).collect();
+        Self { title, text: text.trim().to_string() }
+    }
+
+    fn chars(&self) -> usize {
+        self.text.chars().count()
+    }
+
+    fn append(&mut self, other: Section) {
+        self.text.push_str("\n\n");
+        self.text.push_str(&other.text);
+    }
+}
+
+/// One section's entry in the manifest
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestEntry {
+    /// Place in the document, from 0
+    pub index: usize,
+    pub title: String,
+    pub characters: usize,
+    pub job_id: JobId,
+    /// The file's name in the destination, unless generating it failed
+    pub file: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+    /// The `parentId` of every section's job
+    pub parent_id: JobId,
+    pub voice_id: String,
+    pub model: String,
+    pub created_at: DateTime<Utc>,
+    pub sections: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SectionOutput {
+    /// The audio written, in reading order
+    pub files: Vec<PathBuf>,
+    pub manifest_path: PathBuf,
+    pub manifest: Manifest,
+}
+
+/// `document` split as `split_on` says, short sections joined to a
+/// neighbour (see `merge_short`)
+pub fn split(document: &str, split_on: &SplitOn, min_chars: usize) -> Result<Vec<Section>, String> {
+    let sections = match split_on {
+        SplitOn::Headings => {
+            let text = markdown::to_speech_text(document);
+            by_headings(&text.text, &text.outline)
+        }
+        SplitOn::Delimiter { delimiter } => {
+            if delimiter.trim().is_empty() {
+                return Err("The delimiter cannot be empty".to_string());
+            }
+            by_delimiter(document, delimiter.trim())
+        }
+    };
+    let sections = merge_short(sections, min_chars);
+    if sections.is_empty() {
+        return Err("Text cannot be empty".to_string());
+    }
+    if sections.len() > MAX_SECTIONS {
+        return Err(format!(
+            "The document has {} sections; at most {} can be generated at once",
+            sections.len(),
+            MAX_SECTIONS
+        ));
+    }
+    Ok(sections)
+}
+
+/// A section from each heading of level `MAX_LEVEL` or above in `outline`,
+/// and one for any text before the first
+fn by_headings(text: &str, outline: &[markdown::Heading]) -> Vec<Section> {
+    let byte_offset = |chars: usize| text.char_indices().nth(chars).map_or(text.len(), |(offset, _)| offset);
+    let headings: Vec<_> = outline.iter().filter(|heading| heading.level <= MAX_LEVEL).collect();
+
+    let mut sections = Vec::with_capacity(headings.len() + 1);
+    let first = headings.first().map_or(text.len(), |heading| byte_offset(heading.start));
+    if !text[..first].trim().is_empty() {
+        sections.push(Section::untitled(&text[..first]));
+    }
+    for (i, heading) in headings.iter().enumerate() {
+        let start = byte_offset(heading.start);
+        let end = headings.get(i + 1).map_or(text.len(), |next| byte_offset(next.start));
+        sections.push(Section { title: heading.title.clone(), text: text[start..end].trim().to_string() });
+    }
+    sections
+}
+
+fn by_delimiter(document: &str, delimiter: &str) -> Vec<Section> {
+    let mut sections = Vec::new();
+    let mut current = String::new();
+    for line in document.lines() {
+        if line.trim() == delimiter {
+            sections.push(Section::untitled(&current));
+            current.clear();
+        } else {
+            current.push_str(line);
+            current.push('\n');
+        }
+    }
+    sections.push(Section::untitled(&current));
+    sections.retain(|section| !section.text.is_empty());
+    sections
+}
+
+/// Join each section shorter than `min_chars` to the one before it, and a
+/// short first section to the one after, which keeps its title: a title
+/// page or a line of preamble isn't a chapter
+fn merge_short(sections: Vec<Section>, min_chars: usize) -> Vec<Section> {
+    let mut merged: Vec<Section> = Vec::with_capacity(sections.len());
+    for section in sections {
+        let short_first = merged.len() == 1 && merged[0].chars() < min_chars;
+        match merged.last_mut() {
+            Some(last) if section.chars() < min_chars => last.append(section),
+            Some(first) if short_first => {
+                first.title = section.title.clone();
+                first.append(section);
+            }
+            _ => merged.push(section),
+        }
+    }
+    merged
+}
+
+/// `heading` in lower case with runs of anything but letters and digits
+/// as a single `-`
+pub fn slug(heading: &str) -> String {
+    let mut slug = String::new();
+    for c in heading.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug: String = slug.chars().take(MAX_TITLE_CHARS).collect();
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        "section".to_string()
+    } else {
+        slug.to_string()
+    }
+}
+
+/// The file name of section `index` (from 0) of `total`, which sorts in
+/// reading order: "03-the-harbour.mp3"
+pub fn file_name(index: usize, total: usize, title: &str, format: &str) -> String {
+    let width = total.to_string().len().max(2);
+    format!("{:0width$}-{}.{}", index + 1, slug(title), format, width = width)
+}
+
+/// Generate each of `sections` as a job of its own from `options`, whose
+/// text is replaced, and write the audio and the manifest to the
+/// `destination` directory. A failed section is recorded in the manifest
+/// and the rest are still written.
+pub async fn generate<B: SpeechBackend + Send + Sync + 'static>(
+    jobs: &JobManager<B>,
+    sections: Vec<Section>,
+    options: GenerationOptions,
+    default_model: &str,
+    destination: &Path,
+    format: &str,
+) -> Result<SectionOutput, String> {
+    tokio::fs::create_dir_all(destination)
+        .await
+        .map_err(|e| format!("Failed to create {}: {}", destination.display(), e))?;
+    let parent_id = uuid::Uuid::new_v4().to_string();
+    let model = options.model.clone().unwrap_or_else(|| default_model.to_string());
+    let total = sections.len();
+
+    let mut entries = Vec::with_capacity(total);
+    for (index, section) in sections.into_iter().enumerate() {
+        let job_id = jobs.enqueue(
+            GenerationOptions {
+                text: section.text.clone(),
+                title: Some(section.title.clone()),
+                parent_id: Some(parent_id.clone()),
+                ..options.clone()
+            },
+            default_model,
+        )?;
+        entries.push(ManifestEntry {
+            index,
+            characters: section.chars(),
+            title: section.title,
+            job_id,
+            file: None,
+            error: None,
+        });
+    }
+
+    let results = futures::future::join_all(entries.iter().map(|entry| jobs.wait(&entry.job_id))).await;
+    let mut files = Vec::with_capacity(total);
+    for (entry, result) in entries.iter_mut().zip(results) {
+        let name = file_name(entry.index, total, &entry.title, format);
+        let path = destination.join(&name);
+        let written = match result {
+            Ok(audio) => {
+                tokio::fs::write(&path, audio).await.map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+            }
+            Err(e) => Err(e),
+        };
+        match written {
+            Ok(()) => {
+                entry.file = Some(name);
+                files.push(path);
+            }
+            Err(e) => {
+                tracing::warn!("Section {} of {} failed: {}", entry.index + 1, parent_id, e);
+                entry.error = Some(e);
+            }
+        }
+    }
+
+    let manifest = Manifest { parent_id, voice_id: options.voice_id, model, created_at: Utc::now(), sections: entries };
+    let manifest_path = destination.join(MANIFEST_NAME);
+    let json = serde_json::to_string_pretty(&manifest).map_err(|e| e.to_string())?;
+    tokio::fs::write(&manifest_path, json)
+        .await
+        .map_err(|e| format!("Failed to write {}: {}", manifest_path.display(), e))?;
+    Ok(SectionOutput { files, manifest_path, manifest })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HANDBOOK: &str = include_str!("../tests/fixtures/keepers-handbook.md");
+
+    fn titles(sections: &[Section]) -> Vec<&str> {
+        sections.iter().map(|section| section.title.as_str()).collect()
+    }
+
+    #[test]
+    fn test_splits_on_top_two_heading_levels() {
+        let sections = split(HANDBOOK, &SplitOn::Headings, 0).unwrap();
+        assert_eq!(titles(&sections), ["The Keeper's Handbook", "The Lamp", "Weather", "Storms", "Logbook"]);
+        // A `###` heading stays inside its section, and Markdown is dropped
+        assert!(sections[1].text.starts_with("The Lamp\n\nThe lamp is lit"));
+        assert!(sections[1].text.contains("Spare wicks"));
+        assert!(!sections.iter().any(|section| section.text.contains('#') || section.text.contains("**")));
+    }
+
+    #[test]
+    fn test_short_sections_join_a_neighbour() {
+        let sections = split(HANDBOOK, &SplitOn::Headings, DEFAULT_MIN_CHARS).unwrap();
+        // The title page joins the first chapter, "Storms" the one before it
+        assert_eq!(titles(&sections), ["The Lamp", "Weather", "Logbook"]);
+        assert!(sections[0].text.starts_with("The Keeper's Handbook"));
+        assert!(sections[1].text.contains("Storms\n\nWhen the glass falls"));
+
+        // Everything short leaves a single section
+        assert_eq!(split(HANDBOOK, &SplitOn::Headings, 100_000).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_splits_on_a_delimiter() {
+        let text = "Preface\nA short note.\n\n  ***  \nChapter one\nIt begins.\n***\n\n***\nChapter two\nIt ends.\n";
+        let delimiter = SplitOn::Delimiter { delimiter: "***".to_string() };
+        let sections = split(text, &delimiter, 0).unwrap();
+        assert_eq!(titles(&sections), ["Preface", "Chapter one", "Chapter two"]);
+        assert_eq!(sections[2].text, "Chapter two\nIt ends.");
+
+        assert!(split(text, &SplitOn::Delimiter { delimiter: " ".to_string() }, 0).is_err());
+        assert!(split("***\n***", &delimiter, 0).is_err());
+    }
+
+    #[test]
+    fn test_file_names() {
+        assert_eq!(slug("  Chapter 3: The Harbour (Part 1)!"), "chapter-3-the-harbour-part-1");
+        assert_eq!(slug("Über Élan"), "über-élan");
+        assert_eq!(slug("???"), "section");
+        assert_eq!(file_name(2, 5, "The Harbour", "mp3"), "03-the-harbour.mp3");
+        assert_eq!(file_name(9, 120, "", "opus"), "010-section.opus");
+    }
+}
@@ -0,0 +1,222 @@
+//! Finished audio sent to the window as events of base64 segments, for a
+//! webview where one large invoke() reply blocks the IPC thread or goes
+//! over a payload limit. Each segment carries about `SEGMENT_BYTES` of
+//! base64 tagged with its job id, index and the total; a last event gives
+//! the SHA-256 of the whole, which the window checks once it has put the
+//! segments back together (`receiveJobResult` in src/jobs.ts, with its
+//! tests in src/__tests__/jobs.test.ts). They go out no faster than the
+//! `segments_per_second` setting allows, and stop when the job is cancelled.
+
+use crate::integrity::AudioChecksum;
+use crate::jobs::JobId;
+use base64::{engine::general_purpose, Engine};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Base64 in a full segment; a multiple of 4, so each segment decodes on
+/// its own
+pub const SEGMENT_BYTES: usize = 1024 * 1024;
+
+/// Audio in a full segment
+const SEGMENT_AUDIO_BYTES: usize = SEGMENT_BYTES / 4 * 3;
+
+pub const DEFAULT_PER_SECOND: u32 = 8;
+pub const MAX_PER_SECOND: u32 = 100;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Segment {
+    pub job_id: JobId,
+    /// From 0
+    pub index: usize,
+    pub total: usize,
+    /// Base64 of this segment's part of the audio
+    pub data: String,
+}
+
+/// Sent after the last segment
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SegmentsDone {
+    pub job_id: JobId,
+    pub total: usize,
+    /// Of the audio decoded
+    pub size: u64,
+    /// Lowercase hex of the audio decoded, to check the reassembly by
+    pub sha256: String,
+    pub format: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum SegmentEvent {
+    Segment(Segment),
+    Done(SegmentsDone),
+    /// Cancelled part way; the segments sent so far are of no use
+    Aborted {
+        job_id: JobId,
+    },
+}
+
+/// How many segments `audio` goes out as; none when it is empty
+pub fn count(audio: &[u8]) -> usize {
+    audio.len().div_ceil(SEGMENT_AUDIO_BYTES)
+}
+
+/// `audio` as segments in order, each encoded as it's asked for
+pub fn split<'a>(job_id: &'a str, audio: &'a [u8]) -> impl Iterator<Item = Segment> + 'a {
+    let total = count(audio);
+    audio.chunks(SEGMENT_AUDIO_BYTES).enumerate().map(move |(index, part)| Segment {
+        job_id: job_id.to_string(),
+        index,
+        total,
+        data: general_purpose::STANDARD.encode(part),
+    })
+}
+
+/// The last event for `audio`, sent as `format`
+pub fn done(job_id: &str, audio: &[u8], format: &str) -> SegmentsDone {
+    let checksum = AudioChecksum::of(audio);
+    SegmentsDone {
+        job_id: job_id.to_string(),
+        total: count(audio),
+        size: checksum.size,
+        sha256: checksum.sha256,
+        format: format.to_string(),
+    }
+}
+
+/// Deliveries in progress, to cancel by job id
+#[derive(Default)]
+pub struct Deliveries {
+    cancelled: Mutex<HashMap<JobId, Arc<AtomicBool>>>,
+}
+
+impl Deliveries {
+    /// Send `audio`, job `job_id`'s, to `emit` as segments then the done
+    /// event, at most `per_second` segments a second. Fails if cancelled
+    /// part way, after sending `SegmentEvent::Aborted`.
+    pub async fn send(
+        &self,
+        job_id: &str,
+        audio: &[u8],
+        format: &str,
+        per_second: u32,
+        emit: impl Fn(&SegmentEvent),
+    ) -> Result<SegmentsDone, String> {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.cancelled.lock().unwrap().insert(job_id.to_string(), cancelled.clone());
+        let mut pace = tokio::time::interval(Duration::from_secs(1) / per_second.max(1));
+        pace.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        let mut result = Ok(());
+        for segment in split(job_id, audio) {
+            pace.tick().await;
+            if cancelled.load(Ordering::SeqCst) {
+                result = Err("Delivery was cancelled".to_string());
+                break;
+            }
+            emit(&SegmentEvent::Segment(segment));
+        }
+        self.cancelled.lock().unwrap().remove(job_id);
+        match result {
+            Ok(()) => {
+                let done = done(job_id, audio, format);
+                emit(&SegmentEvent::Done(done.clone()));
+                Ok(done)
+            }
+            Err(e) => {
+                tracing::info!("Stopped sending job {}'s audio: {}", job_id, e);
+                emit(&SegmentEvent::Aborted { job_id: job_id.to_string() });
+                Err(e)
+            }
+        }
+    }
+
+    /// Stop sending job `job_id`'s audio; false if it isn't being sent
+    pub fn cancel(&self, job_id: &str) -> bool {
+        match self.cancelled.lock().unwrap().get(job_id) {
+            Some(cancelled) => !cancelled.swap(true, Ordering::SeqCst),
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn audio(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_split_into_segments_that_decode_alone() {
+        let audio = audio(SEGMENT_AUDIO_BYTES * 2 + 10);
+        let segments: Vec<Segment> = split("job", &audio).collect();
+        assert_eq!(segments.len(), 3);
+        assert!(segments.iter().enumerate().all(|(i, segment)| segment.index == i && segment.total == 3));
+        assert_eq!((segments[0].data.len(), segments[1].data.len()), (SEGMENT_BYTES, SEGMENT_BYTES));
+        assert_eq!(general_purpose::STANDARD.decode(&segments[2].data).unwrap(), &audio[SEGMENT_AUDIO_BYTES * 2..]);
+
+        assert_eq!(split("job", &audio[..SEGMENT_AUDIO_BYTES]).count(), 1);
+        assert_eq!(split("job", &[]).count(), 0);
+        assert_eq!(done("job", &[], "mp3").total, 0);
+    }
+
+    #[tokio::test]
+    async fn test_sent_in_order_at_the_rate_then_done() {
+        let audio = audio(SEGMENT_AUDIO_BYTES * 2 + 1);
+        let events = Mutex::new(Vec::new());
+        let started = Instant::now();
+        let done = Deliveries::default()
+            .send("job", &audio, "opus", 20, |event| events.lock().unwrap().push(event.clone()))
+            .await
+            .unwrap();
+
+        // The first goes straight away, each after it 50 ms later
+        assert!(started.elapsed() >= Duration::from_millis(100));
+        let events = events.into_inner().unwrap();
+        let segments: Vec<Segment> = events
+            .iter()
+            .filter_map(|event| match event {
+                SegmentEvent::Segment(segment) => Some(segment.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(segments.iter().map(|segment| segment.index).collect::<Vec<_>>(), [0, 1, 2]);
+        assert!(matches!(events.last(), Some(SegmentEvent::Done(last)) if *last == done));
+        assert_eq!((done.size, done.format.as_str()), (audio.len() as u64, "opus"));
+        assert_eq!(done.sha256, AudioChecksum::of(&audio).sha256);
+        let decoded: Vec<u8> =
+            segments.iter().flat_map(|segment| general_purpose::STANDARD.decode(&segment.data).unwrap()).collect();
+        assert_eq!(decoded, audio);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_stops_sending() {
+        let audio = audio(SEGMENT_AUDIO_BYTES * 10);
+        let deliveries = Arc::new(Deliveries::default());
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sending = {
+            let (deliveries, events, audio) = (deliveries.clone(), events.clone(), audio.clone());
+            tokio::spawn(async move {
+                deliveries.send("job", &audio, "mp3", 50, |event| events.lock().unwrap().push(event.clone())).await
+            })
+        };
+        while events.lock().unwrap().len() < 2 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert!(deliveries.cancel("job"));
+        assert!(!deliveries.cancel("job"));
+
+        assert_eq!(sending.await.unwrap().unwrap_err(), "Delivery was cancelled");
+        let events = events.lock().unwrap();
+        assert!(events.len() < 10);
+        assert!(matches!(events.last(), Some(SegmentEvent::Aborted { job_id }) if job_id == "job"));
+        assert!(!deliveries.cancel("job"));
+    }
+}
@@ -0,0 +1,797 @@
+//! Preferences changed from the window, kept in the `settings` table as one
+//! JSON value per key. Keys that were never saved fall back to the merged
+//! config (see `config`), so a fresh install behaves as before.
+//!
+//! Settings can also be moved between machines as a versioned bundle
+//! (`SettingsBundle`). The API key lives in the keychain and usage in its
+//! own table, so neither is ever part of one.
+
+use crate::config::{self, Config, SUPPORTED_FORMATS};
+use crate::database::{Database, DEFAULT_PROFILE};
+use crate::extract;
+use crate::i18n;
+use crate::markdown;
+use crate::jobs::{RetryPolicy, DEFAULT_JOB_CONCURRENCY, MAX_JOB_CONCURRENCY, RETRY_BASE_DELAY};
+use crate::notifications;
+use crate::output_format;
+use crate::recent;
+use crate::recovery;
+use crate::segments;
+use crate::shortcut;
+use crate::silence::Gaps;
+use crate::typography;
+use crate::tts::{WithoutFfmpeg, CHUNK_SIZE, SINGLE_REQUEST_LIMIT, VALID_MODEL_IDS, VALID_VOICE_IDS};
+use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
+use serde_json::{Map, Value};
+use std::borrow::Cow;
+use std::path::Path;
+use std::sync::RwLock;
+use std::time::Duration;
+
+/// Smaller chunks mean more requests and audible seams for no benefit
+pub const MIN_CHUNK_SIZE: usize = 500;
+
+/// Layout of `SettingsBundle`; bump when a change would confuse older readers
+pub const BUNDLE_VERSION: u32 = 1;
+
+/// Settings that were once saved and exported but no longer exist; ignored
+/// wherever they turn up rather than rejected as unknown
+const RETIRED: &[&str] = &["audio_dir"];
+
+/// Longest profile name
+pub const MAX_PROFILE_NAME: usize = 40;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    pub voice: String,
+    pub model: String,
+    pub speed: f32,
+    pub response_format: String,
+    /// Characters per request when long text is split
+    pub chunk_size: usize,
+    /// What happens to long text when FFmpeg can't be run to join it
+    pub without_ffmpeg: WithoutFfmpeg,
+    /// Chapter markers in long text joined by FFmpeg, at its headings (see
+    /// `chapters`)
+    pub chapter_markers: bool,
+    /// Silence after each sentence and each paragraph of a generation,
+    /// 0 to `silence::MAX_PAUSE_MS`; a request's own values win
+    pub sentence_gap_ms: u64,
+    pub paragraph_gap_ms: u64,
+    pub preprocessing: Preprocessing,
+    /// Monthly spending limit in USD for the default profile; `None` for
+    /// no limit
+    pub monthly_budget: Option<f64>,
+    /// Generate with tts-1 instead of an HD model once a request would take
+    /// the month close to the budget (see `spend::check_budget`)
+    pub downgrade_near_budget: bool,
+    /// Let `fetch_article` reach localhost and private networks
+    pub allow_private_urls: bool,
+    /// Closing the window hides it; the app keeps running in the tray
+    pub close_to_tray: bool,
+    /// Global hotkey that speaks the clipboard, e.g. "CmdOrCtrl+Alt+Shift+S";
+    /// `None` to leave it unbound. Change it with `set_speak_shortcut`, which
+    /// also registers it.
+    pub speak_shortcut: Option<String>,
+    /// Desktop notifications when a job finishes (see `notifications`)
+    pub notify_on_completion: bool,
+    /// Jobs quicker than this only notify while the window is in the background
+    pub notify_after_secs: u64,
+    /// Speak text as it is copied (see `clipboard_watch`). Change it with
+    /// `set_clipboard_watch`, which also starts or stops the watcher.
+    pub clipboard_watch: bool,
+    /// Keep generations that fail for want of a connection and run them
+    /// once it is back (see `offline`); a job's own flag wins
+    pub queue_when_offline: bool,
+    /// Try generations that fail for a network error again in the
+    /// background (see `jobs::RetryPolicy`)
+    pub network_retry: NetworkRetry,
+    /// Jobs generating at once, 1 to `jobs::MAX_JOB_CONCURRENCY`. A change
+    /// applies to jobs that start after it; running ones finish.
+    pub max_concurrent_jobs: usize,
+    /// Days a job interrupted by quitting or a crash can be recovered,
+    /// 1 to `recovery::MAX_KEEP_DAYS`; older ones are deleted at startup
+    pub keep_interrupted_jobs_days: u32,
+    /// Finished generations kept to play again after the window reloads,
+    /// 0 to `recent::MAX_KEEP`
+    pub recent_generations: u32,
+    /// Most base64 segments a second when audio is sent as events, 1 to
+    /// `segments::MAX_PER_SECOND`
+    pub segments_per_second: u32,
+    /// Language of messages from the backend, one of `i18n::LOCALES`.
+    /// Change it with `set_locale`, which also applies it.
+    pub locale: String,
+    /// OpenAI organization and project requests are billed to, for a key
+    /// in several; `None` leaves it to the key's default
+    pub organization: Option<String>,
+    pub project: Option<String>,
+    /// Usage is recorded against this profile and counts towards its budget:
+    /// `database::DEFAULT_PROFILE` or the name of one of `profiles`
+    pub profile: String,
+    /// Profiles besides the default one, to keep usage apart (say, work and
+    /// personal)
+    pub profiles: Vec<Profile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub name: String,
+    /// Like `Settings::monthly_budget`, for this profile's usage only
+    #[serde(default)]
+    pub monthly_budget: Option<f64>,
+}
+
+/// Retrying jobs that fail for a network error, such as a Wi-Fi blip
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NetworkRetry {
+    pub enabled: bool,
+    /// Retries before giving up, at most `MAX_RETRY_ATTEMPTS`
+    pub max_attempts: u32,
+    /// No retry starts later than this after the job was queued
+    pub max_age_secs: u64,
+}
+
+impl Default for NetworkRetry {
+    fn default() -> Self {
+        Self { enabled: false, max_attempts: 5, max_age_secs: 30 * 60 }
+    }
+}
+
+/// Most retries `NetworkRetry::max_attempts` allows
+pub const MAX_RETRY_ATTEMPTS: u32 = 20;
+
+/// Longest `NetworkRetry::max_age_secs`, a day
+pub const MAX_RETRY_AGE_SECS: u64 = 24 * 60 * 60;
+
+/// Cleanup applied to text before it is sent
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Preprocessing {
+    /// Read Markdown without its formatting: no code blocks, link URLs or
+    /// markup (see `markdown::to_speech_text`)
+    pub strip_markdown: bool,
+    /// Runs of spaces and tabs as one space, and blank lines between
+    /// paragraphs as one blank line
+    pub collapse_whitespace: bool,
+    /// Curly quotes, dashes, unusual spaces and invisible characters to
+    /// plain ones (see `typography`)
+    pub normalize_typography: bool,
+    /// Join lines broken mid-paragraph, as in text copied from a PDF (see
+    /// `extract::reflow`). PDF extraction always does this.
+    pub reflow: bool,
+}
+
+impl Default for Preprocessing {
+    fn default() -> Self {
+        Self { strip_markdown: false, collapse_whitespace: false, normalize_typography: true, reflow: false }
+    }
+}
+
+impl Preprocessing {
+    /// `text` as it is counted, split into chunks and sent: without its
+    /// Markdown, reflowed, with whitespace collapsed, then with plain
+    /// typography, as far as each is on
+    pub fn apply<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        if self.strip_markdown {
+            text = Cow::Owned(markdown::to_speech_text(&text).text);
+        }
+        if self.reflow {
+            text = Cow::Owned(extract::reflow(&text));
+        }
+        if self.collapse_whitespace {
+            text = Cow::Owned(collapse_whitespace(&text));
+        }
+        match text {
+            Cow::Borrowed(text) => typography::prepare(text, self.normalize_typography),
+            Cow::Owned(text) => Cow::Owned(typography::prepare(&text, self.normalize_typography).into_owned()),
+        }
+    }
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines().map(|line| line.split_whitespace().collect::<Vec<_>>().join(" ")) {
+        if !line.is_empty() || lines.last().is_some_and(|last| !last.is_empty()) {
+            lines.push(line);
+        }
+    }
+    while lines.last().is_some_and(String::is_empty) {
+        lines.pop();
+    }
+    lines.join("\n")
+}
+
+impl Settings {
+    /// What the settings are before anything has been saved
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            voice: config.voice.clone(),
+            model: config.model.clone(),
+            speed: config.speed,
+            response_format: config.format.clone(),
+            chunk_size: CHUNK_SIZE,
+            without_ffmpeg: WithoutFfmpeg::default(),
+            chapter_markers: false,
+            sentence_gap_ms: 0,
+            paragraph_gap_ms: 0,
+            preprocessing: Preprocessing::default(),
+            monthly_budget: None,
+            downgrade_near_budget: false,
+            allow_private_urls: false,
+            close_to_tray: false,
+            speak_shortcut: Some(shortcut::DEFAULT_SHORTCUT.to_string()),
+            notify_on_completion: true,
+            notify_after_secs: notifications::DEFAULT_NOTIFY_AFTER_SECS,
+            clipboard_watch: false,
+            queue_when_offline: false,
+            network_retry: NetworkRetry::default(),
+            max_concurrent_jobs: DEFAULT_JOB_CONCURRENCY,
+            keep_interrupted_jobs_days: recovery::DEFAULT_KEEP_DAYS,
+            recent_generations: recent::DEFAULT_KEEP,
+            segments_per_second: segments::DEFAULT_PER_SECOND,
+            locale: i18n::DEFAULT_LOCALE.to_string(),
+            organization: config.organization.clone(),
+            project: config.project.clone(),
+            profile: DEFAULT_PROFILE.to_string(),
+            profiles: Vec::new(),
+        }
+    }
+
+    /// Monthly budget of the active profile
+    pub fn profile_budget(&self) -> Option<f64> {
+        match self.profiles.iter().find(|profile| profile.name == self.profile) {
+            Some(profile) => profile.monthly_budget,
+            None => self.monthly_budget,
+        }
+    }
+
+    /// Pauses for a request that doesn't choose its own
+    pub fn gaps(&self) -> Gaps {
+        Gaps { sentence_ms: self.sentence_gap_ms, paragraph_ms: self.paragraph_gap_ms }
+    }
+
+    /// How failed jobs are retried, if they are
+    pub fn retry_policy(&self) -> Option<RetryPolicy> {
+        self.network_retry.enabled.then(|| RetryPolicy {
+            max_attempts: self.network_retry.max_attempts,
+            max_age: Duration::from_secs(self.network_retry.max_age_secs),
+            base_delay: RETRY_BASE_DELAY,
+        })
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !VALID_VOICE_IDS.contains(&self.voice.as_str()) {
+            return Err(format!("Invalid voice ID: {}", self.voice));
+        }
+        if !VALID_MODEL_IDS.contains(&self.model.as_str()) {
+            return Err(format!("Invalid model: {}", self.model));
+        }
+        if !(0.25..=4.0).contains(&self.speed) {
+            return Err(format!("Speed must be between 0.25 and 4.0, got {}", self.speed));
+        }
+        if !SUPPORTED_FORMATS.contains(&self.response_format.as_str()) && self.response_format != output_format::AUTO {
+            return Err(format!("Unsupported format: {}", self.response_format));
+        }
+        if !(MIN_CHUNK_SIZE..=SINGLE_REQUEST_LIMIT).contains(&self.chunk_size) {
+            return Err(format!(
+                "Chunk size must be between {} and {}, got {}",
+                MIN_CHUNK_SIZE, SINGLE_REQUEST_LIMIT, self.chunk_size
+            ));
+        }
+        if let Some(shortcut) = &self.speak_shortcut {
+            shortcut::validate(shortcut)?;
+        }
+        self.gaps().validate()?;
+        if !(1..=MAX_RETRY_ATTEMPTS).contains(&self.network_retry.max_attempts) {
+            return Err(format!(
+                "Retry attempts must be between 1 and {}, got {}",
+                MAX_RETRY_ATTEMPTS, self.network_retry.max_attempts
+            ));
+        }
+        if !(1..=MAX_RETRY_AGE_SECS).contains(&self.network_retry.max_age_secs) {
+            return Err(format!(
+                "Retry age must be between 1 and {} seconds, got {}",
+                MAX_RETRY_AGE_SECS, self.network_retry.max_age_secs
+            ));
+        }
+        if !(1..=MAX_JOB_CONCURRENCY).contains(&self.max_concurrent_jobs) {
+            return Err(format!(
+                "Concurrent jobs must be between 1 and {}, got {}",
+                MAX_JOB_CONCURRENCY, self.max_concurrent_jobs
+            ));
+        }
+        if !(1..=recovery::MAX_KEEP_DAYS).contains(&self.keep_interrupted_jobs_days) {
+            return Err(format!(
+                "Interrupted jobs must be kept for 1 to {} days, got {}",
+                recovery::MAX_KEEP_DAYS, self.keep_interrupted_jobs_days
+            ));
+        }
+        if self.recent_generations > recent::MAX_KEEP {
+            return Err(format!(
+                "At most {} recent generations can be kept, got {}",
+                recent::MAX_KEEP, self.recent_generations
+            ));
+        }
+        if !(1..=segments::MAX_PER_SECOND).contains(&self.segments_per_second) {
+            return Err(format!(
+                "Audio segments must be sent 1 to {} a second, got {}",
+                segments::MAX_PER_SECOND, self.segments_per_second
+            ));
+        }
+        validate_budget(self.monthly_budget)?;
+        for (i, profile) in self.profiles.iter().enumerate() {
+            let name = &profile.name;
+            if name.trim().is_empty() || name.trim() != name || name.chars().count() > MAX_PROFILE_NAME {
+                return Err(format!(
+                    "Profile names must be 1 to {} characters without spaces at either end, got \"{}\"",
+                    MAX_PROFILE_NAME, name
+                ));
+            }
+            if name == DEFAULT_PROFILE || self.profiles[..i].iter().any(|other| other.name == *name) {
+                return Err(format!("There is already a profile named \"{}\"", name));
+            }
+            validate_budget(profile.monthly_budget)?;
+        }
+        if self.profile != DEFAULT_PROFILE && !self.profiles.iter().any(|profile| profile.name == self.profile) {
+            return Err(format!("Unknown profile: {}", self.profile));
+        }
+        if !i18n::LOCALES.contains(&self.locale.as_str()) {
+            return Err(format!("Unsupported locale: {}", self.locale));
+        }
+        for (name, id) in [("Organization", &self.organization), ("Project", &self.project)] {
+            if let Some(id) = id {
+                config::validate_account_id(name, id)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply a JSON merge patch: keys present replace the current value,
+    /// nested objects merge, `null` clears an optional value and unknown
+    /// keys are rejected.
+    pub fn patched(&self, patch: &Value) -> Result<Settings, String> {
+        if !patch.is_object() {
+            return Err("Settings patch must be an object".to_string());
+        }
+        let mut patch = patch.clone();
+        if let Value::Object(keys) = &mut patch {
+            keys.retain(|key, _| !RETIRED.contains(&key.as_str()));
+        }
+        let mut merged = serde_json::to_value(self).map_err(|e| e.to_string())?;
+        merge_patch(&mut merged, &patch);
+
+        let settings: Settings = serde_json::from_value(merged).map_err(|e| format!("Invalid settings: {}", e))?;
+        settings.validate()?;
+        Ok(settings)
+    }
+}
+
+fn validate_budget(budget: Option<f64>) -> Result<(), String> {
+    match budget {
+        Some(budget) if !budget.is_finite() || budget < 0.0 => Err(format!("Monthly budget must be zero or more, got {}", budget)),
+        _ => Ok(()),
+    }
+}
+
+fn merge_patch(target: &mut Value, patch: &Value) {
+    match (target, patch) {
+        (Value::Object(target), Value::Object(patch)) => {
+            for (key, value) in patch {
+                match target.get_mut(key) {
+                    Some(existing) if existing.is_object() && value.is_object() => merge_patch(existing, value),
+                    _ => {
+                        target.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        (target, patch) => *target = patch.clone(),
+    }
+}
+
+/// The current settings, shared between commands, backed by the database
+/// when there is one.
+pub struct SettingsStore {
+    database: Option<Database>,
+    current: RwLock<Settings>,
+    // Keeps concurrent patches from overwriting each other
+    update_lock: tokio::sync::Mutex<()>,
+}
+
+impl SettingsStore {
+    /// Saved values over `defaults`. A saved value that no longer validates
+    /// (say, a voice that was removed) is skipped rather than failing startup.
+    pub async fn load(database: Option<Database>, defaults: Settings) -> Self {
+        let mut settings = defaults;
+        if let Some(db) = &database {
+            match db.get_all_settings().await {
+                Ok(saved) => {
+                    for (key, value) in saved {
+                        let patch = Value::Object([(key.clone(), value)].into_iter().collect());
+                        match settings.patched(&patch) {
+                            Ok(patched) => settings = patched,
+                            Err(e) => tracing::warn!("Ignoring saved setting '{}': {}", key, e),
+                        }
+                    }
+                }
+                Err(e) => tracing::warn!("Failed to load settings: {}", e),
+            }
+        }
+
+        Self {
+            database,
+            current: RwLock::new(settings),
+            update_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    pub fn get(&self) -> Settings {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Validate and save `patch`, returning the settings now in effect.
+    /// Only the keys that changed are written.
+    pub async fn update(&self, patch: &Value) -> Result<Settings, String> {
+        let _guard = self.update_lock.lock().await;
+        self.write_patch(patch).await
+    }
+
+    /// The settings in effect, for `import` on another machine
+    pub fn export(&self) -> SettingsBundle {
+        let settings = match serde_json::to_value(self.get()) {
+            Ok(Value::Object(settings)) => settings,
+            _ => Map::new(),
+        };
+        SettingsBundle {
+            version: BUNDLE_VERSION,
+            app_version: env!("CARGO_PKG_VERSION").to_string(),
+            exported_at: Utc::now(),
+            settings,
+        }
+    }
+
+    /// Apply `bundle`, or with `dry_run` only report what it would change.
+    /// The whole bundle is validated either way, so a bad one changes nothing.
+    pub async fn import(
+        &self,
+        bundle: &SettingsBundle,
+        strategy: MergeStrategy,
+        dry_run: bool,
+    ) -> Result<ImportReport, String> {
+        if bundle.version > BUNDLE_VERSION {
+            return Err(format!(
+                "Settings were exported by a newer version ({}); update this app to import them",
+                bundle.app_version
+            ));
+        }
+        let _guard = self.update_lock.lock().await;
+        let current = self.get();
+        current.patched(&Value::Object(bundle.settings.clone()))?;
+
+        let saved: Vec<String> = match &self.database {
+            Some(db) => db
+                .get_all_settings()
+                .await
+                .map_err(|e| format!("Failed to load settings: {}", e))?
+                .into_iter()
+                .map(|(key, _)| key)
+                .collect(),
+            None => Vec::new(),
+        };
+        let current_values = serde_json::to_value(&current).map_err(|e| e.to_string())?;
+
+        let mut patch = Map::new();
+        let mut report = ImportReport { dry_run, changes: Vec::new(), kept: Vec::new(), settings: current.clone() };
+        for (key, value) in bundle.settings.iter().filter(|(key, _)| !RETIRED.contains(&key.as_str())) {
+            let existing = current_values.get(key).cloned().unwrap_or(Value::Null);
+            if existing == *value {
+                continue;
+            }
+            if strategy == MergeStrategy::KeepExisting && saved.contains(key) {
+                report.kept.push(key.clone());
+                continue;
+            }
+            report.changes.push(SettingChange { key: key.clone(), from: existing, to: value.clone() });
+            patch.insert(key.clone(), value.clone());
+        }
+
+        let patch = Value::Object(patch);
+        report.settings = if dry_run { current.patched(&patch)? } else { self.write_patch(&patch).await? };
+        Ok(report)
+    }
+
+    /// `update` without taking the lock; callers hold it
+    async fn write_patch(&self, patch: &Value) -> Result<Settings, String> {
+        let old = self.get();
+        let new = old.patched(patch)?;
+
+        if let Some(db) = &self.database {
+            let old_values = serde_json::to_value(&old).map_err(|e| e.to_string())?;
+            let new_values = serde_json::to_value(&new).map_err(|e| e.to_string())?;
+            if let (Value::Object(old_values), Value::Object(new_values)) = (old_values, new_values) {
+                for (key, value) in new_values {
+                    if old_values.get(&key) != Some(&value) {
+                        db.set_setting(&key, &value)
+                            .await
+                            .map_err(|e| format!("Failed to save settings: {}", e))?;
+                    }
+                }
+            }
+        }
+
+        *self.current.write().unwrap() = new.clone();
+        Ok(new)
+    }
+}
+
+/// Settings as written by `export_settings`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettingsBundle {
+    pub version: u32,
+    /// Version of the app that wrote the bundle, for error messages
+    pub app_version: String,
+    pub exported_at: DateTime<Utc>,
+    /// Same keys and values as `Settings`
+    pub settings: Map<String, Value>,
+}
+
+impl SettingsBundle {
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path).map_err(|e| format!("Failed to read settings file: {}", e))?;
+        serde_json::from_str(&content).map_err(|e| format!("Not a settings file: {}", e))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        let content = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        std::fs::write(path, content).map_err(|e| format!("Failed to write settings file: {}", e))
+    }
+}
+
+/// What to do with a setting that was already saved on this machine
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeStrategy {
+    /// The imported value wins
+    #[default]
+    Replace,
+    /// Only settings never saved here (still at their defaults) are imported
+    KeepExisting,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub changes: Vec<SettingChange>,
+    /// Keys that differ but were left alone by `MergeStrategy::KeepExisting`
+    pub kept: Vec<String>,
+    /// The settings after the import, or as they would be after a dry run
+    pub settings: Settings,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettingChange {
+    pub key: String,
+    pub from: Value,
+    pub to: Value,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    fn defaults() -> Settings {
+        Settings::from_config(&Config::default())
+    }
+
+    #[test]
+    fn test_validation() {
+        assert!(defaults().validate().is_ok());
+
+        let err = defaults().patched(&json!({ "voice": "rachel" })).unwrap_err();
+        assert!(err.contains("Invalid voice"));
+        assert!(defaults().patched(&json!({ "chunk_size": -100 })).is_err());
+        assert!(defaults().patched(&json!({ "chunk_size": 10 })).is_err());
+        assert!(defaults().patched(&json!({ "speed": 9.0 })).is_err());
+        assert!(defaults().patched(&json!({ "monthly_budget": -1.0 })).is_err());
+        assert!(defaults().patched(&json!({ "response_format": "ogg" })).is_err());
+        assert!(defaults().patched(&json!({ "response_format": "auto" })).is_ok());
+        assert!(defaults().patched(&json!({ "speak_shortcut": "CmdOrCtrl+V" })).is_err());
+        assert!(defaults().patched(&json!({ "speak_shortcut": null })).is_ok());
+        assert!(defaults().patched(&json!({ "organization": "org-abc", "project": "proj_abc" })).is_ok());
+        assert!(defaults().patched(&json!({ "organization": "" })).is_err());
+        assert!(defaults().patched(&json!({ "project": "my project" })).is_err());
+        assert!(defaults().patched(&json!({ "colour": "blue" })).is_err());
+        assert!(defaults().patched(&json!({ "network_retry": { "max_attempts": 0 } })).is_err());
+        assert!(defaults().patched(&json!({ "max_concurrent_jobs": 0 })).is_err());
+        assert!(defaults().patched(&json!({ "max_concurrent_jobs": MAX_JOB_CONCURRENCY + 1 })).is_err());
+        assert_eq!(defaults().patched(&json!({ "max_concurrent_jobs": 1 })).unwrap().max_concurrent_jobs, 1);
+        assert!(defaults().patched(&json!({ "keep_interrupted_jobs_days": 0 })).is_err());
+        assert!(defaults().patched(&json!({ "keep_interrupted_jobs_days": recovery::MAX_KEEP_DAYS + 1 })).is_err());
+        assert_eq!(defaults().patched(&json!({ "recent_generations": 0 })).unwrap().recent_generations, 0);
+        assert!(defaults().patched(&json!({ "recent_generations": recent::MAX_KEEP + 1 })).is_err());
+        assert!(defaults().patched(&json!({ "segments_per_second": 0 })).is_err());
+        assert!(defaults().patched(&json!({ "segments_per_second": segments::MAX_PER_SECOND + 1 })).is_err());
+        assert!(defaults().patched(&json!({ "paragraph_gap_ms": 1000, "sentence_gap_ms": 5000 })).is_ok());
+        assert!(defaults().patched(&json!({ "paragraph_gap_ms": 5001 })).is_err());
+        assert!(defaults().patched(&json!({ "sentence_gap_ms": -1 })).is_err());
+        assert!(defaults().patched(&json!({ "network_retry": { "max_age_secs": 1_000_000 } })).is_err());
+        assert!(defaults().patched(&json!({ "profile": "work" })).is_err());
+        for profiles in [
+            json!([{ "name": "" }]),
+            json!([{ "name": " work" }]),
+            json!([{ "name": "default" }]),
+            json!([{ "name": "work" }, { "name": "work" }]),
+            json!([{ "name": "work", "monthly_budget": -5.0 }]),
+        ] {
+            assert!(defaults().patched(&json!({ "profiles": profiles })).is_err(), "{}", profiles);
+        }
+        assert!(defaults().patched(&json!(["voice"])).is_err());
+    }
+
+    #[test]
+    fn test_partial_patch() {
+        let settings = defaults().patched(&json!({ "speed": 1.5, "monthly_budget": 20.0 })).unwrap();
+        assert_eq!(settings.speed, 1.5);
+        assert_eq!(settings.voice, defaults().voice);
+        assert_eq!(settings.monthly_budget, Some(20.0));
+
+        // Nested objects merge instead of being replaced
+        let settings = settings.patched(&json!({ "preprocessing": { "strip_markdown": true } })).unwrap();
+        let settings = settings.patched(&json!({ "preprocessing": { "collapse_whitespace": true } })).unwrap();
+        assert_eq!(
+            settings.preprocessing,
+            Preprocessing { strip_markdown: true, collapse_whitespace: true, normalize_typography: true, reflow: false }
+        );
+
+        // null clears optional values but isn't a valid voice
+        let settings = settings.patched(&json!({ "monthly_budget": null })).unwrap();
+        assert_eq!(settings.monthly_budget, None);
+        assert!(settings.patched(&json!({ "voice": null })).is_err());
+    }
+
+    #[test]
+    fn test_preprocessing_flags() {
+        let text = "# Notes\n\n\n\nSee   the [docs](https://example.com)\tfirst.\n\n";
+        let off = Preprocessing { normalize_typography: false, ..Preprocessing::default() };
+        assert!(matches!(off.apply(text), Cow::Borrowed(t) if t == text));
+
+        let stripped = Preprocessing { strip_markdown: true, ..off.clone() }.apply(text).into_owned();
+        assert!(!stripped.contains('#') && !stripped.contains("https://"), "{:?}", stripped);
+        assert!(stripped.contains("the docs"), "{:?}", stripped);
+
+        let collapsed = Preprocessing { collapse_whitespace: true, ..off.clone() }.apply(text);
+        assert_eq!(collapsed, "# Notes\n\nSee the [docs](https://example.com) first.");
+
+        let both = Preprocessing { strip_markdown: true, collapse_whitespace: true, ..off }.apply(text);
+        assert!(!both.contains("  ") && !both.contains("\n\n\n"), "{:?}", both);
+    }
+
+    #[test]
+    fn test_retry_policy_follows_the_settings() {
+        assert_eq!(defaults().retry_policy(), None);
+        let settings = defaults().patched(&json!({ "network_retry": { "enabled": true, "max_attempts": 3 } })).unwrap();
+        assert_eq!(
+            settings.retry_policy(),
+            Some(RetryPolicy { max_attempts: 3, max_age: Duration::from_secs(30 * 60), base_delay: RETRY_BASE_DELAY })
+        );
+    }
+
+    #[test]
+    fn test_budget_of_the_active_profile() {
+        let settings = defaults()
+            .patched(&json!({
+                "monthly_budget": 20.0,
+                "profiles": [{ "name": "work", "monthly_budget": 50.0 }, { "name": "personal" }],
+            }))
+            .unwrap();
+        assert_eq!(settings.profile_budget(), Some(20.0));
+        assert_eq!(settings.patched(&json!({ "profile": "work" })).unwrap().profile_budget(), Some(50.0));
+        assert_eq!(settings.patched(&json!({ "profile": "personal" })).unwrap().profile_budget(), None);
+    }
+
+    #[tokio::test]
+    async fn test_settings_persist_across_reopen() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("settings.db");
+
+        let store = SettingsStore::load(Some(Database::open(&path).await.unwrap()), defaults()).await;
+        store.update(&json!({ "voice": "onyx", "chunk_size": 2000 })).await.unwrap();
+        assert!(store.update(&json!({ "voice": "rachel" })).await.is_err());
+        assert_eq!(store.get().voice, "onyx");
+        drop(store);
+
+        let store = SettingsStore::load(Some(Database::open(&path).await.unwrap()), defaults()).await;
+        let settings = store.get();
+        assert_eq!(settings.voice, "onyx");
+        assert_eq!(settings.chunk_size, 2000);
+        assert_eq!(settings.model, defaults().model);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_saved_value_is_skipped() {
+        let dir = TempDir::new().unwrap();
+        let db = Database::open(&dir.path().join("settings.db")).await.unwrap();
+        db.set_setting("voice", &json!("rachel")).await.unwrap();
+        db.set_setting("speed", &json!(2.0)).await.unwrap();
+
+        db.set_setting("audio_dir", &json!(null)).await.unwrap();
+
+        let store = SettingsStore::load(Some(db), defaults()).await;
+        assert_eq!(store.get().voice, defaults().voice);
+        assert_eq!(store.get().speed, 2.0);
+    }
+
+    #[tokio::test]
+    async fn test_bundle_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let source = SettingsStore::load(Some(Database::open(&dir.path().join("a.db")).await.unwrap()), defaults()).await;
+        source
+            .update(&json!({
+                "voice": "onyx",
+                "speed": 1.25,
+                "monthly_budget": 15.0,
+                "preprocessing": { "strip_markdown": true },
+            }))
+            .await
+            .unwrap();
+        let path = dir.path().join("settings.json");
+        source.export().write(&path).unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert!(!written.contains("api_key") && !written.contains("sk-"));
+
+        let bundle = SettingsBundle::read(&path).unwrap();
+        assert_eq!(bundle.version, BUNDLE_VERSION);
+        let target = SettingsStore::load(Some(Database::open(&dir.path().join("b.db")).await.unwrap()), defaults()).await;
+
+        let preview = target.import(&bundle, MergeStrategy::Replace, true).await.unwrap();
+        let mut keys: Vec<_> = preview.changes.iter().map(|change| change.key.as_str()).collect();
+        keys.sort_unstable();
+        assert_eq!(keys, ["monthly_budget", "preprocessing", "speed", "voice"]);
+        assert_eq!(preview.settings, source.get());
+        assert_eq!(target.get(), defaults(), "a dry run changes nothing");
+
+        let report = target.import(&bundle, MergeStrategy::Replace, false).await.unwrap();
+        assert_eq!(report.changes.len(), 4);
+        assert_eq!(target.get(), source.get());
+        drop(target);
+        let reopened = SettingsStore::load(Some(Database::open(&dir.path().join("b.db")).await.unwrap()), defaults()).await;
+        assert_eq!(reopened.get(), source.get());
+    }
+
+    #[tokio::test]
+    async fn test_keep_existing_and_validation() {
+        let dir = TempDir::new().unwrap();
+        let store = SettingsStore::load(Some(Database::open(&dir.path().join("settings.db")).await.unwrap()), defaults()).await;
+        store.update(&json!({ "voice": "shimmer" })).await.unwrap();
+
+        let mut bundle = store.export();
+        bundle.settings.insert("voice".to_string(), json!("echo"));
+        bundle.settings.insert("speed".to_string(), json!(2.0));
+        // Written by a version that still had it
+        bundle.settings.insert("audio_dir".to_string(), json!("/somewhere/else"));
+        let report = store.import(&bundle, MergeStrategy::KeepExisting, false).await.unwrap();
+        assert_eq!(report.kept, ["voice"]);
+        assert_eq!(report.changes.len(), 1);
+        assert_eq!((store.get().voice.as_str(), store.get().speed), ("shimmer", 2.0));
+
+        // One bad value rejects the bundle, even where it would have been kept
+        bundle.settings.insert("voice".to_string(), json!("rachel"));
+        bundle.settings.insert("speed".to_string(), json!(1.0));
+        assert!(store.import(&bundle, MergeStrategy::KeepExisting, false).await.is_err());
+        assert_eq!(store.get().speed, 2.0);
+
+        let mut newer = store.export();
+        newer.version = BUNDLE_VERSION + 1;
+        assert!(store.import(&newer, MergeStrategy::Replace, true).await.unwrap_err().contains("newer version"));
+    }
+}
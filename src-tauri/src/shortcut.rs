@@ -0,0 +1,329 @@
+//! The global "speak clipboard" hotkey. Shortcut strings are checked and
+//! normalized here before the global-shortcut plugin sees them, and the
+//! press handler runs against `JobManager` and `ClipboardSource` so it can
+//! be tested without a keyboard hook.
+
+use crate::clipboard::{self, ClipboardSource};
+use crate::database::SOURCE_CLIPBOARD;
+use crate::jobs::{GenerationOptions, JobId, JobManager};
+use crate::tts::SpeechBackend;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Unlikely to be taken by the system or a common app
+pub const DEFAULT_SHORTCUT: &str = "CmdOrCtrl+Alt+Shift+S";
+
+/// Modifiers in the order a normalized shortcut lists them
+const MODIFIERS: &[(&str, &[&str])] = &[
+    ("CmdOrCtrl", &["cmdorctrl", "commandorcontrol", "cmdorcontrol", "commandorctrl"]),
+    ("Ctrl", &["ctrl", "control"]),
+    ("Super", &["super", "cmd", "command", "meta", "win"]),
+    ("Alt", &["alt", "option"]),
+    ("Shift", &["shift"]),
+];
+
+const NAMED_KEYS: &[&str] = &[
+    "Space", "Enter", "Tab", "Backspace", "Delete", "Insert", "Home", "End", "PageUp", "PageDown", "Up", "Down", "Left",
+    "Right",
+];
+
+/// Shortcuts the operating system or nearly every app already uses
+const RESERVED: &[(&str, &str)] = &[
+    ("CmdOrCtrl+C", "copy"),
+    ("CmdOrCtrl+V", "paste"),
+    ("CmdOrCtrl+X", "cut"),
+    ("CmdOrCtrl+Z", "undo"),
+    ("CmdOrCtrl+A", "select all"),
+    ("CmdOrCtrl+S", "save"),
+    ("CmdOrCtrl+Q", "quit"),
+    ("CmdOrCtrl+W", "close window"),
+    ("CmdOrCtrl+Tab", "switching tabs"),
+    ("CmdOrCtrl+Space", "input source or Spotlight"),
+    ("Alt+Tab", "switching windows"),
+    ("Alt+F4", "closing windows"),
+    ("Super+Tab", "switching apps"),
+    ("Super+Space", "Spotlight"),
+    ("Super+L", "locking the screen"),
+    ("Ctrl+Alt+Delete", "the security screen"),
+];
+
+/// `shortcut` in a canonical spelling, e.g. "ctrl + shift + p" is
+/// "Ctrl+Shift+P". Needs exactly one key; letters, digits and named keys
+/// need a modifier, F-keys don't.
+pub fn normalize(shortcut: &str) -> Result<String, String> {
+    let invalid = |reason: &str| format!("Invalid shortcut '{}': {}", shortcut.trim(), reason);
+    let mut modifiers = [false; MODIFIERS.len()];
+    let mut key: Option<String> = None;
+
+    for part in shortcut.split('+').map(str::trim) {
+        if part.is_empty() {
+            return Err(invalid("empty key name"));
+        }
+        let lower = part.to_ascii_lowercase();
+        if let Some(index) = MODIFIERS.iter().position(|(_, names)| names.contains(&lower.as_str())) {
+            if modifiers[index] {
+                return Err(invalid(&format!("{} appears twice", MODIFIERS[index].0)));
+            }
+            modifiers[index] = true;
+            continue;
+        }
+        if key.is_some() {
+            return Err(invalid("only one key besides modifiers is allowed"));
+        }
+        key = Some(normalize_key(part).ok_or_else(|| invalid(&format!("unknown key '{}'", part)))?);
+    }
+
+    let key = key.ok_or_else(|| invalid("no key besides modifiers"))?;
+    let is_function_key = key.len() > 1 && key.starts_with('F') && key[1..].chars().all(|c| c.is_ascii_digit());
+    if !is_function_key && !modifiers.contains(&true) {
+        return Err(invalid("needs at least one modifier"));
+    }
+    let mut parts: Vec<&str> = MODIFIERS
+        .iter()
+        .zip(modifiers)
+        .filter(|(_, held)| *held)
+        .map(|((name, _), _)| *name)
+        .collect();
+    parts.push(&key);
+    Ok(parts.join("+"))
+}
+
+fn normalize_key(key: &str) -> Option<String> {
+    let mut chars = key.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return c.is_ascii_alphanumeric().then(|| c.to_ascii_uppercase().to_string());
+    }
+    if let Some(number) = key.strip_prefix(['F', 'f']).and_then(|number| number.parse::<u8>().ok()) {
+        return (1..=24).contains(&number).then(|| format!("F{}", number));
+    }
+    NAMED_KEYS.iter().find(|name| name.eq_ignore_ascii_case(key)).map(|name| name.to_string())
+}
+
+/// Normalize `shortcut` and refuse ones the system or most apps rely on.
+/// Whether another app holds it is only known when registering.
+pub fn validate(shortcut: &str) -> Result<String, String> {
+    let normalized = normalize(shortcut)?;
+    if let Some((_, purpose)) = RESERVED.iter().find(|(reserved, _)| *reserved == normalized) {
+        return Err(format!("{} is already used for {}", normalized, purpose));
+    }
+    Ok(normalized)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PressOutcome {
+    /// A generation or playback was running and has been stopped
+    Stopped,
+    Enqueued(JobId),
+    NothingToSpeak,
+}
+
+#[derive(Debug, Default)]
+enum Speaking {
+    #[default]
+    Idle,
+    Generating(JobId),
+    Playing(Arc<AtomicBool>),
+}
+
+/// What the hotkey set going, so a second press can stop it
+#[derive(Debug, Default)]
+pub struct ClipboardSpeaker {
+    state: Mutex<Speaking>,
+}
+
+impl ClipboardSpeaker {
+    /// Stop what's speaking, or queue the clipboard text with `voice_id`
+    /// and `model`, tracked as a clipboard generation
+    pub fn on_press<B: SpeechBackend + Send + Sync + 'static>(
+        &self,
+        clipboard: &mut dyn ClipboardSource,
+        jobs: &JobManager<B>,
+        voice_id: &str,
+        model: &str,
+    ) -> Result<PressOutcome, String> {
+        let mut state = self.state.lock().unwrap();
+        match std::mem::take(&mut *state) {
+            Speaking::Generating(id) => {
+                jobs.cancel(&id);
+                return Ok(PressOutcome::Stopped);
+            }
+            Speaking::Playing(stop) => {
+                stop.store(true, Ordering::SeqCst);
+                return Ok(PressOutcome::Stopped);
+            }
+            Speaking::Idle => {}
+        }
+
+        let text = match clipboard::read(clipboard)?.map(|copied| copied.text) {
+            Some(text) if !text.trim().is_empty() => text.trim().to_string(),
+            _ => return Ok(PressOutcome::NothingToSpeak),
+        };
+        let id = enqueue(jobs, text, voice_id, model, SOURCE_CLIPBOARD)?;
+        *state = Speaking::Generating(id.clone());
+        Ok(PressOutcome::Enqueued(id))
+    }
+
+    /// Queue `text` in place of whatever is speaking, for clipboard watch
+    /// mode where a new copy supersedes the last
+    pub fn replace<B: SpeechBackend + Send + Sync + 'static>(
+        &self,
+        text: String,
+        jobs: &JobManager<B>,
+        voice_id: &str,
+        model: &str,
+        source: &'static str,
+    ) -> Result<JobId, String> {
+        let mut state = self.state.lock().unwrap();
+        match std::mem::take(&mut *state) {
+            Speaking::Generating(id) => {
+                jobs.cancel(&id);
+            }
+            Speaking::Playing(stop) => stop.store(true, Ordering::SeqCst),
+            Speaking::Idle => {}
+        }
+        let id = enqueue(jobs, text, voice_id, model, source)?;
+        *state = Speaking::Generating(id.clone());
+        Ok(id)
+    }
+
+    /// Job `id` has its audio; returns the flag that stops its playback, or
+    /// `None` if it was stopped in the meantime
+    pub fn start_playing(&self, id: &str) -> Option<Arc<AtomicBool>> {
+        let mut state = self.state.lock().unwrap();
+        if !matches!(&*state, Speaking::Generating(current) if current == id) {
+            return None;
+        }
+        let stop = Arc::new(AtomicBool::new(false));
+        *state = Speaking::Playing(stop.clone());
+        Some(stop)
+    }
+
+    /// Back to idle once the job failed or its playback ended, unless a
+    /// newer press has taken over
+    pub fn finish(&self, id: &str, stop: Option<&Arc<AtomicBool>>) {
+        let mut state = self.state.lock().unwrap();
+        let current = match (&*state, stop) {
+            (Speaking::Generating(current), _) => current == id,
+            (Speaking::Playing(current), Some(stop)) => Arc::ptr_eq(current, stop),
+            _ => false,
+        };
+        if current {
+            *state = Speaking::Idle;
+        }
+    }
+}
+
+fn enqueue<B: SpeechBackend + Send + Sync + 'static>(
+    jobs: &JobManager<B>,
+    text: String,
+    voice_id: &str,
+    model: &str,
+    source: &'static str,
+) -> Result<JobId, String> {
+    let options = GenerationOptions {
+        text,
+        voice_id: voice_id.to_string(),
+        model: Some(model.to_string()),
+        title: Some("Clipboard".to_string()),
+        source: Some(source),
+        // What was copied is read now or not at all
+        queue_if_offline: Some(false),
+        downgrade: None,
+        resolved: None,
+        sentence_gap_ms: None,
+        paragraph_gap_ms: None,
+        parent_id: None,
+        trim_silence: false,
+    };
+    jobs.enqueue(options, model)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::SOURCE_CLIPBOARD_WATCH;
+    use crate::jobs::JobState;
+    use crate::test_support::FakeBackend;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("ctrl + shift + p").unwrap(), "Ctrl+Shift+P");
+        assert_eq!(normalize("Shift+CommandOrControl+space").unwrap(), "CmdOrCtrl+Shift+Space");
+        assert_eq!(normalize("Option+Cmd+1").unwrap(), "Super+Alt+1");
+        assert_eq!(normalize("f13").unwrap(), "F13");
+        assert_eq!(normalize(DEFAULT_SHORTCUT).unwrap(), DEFAULT_SHORTCUT);
+
+        for bad in ["", "P", "Space", "Ctrl+Shift", "Ctrl++P", "Ctrl+Ctrl+P", "Ctrl+P+Q", "Ctrl+F25", "Ctrl+Hyper", "Ctrl+é"] {
+            assert!(normalize(bad).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn test_reserved_shortcuts_conflict() {
+        assert_eq!(validate("cmdorctrl+c").unwrap_err(), "CmdOrCtrl+C is already used for copy");
+        assert!(validate("Alt+F4").is_err());
+        assert!(validate("Ctrl+Alt+Delete").is_err());
+        assert_eq!(validate("CmdOrCtrl+Shift+C").unwrap(), "CmdOrCtrl+Shift+C");
+    }
+
+    struct FakeClipboard(Option<String>);
+
+    impl ClipboardSource for FakeClipboard {
+        fn read_text(&mut self) -> Result<Option<String>, String> {
+            Ok(self.0.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_press_queues_clipboard_and_second_press_stops() {
+        let backend = Arc::new(FakeBackend::default());
+        let jobs = JobManager::new(backend.clone(), 1);
+        let speaker = ClipboardSpeaker::default();
+
+        let outcome = speaker.on_press(&mut FakeClipboard(Some("  \n".to_string())), &jobs, "nova", "tts-1").unwrap();
+        assert_eq!(outcome, PressOutcome::NothingToSpeak);
+        assert!(jobs.list().is_empty());
+
+        let mut clipboard = FakeClipboard(Some(" Read this.\n".to_string()));
+        let PressOutcome::Enqueued(id) = speaker.on_press(&mut clipboard, &jobs, "nova", "tts-1").unwrap() else {
+            panic!("nothing was queued");
+        };
+        assert_eq!(jobs.wait(&id).await.unwrap(), b"Read this.");
+        assert_eq!(*backend.sources.lock().unwrap(), [Some("clipboard".to_string())]);
+
+        // Playing: the next press stops playback instead of queueing
+        let stop = speaker.start_playing(&id).unwrap();
+        assert_eq!(speaker.on_press(&mut clipboard, &jobs, "nova", "tts-1").unwrap(), PressOutcome::Stopped);
+        assert!(stop.load(Ordering::SeqCst));
+        speaker.finish(&id, Some(&stop));
+        assert_eq!(jobs.list().len(), 0);
+
+        // Generating: the next press cancels the job, and its audio is never played
+        let mut slow = FakeClipboard(Some("slow text".to_string()));
+        let PressOutcome::Enqueued(id) = speaker.on_press(&mut slow, &jobs, "nova", "tts-1").unwrap() else {
+            panic!("nothing was queued");
+        };
+        assert_eq!(speaker.on_press(&mut slow, &jobs, "nova", "tts-1").unwrap(), PressOutcome::Stopped);
+        assert_eq!(jobs.status(&id).unwrap().state, JobState::Cancelled);
+        assert!(speaker.start_playing(&id).is_none());
+        assert!(matches!(speaker.on_press(&mut clipboard, &jobs, "nova", "tts-1").unwrap(), PressOutcome::Enqueued(_)));
+    }
+
+    #[tokio::test]
+    async fn test_replace_supersedes_what_is_speaking() {
+        let backend = Arc::new(FakeBackend::default());
+        let jobs = JobManager::new(backend.clone(), 1);
+        let speaker = ClipboardSpeaker::default();
+
+        let slow = speaker.replace("slow text".to_string(), &jobs, "nova", "tts-1", SOURCE_CLIPBOARD_WATCH).unwrap();
+        let next = speaker.replace("Next copy".to_string(), &jobs, "nova", "tts-1", SOURCE_CLIPBOARD_WATCH).unwrap();
+        assert_eq!(jobs.status(&slow).unwrap().state, JobState::Cancelled);
+        assert_eq!(jobs.wait(&next).await.unwrap(), b"Next copy");
+
+        let stop = speaker.start_playing(&next).unwrap();
+        let last = speaker.replace("Last copy".to_string(), &jobs, "nova", "tts-1", SOURCE_CLIPBOARD_WATCH).unwrap();
+        assert!(stop.load(Ordering::SeqCst));
+        assert_eq!(jobs.wait(&last).await.unwrap(), b"Last copy");
+        assert!(backend.sources.lock().unwrap().iter().all(|source| source.as_deref() == Some(SOURCE_CLIPBOARD_WATCH)));
+    }
+}
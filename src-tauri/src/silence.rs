@@ -0,0 +1,284 @@
+//! Silence spliced between pieces of generated speech, e.g. to keep the
+//! gaps between subtitle cues. Gaps are plain WAV files cached by length;
+//! ffmpeg's concat filter joins them with the speech and re-encodes.
+
+use crate::i18n::{self, CommandError};
+use crate::settings::Settings;
+use crate::spend::{self, apply_budget, BudgetedModel};
+use crate::tts::TTSService;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// What the speech endpoint produces: 24 kHz mono
+pub const SAMPLE_RATE: u32 = 24_000;
+
+/// Longer gaps are shortened to this; nobody wants to wait through a minute
+/// of a film's action scene
+pub const MAX_PAUSE_MS: u64 = 5_000;
+
+/// A piece of text to generate on its own, and how long to stay silent after it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Segment {
+    pub text: String,
+    pub pause_after_ms: u64,
+}
+
+/// Silence between the sentences and between the paragraphs of text read
+/// in one go, for pacing: 0 leaves it to the voice
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Gaps {
+    pub sentence_ms: u64,
+    pub paragraph_ms: u64,
+}
+
+impl Gaps {
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, ms) in [("Sentence", self.sentence_ms), ("Paragraph", self.paragraph_ms)] {
+            if ms > MAX_PAUSE_MS {
+                return Err(format!("{} gap must be between 0 and {} ms, got {}", name, MAX_PAUSE_MS, ms));
+            }
+        }
+        Ok(())
+    }
+
+    /// Nothing to add, so the text is generated as usual
+    pub fn is_none(&self) -> bool {
+        self.sentence_ms == 0 && self.paragraph_ms == 0
+    }
+}
+
+/// `text` as segments with `gaps` after them: one per paragraph (blank
+/// lines between them), or per sentence when there's a sentence gap
+pub fn paced(text: &str, gaps: Gaps) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    for paragraph in paragraphs(text) {
+        if gaps.sentence_ms == 0 {
+            segments.push(Segment { text: paragraph, pause_after_ms: gaps.paragraph_ms });
+            continue;
+        }
+        let sentences: Vec<&str> = crate::tts::sentences(&paragraph).into_iter().map(str::trim).filter(|s| !s.is_empty()).collect();
+        let last = sentences.len().saturating_sub(1);
+        segments.extend(sentences.into_iter().enumerate().map(|(i, sentence)| Segment {
+            text: sentence.to_string(),
+            pause_after_ms: if i == last { gaps.paragraph_ms } else { gaps.sentence_ms },
+        }));
+    }
+    if let Some(last) = segments.last_mut() {
+        last.pause_after_ms = 0;
+    }
+    segments
+}
+
+fn paragraphs(text: &str) -> Vec<String> {
+    let mut paragraphs = Vec::new();
+    let mut current: Vec<&str> = Vec::new();
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() {
+            if !current.is_empty() {
+                paragraphs.push(current.join("\n"));
+                current.clear();
+            }
+        } else {
+            current.push(line);
+        }
+    }
+    if !current.is_empty() {
+        paragraphs.push(current.join("\n"));
+    }
+    paragraphs
+}
+
+/// Generate `segments` with the pause after each (see
+/// `TTSService::generate_speech_with_pauses`) within the budget, and record
+/// the usage under `source`. Hands back the model it was generated with.
+pub async fn generate_with_pauses(
+    tts_service: &TTSService,
+    settings: &Settings,
+    segments: &[Segment],
+    voice_id: &str,
+    model: &str,
+    source: &str,
+) -> Result<(Vec<u8>, BudgetedModel), CommandError> {
+    let text = segments.iter().map(|segment| segment.text.as_str()).collect::<Vec<_>>().join(" ");
+    tts_service.validate_text(&text).await?;
+    if !tts_service.is_valid_voice(voice_id) {
+        return Err(i18n::invalid_voice(voice_id).into());
+    }
+
+    let budgeted = apply_budget(tts_service, settings, model, &text).await?;
+    if let Some(downgrade) = &budgeted.downgrade {
+        crate::warnings::warn(crate::warnings::downgraded(downgrade));
+    }
+    let audio = tts_service.generate_speech_with_pauses(segments, voice_id, &budgeted.model).await?;
+    spend::track_budgeted(tts_service, &text, voice_id, &budgeted, source).await;
+    Ok((audio, budgeted))
+}
+
+/// A mono 16-bit PCM WAV of `duration_ms` of silence
+pub fn silent_wav(duration_ms: u64) -> Vec<u8> {
+    let samples = (SAMPLE_RATE as u64 * duration_ms / 1000) as u32;
+    let data_len = samples * 2;
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&1u16.to_le_bytes()); // mono
+    wav.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    wav.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    wav.resize(wav.len() + data_len as usize, 0);
+    wav
+}
+
+/// Silence files by length, written once and reused
+pub struct SilenceCache {
+    dir: PathBuf,
+}
+
+impl SilenceCache {
+    pub fn new() -> Self {
+        Self::with_dir(crate::paths::silence_dir())
+    }
+
+    pub fn with_dir(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn path_for(&self, duration_ms: u64) -> PathBuf {
+        self.dir.join(format!("silence-{}ms.wav", duration_ms))
+    }
+
+    /// The file for `duration_ms`, creating it if this is the first use
+    pub fn segment(&self, duration_ms: u64) -> std::io::Result<PathBuf> {
+        let path = self.path_for(duration_ms);
+        if !path.is_file() {
+            crate::permissions::create_private_dir(&self.dir)?;
+            // Write under another name first so a concurrent reader never sees half a file
+            let partial = self.dir.join(format!("silence-{}ms.{}.partial", duration_ms, uuid::Uuid::new_v4()));
+            std::fs::write(&partial, silent_wav(duration_ms))?;
+            std::fs::rename(&partial, &path)?;
+        }
+        Ok(path)
+    }
+}
+
+impl Default for SilenceCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// ffmpeg arguments joining `inputs` in order into `output`, whose extension
+/// picks the encoder. Inputs may differ in format; each is resampled to the
+/// speech format before the concat filter.
+pub fn concat_args(inputs: &[PathBuf], output: &Path) -> Vec<String> {
+    let mut args = vec!["-hide_banner".to_string(), "-loglevel".to_string(), "error".to_string()];
+    for input in inputs {
+        args.push("-i".to_string());
+        args.push(input.to_string_lossy().into_owned());
+    }
+    let mut filter = String::new();
+    for i in 0..inputs.len() {
+        filter.push_str(&format!(
+            "[{i}:a]aformat=sample_rates={}:channel_layouts=mono[a{i}];",
+            SAMPLE_RATE
+        ));
+    }
+    for i in 0..inputs.len() {
+        filter.push_str(&format!("[a{i}]"));
+    }
+    filter.push_str(&format!("concat=n={}:v=0:a=1[out]", inputs.len()));
+    args.extend([
+        "-filter_complex".to_string(),
+        filter,
+        "-map".to_string(),
+        "[out]".to_string(),
+        "-y".to_string(),
+        output.to_string_lossy().into_owned(),
+    ]);
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_silent_wav_length() {
+        let wav = silent_wav(500);
+        assert_eq!(wav.len(), 44 + 12_000 * 2);
+        assert!(wav[44..].iter().all(|&b| b == 0));
+
+        let decoded = crate::playback::decode(wav).unwrap();
+        use rodio::Source;
+        assert_eq!(decoded.total_duration(), Some(std::time::Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn test_cache_reuses_files_per_duration() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache = SilenceCache::with_dir(temp_dir.path());
+
+        let short = cache.segment(250).unwrap();
+        let again = cache.segment(250).unwrap();
+        let long = cache.segment(1000).unwrap();
+        assert_eq!(short, again);
+        assert_ne!(short, long);
+        assert_eq!(std::fs::read(&long).unwrap(), silent_wav(1000));
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_gaps_validation() {
+        assert!(Gaps::default().validate().is_ok());
+        assert!(Gaps::default().is_none());
+        assert!(Gaps { sentence_ms: 0, paragraph_ms: MAX_PAUSE_MS }.validate().is_ok());
+        let error = Gaps { sentence_ms: 5_001, paragraph_ms: 400 }.validate().unwrap_err();
+        assert_eq!(error, "Sentence gap must be between 0 and 5000 ms, got 5001");
+        assert!(Gaps { sentence_ms: 0, paragraph_ms: 60_000 }.validate().is_err());
+    }
+
+    #[test]
+    fn test_paced_segments() {
+        let text = "Markets rose. Bonds fell.\n\n  \nIn other news,\nit rained.";
+        let news = Gaps { sentence_ms: 0, paragraph_ms: 400 };
+        assert_eq!(
+            paced(text, news),
+            [
+                Segment { text: "Markets rose. Bonds fell.".to_string(), pause_after_ms: 400 },
+                Segment { text: "In other news,\nit rained.".to_string(), pause_after_ms: 0 },
+            ]
+        );
+
+        let poetry = Gaps { sentence_ms: 250, paragraph_ms: 1000 };
+        let pauses: Vec<(String, u64)> =
+            paced(text, poetry).into_iter().map(|segment| (segment.text, segment.pause_after_ms)).collect();
+        assert_eq!(
+            pauses,
+            [
+                ("Markets rose.".to_string(), 250),
+                ("Bonds fell.".to_string(), 1000),
+                ("In other news,\nit rained.".to_string(), 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_concat_args() {
+        let inputs = [PathBuf::from("a.mp3"), PathBuf::from("gap.wav"), PathBuf::from("b.mp3")];
+        let args = concat_args(&inputs, Path::new("out.mp3"));
+        assert_eq!(&args[3..9], ["-i", "a.mp3", "-i", "gap.wav", "-i", "b.mp3"]);
+        let filter = &args[args.iter().position(|a| a == "-filter_complex").unwrap() + 1];
+        assert!(filter.starts_with("[0:a]aformat=sample_rates=24000:channel_layouts=mono[a0];"));
+        assert!(filter.ends_with("[a0][a1][a2]concat=n=3:v=0:a=1[out]"));
+        assert_eq!(args.last().unwrap(), "out.mp3");
+    }
+}
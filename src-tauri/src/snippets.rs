@@ -0,0 +1,419 @@
+//! Several snippets generated in one request, such as selections the window
+//! queues together. Each becomes a job of its own (see `jobs`), so one that
+//! fails leaves the rest usable; the batch follows its jobs, saves each
+//! finished one for playing and reports every change to an item.
+
+use crate::file_manager::GeneratedAudio;
+use crate::jobs::{GenerationOptions, JobId, JobManager, JobState, RESULT_TTL};
+use crate::resolve;
+use crate::settings::Settings;
+use crate::tts::SpeechBackend;
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// More than this in one request is refused outright
+pub const MAX_SNIPPETS: usize = 100;
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+pub type BatchId = String;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Snippet {
+    pub text: String,
+    /// The default voice when left out (see `resolve`)
+    #[serde(default)]
+    pub voice: Option<String>,
+    #[serde(default)]
+    pub preset_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum ItemStatus {
+    /// Waiting for its turn, or parked before a retry
+    Queued,
+    Running { progress: f32 },
+    Done { audio: Box<GeneratedAudio> },
+    Failed { error: String },
+    Cancelled,
+}
+
+impl ItemStatus {
+    pub fn is_finished(&self) -> bool {
+        matches!(self, ItemStatus::Done { .. } | ItemStatus::Failed { .. } | ItemStatus::Cancelled)
+    }
+
+    /// Whether `self` and `other` would look the same to the window
+    fn same_as(&self, other: &ItemStatus) -> bool {
+        match (self, other) {
+            (ItemStatus::Running { progress: a }, ItemStatus::Running { progress: b }) => a == b,
+            (ItemStatus::Queued, ItemStatus::Queued) => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchItem {
+    /// Position in the request
+    pub index: usize,
+    /// `None` when the snippet was refused before it became a job
+    pub job_id: Option<JobId>,
+    #[serde(flatten)]
+    pub status: ItemStatus,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchResult {
+    pub id: BatchId,
+    pub items: Vec<BatchItem>,
+    pub succeeded: usize,
+    pub failed: usize,
+    /// Every item is done, failed or cancelled
+    pub finished: bool,
+}
+
+impl BatchResult {
+    fn update_counts(&mut self) {
+        self.succeeded = self.items.iter().filter(|item| matches!(item.status, ItemStatus::Done { .. })).count();
+        self.failed = self.items.iter().filter(|item| matches!(item.status, ItemStatus::Failed { .. })).count();
+        self.finished = self.items.iter().all(|item| item.status.is_finished());
+    }
+}
+
+/// What the listener hears about
+pub enum BatchEvent<'a> {
+    Item { batch_id: &'a str, item: &'a BatchItem },
+    Finished(&'a BatchResult),
+}
+
+/// Called after every change; the app forwards these as events
+pub type BatchListener = Arc<dyn Fn(&BatchEvent) + Send + Sync>;
+
+/// Saves a finished job's audio, given its id, audio and character count,
+/// for the window to play
+pub type Deliver = Arc<dyn Fn(JobId, Vec<u8>, usize) -> BoxFuture<'static, Result<GeneratedAudio, String>> + Send + Sync>;
+
+/// Job options for `snippet`, with the voice resolved against `settings` as
+/// a single generation's would be
+pub fn prepare(snippet: &Snippet, settings: &Settings) -> Result<GenerationOptions, String> {
+    if let Some(preset_id) = &snippet.preset_id {
+        // There are no presets yet (see `resolve`)
+        return Err(format!("Unknown preset: {}", preset_id));
+    }
+    let resolved = resolve::resolve(snippet.voice.as_deref(), None, settings).map_err(|message| message.text)?;
+    Ok(GenerationOptions {
+        text: snippet.text.clone(),
+        voice_id: resolved.voice_id.clone(),
+        model: Some(resolved.model.clone()),
+        title: None,
+        source: None,
+        queue_if_offline: None,
+        downgrade: None,
+        resolved: Some(resolved),
+        sentence_gap_ms: Some(settings.sentence_gap_ms),
+        paragraph_gap_ms: Some(settings.paragraph_gap_ms),
+        parent_id: None,
+        trim_silence: false,
+    })
+}
+
+struct Batch {
+    result: BatchResult,
+    finished_at: Option<Instant>,
+}
+
+struct Shared {
+    batches: Mutex<HashMap<BatchId, Batch>>,
+    listener: Option<BatchListener>,
+    deliver: Deliver,
+}
+
+impl Shared {
+    /// Set item `index` of batch `id` to `status` and tell the listener,
+    /// unless nothing changed
+    fn update(&self, id: &str, index: usize, status: ItemStatus) {
+        let (item, finished) = {
+            let mut batches = self.batches.lock().unwrap();
+            let Some(batch) = batches.get_mut(id) else {
+                return;
+            };
+            let item = &mut batch.result.items[index];
+            if item.status.same_as(&status) {
+                return;
+            }
+            item.status = status;
+            let item = item.clone();
+            batch.result.update_counts();
+            if batch.result.finished {
+                batch.finished_at = Some(Instant::now());
+            }
+            (item, batch.result.finished.then(|| batch.result.clone()))
+        };
+        if let Some(listener) = &self.listener {
+            listener(&BatchEvent::Item { batch_id: id, item: &item });
+            if let Some(result) = &finished {
+                listener(&BatchEvent::Finished(result));
+            }
+        }
+    }
+}
+
+pub struct Batches {
+    shared: Arc<Shared>,
+}
+
+impl Batches {
+    pub fn new(deliver: Deliver) -> Self {
+        Self { shared: Arc::new(Shared { batches: Mutex::new(HashMap::new()), listener: None, deliver }) }
+    }
+
+    /// Must be called before the first batch starts
+    pub fn with_listener(mut self, listener: BatchListener) -> Self {
+        Arc::get_mut(&mut self.shared).expect("listener set after batches started").listener = Some(listener);
+        self
+    }
+
+    /// Queue each prepared snippet as a job and follow them; a snippet
+    /// refused by `prepare` or the job manager fails on its own. Returns
+    /// straight away.
+    pub fn start<B: SpeechBackend + Send + Sync + 'static>(
+        &self,
+        jobs: &JobManager<B>,
+        prepared: Vec<Result<GenerationOptions, String>>,
+        default_model: &str,
+    ) -> Result<BatchId, String> {
+        if prepared.is_empty() {
+            return Err("There are no snippets to generate".to_string());
+        }
+        if prepared.len() > MAX_SNIPPETS {
+            return Err(format!("At most {} snippets can be generated at once", MAX_SNIPPETS));
+        }
+        self.prune(Instant::now());
+
+        let items: Vec<BatchItem> = prepared
+            .into_iter()
+            .enumerate()
+            .map(|(index, options)| match options.and_then(|options| jobs.enqueue(options, default_model)) {
+                Ok(job_id) => BatchItem { index, job_id: Some(job_id), status: ItemStatus::Queued },
+                Err(error) => BatchItem { index, job_id: None, status: ItemStatus::Failed { error } },
+            })
+            .collect();
+        let id = uuid::Uuid::new_v4().to_string();
+        let mut result = BatchResult { id: id.clone(), items, succeeded: 0, failed: 0, finished: false };
+        result.update_counts();
+        tracing::info!("Batch {}: {} snippet(s), {} refused", id, result.items.len(), result.failed);
+        let followed: Vec<(usize, JobId)> =
+            result.items.iter().filter_map(|item| Some((item.index, item.job_id.clone()?))).collect();
+        let finished = result.finished;
+        self.shared.batches.lock().unwrap().insert(
+            id.clone(),
+            Batch { result: result.clone(), finished_at: finished.then(Instant::now) },
+        );
+        if let Some(listener) = &self.shared.listener {
+            for item in &result.items {
+                listener(&BatchEvent::Item { batch_id: &id, item });
+            }
+            if finished {
+                listener(&BatchEvent::Finished(&result));
+            }
+        }
+
+        let shared = self.shared.clone();
+        let jobs = jobs.clone();
+        let batch_id = id.clone();
+        tokio::spawn(async move { follow(&shared, &jobs, &batch_id, followed).await });
+        Ok(id)
+    }
+
+    /// Every item's status so far, with the audio of those that are done
+    pub fn result(&self, id: &str) -> Option<BatchResult> {
+        self.prune(Instant::now());
+        self.shared.batches.lock().unwrap().get(id).map(|batch| batch.result.clone())
+    }
+
+    /// Forget batches that finished more than `RESULT_TTL` before `now`
+    fn prune(&self, now: Instant) {
+        self.shared.batches.lock().unwrap().retain(|_, batch| match batch.finished_at {
+            Some(finished_at) => now.duration_since(finished_at) < RESULT_TTL,
+            None => true,
+        });
+    }
+}
+
+/// Watch the batch's jobs until each has finished, taking and delivering
+/// the audio of every one that succeeded
+async fn follow<B: SpeechBackend + Send + Sync + 'static>(
+    shared: &Shared,
+    jobs: &JobManager<B>,
+    batch_id: &str,
+    mut followed: Vec<(usize, JobId)>,
+) {
+    while !followed.is_empty() {
+        let mut running = Vec::new();
+        for (index, job_id) in followed {
+            let status = match jobs.status(&job_id).map(|job| job.state) {
+                Some(JobState::Queued | JobState::Parked { .. }) => ItemStatus::Queued,
+                Some(JobState::Running { progress }) => ItemStatus::Running { progress },
+                Some(JobState::Cancelled) => ItemStatus::Cancelled,
+                Some(JobState::Done { .. }) => {
+                    let characters = jobs.status(&job_id).map_or(0, |job| job.characters);
+                    match jobs.take_result(&job_id) {
+                        Ok(audio) => match (shared.deliver)(job_id.clone(), audio, characters).await {
+                            Ok(audio) => ItemStatus::Done { audio: Box::new(audio) },
+                            Err(error) => ItemStatus::Failed { error },
+                        },
+                        Err(error) => ItemStatus::Failed { error },
+                    }
+                }
+                // Failed, deferred or forgotten; `take_result` says why
+                _ => ItemStatus::Failed {
+                    error: jobs.take_result(&job_id).err().unwrap_or_else(|| "Job has no audio".to_string()),
+                },
+            };
+            if !status.is_finished() {
+                running.push((index, job_id));
+            }
+            shared.update(batch_id, index, status);
+        }
+        followed = running;
+        if !followed.is_empty() {
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::file_manager::FileManager;
+    use crate::test_support::FakeBackend;
+    use tempfile::TempDir;
+
+    fn snippet(text: &str) -> Snippet {
+        Snippet { text: text.to_string(), voice: None, preset_id: None }
+    }
+
+    type Events = Arc<Mutex<Vec<String>>>;
+
+    /// Batches saving audio to `dir`, recording each event as
+    /// "<index>:<status>" or "finished"
+    fn batches(dir: &TempDir) -> (Batches, Events) {
+        let files = Arc::new(FileManager::with_dir(dir.path()));
+        let deliver: Deliver = Arc::new(move |id, audio, characters| {
+            let files = files.clone();
+            Box::pin(async move {
+                files.deliver(&id, audio, "mp3", false, characters as f64 / 15.0).await.map_err(|e| e.to_string())
+            })
+        });
+        let events: Events = Arc::default();
+        let recorded = events.clone();
+        let batches = Batches::new(deliver).with_listener(Arc::new(move |event: &BatchEvent| {
+            let event = match event {
+                BatchEvent::Item { item, .. } => {
+                    format!("{}:{}", item.index, serde_json::to_value(&item.status).unwrap()["status"].as_str().unwrap())
+                }
+                BatchEvent::Finished(_) => "finished".to_string(),
+            };
+            recorded.lock().unwrap().push(event);
+        }));
+        (batches, events)
+    }
+
+    /// What `generate_batch_command` does, short of the budget
+    fn generate(batches: &Batches, jobs: &JobManager<FakeBackend>, snippets: &[Snippet]) -> Result<BatchId, String> {
+        let settings = Settings::from_config(&Config::default());
+        let prepared = snippets.iter().map(|snippet| prepare(snippet, &settings)).collect();
+        batches.start(jobs, prepared, &settings.model)
+    }
+
+    async fn wait_until_finished(batches: &Batches, id: &str) -> BatchResult {
+        for _ in 0..200 {
+            let result = batches.result(id).unwrap();
+            if result.finished {
+                return result;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        panic!("batch did not finish");
+    }
+
+    #[tokio::test]
+    async fn test_partial_failure_keeps_the_rest() {
+        let dir = TempDir::new().unwrap();
+        let (batches, events) = batches(&dir);
+        let jobs = JobManager::new(Arc::new(FakeBackend::default()), 2);
+        let snippets = [
+            snippet("First selection"),
+            snippet("This one should FAIL"),
+            Snippet { voice: Some("robot".to_string()), ..snippet("Bad voice") },
+            snippet("Third selection"),
+        ];
+        let id = generate(&batches, &jobs, &snippets).unwrap();
+
+        let result = wait_until_finished(&batches, &id).await;
+        assert_eq!((result.succeeded, result.failed), (2, 2));
+        let ItemStatus::Done { audio } = &result.items[0].status else { panic!("{:?}", result.items[0]) };
+        let path = audio.path.as_ref().unwrap();
+        assert!(path.starts_with(dir.path()));
+        assert_eq!(std::fs::read(path).unwrap(), b"First selection");
+        assert!(matches!(&result.items[1].status, ItemStatus::Failed { error } if error.contains("HTTP 500")));
+        assert!(matches!(&result.items[2].status, ItemStatus::Failed { error } if error.contains("robot")));
+        assert_eq!(result.items[2].job_id, None);
+        assert!(matches!(result.items[3].status, ItemStatus::Done { .. }));
+
+        let events = events.lock().unwrap();
+        assert_eq!(events[..4], ["0:queued", "1:queued", "2:failed", "3:queued"]);
+        assert_eq!(events.last().unwrap(), "finished");
+        assert_eq!(events.iter().filter(|event| *event == "finished").count(), 1);
+        for item in ["0:done", "1:failed", "3:done"] {
+            assert!(events.iter().any(|event| event == item), "no {} in {:?}", item, events);
+        }
+
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["items"][0]["status"], "done");
+        assert!(json["items"][0]["audio"]["path"].is_string());
+        assert_eq!(json["items"][2]["jobId"], serde_json::Value::Null);
+    }
+
+    #[tokio::test]
+    async fn test_refused_snippets_and_requests() {
+        let dir = TempDir::new().unwrap();
+        let (batches, events) = batches(&dir);
+        let jobs = JobManager::new(Arc::new(FakeBackend::default()), 2);
+        assert!(generate(&batches, &jobs, &[]).is_err());
+        assert!(generate(&batches, &jobs, &vec![snippet("Hi"); MAX_SNIPPETS + 1]).unwrap_err().contains("100"));
+
+        let snippets = [snippet("   "), Snippet { preset_id: Some("narrator".to_string()), ..snippet("Hi") }];
+        let id = generate(&batches, &jobs, &snippets).unwrap();
+        let result = batches.result(&id).unwrap();
+        assert!(result.finished);
+        assert_eq!(result.failed, 2);
+        assert!(matches!(&result.items[1].status, ItemStatus::Failed { error } if error == "Unknown preset: narrator"));
+        assert_eq!(*events.lock().unwrap(), ["0:failed", "1:failed", "finished"]);
+        assert!(jobs.list().is_empty());
+        assert!(batches.result("nonexistent").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_job_cancels_its_item() {
+        let dir = TempDir::new().unwrap();
+        let (batches, _) = batches(&dir);
+        let jobs = JobManager::new(Arc::new(FakeBackend::default()), 1);
+        let id = generate(&batches, &jobs, &[snippet("One"), snippet("Two")]).unwrap();
+        let second = batches.result(&id).unwrap().items[1].job_id.clone().unwrap();
+        assert!(jobs.cancel(&second));
+
+        let result = wait_until_finished(&batches, &id).await;
+        assert!(matches!(result.items[0].status, ItemStatus::Done { .. }));
+        assert!(matches!(result.items[1].status, ItemStatus::Cancelled));
+        assert_eq!((result.succeeded, result.failed), (1, 0));
+    }
+}
@@ -0,0 +1,137 @@
+//! Listening faster or slower without generating again: ffmpeg stretches
+//! the audio already on disk, and the result is kept per source and factor
+//! so turning the knob back and forth doesn't redo the work.
+
+use std::path::Path;
+
+/// The same range the speech endpoint's `speed` accepts
+pub const MIN_FACTOR: f64 = 0.25;
+pub const MAX_FACTOR: f64 = 4.0;
+
+/// The most one `atempo` filter takes in either direction; older ffmpeg
+/// builds refuse anything outside 0.5–2.0
+const ATEMPO_MIN: f64 = 0.5;
+const ATEMPO_MAX: f64 = 2.0;
+
+pub fn validate_factor(factor: f64) -> Result<(), String> {
+    if !(MIN_FACTOR..=MAX_FACTOR).contains(&factor) {
+        return Err(format!("Speed must be between {} and {}, got {}", MIN_FACTOR, MAX_FACTOR, factor));
+    }
+    Ok(())
+}
+
+/// `factor` as a product of `atempo` stages each within 0.5–2.0: 3.0 is
+/// 2.0 × 1.5 and 0.4 is 0.5 × 0.8
+pub fn atempo_stages(factor: f64) -> Vec<f64> {
+    let mut stages = Vec::new();
+    let mut rest = factor;
+    while rest > ATEMPO_MAX {
+        stages.push(ATEMPO_MAX);
+        rest /= ATEMPO_MAX;
+    }
+    while rest < ATEMPO_MIN {
+        stages.push(ATEMPO_MIN);
+        rest /= ATEMPO_MIN;
+    }
+    stages.push(rest);
+    stages
+}
+
+/// The audio filter for `factor`. Keeping the pitch uses `atempo`;
+/// otherwise the audio is played back at a different sample rate, like a
+/// tape run fast, which sounds higher as well as quicker.
+pub fn speed_filter(factor: f64, preserve_pitch: bool, sample_rate: u32) -> Result<String, String> {
+    validate_factor(factor)?;
+    if preserve_pitch {
+        let stages: Vec<String> = atempo_stages(factor).iter().map(|stage| format!("atempo={}", stage)).collect();
+        Ok(stages.join(","))
+    } else {
+        let rate = (sample_rate as f64 * factor).round() as u32;
+        Ok(format!("asetrate={},aresample={}", rate, sample_rate))
+    }
+}
+
+/// ffmpeg arguments applying `filter` to `input`, written to `output`,
+/// whose extension picks the encoder
+pub fn ffmpeg_args(input: &Path, output: &Path, filter: &str) -> Vec<String> {
+    vec![
+        "-hide_banner".to_string(),
+        "-loglevel".to_string(),
+        "error".to_string(),
+        "-i".to_string(),
+        input.to_string_lossy().into_owned(),
+        "-filter:a".to_string(),
+        filter.to_string(),
+        "-y".to_string(),
+        output.to_string_lossy().into_owned(),
+    ]
+}
+
+/// The audio id the result for `source` at `factor` is saved under, so a
+/// second request finds it
+pub fn cached_id(source: &Path, factor: f64, preserve_pitch: bool) -> String {
+    let key = format!("{}|{}|{}", source.display(), factor, preserve_pitch);
+    format!("speed-{:016x}", crate::watch::hash(&key))
+}
+
+/// The sample rate from `path`'s headers
+pub fn sample_rate(path: &Path) -> Option<u32> {
+    use rodio::Source;
+    let file = std::fs::File::open(path).ok()?;
+    Some(rodio::Decoder::new(std::io::BufReader::new(file)).ok()?.sample_rate())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_atempo_chain() {
+        assert_eq!(speed_filter(3.0, true, 24_000).unwrap(), "atempo=2,atempo=1.5");
+        assert_eq!(speed_filter(0.4, true, 24_000).unwrap(), "atempo=0.5,atempo=0.8");
+        assert_eq!(speed_filter(1.5, true, 24_000).unwrap(), "atempo=1.5");
+        assert_eq!(speed_filter(4.0, true, 24_000).unwrap(), "atempo=2,atempo=2");
+        assert_eq!(speed_filter(0.25, true, 24_000).unwrap(), "atempo=0.5,atempo=0.5");
+    }
+
+    #[test]
+    fn test_stages_multiply_to_the_factor() {
+        for factor in [0.25, 0.3, 0.4, 0.5, 0.75, 1.0, 1.25, 2.0, 2.5, 3.0, 3.7, 4.0] {
+            let stages = atempo_stages(factor);
+            assert!(stages.iter().all(|stage| (ATEMPO_MIN..=ATEMPO_MAX).contains(stage)), "{}: {:?}", factor, stages);
+            let product: f64 = stages.iter().product();
+            assert!((product - factor).abs() < 1e-9, "{}: {:?}", factor, stages);
+        }
+    }
+
+    #[test]
+    fn test_pitch_shifting_filter() {
+        assert_eq!(speed_filter(1.5, false, 24_000).unwrap(), "asetrate=36000,aresample=24000");
+        assert_eq!(speed_filter(0.5, false, 44_100).unwrap(), "asetrate=22050,aresample=44100");
+    }
+
+    #[test]
+    fn test_factor_bounds() {
+        assert!(speed_filter(4.5, true, 24_000).is_err());
+        assert!(speed_filter(0.1, false, 24_000).is_err());
+        assert!(speed_filter(f64::NAN, true, 24_000).is_err());
+    }
+
+    #[test]
+    fn test_cache_ids() {
+        let source = Path::new("/tmp/tts-player/abc.mp3");
+        let id = cached_id(source, 1.5, true);
+        assert_eq!(id, cached_id(source, 1.5, true));
+        assert_ne!(id, cached_id(source, 1.25, true));
+        assert_ne!(id, cached_id(source, 1.5, false));
+        assert_ne!(id, cached_id(Path::new("/tmp/tts-player/def.mp3"), 1.5, true));
+        // Usable as an audio id
+        assert!(id.len() <= 64 && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'));
+    }
+
+    #[test]
+    fn test_ffmpeg_args() {
+        let args = ffmpeg_args(Path::new("in.mp3"), Path::new("out.mp3"), "atempo=1.5");
+        assert_eq!(args[3..], ["-i", "in.mp3", "-filter:a", "atempo=1.5", "-y", "out.mp3"]);
+    }
+}
@@ -0,0 +1,629 @@
+//! What generation has cost over a period, priced with the same table as
+//! the estimates so the two never disagree. Periods follow the local
+//! calendar: "this month" starts at local midnight on the 1st, and an hour
+//! of `hourly_usage` at the top of a local hour.
+
+use crate::database::{Database, ModelUsage};
+use crate::i18n::{self, CommandError};
+use crate::settings::Settings;
+use crate::tts::{estimate_cost, SpeechBackend, TTSError, TTSService};
+use anyhow::Result;
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, TimeZone, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+
+/// `"today"`, `"week"` (from Monday), `"month"`, `"year"`, `"all"`, or
+/// `{"range": {"start": "2026-01-01", "end": "2026-01-31"}}` with both
+/// dates included
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpendPeriod {
+    Today,
+    Week,
+    Month,
+    Year,
+    All,
+    Range { start: NaiveDate, end: NaiveDate },
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelSpend {
+    pub model_id: String,
+    pub cost: f64,
+    pub character_count: i64,
+    pub request_count: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpendSummary {
+    /// `None` for the open side of "all"
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+    /// Nominal cost in USD at list prices
+    pub total_cost: f64,
+    pub character_count: i64,
+    pub request_count: i64,
+    pub by_model: Vec<ModelSpend>,
+}
+
+impl SpendPeriod {
+    /// `[start, end)` of the period containing `now`, at midnights in `now`'s time zone
+    pub fn bounds<Tz: TimeZone>(&self, now: &DateTime<Tz>) -> (Option<DateTime<Utc>>, Option<DateTime<Utc>>) {
+        let today = now.date_naive();
+        let (first, last) = match self {
+            SpendPeriod::All => return (None, None),
+            SpendPeriod::Today => (today, today),
+            SpendPeriod::Week => {
+                let monday = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+                (monday, monday + Duration::days(6))
+            }
+            SpendPeriod::Month => {
+                let first = today.with_day(1).unwrap_or(today);
+                let next = first.checked_add_months(chrono::Months::new(1)).unwrap_or(first);
+                (first, next.pred_opt().unwrap_or(first))
+            }
+            SpendPeriod::Year => {
+                let first = NaiveDate::from_ymd_opt(today.year(), 1, 1).unwrap_or(today);
+                (first, NaiveDate::from_ymd_opt(today.year(), 12, 31).unwrap_or(today))
+            }
+            SpendPeriod::Range { start, end } => (*start.min(end), *start.max(end)),
+        };
+        let midnight = |date: NaiveDate| {
+            let naive = date.and_hms_opt(0, 0, 0).unwrap_or_default();
+            // A midnight skipped by a DST change falls back to the earliest valid time
+            now.timezone()
+                .from_local_datetime(&naive)
+                .earliest()
+                .map(|time| time.with_timezone(&Utc))
+                .unwrap_or_else(|| naive.and_utc())
+        };
+        (Some(midnight(first)), last.succ_opt().map(midnight))
+    }
+}
+
+/// Spend in `period`, with "now" being the local time of this machine, in
+/// `profile` or all of them
+pub async fn total_spend(database: &Database, period: &SpendPeriod, profile: Option<&str>) -> Result<SpendSummary> {
+    total_spend_at(database, period, profile, &Local::now()).await
+}
+
+pub async fn total_spend_at<Tz: TimeZone>(
+    database: &Database,
+    period: &SpendPeriod,
+    profile: Option<&str>,
+    now: &DateTime<Tz>,
+) -> Result<SpendSummary> {
+    let (start, end) = period.bounds(now);
+    let usage = database.get_model_usage_between(start, end, profile).await?;
+    Ok(summarize(start, end, &usage))
+}
+
+/// Most hours `hourly_usage` covers: a week
+pub const MAX_HOURS: i32 = 24 * 7;
+
+/// One local hour of `hourly_usage`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HourlyUsage {
+    pub start: DateTime<Utc>,
+    /// Successful requests only, as failed ones aren't billed
+    pub character_count: i64,
+    pub cost: f64,
+    /// Every request, failed ones too
+    pub request_count: i64,
+}
+
+/// Usage in each of the last `hours` local hours (at most `MAX_HOURS`),
+/// this one included, oldest first; hours without any are there too. Only
+/// `profile`'s when there is one.
+pub async fn hourly_usage(database: &Database, hours: i32, profile: Option<&str>) -> Result<Vec<HourlyUsage>> {
+    hourly_usage_at(database, hours, profile, &Local::now()).await
+}
+
+pub async fn hourly_usage_at<Tz: TimeZone>(
+    database: &Database,
+    hours: i32,
+    profile: Option<&str>,
+    now: &DateTime<Tz>,
+) -> Result<Vec<HourlyUsage>> {
+    let hours = hours.clamp(1, MAX_HOURS);
+    // Back from the top of this hour, as local time has it
+    let this_hour = now.with_minute(0).and_then(|now| now.with_second(0)).and_then(|now| now.with_nanosecond(0));
+    let this_hour = this_hour.map_or_else(|| now.with_timezone(&Utc), |hour| hour.with_timezone(&Utc));
+    let start = this_hour - Duration::hours(hours as i64 - 1);
+    let mut usage: Vec<HourlyUsage> = (0..hours)
+        .map(|hour| HourlyUsage { start: start + Duration::hours(hour as i64), character_count: 0, cost: 0.0, request_count: 0 })
+        .collect();
+
+    for record in database.get_usage_records_between(start, this_hour + Duration::hours(1), profile).await? {
+        let Some(hour) = usage.get_mut((record.timestamp - start).num_hours() as usize) else {
+            continue;
+        };
+        hour.request_count += 1;
+        if record.success {
+            hour.character_count += record.character_count as i64;
+            hour.cost += estimate_cost(record.character_count as i64, &record.model_id);
+        }
+    }
+    Ok(usage)
+}
+
+/// Whether `profile`'s spend this month has reached its `budget`
+/// (`Settings::profile_budget`)
+pub async fn budget_reached(database: &Database, budget: Option<f64>, profile: &str) -> Result<bool> {
+    let Some(budget) = budget else {
+        return Ok(false);
+    };
+    Ok(total_spend(database, &SpendPeriod::Month, Some(profile)).await?.total_cost >= budget)
+}
+
+/// Share of the monthly budget past which `Settings::downgrade_near_budget`
+/// moves requests to the standard model
+pub const SOFT_BUDGET_SHARE: f64 = 0.9;
+/// What requests are moved to near the budget
+pub const DOWNGRADE_MODEL: &str = "tts-1";
+
+/// A request moved to a cheaper model, and why, for the window to show
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Downgrade {
+    pub from: String,
+    pub to: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BudgetCheck {
+    Allowed,
+    /// Generate with `to` instead
+    Downgraded(Downgrade),
+    /// The monthly `budget` is used up
+    Blocked { budget: f64 },
+}
+
+/// What the monthly `budget` says about generating `characters` characters
+/// with `model` after `spent` this month. Once the budget is reached nothing
+/// more is generated. With `downgrade`, a request that would take the month
+/// past `SOFT_BUDGET_SHARE` of it uses `DOWNGRADE_MODEL` if that is cheaper.
+pub fn check_budget(spent: f64, budget: Option<f64>, downgrade: bool, model: &str, characters: usize) -> BudgetCheck {
+    let Some(budget) = budget else {
+        return BudgetCheck::Allowed;
+    };
+    if spent >= budget {
+        return BudgetCheck::Blocked { budget };
+    }
+    let cost = estimate_cost(characters as i64, model);
+    let cheaper = estimate_cost(characters as i64, DOWNGRADE_MODEL) < cost;
+    if downgrade && cheaper && spent + cost >= budget * SOFT_BUDGET_SHARE {
+        return BudgetCheck::Downgraded(Downgrade {
+            from: model.to_string(),
+            to: DOWNGRADE_MODEL.to_string(),
+            reason: format!(
+                "This would take the month past {:.0}% of the ${:.2} budget, so {} was used instead of {}",
+                SOFT_BUDGET_SHARE * 100.0,
+                budget,
+                DOWNGRADE_MODEL,
+                model
+            ),
+        });
+    }
+    BudgetCheck::Allowed
+}
+
+/// `check_budget` against `profile`'s spend so far this month
+pub async fn budget_check(
+    database: &Database,
+    budget: Option<f64>,
+    downgrade: bool,
+    model: &str,
+    characters: usize,
+    profile: &str,
+) -> Result<BudgetCheck> {
+    budget_check_at(database, budget, downgrade, model, characters, profile, &Local::now()).await
+}
+
+pub async fn budget_check_at<Tz: TimeZone>(
+    database: &Database,
+    budget: Option<f64>,
+    downgrade: bool,
+    model: &str,
+    characters: usize,
+    profile: &str,
+    now: &DateTime<Tz>,
+) -> Result<BudgetCheck> {
+    if budget.is_none() {
+        return Ok(BudgetCheck::Allowed);
+    }
+    let spent = total_spend_at(database, &SpendPeriod::Month, Some(profile), now).await?.total_cost;
+    Ok(check_budget(spent, budget, downgrade, model, characters))
+}
+
+/// The model to generate with once the budget has had its say
+pub struct BudgetedModel {
+    pub model: String,
+    /// Why it isn't the one asked for
+    pub downgrade: Option<Downgrade>,
+}
+
+/// Refuse a generation once the active profile's budget for this month is
+/// reached, and move it to tts-1 near the budget if the settings ask for
+/// that (see `check_budget`). Without usage tracking nothing is checked.
+pub async fn apply_budget(
+    tts_service: &TTSService,
+    settings: &Settings,
+    model: &str,
+    text: &str,
+) -> std::result::Result<BudgetedModel, CommandError> {
+    let requested = BudgetedModel { model: model.to_string(), downgrade: None };
+    let Some(database) = tts_service.database() else {
+        return Ok(requested);
+    };
+    let characters = tts_service.prepare(text).chars().count();
+    let check = budget_check(
+        database,
+        settings.profile_budget(),
+        settings.downgrade_near_budget,
+        model,
+        characters,
+        &settings.profile,
+    );
+    match check.await {
+        Ok(BudgetCheck::Allowed) => Ok(requested),
+        Ok(BudgetCheck::Downgraded(downgrade)) => {
+            tracing::info!("Near the monthly budget; generating with {} instead of {}", downgrade.to, downgrade.from);
+            Ok(BudgetedModel { model: downgrade.to.clone(), downgrade: Some(downgrade) })
+        }
+        Ok(BudgetCheck::Blocked { budget }) => Err(i18n::budget_reached(budget).into()),
+        Err(e) => {
+            tracing::warn!("Failed to check the budget: {}", e);
+            Ok(requested)
+        }
+    }
+}
+
+/// Record a successful generation of `text` with `budgeted` under `source`,
+/// with the model it was moved from if it was
+pub async fn track_budgeted(tts_service: &TTSService, text: &str, voice_id: &str, budgeted: &BudgetedModel, source: &str) {
+    let model = budgeted.model.as_str();
+    let _ = match &budgeted.downgrade {
+        Some(downgrade) => tts_service.track_downgraded(source, text, voice_id, model, &downgrade.from).await,
+        None => tts_service.track_usage_as(source, text, voice_id, model, true, None).await,
+    };
+}
+
+/// `TTSService` with each request held to the budget by `apply_budget`,
+/// for generations that go through `SpeechBackend` one request at a time,
+/// such as a project's items. A downgraded request is still kept as the
+/// model it asked for, so it is found again when the project is exported.
+pub struct WithinBudget {
+    service: TTSService,
+    settings: Settings,
+}
+
+impl WithinBudget {
+    pub fn new(service: TTSService, settings: Settings) -> Self {
+        Self { service, settings }
+    }
+}
+
+impl SpeechBackend for WithinBudget {
+    async fn synthesize(&self, text: &str, voice_id: &str, model: &str) -> std::result::Result<Vec<u8>, TTSError> {
+        let budgeted = apply_budget(&self.service, &self.settings, model, text)
+            .await
+            .map_err(|e| TTSError::ValidationError(e.message))?;
+        match &budgeted.downgrade {
+            Some(downgrade) => {
+                crate::warnings::warn(crate::warnings::downgraded(downgrade));
+                self.service.synthesize_downgraded(None, text, voice_id, &budgeted.model, &downgrade.from).await
+            }
+            None => self.service.synthesize(text, voice_id, model).await,
+        }
+    }
+}
+
+fn summarize(start: Option<DateTime<Utc>>, end: Option<DateTime<Utc>>, usage: &[ModelUsage]) -> SpendSummary {
+    let by_model: Vec<ModelSpend> = usage
+        .iter()
+        .map(|usage| ModelSpend {
+            model_id: usage.model_id.clone(),
+            cost: estimate_cost(usage.character_count, &usage.model_id),
+            character_count: usage.character_count,
+            request_count: usage.request_count,
+        })
+        .collect();
+    SpendSummary {
+        start,
+        end,
+        total_cost: by_model.iter().map(|model| model.cost).sum(),
+        character_count: by_model.iter().map(|model| model.character_count).sum(),
+        request_count: by_model.iter().map(|model| model.request_count).sum(),
+        by_model,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{UsageRecord, DEFAULT_PROFILE, SOURCE_APP};
+    use chrono::FixedOffset;
+
+    /// 10:00 on 1 March in a UTC+10 zone, which is still February in UTC
+    fn now() -> DateTime<FixedOffset> {
+        FixedOffset::east_opt(10 * 3600).unwrap().with_ymd_and_hms(2026, 3, 1, 10, 0, 0).unwrap()
+    }
+
+    fn utc(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_local_bounds() {
+        let (start, end) = SpendPeriod::Month.bounds(&now());
+        assert_eq!(start, Some(utc("2026-02-28T14:00:00Z")));
+        assert_eq!(end, Some(utc("2026-03-31T14:00:00Z")));
+
+        let (start, end) = SpendPeriod::Today.bounds(&now());
+        assert_eq!((start, end), (Some(utc("2026-02-28T14:00:00Z")), Some(utc("2026-03-01T14:00:00Z"))));
+
+        // 1 March 2026 is a Sunday, so the week began on 23 February
+        let (start, _) = SpendPeriod::Week.bounds(&now());
+        assert_eq!(start, Some(utc("2026-02-22T14:00:00Z")));
+
+        let range = SpendPeriod::Range {
+            start: NaiveDate::from_ymd_opt(2026, 1, 1).unwrap(),
+            end: NaiveDate::from_ymd_opt(2026, 1, 31).unwrap(),
+        };
+        let (start, end) = range.bounds(&Utc::now());
+        assert_eq!((start, end), (Some(utc("2026-01-01T00:00:00Z")), Some(utc("2026-02-01T00:00:00Z"))));
+        assert_eq!(SpendPeriod::All.bounds(&now()), (None, None));
+    }
+
+    #[test]
+    fn test_period_serialization() {
+        assert_eq!(serde_json::from_str::<SpendPeriod>(r#""month""#).unwrap(), SpendPeriod::Month);
+        let range: SpendPeriod = serde_json::from_str(r#"{"range": {"start": "2026-01-01", "end": "2026-01-31"}}"#).unwrap();
+        assert!(matches!(range, SpendPeriod::Range { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_spend_across_month_boundary() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = Database::open(&dir.path().join("usage.db")).await.unwrap();
+        let record = |timestamp: &str, characters: i32, model: &str, success: bool| UsageRecord {
+            id: None,
+            timestamp: utc(timestamp),
+            text: "x".repeat(characters as usize),
+            character_count: characters,
+            voice_id: "nova".to_string(),
+            model_id: model.to_string(),
+            success,
+            error_message: None,
+            source: SOURCE_APP.to_string(),
+            regenerated_from: None,
+            downgraded_from: None,
+            key_fingerprint: None,
+            app_version: None,
+            profile: DEFAULT_PROFILE.to_string(),
+            latency_ms: None,
+            chunk_count: None,
+        };
+        for usage in [
+            // Local 28 February 23:30: last month
+            record("2026-02-28T13:30:00Z", 1_000_000, "tts-1", true),
+            // Local 1 March 00:30 and 09:00, though still February in UTC
+            record("2026-02-28T14:30:00Z", 100_000, "tts-1", true),
+            record("2026-02-28T23:00:00Z", 100_000, "tts-1-hd", true),
+            record("2026-02-28T23:30:00Z", 500_000, "tts-1-hd", false),
+        ] {
+            db.record_usage(&usage).await.unwrap();
+        }
+
+        let month = total_spend_at(&db, &SpendPeriod::Month, None, &now()).await.unwrap();
+        assert_eq!(month.request_count, 2);
+        assert_eq!(month.character_count, 200_000);
+        assert!((month.total_cost - (1.5 + 3.0)).abs() < 1e-9, "{}", month.total_cost);
+        let models: Vec<_> = month.by_model.iter().map(|model| model.model_id.as_str()).collect();
+        assert_eq!(models.len(), 2);
+        assert!(models.contains(&"tts-1") && models.contains(&"tts-1-hd"));
+
+        let all = total_spend_at(&db, &SpendPeriod::All, None, &now()).await.unwrap();
+        assert_eq!(all.request_count, 3);
+        assert!((all.total_cost - (15.0 + 1.5 + 3.0)).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_hourly_usage_across_an_hour_boundary() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = Database::open(&dir.path().join("usage.db")).await.unwrap();
+        let record = |timestamp: &str, characters: i32, model: &str, success: bool| UsageRecord {
+            id: None,
+            timestamp: utc(timestamp),
+            text: "x".to_string(),
+            character_count: characters,
+            voice_id: "nova".to_string(),
+            model_id: model.to_string(),
+            success,
+            error_message: None,
+            source: SOURCE_APP.to_string(),
+            regenerated_from: None,
+            downgraded_from: None,
+            key_fingerprint: None,
+            app_version: None,
+            profile: DEFAULT_PROFILE.to_string(),
+            latency_ms: None,
+            chunk_count: None,
+        };
+        for usage in [
+            // Local 05:59, before the window
+            record("2026-02-28T19:59:00Z", 9_000, "tts-1", true),
+            // Local 07:59
+            record("2026-02-28T21:59:00Z", 3_000, "tts-1", true),
+            // Local 08:59:59 and 09:00:00, either side of the hour
+            record("2026-02-28T22:59:59Z", 1_000, "tts-1", true),
+            record("2026-02-28T23:00:00Z", 2_000, "tts-1-hd", true),
+            record("2026-02-28T23:10:00Z", 500, "tts-1-hd", false),
+            // Local 10:05, this hour
+            record("2026-03-01T00:05:00Z", 1_000, "tts-1", true),
+        ] {
+            db.record_usage(&usage).await.unwrap();
+        }
+
+        // Five hours to local 10:20 on 1 March: 06:00 to 10:59
+        let now = FixedOffset::east_opt(10 * 3600).unwrap().with_ymd_and_hms(2026, 3, 1, 10, 20, 0).unwrap();
+        let hours = hourly_usage_at(&db, 5, None, &now).await.unwrap();
+        let starts: Vec<_> = hours.iter().map(|hour| hour.start).collect();
+        assert_eq!(starts[0], utc("2026-02-28T20:00:00Z"));
+        assert_eq!(starts[4], utc("2026-03-01T00:00:00Z"));
+        let counts: Vec<_> = hours.iter().map(|hour| (hour.character_count, hour.request_count)).collect();
+        assert_eq!(counts, [(0, 0), (3_000, 1), (1_000, 1), (2_000, 2), (1_000, 1)]);
+        // $15 and $30 per million characters
+        assert!((hours[3].cost - 0.06).abs() < 1e-9, "{}", hours[3].cost);
+        assert!((hours[2].cost - 0.015).abs() < 1e-9, "{}", hours[2].cost);
+
+        // Half-hour zones start their hours half an hour off UTC's
+        let now = FixedOffset::east_opt(5 * 3600 + 1800).unwrap().with_ymd_and_hms(2026, 3, 1, 4, 40, 0).unwrap();
+        let hours = hourly_usage_at(&db, 2, None, &now).await.unwrap();
+        assert_eq!(hours[0].start, utc("2026-02-28T21:30:00Z"));
+        assert_eq!(hours.iter().map(|hour| hour.request_count).collect::<Vec<_>>(), [1, 3]);
+    }
+
+    #[test]
+    fn test_downgrade_threshold() {
+        // 10,000 characters: $0.30 with HD, $0.15 standard
+        let check = |spent, downgrade, model| check_budget(spent, Some(10.0), downgrade, model, 10_000);
+        assert_eq!(check(8.69, true, "tts-1-hd"), BudgetCheck::Allowed);
+        // 8.70 + 0.30 lands exactly on 90%
+        let BudgetCheck::Downgraded(downgrade) = check(8.70, true, "tts-1-hd") else {
+            panic!("expected a downgrade");
+        };
+        assert_eq!((downgrade.from.as_str(), downgrade.to.as_str()), ("tts-1-hd", "tts-1"));
+        assert!(downgrade.reason.contains("90% of the $10.00 budget"), "{}", downgrade.reason);
+
+        // Only with the setting, and only when there's something cheaper
+        assert_eq!(check(8.70, false, "tts-1-hd"), BudgetCheck::Allowed);
+        assert_eq!(check(9.50, true, "tts-1"), BudgetCheck::Allowed);
+
+        // The budget itself still stops everything
+        assert!(matches!(check(10.0, true, "tts-1-hd"), BudgetCheck::Blocked { .. }));
+        assert!(matches!(check(10.0, false, "tts-1"), BudgetCheck::Blocked { .. }));
+        assert_eq!(check_budget(1000.0, None, true, "tts-1-hd", 10_000), BudgetCheck::Allowed);
+    }
+
+    #[tokio::test]
+    async fn test_budget_check_uses_this_months_spend() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = Database::open(&dir.path().join("usage.db")).await.unwrap();
+        let record = |timestamp: &str, characters: i32| UsageRecord {
+            id: None,
+            timestamp: utc(timestamp),
+            text: "x".repeat(characters as usize),
+            character_count: characters,
+            voice_id: "nova".to_string(),
+            model_id: "tts-1-hd".to_string(),
+            success: true,
+            error_message: None,
+            source: SOURCE_APP.to_string(),
+            regenerated_from: None,
+            downgraded_from: None,
+            key_fingerprint: None,
+            app_version: None,
+            profile: DEFAULT_PROFILE.to_string(),
+            latency_ms: None,
+            chunk_count: None,
+        };
+        // Last month's $6 doesn't count; this month's $4.20 does
+        db.record_usage(&record("2026-02-27T12:00:00Z", 200_000)).await.unwrap();
+        db.record_usage(&record("2026-02-28T15:00:00Z", 140_000)).await.unwrap();
+
+        let budget = Some(5.0);
+        let small = budget_check_at(&db, budget, true, "tts-1-hd", 1_000, DEFAULT_PROFILE, &now()).await.unwrap();
+        assert_eq!(small, BudgetCheck::Allowed);
+        // $4.20 + $0.30 reaches $4.50, 90% of the budget
+        let large = budget_check_at(&db, budget, true, "tts-1-hd", 10_000, DEFAULT_PROFILE, &now()).await.unwrap();
+        assert!(matches!(large, BudgetCheck::Downgraded(_)), "{:?}", large);
+
+        db.record_usage(&record("2026-02-28T16:00:00Z", 30_000)).await.unwrap();
+        let spent = budget_check_at(&db, budget, true, "tts-1-hd", 1_000, DEFAULT_PROFILE, &now()).await.unwrap();
+        assert!(matches!(spent, BudgetCheck::Blocked { .. }), "{:?}", spent);
+    }
+
+    #[tokio::test]
+    async fn test_budgets_are_per_profile() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = Database::open(&dir.path().join("usage.db")).await.unwrap();
+        // $4.50 on the work profile this month, $0.30 on the default one
+        for (profile, characters) in [("work", 150_000), (DEFAULT_PROFILE, 10_000)] {
+            db.record_usage(&UsageRecord {
+                id: None,
+                timestamp: utc("2026-02-28T16:00:00Z"),
+                text: "Seeded".to_string(),
+                character_count: characters,
+                voice_id: "nova".to_string(),
+                model_id: "tts-1-hd".to_string(),
+                success: true,
+                error_message: None,
+                source: SOURCE_APP.to_string(),
+                regenerated_from: None,
+                downgraded_from: None,
+                key_fingerprint: None,
+                app_version: None,
+                profile: profile.to_string(),
+                latency_ms: None,
+                chunk_count: None,
+            })
+            .await
+            .unwrap();
+        }
+
+        let budget = Some(4.0);
+        let work = budget_check_at(&db, budget, false, "tts-1-hd", 100, "work", &now()).await.unwrap();
+        assert_eq!(work, BudgetCheck::Blocked { budget: 4.0 });
+        let default = budget_check_at(&db, budget, false, "tts-1-hd", 100, DEFAULT_PROFILE, &now()).await.unwrap();
+        assert_eq!(default, BudgetCheck::Allowed);
+        let unused = budget_check_at(&db, Some(0.1), false, "tts-1-hd", 100, "personal", &now()).await.unwrap();
+        assert_eq!(unused, BudgetCheck::Allowed);
+
+        let now = now();
+        let work = total_spend_at(&db, &SpendPeriod::Month, Some("work"), &now).await.unwrap();
+        assert!((work.total_cost - 4.5).abs() < 1e-9);
+        let all = total_spend_at(&db, &SpendPeriod::Month, None, &now).await.unwrap();
+        assert!((all.total_cost - 4.8).abs() < 1e-9);
+        let hours = hourly_usage_at(&db, 24, Some(DEFAULT_PROFILE), &now).await.unwrap();
+        assert_eq!(hours.iter().map(|hour| hour.character_count).sum::<i64>(), 10_000);
+    }
+
+    #[tokio::test]
+    async fn test_blocked_profile_generates_nothing() {
+        let mut server = mockito::Server::new_async().await;
+        let speech = server.mock("POST", mockito::Matcher::Any).expect(0).create_async().await;
+        let dir = tempfile::TempDir::new().unwrap();
+        let config = crate::config::Config {
+            data_dir: dir.path().to_path_buf(),
+            api_base_url: server.url(),
+            ..crate::config::Config::default()
+        };
+        let service = TTSService::from_config(Some("sk-test"), &config).await.unwrap();
+        let settings = Settings { monthly_budget: Some(1.0), ..Settings::from_config(&config) };
+        service.apply_settings(&settings);
+        // $1.50 this month, past the $1 budget, then something to regenerate
+        service.track_usage(&"x".repeat(50_000), "nova", "tts-1-hd", true, None).await.unwrap();
+        service.track_usage("Read this again", "nova", "tts-1", true, None).await.unwrap();
+        let database = service.database().unwrap();
+        let original = database.get_usage_records(1, None).await.unwrap()[0].id.unwrap();
+
+        let regenerated = crate::regenerate::run(&service, &settings, original, Default::default()).await;
+        assert_eq!(regenerated.err().map(|e| e.key), Some("error.budget_reached"));
+        let segments = [
+            crate::silence::Segment { text: "One".to_string(), pause_after_ms: 500 },
+            crate::silence::Segment { text: "Two".to_string(), pause_after_ms: 0 },
+        ];
+        let paused = crate::silence::generate_with_pauses(&service, &settings, &segments, "nova", "tts-1", SOURCE_APP);
+        assert_eq!(paused.await.err().map(|e| e.key), Some("error.budget_reached"));
+        let item = WithinBudget::new(service.clone(), settings).synthesize("An item", "nova", "tts-1").await;
+        assert!(item.unwrap_err().to_string().contains("budget"));
+
+        speech.assert_async().await;
+        assert_eq!(database.get_usage_records(10, None).await.unwrap().len(), 2);
+    }
+}
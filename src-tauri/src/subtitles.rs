@@ -0,0 +1,244 @@
+//! Subtitle files (SRT and WebVTT) read as narration: cue numbers, timings
+//! and styling dropped, and cues that split a sentence merged back into it.
+//! The gaps between cues are kept as pauses for `generate_speech_with_pauses`.
+
+use crate::extract::{self, ExtractError};
+use crate::silence::{Segment, MAX_PAUSE_MS};
+use crate::tts::{GenerationEstimate, TTSService};
+use serde::Serialize;
+use std::path::Path;
+
+/// Gaps shorter than this are just the pace of speech, not a pause
+const MIN_PAUSE_MS: u64 = 250;
+
+/// Gaps this long start a new paragraph in the text
+const PARAGRAPH_GAP_MS: u64 = 2_000;
+
+/// A gap this long ends a group even mid-sentence
+const SCENE_GAP_MS: u64 = 1_000;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubtitleText {
+    pub text: String,
+    /// The text as generated with the original timing: sentences, each
+    /// followed by the gap before the next cue
+    pub segments: Vec<Segment>,
+    pub cue_count: usize,
+    pub estimate: GenerationEstimate,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Cue {
+    /// Milliseconds; `None` when the timestamp couldn't be read
+    start: Option<u64>,
+    end: Option<u64>,
+    text: String,
+}
+
+pub fn extract_text(path: &Path) -> Result<SubtitleText, ExtractError> {
+    let bytes = std::fs::read(path).map_err(|e| ExtractError::Io(e.to_string()))?;
+    let content = String::from_utf8_lossy(&bytes);
+    let cues = parse_cues(&content);
+    if cues.is_empty() {
+        return Err(ExtractError::Unreadable("no subtitle cues found".to_string()));
+    }
+    let subtitles = merge_cues(&cues);
+    if subtitles.text.is_empty() {
+        return Err(ExtractError::NoText);
+    }
+    Ok(subtitles)
+}
+
+/// Cues from SRT or WebVTT; the two differ little enough to share a parser.
+/// Blocks without a `-->` line (WebVTT headers, NOTE, STYLE) are skipped.
+fn parse_cues(content: &str) -> Vec<Cue> {
+    let content = content.trim_start_matches('\u{feff}').replace("\r\n", "\n").replace('\r', "\n");
+    let mut cues = Vec::new();
+    for block in content.split("\n\n") {
+        let mut lines = block.lines().skip_while(|line| !line.contains("-->"));
+        let Some(timing) = lines.next() else {
+            continue;
+        };
+        let (start, end) = timing.split_once("-->").unwrap_or_default();
+        let end = end.split_whitespace().next().unwrap_or_default();
+        let text = lines
+            .map(clean_line)
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        if !text.is_empty() {
+            cues.push(Cue { start: parse_timestamp(start), end: parse_timestamp(end), text });
+        }
+    }
+    cues
+}
+
+/// `HH:MM:SS,mmm`, `HH:MM:SS.mmm` or `MM:SS.mmm`, forgiving of short
+/// fractions, missing fractions and a colon before the milliseconds
+fn parse_timestamp(timestamp: &str) -> Option<u64> {
+    let timestamp = timestamp.trim().replace(',', ".");
+    let (clock, fraction) = timestamp.split_once('.').unwrap_or((&timestamp, ""));
+    let mut parts: Vec<&str> = clock.split(':').collect();
+    let mut millis = 0;
+    if parts.len() == 4 && fraction.is_empty() {
+        millis = parse_fraction(parts.pop()?)?;
+    } else if !fraction.is_empty() {
+        millis = parse_fraction(fraction)?;
+    }
+    let numbers = parts
+        .iter()
+        .map(|part| part.trim().parse::<u64>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    let seconds = match numbers.as_slice() {
+        [hours, minutes, seconds] => hours * 3600 + minutes * 60 + seconds,
+        [minutes, seconds] => minutes * 60 + seconds,
+        _ => return None,
+    };
+    Some(seconds * 1000 + millis)
+}
+
+/// ".5" is 500 ms and ".0501" is 50 ms
+fn parse_fraction(fraction: &str) -> Option<u64> {
+    if fraction.is_empty() || !fraction.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let digits: String = fraction.chars().chain("00".chars()).take(3).collect();
+    digits.parse().ok()
+}
+
+/// A cue line without markup: `<i>`, `<font …>`, WebVTT voice, class and
+/// timestamp tags, `{\an8}`-style positioning, and leading dialogue dashes
+fn clean_line(line: &str) -> String {
+    let mut text = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(open) = rest.find(['<', '{']) {
+        text.push_str(&rest[..open]);
+        let close = if rest[open..].starts_with('<') { '>' } else { '}' };
+        match rest[open..].find(close) {
+            Some(end) if close == '>' || rest[open..].starts_with("{\\") => rest = &rest[open + end + 1..],
+            _ => {
+                text.push_str(&rest[open..open + 1]);
+                rest = &rest[open + 1..];
+            }
+        }
+    }
+    text.push_str(rest);
+    let text = extract::decode_entities(&text);
+    let text = text.trim().trim_start_matches(['-', '–', '—']).trim();
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// "night?" and "Ana.”" end sentences; "came in" doesn't
+fn ends_sentence(text: &str) -> bool {
+    text.trim_end_matches(['"', '\'', '”', '’', ')', ']'])
+        .ends_with(['.', '!', '?', '…', '。'])
+}
+
+/// Join cues into sentences. A group ends at a sentence end or a long gap;
+/// the gap after it becomes its pause.
+fn merge_cues(cues: &[Cue]) -> SubtitleText {
+    let mut segments: Vec<Segment> = Vec::new();
+    let mut text = String::new();
+    let mut current = String::new();
+
+    for (i, cue) in cues.iter().enumerate() {
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(&cue.text);
+
+        let gap = cues.get(i + 1).and_then(|next| Some(next.start?.saturating_sub(cue.end?)));
+        let is_last = i + 1 == cues.len();
+        if !(is_last || ends_sentence(&current) || gap.is_some_and(|gap| gap >= SCENE_GAP_MS)) {
+            continue;
+        }
+
+        let gap = gap.unwrap_or(0);
+        let pause_after_ms = if gap >= MIN_PAUSE_MS { gap.min(MAX_PAUSE_MS) } else { 0 };
+        text.push_str(&current);
+        if !is_last {
+            text.push_str(if gap >= PARAGRAPH_GAP_MS { "\n\n" } else { " " });
+        }
+        segments.push(Segment { text: std::mem::take(&mut current), pause_after_ms });
+    }
+
+    let estimate = TTSService::estimate(&text);
+    SubtitleText { text, segments, cue_count: cues.len(), estimate }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SRT: &str = include_str!("../tests/fixtures/interview.srt");
+    const VTT: &str = include_str!("../tests/fixtures/interview.vtt");
+
+    fn segment(text: &str, pause_after_ms: u64) -> Segment {
+        Segment { text: text.to_string(), pause_after_ms }
+    }
+
+    #[test]
+    fn test_srt() {
+        let cues = parse_cues(SRT);
+        assert_eq!(cues.len(), 6);
+        assert_eq!(cues[0].text, "Where were you last night?");
+        assert_eq!(cues[1].text, "At the harbour.");
+        assert_eq!(cues[2].text, "Alone? With Tom & Ana.");
+        // Tolerated: a one-digit fraction, and a broken start that loses only its timing
+        assert_eq!((cues[4].start, cues[4].end), (Some(11_200), Some(12_800)));
+        assert_eq!((cues[5].start, cues[5].end), (None, Some(14_000)));
+
+        let subtitles = merge_cues(&cues);
+        assert_eq!(
+            subtitles.text,
+            "Where were you last night? At the harbour. Alone? With Tom & Ana.\n\n\
+             The storm came in after midnight. Nobody slept."
+        );
+        assert_eq!(
+            subtitles.segments,
+            vec![
+                segment("Where were you last night?", 0),
+                segment("At the harbour.", 0),
+                segment("Alone? With Tom & Ana.", 2_600),
+                segment("The storm came in after midnight.", 0),
+                segment("Nobody slept.", 0),
+            ]
+        );
+        assert_eq!(subtitles.cue_count, 6);
+    }
+
+    #[test]
+    fn test_vtt() {
+        let cues = parse_cues(VTT);
+        assert_eq!(cues.len(), 5);
+        assert_eq!(cues[0], Cue { start: Some(1_000), end: Some(3_200), text: "Where were you last night?".to_string() });
+        assert_eq!(cues[1].text, "At the harbour.");
+        assert_eq!(cues[2].text, "Alone? With Tom & Ana.");
+
+        let subtitles = merge_cues(&cues);
+        assert!(subtitles.text.starts_with("Where were you last night? At the harbour."));
+        assert!(!subtitles.text.contains("cue"));
+        assert_eq!(subtitles.segments.len(), 4);
+        assert_eq!(subtitles.segments[2].pause_after_ms, 2_600);
+    }
+
+    #[test]
+    fn test_timestamps() {
+        assert_eq!(parse_timestamp("01:02:03,456"), Some(3_723_456));
+        assert_eq!(parse_timestamp(" 00:00:01.5 "), Some(1_500));
+        assert_eq!(parse_timestamp("02:03.040"), Some(123_040));
+        assert_eq!(parse_timestamp("00:00:04:250"), Some(4_250));
+        assert_eq!(parse_timestamp("00:00:04"), Some(4_000));
+        assert_eq!(parse_timestamp("soon"), None);
+        assert_eq!(parse_timestamp("00:0a:04,000"), None);
+    }
+
+    #[test]
+    fn test_long_gaps_are_capped_and_split_sentences() {
+        let cue = |start, end, text: &str| Cue { start: Some(start), end: Some(end), text: text.to_string() };
+        let subtitles = merge_cues(&[cue(0, 1_000, "And then"), cue(60_000, 61_000, "silence.")]);
+        assert_eq!(subtitles.segments, vec![segment("And then", MAX_PAUSE_MS), segment("silence.", 0)]);
+        assert_eq!(clean_line("a < b {not a tag}"), "a < b {not a tag}");
+    }
+}
@@ -0,0 +1,65 @@
+//! Fakes shared by the tests of several modules
+
+use crate::tts::{SpeechBackend, TTSError};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Returns the text back as "audio"; fails for any text containing "FAIL".
+/// Each call takes 10 ms, or 5 s for text containing "slow", and is
+/// recorded with the source it came as.
+#[derive(Default)]
+pub struct FakeBackend {
+    /// The texts asked for, in order
+    pub calls: Mutex<Vec<String>>,
+    /// The source of each call, where one was given
+    pub sources: Mutex<Vec<Option<String>>>,
+    /// Most calls that were running at once
+    pub max_in_flight: AtomicUsize,
+    in_flight: AtomicUsize,
+    scripted: Mutex<VecDeque<Result<Vec<u8>, TTSError>>>,
+    audio: Option<fn(&str) -> Vec<u8>>,
+}
+
+impl FakeBackend {
+    /// Answers with `results` in order, then as usual
+    pub fn scripted(results: Vec<Result<Vec<u8>, TTSError>>) -> Self {
+        Self { scripted: Mutex::new(results.into()), ..Self::default() }
+    }
+
+    /// Answers with `audio` of the text instead of the text itself
+    pub fn with_audio(audio: fn(&str) -> Vec<u8>) -> Self {
+        Self { audio: Some(audio), ..Self::default() }
+    }
+}
+
+impl SpeechBackend for FakeBackend {
+    async fn synthesize(&self, text: &str, voice_id: &str, model: &str) -> Result<Vec<u8>, TTSError> {
+        self.synthesize_as("", text, voice_id, model).await
+    }
+
+    async fn synthesize_as(
+        &self,
+        source: &str,
+        text: &str,
+        _voice_id: &str,
+        _model: &str,
+    ) -> Result<Vec<u8>, TTSError> {
+        self.calls.lock().unwrap().push(text.to_string());
+        self.sources.lock().unwrap().push(Some(source.to_string()).filter(|source| !source.is_empty()));
+        let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_in_flight.fetch_max(now, Ordering::SeqCst);
+        let delay = if text.contains("slow") { 5_000 } else { 10 };
+        tokio::time::sleep(Duration::from_millis(delay)).await;
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+        if let Some(result) = self.scripted.lock().unwrap().pop_front() {
+            result
+        } else if text.contains("FAIL") {
+            Err(TTSError::UnknownError("HTTP 500".to_string()))
+        } else {
+            Ok(self.audio.map_or_else(|| text.as_bytes().to_vec(), |audio| audio(text)))
+        }
+    }
+}
@@ -0,0 +1,215 @@
+//! Whether the service is holding back from the API, and until when. After
+//! a 429 the requests that follow wait out its Retry-After rather than
+//! being refused again straight away, and failed requests are retried after
+//! a pause (see `TTSService::generate_speech_with_retry`). Without this
+//! state the window just looks frozen meanwhile; with it, it can say why.
+//!
+//! A job learns that it is the one waiting through `report_waits`, which
+//! works like `warnings::collect`.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Longer waits than this aren't sat out; the request fails with
+/// `TTSError::RateLimit` instead, so a job isn't stuck for an hour
+pub const MAX_WAIT: Duration = Duration::from_secs(60);
+
+/// Without a Retry-After, how long after a 429 requests hold back
+pub const DEFAULT_BACKOFF: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ThrottleReason {
+    /// The API answered 429
+    RateLimited,
+    /// Pausing before trying a failed request again
+    Retrying,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ThrottleStatus {
+    pub backing_off: bool,
+    pub reason: Option<ThrottleReason>,
+    /// When requests go out again
+    pub resume_at: Option<DateTime<Utc>>,
+}
+
+impl ThrottleStatus {
+    fn idle() -> Self {
+        Self { backing_off: false, reason: None, resume_at: None }
+    }
+}
+
+/// Told whenever the status changes; the app forwards it as `tts:throttle`
+pub type ThrottleListener = Arc<dyn Fn(&ThrottleStatus) + Send + Sync>;
+
+/// Told when the task it was given to starts (`Some`) or stops waiting
+pub type WaitListener = Arc<dyn Fn(Option<&ThrottleStatus>) + Send + Sync>;
+
+tokio::task_local! {
+    static WAITS: WaitListener;
+}
+
+/// Run `work`, telling `on_wait` whenever it waits on the throttle
+pub async fn report_waits<T>(on_wait: WaitListener, work: impl Future<Output = T>) -> T {
+    WAITS.scope(on_wait, work).await
+}
+
+fn report_wait(status: Option<&ThrottleStatus>) {
+    let _ = WAITS.try_with(|on_wait| on_wait(status));
+}
+
+#[derive(Default)]
+pub struct Throttle {
+    backoff: Mutex<Option<(ThrottleReason, DateTime<Utc>)>>,
+    listener: Mutex<Option<ThrottleListener>>,
+}
+
+impl Throttle {
+    pub fn set_listener(&self, listener: ThrottleListener) {
+        *self.listener.lock().unwrap() = Some(listener);
+    }
+
+    pub fn status(&self) -> ThrottleStatus {
+        match *self.backoff.lock().unwrap() {
+            Some((reason, until)) if until > Utc::now() => {
+                ThrottleStatus { backing_off: true, reason: Some(reason), resume_at: Some(until) }
+            }
+            _ => ThrottleStatus::idle(),
+        }
+    }
+
+    /// Hold requests back for `wait` (`DEFAULT_BACKOFF` when the API didn't
+    /// say) after a 429
+    pub fn rate_limited(&self, wait: Option<Duration>) {
+        self.back_off(ThrottleReason::RateLimited, wait.unwrap_or(DEFAULT_BACKOFF));
+    }
+
+    fn back_off(&self, reason: ThrottleReason, wait: Duration) {
+        let until = Utc::now() + chrono::Duration::from_std(wait).unwrap_or(chrono::Duration::MAX);
+        {
+            let mut backoff = self.backoff.lock().unwrap();
+            // A later resume time from another request wins
+            if matches!(*backoff, Some((_, current)) if current > until) {
+                return;
+            }
+            *backoff = Some((reason, until));
+        }
+        self.notify();
+    }
+
+    /// Requests can go out again, e.g. after one succeeded
+    pub fn resume(&self) {
+        if self.backoff.lock().unwrap().take().is_some() {
+            self.notify();
+        }
+    }
+
+    /// Before a request: wait while backing off. `Err` with the seconds
+    /// left when that's more than `MAX_WAIT`.
+    pub async fn ready(&self) -> Result<(), u64> {
+        let status = self.status();
+        let Some(resume_at) = status.resume_at else {
+            return Ok(());
+        };
+        let wait = (resume_at - Utc::now()).to_std().unwrap_or_default();
+        if wait > MAX_WAIT {
+            return Err(wait.as_secs());
+        }
+        tracing::info!("Waiting {} ms for the rate limit", wait.as_millis());
+        self.wait(&status, wait).await;
+        Ok(())
+    }
+
+    /// Pause `delay` before trying a failed request again
+    pub async fn pause(&self, delay: Duration) {
+        self.back_off(ThrottleReason::Retrying, delay);
+        self.wait(&self.status(), delay).await;
+        // Unless a 429 came in meanwhile, which still holds requests back
+        let retrying = matches!(*self.backoff.lock().unwrap(), Some((ThrottleReason::Retrying, _)));
+        if retrying {
+            self.resume();
+        }
+    }
+
+    async fn wait(&self, status: &ThrottleStatus, delay: Duration) {
+        report_wait(Some(status));
+        tokio::time::sleep(delay).await;
+        report_wait(None);
+    }
+
+    fn notify(&self) {
+        let listener = self.listener.lock().unwrap().clone();
+        if let Some(listener) = listener {
+            listener(&self.status());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recording(throttle: &Throttle) -> Arc<Mutex<Vec<ThrottleStatus>>> {
+        let events: Arc<Mutex<Vec<ThrottleStatus>>> = Arc::default();
+        let recorded = events.clone();
+        throttle.set_listener(Arc::new(move |status: &ThrottleStatus| recorded.lock().unwrap().push(status.clone())));
+        events
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_backoff() {
+        let throttle = Throttle::default();
+        let events = recording(&throttle);
+        assert_eq!(throttle.status(), ThrottleStatus::idle());
+
+        let before = Utc::now();
+        throttle.rate_limited(Some(Duration::from_secs(30)));
+        let status = throttle.status();
+        assert!(status.backing_off);
+        assert_eq!(status.reason, Some(ThrottleReason::RateLimited));
+        let resume_at = status.resume_at.unwrap();
+        assert!(resume_at >= before + chrono::Duration::seconds(30) && resume_at <= Utc::now() + chrono::Duration::seconds(30));
+
+        // A shorter wait doesn't bring it forward
+        throttle.rate_limited(Some(Duration::from_secs(1)));
+        assert_eq!(throttle.status().resume_at, Some(resume_at));
+
+        throttle.resume();
+        assert_eq!(throttle.status(), ThrottleStatus::idle());
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(events[0].backing_off);
+        assert_eq!(events[1], ThrottleStatus::idle());
+    }
+
+    #[tokio::test]
+    async fn test_requests_wait_out_the_rate_limit() {
+        let throttle = Throttle::default();
+        throttle.rate_limited(Some(Duration::from_millis(150)));
+        let waits: Arc<Mutex<Vec<bool>>> = Arc::default();
+        let recorded = waits.clone();
+        let started = std::time::Instant::now();
+        let on_wait = Arc::new(move |status: Option<&ThrottleStatus>| recorded.lock().unwrap().push(status.is_some()));
+        report_waits(on_wait, throttle.ready()).await.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(100), "{:?}", started.elapsed());
+        assert_eq!(*waits.lock().unwrap(), [true, false]);
+
+        throttle.rate_limited(Some(Duration::from_secs(3600)));
+        let refused = throttle.ready().await.unwrap_err();
+        assert!((3590..=3600).contains(&refused), "{}", refused);
+    }
+
+    #[tokio::test]
+    async fn test_retry_pause() {
+        let throttle = Throttle::default();
+        let events = recording(&throttle);
+        throttle.pause(Duration::from_millis(20)).await;
+        let reasons: Vec<_> = events.lock().unwrap().iter().map(|status| status.reason).collect();
+        assert_eq!(reasons, [Some(ThrottleReason::Retrying), None]);
+    }
+}
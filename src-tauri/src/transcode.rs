@@ -0,0 +1,258 @@
+//! Converting saved audio to another format with FFmpeg, e.g. WAV to edit
+//! or Ogg for a web page, without generating it again.
+
+use serde::Deserialize;
+use std::path::Path;
+
+/// What a clip can be converted to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetFormat {
+    Wav,
+    Flac,
+    Mp3,
+    Ogg,
+    Opus,
+    M4a,
+}
+
+impl TargetFormat {
+    pub fn parse(format: &str) -> Result<Self, TranscodeError> {
+        match format.trim().trim_start_matches('.').to_ascii_lowercase().as_str() {
+            "wav" => Ok(Self::Wav),
+            "flac" => Ok(Self::Flac),
+            "mp3" => Ok(Self::Mp3),
+            "ogg" | "vorbis" => Ok(Self::Ogg),
+            "opus" => Ok(Self::Opus),
+            "m4a" | "aac" => Ok(Self::M4a),
+            _ => Err(TranscodeError::Unsupported(format!("Can't convert to \"{}\"", format))),
+        }
+    }
+
+    /// File extension of the result
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Wav => "wav",
+            Self::Flac => "flac",
+            Self::Mp3 => "mp3",
+            Self::Ogg => "ogg",
+            Self::Opus => "opus",
+            Self::M4a => "m4a",
+        }
+    }
+
+    /// The encoder, and its bitrate in kbit/s when it is lossy
+    fn codec(self) -> (&'static str, Option<u32>) {
+        match self {
+            Self::Wav => ("pcm_s16le", None),
+            Self::Flac => ("flac", None),
+            Self::Mp3 => ("libmp3lame", Some(192)),
+            Self::Ogg => ("libvorbis", Some(160)),
+            Self::Opus => ("libopus", Some(96)),
+            Self::M4a => ("aac", Some(160)),
+        }
+    }
+}
+
+/// Anything left out stays as the source has it, or the encoder's default
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TranscodeOptions {
+    #[serde(default)]
+    pub sample_rate: Option<u32>,
+    /// kbit/s; lossy formats only
+    #[serde(default)]
+    pub bitrate_kbps: Option<u32>,
+    /// 1 for mono, 2 for stereo
+    #[serde(default)]
+    pub channels: Option<u8>,
+}
+
+/// Sample rates Opus can encode at
+const OPUS_SAMPLE_RATES: [u32; 5] = [8000, 12_000, 16_000, 24_000, 48_000];
+
+/// FFmpeg couldn't be run from this path; the one error for it, whether
+/// joining long text, converting or trimming
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FfmpegMissing(pub String);
+
+impl FfmpegMissing {
+    /// For `ffmpeg` failing to start with `e`
+    pub fn after(ffmpeg: &str, e: std::io::Error) -> Self {
+        tracing::warn!("Failed to run ffmpeg ({}): {}", ffmpeg, e);
+        Self(ffmpeg.to_string())
+    }
+}
+
+impl std::fmt::Display for FfmpegMissing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "FFmpeg is needed to join long text and to convert or trim audio, but \"{}\" could not be run. Install \
+             FFmpeg, or set ffmpeg_path in config.toml (or TTS_PLAYER_FFMPEG) to where it is",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for FfmpegMissing {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TranscodeError {
+    FfmpegMissing(FfmpegMissing),
+    /// Not a format, or not options, it can be converted with
+    Unsupported(String),
+    /// FFmpeg ran and failed, with what it said
+    Failed(String),
+}
+
+impl std::fmt::Display for TranscodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TranscodeError::FfmpegMissing(missing) => write!(f, "{}", missing),
+            TranscodeError::Unsupported(detail) => write!(f, "{}", detail),
+            TranscodeError::Failed(stderr) => write!(f, "ffmpeg failed: {}", stderr),
+        }
+    }
+}
+
+impl std::error::Error for TranscodeError {}
+
+/// FFmpeg's arguments to convert `input` into `output` as `format`
+pub fn ffmpeg_args(
+    input: &Path,
+    output: &Path,
+    format: TargetFormat,
+    options: &TranscodeOptions,
+) -> Result<Vec<String>, TranscodeError> {
+    let (codec, default_bitrate) = format.codec();
+    let mut args: Vec<String> = ["-hide_banner", "-loglevel", "error", "-i"].map(String::from).to_vec();
+    args.push(input.to_string_lossy().into_owned());
+    // Cover art and the like aren't carried over
+    args.push("-vn".to_string());
+
+    if let Some(sample_rate) = options.sample_rate {
+        if !(8000..=192_000).contains(&sample_rate) {
+            return Err(TranscodeError::Unsupported(format!("Sample rate {} Hz is out of range", sample_rate)));
+        }
+        if format == TargetFormat::Opus && !OPUS_SAMPLE_RATES.contains(&sample_rate) {
+            return Err(TranscodeError::Unsupported(format!(
+                "Opus can't be encoded at {} Hz; use 8000, 12000, 16000, 24000 or 48000",
+                sample_rate
+            )));
+        }
+        args.extend(["-ar".to_string(), sample_rate.to_string()]);
+    }
+    match options.channels {
+        None => {}
+        Some(channels @ (1 | 2)) => args.extend(["-ac".to_string(), channels.to_string()]),
+        Some(channels) => {
+            return Err(TranscodeError::Unsupported(format!("{} channels; only mono (1) or stereo (2)", channels)))
+        }
+    }
+
+    args.extend(["-c:a".to_string(), codec.to_string()]);
+    match (options.bitrate_kbps, default_bitrate) {
+        (Some(_), None) => {
+            return Err(TranscodeError::Unsupported(format!(
+                "{} is lossless, so it has no bitrate to set",
+                format.extension()
+            )))
+        }
+        (Some(bitrate), Some(_)) if !(8..=512).contains(&bitrate) => {
+            return Err(TranscodeError::Unsupported(format!("Bitrate {} kbit/s is out of range", bitrate)))
+        }
+        (bitrate, Some(default)) => args.extend(["-b:a".to_string(), format!("{}k", bitrate.unwrap_or(default))]),
+        (None, None) => {}
+    }
+
+    args.extend(["-y".to_string(), output.to_string_lossy().into_owned()]);
+    Ok(args)
+}
+
+/// Run `ffmpeg` with `args`
+pub async fn run(ffmpeg: &str, args: &[String]) -> Result<(), TranscodeError> {
+    let output = tokio::process::Command::new(ffmpeg)
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| TranscodeError::FfmpegMissing(FfmpegMissing::after(ffmpeg, e)))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        tracing::error!("FFmpeg failed with stderr: {}", stderr);
+        return Err(TranscodeError::Failed(stderr.trim().to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn args(format: &str, options: TranscodeOptions) -> Result<String, TranscodeError> {
+        let format = TargetFormat::parse(format)?;
+        let args = ffmpeg_args(Path::new("in.mp3"), &PathBuf::from(format!("out.{}", format.extension())), format, &options)?;
+        Ok(args.join(" "))
+    }
+
+    #[test]
+    fn test_arguments_per_format() {
+        let defaults = TranscodeOptions::default();
+        let prefix = "-hide_banner -loglevel error -i in.mp3 -vn";
+        assert_eq!(args("wav", defaults.clone()).unwrap(), format!("{} -c:a pcm_s16le -y out.wav", prefix));
+        assert_eq!(args("FLAC", defaults.clone()).unwrap(), format!("{} -c:a flac -y out.flac", prefix));
+        assert_eq!(args("mp3", defaults.clone()).unwrap(), format!("{} -c:a libmp3lame -b:a 192k -y out.mp3", prefix));
+        assert_eq!(args(".ogg", defaults.clone()).unwrap(), format!("{} -c:a libvorbis -b:a 160k -y out.ogg", prefix));
+        assert_eq!(args("opus", defaults.clone()).unwrap(), format!("{} -c:a libopus -b:a 96k -y out.opus", prefix));
+        assert_eq!(args("aac", defaults).unwrap(), format!("{} -c:a aac -b:a 160k -y out.m4a", prefix));
+
+        let options = TranscodeOptions { sample_rate: Some(22_050), bitrate_kbps: Some(64), channels: Some(1) };
+        assert_eq!(
+            args("mp3", options).unwrap(),
+            format!("{} -ar 22050 -ac 1 -c:a libmp3lame -b:a 64k -y out.mp3", prefix)
+        );
+        let options = TranscodeOptions { sample_rate: Some(44_100), channels: Some(2), ..TranscodeOptions::default() };
+        assert_eq!(args("wav", options).unwrap(), format!("{} -ar 44100 -ac 2 -c:a pcm_s16le -y out.wav", prefix));
+    }
+
+    #[test]
+    fn test_unsupported_conversions() {
+        let unsupported = |format: &str, options: TranscodeOptions| match args(format, options) {
+            Err(TranscodeError::Unsupported(detail)) => detail,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(unsupported("aiff", TranscodeOptions::default()), "Can't convert to \"aiff\"");
+        let bitrate = TranscodeOptions { bitrate_kbps: Some(128), ..TranscodeOptions::default() };
+        assert_eq!(unsupported("wav", bitrate), "wav is lossless, so it has no bitrate to set");
+        let opus = TranscodeOptions { sample_rate: Some(44_100), ..TranscodeOptions::default() };
+        assert!(unsupported("opus", opus).starts_with("Opus can't be encoded at 44100 Hz"));
+        let surround = TranscodeOptions { channels: Some(6), ..TranscodeOptions::default() };
+        assert!(unsupported("mp3", surround).starts_with("6 channels"));
+        let bitrate = TranscodeOptions { bitrate_kbps: Some(4), ..TranscodeOptions::default() };
+        assert!(unsupported("mp3", bitrate).contains("out of range"));
+    }
+
+    #[tokio::test]
+    async fn test_missing_ffmpeg() {
+        let error = run("/nonexistent/ffmpeg", &["-version".to_string()]).await.unwrap_err();
+        assert_eq!(error, TranscodeError::FfmpegMissing(FfmpegMissing("/nonexistent/ffmpeg".to_string())));
+        assert!(error.to_string().contains("ffmpeg_path"), "{}", error);
+    }
+
+    /// Runs only where FFmpeg is installed
+    #[tokio::test]
+    async fn test_converts_with_ffmpeg() {
+        if run("ffmpeg", &["-version".to_string()]).await.is_err() {
+            return;
+        }
+        let dir = tempfile::TempDir::new().unwrap();
+        let input = dir.path().join("in.wav");
+        std::fs::write(&input, crate::silence::silent_wav(500)).unwrap();
+        for format in [TargetFormat::Wav, TargetFormat::Flac, TargetFormat::Mp3, TargetFormat::M4a] {
+            let output = dir.path().join(format!("out.{}", format.extension()));
+            let options = TranscodeOptions { sample_rate: Some(16_000), channels: Some(1), ..TranscodeOptions::default() };
+            run("ffmpeg", &ffmpeg_args(&input, &output, format, &options).unwrap()).await.unwrap();
+            assert!(std::fs::metadata(&output).unwrap().len() > 0, "{:?}", format);
+        }
+    }
+}
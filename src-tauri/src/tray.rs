@@ -0,0 +1,243 @@
+//! The tray (menu bar) icon's menu. What the menu shows and what each item
+//! does are decided here, against `TrayHost`, so both can be tested without
+//! a tray; `main` turns the entries into real menu items.
+
+use crate::clipboard::{self, ClipboardSource};
+use crate::database::UsageRecord;
+
+pub const SPEAK_CLIPBOARD: &str = "speak_clipboard";
+pub const STOP: &str = "stop";
+pub const QUIT: &str = "quit";
+/// Followed by the usage record's id
+pub const HISTORY_PREFIX: &str = "history:";
+
+/// How many recent generations the menu lists
+pub const HISTORY_ITEMS: usize = 5;
+const HISTORY_LABEL_CHARS: usize = 40;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrayEntry {
+    Item { id: String, label: String, enabled: bool },
+    Separator,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrayAction {
+    SpeakClipboard,
+    Stop,
+    /// Put a past generation's text back in the window
+    OpenHistory(i64),
+    Quit,
+}
+
+impl TrayAction {
+    pub fn from_id(id: &str) -> Option<Self> {
+        match id {
+            SPEAK_CLIPBOARD => Some(TrayAction::SpeakClipboard),
+            STOP => Some(TrayAction::Stop),
+            QUIT => Some(TrayAction::Quit),
+            _ => id.strip_prefix(HISTORY_PREFIX)?.parse().ok().map(TrayAction::OpenHistory),
+        }
+    }
+}
+
+/// What the tray actions do in the app
+pub trait TrayHost {
+    fn speak(&mut self, text: String);
+    fn stop(&mut self);
+    fn open_history(&mut self, id: i64);
+    fn quit(&mut self);
+    fn notify(&mut self, title: &str, body: &str);
+}
+
+/// The menu, with "Speak clipboard" greyed out as "Generating…" while a
+/// generation started from the tray runs
+pub fn menu_entries(generating: bool, history: &[UsageRecord]) -> Vec<TrayEntry> {
+    let item = |id: &str, label: &str, enabled: bool| TrayEntry::Item {
+        id: id.to_string(),
+        label: label.to_string(),
+        enabled,
+    };
+    let mut entries = vec![
+        if generating {
+            item(SPEAK_CLIPBOARD, "Generating…", false)
+        } else {
+            item(SPEAK_CLIPBOARD, "Speak clipboard", true)
+        },
+        item(STOP, "Stop", true),
+    ];
+
+    let history: Vec<TrayEntry> = history
+        .iter()
+        .filter(|record| record.success)
+        .filter_map(|record| {
+            let id = record.id?;
+            Some(TrayEntry::Item {
+                id: format!("{}{}", HISTORY_PREFIX, id),
+                label: history_label(&record.text),
+                enabled: true,
+            })
+        })
+        .take(HISTORY_ITEMS)
+        .collect();
+    if !history.is_empty() {
+        entries.push(TrayEntry::Separator);
+        entries.extend(history);
+    }
+
+    entries.push(TrayEntry::Separator);
+    entries.push(item(QUIT, "Quit", true));
+    entries
+}
+
+/// The start of `text` on one line, short enough for a menu
+fn history_label(text: &str) -> String {
+    let line = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if line.chars().count() <= HISTORY_LABEL_CHARS {
+        return line;
+    }
+    let start: String = line.chars().take(HISTORY_LABEL_CHARS - 1).collect();
+    format!("{}…", start.trim_end())
+}
+
+pub fn dispatch(action: TrayAction, clipboard: &mut dyn ClipboardSource, host: &mut dyn TrayHost) {
+    match action {
+        TrayAction::SpeakClipboard => match clipboard::read(clipboard).map(|copied| copied.map(|copied| copied.text)) {
+            Ok(Some(text)) if !text.trim().is_empty() => host.speak(text.trim().to_string()),
+            Ok(_) => host.notify("Nothing to speak", "The clipboard has no text"),
+            Err(e) => {
+                tracing::warn!("{}", e);
+                host.notify("Nothing to speak", &e);
+            }
+        },
+        TrayAction::Stop => host.stop(),
+        TrayAction::OpenHistory(id) => host.open_history(id),
+        TrayAction::Quit => host.quit(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::SOURCE_APP;
+
+    struct FakeClipboard(Result<Option<String>, String>);
+
+    impl ClipboardSource for FakeClipboard {
+        fn read_text(&mut self) -> Result<Option<String>, String> {
+            self.0.clone()
+        }
+    }
+
+    #[derive(Default)]
+    struct RecordingHost(Vec<String>);
+
+    impl TrayHost for RecordingHost {
+        fn speak(&mut self, text: String) {
+            self.0.push(format!("speak {}", text));
+        }
+        fn stop(&mut self) {
+            self.0.push("stop".to_string());
+        }
+        fn open_history(&mut self, id: i64) {
+            self.0.push(format!("history {}", id));
+        }
+        fn quit(&mut self) {
+            self.0.push("quit".to_string());
+        }
+        fn notify(&mut self, title: &str, _body: &str) {
+            self.0.push(format!("notify {}", title));
+        }
+    }
+
+    fn record(id: i64, text: &str, success: bool) -> UsageRecord {
+        UsageRecord {
+            id: Some(id),
+            timestamp: chrono::Utc::now(),
+            text: text.to_string(),
+            character_count: text.len() as i32,
+            voice_id: "nova".to_string(),
+            model_id: "tts-1".to_string(),
+            success,
+            error_message: None,
+            source: SOURCE_APP.to_string(),
+            regenerated_from: None,
+            downgraded_from: None,
+            key_fingerprint: None,
+            app_version: None,
+            profile: crate::database::DEFAULT_PROFILE.to_string(),
+            latency_ms: None,
+            chunk_count: None,
+        }
+    }
+
+    fn labels(entries: &[TrayEntry]) -> Vec<String> {
+        entries
+            .iter()
+            .map(|entry| match entry {
+                TrayEntry::Item { label, enabled: true, .. } => label.clone(),
+                TrayEntry::Item { label, enabled: false, .. } => format!("({})", label),
+                TrayEntry::Separator => "-".to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_menu_entries() {
+        let history = [
+            record(7, "Short note", true),
+            record(6, "Failed attempt", false),
+            record(5, "A much longer passage\nthat wraps over several lines of text", true),
+        ];
+        assert_eq!(
+            labels(&menu_entries(false, &history)),
+            [
+                "Speak clipboard",
+                "Stop",
+                "-",
+                "Short note",
+                "A much longer passage that wraps over s…",
+                "-",
+                "Quit"
+            ]
+        );
+        assert_eq!(labels(&menu_entries(true, &[])), ["(Generating…)", "Stop", "-", "Quit"]);
+    }
+
+    #[test]
+    fn test_ids_round_trip() {
+        for entry in menu_entries(false, &[record(42, "Hello", true)]) {
+            if let TrayEntry::Item { id, .. } = entry {
+                assert!(TrayAction::from_id(&id).is_some(), "{}", id);
+            }
+        }
+        assert_eq!(TrayAction::from_id("history:42"), Some(TrayAction::OpenHistory(42)));
+        assert_eq!(TrayAction::from_id("history:x"), None);
+        assert_eq!(TrayAction::from_id("settings"), None);
+    }
+
+    #[test]
+    fn test_dispatch() {
+        let mut host = RecordingHost::default();
+        dispatch(TrayAction::SpeakClipboard, &mut FakeClipboard(Ok(Some("  Read this.\n".to_string()))), &mut host);
+        dispatch(TrayAction::SpeakClipboard, &mut FakeClipboard(Ok(Some("   ".to_string()))), &mut host);
+        dispatch(TrayAction::SpeakClipboard, &mut FakeClipboard(Ok(None)), &mut host);
+        dispatch(TrayAction::SpeakClipboard, &mut FakeClipboard(Err("no display".to_string())), &mut host);
+        let mut unused = FakeClipboard(Ok(None));
+        dispatch(TrayAction::Stop, &mut unused, &mut host);
+        dispatch(TrayAction::OpenHistory(3), &mut unused, &mut host);
+        dispatch(TrayAction::Quit, &mut unused, &mut host);
+        assert_eq!(
+            host.0,
+            [
+                "speak Read this.",
+                "notify Nothing to speak",
+                "notify Nothing to speak",
+                "notify Nothing to speak",
+                "stop",
+                "history 3",
+                "quit"
+            ]
+        );
+    }
+}
@@ -0,0 +1,252 @@
+//! Dead air cut from the start and end of finished audio, for clips that go
+//! into other media. FFmpeg's silencedetect measures the silence at each
+//! end first. An end with more than `MAX_TRIM` of it is left alone, as
+//! that is more likely quiet speech than dead air; the rest goes with
+//! silenceremove, run on the audio reversed for the end.
+
+use crate::transcode::{self, FfmpegMissing, TranscodeError};
+use std::path::Path;
+use std::time::Duration;
+
+/// Quieter than this counts as silence
+pub const THRESHOLD_DB: i32 = -50;
+
+/// Shortest stretch of quiet taken for silence
+const MIN_SILENCE: Duration = Duration::from_millis(100);
+
+/// Silence left at each end so the speech doesn't start abruptly
+pub const KEEP: Duration = Duration::from_millis(50);
+
+/// An end with more silence than this isn't trimmed at all
+pub const MAX_TRIM: Duration = Duration::from_secs(3);
+
+/// How close to either end a silence has to reach to count as being at it
+const EDGE: Duration = Duration::from_millis(10);
+
+/// The silence measured at each end of a clip
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Silence {
+    pub head: Duration,
+    pub tail: Duration,
+}
+
+/// What is cut from each end
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Trim {
+    pub head: Duration,
+    pub tail: Duration,
+}
+
+impl Trim {
+    pub fn is_empty(&self) -> bool {
+        self.head.is_zero() && self.tail.is_zero()
+    }
+}
+
+/// FFmpeg's arguments to measure the silence in `input`; what it finds is
+/// in its stderr, for `parse_detected`
+pub fn detect_args(input: &Path) -> Vec<String> {
+    let mut args: Vec<String> = ["-hide_banner", "-i"].map(String::from).to_vec();
+    args.push(input.to_string_lossy().into_owned());
+    args.extend([
+        "-af".to_string(),
+        format!("silencedetect=noise={}dB:d={}", THRESHOLD_DB, MIN_SILENCE.as_secs_f64()),
+        "-f".to_string(),
+        "null".to_string(),
+        "-".to_string(),
+    ]);
+    args
+}
+
+/// The silence at each end from silencedetect's `stderr`. The length of
+/// the clip is the last `time=` it reports; without one nothing is found.
+pub fn parse_detected(stderr: &str) -> Silence {
+    let Some(length) = stderr.rfind("time=").and_then(|at| parse_timestamp(&stderr[at + "time=".len()..])) else {
+        return Silence::default();
+    };
+
+    // Each silence as (start, end); one still going when the audio ran out
+    // may have no end
+    let mut silences: Vec<(f64, Option<f64>)> = Vec::new();
+    for line in stderr.lines() {
+        if let Some(start) = value_after(line, "silence_start:") {
+            silences.push((start, None));
+        } else if let Some(end) = value_after(line, "silence_end:") {
+            if let Some(last) = silences.last_mut().filter(|(_, open)| open.is_none()) {
+                last.1 = Some(end);
+            }
+        }
+    }
+
+    let length = length.as_secs_f64();
+    let edge = EDGE.as_secs_f64();
+    let (head, tail) = match (silences.first(), silences.last()) {
+        (Some(first), Some(last)) => {
+            let head = if first.0 <= edge { first.1.unwrap_or(length) } else { 0.0 };
+            let tail = if last.1.is_none_or(|end| end >= length - edge) { length - last.0 } else { 0.0 };
+            (head, tail)
+        }
+        _ => (0.0, 0.0),
+    };
+    if head >= length - edge {
+        // Nothing but silence; there's no speech to trim towards
+        return Silence::default();
+    }
+    let duration = |secs: f64| Duration::from_secs_f64(secs.max(0.0));
+    Silence { head: duration(head), tail: duration(tail) }
+}
+
+fn value_after(line: &str, label: &str) -> Option<f64> {
+    let (_, rest) = line.split_once(label)?;
+    rest.split_whitespace().next()?.parse().ok()
+}
+
+/// "00:01:02.50" as a duration
+fn parse_timestamp(text: &str) -> Option<Duration> {
+    let timestamp = text.split_whitespace().next()?;
+    let mut secs = 0.0;
+    for part in timestamp.split(':') {
+        secs = secs * 60.0 + part.parse::<f64>().ok()?;
+    }
+    (secs >= 0.0).then(|| Duration::from_secs_f64(secs))
+}
+
+/// What to cut from each end of audio with `silence`: all but `KEEP` of
+/// it, or nothing at an end with more than `MAX_TRIM`
+pub fn plan(silence: Silence) -> Trim {
+    let cut = |measured: Duration| {
+        if measured > MAX_TRIM {
+            tracing::info!("Not trimming {} ms of silence; it may be quiet speech", measured.as_millis());
+            Duration::ZERO
+        } else {
+            measured.saturating_sub(KEEP)
+        }
+    };
+    Trim { head: cut(silence.head), tail: cut(silence.tail) }
+}
+
+/// The audio filter that makes `trim`, if it cuts anything
+pub fn filter(trim: &Trim) -> Option<String> {
+    let remove = format!(
+        "silenceremove=start_periods=1:start_threshold={}dB:start_silence={}",
+        THRESHOLD_DB,
+        KEEP.as_secs_f64()
+    );
+    let mut filters = Vec::new();
+    if !trim.head.is_zero() {
+        filters.push(remove.clone());
+    }
+    if !trim.tail.is_zero() {
+        filters.extend(["areverse".to_string(), remove, "areverse".to_string()]);
+    }
+    (!filters.is_empty()).then(|| filters.join(","))
+}
+
+/// FFmpeg's arguments to write `input` to `output` through `filter`
+pub fn trim_args(input: &Path, output: &Path, filter: &str) -> Vec<String> {
+    let mut args: Vec<String> = ["-hide_banner", "-loglevel", "error", "-i"].map(String::from).to_vec();
+    args.push(input.to_string_lossy().into_owned());
+    args.extend(["-af".to_string(), filter.to_string(), "-y".to_string(), output.to_string_lossy().into_owned()]);
+    args
+}
+
+/// `audio`, a `format` file, with the silence at its ends trimmed, and
+/// what was cut; `None` when there was nothing to cut
+pub async fn trim_ends(ffmpeg: &str, audio: &[u8], format: &str) -> Result<Option<(Vec<u8>, Trim)>, TranscodeError> {
+    if format == "pcm" {
+        // Raw samples have no header for FFmpeg to read them by
+        return Ok(None);
+    }
+    let dir = tempfile::tempdir().map_err(|e| TranscodeError::Failed(format!("Failed to create temp dir: {}", e)))?;
+    let input = dir.path().join(format!("untrimmed.{}", format));
+    let output = dir.path().join(format!("trimmed.{}", format));
+    tokio::fs::write(&input, audio).await.map_err(|e| TranscodeError::Failed(format!("Failed to write audio: {}", e)))?;
+
+    let detected = tokio::process::Command::new(ffmpeg)
+        .args(detect_args(&input))
+        .output()
+        .await
+        .map_err(|e| TranscodeError::FfmpegMissing(FfmpegMissing::after(ffmpeg, e)))?;
+    if !detected.status.success() {
+        return Err(TranscodeError::Failed(String::from_utf8_lossy(&detected.stderr).trim().to_string()));
+    }
+    let trim = plan(parse_detected(&String::from_utf8_lossy(&detected.stderr)));
+    let Some(filter) = filter(&trim) else {
+        return Ok(None);
+    };
+
+    transcode::run(ffmpeg, &trim_args(&input, &output, &filter)).await?;
+    let trimmed =
+        tokio::fs::read(&output).await.map_err(|e| TranscodeError::Failed(format!("Failed to read audio: {}", e)))?;
+    Ok(Some((trimmed, trim)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DETECTED: &str = "\
+Input #0, mp3, from 'untrimmed.mp3':
+  Duration: 00:00:04.03, start: 0.000000, bitrate: 160 kb/s
+[silencedetect @ 0x6000] silence_start: 0
+[silencedetect @ 0x6000] silence_end: 0.312 | silence_duration: 0.312
+[silencedetect @ 0x6000] silence_start: 1.804
+[silencedetect @ 0x6000] silence_end: 2.05 | silence_duration: 0.246
+[silencedetect @ 0x6000] silence_start: 2.96
+size=N/A time=00:00:04.00 bitrate=N/A speed= 412x
+";
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn test_parses_silence_at_the_ends() {
+        assert_eq!(parse_detected(DETECTED), Silence { head: ms(312), tail: ms(1040) });
+
+        // Newer FFmpeg closes a silence that runs to the end
+        let closed = DETECTED.replace("silence_start: 2.96\n", "silence_start: 2.96\n[silencedetect] silence_end: 4\n");
+        assert_eq!(parse_detected(&closed), Silence { head: ms(312), tail: ms(1040) });
+
+        // Silence only in the middle is left to the speech
+        let middle = "silence_start: 1.5\nsilence_end: 2 | silence_duration: 0.5\nsize=N/A time=00:00:04.00\n";
+        assert_eq!(parse_detected(middle), Silence::default());
+
+        // All silence, or no length to go by, is left alone
+        assert_eq!(parse_detected("silence_start: 0\nsize=N/A time=00:00:02.00\n"), Silence::default());
+        assert_eq!(parse_detected("silence_start: 0\nsilence_end: 0.3\n"), Silence::default());
+    }
+
+    #[test]
+    fn test_never_trims_more_than_the_cap() {
+        assert_eq!(plan(Silence { head: ms(300), tail: ms(1000) }), Trim { head: ms(250), tail: ms(950) });
+        // Too long to be dead air at one end; the other is still trimmed
+        assert_eq!(plan(Silence { head: ms(4000), tail: ms(1000) }), Trim { head: ms(0), tail: ms(950) });
+        assert_eq!(plan(Silence { head: ms(300), tail: MAX_TRIM + ms(1) }), Trim { head: ms(250), tail: ms(0) });
+        assert_eq!(plan(Silence { head: MAX_TRIM, tail: ms(0) }), Trim { head: MAX_TRIM - KEEP, tail: ms(0) });
+        // No more than `KEEP` stays as it is
+        assert!(plan(Silence { head: ms(30), tail: ms(50) }).is_empty());
+    }
+
+    #[test]
+    fn test_filter_arguments() {
+        let remove = "silenceremove=start_periods=1:start_threshold=-50dB:start_silence=0.05";
+        assert_eq!(filter(&Trim { head: ms(250), tail: ms(0) }).unwrap(), remove);
+        assert_eq!(filter(&Trim { head: ms(0), tail: ms(950) }).unwrap(), format!("areverse,{},areverse", remove));
+        assert_eq!(
+            filter(&Trim { head: ms(250), tail: ms(950) }).unwrap(),
+            format!("{},areverse,{},areverse", remove, remove)
+        );
+        assert_eq!(filter(&Trim::default()), None);
+
+        let input = Path::new("in.mp3");
+        assert_eq!(
+            detect_args(input).join(" "),
+            "-hide_banner -i in.mp3 -af silencedetect=noise=-50dB:d=0.1 -f null -"
+        );
+        assert_eq!(
+            trim_args(input, Path::new("out.mp3"), remove).join(" "),
+            format!("-hide_banner -loglevel error -i in.mp3 -af {} -y out.mp3", remove)
+        );
+    }
+}
@@ -1,24 +1,56 @@
 use reqwest;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::time::Duration;
+use std::borrow::Cow;
+use std::future::Future;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 use tokio::time::sleep;
-use chrono::Utc;
-use crate::database::{Database, UsageRecord, UserInfo};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use crate::chapters;
+use crate::config::Config;
+use crate::database::{Database, UsageRecord, UserInfo, DEFAULT_PROFILE, SOURCE_APP};
+use crate::inflight::InFlight;
+use crate::latency::PendingLatencies;
+use crate::settings::{Preprocessing, Settings};
+use crate::silence::{self, Gaps, Segment, SilenceCache, MAX_PAUSE_MS};
+use crate::throttle::Throttle;
+use crate::transcode::FfmpegMissing;
+use crate::logging;
+use crate::output_format;
+use crate::warnings;
 use std::process::Command;
 use std::io::{Write, Read};
 
-#[derive(Debug)]
+/// Longest text sent as a single request (the API limit is 4096)
+pub const SINGLE_REQUEST_LIMIT: usize = 4000;
+/// Chunk size used when long text is split (safe margin under 4096)
+pub const CHUNK_SIZE: usize = 3800;
+/// List of OpenAI TTS voice IDs, from the table in `voices`
+pub const VALID_VOICE_IDS: &[&str] = &crate::voices::VOICE_IDS;
+/// Models accepted by the speech endpoint, from the table in `models`
+pub const VALID_MODEL_IDS: &[&str] = &crate::models::MODEL_IDS;
+/// Rough speaking rate of the OpenAI voices, used for duration estimates
+pub const CHARACTERS_PER_SECOND: f64 = 15.0;
+
+#[derive(Debug, Clone)]
 pub enum TTSError {
+    /// No API key has been provided yet
+    NotConfigured,
     Authentication(String),
     RateLimit(Option<u64>),
     ValidationError(String),
     NetworkError(String),
     UnknownError(String),
+    /// Text too long for one request, with no FFmpeg to join the pieces
+    /// (see `WithoutFfmpeg`)
+    FfmpegMissing(FfmpegMissing),
 }
 
 impl std::fmt::Display for TTSError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            TTSError::NotConfigured => write!(f, "OpenAI API key is not configured"),
             TTSError::Authentication(msg) => write!(f, "Authentication error: {}", msg),
             TTSError::RateLimit(retry_after) => {
                 if let Some(seconds) = retry_after {
@@ -30,59 +62,679 @@ impl std::fmt::Display for TTSError {
             TTSError::ValidationError(msg) => write!(f, "Validation error: {}", msg),
             TTSError::NetworkError(msg) => write!(f, "Network error: {}", msg),
             TTSError::UnknownError(msg) => write!(f, "Unknown error: {}", msg),
+            TTSError::FfmpegMissing(missing) => write!(f, "{}", missing),
         }
     }
 }
 
 impl std::error::Error for TTSError {}
 
+// Process exit codes for the CLI, kept beside `TTSError` so the mapping
+// below can't drift from the variants
+pub const EXIT_SUCCESS: i32 = 0;
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_AUTH: i32 = 3;
+pub const EXIT_RATE_LIMIT: i32 = 4;
+pub const EXIT_NETWORK: i32 = 5;
+pub const EXIT_OTHER: i32 = 10;
+
+impl TTSError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            TTSError::NotConfigured => EXIT_USAGE,
+            TTSError::Authentication(_) => EXIT_AUTH,
+            TTSError::RateLimit(_) => EXIT_RATE_LIMIT,
+            TTSError::ValidationError(_) => EXIT_USAGE,
+            TTSError::NetworkError(_) => EXIT_NETWORK,
+            TTSError::UnknownError(_) => EXIT_OTHER,
+            TTSError::FfmpegMissing(_) => EXIT_OTHER,
+        }
+    }
+
+    /// Stable identifier for JSON output; matches the frontend's error types
+    pub fn kind(&self) -> &'static str {
+        match self {
+            TTSError::NotConfigured => "not_configured",
+            TTSError::Authentication(_) => "auth",
+            TTSError::RateLimit(_) => "rate_limit",
+            TTSError::ValidationError(_) => "validation",
+            TTSError::NetworkError(_) => "network",
+            TTSError::UnknownError(_) => "unknown",
+            TTSError::FfmpegMissing(_) => "ffmpeg_missing",
+        }
+    }
+}
+
+/// How the message of a `NetworkError` starts when the server couldn't be
+/// reached at all, as opposed to failing partway through a response
+pub const UNREACHABLE: &str = "Could not reach the server";
+
+impl TTSError {
+    /// Whether this failed for want of a connection, so trying again later
+    /// could work (see `offline`)
+    pub fn is_offline(&self) -> bool {
+        matches!(self, TTSError::NetworkError(msg) if msg.starts_with(UNREACHABLE))
+    }
+}
+
+/// Seconds to wait from a 429's Retry-After, which is either a number of
+/// seconds or an HTTP-date (RFC 7231 7.1.3)
+fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<u64> {
+    let value = headers.get(reqwest::header::RETRY_AFTER)?.to_str().ok()?;
+    parse_retry_after(value, Utc::now())
+}
+
+fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<u64> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse() {
+        return Some(seconds);
+    }
+    // IMF-fixdate, then the obsolete RFC 850 and asctime forms, all in GMT
+    let date = DateTime::parse_from_rfc2822(value)
+        .map(|date| date.with_timezone(&Utc))
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%A, %d-%b-%y %H:%M:%S GMT").map(|date| date.and_utc()))
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%a %b %e %H:%M:%S %Y").map(|date| date.and_utc()))
+        .ok()?;
+    // A date already past means now
+    Some((date - now).num_seconds().max(0) as u64)
+}
+
+fn send_error(e: reqwest::Error) -> TTSError {
+    if e.is_connect() || e.is_timeout() {
+        TTSError::NetworkError(format!("{}: {}", UNREACHABLE, e))
+    } else {
+        TTSError::NetworkError(format!("Failed to send request: {}", e))
+    }
+}
+
+/// The client behind a service and all its copies. A chunked generation
+/// sends dozens of requests one after another, so connections are kept
+/// open between them rather than each paying for its own TLS handshake;
+/// HTTP/2 is used where the server offers it.
+fn http_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(120))
+        .connect_timeout(Duration::from_secs(10))
+        .pool_idle_timeout(Duration::from_secs(90))
+        .pool_max_idle_per_host(4)
+        .tcp_keepalive(Duration::from_secs(30))
+        .http2_adaptive_window(true)
+        .build()
+        .unwrap()
+}
+
+/// When a request started and got its response headers. Connecting, when
+/// no kept-open connection was free, falls in the time to the headers, so
+/// a drop there after the first request is the reuse at work.
+struct RequestTiming {
+    started: Instant,
+    headers: Duration,
+}
+
+impl RequestTiming {
+    fn headers_received(started: Instant) -> Self {
+        Self { started, headers: started.elapsed() }
+    }
+
+    fn body_received(&self, url: &str, bytes: usize) {
+        tracing::debug!(
+            "POST {} read {} bytes: {} ms to headers, {} ms in all",
+            url, bytes, self.headers.as_millis(), self.started.elapsed().as_millis()
+        );
+    }
+}
+
 impl From<TTSError> for String {
     fn from(error: TTSError) -> String {
         error.to_string()
     }
 }
 
+/// Whatever turns text into audio; `TTSService` in the app, a fake in tests.
+/// The future is `Send` so jobs can run it on spawned tasks.
+pub trait SpeechBackend {
+    fn synthesize(&self, text: &str, voice_id: &str, model: &str)
+        -> impl Future<Output = Result<Vec<u8>, TTSError>> + Send;
+
+    /// `synthesize`, with usage recorded under `source` (see `database`)
+    /// where the backend records usage at all
+    fn synthesize_as(&self, source: &str, text: &str, voice_id: &str, model: &str)
+        -> impl Future<Output = Result<Vec<u8>, TTSError>> + Send {
+        let _ = source;
+        self.synthesize(text, voice_id, model)
+    }
+
+    /// `synthesize_as`, or `synthesize` without a `source`, for a request
+    /// moved from `requested_model` to `model` near the budget (see
+    /// `spend::check_budget`); the usage record says so
+    fn synthesize_downgraded(&self, source: Option<&str>, text: &str, voice_id: &str, model: &str, requested_model: &str)
+        -> impl Future<Output = Result<Vec<u8>, TTSError>> + Send {
+        let _ = (source, requested_model);
+        self.synthesize(text, voice_id, model)
+    }
+
+    /// A job's request: `synthesize_downgraded` with `gaps` of silence
+    /// between the sentences and paragraphs (see `silence::paced`).
+    /// Backends that can't add silence read the text straight through.
+    fn synthesize_paced(&self, source: Option<&str>, text: &str, voice_id: &str, model: &str, requested_model: Option<&str>, gaps: Gaps)
+        -> impl Future<Output = Result<Vec<u8>, TTSError>> + Send where Self: Sync {
+        let _ = gaps;
+        async move {
+            match (requested_model, source) {
+                (Some(requested_model), source) => self.synthesize_downgraded(source, text, voice_id, model, requested_model).await,
+                (None, Some(source)) => self.synthesize_as(source, text, voice_id, model).await,
+                (None, None) => self.synthesize(text, voice_id, model).await,
+            }
+        }
+    }
+
+    /// The texts a job sends `text` as, one request each, so each piece of
+    /// audio can be kept as it arrives (see `recovery`); all of it at once
+    /// for backends that don't split
+    fn request_texts(&self, text: &str) -> Vec<String> {
+        vec![text.to_string()]
+    }
+
+    /// The audio generated from each of `texts`, in order, as one
+    fn join(&self, texts: &[String], pieces: Vec<Vec<u8>>) -> Result<Vec<u8>, TTSError> {
+        let _ = texts;
+        Ok(pieces.concat())
+    }
+
+    /// `audio` with the dead air at its start and end cut (see `trim`);
+    /// backends that can't cut it return it as it is
+    fn trim_silence(&self, audio: Vec<u8>) -> impl Future<Output = Vec<u8>> + Send {
+        std::future::ready(audio)
+    }
+}
+
+impl SpeechBackend for TTSService {
+    async fn synthesize(&self, text: &str, voice_id: &str, model: &str) -> Result<Vec<u8>, TTSError> {
+        self.synthesize_as(&self.inner.usage_source, text, voice_id, model).await
+    }
+
+    async fn synthesize_as(&self, source: &str, text: &str, voice_id: &str, model: &str) -> Result<Vec<u8>, TTSError> {
+        self.synthesize_recorded(source, text, voice_id, model, None, Gaps::default()).await
+    }
+
+    async fn synthesize_downgraded(&self, source: Option<&str>, text: &str, voice_id: &str, model: &str, requested_model: &str) -> Result<Vec<u8>, TTSError> {
+        let source = source.unwrap_or(&self.inner.usage_source);
+        self.synthesize_recorded(source, text, voice_id, model, Some(requested_model), Gaps::default()).await
+    }
+
+    async fn synthesize_paced(&self, source: Option<&str>, text: &str, voice_id: &str, model: &str, requested_model: Option<&str>, gaps: Gaps) -> Result<Vec<u8>, TTSError> {
+        let source = source.unwrap_or(&self.inner.usage_source);
+        self.synthesize_recorded(source, text, voice_id, model, requested_model, gaps).await
+    }
+
+    fn request_texts(&self, text: &str) -> Vec<String> {
+        request_texts(&self.prepare(text), self.chunk_size())
+    }
+
+    fn join(&self, texts: &[String], pieces: Vec<Vec<u8>>) -> Result<Vec<u8>, TTSError> {
+        if pieces.len() == 1 {
+            return Ok(pieces.into_iter().next().unwrap_or_default());
+        }
+        if !self.ffmpeg_available() {
+            tracing::warn!("FFmpeg not found at {}; can't join the chunks", self.inner.ffmpeg_path);
+            return Err(TTSError::FfmpegMissing(FfmpegMissing(self.inner.ffmpeg_path.clone())));
+        }
+        let format = self.response_format();
+        let temp_files = pieces
+            .iter()
+            .map(|audio| {
+                let mut temp_file = tempfile::Builder::new().suffix(&format!(".{}", format)).tempfile()?;
+                temp_file.write_all(audio)?;
+                temp_file.flush()?;
+                Ok(temp_file)
+            })
+            .collect::<std::io::Result<Vec<_>>>()
+            .map_err(|e| TTSError::UnknownError(format!("Failed to write temp file: {}", e)))?;
+        self.concat_files(texts, &temp_files)
+    }
+
+    async fn trim_silence(&self, audio: Vec<u8>) -> Vec<u8> {
+        match crate::trim::trim_ends(&self.inner.ffmpeg_path, &audio, &self.response_format()).await {
+            Ok(Some((trimmed, trim))) => {
+                warnings::warn(warnings::silence_trimmed(trim.head.as_millis() as u64, trim.tail.as_millis() as u64));
+                trimmed
+            }
+            Ok(None) => audio,
+            Err(e) => {
+                // The audio is fine as it is, if longer than asked for
+                tracing::warn!("Failed to trim the silence; keeping it: {}", e);
+                audio
+            }
+        }
+    }
+}
+
+/// Pre-flight summary of what generating a text would cost, computed
+/// without any network or database access
+#[derive(Debug, Clone, Serialize)]
+pub struct GenerationEstimate {
+    pub character_count: usize,
+    pub chunk_count: usize,
+    pub estimated_duration_secs: f64,
+    pub estimated_cost_tts_1: f64,
+    pub estimated_cost_tts_1_hd: f64,
+}
+
+/// What the window needs to know before offering to generate anything
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ServiceStatus {
+    pub configured: bool,
+}
+
+/// A handle to the service. Clones are cheap and share everything, the
+/// key and settings included, so each command and job can hold one.
+#[derive(Clone)]
 pub struct TTSService {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
     client: reqwest::Client,
-    api_key: String,
+    // Replaced at runtime when the key changes, so the service can be shared
+    api_key: RwLock<Option<String>>,
     base_url: String,
     database: Option<Database>,
+    ffmpeg_path: String,
+    defaults: RwLock<RequestDefaults>,
+    usage_source: String,
+    /// Generations running now, so identical ones share a request
+    in_flight: Arc<InFlight>,
+    /// How long finished generations took, until their usage is recorded
+    latencies: Arc<PendingLatencies>,
+    /// Backing off after a 429 (see `throttle`)
+    throttle: Arc<Throttle>,
+}
+
+/// Per-request defaults that follow the settings while the app runs
+#[derive(Debug, Clone)]
+struct RequestDefaults {
+    speed: f32,
+    response_format: String,
+    chunk_size: usize,
+    without_ffmpeg: WithoutFfmpeg,
+    /// Sent as `OpenAI-Organization` and `OpenAI-Project` when set
+    organization: Option<String>,
+    project: Option<String>,
+    /// Cleanup before the text is counted and sent
+    preprocessing: Preprocessing,
+    /// Usage is recorded against it (see `Settings::profile`)
+    profile: String,
+    /// Mark chapters in long text joined by FFmpeg (see `chapters`)
+    chapter_markers: bool,
+}
+
+impl Default for RequestDefaults {
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            response_format: "mp3".to_string(),
+            chunk_size: CHUNK_SIZE,
+            without_ffmpeg: WithoutFfmpeg::Fail,
+            organization: None,
+            project: None,
+            preprocessing: Preprocessing::default(),
+            profile: DEFAULT_PROFILE.to_string(),
+            chapter_markers: false,
+        }
+    }
+}
+
+/// What happens to text over `SINGLE_REQUEST_LIMIT` when FFmpeg, which
+/// joins the audio of its pieces, can't be run. The audio is never cut short.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WithoutFfmpeg {
+    /// Fail with `TTSError::FfmpegMissing`
+    #[default]
+    Fail,
+    /// Hand back the pieces to play one after another (see
+    /// `generate_speech_chunked`)
+    Playlist,
 }
 
 impl TTSService {
     pub fn new(api_key: &str, base_url: &str) -> Self {
-        let client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(120))
-            .build()
-            .unwrap();
+        let client = http_client();
             
         Self {
-            client,
-            api_key: api_key.to_string(),
-            base_url: base_url.to_string(),
-            database: None,
+            inner: Arc::new(Inner {
+                client,
+                api_key: RwLock::new(Some(api_key.to_string()).filter(|key| !key.is_empty())),
+                base_url: base_url.to_string(),
+                database: None,
+                ffmpeg_path: "ffmpeg".to_string(),
+                defaults: RwLock::new(RequestDefaults::default()),
+                usage_source: SOURCE_APP.to_string(),
+                in_flight: Arc::default(),
+                latencies: Arc::default(),
+                throttle: Arc::default(),
+            }),
         }
     }
 
     pub async fn with_database(api_key: &str, base_url: &str) -> Result<Self, TTSError> {
-        let client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(120))
-            .build()
-            .unwrap();
-
         let database = Database::new().await
             .map_err(|e| TTSError::UnknownError(format!("Database error: {}", e)))?;
             
-        Ok(Self {
-            client,
-            api_key: api_key.to_string(),
-            base_url: base_url.to_string(),
-            database: Some(database),
-        })
+        let mut service = Self::new(api_key, base_url);
+        service.configure().database = Some(database);
+        Ok(service)
+    }
+
+    /// The parts of a service not yet cloned, to set it up
+    fn configure(&mut self) -> &mut Inner {
+        Arc::get_mut(&mut self.inner).expect("TTSService configured after it was cloned")
+    }
+
+    /// Build a service from the merged config: base URL, database location,
+    /// FFmpeg binary and request defaults all come from `config`. Without
+    /// an API key every request fails with `TTSError::NotConfigured`.
+    pub async fn from_config(api_key: Option<&str>, config: &Config) -> Result<Self, TTSError> {
+        let database = Database::open(&config.database_path()).await
+            .map_err(|e| TTSError::UnknownError(format!("Database error: {}", e)))?;
+
+        let mut service = Self::from_config_without_database(api_key, config);
+        service.configure().database = Some(database);
+        Ok(service)
+    }
+
+    /// `from_config` for when the database can't be opened; generation
+    /// works but nothing is tracked.
+    pub fn from_config_without_database(api_key: Option<&str>, config: &Config) -> Self {
+        let mut service = Self::new(api_key.unwrap_or_default(), &config.api_base_url);
+        let inner = service.configure();
+        inner.ffmpeg_path = config.ffmpeg_path.clone();
+        inner.defaults = RwLock::new(RequestDefaults {
+            speed: config.speed,
+            response_format: output_format::for_cli(&config.format).to_string(),
+            chunk_size: CHUNK_SIZE,
+            without_ffmpeg: WithoutFfmpeg::default(),
+            organization: config.organization.clone(),
+            project: config.project.clone(),
+            preprocessing: Preprocessing::default(),
+            profile: DEFAULT_PROFILE.to_string(),
+            chapter_markers: false,
+        });
+        service
+    }
+
+    /// Follow the window's settings for requests made from now on
+    pub fn apply_settings(&self, settings: &Settings) {
+        *self.inner.defaults.write().unwrap() = RequestDefaults {
+            speed: settings.speed,
+            response_format: output_format::for_window(&settings.response_format).to_string(),
+            chunk_size: settings.chunk_size,
+            without_ffmpeg: settings.without_ffmpeg,
+            organization: settings.organization.clone(),
+            project: settings.project.clone(),
+            preprocessing: settings.preprocessing.clone(),
+            profile: settings.profile.clone(),
+            chapter_markers: settings.chapter_markers,
+        };
+    }
+
+    pub fn database(&self) -> Option<&Database> {
+        self.inner.database.as_ref()
+    }
+
+    /// Swap the key used by subsequent requests; `None` unconfigures the service
+    pub fn set_api_key(&self, api_key: Option<&str>) {
+        let api_key = api_key.map(str::trim).filter(|key| !key.is_empty()).map(str::to_string);
+        *self.inner.api_key.write().unwrap() = api_key;
+    }
+
+    pub fn is_configured(&self) -> bool {
+        self.inner.api_key.read().unwrap().is_some()
+    }
+
+    pub fn status(&self) -> ServiceStatus {
+        ServiceStatus { configured: self.is_configured() }
+    }
+
+    /// Whether the API server answers at all; any HTTP response counts
+    pub async fn is_reachable(&self) -> bool {
+        self.inner.client
+            .head(&self.inner.base_url)
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await
+            .is_ok()
+    }
+
+    /// `text` with the API key, and anything else shaped like a key or
+    /// bearer token (see `logging::redact`), blanked out, for diagnostics
+    /// shown to the user
+    pub fn redact(&self, text: &str) -> String {
+        let text = match self.inner.api_key.read().unwrap().as_deref() {
+            Some(api_key) => text.replace(api_key, logging::REDACTED),
+            None => text.to_string(),
+        };
+        logging::redact(&text)
+    }
+
+    /// `error` with its message passed through `redact`, for errors quoting
+    /// a response body or a URL
+    fn redacted(&self, error: TTSError) -> TTSError {
+        let error = match error {
+            TTSError::Authentication(message) => TTSError::Authentication(self.redact(&message)),
+            TTSError::ValidationError(message) => TTSError::ValidationError(self.redact(&message)),
+            TTSError::NetworkError(message) => TTSError::NetworkError(self.redact(&message)),
+            TTSError::UnknownError(message) => TTSError::UnknownError(self.redact(&message)),
+            error => error,
+        };
+        debug_assert!(
+            self.inner.api_key.read().unwrap().as_deref().is_none_or(|api_key| !error.to_string().contains(api_key)),
+            "API key in an error message"
+        );
+        error
+    }
+
+    fn authorization(&self) -> Result<String, TTSError> {
+        let api_key = self.inner.api_key.read().unwrap();
+        let api_key = api_key.as_deref().ok_or(TTSError::NotConfigured)?;
+        Ok(format!("Bearer {}", api_key))
+    }
+
+    /// `request` with the key, and the organization and project to bill
+    /// when the settings name them
+    fn authorized(&self, request: reqwest::RequestBuilder) -> Result<reqwest::RequestBuilder, TTSError> {
+        let mut request = request.header("Authorization", self.authorization()?);
+        let defaults = self.inner.defaults.read().unwrap();
+        if let Some(organization) = &defaults.organization {
+            request = request.header("OpenAI-Organization", organization);
+        }
+        if let Some(project) = &defaults.project {
+            request = request.header("OpenAI-Project", project);
+        }
+        Ok(request)
+    }
+
+    /// Check the key, with the organization and project, against the API
+    /// without generating anything. A key that isn't in the organization
+    /// or project fails with an `Authentication` error saying so.
+    pub async fn validate_api_key(&self) -> Result<(), TTSError> {
+        let url = format!("{}/v1/models", self.inner.base_url);
+        let response = self.authorized(self.inner.client.get(&url))?
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await
+            .map_err(|e| self.redacted(send_error(e)))?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            return Err(self.rate_limited(response.headers()));
+        }
+        let body = response.text().await.unwrap_or_default();
+        let message = serde_json::from_str::<serde_json::Value>(&body)
+            .ok()
+            .and_then(|body| body["error"]["message"].as_str().map(str::to_string))
+            .unwrap_or(body);
+        let error = if !matches!(status, reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN) {
+            TTSError::UnknownError(format!("HTTP {}: {}", status, message))
+        } else {
+            let defaults = self.inner.defaults.read().unwrap();
+            let lowercase = message.to_lowercase();
+            match (&defaults.organization, &defaults.project) {
+                (Some(organization), _) if lowercase.contains("organization") => TTSError::Authentication(
+                    format!("The key can't be used with organization {}: {}", organization, message),
+                ),
+                (_, Some(project)) if lowercase.contains("project") => TTSError::Authentication(
+                    format!("The key can't be used with project {}: {}", project, message),
+                ),
+                _ => TTSError::Authentication(message),
+            }
+        };
+        Err(self.redacted(error))
+    }
+
+    /// Record usage from this service under `source` (see `database::SOURCE_*`)
+    pub fn with_usage_source(mut self, source: &str) -> Self {
+        self.configure().usage_source = source.to_string();
+        self
+    }
+
+    /// Join long text with the FFmpeg binary at `path`
+    pub fn with_ffmpeg_path(mut self, path: &str) -> Self {
+        self.configure().ffmpeg_path = path.to_string();
+        self
+    }
+
+    /// A copy of this service that sends `api_key` instead, for a request
+    /// made on behalf of another account. It shares the client and database
+    /// but not requests in flight, which the other key would pay for, or
+    /// the other key's rate limit.
+    pub fn with_api_key(&self, api_key: &str) -> Self {
+        let mut copy = self.copy(self.inner.defaults.read().unwrap().clone());
+        let inner = copy.configure();
+        inner.in_flight = Arc::default();
+        inner.throttle = Arc::default();
+        copy.set_api_key(Some(api_key));
+        copy
+    }
+
+    /// The service a command should use: a copy for `api_key` if one was
+    /// passed with the request, else this one, with the key from the
+    /// environment or the keychain
+    pub fn for_request(&self, api_key: Option<&str>) -> Self {
+        match key_override(api_key) {
+            Some(api_key) => self.with_api_key(api_key),
+            None => self.clone(),
+        }
+    }
+
+    /// A copy of this service that asks for `speed` instead of the default,
+    /// sharing the client, key and database
+    pub fn at_speed(&self, speed: f32) -> Self {
+        let mut defaults = self.inner.defaults.read().unwrap().clone();
+        defaults.speed = speed;
+        self.copy(defaults)
+    }
+
+    /// A service of its own with `defaults`, which changes to this one's key
+    /// and settings no longer reach; everything else is shared
+    fn copy(&self, defaults: RequestDefaults) -> Self {
+        let inner = &self.inner;
+        Self {
+            inner: Arc::new(Inner {
+                client: inner.client.clone(),
+                api_key: RwLock::new(inner.api_key.read().unwrap().clone()),
+                base_url: inner.base_url.clone(),
+                database: inner.database.clone(),
+                ffmpeg_path: inner.ffmpeg_path.clone(),
+                defaults: RwLock::new(defaults),
+                usage_source: inner.usage_source.clone(),
+                in_flight: inner.in_flight.clone(),
+                latencies: inner.latencies.clone(),
+                throttle: inner.throttle.clone(),
+            }),
+        }
+    }
+
+    /// Whether requests are being held back, and until when
+    pub fn throttle(&self) -> &Arc<Throttle> {
+        &self.inner.throttle
+    }
+
+    /// The error for a 429, after holding requests back for its Retry-After
+    fn rate_limited(&self, headers: &reqwest::header::HeaderMap) -> TTSError {
+        let seconds = retry_after(headers);
+        self.inner.throttle.rate_limited(seconds.map(Duration::from_secs));
+        TTSError::RateLimit(seconds)
+    }
+
+    /// Wait out a rate limit before a request, or fail if it has long to go
+    async fn wait_for_rate_limit(&self) -> Result<(), TTSError> {
+        self.inner.throttle.ready().await.map_err(|seconds| TTSError::RateLimit(Some(seconds)))
+    }
+
+    pub fn response_format(&self) -> String {
+        self.inner.defaults.read().unwrap().response_format.clone()
+    }
+
+    /// `text` as it is counted, checked and sent (see `Preprocessing::apply`)
+    pub fn prepare<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        self.inner.defaults.read().unwrap().preprocessing.apply(text)
+    }
+
+    fn chunk_size(&self) -> usize {
+        self.inner.defaults.read().unwrap().chunk_size
+    }
+
+    fn speech_request_body(&self, text: &str, voice_id: &str, model: &str) -> serde_json::Value {
+        let defaults = self.inner.defaults.read().unwrap().clone();
+        let mut body = json!({
+            "model": model,
+            "input": text,
+            "voice": voice_id,
+            "response_format": defaults.response_format
+        });
+        // Only send speed when it differs from the API default
+        if defaults.speed != 1.0 {
+            body["speed"] = json!(defaults.speed);
+        }
+        body
+    }
+
+    fn ffmpeg_available(&self) -> bool {
+        Command::new(&self.inner.ffmpeg_path)
+            .arg("-version")
+            .output()
+            .map(|output| output.status.success())
+            .unwrap_or(false)
+    }
+
+    /// Whether `text` comes back as separate pieces from
+    /// `generate_speech_chunked` rather than joined: it is too long for one
+    /// request, FFmpeg can't be run and the settings ask for a playlist
+    pub fn plays_as_playlist(&self, text: &str) -> bool {
+        text.len() > SINGLE_REQUEST_LIMIT
+            && self.inner.defaults.read().unwrap().without_ffmpeg == WithoutFfmpeg::Playlist
+            && !self.ffmpeg_available()
+    }
+
+    /// Estimate characters, chunks, duration and cost for `text`. This is an
+    /// associated function so callers never need an HTTP client for it.
+    pub fn estimate(text: &str) -> GenerationEstimate {
+        let character_count = text.len();
+        GenerationEstimate {
+            character_count,
+            chunk_count: chunk_count(text, CHUNK_SIZE),
+            estimated_duration_secs: estimate_duration_secs(text.chars().count()),
+            estimated_cost_tts_1: estimate_cost(character_count as i64, "tts-1"),
+            estimated_cost_tts_1_hd: estimate_cost(character_count as i64, "tts-1-hd"),
+        }
     }
 
     pub async fn validate_text(&self, text: &str) -> Result<(), TTSError> {
-        if text.trim().is_empty() {
+        if self.prepare(text).trim().is_empty() {
             return Err(TTSError::ValidationError("Text cannot be empty".to_string()));
         }
         
@@ -91,91 +743,67 @@ impl TTSService {
     }
 
     pub fn is_valid_voice(&self, voice_id: &str) -> bool {
-        // List of OpenAI TTS voice IDs
-        const VALID_VOICE_IDS: &[&str] = &[
-            "alloy",   // Neutral, versatile
-            "echo",    // Male voice
-            "fable",   // British accent
-            "onyx",    // Deep male voice
-            "nova",    // Natural female voice
-            "shimmer", // Expressive female
-        ];
-        
         let voice_id = voice_id.trim();
         !voice_id.is_empty() && VALID_VOICE_IDS.contains(&voice_id)
     }
 
     pub async fn generate_speech(&self, text: &str, voice_id: &str) -> Result<Vec<u8>, TTSError> {
+        let text = &*self.prepare(text);
         // For long text, use chunking with proper concatenation
-        if text.len() > 4000 {
-            eprintln!("[TTS] Text is {} characters, using chunked generation", text.len());
+        if text.len() > SINGLE_REQUEST_LIMIT {
+            tracing::info!("Text is {} characters, using chunked generation", text.len());
             // Check if FFmpeg is available
-            match Command::new("which").arg("ffmpeg").output() {
-                Ok(output) if output.status.success() => {
-                    eprintln!("[TTS] FFmpeg found, using concatenation");
-                    return self.generate_speech_with_ffmpeg_concat(text, voice_id).await;
-                }
-                _ => {
-                    eprintln!("[TTS] FFmpeg not found, falling back to simple truncation");
-                    // Fallback: just use the first 4000 characters
-                    let truncated = if text.len() > 4000 {
-                        &text[..4000]
-                    } else {
-                        text
-                    };
-                    eprintln!("[TTS] WARNING: Text truncated to {} characters", truncated.len());
-                }
+            if !self.ffmpeg_available() {
+                tracing::warn!("FFmpeg not found at {}; can't join the chunks", self.inner.ffmpeg_path);
+                return Err(TTSError::FfmpegMissing(FfmpegMissing(self.inner.ffmpeg_path.clone())));
             }
+            tracing::debug!("FFmpeg found, using concatenation");
+            return self.generate_speech_with_ffmpeg_concat(text, voice_id, "tts-1-hd").await;
         }
         
-        let url = format!("{}/v1/audio/speech", self.base_url);
+        let url = format!("{}/v1/audio/speech", self.inner.base_url);
         
-        let request_body = json!({
-            "model": "tts-1-hd",
-            "input": text,
-            "voice": voice_id,
-            "response_format": "mp3"
-        });
+        let request_body = self.speech_request_body(text, voice_id, "tts-1-hd");
 
-        let response = self.client
-            .post(&url)
-            .header("Authorization", &format!("Bearer {}", self.api_key))
+        self.wait_for_rate_limit().await?;
+        let started = Instant::now();
+        let response = self.authorized(self.inner.client.post(&url))?
             .header("Content-Type", "application/json")
             .json(&request_body)
             .send()
             .await
-            .map_err(|e| TTSError::NetworkError(e.to_string()))?;
+            .map_err(|e| self.redacted(send_error(e)))?;
+        let timing = RequestTiming::headers_received(started);
+        tracing::debug!("POST {} -> {} in {} ms", url, response.status(), timing.headers.as_millis());
 
         match response.status() {
             reqwest::StatusCode::OK => {
+                self.inner.throttle.resume();
                 let audio_data = response.bytes().await
-                    .map_err(|e| TTSError::NetworkError(e.to_string()))?;
+                    .map_err(|e| self.redacted(TTSError::NetworkError(e.to_string())))?;
+                timing.body_received(&url, audio_data.len());
                 Ok(audio_data.to_vec())
             }
             reqwest::StatusCode::UNAUTHORIZED => {
                 let error_text = response.text().await.unwrap_or_default();
-                Err(TTSError::Authentication(error_text))
+                Err(self.redacted(TTSError::Authentication(error_text)))
             }
             reqwest::StatusCode::TOO_MANY_REQUESTS => {
-                let retry_after = response.headers()
-                    .get("retry-after")
-                    .and_then(|v| v.to_str().ok())
-                    .and_then(|s| s.parse().ok());
-                Err(TTSError::RateLimit(retry_after))
+                Err(self.rate_limited(response.headers()))
             }
             status => {
                 let error_text = response.text().await.unwrap_or_default();
-                Err(TTSError::UnknownError(format!("HTTP {}: {}", status, error_text)))
+                Err(self.redacted(TTSError::UnknownError(format!("HTTP {}: {}", status, error_text))))
             }
         }
     }
 
-    // Generate speech for long text using proper FFmpeg concatenation
-    async fn generate_speech_with_ffmpeg_concat(&self, text: &str, voice_id: &str) -> Result<Vec<u8>, TTSError> {
-        const MAX_CHUNK_SIZE: usize = 3800; // Safe margin under 4096
-        
-        let chunks = self.split_text_semantically(text, MAX_CHUNK_SIZE);
-        eprintln!("Split text into {} chunks", chunks.len());
+    // Generate speech for long text using proper FFmpeg concatenation.
+    // Usage is left to the caller to record.
+    async fn generate_speech_with_ffmpeg_concat(&self, text: &str, voice_id: &str, model: &str) -> Result<Vec<u8>, TTSError> {
+        let generation_started = Instant::now();
+        let chunks = request_texts(text, self.chunk_size());
+        tracing::info!("Split text into {} chunks", chunks.len());
         
         if chunks.is_empty() {
             return Err(TTSError::ValidationError("No valid text chunks found".to_string()));
@@ -185,8 +813,8 @@ impl TTSService {
         let mut temp_files = Vec::new();
         
         for (i, chunk) in chunks.iter().enumerate() {
-            eprintln!("[TTS] Generating audio for chunk {} of {} ({} chars)", i + 1, chunks.len(), chunk.len());
-            eprintln!("[TTS] Chunk {} preview: {}...", i + 1, &chunk.chars().take(50).collect::<String>());
+            tracing::info!("Generating audio for chunk {} of {} ({} chars)", i + 1, chunks.len(), chunk.len());
+            tracing::debug!("Chunk {} preview: {}...", i + 1, &chunk.chars().take(50).collect::<String>());
             
             // Add delay between API calls to avoid rate limiting
             if i > 0 {
@@ -194,50 +822,52 @@ impl TTSService {
             }
             
             // Generate audio for this chunk
-            let url = format!("{}/v1/audio/speech", self.base_url);
-            let request_body = json!({
-                "model": "tts-1-hd",
-                "input": chunk,
-                "voice": voice_id,
-                "response_format": "mp3"
-            });
+            let url = format!("{}/v1/audio/speech", self.inner.base_url);
+            let request_body = self.speech_request_body(chunk, voice_id, model);
 
-            let response = self.client
-                .post(&url)
-                .header("Authorization", &format!("Bearer {}", self.api_key))
+            self.wait_for_rate_limit().await?;
+            let started = Instant::now();
+            let response = self.authorized(self.inner.client.post(&url))?
                 .header("Content-Type", "application/json")
                 .json(&request_body)
                 .send()
                 .await
                 .map_err(|e| {
-                    eprintln!("[TTS] Failed to send request for chunk {}: {}", i + 1, e);
-                    TTSError::NetworkError(format!("Failed to send request: {}", e))
+                    tracing::error!("Failed to send request for chunk {}: {}", i + 1, e);
+                    self.redacted(send_error(e))
                 })?;
 
             let status = response.status();
-            eprintln!("[TTS] Chunk {} response status: {}", i + 1, status);
+            let timing = RequestTiming::headers_received(started);
+            tracing::debug!("Chunk {} POST {} -> {} in {} ms", i + 1, url, status, timing.headers.as_millis());
+            if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+                tracing::error!("Rate limited on chunk {}", i + 1);
+                return Err(self.rate_limited(response.headers()));
+            }
             
             // Read the response body as bytes first
             let body_bytes = response.bytes().await
                 .map_err(|e| {
-                    eprintln!("[TTS] Failed to read response body for chunk {}: {}", i + 1, e);
-                    TTSError::NetworkError(format!("Failed to read response: {}", e))
+                    tracing::error!("Failed to read response body for chunk {}: {}", i + 1, e);
+                    self.redacted(TTSError::NetworkError(format!("Failed to read response: {}", e)))
                 })?;
             
             // Check if we got an error response
             if !status.is_success() {
                 let error_text = String::from_utf8_lossy(&body_bytes);
-                eprintln!("[TTS] API error for chunk {}: HTTP {} - {}", i + 1, status, error_text);
-                return Err(TTSError::UnknownError(format!("HTTP {}: {}", status, error_text)));
+                tracing::error!("API error for chunk {}: HTTP {} - {}", i + 1, status, error_text);
+                return Err(self.redacted(TTSError::UnknownError(format!("HTTP {}: {}", status, error_text))));
             }
             
             let audio_data = body_bytes;
+            timing.body_received(&url, audio_data.len());
+            self.inner.throttle.resume();
             
-            eprintln!("[TTS] Chunk {} generated {} bytes", i + 1, audio_data.len());
+            tracing::debug!("Chunk {} generated {} bytes", i + 1, audio_data.len());
             
             // Write to temp file with .mp3 extension
             let mut temp_file = tempfile::Builder::new()
-                .suffix(".mp3")
+                .suffix(&format!(".{}", self.response_format()))
                 .tempfile()
                 .map_err(|e| TTSError::NetworkError(format!("Failed to create temp file: {}", e)))?;
             temp_file.write_all(&audio_data)
@@ -248,6 +878,15 @@ impl TTSService {
             temp_files.push(temp_file);
         }
         
+        let buffer = self.concat_files(&chunks, &temp_files)?;
+
+        self.inner.latencies.finished(text, voice_id, model, generation_started, chunks.len());
+        Ok(buffer)
+    }
+    
+    /// The audio in `temp_files`, generated from `chunks`, as one file,
+    /// with chapter markers if the settings ask for them
+    fn concat_files(&self, chunks: &[String], temp_files: &[tempfile::NamedTempFile]) -> Result<Vec<u8>, TTSError> {
         // If only one chunk, return it directly
         if temp_files.len() == 1 {
             let mut buffer = Vec::new();
@@ -258,18 +897,18 @@ impl TTSService {
         }
         
         // Concatenate using ffmpeg
-        eprintln!("[TTS] Concatenating {} audio files with ffmpeg", temp_files.len());
+        tracing::info!("Concatenating {} audio files with ffmpeg", temp_files.len());
         
         // Create a list file for ffmpeg concat with .txt extension
         let mut list_file = tempfile::Builder::new()
             .suffix(".txt")
             .tempfile()
             .map_err(|e| {
-                eprintln!("[TTS] Failed to create list file: {}", e);
+                tracing::error!("Failed to create list file: {}", e);
                 TTSError::NetworkError(format!("Failed to create list file: {}", e))
             })?;
         
-        for temp_file in &temp_files {
+        for temp_file in temp_files {
             writeln!(list_file, "file '{}'" , temp_file.path().display())
                 .map_err(|e| TTSError::NetworkError(format!("Failed to write list file: {}", e)))?;
         }
@@ -278,40 +917,65 @@ impl TTSService {
         
         // Create output temp file with .mp3 extension
         let output_file = tempfile::Builder::new()
-            .suffix(".mp3")
+            .suffix(&format!(".{}", self.response_format()))
             .tempfile()
             .map_err(|e| TTSError::NetworkError(format!("Failed to create output file: {}", e)))?;
         
         // Log the list file for debugging
-        eprintln!("[TTS] List file path: {}", list_file.path().display());
-        eprintln!("[TTS] Output file path: {}", output_file.path().display());
+        tracing::debug!("List file path: {}", list_file.path().display());
+        tracing::debug!("Output file path: {}", output_file.path().display());
         
+        // Chapter markers go in as a second input, an FFmpeg metadata file
+        let metadata_file = if self.inner.defaults.read().unwrap().chapter_markers {
+            let pieces: Vec<(&str, Duration)> = chunks
+                .iter()
+                .zip(temp_files)
+                .map(|(chunk, temp_file)| {
+                    let duration = crate::file_manager::file_duration(temp_file.path()).unwrap_or_else(|| {
+                        Duration::from_secs_f64(estimate_duration_secs(chunk.chars().count()))
+                    });
+                    (chunk.as_str(), duration)
+                })
+                .collect();
+            let chapters = chapters::from_pieces(&pieces);
+            tracing::debug!("Adding {} chapter markers", chapters.len());
+            let mut metadata_file = tempfile::Builder::new()
+                .suffix(".txt")
+                .tempfile()
+                .map_err(|e| TTSError::NetworkError(format!("Failed to create metadata file: {}", e)))?;
+            metadata_file.write_all(chapters::ffmetadata(&chapters).as_bytes())
+                .and_then(|_| metadata_file.flush())
+                .map_err(|e| TTSError::NetworkError(format!("Failed to write metadata file: {}", e)))?;
+            Some(metadata_file)
+        } else {
+            None
+        };
+
         // Run ffmpeg to concatenate
-        eprintln!("[TTS] Running ffmpeg concat command");
-        let output = Command::new("ffmpeg")
-            .args(&[
-                "-f", "concat",
-                "-safe", "0",
-                "-i", list_file.path().to_str().unwrap(),
-                "-c", "copy",
-                "-y",
-                output_file.path().to_str().unwrap()
-            ])
+        tracing::debug!("Running ffmpeg concat command");
+        let mut args = vec!["-f", "concat", "-safe", "0", "-i", list_file.path().to_str().unwrap()];
+        if let Some(metadata_file) = &metadata_file {
+            args.extend(["-i", metadata_file.path().to_str().unwrap()]);
+            args.extend(["-map", "0:a", "-map_metadata", "1", "-map_chapters", "1"]);
+        }
+        args.extend(["-c", "copy", "-y", output_file.path().to_str().unwrap()]);
+        let output = Command::new(&self.inner.ffmpeg_path)
+            .args(&args)
             .output()
             .map_err(|e| {
-                eprintln!("[TTS] Failed to run ffmpeg: {}", e);
+                tracing::error!("Failed to run ffmpeg: {}", e);
                 TTSError::NetworkError(format!("Failed to run ffmpeg: {}", e))
             })?;
         
         if !output.status.success() {
             let stderr = String::from_utf8_lossy(&output.stderr);
             let stdout = String::from_utf8_lossy(&output.stdout);
-            eprintln!("[TTS] FFmpeg failed with stderr: {}", stderr);
-            eprintln!("[TTS] FFmpeg stdout: {}", stdout);
+            tracing::error!("FFmpeg failed with stderr: {}", stderr);
+            tracing::debug!("FFmpeg stdout: {}", stdout);
             return Err(TTSError::NetworkError(format!("ffmpeg failed: {}", stderr)));
         }
         
-        eprintln!("[TTS] FFmpeg concatenation successful");
+        tracing::debug!("FFmpeg concatenation successful");
         
         // Read the concatenated file
         let mut buffer = Vec::new();
@@ -319,83 +983,146 @@ impl TTSService {
             .and_then(|mut f| std::io::Read::read_to_end(&mut f, &mut buffer))
             .map_err(|e| TTSError::NetworkError(format!("Failed to read output file: {}", e)))?;
         
-        eprintln!("[TTS] Successfully concatenated audio ({} bytes)", buffer.len());
-        
-        // Track usage for all chunks
-        let _ = self.track_usage(text, voice_id, "tts-1-hd", true, None).await;
-        
+        tracing::info!("Successfully concatenated audio ({} bytes)", buffer.len());
         Ok(buffer)
     }
-    
+
+    /// `generate_speech_with_model`, except that while an identical request
+    /// (same text, voice, model, speed and format) is running this waits for
+    /// it and shares its audio rather than paying for it again. Whether it
+    /// did.
+    pub async fn generate_speech_shared(&self, text: &str, voice_id: &str, model: &str) -> (Result<Vec<u8>, TTSError>, bool) {
+        let text = &*self.prepare(text);
+        let key = {
+            let defaults = self.inner.defaults.read().unwrap();
+            crate::watch::hash(&format!("{}\0{}\0{}\0{}\0{}", text, voice_id, model, defaults.speed, defaults.response_format))
+        };
+        let (result, shared) = self.inner.in_flight.run(key, || self.generate_speech_with_model(text, voice_id, model)).await;
+        if shared {
+            warnings::warn(warnings::shared());
+        }
+        (result, shared)
+    }
+
     pub async fn generate_speech_with_model(&self, text: &str, voice_id: &str, model: &str) -> Result<Vec<u8>, TTSError> {
-        const MAX_CHUNK_SIZE: usize = 4000; // Leave buffer for safety
-        
-        if text.len() <= MAX_CHUNK_SIZE {
+        let text = &*self.prepare(text);
+        if text.len() <= SINGLE_REQUEST_LIMIT {
             // Text fits in single request
-            self.generate_speech_with_model_single(text, voice_id, model).await
+            let started = Instant::now();
+            let audio = self.generate_speech_with_model_single(text, voice_id, model).await?;
+            self.inner.latencies.finished(text, voice_id, model, started, 1);
+            Ok(audio)
         } else {
             // Use FFmpeg concatenation for long text
-            eprintln!("[TTS] Text is {} characters, using FFmpeg concatenation", text.len());
-            // Check if FFmpeg is available
-            match Command::new("which").arg("ffmpeg").output() {
-                Ok(output) if output.status.success() => {
-                    eprintln!("[TTS] FFmpeg found, using concatenation");
-                    self.generate_speech_with_ffmpeg_concat(text, voice_id).await
-                }
-                _ => {
-                    eprintln!("[TTS] FFmpeg not found, using fallback single chunk");
-                    // Fallback: just use the first 4000 characters with the given model
-                    let truncated = if text.len() > 4000 {
-                        &text[..4000]
-                    } else {
-                        text
-                    };
-                    eprintln!("[TTS] WARNING: Text truncated to {} characters", truncated.len());
-                    self.generate_speech_with_model_single(truncated, voice_id, model).await
-                }
+            tracing::info!("Text is {} characters, using FFmpeg concatenation", text.len());
+            if !self.ffmpeg_available() {
+                tracing::warn!("FFmpeg not found at {}; can't join the chunks", self.inner.ffmpeg_path);
+                return Err(TTSError::FfmpegMissing(FfmpegMissing(self.inner.ffmpeg_path.clone())));
             }
+            tracing::debug!("FFmpeg found, using concatenation");
+            self.generate_speech_with_ffmpeg_concat(text, voice_id, model).await
         }
     }
     
+    /// Generate `segments` separately and join them with their pauses, so
+    /// the result keeps the timing of the source (e.g. subtitle cues).
+    /// Without ffmpeg the text is generated in one go and the pauses are lost.
+    pub async fn generate_speech_with_pauses(&self, segments: &[Segment], voice_id: &str, model: &str) -> Result<Vec<u8>, TTSError> {
+        let segments = segments.iter().map(|segment| Segment { text: self.prepare(&segment.text).into_owned(), ..segment.clone() });
+        // Neighbours with no gap between them go in one request
+        let mut groups: Vec<Segment> = Vec::new();
+        for segment in segments.filter(|segment| !segment.text.trim().is_empty()) {
+            match groups.last_mut() {
+                Some(last) if last.pause_after_ms == 0 && last.text.len() + segment.text.len() < self.chunk_size() => {
+                    last.text.push(' ');
+                    last.text.push_str(&segment.text);
+                    last.pause_after_ms = segment.pause_after_ms;
+                }
+                _ => groups.push(segment),
+            }
+        }
+        if groups.is_empty() {
+            return Err(TTSError::ValidationError("No text to generate".to_string()));
+        }
+        if groups.len() == 1 || !self.ffmpeg_available() {
+            if groups.len() > 1 {
+                warnings::warn(warnings::pauses_dropped());
+            }
+            let text = groups.iter().map(|group| group.text.as_str()).collect::<Vec<_>>().join(" ");
+            return self.generate_speech_with_model(&text, voice_id, model).await;
+        }
+
+        tracing::info!("Generating {} segments with pauses", groups.len());
+        let work_dir = tempfile::tempdir()
+            .map_err(|e| TTSError::UnknownError(format!("Failed to create temp dir: {}", e)))?;
+        let silence = SilenceCache::new();
+        let format = self.response_format();
+        let mut inputs = Vec::new();
+        let last = groups.len() - 1;
+        for (i, group) in groups.iter().enumerate() {
+            if i > 0 {
+                sleep(Duration::from_millis(200)).await;
+            }
+            let audio = self.generate_speech_with_model(&group.text, voice_id, model).await?;
+            let path = work_dir.path().join(format!("segment-{}.{}", i, format));
+            std::fs::write(&path, audio)
+                .map_err(|e| TTSError::UnknownError(format!("Failed to write temp file: {}", e)))?;
+            inputs.push(path);
+            if i < last && group.pause_after_ms > 0 {
+                let gap = silence
+                    .segment(group.pause_after_ms.min(MAX_PAUSE_MS))
+                    .map_err(|e| TTSError::UnknownError(format!("Failed to write silence: {}", e)))?;
+                inputs.push(gap);
+            }
+        }
+
+        let output_path = work_dir.path().join(format!("joined.{}", format));
+        let output = Command::new(&self.inner.ffmpeg_path)
+            .args(silence::concat_args(&inputs, &output_path))
+            .output()
+            .map_err(|e| TTSError::UnknownError(format!("Failed to run ffmpeg: {}", e)))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            tracing::error!("FFmpeg failed with stderr: {}", stderr);
+            return Err(TTSError::UnknownError(format!("ffmpeg failed: {}", stderr)));
+        }
+        std::fs::read(&output_path).map_err(|e| TTSError::UnknownError(format!("Failed to read output file: {}", e)))
+    }
+
     async fn generate_speech_with_model_single(&self, text: &str, voice_id: &str, model: &str) -> Result<Vec<u8>, TTSError> {
-        let url = format!("{}/v1/audio/speech", self.base_url);
+        let url = format!("{}/v1/audio/speech", self.inner.base_url);
         
-        let request_body = json!({
-            "model": model,
-            "input": text,
-            "voice": voice_id,
-            "response_format": "mp3"
-        });
+        let request_body = self.speech_request_body(text, voice_id, model);
 
-        let response = self.client
-            .post(&url)
-            .header("Authorization", &format!("Bearer {}", self.api_key))
+        self.wait_for_rate_limit().await?;
+        let started = Instant::now();
+        let response = self.authorized(self.inner.client.post(&url))?
             .header("Content-Type", "application/json")
             .json(&request_body)
             .send()
             .await
-            .map_err(|e| TTSError::NetworkError(e.to_string()))?;
+            .map_err(|e| self.redacted(send_error(e)))?;
+        let timing = RequestTiming::headers_received(started);
+        tracing::debug!("POST {} -> {} in {} ms", url, response.status(), timing.headers.as_millis());
 
         match response.status() {
             reqwest::StatusCode::OK => {
+                self.inner.throttle.resume();
                 let audio_data = response.bytes().await
-                    .map_err(|e| TTSError::NetworkError(e.to_string()))?;
+                    .map_err(|e| self.redacted(TTSError::NetworkError(e.to_string())))?;
+                timing.body_received(&url, audio_data.len());
                 Ok(audio_data.to_vec())
             }
             reqwest::StatusCode::UNAUTHORIZED => {
                 let error_text = response.text().await.unwrap_or_default();
-                Err(TTSError::Authentication(error_text))
+                Err(self.redacted(TTSError::Authentication(error_text)))
             }
             reqwest::StatusCode::TOO_MANY_REQUESTS => {
-                let retry_after = response.headers()
-                    .get("retry-after")
-                    .and_then(|v| v.to_str().ok())
-                    .and_then(|s| s.parse().ok());
-                Err(TTSError::RateLimit(retry_after))
+                Err(self.rate_limited(response.headers()))
             }
             status => {
                 let error_text = response.text().await.unwrap_or_default();
-                Err(TTSError::UnknownError(format!("HTTP {}: {}", status, error_text)))
+                Err(self.redacted(TTSError::UnknownError(format!("HTTP {}: {}", status, error_text))))
             }
         }
     }
@@ -410,10 +1137,11 @@ impl TTSService {
                 Err(TTSError::RateLimit(_)) => return Err(TTSError::RateLimit(None)), // Don't retry rate limits
                 Err(TTSError::Authentication(_)) => return Err(TTSError::Authentication("API key invalid".to_string())), // Don't retry auth errors
                 Err(err) if attempt == MAX_RETRIES - 1 => return Err(err), // Last attempt
-                Err(_) => {
+                Err(err) => {
+                    warnings::warn(warnings::retried(attempt + 2, &err.to_string()));
                     // Exponential backoff
                     let delay = Duration::from_millis(BASE_DELAY_MS * 2_u64.pow(attempt));
-                    sleep(delay).await;
+                    self.inner.throttle.pause(delay).await;
                 }
             }
         }
@@ -424,15 +1152,28 @@ impl TTSService {
     pub async fn get_user_info(&self) -> Result<UserInfo, TTSError> {
         // OpenAI TTS is pay-per-use, no subscription tiers or limits
         // Get local usage data from database instead
-        let character_used = if let Some(db) = &self.database {
-            match db.get_usage_stats(30).await { // Get last 30 days
+        let character_used = if let Some(db) = &self.inner.database {
+            match db.get_usage_stats(30, None).await { // Get last 30 days
                 Ok(stats) => stats.total_characters,
                 Err(_) => 0,
             }
         } else {
             0
         };
+        self.user_info(character_used).await
+    }
+
+    /// `get_user_info` counting only what this service's key paid for
+    pub async fn get_account_info(&self) -> Result<UserInfo, TTSError> {
+        let fingerprint = self.inner.api_key.read().unwrap().as_deref().map(crate::keychain::fingerprint);
+        let character_used = match (&self.inner.database, fingerprint) {
+            (Some(db), Some(fingerprint)) => db.characters_by_key(&fingerprint, 30).await.unwrap_or(0),
+            _ => 0,
+        };
+        self.user_info(character_used).await
+    }
 
+    async fn user_info(&self, character_used: i64) -> Result<UserInfo, TTSError> {
         let user_info = UserInfo {
             subscription_tier: "Pay-per-use".to_string(),
             character_limit: -1, // Unlimited
@@ -443,7 +1184,7 @@ impl TTSService {
         };
 
         // Cache the user info
-        if let Some(db) = &self.database {
+        if let Some(db) = &self.inner.database {
             let _ = db.cache_user_info(&user_info).await;
         }
 
@@ -451,81 +1192,103 @@ impl TTSService {
     }
 
     pub async fn track_usage(&self, text: &str, voice_id: &str, model_id: &str, success: bool, error_message: Option<String>) -> Result<(), TTSError> {
-        if let Some(db) = &self.database {
-            let record = UsageRecord {
-                id: None,
-                timestamp: Utc::now(),
-                text: if text.len() > 100 { 
-                    // Store only first 100 chars to save space
-                    format!("{}...", &text[..97])
-                } else { 
-                    text.to_string() 
-                },
-                character_count: text.len() as i32,
-                voice_id: voice_id.to_string(),
-                model_id: model_id.to_string(),
-                success,
-                error_message,
-            };
+        self.track_usage_as(&self.inner.usage_source, text, voice_id, model_id, success, error_message).await
+    }
+
+    /// `track_usage` under an explicit source, for a service shared by
+    /// several callers (the window and deep links)
+    pub async fn track_usage_as(&self, source: &str, text: &str, voice_id: &str, model_id: &str, success: bool, error_message: Option<String>) -> Result<(), TTSError> {
+        self.record(self.usage_record(source, &self.prepare(text), voice_id, model_id, success, error_message)).await
+    }
+
+    /// `track_usage` for generating history record `original_id` again,
+    /// linking the new record to it, and to the model it was moved from
+    /// near the budget if it was
+    pub async fn track_regeneration(&self, original_id: i64, text: &str, voice_id: &str, model_id: &str, downgraded_from: Option<&str>) -> Result<(), TTSError> {
+        let mut record = self.usage_record(&self.inner.usage_source, &self.prepare(text), voice_id, model_id, true, None);
+        record.regenerated_from = Some(original_id);
+        record.downgraded_from = downgraded_from.map(str::to_string);
+        self.record(record).await
+    }
+
+    /// Generate, sharing an identical request already running, and record
+    /// the usage unless that request does
+    async fn synthesize_recorded(&self, source: &str, text: &str, voice_id: &str, model: &str, downgraded_from: Option<&str>, gaps: Gaps) -> Result<Vec<u8>, TTSError> {
+        let text = &*self.prepare(text);
+        let (result, shared) = if gaps.is_none() {
+            self.generate_speech_shared(text, voice_id, model).await
+        } else {
+            (self.generate_speech_with_pauses(&silence::paced(text, gaps), voice_id, model).await, false)
+        };
+        if shared {
+            return result;
+        }
+        let error_message = result.as_ref().err().map(|e| e.to_string());
+        let mut record = self.usage_record(source, text, voice_id, model, result.is_ok(), error_message);
+        record.downgraded_from = downgraded_from.map(str::to_string);
+        let _ = self.record(record).await;
+        result
+    }
 
+    /// `track_usage_as` for a successful request moved from
+    /// `requested_model` to `model_id` near the budget
+    pub async fn track_downgraded(&self, source: &str, text: &str, voice_id: &str, model_id: &str, requested_model: &str) -> Result<(), TTSError> {
+        let mut record = self.usage_record(source, &self.prepare(text), voice_id, model_id, true, None);
+        record.downgraded_from = Some(requested_model.to_string());
+        self.record(record).await
+    }
+
+    /// The record of one request, with how long it took if it was timed
+    fn usage_record(&self, source: &str, text: &str, voice_id: &str, model_id: &str, success: bool, error_message: Option<String>) -> UsageRecord {
+        let mut record = usage_record(source, text, voice_id, model_id, success, error_message);
+        if let Some(latency) = self.inner.latencies.take(text, voice_id, model_id) {
+            record.latency_ms = Some(latency.latency_ms);
+            record.chunk_count = Some(latency.chunk_count);
+        }
+        record
+    }
+
+    async fn record(&self, mut record: UsageRecord) -> Result<(), TTSError> {
+        record.key_fingerprint = self.inner.api_key.read().unwrap().as_deref().map(crate::keychain::fingerprint);
+        record.app_version = Some(env!("CARGO_PKG_VERSION").to_string());
+        record.profile = self.inner.defaults.read().unwrap().profile.clone();
+        if let Some(db) = &self.inner.database {
             db.record_usage(&record).await
                 .map_err(|e| TTSError::UnknownError(format!("Database error: {}", e)))?;
         }
         Ok(())
     }
 
-    pub async fn generate_speech_chunked(&self, text: &str, voice_id: &str) -> Result<Vec<Vec<u8>>, TTSError> {
-        const MAX_CHUNK_SIZE: usize = 3800; // Safe margin under 4096
+    /// `text` as separate pieces of audio, one request each, to play one
+    /// after another. Usage is left to the caller to record.
+    pub async fn generate_speech_chunked(&self, text: &str, voice_id: &str, model: &str) -> Result<Vec<Vec<u8>>, TTSError> {
+        let text = &*self.prepare(text);
+        tracing::debug!("generate_speech_chunked called with {} characters", text.len());
+        let started = Instant::now();
         
-        eprintln!("generate_speech_chunked called with {} characters", text.len());
+        let chunks = request_texts(text, self.chunk_size());
+        tracing::info!("Split text into {} chunks", chunks.len());
+        let mut audio_chunks = Vec::new();
         
-        if text.len() <= MAX_CHUNK_SIZE {
-            // Single chunk - return as single-element vector
-            eprintln!("Text fits in single chunk");
-            let audio = self.generate_speech_tracked_single(text, voice_id).await?;
-            Ok(vec![audio])
-        } else {
-            // Multiple chunks needed
-            let chunks = self.split_text_semantically(text, MAX_CHUNK_SIZE);
-            eprintln!("Split text into {} chunks", chunks.len());
-            let mut audio_chunks = Vec::new();
-            
-            for (i, chunk) in chunks.iter().enumerate() {
-                eprintln!("Processing chunk {} of {} ({} chars)", i + 1, chunks.len(), chunk.len());
-                // Add delay between API calls to avoid rate limiting
-                if i > 0 {
-                    sleep(Duration::from_millis(200)).await;
-                }
-                
-                let audio = self.generate_speech_tracked_single(chunk, voice_id).await?;
-                eprintln!("Chunk {} generated {} bytes of audio", i + 1, audio.len());
-                audio_chunks.push(audio);
+        for (i, chunk) in chunks.iter().enumerate() {
+            tracing::info!("Processing chunk {} of {} ({} chars)", i + 1, chunks.len(), chunk.len());
+            // Add delay between API calls to avoid rate limiting
+            if i > 0 {
+                sleep(Duration::from_millis(200)).await;
             }
             
-            Ok(audio_chunks)
+            let audio = self.generate_speech_with_model_single(chunk, voice_id, model).await?;
+            tracing::debug!("Chunk {} generated {} bytes of audio", i + 1, audio.len());
+            audio_chunks.push(audio);
         }
-    }
-    
-    async fn generate_speech_tracked_single(&self, text: &str, voice_id: &str) -> Result<Vec<u8>, TTSError> {
-        let model_id = "tts-1-hd"; // OpenAI high-quality model
+        self.inner.latencies.finished(text, voice_id, model, started, chunks.len());
         
-        // Generate speech for a single chunk
-        match self.generate_speech(text, voice_id).await {
-            Ok(audio_data) => {
-                self.track_usage(text, voice_id, model_id, true, None).await?;
-                Ok(audio_data)
-            }
-            Err(error) => {
-                let error_msg = error.to_string();
-                self.track_usage(text, voice_id, model_id, false, Some(error_msg.clone())).await?;
-                Err(error)
-            }
-        }
+        Ok(audio_chunks)
     }
 
-    pub async fn get_usage_stats(&self, days: i32) -> Result<crate::database::UsageStats, TTSError> {
-        if let Some(db) = &self.database {
-            db.get_usage_stats(days).await
+    pub async fn get_usage_stats(&self, days: i32, profile: Option<&str>) -> Result<crate::database::UsageStats, TTSError> {
+        if let Some(db) = &self.inner.database {
+            db.get_usage_stats(days, profile).await
                 .map_err(|e| TTSError::UnknownError(format!("Database error: {}", e)))
         } else {
             Err(TTSError::UnknownError("Database not available".to_string()))
@@ -533,7 +1296,7 @@ impl TTSService {
     }
 
     pub async fn get_usage_history(&self, limit: i32, days: Option<i32>) -> Result<Vec<UsageRecord>, TTSError> {
-        if let Some(db) = &self.database {
+        if let Some(db) = &self.inner.database {
             db.get_usage_records(limit, days).await
                 .map_err(|e| TTSError::UnknownError(format!("Database error: {}", e)))
         } else {
@@ -546,86 +1309,184 @@ impl TTSService {
     }
 
     pub fn estimate_usage_cost(&self, character_count: i32, model: &str) -> f64 {
-        // OpenAI TTS pricing (pay-per-use)
-        match model {
-            "tts-1" => character_count as f64 * 0.000015,    // $15 per 1M characters
-            "tts-1-hd" => character_count as f64 * 0.00003,  // $30 per 1M characters
-            _ => character_count as f64 * 0.00003, // Default to HD pricing
+        estimate_cost(character_count as i64, model)
+    }
+
+}
+
+/// The key a request passed, if any; blank counts as none
+pub fn key_override(api_key: Option<&str>) -> Option<&str> {
+    api_key.map(str::trim).filter(|api_key| !api_key.is_empty())
+}
+
+/// The history entry for one request; long text is stored as a preview
+fn usage_record(source: &str, text: &str, voice_id: &str, model_id: &str, success: bool, error_message: Option<String>) -> UsageRecord {
+    UsageRecord {
+        id: None,
+        timestamp: Utc::now(),
+        text: if text.len() > 100 { 
+            // Store only first 100 chars to save space
+            format!("{}...", &text[..97])
+        } else { 
+            text.to_string() 
+        },
+        character_count: text.len() as i32,
+        voice_id: voice_id.to_string(),
+        model_id: model_id.to_string(),
+        success,
+        error_message,
+        source: source.to_string(),
+        regenerated_from: None,
+        downgraded_from: None,
+        key_fingerprint: None,
+        app_version: None,
+        profile: DEFAULT_PROFILE.to_string(),
+        latency_ms: None,
+        chunk_count: None,
+    }
+}
+
+/// How many requests generating `text` takes: one up to
+/// `SINGLE_REQUEST_LIMIT`, otherwise a chunk of at most `chunk_size` each
+pub fn chunk_count(text: &str, chunk_size: usize) -> usize {
+    if text.trim().is_empty() {
+        0
+    } else {
+        request_texts(text, chunk_size).len()
+    }
+}
+
+/// The text sent in each request generating `text` takes: all of it up to
+/// `SINGLE_REQUEST_LIMIT`, otherwise chunks of at most `chunk_size`
+pub fn request_texts(text: &str, chunk_size: usize) -> Vec<String> {
+    if text.len() <= SINGLE_REQUEST_LIMIT {
+        vec![text.to_string()]
+    } else {
+        split_text_semantically(text, chunk_size)
+    }
+}
+
+/// Seconds of speech for `characters` characters
+pub fn estimate_duration_secs(characters: usize) -> f64 {
+    characters as f64 / CHARACTERS_PER_SECOND
+}
+
+/// Where a sentence ends, as the chunker sees it. The full-width marks of
+/// Chinese and Japanese need no space after them.
+const SENTENCE_ENDINGS: [&str; 9] = [". ", "! ", "? ", ".\n", "!\n", "?\n", "。", "！", "？"];
+
+/// Abbreviations ahead of a number, as in "No. 5", and titles ahead of a name
+const NUMBER_ABBREVIATIONS: [&str; 7] = ["No", "Nos", "Nr", "Vol", "Fig", "p", "pp"];
+const TITLES: [&str; 5] = ["Mr", "Mrs", "Ms", "Dr", "Prof"];
+
+/// Whether the period at `pos` in `text` is part of something other than a
+/// sentence's end: a number broken across a space ("version 2. 5", common
+/// in text from PDFs), an abbreviation, or an initial ("J. K. Rowling")
+fn period_continues_sentence(text: &str, pos: usize) -> bool {
+    let word = text[..pos].rsplit(|c: char| !c.is_alphanumeric()).next().unwrap_or_default();
+    let next = text[pos + 1..].trim_start().chars().next();
+    let next_is_digit = next.is_some_and(|c| c.is_ascii_digit());
+
+    ((word.ends_with(|c: char| c.is_ascii_digit()) || NUMBER_ABBREVIATIONS.contains(&word)) && next_is_digit)
+        || TITLES.contains(&word)
+        || (word.len() == 1 && word.chars().all(|c| c.is_ascii_uppercase()) && next.is_some_and(char::is_uppercase))
+}
+
+/// The first sentence of `text`, through its ending, and the rest
+fn split_first_sentence(text: &str) -> (&str, &str) {
+    let sentence_end = text.char_indices().find_map(|(pos, c)| {
+        let ending = SENTENCE_ENDINGS.iter().find(|ending| text[pos..].starts_with(*ending))?;
+        if c == '.' && period_continues_sentence(text, pos) {
+            return None;
+        }
+        Some(pos + ending.len())
+    });
+    match sentence_end {
+        Some(end_pos) => text.split_at(end_pos),
+        // No sentence boundary found, take the whole remaining text
+        None => (text, ""),
+    }
+}
+
+/// `text` cut into sentences where the chunker would cut it
+pub fn sentences(text: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut remaining_text = text;
+    while !remaining_text.is_empty() {
+        let (sentence, rest) = split_first_sentence(remaining_text);
+        sentences.push(sentence);
+        remaining_text = rest;
+    }
+    sentences
+}
+
+/// Where to cut `text` so the piece before fits in `max_size`: just after
+/// the last whitespace that allows it, or, in a URL, a pasted blob or
+/// unspaced Chinese, at the last character that does. Always past the
+/// first character, so cutting gets somewhere.
+fn whitespace_cut(text: &str, max_size: usize) -> usize {
+    let mut cut = None;
+    for (i, c) in text.char_indices().filter(|(_, c)| c.is_whitespace()) {
+        let end = i + c.len_utf8();
+        if end > max_size {
+            break;
         }
+        cut = Some(end);
     }
+    cut.unwrap_or_else(|| {
+        let first = text.chars().next().map_or(0, char::len_utf8);
+        (first..=max_size.max(first)).rev().find(|&end| text.is_char_boundary(end)).unwrap_or(first)
+    })
+}
+
+/// `text` in chunks of whole sentences up to `max_size` bytes each (so
+/// never more characters than that either), which joined together give
+/// back `text` exactly. Based on best practices from tts-joinery and
+/// text-splitter implementations.
+pub fn split_text_semantically(text: &str, max_size: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current_chunk = String::new();
     
-    /// Split text into chunks at sentence boundaries when possible
-    /// Based on best practices from tts-joinery and text-splitter implementations
-    fn split_text_semantically(&self, text: &str, max_size: usize) -> Vec<String> {
-        let mut chunks = Vec::new();
-        let mut current_chunk = String::new();
-        
-        // Split by common sentence endings
-        let sentence_endings = [". ", "! ", "? ", ".\n", "!\n", "?\n"];
-        let mut remaining_text = text;
-        
-        while !remaining_text.is_empty() {
-            // Find the next sentence boundary
-            let mut sentence_end = None;
-            for ending in &sentence_endings {
-                if let Some(pos) = remaining_text.find(ending) {
-                    let end_pos = pos + ending.len();
-                    if sentence_end.is_none() || end_pos < sentence_end.unwrap() {
-                        sentence_end = Some(end_pos);
-                    }
-                }
-            }
-            
-            let (sentence, rest) = if let Some(end_pos) = sentence_end {
-                remaining_text.split_at(end_pos)
-            } else {
-                // No sentence boundary found, take the whole remaining text
-                (remaining_text, "")
-            };
-            
-            // Check if adding this sentence would exceed the limit
-            if !current_chunk.is_empty() && current_chunk.len() + sentence.len() > max_size {
-                // Save current chunk and start a new one
-                chunks.push(current_chunk.clone());
-                current_chunk.clear();
-            }
-            
-            // Handle case where single sentence exceeds max_size
-            if sentence.len() > max_size {
-                // Split long sentence at word boundaries
-                let words: Vec<&str> = sentence.split_whitespace().collect();
-                for word in words {
-                    if current_chunk.len() + word.len() + 1 > max_size {
-                        if !current_chunk.is_empty() {
-                            chunks.push(current_chunk.clone());
-                            current_chunk.clear();
-                        }
-                    }
-                    if !current_chunk.is_empty() {
-                        current_chunk.push(' ');
-                    }
-                    current_chunk.push_str(word);
-                }
-            } else {
-                current_chunk.push_str(sentence);
-            }
-            
-            remaining_text = rest;
+    for sentence in sentences(text) {
+        // Check if adding this sentence would exceed the limit
+        if !current_chunk.is_empty() && current_chunk.len() + sentence.len() > max_size {
+            // Save current chunk and start a new one
+            chunks.push(current_chunk.clone());
+            current_chunk.clear();
         }
         
-        // Add the last chunk if not empty
-        if !current_chunk.is_empty() {
-            chunks.push(current_chunk);
+        // A sentence too long for a chunk of its own is cut at whitespace if
+        // it has any, keeping the whitespace as it was so a list or a poem
+        // reads the same
+        let mut rest = sentence;
+        while rest.len() > max_size {
+            let (piece, after) = rest.split_at(whitespace_cut(rest, max_size));
+            chunks.push(piece.to_string());
+            rest = after;
         }
-        
-        chunks
+        current_chunk.push_str(rest);
     }
+    
+    // Add the last chunk if not empty
+    if !current_chunk.is_empty() {
+        chunks.push(current_chunk);
+    }
+    
+    chunks
+}
+
+/// Estimated cost in USD of synthesizing `character_count` characters with `model`
+pub fn estimate_cost(character_count: i64, model: &str) -> f64 {
+    // Unknown models are priced like the recommended one
+    let model = crate::models::find(model).unwrap_or_else(crate::models::recommended);
+    character_count as f64 * model.price_per_1k_chars / 1000.0
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use mockito::{Matcher, Server};
+    use crate::throttle::ThrottleStatus;
     use tokio_test;
 
     #[tokio::test]
@@ -655,4 +1516,662 @@ mod tests {
         assert!(!service.is_valid_voice("invalid"));
         assert!(!service.is_valid_voice(""));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_error_exit_codes() {
+        assert_eq!(TTSError::Authentication("bad key".to_string()).exit_code(), 3);
+        assert_eq!(TTSError::RateLimit(Some(5)).exit_code(), 4);
+        assert_eq!(TTSError::NetworkError("timeout".to_string()).exit_code(), 5);
+        assert_eq!(TTSError::ValidationError("empty".to_string()).exit_code(), 2);
+        assert_eq!(TTSError::UnknownError("HTTP 500".to_string()).exit_code(), 10);
+        assert_eq!(TTSError::RateLimit(None).kind(), "rate_limit");
+        assert_eq!(TTSError::NotConfigured.exit_code(), 2);
+        assert_eq!(TTSError::NotConfigured.kind(), "not_configured");
+    }
+
+    #[tokio::test]
+    async fn test_request_without_key_then_after_key_set() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/audio/speech")
+            .match_header("authorization", "Bearer sk-new")
+            .with_status(200)
+            .with_body("audio")
+            .expect(1)
+            .create_async()
+            .await;
+
+        let service = TTSService::new("", &server.url());
+        assert_eq!(service.status(), ServiceStatus { configured: false });
+        let err = service.generate_speech_with_model("Hello", "nova", "tts-1").await.unwrap_err();
+        assert!(matches!(err, TTSError::NotConfigured));
+
+        service.set_api_key(Some("  sk-new \n"));
+        assert!(service.status().configured);
+        let audio = service.generate_speech_with_model("Hello", "nova", "tts-1").await.unwrap();
+        assert_eq!(audio, b"audio");
+        mock.assert_async().await;
+
+        service.set_api_key(None);
+        assert!(!service.is_configured());
+    }
+
+    #[tokio::test]
+    async fn test_identical_concurrent_requests_share_one_call() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/audio/speech")
+            .with_status(200)
+            .with_body("audio")
+            .expect(1)
+            .create_async()
+            .await;
+        let service = TTSService::new("sk-test", &server.url());
+
+        let (first, second) = tokio::join!(
+            service.generate_speech_shared("Hello", "nova", "tts-1"),
+            service.generate_speech_shared("Hello", "nova", "tts-1"),
+        );
+        assert_eq!(first.0.unwrap(), b"audio");
+        assert_eq!(second.0.unwrap(), b"audio");
+        assert_eq!((first.1, second.1), (false, true));
+        mock.assert_async().await;
+        mock.remove_async().await;
+
+        // Done, so the next one is a request of its own
+        let other = server.mock("POST", "/v1/audio/speech").with_status(500).expect(2).create_async().await;
+        assert!(service.generate_speech_shared("Hello", "nova", "tts-1").await.0.is_err());
+        let (result, shared) = service.generate_speech_shared("Hello", "nova", "tts-1").await;
+        assert!(result.is_err() && !shared);
+        other.assert_async().await;
+        assert!(service.inner.in_flight.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_long_text_without_ffmpeg_fails_rather_than_truncating() {
+        let mut server = Server::new_async().await;
+        let mock = server.mock("POST", "/v1/audio/speech").expect(0).create_async().await;
+        let service = TTSService::new("sk-test", &server.url()).with_ffmpeg_path("/nonexistent/ffmpeg");
+        let text = "A sentence that goes on for a while. ".repeat(250);
+
+        for result in [
+            service.generate_speech_with_model(&text, "nova", "tts-1").await,
+            service.generate_speech(&text, "nova").await,
+        ] {
+            let error = result.unwrap_err();
+            assert!(matches!(&error, TTSError::FfmpegMissing(FfmpegMissing(path)) if path == "/nonexistent/ffmpeg"));
+            assert!(error.to_string().contains("ffmpeg_path"), "{}", error);
+        }
+        assert!(!service.plays_as_playlist(&text));
+        // Nothing is sent, so nothing is paid for
+        mock.assert_async().await;
+    }
+
+    /// An FFmpeg that answers `-version` and "joins" anything into "joined"
+    #[cfg(unix)]
+    fn fake_ffmpeg(dir: &std::path::Path) -> String {
+        use std::os::unix::fs::PermissionsExt;
+        let fake = dir.join("ffmpeg");
+        std::fs::write(&fake, "#!/bin/sh\nfor last; do :; done\n[ \"$1\" = -version ] || printf joined > \"$last\"\n").unwrap();
+        std::fs::set_permissions(&fake, std::fs::Permissions::from_mode(0o755)).unwrap();
+        fake.to_string_lossy().into_owned()
+    }
+
+    /// A service with a database in `dir` and `fake_ffmpeg`, and mocks for
+    /// requests with model `sent` (`chunks` of them) and with any other
+    #[cfg(unix)]
+    async fn long_text_service(
+        server: &mut mockito::ServerGuard,
+        dir: &std::path::Path,
+        sent: &str,
+        chunks: usize,
+    ) -> (TTSService, mockito::Mock, mockito::Mock) {
+        let config = Config { data_dir: dir.to_path_buf(), api_base_url: server.url(), ..Config::default() };
+        let service = TTSService::from_config(Some("sk-test"), &config).await.unwrap().with_ffmpeg_path(&fake_ffmpeg(dir));
+        let expected = server.mock("POST", "/v1/audio/speech")
+            .match_body(Matcher::PartialJson(json!({ "model": sent })))
+            .with_status(200).with_body("piece").expect(chunks).create_async().await;
+        let other = server.mock("POST", "/v1/audio/speech").expect(0).create_async().await;
+        (service, expected, other)
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_downgraded_long_text_is_sent_and_recorded_once_as_the_cheaper_model() {
+        let mut server = Server::new_async().await;
+        let dir = tempfile::TempDir::new().unwrap();
+        let text = "A sentence that goes on for a while. ".repeat(250);
+        let chunks = chunk_count(&text, CHUNK_SIZE);
+        assert!(chunks > 1);
+        let (service, downgraded, other) = long_text_service(&mut server, dir.path(), "tts-1", chunks).await;
+
+        let audio = service.synthesize_downgraded(None, &text, "nova", "tts-1", "tts-1-hd").await.unwrap();
+        assert_eq!(audio, b"joined");
+        downgraded.assert_async().await;
+        other.assert_async().await;
+
+        let records = service.database().unwrap().get_usage_records(10, None).await.unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!((records[0].model_id.as_str(), records[0].downgraded_from.as_deref()), ("tts-1", Some("tts-1-hd")));
+        // Timed as the model it was made with
+        assert_eq!(records[0].chunk_count, Some(chunks as i32));
+        assert!(records[0].latency_ms.is_some());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_long_text_regenerated_with_another_model() {
+        let mut server = Server::new_async().await;
+        let dir = tempfile::TempDir::new().unwrap();
+        let text = "A sentence that goes on for a while. ".repeat(250);
+        let chunks = chunk_count(&text, CHUNK_SIZE);
+        let (service, sent, other) = long_text_service(&mut server, dir.path(), "tts-1", chunks).await;
+
+        service.track_usage(&text, "nova", "tts-1-hd", true, None).await.unwrap();
+        let original = service.database().unwrap().get_usage_records(1, None).await.unwrap()[0].id.unwrap();
+
+        // As `regenerate_from_record` does it
+        service.generate_speech_with_model(&text, "nova", "tts-1").await.unwrap();
+        service.track_regeneration(original, &text, "nova", "tts-1", None).await.unwrap();
+        sent.assert_async().await;
+        other.assert_async().await;
+
+        let records = service.database().unwrap().get_usage_records(10, None).await.unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!((records[0].model_id.as_str(), records[0].regenerated_from), ("tts-1", Some(original)));
+        assert_eq!(records[0].chunk_count, Some(chunks as i32));
+    }
+
+    #[tokio::test]
+    async fn test_long_text_without_ffmpeg_as_playlist() {
+        let mut server = Server::new_async().await;
+        let text = "A sentence that goes on for a while. ".repeat(250);
+        let chunks = chunk_count(&text, CHUNK_SIZE);
+        assert!(chunks > 1);
+        let mock = server
+            .mock("POST", "/v1/audio/speech")
+            .with_status(200)
+            .with_body("piece")
+            .expect(chunks)
+            .create_async()
+            .await;
+        let service = TTSService::new("sk-test", &server.url()).with_ffmpeg_path("/nonexistent/ffmpeg");
+        let settings = Settings { without_ffmpeg: WithoutFfmpeg::Playlist, ..Settings::from_config(&Config::default()) };
+        service.apply_settings(&settings);
+
+        assert!(service.plays_as_playlist(&text));
+        assert!(!service.plays_as_playlist("Short enough for one request."));
+        let pieces = service.generate_speech_chunked(&text, "nova", "tts-1").await.unwrap();
+        assert_eq!(pieces.len(), chunks);
+        assert!(pieces.iter().all(|piece| piece == b"piece"));
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_shared_request_warns_the_caller_that_waited() {
+        let mut server = Server::new_async().await;
+        let mock = server.mock("POST", "/v1/audio/speech").with_status(200).with_body("audio").expect(1).create_async().await;
+        let service = TTSService::new("sk-test", &server.url());
+
+        let ((first, first_warnings), (second, second_warnings)) = tokio::join!(
+            warnings::collect(service.generate_speech_shared("Hello", "nova", "tts-1")),
+            warnings::collect(service.generate_speech_shared("Hello", "nova", "tts-1")),
+        );
+        assert_eq!((first.1, second.1), (false, true));
+        assert!(first_warnings.is_empty());
+        let codes: Vec<&str> = second_warnings.iter().map(|warning| warning.code).collect();
+        assert_eq!(codes, ["warning.shared"]);
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_retries_are_reported_as_warnings() {
+        let mut server = Server::new_async().await;
+        let mock = server.mock("POST", "/v1/audio/speech").with_status(500).expect(3).create_async().await;
+        let service = TTSService::new("sk-test", &server.url());
+
+        let (result, warnings) = warnings::collect(service.generate_speech_with_retry("Hello", "nova")).await;
+        assert!(result.is_err());
+        let attempts: Vec<_> = warnings.iter().map(|warning| (warning.code, warning.data["attempt"].clone())).collect();
+        assert_eq!(attempts, [("warning.retried", json!(2)), ("warning.retried", json!(3))]);
+        assert!(warnings[0].message.contains("HTTP 500"), "{}", warnings[0].message);
+        mock.assert_async().await;
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        let now = DateTime::parse_from_rfc3339("2026-03-01T08:49:37Z").unwrap().with_timezone(&Utc);
+        assert_eq!(parse_retry_after("120", now), Some(120));
+        assert_eq!(parse_retry_after(" 0 ", now), Some(0));
+        assert_eq!(parse_retry_after("Sun, 01 Mar 2026 08:51:07 GMT", now), Some(90));
+        assert_eq!(parse_retry_after("Sunday, 01-Mar-26 08:50:37 GMT", now), Some(60));
+        assert_eq!(parse_retry_after("Sun Mar  1 08:49:47 2026", now), Some(10));
+        // Already past
+        assert_eq!(parse_retry_after("Sun, 01 Mar 2026 08:00:00 GMT", now), Some(0));
+        assert_eq!(parse_retry_after("soon", now), None);
+        assert_eq!(parse_retry_after("-5", now), None);
+    }
+
+    async fn rate_limited(retry_after: &str) -> (mockito::ServerGuard, mockito::Mock) {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/audio/speech")
+            .with_status(429)
+            .with_header("Retry-After", retry_after)
+            .create_async()
+            .await;
+        (server, mock)
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_seconds_and_dates() {
+        let (server, mock) = rate_limited("120").await;
+        let service = TTSService::new("sk-test", &server.url());
+        let error = service.generate_speech_with_model("Hello", "nova", "tts-1").await.unwrap_err();
+        assert!(matches!(error, TTSError::RateLimit(Some(120))), "{:?}", error);
+        mock.assert_async().await;
+
+        let in_90_seconds = (Utc::now() + chrono::Duration::seconds(90)).format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+        let (server, _mock) = rate_limited(&in_90_seconds).await;
+        let service = TTSService::new("sk-test", &server.url());
+        for result in [
+            service.generate_speech_with_model("Hello", "nova", "tts-1").await,
+            service.generate_speech("Hello", "nova").await,
+            // The chunked path, with FFmpeg never reached
+            service.generate_speech_with_ffmpeg_concat("Hello", "nova", "tts-1").await,
+        ] {
+            let Err(TTSError::RateLimit(Some(seconds))) = result else {
+                panic!("expected a rate limit with a wait, got {:?}", result.map(|audio| audio.len()));
+            };
+            assert!((85..=90).contains(&seconds), "{}", seconds);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_paced_text_without_ffmpeg_is_read_straight_through() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/audio/speech")
+            .match_body(Matcher::PartialJson(serde_json::json!({ "input": "One. Two. Three." })))
+            .with_status(200)
+            .with_body("audio")
+            .expect(1)
+            .create_async()
+            .await;
+        let service = TTSService::new("sk-test", &server.url()).with_ffmpeg_path("/nonexistent/ffmpeg");
+        let gaps = Gaps { sentence_ms: 300, paragraph_ms: 1000 };
+        let (result, warnings) =
+            warnings::collect(service.synthesize_paced(None, "One. Two.\n\nThree.", "nova", "tts-1", None, gaps)).await;
+        assert_eq!(result.unwrap(), b"audio");
+        assert_eq!(warnings.iter().map(|warning| warning.code).collect::<Vec<_>>(), ["warning.pauses_dropped"]);
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_requests_wait_out_a_rate_limit() {
+        let (mut server, limited) = rate_limited("1").await;
+        let service = TTSService::new("sk-test", &server.url());
+        let events: Arc<std::sync::Mutex<Vec<ThrottleStatus>>> = Arc::default();
+        let recorded = events.clone();
+        service.throttle().set_listener(Arc::new(move |status: &ThrottleStatus| recorded.lock().unwrap().push(status.clone())));
+
+        let error = service.generate_speech_with_model("Hello", "nova", "tts-1").await.unwrap_err();
+        assert!(matches!(error, TTSError::RateLimit(Some(1))), "{:?}", error);
+        let status = service.throttle().status();
+        assert!(status.backing_off);
+        assert_eq!(status.reason, Some(crate::throttle::ThrottleReason::RateLimited));
+        // Copies for another speed share it; ones for another key don't
+        assert!(service.at_speed(1.5).throttle().status().backing_off);
+        assert!(!service.with_api_key("sk-other").throttle().status().backing_off);
+
+        limited.remove_async().await;
+        let ok = server.mock("POST", "/v1/audio/speech").with_status(200).with_body("audio").create_async().await;
+        let started = Instant::now();
+        assert_eq!(service.generate_speech_with_model("Hello", "nova", "tts-1").await.unwrap(), b"audio");
+        assert!(started.elapsed() >= Duration::from_millis(500), "{:?}", started.elapsed());
+        ok.assert_async().await;
+        assert!(!service.throttle().status().backing_off);
+        assert!(events.lock().unwrap().first().is_some_and(|status| status.backing_off));
+    }
+
+    #[test]
+    fn test_sentence_boundaries() {
+        let cases: [(&str, &[&str]); 10] = [
+            ("Pi is 3.14159. Next sentence.", &["Pi is 3.14159. ", "Next sentence."]),
+            ("Upgrade to 2.5.1 today.", &["Upgrade to 2.5.1 today."]),
+            ("Upgrade to version 2. 5 today. Then restart.", &["Upgrade to version 2. 5 today. ", "Then restart."]),
+            ("Chapter 3.\n4 apples fell.", &["Chapter 3.\n4 apples fell."]),
+            ("See No. 5 on the list. It is short.", &["See No. 5 on the list. ", "It is short."]),
+            ("No. I disagree.", &["No. ", "I disagree."]),
+            ("J. K. Rowling wrote it. She lives in Edinburgh.", &["J. K. Rowling wrote it. ", "She lives in Edinburgh."]),
+            ("Ask Dr. Smith. He knows.", &["Ask Dr. Smith. ", "He knows."]),
+            ("It was 1999. the end.", &["It was 1999. ", "the end."]),
+            ("Hi! Is it 5? Yes.\n終わり。次", &["Hi! ", "Is it 5? ", "Yes.\n", "終わり。", "次"]),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(sentences(text), expected, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn test_chunks_rejoin_to_the_original() {
+        let pieces = [
+            "Word", "another", "longerwordhere", " ", "  ", "\n", "\n\n", "\t", ". ", "! ", "?\n", ",", "- ", "1. ",
+            "café", "日本語", "。", "  * item\n",
+        ];
+        // A fixed sequence, so a failure can be reproduced
+        let mut state: u64 = 0x2545_f491;
+        let mut next = |bound: usize| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 33) as usize % bound
+        };
+        for _ in 0..200 {
+            let text: String = (0..next(400)).map(|_| pieces[next(pieces.len())]).collect();
+            let max_size = 20 + next(200);
+            let chunks = split_text_semantically(&text, max_size);
+            assert_eq!(chunks.concat(), text, "max_size {}", max_size);
+            assert!(chunks.iter().all(|chunk| !chunk.is_empty() && chunk.len() <= max_size), "{:?}", chunks);
+        }
+
+        let poem = format!("{}\n\tIndented line\n\n  two spaces   three spaces", "Line one, ".repeat(10));
+        let chunks = split_text_semantically(&poem, 40);
+        assert_eq!(chunks.concat(), poem);
+        assert!(chunks.iter().all(|chunk| chunk.len() <= 40), "{:?}", chunks);
+    }
+
+    #[test]
+    fn test_unbroken_text_is_cut_to_the_limit() {
+        let url = format!("https://example.com/?q={}", "a1B2".repeat(5000));
+        let chinese = "汉字没有空格".repeat(3400);
+        for text in [url.as_str(), &chinese[..60_000], &format!("Short. {} Then more.", url)] {
+            let chunks = split_text_semantically(text, CHUNK_SIZE);
+            assert_eq!(chunks.concat(), text);
+            assert!(chunks.iter().all(|chunk| chunk.len() <= CHUNK_SIZE && chunk.chars().count() <= CHUNK_SIZE));
+            assert!(chunks.len() >= text.len() / CHUNK_SIZE);
+        }
+        // Even a limit narrower than one character moves along
+        assert_eq!(split_text_semantically("日本", 2), ["日", "本"]);
+    }
+
+    #[test]
+    fn test_redact_api_key() {
+        let service = TTSService::new("sk-secret", "https://api.openai.com/v1");
+        assert_eq!(service.redact("Authorization: Bearer sk-secret"), "Authorization: Bearer [redacted]");
+        // Without a key only what looks like one is blanked out
+        service.set_api_key(None);
+        assert_eq!(service.redact("Bearer sk-secret"), "Bearer [redacted]");
+        assert_eq!(service.redact("key=sk-proj-abcdef123456 ok"), "key=[redacted] ok");
+        assert_eq!(service.redact("sk-short and sketchy"), "sk-short and sketchy");
+    }
+
+    /// Not a pass/fail timing: run with `--nocapture` to compare 20 requests
+    /// on the shared client with 20 on a client made for each, as the
+    /// commands did before the service was kept in managed state
+    #[tokio::test]
+    async fn test_shared_client_against_client_per_request() {
+        const REQUESTS: usize = 20;
+        let mut server = Server::new_async().await;
+        let mock = server.mock("POST", "/v1/audio/speech").with_body(b"audio").expect(2 * REQUESTS).create_async().await;
+
+        let shared = TTSService::new("sk-test", &server.url());
+        let started = Instant::now();
+        for _ in 0..REQUESTS {
+            assert_eq!(shared.generate_speech_with_model("Hello", "nova", "tts-1").await.unwrap(), b"audio");
+        }
+        let shared_time = started.elapsed();
+
+        let started = Instant::now();
+        for _ in 0..REQUESTS {
+            let fresh = TTSService::new("sk-test", &server.url());
+            assert_eq!(fresh.generate_speech_with_model("Hello", "nova", "tts-1").await.unwrap(), b"audio");
+        }
+        let fresh_time = started.elapsed();
+
+        println!("{} requests: {:?} on a shared client, {:?} on a client each", REQUESTS, shared_time, fresh_time);
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_api_key_kept_out_of_errors() {
+        let key = "sk-test-secret-0123456789";
+        for (status, body) in [
+            (401, format!("{{\"error\":{{\"message\":\"Incorrect API key provided: {}\"}}}}", key)),
+            (400, format!("Rejected request with Authorization: Bearer {}", key)),
+        ] {
+            let mut server = Server::new_async().await;
+            let mock = server.mock("POST", "/v1/audio/speech").with_status(status).with_body(&body).create_async().await;
+            let service = TTSService::new(key, &server.url());
+            let error = service.generate_speech_with_model("Hello", "nova", "tts-1").await.unwrap_err();
+            assert!(!format!("{} {:?}", error, error).contains(key), "{:?}", error);
+            let command_error = crate::i18n::CommandError::from(error);
+            assert!(!serde_json::to_string(&command_error).unwrap().contains(key), "{:?}", command_error);
+            mock.assert_async().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_organization_and_project_headers() {
+        let mut server = Server::new_async().await;
+        let without = server.mock("POST", "/v1/audio/speech")
+            .match_header("openai-organization", Matcher::Missing)
+            .match_header("openai-project", Matcher::Missing)
+            .with_status(200).with_body("audio").expect(1).create_async().await;
+        let service = TTSService::new("sk-test", &server.url());
+        service.generate_speech_with_model("Hello", "nova", "tts-1").await.unwrap();
+        without.assert_async().await;
+        without.remove_async().await;
+
+        let with = server.mock("POST", "/v1/audio/speech")
+            .match_header("openai-organization", "org-work")
+            .match_header("openai-project", "proj_audio")
+            .with_status(200).with_body("audio").expect(2).create_async().await;
+        let settings = Settings {
+            organization: Some("org-work".to_string()),
+            project: Some("proj_audio".to_string()),
+            ..Settings::from_config(&Config::default())
+        };
+        service.apply_settings(&settings);
+        service.generate_speech_with_model("Hello", "nova", "tts-1").await.unwrap();
+        // A copy for another key bills the same organization
+        service.with_api_key("sk-other").generate_speech_with_model("Hello", "nova", "tts-1").await.unwrap();
+        with.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_validate_api_key_names_a_wrong_organization() {
+        let mut server = Server::new_async().await;
+        let valid = server.mock("GET", "/v1/models")
+            .match_header("authorization", "Bearer sk-test")
+            .match_header("openai-organization", Matcher::Missing)
+            .with_status(200).with_body("{\"data\":[]}").create_async().await;
+        let service = TTSService::new("sk-test", &server.url());
+        service.validate_api_key().await.unwrap();
+        valid.assert_async().await;
+
+        let mismatched = server.mock("GET", "/v1/models")
+            .match_header("openai-organization", "org-wrong")
+            .with_status(401)
+            .with_body(r#"{"error":{"message":"OpenAI-Organization header should match organization for API key","code":"mismatched_organization"}}"#)
+            .create_async().await;
+        let settings = Settings { organization: Some("org-wrong".to_string()), ..Settings::from_config(&Config::default()) };
+        service.apply_settings(&settings);
+        let error = service.validate_api_key().await.unwrap_err();
+        assert!(matches!(&error, TTSError::Authentication(message) if message.contains("organization org-wrong")), "{}", error);
+        mismatched.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_typography_normalized_before_sending_and_counting() {
+        let mut server = Server::new_async().await;
+        let normalized = server.mock("POST", "/v1/audio/speech")
+            .match_body(Matcher::PartialJson(json!({ "input": "\"Hyphenation\" - 50 km" })))
+            .with_status(200).with_body("audio").expect(1).create_async().await;
+        let dir = tempfile::TempDir::new().unwrap();
+        let config = Config { data_dir: dir.path().to_path_buf(), api_base_url: server.url(), ..Config::default() };
+        let service = TTSService::from_config(Some("sk-test"), &config).await.unwrap();
+
+        let text = "\u{201C}Hyphen\u{00AD}ation\u{201D}\u{2014}50\u{00A0}km\u{200B}";
+        service.synthesize(text, "nova", "tts-1").await.unwrap();
+        normalized.assert_async().await;
+        let record = &service.database().unwrap().get_usage_records(1, None).await.unwrap()[0];
+        assert_eq!(record.character_count, 21);
+
+        // Turned off, the text goes as it is
+        let mut settings = Settings::from_config(&config);
+        settings.preprocessing.normalize_typography = false;
+        service.apply_settings(&settings);
+        let as_is = server.mock("POST", "/v1/audio/speech")
+            .match_body(Matcher::PartialJson(json!({ "input": text })))
+            .with_status(200).with_body("audio").expect(1).create_async().await;
+        service.synthesize(text, "nova", "tts-1").await.unwrap();
+        as_is.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_pasted_text_reflowed_when_asked() {
+        let text = include_str!("../tests/fixtures/hard-wrapped.txt");
+        let reflowed = "The lighthouse keeper climbed the one hundred and twelve steps every evening at dusk, \
+            trimmed the wick and wound the clockwork that turned the lens. In winter the climb took longer.\n\n\
+            He kept a short list by the door:\n- oil for the lamp\n- matches, in a tin\n\
+            1. Check the glass for salt.\n2. Log the weather.";
+        let service = TTSService::new("sk-test", "http://localhost");
+        // Off by default for pasted text
+        assert_eq!(service.prepare(text), text);
+
+        let mut settings = Settings::from_config(&Config::default());
+        settings.preprocessing.reflow = true;
+        service.apply_settings(&settings);
+        assert_eq!(service.prepare(text), reflowed);
+        let chunks = request_texts(&service.prepare(text), 200);
+        assert!(chunks.iter().all(|chunk| !chunk.contains("steps\nevery")), "{:?}", chunks);
+    }
+
+    #[tokio::test]
+    async fn test_markdown_and_whitespace_cleaned_when_asked() {
+        let text = "**Bold**   claim.\n\n\n\nSee [here](https://example.com).";
+        let service = TTSService::new("sk-test", "http://localhost");
+        assert_eq!(service.prepare(text), text);
+
+        let mut settings = Settings::from_config(&Config::default());
+        settings.preprocessing.strip_markdown = true;
+        settings.preprocessing.collapse_whitespace = true;
+        service.apply_settings(&settings);
+        assert_eq!(service.prepare(text), "Bold claim.\n\nSee here.");
+    }
+
+    #[tokio::test]
+    async fn test_key_passed_with_the_request_comes_first() {
+        let mut server = Server::new_async().await;
+        let configured = server.mock("POST", "/v1/audio/speech").match_header("authorization", "Bearer sk-configured")
+            .with_status(200).with_body("audio").expect(2).create_async().await;
+        let passed = server.mock("POST", "/v1/audio/speech").match_header("authorization", "Bearer sk-work")
+            .with_status(200).with_body("audio").expect(1).create_async().await;
+        let service = TTSService::new("sk-configured", &server.url());
+
+        for api_key in [None, Some("  ")] {
+            let for_request = service.for_request(api_key);
+            assert!(Arc::ptr_eq(&for_request.inner, &service.inner));
+            for_request.generate_speech_with_model("Hello", "nova", "tts-1").await.unwrap();
+        }
+        service.for_request(Some(" sk-work\n")).generate_speech_with_model("Hello", "nova", "tts-1").await.unwrap();
+
+        configured.assert_async().await;
+        passed.assert_async().await;
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_shared_service_serves_concurrent_commands() {
+        let mut server = Server::new_async().await;
+        let mock = server.mock("POST", "/v1/audio/speech")
+            .with_status(200).with_body("audio").expect(16).create_async().await;
+        let dir = tempfile::TempDir::new().unwrap();
+        let config = Config { data_dir: dir.path().to_path_buf(), api_base_url: server.url(), ..Config::default() };
+        let service = TTSService::from_config(Some("sk-test"), &config).await.unwrap();
+        let settings = Settings::from_config(&config);
+
+        // Generations, stats and settings changes all at once, as commands
+        // from the window and jobs would make them
+        let mut tasks = tokio::task::JoinSet::new();
+        for i in 0..16 {
+            let (service, settings) = (service.clone(), settings.clone());
+            tasks.spawn(async move {
+                let generated = service.synthesize(&format!("Text number {}", i), "nova", "tts-1").await;
+                service.apply_settings(&settings);
+                service.get_usage_stats(30, None).await.unwrap();
+                service.get_usage_history(10, None).await.unwrap();
+                assert!(service.status().configured);
+                generated
+            });
+        }
+        let finished = tokio::time::timeout(Duration::from_secs(10), async {
+            let mut results = Vec::new();
+            while let Some(result) = tasks.join_next().await {
+                results.push(result.unwrap());
+            }
+            results
+        })
+        .await
+        .expect("concurrent commands deadlocked");
+
+        assert!(finished.iter().all(|audio| matches!(audio.as_deref(), Ok(b"audio"))));
+        mock.assert_async().await;
+        let stats = service.get_usage_stats(30, None).await.unwrap();
+        assert_eq!((stats.total_requests, stats.successful_requests), (16, 16));
+
+        // A key changed through one handle is the key for all of them
+        let handle = service.clone();
+        handle.set_api_key(None);
+        assert!(!service.status().configured);
+    }
+
+    #[tokio::test]
+    async fn test_usage_is_tagged_with_the_key_fingerprint() {
+        let mut server = Server::new_async().await;
+        let _mock = server.mock("POST", "/v1/audio/speech").with_status(200).with_body("audio").create_async().await;
+        let dir = tempfile::TempDir::new().unwrap();
+        let config = Config { data_dir: dir.path().to_path_buf(), api_base_url: server.url(), ..Config::default() };
+        let personal = TTSService::from_config(Some("sk-personal-1111"), &config).await.unwrap();
+        let work = personal.for_request(Some("sk-work-2222"));
+
+        personal.synthesize("Personal", "nova", "tts-1").await.unwrap();
+        work.synthesize("Work, twice as long", "nova", "tts-1").await.unwrap();
+
+        let database = personal.database().unwrap();
+        let records = database.get_usage_records(10, None).await.unwrap();
+        let tagged: Vec<_> = records.iter().map(|r| (r.text.as_str(), r.key_fingerprint.clone())).collect();
+        assert!(tagged.contains(&("Personal", Some(crate::keychain::fingerprint("sk-personal-1111")))));
+        assert!(tagged.contains(&("Work, twice as long", Some(crate::keychain::fingerprint("sk-work-2222")))));
+        assert!(records.iter().all(|r| !format!("{:?}", r).contains("2222")));
+        assert!(records.iter().all(|r| r.app_version.as_deref() == Some(env!("CARGO_PKG_VERSION"))));
+
+        assert_eq!(personal.get_user_info().await.unwrap().character_used, 27);
+        assert_eq!(work.get_account_info().await.unwrap().character_used, 19);
+    }
+
+    #[tokio::test]
+    async fn test_usage_records_how_long_the_generation_took() {
+        let mut server = Server::new_async().await;
+        let _mock = server.mock("POST", "/v1/audio/speech").with_status(200).with_body("audio").create_async().await;
+        let dir = tempfile::TempDir::new().unwrap();
+        let config = Config { data_dir: dir.path().to_path_buf(), api_base_url: server.url(), ..Config::default() };
+        let service = TTSService::from_config(Some("sk-test"), &config).await.unwrap().with_ffmpeg_path("/nonexistent/ffmpeg");
+        let settings = Settings { without_ffmpeg: WithoutFfmpeg::Playlist, ..Settings::from_config(&config) };
+        service.apply_settings(&settings);
+
+        service.synthesize("Hello", "nova", "tts-1").await.unwrap();
+        let long = "A sentence that goes on for a while. ".repeat(250);
+        let pieces = service.generate_speech_chunked(&long, "nova", "tts-1").await.unwrap();
+        service.track_usage(&long, "nova", "tts-1", true, None).await.unwrap();
+        // Recorded without generating, so there's nothing to time
+        service.track_usage("Not generated", "nova", "tts-1", true, None).await.unwrap();
+
+        let records = service.database().unwrap().get_usage_records(10, None).await.unwrap();
+        let timed = |text: &str| {
+            let record = records.iter().find(|r| r.text.starts_with(text)).unwrap();
+            (record.latency_ms.is_some_and(|ms| ms >= 0), record.chunk_count)
+        };
+        assert_eq!(timed("Hello"), (true, Some(1)));
+        assert_eq!(timed("A sentence"), (true, Some(pieces.len() as i32)));
+        assert_eq!(timed("Not generated"), (false, None));
+    }
+}
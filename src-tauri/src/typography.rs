@@ -0,0 +1,133 @@
+//! Plain-text equivalents for the typographic characters text picks up in
+//! word processors and on the web. Curly quotes and dashes become ASCII,
+//! unusual spaces become plain ones, and invisible characters such as soft
+//! hyphens are dropped: the voice can stumble over them, and each one is a
+//! character paid for. Applied before text is counted, checked or split,
+//! unless `Preprocessing::normalize_typography` is turned off.
+
+use std::borrow::Cow;
+
+/// Each character replaced and what it becomes. A space at either end of a
+/// replacement is left out where there is already whitespace on that side.
+const REPLACEMENTS: &[(char, &str)] = &[
+    // Single quotes and apostrophes
+    ('\u{2018}', "'"),
+    ('\u{2019}', "'"),
+    ('\u{201A}', "'"),
+    ('\u{201B}', "'"),
+    ('\u{2032}', "'"),
+    ('\u{2039}', "'"),
+    ('\u{203A}', "'"),
+    // Double quotes
+    ('\u{201C}', "\""),
+    ('\u{201D}', "\""),
+    ('\u{201E}', "\""),
+    ('\u{201F}', "\""),
+    ('\u{2033}', "\""),
+    ('\u{00AB}', "\""),
+    ('\u{00BB}', "\""),
+    // Hyphens and dashes; a dash between words keeps its pause
+    ('\u{2010}', "-"),
+    ('\u{2011}', "-"),
+    ('\u{2012}', "-"),
+    ('\u{2013}', "-"),
+    ('\u{2212}', "-"),
+    ('\u{2014}', " - "),
+    ('\u{2015}', " - "),
+    ('\u{2026}', "..."),
+    // Spaces
+    ('\u{00A0}', " "),
+    ('\u{1680}', " "),
+    ('\u{2000}', " "),
+    ('\u{2001}', " "),
+    ('\u{2002}', " "),
+    ('\u{2003}', " "),
+    ('\u{2004}', " "),
+    ('\u{2005}', " "),
+    ('\u{2006}', " "),
+    ('\u{2007}', " "),
+    ('\u{2008}', " "),
+    ('\u{2009}', " "),
+    ('\u{200A}', " "),
+    ('\u{202F}', " "),
+    ('\u{205F}', " "),
+    ('\u{3000}', " "),
+    // Invisible: soft hyphen, zero-width space, non-joiner and joiner,
+    // word joiner and byte order mark
+    ('\u{00AD}', ""),
+    ('\u{200B}', ""),
+    ('\u{200C}', ""),
+    ('\u{200D}', ""),
+    ('\u{2060}', ""),
+    ('\u{FEFF}', ""),
+];
+
+fn replacement(c: char) -> Option<&'static str> {
+    REPLACEMENTS.iter().find(|(from, _)| *from == c).map(|(_, to)| *to)
+}
+
+/// `text` with `REPLACEMENTS` applied; borrowed when there was nothing to do
+pub fn normalize(text: &str) -> Cow<'_, str> {
+    if !text.chars().any(|c| replacement(c).is_some()) {
+        return Cow::Borrowed(text);
+    }
+    let mut normalized = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        let Some(mut to) = replacement(c) else {
+            normalized.push(c);
+            continue;
+        };
+        if normalized.is_empty() || normalized.ends_with(char::is_whitespace) {
+            to = to.trim_start_matches(' ');
+        }
+        if chars.peek().is_none_or(|next| next.is_whitespace()) {
+            to = to.trim_end_matches(' ');
+        }
+        normalized.push_str(to);
+    }
+    Cow::Owned(normalized)
+}
+
+/// `normalize` if `enabled`
+pub fn prepare(text: &str, enabled: bool) -> Cow<'_, str> {
+    if enabled { normalize(text) } else { Cow::Borrowed(text) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_each_character() {
+        for &(from, to) in REPLACEMENTS {
+            let text = format!("a{}b", from);
+            assert_eq!(normalize(&text), format!("a{}b", to), "U+{:04X}", from as u32);
+        }
+    }
+
+    #[test]
+    fn test_in_context() {
+        for (text, expected) in [
+            ("\u{201C}It\u{2019}s fine,\u{201D} she said", "\"It's fine,\" she said"),
+            ("pages 10\u{2013}12", "pages 10-12"),
+            ("wait\u{2014}what?", "wait - what?"),
+            ("wait \u{2014} what?", "wait - what?"),
+            ("\u{2014}and then", "- and then"),
+            ("and then\u{2026}", "and then..."),
+            ("hyphen\u{00AD}ation", "hyphenation"),
+            ("\u{FEFF}Title", "Title"),
+            ("50\u{00A0}km and 3\u{202F}%", "50 km and 3 %"),
+        ] {
+            assert_eq!(normalize(text), expected, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn test_plain_text_is_untouched() {
+        let text = "Plain \"ASCII\" text - with 'quotes'... and accents: caf\u{00E9}, na\u{00EF}ve";
+        assert!(matches!(normalize(text), Cow::Borrowed(_)));
+        assert_eq!(normalize(&normalize("it\u{2019}s \u{2014} ok")), normalize("it\u{2019}s \u{2014} ok"));
+        assert_eq!(prepare("it\u{2019}s", false), "it\u{2019}s");
+    }
+}
@@ -0,0 +1,206 @@
+//! Usage history from another tool, read from CSV so it shows up in the
+//! stats. The caller says which column holds what; every row is checked
+//! before anything is written, and a row that doesn't check out is reported
+//! with its line number rather than left out (see
+//! `Database::import_usage_csv`).
+
+use crate::database::{UsageRecord, DEFAULT_PROFILE, SOURCE_IMPORT};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+
+/// The header of the column holding each field. `text` is optional; the
+/// rest must be in the file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CsvMapping {
+    pub timestamp: String,
+    pub characters: String,
+    pub voice: String,
+    pub model: String,
+    pub success: String,
+    #[serde(default)]
+    pub text: Option<String>,
+}
+
+/// A row that can't be imported
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RowError {
+    /// Line in the file, counting the header as 1
+    pub line: u64,
+    pub message: String,
+}
+
+/// What an import did, or with `dry_run` would have done. Nothing is
+/// imported while there are `errors`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    /// Rows imported, or that would be without `dry_run`
+    pub imported: usize,
+    pub errors: Vec<RowError>,
+}
+
+/// Timestamps without an offset are taken as UTC
+const NAIVE_FORMATS: &[&str] = &["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M"];
+
+fn parse_timestamp(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(value) {
+        return Some(timestamp.with_timezone(&Utc));
+    }
+    NAIVE_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .or_else(|| NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?.and_hms_opt(0, 0, 0))
+        .map(|timestamp| timestamp.and_utc())
+}
+
+fn parse_success(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "1" | "ok" | "success" | "succeeded" => Some(true),
+        "false" | "no" | "0" | "error" | "failed" | "failure" => Some(false),
+        _ => None,
+    }
+}
+
+/// Where each mapped column is in `headers`
+struct Columns {
+    timestamp: usize,
+    characters: usize,
+    voice: usize,
+    model: usize,
+    success: usize,
+    text: Option<usize>,
+}
+
+impl Columns {
+    fn find(headers: &csv::StringRecord, mapping: &CsvMapping) -> Result<Self> {
+        let find = |name: &str| {
+            headers
+                .iter()
+                .position(|header| header.trim() == name.trim())
+                .ok_or_else(|| anyhow!("No column \"{}\" in the CSV header", name))
+        };
+        Ok(Self {
+            timestamp: find(&mapping.timestamp)?,
+            characters: find(&mapping.characters)?,
+            voice: find(&mapping.voice)?,
+            model: find(&mapping.model)?,
+            success: find(&mapping.success)?,
+            text: mapping.text.as_deref().map(find).transpose()?,
+        })
+    }
+
+    fn record(&self, row: &csv::StringRecord, mapping: &CsvMapping) -> Result<UsageRecord, String> {
+        let field = |index: usize, name: &str| match row.get(index).map(str::trim) {
+            Some(value) if !value.is_empty() => Ok(value),
+            _ => Err(format!("no value for \"{}\"", name)),
+        };
+        let timestamp = field(self.timestamp, &mapping.timestamp)?;
+        let timestamp = parse_timestamp(timestamp).ok_or_else(|| format!("invalid timestamp \"{}\"", timestamp))?;
+        let characters = field(self.characters, &mapping.characters)?;
+        let character_count = characters
+            .parse::<i32>()
+            .ok()
+            .filter(|count| *count >= 0)
+            .ok_or_else(|| format!("invalid character count \"{}\"", characters))?;
+        let success = field(self.success, &mapping.success)?;
+        let success = parse_success(success).ok_or_else(|| format!("invalid success value \"{}\"", success))?;
+        Ok(UsageRecord {
+            id: None,
+            timestamp,
+            text: self.text.and_then(|index| row.get(index)).unwrap_or_default().to_string(),
+            character_count,
+            voice_id: field(self.voice, &mapping.voice)?.to_string(),
+            model_id: field(self.model, &mapping.model)?.to_string(),
+            success,
+            error_message: None,
+            source: SOURCE_IMPORT.to_string(),
+            regenerated_from: None,
+            downgraded_from: None,
+            key_fingerprint: None,
+            app_version: None,
+            profile: DEFAULT_PROFILE.to_string(),
+            latency_ms: None,
+            chunk_count: None,
+        })
+    }
+}
+
+/// The records in `csv`, and the rows that couldn't be read. Fails outright
+/// when the header is missing a mapped column.
+pub fn parse(csv: impl Read, mapping: &CsvMapping) -> Result<(Vec<UsageRecord>, Vec<RowError>)> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(csv);
+    let columns = Columns::find(reader.headers()?, mapping)?;
+
+    let mut records = Vec::new();
+    let mut errors = Vec::new();
+    for row in reader.records() {
+        let row = match row {
+            Ok(row) => row,
+            Err(e) => {
+                let line = e.position().map_or(0, |position| position.line());
+                errors.push(RowError { line, message: e.to_string() });
+                continue;
+            }
+        };
+        let line = row.position().map_or(0, |position| position.line());
+        match columns.record(&row, mapping) {
+            Ok(record) => records.push(record),
+            Err(message) => errors.push(RowError { line, message }),
+        }
+    }
+    Ok((records, errors))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPORT: &str = include_str!("../tests/fixtures/usage-export.csv");
+
+    fn mapping() -> CsvMapping {
+        CsvMapping {
+            timestamp: "Date".to_string(),
+            characters: "Chars".to_string(),
+            voice: "Voice".to_string(),
+            model: "Model".to_string(),
+            success: "Status".to_string(),
+            text: Some("Text".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_rows_and_errors_with_line_numbers() {
+        let (records, errors) = parse(EXPORT.as_bytes(), &mapping()).unwrap();
+        assert_eq!(records.len(), 5);
+        assert!(records.iter().all(|r| r.source == SOURCE_IMPORT));
+        assert_eq!(records[0].timestamp.to_rfc3339(), "2026-03-02T09:15:00+00:00");
+        assert_eq!(records[1].timestamp.to_rfc3339(), "2026-03-02T12:00:00+00:00");
+        assert_eq!(records[2].text, "Quoted, with a comma");
+        assert!(!records[3].success);
+
+        let errors: Vec<_> = errors.iter().map(|e| (e.line, e.message.as_str())).collect();
+        assert_eq!(
+            errors,
+            [
+                (5, "invalid timestamp \"yesterday\""),
+                (7, "invalid character count \"-12\""),
+                (8, "no value for \"Model\""),
+                (10, "invalid success value \"maybe\""),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_header_must_have_the_mapped_columns() {
+        let mapping = CsvMapping { voice: "Speaker".to_string(), ..mapping() };
+        let error = parse(EXPORT.as_bytes(), &mapping).unwrap_err();
+        assert_eq!(error.to_string(), "No column \"Speaker\" in the CSV header");
+
+        // Text is optional
+        let mapping = CsvMapping { text: None, ..self::mapping() };
+        let (records, _) = parse(EXPORT.as_bytes(), &mapping).unwrap();
+        assert!(records.iter().all(|r| r.text.is_empty()));
+    }
+}
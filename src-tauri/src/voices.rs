@@ -0,0 +1,304 @@
+//! The voices the speech endpoint offers, described for the picker. This
+//! table is the only list: `tts::VALID_VOICE_IDS`, and so every validation
+//! of a voice id, is derived from it. A backend with voices of its own
+//! would get a table of the same shape.
+
+use crate::database::VoiceUsage;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VoiceInfo {
+    pub id: &'static str,
+    pub name: &'static str,
+    pub description: &'static str,
+    pub gender: &'static str,
+    pub style: &'static [&'static str],
+    /// BCP 47 tags of the accents it sounds most natural in, e.g. "en-GB".
+    /// Every voice reads other languages too, just less natively.
+    pub languages: &'static [&'static str],
+    /// Models that can speak with this voice
+    pub models: &'static [&'static str],
+}
+
+const CLASSIC_MODELS: &[&str] = &["tts-1", "tts-1-hd"];
+const AMERICAN: &[&str] = &["en-US"];
+
+/// A voice as `get_available_voices` returns it
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AvailableVoice {
+    #[serde(flatten)]
+    pub info: &'static VoiceInfo,
+    pub preview_cached: bool,
+}
+
+/// Most used voices the picker floats to the top
+pub const FREQUENT_VOICES: usize = 3;
+
+/// A voice with how much it was used lately, as `get_voice_usage_summary`
+/// returns it
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VoiceUsageSummary {
+    pub voice_id: String,
+    /// `None` for a voice in the history that is no longer offered, such as
+    /// one from before the switch to OpenAI
+    pub voice: Option<&'static VoiceInfo>,
+    /// It can't be chosen any more; its usage is shown for the record
+    pub legacy: bool,
+    /// One of the `FREQUENT_VOICES` most used
+    pub frequent: bool,
+    pub request_count: i64,
+    pub character_count: i64,
+    pub last_used: Option<DateTime<Utc>>,
+}
+
+pub const VOICES: &[VoiceInfo] = &[
+    VoiceInfo {
+        id: "alloy",
+        name: "Alloy",
+        description: "Neutral, versatile",
+        gender: "neutral",
+        style: &["balanced"],
+        languages: AMERICAN,
+        models: CLASSIC_MODELS,
+    },
+    VoiceInfo {
+        id: "ash",
+        name: "Ash",
+        description: "Clear, conversational male",
+        gender: "male",
+        style: &["conversational"],
+        languages: AMERICAN,
+        models: CLASSIC_MODELS,
+    },
+    VoiceInfo {
+        id: "coral",
+        name: "Coral",
+        description: "Warm, friendly female",
+        gender: "female",
+        style: &["warm", "friendly"],
+        languages: AMERICAN,
+        models: CLASSIC_MODELS,
+    },
+    VoiceInfo {
+        id: "echo",
+        name: "Echo",
+        description: "Male voice",
+        gender: "male",
+        style: &["calm"],
+        languages: AMERICAN,
+        models: CLASSIC_MODELS,
+    },
+    VoiceInfo {
+        id: "fable",
+        name: "Fable",
+        description: "British accent",
+        gender: "neutral",
+        style: &["british", "storytelling"],
+        languages: &["en-GB"],
+        models: CLASSIC_MODELS,
+    },
+    VoiceInfo {
+        id: "onyx",
+        name: "Onyx",
+        description: "Deep male voice",
+        gender: "male",
+        style: &["deep", "authoritative"],
+        languages: AMERICAN,
+        models: CLASSIC_MODELS,
+    },
+    VoiceInfo {
+        id: "nova",
+        name: "Nova",
+        description: "Natural female voice",
+        gender: "female",
+        style: &["natural", "bright"],
+        languages: AMERICAN,
+        models: CLASSIC_MODELS,
+    },
+    VoiceInfo {
+        id: "sage",
+        name: "Sage",
+        description: "Measured, thoughtful female",
+        gender: "female",
+        style: &["measured"],
+        languages: AMERICAN,
+        models: CLASSIC_MODELS,
+    },
+    VoiceInfo {
+        id: "shimmer",
+        name: "Shimmer",
+        description: "Expressive female",
+        gender: "female",
+        style: &["expressive"],
+        languages: AMERICAN,
+        models: CLASSIC_MODELS,
+    },
+];
+
+/// The ids in `VOICES`, in the same order
+pub const VOICE_IDS: [&str; VOICES.len()] = {
+    let mut ids = [""; VOICES.len()];
+    let mut i = 0;
+    while i < VOICES.len() {
+        ids[i] = VOICES[i].id;
+        i += 1;
+    }
+    ids
+};
+
+/// Audio id under which a voice's preview sample is kept (see `file_manager`)
+pub fn preview_id(voice_id: &str) -> String {
+    format!("preview-{}", voice_id)
+}
+
+pub fn find(id: &str) -> Option<&'static VoiceInfo> {
+    VOICES.iter().find(|voice| voice.id == id)
+}
+
+/// The voice to read text in `language` (a BCP 47 tag) with, given the
+/// user's `current` one: a voice with that exact accent if `current` lacks
+/// it, otherwise `current`, which reads every language
+pub fn for_language(language: &str, current: &'static VoiceInfo) -> &'static VoiceInfo {
+    let speaks = |voice: &VoiceInfo| voice.languages.iter().any(|tag| tag.eq_ignore_ascii_case(language));
+    if speaks(current) {
+        return current;
+    }
+    VOICES.iter().find(|voice| speaks(voice)).unwrap_or(current)
+}
+
+/// Voices usable with `model`, or all of them
+pub fn for_model(model: Option<&str>) -> impl Iterator<Item = &'static VoiceInfo> + '_ {
+    VOICES.iter().filter(move |voice| model.is_none_or(|model| voice.models.contains(&model)))
+}
+
+/// Every voice with its `usage`, the most used first and the unused in
+/// table order, then the retired voices found in `usage`, flagged legacy
+pub fn usage_summary(usage: &[VoiceUsage]) -> Vec<VoiceUsageSummary> {
+    let summary = |voice_id: &str, voice: Option<&'static VoiceInfo>| {
+        let used = usage.iter().find(|used| used.voice_id == voice_id);
+        VoiceUsageSummary {
+            voice_id: voice_id.to_string(),
+            voice,
+            legacy: voice.is_none(),
+            frequent: false,
+            request_count: used.map_or(0, |used| used.request_count),
+            character_count: used.map_or(0, |used| used.character_count),
+            last_used: used.map(|used| used.last_used),
+        }
+    };
+    let mut voices: Vec<VoiceUsageSummary> = VOICES.iter().map(|voice| summary(voice.id, Some(voice))).collect();
+    voices.sort_by(|a, b| b.request_count.cmp(&a.request_count).then(b.last_used.cmp(&a.last_used)));
+    for voice in voices.iter_mut().take(FREQUENT_VOICES).filter(|voice| voice.request_count > 0) {
+        voice.frequent = true;
+    }
+    voices.extend(usage.iter().filter(|used| find(&used.voice_id).is_none()).map(|used| summary(&used.voice_id, None)));
+    voices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tts::{TTSService, VALID_MODEL_IDS, VALID_VOICE_IDS};
+
+    #[test]
+    fn test_validation_uses_this_table() {
+        assert_eq!(VALID_VOICE_IDS, VOICE_IDS.as_slice());
+        let service = TTSService::new("key", "http://localhost");
+        for voice in VOICES {
+            assert!(service.is_valid_voice(voice.id), "{}", voice.id);
+            assert!(voice.models.iter().all(|model| VALID_MODEL_IDS.contains(model)), "{}", voice.id);
+        }
+        assert!(!service.is_valid_voice("rachel"));
+    }
+
+    #[test]
+    fn test_every_voice_is_described() {
+        for id in VALID_VOICE_IDS {
+            let voice = find(id).unwrap_or_else(|| panic!("no entry for {}", id));
+            assert!(!voice.name.is_empty() && !voice.description.is_empty(), "{}", id);
+            assert!(["female", "male", "neutral"].contains(&voice.gender), "{}", id);
+            assert!(!voice.style.is_empty() && !voice.languages.is_empty() && !voice.models.is_empty(), "{}", id);
+        }
+    }
+
+    #[test]
+    fn test_voice_for_language() {
+        let nova = find("nova").unwrap();
+        assert_eq!(for_language("en-GB", nova).id, "fable");
+        assert_eq!(for_language("EN-gb", nova).id, "fable");
+        // Nobody is better at it than the voice already chosen
+        assert_eq!(for_language("en-US", nova).id, "nova");
+        assert_eq!(for_language("de", nova).id, "nova");
+        let fable = find("fable").unwrap();
+        assert_eq!(for_language("en-GB", fable).id, "fable");
+        assert_eq!(for_language("en-US", fable).id, "alloy");
+    }
+
+    #[test]
+    fn test_ids_are_unique() {
+        let mut ids = VOICE_IDS.to_vec();
+        ids.sort_unstable();
+        ids.dedup();
+        assert_eq!(ids.len(), VOICES.len());
+    }
+
+    #[test]
+    fn test_serialization() {
+        let json = serde_json::to_value(find("onyx").unwrap()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "id": "onyx",
+                "name": "Onyx",
+                "description": "Deep male voice",
+                "gender": "male",
+                "style": ["deep", "authoritative"],
+                "languages": ["en-US"],
+                "models": ["tts-1", "tts-1-hd"],
+            })
+        );
+        let available = AvailableVoice { info: find("nova").unwrap(), preview_cached: true };
+        let json = serde_json::to_value(available).unwrap();
+        assert_eq!((json["id"].as_str(), json["previewCached"].as_bool()), (Some("nova"), Some(true)));
+
+        assert_eq!(for_model(Some("tts-1")).count(), VOICES.len());
+        assert_eq!(for_model(Some("whisper-1")).count(), 0);
+    }
+
+    #[test]
+    fn test_usage_summary() {
+        let at = |hour| DateTime::from_timestamp(hour * 3600, 0).unwrap();
+        let used = |voice_id: &str, request_count: i64, last_used: i64| VoiceUsage {
+            voice_id: voice_id.to_string(),
+            request_count,
+            character_count: request_count * 100,
+            last_used: at(last_used),
+        };
+        // Already most used first, as the database returns it
+        let usage = [used("rachel", 50, 1), used("nova", 9, 5), used("sage", 4, 2), used("echo", 4, 3), used("onyx", 1, 9)];
+        let summary = usage_summary(&usage);
+
+        let ids: Vec<&str> = summary.iter().map(|voice| voice.voice_id.as_str()).collect();
+        assert_eq!(ids, ["nova", "echo", "sage", "onyx", "alloy", "ash", "coral", "fable", "shimmer", "rachel"]);
+        let frequent: Vec<&str> = summary.iter().filter(|voice| voice.frequent).map(|voice| voice.voice_id.as_str()).collect();
+        assert_eq!(frequent, ["nova", "echo", "sage"]);
+
+        let rachel = summary.last().unwrap();
+        assert!(rachel.legacy && rachel.voice.is_none() && !rachel.frequent);
+        assert_eq!((rachel.request_count, rachel.character_count), (50, 5000));
+        let nova = &summary[0];
+        assert_eq!((nova.voice.unwrap().name, nova.last_used), ("Nova", Some(at(5))));
+        let alloy = serde_json::to_value(&summary[4]).unwrap();
+        assert_eq!(alloy["voice"]["id"], "alloy");
+        assert_eq!((alloy["requestCount"].as_i64(), alloy["lastUsed"].is_null(), alloy["legacy"].as_bool()), (Some(0), true, Some(false)));
+
+        // Fewer than three used, fewer float up
+        let summary = usage_summary(&[used("fable", 2, 1)]);
+        assert_eq!(summary.iter().filter(|voice| voice.frequent).count(), 1);
+        assert_eq!(summary.len(), VOICES.len());
+    }
+}
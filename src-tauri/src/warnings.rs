@@ -0,0 +1,155 @@
+//! Things about a generation worth telling the user that didn't stop it: a
+//! request that needed retrying, audio shared with an identical request,
+//! a cheaper model near the budget. Code anywhere in the pipeline reports
+//! them with `warn`; whoever runs the generation gathers them with
+//! `collect` and hands them back beside the audio.
+
+use crate::i18n::{self, Message};
+use crate::spend::Downgrade;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::cell::RefCell;
+use std::future::Future;
+
+tokio::task_local! {
+    static COLLECTED: RefCell<Vec<Warning>>;
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Warning {
+    /// Stable, e.g. "warning.retried", for the window to pick a notice by
+    pub code: &'static str,
+    /// In the locale from `i18n::set_locale`
+    pub message: String,
+    /// The details the message was made from
+    pub data: Map<String, Value>,
+}
+
+impl From<Message> for Warning {
+    fn from(message: Message) -> Self {
+        Self { code: message.key, message: message.translate(i18n::locale()), data: message.params }
+    }
+}
+
+/// Report `message` to the `collect` this runs under, if any, and log it
+pub fn warn(message: Message) {
+    tracing::warn!("{}", message.text);
+    let _ = COLLECTED.try_with(|collected| collected.borrow_mut().push(message.into()));
+}
+
+/// Run `generation`, gathering what it `warn`s about in order
+pub async fn collect<T>(generation: impl Future<Output = T>) -> (T, Vec<Warning>) {
+    COLLECTED
+        .scope(RefCell::default(), async {
+            let output = generation.await;
+            (output, COLLECTED.with(|collected| collected.take()))
+        })
+        .await
+}
+
+/// Request `attempt` (counting from 1) after the previous one failed
+pub fn retried(attempt: u32, error: &str) -> Message {
+    Message::new("warning.retried", format!("A request failed ({}) and was tried again (attempt {})", error, attempt))
+        .with("attempt", attempt)
+        .with("error", error)
+}
+
+/// The audio came from an identical request already running (see `inflight`)
+pub fn shared() -> Message {
+    Message::new("warning.shared", "The same text was already being generated, so its audio was reused")
+}
+
+/// Audio made earlier for the same request was used (e.g. `speed::cached_id`)
+pub fn cached() -> Message {
+    Message::new("warning.cached", "This was made before, so the saved audio was used")
+}
+
+pub fn downgraded(downgrade: &Downgrade) -> Message {
+    Message::new("warning.downgraded", downgrade.reason.clone())
+        .with("from", downgrade.from.as_str())
+        .with("to", downgrade.to.as_str())
+}
+
+/// Long text that FFmpeg wasn't there to join (see `tts::WithoutFfmpeg`)
+pub fn playlist(pieces: usize) -> Message {
+    Message::new(
+        "warning.playlist",
+        format!("FFmpeg could not be run, so the audio is in {} pieces that play one after another", pieces),
+    )
+    .with("pieces", pieces)
+}
+
+/// `TTSService::generate_speech_with_pauses` without FFmpeg
+pub fn pauses_dropped() -> Message {
+    Message::new("warning.pauses_dropped", "FFmpeg could not be run, so the text was read without its pauses")
+}
+
+/// Dead air cut from the ends of the audio, in milliseconds (see `trim`)
+pub fn silence_trimmed(start_ms: u64, end_ms: u64) -> Message {
+    Message::new(
+        "warning.silence_trimmed",
+        format!("{} ms of silence was trimmed from the start and {} ms from the end", start_ms, end_ms),
+    )
+    .with("start_ms", start_ms)
+    .with("end_ms", end_ms)
+}
+
+/// A text file without a BOM that wasn't UTF-8 (see `encoding::decode`)
+pub fn reencoded(encoding: &str) -> Message {
+    Message::new(
+        "warning.reencoded",
+        format!("The file wasn't saved as UTF-8 and was read as {}; check that accented letters came out right", encoding),
+    )
+    .with("encoding", encoding)
+}
+
+/// Characters in a text file that couldn't be decoded
+pub fn replaced(count: usize) -> Message {
+    Message::new("warning.replaced", format!("{} characters in the file couldn't be read and are missing", count))
+        .with("count", count)
+}
+
+/// Text read from an image that Tesseract wasn't sure of (see `ocr`)
+pub fn low_confidence(confidence: f32) -> Message {
+    let percent = confidence.round() as u32;
+    Message::new(
+        "warning.low_confidence",
+        format!("The text was read from an image with {}% confidence; check it for mistakes", percent),
+    )
+    .with("confidence", percent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_collects_in_order_within_the_scope_only() {
+        warn(shared()); // nobody collecting; only logged
+        let (output, warnings) = collect(async {
+            warn(retried(2, "HTTP 500"));
+            tokio::task::yield_now().await;
+            warn(playlist(3));
+            "audio"
+        })
+        .await;
+        assert_eq!(output, "audio");
+        let codes: Vec<&str> = warnings.iter().map(|warning| warning.code).collect();
+        assert_eq!(codes, ["warning.retried", "warning.playlist"]);
+        assert_eq!(warnings[0].data["attempt"], 2);
+        assert_eq!(warnings[1].data["pieces"], 3);
+
+        let (_, none) = collect(async {}).await;
+        assert!(none.is_empty());
+    }
+
+    #[test]
+    fn test_every_warning_is_translated() {
+        let downgrade = Downgrade { from: "tts-1-hd".to_string(), to: "tts-1".to_string(), reason: "Near the budget".to_string() };
+        for message in [retried(2, "HTTP 500"), shared(), cached(), downgraded(&downgrade), playlist(3), pauses_dropped(), silence_trimmed(250, 950), reencoded("windows-1252"), replaced(2)] {
+            let german = message.translate("de");
+            assert_ne!(german, message.text, "no German for {}", message.key);
+            assert!(!german.contains('{'), "unfilled parameter in {:?}", german);
+        }
+    }
+}
@@ -0,0 +1,146 @@
+//! `tts-player watch notes.txt`: speak a file again whenever it's saved.
+//! The file system side is in `headless`; this is the bookkeeping that
+//! decides when a save should actually produce speech.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::time::{Duration, Instant};
+
+/// Editors often write a file several times per save (truncate, write, chmod)
+pub const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Collapses a burst of file events into one, fired once the file has been
+/// quiet for `window`.
+#[derive(Debug)]
+pub struct Debouncer {
+    window: Duration,
+    last_event: Option<Instant>,
+}
+
+impl Debouncer {
+    pub fn new(window: Duration) -> Self {
+        Self { window, last_event: None }
+    }
+
+    pub fn event(&mut self, now: Instant) {
+        self.last_event = Some(now);
+    }
+
+    /// When the pending burst will be considered finished, if there is one
+    pub fn deadline(&self) -> Option<Instant> {
+        self.last_event.map(|last| last + self.window)
+    }
+
+    /// True once per burst, after it has gone quiet
+    pub fn take_ready(&mut self, now: Instant) -> bool {
+        match self.deadline() {
+            Some(deadline) if now >= deadline => {
+                self.last_event = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Remembers what was last spoken so unchanged saves are skipped and, when
+/// text was only appended, just the new part is spoken.
+#[derive(Debug, Default)]
+pub struct ContentTracker {
+    last_hash: Option<u64>,
+    last_content: String,
+}
+
+impl ContentTracker {
+    /// Record `content` as already spoken without producing anything
+    pub fn baseline(&mut self, content: &str) {
+        self.last_hash = Some(hash(content));
+        self.last_content = content.to_string();
+    }
+
+    /// The text to speak for this version of the file, if any
+    pub fn next_text(&mut self, content: &str) -> Option<String> {
+        let content_hash = hash(content);
+        if self.last_hash == Some(content_hash) {
+            return None;
+        }
+
+        let text = match content.strip_prefix(self.last_content.as_str()) {
+            Some(appended) if !self.last_content.is_empty() => appended,
+            _ => content,
+        };
+        let text = text.trim().to_string();
+
+        self.last_hash = Some(content_hash);
+        self.last_content = content.to_string();
+        (!text.is_empty()).then_some(text)
+    }
+}
+
+/// Cheap fingerprint for telling whether text changed
+pub fn hash(content: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_burst_fires_once_after_quiet_period() {
+        let start = Instant::now();
+        let ms = |n| start + Duration::from_millis(n);
+        let mut debouncer = Debouncer::new(Duration::from_millis(300));
+
+        assert!(!debouncer.take_ready(ms(0)));
+        assert_eq!(debouncer.deadline(), None);
+
+        // truncate, write, chmod from a single save
+        debouncer.event(ms(0));
+        debouncer.event(ms(20));
+        debouncer.event(ms(40));
+        assert_eq!(debouncer.deadline(), Some(ms(340)));
+        assert!(!debouncer.take_ready(ms(200)));
+        assert!(debouncer.take_ready(ms(340)));
+        assert!(!debouncer.take_ready(ms(1000)));
+
+        // A later save is a new burst
+        debouncer.event(ms(2000));
+        assert!(!debouncer.take_ready(ms(2100)));
+        assert!(debouncer.take_ready(ms(2300)));
+    }
+
+    #[test]
+    fn test_unchanged_save_is_skipped() {
+        let mut tracker = ContentTracker::default();
+        tracker.baseline("Buy milk\n");
+        assert_eq!(tracker.next_text("Buy milk\n"), None);
+
+        assert_eq!(tracker.next_text("Buy oat milk\n"), Some("Buy oat milk".to_string()));
+        assert_eq!(tracker.next_text("Buy oat milk\n"), None);
+    }
+
+    #[test]
+    fn test_appended_text_only() {
+        let mut tracker = ContentTracker::default();
+        tracker.baseline("First note.\n");
+
+        assert_eq!(tracker.next_text("First note.\nSecond note.\n"), Some("Second note.".to_string()));
+        // Whitespace-only additions say nothing but still move the baseline
+        assert_eq!(tracker.next_text("First note.\nSecond note.\n\n"), None);
+        // An edit in the middle speaks the whole file
+        assert_eq!(
+            tracker.next_text("First note!\nSecond note.\n"),
+            Some("First note!\nSecond note.".to_string())
+        );
+    }
+
+    #[test]
+    fn test_first_content_without_baseline_is_spoken_whole() {
+        let mut tracker = ContentTracker::default();
+        assert_eq!(tracker.next_text("Hello"), Some("Hello".to_string()));
+        assert_eq!(tracker.next_text(""), None);
+    }
+}
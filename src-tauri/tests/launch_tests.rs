@@ -0,0 +1,83 @@
+use tts_player::cli::{launch_request, parse_cli_args, LaunchRequest};
+use tts_player::config::{Config, ConfigFile};
+
+fn args(list: &[&str]) -> Vec<String> {
+    std::iter::once("tts-player").chain(list.iter().copied()).map(String::from).collect()
+}
+
+/// Resolve the config the way startup does, minus the real environment
+fn config_for(cli: &tts_player::cli::CliArgs) -> Result<Config, String> {
+    Config::resolve(ConfigFile::default(), |_| None, cli).map_err(|e| e.to_string())
+}
+
+#[test]
+fn test_text_and_voice_become_launch_payload() {
+    let cli = parse_cli_args(args(&["--text", "Hello there", "--voice", "onyx"])).unwrap();
+    let config = config_for(&cli).unwrap();
+
+    let request = launch_request(&cli, &config).unwrap().unwrap();
+    assert_eq!(
+        request,
+        LaunchRequest {
+            text: "Hello there".to_string(),
+            voice_id: "onyx".to_string(),
+            auto_generate: true,
+            job_id: None,
+            notice: None,
+        }
+    );
+
+    let payload = serde_json::to_value(&request).unwrap();
+    assert_eq!(payload, serde_json::json!({
+            "text": "Hello there",
+            "voiceId": "onyx",
+            "autoGenerate": true,
+            "jobId": null,
+            "notice": null
+        }));
+}
+
+#[test]
+fn test_file_is_read_into_payload() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("notes.txt");
+    std::fs::write(&path, "From a file").unwrap();
+
+    let cli = parse_cli_args(args(&["-f", path.to_str().unwrap()])).unwrap();
+    let request = launch_request(&cli, &config_for(&cli).unwrap()).unwrap().unwrap();
+    assert_eq!(request.text, "From a file");
+    assert_eq!(request.voice_id, "nova");
+
+    let cli = parse_cli_args(args(&["-f", "/does/not/exist.txt"])).unwrap();
+    assert!(launch_request(&cli, &config_for(&cli).unwrap()).unwrap_err().contains("/does/not/exist.txt"));
+}
+
+#[test]
+fn test_file_not_in_utf8_comes_with_a_notice() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let utf8 = dir.path().join("notes.txt");
+    std::fs::write(&utf8, "Café crème").unwrap();
+    let cli = parse_cli_args(args(&["-f", utf8.to_str().unwrap()])).unwrap();
+    let request = launch_request(&cli, &config_for(&cli).unwrap()).unwrap().unwrap();
+    assert_eq!(request.notice, None);
+
+    let exported = dir.path().join("exported.txt");
+    std::fs::write(&exported, include_bytes!("fixtures/windows-1252.txt")).unwrap();
+    let cli = parse_cli_args(args(&["-f", exported.to_str().unwrap()])).unwrap();
+    let request = launch_request(&cli, &config_for(&cli).unwrap()).unwrap().unwrap();
+    assert!(request.text.starts_with("Café crème"));
+    assert!(request.notice.unwrap().contains("windows-1252"));
+}
+
+#[test]
+fn test_no_text_means_no_launch_request() {
+    let cli = parse_cli_args(args(&[])).unwrap();
+    assert_eq!(launch_request(&cli, &config_for(&cli).unwrap()).unwrap(), None);
+}
+
+#[test]
+fn test_invalid_voice_is_an_error_not_a_fallback() {
+    let cli = parse_cli_args(args(&["--text", "Hello", "--voice", "rachel"])).unwrap();
+    let err = config_for(&cli).unwrap_err();
+    assert!(err.contains("rachel"), "{}", err);
+}
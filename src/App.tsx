@@ -1,61 +1,84 @@
 import { useState, useEffect } from 'react';
 import { invoke } from '@tauri-apps/api/core';
-import { getMatches } from '@tauri-apps/plugin-cli';
+import { listen } from '@tauri-apps/api/event';
 import { readText } from '@tauri-apps/plugin-clipboard-manager';
 import { TTSPlayer } from './components/TTSPlayer';
+import { resolveDefaults } from './audio';
 
-interface CliMatches {
-  args: {
-    text?: { value: string };
-    voice?: { value: string };
-    file?: { value: string };
-  };
+interface LaunchRequest {
+  text: string;
+  voiceId: string;
+  autoGenerate: boolean;
+  jobId: string | null;
+  notice: string | null;
+}
+
+interface ServiceStatus {
+  configured: boolean;
+}
+
+interface ApiKeyStatus {
+  configured: boolean;
+  source: 'environment' | 'keychain' | null;
+  masked: string | null;
 }
 
 function App() {
   const [initialText, setInitialText] = useState<string>('');
-  const [initialVoice, setInitialVoice] = useState<string>('nova');
+  // Empty until the defaults load; the backend fills in its default meanwhile
+  const [initialVoice, setInitialVoice] = useState<string>('');
+  const [autoGenerate, setAutoGenerate] = useState<boolean>(true);
+  const [launchJobId, setLaunchJobId] = useState<string | null>(null);
+  const [notice, setNotice] = useState<string>('');
+  const [configured, setConfigured] = useState<boolean>(true);
+  const [apiKeyInput, setApiKeyInput] = useState<string>('');
+  const [apiKeyError, setApiKeyError] = useState<string>('');
+
+  const saveApiKey = async () => {
+    try {
+      const status = await invoke<ApiKeyStatus>('set_api_key', { key: apiKeyInput });
+      setApiKeyInput('');
+      setApiKeyError('');
+      setConfigured(status.configured);
+    } catch (error) {
+      setApiKeyError(String(error));
+    }
+  };
+
+  // The backend may already be generating the text; follow its job then
+  const applyLaunch = (launch: LaunchRequest) => {
+    setInitialVoice(launch.voiceId);
+    setAutoGenerate(launch.autoGenerate && !launch.jobId);
+    setLaunchJobId(launch.jobId);
+    setNotice(launch.notice ?? '');
+    setInitialText(launch.text);
+  };
+
+  useEffect(() => {
+    invoke<ServiceStatus>('get_service_status')
+      .then((status) => setConfigured(status.configured))
+      .catch((error) => console.error('Error loading service status:', error));
+  }, []);
 
   useEffect(() => {
     const loadInitialText = async () => {
       try {
-        // First try CLI arguments
-        const matches = await getMatches() as CliMatches;
-        console.log('CLI matches:', matches);
-        
-        if (matches?.args?.text?.value) {
-          console.log('Raw text arg:', matches.args.text.value);
-          // Decode URL-encoded text
-          const decodedText = decodeURIComponent(matches.args.text.value);
-          console.log('Decoded text:', decodedText);
-          setInitialText(decodedText);
-          
-          if (matches?.args?.voice?.value) {
-            setInitialVoice(matches.args.voice.value);
-          }
+        // First try CLI arguments (--text/--file, parsed by the backend)
+        const launch = await invoke<LaunchRequest | null>('take_launch_request');
+        if (launch) {
+          applyLaunch(launch);
           return; // CLI args worked, don't try other methods
         }
-        
-        // Try file argument
-        if (matches?.args?.file?.value) {
-          console.log('File argument found:', matches.args.file.value);
-          try {
-            const fileContent: string = await invoke('read_text_file', { filePath: matches.args.file.value });
-            console.log('File content loaded:', fileContent);
-            setInitialText(fileContent);
-            
-            if (matches?.args?.voice?.value) {
-              setInitialVoice(matches.args.voice.value);
-            }
-            return; // File loaded successfully
-          } catch (fileError) {
-            console.error('Error reading file:', fileError);
-            // Continue to clipboard fallback
-          }
-        }
       } catch (error) {
         console.error('Error loading CLI args:', error);
       }
+
+      try {
+        const defaults = await resolveDefaults();
+        setInitialVoice(defaults.voiceId);
+      } catch (error) {
+        console.error('Error loading settings:', error);
+      }
       
       // If CLI args didn't work or weren't provided, try clipboard
       try {
@@ -78,12 +101,62 @@ function App() {
     loadInitialText();
   }, []);
 
+  // Launching the app again while it runs hands the new text to this window.
+  // Events sent before this listener existed are held by the backend until
+  // `frontend_ready`.
+  useEffect(() => {
+    const unlisten = listen('launch-request', async () => {
+      try {
+        const launch = await invoke<LaunchRequest | null>('take_launch_request');
+        if (launch) {
+          applyLaunch(launch);
+        }
+      } catch (error) {
+        console.error('Error loading forwarded launch:', error);
+      }
+    });
+    // The player's listeners were registered before this one, so once it is
+    // in place the backend can replay what happened while the page loaded
+    unlisten
+      .then(() => invoke('frontend_ready'))
+      .catch((error) => console.error('Error signalling frontend ready:', error));
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, []);
+
   return (
     <div className="min-h-screen bg-white">
       <div className="max-w-2xl mx-auto px-6 py-12">
+        {!configured && (
+          <div className="mb-6 bg-error/10 text-error px-6 py-4 rounded-2xl text-sm font-medium leading-relaxed">
+            <p>No OpenAI API key is configured. It will be stored in your system keychain.</p>
+            <div className="mt-3 flex gap-2">
+              <input
+                type="password"
+                value={apiKeyInput}
+                onChange={(e) => setApiKeyInput(e.target.value)}
+                onKeyDown={(e) => e.key === 'Enter' && saveApiKey()}
+                placeholder="sk-..."
+                className="flex-1 px-3 py-2 rounded-lg border border-error/30 bg-white text-gray-900"
+              />
+              <button
+                onClick={saveApiKey}
+                disabled={!apiKeyInput.trim()}
+                className="px-4 py-2 rounded-lg bg-error text-white disabled:opacity-50"
+              >
+                Save
+              </button>
+            </div>
+            {apiKeyError && <p className="mt-2">{apiKeyError}</p>}
+          </div>
+        )}
         <TTSPlayer 
           initialText={initialText}
           initialVoice={initialVoice}
+          autoGenerate={autoGenerate && configured}
+          launchJobId={launchJobId}
+          notice={notice}
         />
       </div>
     </div>
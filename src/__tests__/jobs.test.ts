@@ -0,0 +1,90 @@
+import { describe, it, expect, vi, beforeEach } from 'vitest';
+import { createHash, webcrypto } from 'node:crypto';
+
+// Mock Tauri invoke and events
+vi.mock('@tauri-apps/api/core', () => ({
+  invoke: vi.fn()
+}));
+vi.mock('@tauri-apps/api/event', () => ({
+  listen: vi.fn()
+}));
+
+import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
+import { receiveJobResult, type AudioSegment, type SegmentsDone } from '../jobs';
+const mockInvoke = vi.mocked(invoke);
+const mockListen = vi.mocked(listen);
+
+// jsdom has no SubtleCrypto to check the SHA-256 with
+vi.stubGlobal('crypto', webcrypto);
+
+const audio = Uint8Array.from({ length: 10 }, (_, i) => (i * 7) % 251);
+
+const toBase64 = (bytes: Uint8Array) => btoa(String.fromCharCode(...bytes));
+
+/** `bytes` in segments of `size`, as `send_job_result` sends them */
+function segments(jobId: string, bytes: Uint8Array, size: number): AudioSegment[] {
+  const total = Math.ceil(bytes.length / size);
+  return Array.from({ length: total }, (_, index) => ({
+    jobId,
+    index,
+    total,
+    data: toBase64(bytes.subarray(index * size, (index + 1) * size)),
+  }));
+}
+
+function done(jobId: string, bytes: Uint8Array, total: number): SegmentsDone {
+  const sha256 = createHash('sha256').update(bytes).digest('hex');
+  return { jobId, total, size: bytes.length, sha256, format: 'mp3' };
+}
+
+/** Have `send_job_result` emit `sent` as `audio:segment` events, then answer with `reply` */
+function sending(sent: AudioSegment[], reply: SegmentsDone) {
+  const unlisten = vi.fn();
+  mockListen.mockImplementation(async (_event, handler) => {
+    const emit = handler as (event: { payload: AudioSegment }) => void;
+    mockInvoke.mockImplementation(async () => {
+      sent.forEach((payload) => emit({ payload }));
+      return reply;
+    });
+    return unlisten;
+  });
+  return unlisten;
+}
+
+describe('receiveJobResult', () => {
+  beforeEach(() => {
+    vi.clearAllMocks();
+  });
+
+  it('puts segments back in order, ignoring other jobs', async () => {
+    const sent = segments('job-1', audio, 4).reverse();
+    sent.splice(1, 0, ...segments('job-2', Uint8Array.of(1, 2, 3), 4));
+    const unlisten = sending(sent, done('job-1', audio, 3));
+
+    const result = await receiveJobResult('job-1');
+
+    expect(Array.from(result.audio)).toEqual(Array.from(audio));
+    expect(result.format).toBe('mp3');
+    expect(mockListen).toHaveBeenCalledWith('audio:segment', expect.any(Function));
+    expect(mockInvoke).toHaveBeenCalledWith('send_job_result', { jobId: 'job-1' });
+    expect(unlisten).toHaveBeenCalled();
+  });
+
+  it('rejects a missing segment', async () => {
+    const sent = segments('job-1', audio, 4).filter((segment) => segment.index !== 1);
+    const unlisten = sending(sent, done('job-1', audio, 3));
+
+    await expect(receiveJobResult('job-1')).rejects.toThrow('Got 2 of 3 audio segments');
+    expect(unlisten).toHaveBeenCalled();
+  });
+
+  it('rejects audio that does not match the checksum', async () => {
+    const sent = segments('job-1', audio, 4);
+    sent[2] = { ...sent[2], data: toBase64(Uint8Array.of(0, 0)) };
+    const unlisten = sending(sent, done('job-1', audio, 3));
+
+    await expect(receiveJobResult('job-1')).rejects.toThrow('The audio was damaged on the way; ask for it again');
+    expect(unlisten).toHaveBeenCalled();
+  });
+});
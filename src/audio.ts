@@ -0,0 +1,151 @@
+import { convertFileSrc, invoke } from '@tauri-apps/api/core';
+
+/** Something about a generation that didn't stop it, such as a retried request */
+export interface GenerationWarning {
+  /** Stable, e.g. "warning.retried" */
+  code: string;
+  /** Already in the user's language */
+  message: string;
+  data: Record<string, unknown>;
+}
+
+/** Where a voice or model came from: asked for, the settings' default, or the built-in one */
+export type Origin = 'request' | 'settings' | 'builtin';
+
+export interface Resolved {
+  voiceId: string;
+  model: string;
+  voiceFrom: Origin;
+  modelFrom: Origin;
+}
+
+/** The voice and model to start with: the settings', or built-in ones if those are no longer valid */
+export const resolveDefaults = () => invoke<Resolved>('resolve_defaults');
+
+/** A finished generation: a file streamed over ttsaudio://, or a small inline clip */
+export interface GeneratedAudio {
+  id: string;
+  path: string | null;
+  dataUrl: string | null;
+  size: number;
+  /** e.g. "opus" when the format setting is "auto" */
+  format: string;
+  durationSecs: number;
+  /** Shared with an identical generation already running, so not billed twice */
+  coalesced: boolean;
+  /** Generated with tts-1 instead of the chosen model because the month is near its budget */
+  downgraded: boolean;
+  downgradeReason: string | null;
+  /** Long text FFmpeg couldn't join: every piece in order, this audio being the first */
+  playlist: GeneratedAudio[];
+  warnings: GenerationWarning[];
+  /** What it was made with; null for audio that wasn't newly generated */
+  resolved: Resolved | null;
+  /** Silence added after each sentence and paragraph, for audio from a job */
+  gaps: Gaps | null;
+}
+
+/** Milliseconds, 0 to 5000; 0 leaves the pacing to the voice */
+export interface Gaps {
+  sentenceMs: number;
+  paragraphMs: number;
+}
+
+/** URL for an <audio> element; the stream supports range requests for seeking */
+export const audioSource = (audio: GeneratedAudio) =>
+  audio.dataUrl ?? convertFileSrc(audio.id, 'ttsaudio');
+
+/** Let the backend delete the file once nothing plays it any more */
+export function releaseAudio(audio: GeneratedAudio | null) {
+  const paths = new Set([audio?.path, ...(audio?.playlist ?? []).map((piece) => piece.path)]);
+  for (const path of paths) {
+    if (path) invoke('release_audio', { path }).catch(() => {});
+  }
+}
+
+/** "corrupted" when the file is no longer what was generated, e.g. cut short */
+export type AudioStatus = 'ok' | 'corrupted' | 'missing' | 'unverified';
+
+export interface AudioFile {
+  id: string;
+  path: string;
+  /** Bytes on disk; null when missing */
+  size: number | null;
+  status: AudioStatus;
+}
+
+/** Generated audio on disk; only the length is checked */
+export const listAudioFiles = () => invoke<AudioFile[]>('list_audio_files');
+
+/** Reads every saved file, project audio included, and compares its SHA-256 */
+export const verifyLibrary = () => invoke<AudioFile[]>('verify_library');
+
+/**
+ * The same audio at `factor` times the speed, made from the file rather than
+ * generated again. Without `preservePitch` it also sounds higher or lower.
+ */
+export const changeSpeed = (audio: GeneratedAudio, factor: number, preservePitch = true) =>
+  invoke<GeneratedAudio>('transform_audio_speed', { source: { kind: 'audio', id: audio.id }, factor, preservePitch });
+
+export type TranscodeFormat = 'wav' | 'flac' | 'mp3' | 'ogg' | 'opus' | 'm4a';
+
+/** Left out: as the source has it, or the encoder's default */
+export interface TranscodeOptions {
+  sampleRate?: number;
+  /** kbit/s; lossy formats only */
+  bitrateKbps?: number;
+  /** 1 for mono, 2 for stereo */
+  channels?: 1 | 2;
+}
+
+/** A copy of `audio` in another format, converted with FFmpeg */
+export const transcodeAudio = (audio: GeneratedAudio, targetFormat: TranscodeFormat, options: TranscodeOptions = {}) =>
+  invoke<GeneratedAudio>('transcode_audio', { source: { kind: 'audio', id: audio.id }, targetFormat, options });
+
+/** What to change when generating a history entry again; the rest is reused */
+export interface RegenerateOverrides {
+  voiceId?: string;
+  model?: string;
+  speed?: number;
+}
+
+/** Generate history record `recordId` again; fails if only a preview of its text was kept */
+export const regenerateFromRecord = (recordId: number, overrides: RegenerateOverrides = {}) =>
+  invoke<GeneratedAudio>('regenerate_from_record', { recordId, overrides });
+
+export interface ModelCost {
+  model: string;
+  estimatedCost: number;
+}
+
+/** Pre-generation summary of `text`; cheap enough to call on every (debounced) keystroke */
+export interface TextAnalysis {
+  characters: number;
+  words: number;
+  sentences: number;
+  chunks: number;
+  estimatedDurationSecs: number;
+  estimatedCost: number;
+  costs: ModelCost[];
+}
+
+export const analyzeText = (text: string, model: string) => invoke<TextAnalysis>('analyze_text', { text, model });
+
+export interface ChunkPreview {
+  index: number;
+  /** In characters of the text as sent (typography normalized); end is exclusive */
+  start: number;
+  end: number;
+  characters: number;
+  head: string;
+  tail: string;
+}
+
+export const previewChunks = (text: string, options?: { chunkSize?: number }) =>
+  invoke<ChunkPreview[]>('preview_chunks', { text, options });
+
+/**
+ * Show a file in Finder or Explorer (on Linux, open its folder). Only files the
+ * app wrote, or that were exported this session, can be shown.
+ */
+export const revealInFileManager = (path: string) => invoke<void>('reveal_in_file_manager', { path });
@@ -1,4 +1,5 @@
 import React, { useState, useRef, useEffect } from 'react';
+import { listen } from '@tauri-apps/api/event';
 import { Play, Pause } from 'lucide-react';
 
 interface CompactMediaPlayerProps {
@@ -96,6 +97,19 @@ export function CompactMediaPlayer({
     };
   }, [audioSrc, autoplay]);
 
+  // "Stop" in the tray menu
+  useEffect(() => {
+    const unlisten = listen('playback:stop', () => {
+      const audio = audioRef.current;
+      if (!audio) return;
+      audio.pause();
+      audio.currentTime = 0;
+    });
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, []);
+
   const togglePlayPause = async () => {
     const audio = audioRef.current;
     if (!audio) return;
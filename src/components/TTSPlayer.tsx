@@ -1,27 +1,58 @@
-import { useState, useEffect, useCallback } from 'react';
+import { useState, useEffect, useCallback, useRef } from 'react';
 import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
+import { followJob, generateWithJob, JobCancelledError } from '../jobs';
+import { audioSource, releaseAudio, type GeneratedAudio, type GenerationWarning } from '../audio';
+import { correlationIdOf, formatLogs, getRecentLogs } from '../logs';
+import { errorMessage, isCommandError } from '../errors';
 import { CompactMediaPlayer } from './CompactMediaPlayer';
 import { CharacterCounter } from './CharacterCounter';
 import { UsageStatsDisplay } from './UsageStatsDisplay';
 
 interface TTSError {
-  type: 'auth' | 'rate_limit' | 'network' | 'unknown';
+  type: 'not_configured' | 'auth' | 'rate_limit' | 'network' | 'unknown';
   message: string;
   retryAfter?: number;
 }
 
 function parseError(error: unknown): TTSError {
-  const errorMessage = error instanceof Error ? error.message : String(error);
-  
-  if (errorMessage.includes('401') || errorMessage.includes('API key')) {
+  if (isCommandError(error)) {
+    // Already in the user's language
+    switch (error.key) {
+      case 'error.not_configured':
+        return { type: 'not_configured', message: error.message };
+      case 'error.authentication':
+        return { type: 'auth', message: error.message };
+      case 'error.rate_limited':
+      case 'error.rate_limited_no_wait': {
+        const seconds = typeof error.params.seconds === 'number' ? error.params.seconds : 60;
+        return { type: 'rate_limit', message: error.message, retryAfter: seconds };
+      }
+      case 'error.offline':
+      case 'error.network':
+        return { type: 'network', message: error.message };
+      default:
+        return { type: 'unknown', message: error.message };
+    }
+  }
+
+  const message = errorMessage(error);
+  if (message.includes('not configured')) {
+    return {
+      type: 'not_configured',
+      message: 'No OpenAI API key is configured.'
+    };
+  }
+
+  if (message.includes('401') || message.includes('API key')) {
     return {
       type: 'auth',
       message: 'Authentication failed. Please check your API key.'
     };
   }
   
-  if (errorMessage.includes('429') || errorMessage.includes('rate limit')) {
-    const retryMatch = errorMessage.match(/retry.*?(\d+)/i);
+  if (message.includes('429') || message.includes('rate limit')) {
+    const retryMatch = message.match(/retry.*?(\d+)/i);
     const seconds = retryMatch ? parseInt(retryMatch[1], 10) : 60;
     return {
       type: 'rate_limit',
@@ -30,7 +61,7 @@ function parseError(error: unknown): TTSError {
     };
   }
   
-  if (errorMessage.includes('network') || errorMessage.includes('fetch')) {
+  if (message.includes('network') || message.includes('fetch')) {
     return {
       type: 'network',
       message: 'Network error. Please check your internet connection.'
@@ -39,74 +70,233 @@ function parseError(error: unknown): TTSError {
   
   return {
     type: 'unknown',
-    message: errorMessage
+    message
   };
 }
 
+interface VoiceOption {
+  id: string;
+  name: string;
+  description: string;
+  gender?: string;
+  style?: string[];
+  languages?: string[];
+  models?: string[];
+  previewCached?: boolean;
+}
+
+/** A document's text, from `open_text_file` or the `text:loaded` event */
+interface LoadedText {
+  text: string;
+  source: string;
+  characterCount: number;
+  warnings: GenerationWarning[];
+}
+
+const FALLBACK_VOICES: VoiceOption[] = [
+  { id: 'nova', name: 'Nova', description: 'Natural female voice' },
+  { id: 'alloy', name: 'Alloy', description: 'Neutral, versatile' },
+];
+
 interface TTSPlayerProps {
   initialText?: string;
   initialVoice?: string;
+  autoGenerate?: boolean;
+  /** A job the backend started for the launch text, to play when it finishes */
+  launchJobId?: string | null;
+  /** Shown above the text, e.g. that an unknown voice was replaced */
+  notice?: string;
 }
 
-export function TTSPlayer({ initialText = '', initialVoice = 'nova' }: TTSPlayerProps) {
+export function TTSPlayer({
+  initialText = '',
+  initialVoice = '',
+  autoGenerate = true,
+  launchJobId = null,
+  notice = '',
+}: TTSPlayerProps) {
   const [text, setText] = useState(initialText);
   const [voice, setVoice] = useState(initialVoice);
   const [isGenerating, setIsGenerating] = useState(false);
   const [audioSrc, setAudioSrc] = useState<string>('');
   const [audioSrcs, setAudioSrcs] = useState<string[]>([]);
   const [currentChunkIndex, setCurrentChunkIndex] = useState(0);
+  // Notices about the audio shown, e.g. that a cheaper model was used
+  const [warnings, setWarnings] = useState<GenerationWarning[]>([]);
   const [error, setError] = useState<string>('');
+  // Which backend log lines explain the error, for the copy-details button
+  const [errorRef, setErrorRef] = useState<string | null>(null);
   const [showUsageStats, setShowUsageStats] = useState(false);
   const [isFocused, setIsFocused] = useState(false);
   const [shouldAutoplay, setShouldAutoplay] = useState(false);
   const [showVoiceSelector, setShowVoiceSelector] = useState(false);
+  // Only the most recent request may update the player
+  const latestJobId = useRef<string | null>(null);
+  const requestCount = useRef(0);
+  // The file behind audioSrc, released when something replaces it
+  const currentAudio = useRef<GeneratedAudio | null>(null);
+
+  const showAudio = useCallback((audio: GeneratedAudio) => {
+    releaseAudio(currentAudio.current);
+    currentAudio.current = audio;
+    setAudioSrc(audioSource(audio));
+    // Pieces of long text that couldn't be joined play one after another
+    setAudioSrcs(audio.playlist.map(audioSource));
+    setCurrentChunkIndex(0);
+    setWarnings(audio.warnings);
+  }, []);
+
+  useEffect(() => () => releaseAudio(currentAudio.current), []);
 
-  const availableVoices = [
-    { id: 'nova', name: 'Nova', description: 'Natural female voice' },
-    { id: 'shimmer', name: 'Shimmer', description: 'Expressive female' },
-    { id: 'alloy', name: 'Alloy', description: 'Neutral, versatile' },
-    { id: 'echo', name: 'Echo', description: 'Male voice' },
-    { id: 'fable', name: 'Fable', description: 'British accent' },
-    { id: 'onyx', name: 'Onyx', description: 'Deep male voice' },
-  ];
+  useEffect(() => {
+    if (!error) setErrorRef(null);
+  }, [error]);
+
+  const showError = useCallback((err: unknown) => {
+    setError(parseError(err).message);
+    setErrorRef(correlationIdOf(err));
+  }, []);
+
+  const copyErrorDetails = useCallback(async () => {
+    const lines = await getRecentLogs(errorRef);
+    await navigator.clipboard.writeText([error, formatLogs(lines)].join('\n\n'));
+  }, [error, errorRef]);
+
+  /**
+   * Resolves to null when a newer request replaced this one. With `existingJobId`
+   * it waits for a job the backend already queued instead of queueing one.
+   */
+  const runGeneration = useCallback(async (textToSpeak: string, voiceId: string, existingJobId?: string) => {
+    const request = ++requestCount.current;
+    try {
+      let jobId: string | null = null;
+      const claim = (id: string) => {
+        const previous = latestJobId.current;
+        if (previous) {
+          invoke('cancel_job', { jobId: previous }).catch(() => {});
+        }
+        jobId = id;
+        latestJobId.current = id;
+      };
+      let audio: GeneratedAudio;
+      if (existingJobId) {
+        claim(existingJobId);
+        audio = await followJob(existingJobId);
+      } else {
+        audio = await generateWithJob(textToSpeak, voiceId, claim);
+      }
+      if (jobId !== latestJobId.current) {
+        releaseAudio(audio);
+        return null;
+      }
+      return audio;
+    } finally {
+      if (request === requestCount.current) {
+        setIsGenerating(false);
+      }
+    }
+  }, []);
+
+  // The backend's voice table; the fallback only covers the first render
+  const [availableVoices, setAvailableVoices] = useState<VoiceOption[]>(FALLBACK_VOICES);
+
+  useEffect(() => {
+    invoke<VoiceOption[]>('get_available_voices')
+      .then((voices) => {
+        if (voices.length > 0) setAvailableVoices(voices);
+      })
+      .catch(() => {});
+  }, []);
 
   const currentVoiceName = availableVoices.find(v => v.id === voice)?.name || 'Nova';
 
   useEffect(() => {
     setText(initialText);
     // Automatically generate speech when text is loaded from clipboard/CLI
-    if (initialText && initialText.trim()) {
+    if (autoGenerate && initialText && initialText.trim()) {
       // Call the generation function directly with the initial text
       generateSpeechAuto(initialText.trim(), voice);
     }
-  }, [initialText, voice]);
+  }, [initialText, voice, autoGenerate]);
 
   useEffect(() => {
     setVoice(initialVoice);
   }, [initialVoice]);
 
+  // Text from the command line is already generating in the backend
+  useEffect(() => {
+    if (launchJobId) {
+      generateSpeechAuto('', '', launchJobId);
+    }
+  }, [launchJobId]);
+
+  // tts-player:// links and the tray's "Speak clipboard" are generated in the
+  // backend; just show and play the result
+  useEffect(() => {
+    const play = (event: { payload: { text: string; voiceId: string; audio: GeneratedAudio } }) => {
+      setText(event.payload.text);
+      setVoice(event.payload.voiceId);
+      setError('');
+      setShouldAutoplay(true);
+      showAudio(event.payload.audio);
+    };
+    const unlistenLink = listen<{ text: string; voiceId: string; audio: GeneratedAudio }>('deep-link-speech', play);
+    const unlistenClipboard = listen<{ text: string; voiceId: string; audio: GeneratedAudio }>('clipboard-speech', play);
+    return () => {
+      unlistenLink.then((fn) => fn());
+      unlistenClipboard.then((fn) => fn());
+    };
+  }, [showAudio]);
+
+  // Files dropped on the window arrive as extracted text, one event per file
+  useEffect(() => {
+    const unlistenLoaded = listen<LoadedText>('text:loaded', (event) => {
+      setText(event.payload.text);
+      setError('');
+      setWarnings(event.payload.warnings); // e.g. a text file that wasn't UTF-8
+    });
+    const unlistenFailed = listen<{ source: string; reason: string }>('text:load_error', (event) => {
+      const { source, reason } = event.payload;
+      setError(source ? `${source}: ${reason}` : reason);
+      setErrorRef(null);
+    });
+    return () => {
+      unlistenLoaded.then((fn) => fn());
+      unlistenFailed.then((fn) => fn());
+    };
+  }, []);
+
+  const openFile = useCallback(async () => {
+    try {
+      const loaded = await invoke<LoadedText | null>('open_text_file');
+      if (loaded) {
+        setText(loaded.text);
+        setError('');
+        setWarnings(loaded.warnings);
+      }
+    } catch (err) {
+      setError(errorMessage(err));
+      setErrorRef(correlationIdOf(err));
+    }
+  }, []);
+
   // Separate function for auto-generation
-  const generateSpeechAuto = useCallback(async (textToSpeak: string, voiceId: string) => {
+  const generateSpeechAuto = useCallback(async (textToSpeak: string, voiceId: string, existingJobId?: string) => {
     setIsGenerating(true);
     setError('');
     setShouldAutoplay(true); // Enable autoplay for auto-generated speech
 
     try {
-      const audioPath: string = await invoke('generate_speech', {
-        text: textToSpeak,
-        voiceId: voiceId,
-      });
-      
-      setAudioSrc(audioPath);
-      setAudioSrcs([]);
+      const audio = await runGeneration(textToSpeak, voiceId, existingJobId);
+      if (audio === null) return; // superseded by a newer request
+
+      showAudio(audio);
       // Keep text for manual editing/regeneration instead of nuclear clear
     } catch (err) {
-      const parsedError = parseError(err);
-      setError(parsedError.message);
-    } finally {
-      setIsGenerating(false);
+      if (err instanceof JobCancelledError) return;
+      showError(err);
     }
-  }, []);
+  }, [runGeneration, showAudio, showError]);
 
   const handleGenerate = async () => {
     if (!text.trim()) return;
@@ -116,19 +306,14 @@ export function TTSPlayer({ initialText = '', initialVoice = 'nova' }: TTSPlayer
     setShouldAutoplay(false); // Don't autoplay for manual generation
 
     try {
-      const audioPath: string = await invoke('generate_speech', {
-        text: text.trim(),
-        voiceId: voice,
-      });
-      
-      setAudioSrc(audioPath);
-      setAudioSrcs([]);
+      const audio = await runGeneration(text.trim(), voice);
+      if (audio === null) return; // superseded by a newer request
+
+      showAudio(audio);
       // Keep text for manual editing/regeneration instead of nuclear clear
     } catch (err) {
-      const parsedError = parseError(err);
-      setError(parsedError.message);
-    } finally {
-      setIsGenerating(false);
+      if (err instanceof JobCancelledError) return;
+      showError(err);
     }
   };
 
@@ -182,6 +367,11 @@ export function TTSPlayer({ initialText = '', initialVoice = 'nova' }: TTSPlayer
               Playing chunk {currentChunkIndex + 1} of {audioSrcs.length}
             </div>
           )}
+          {warnings.map((warning, i) => (
+            <div key={i} className="text-xs text-text-tertiary text-center mt-2">
+              {warning.message}
+            </div>
+          ))}
         </div>
       )}
 
@@ -255,16 +445,41 @@ export function TTSPlayer({ initialText = '', initialVoice = 'nova' }: TTSPlayer
               )}
             </div>
             
-            <CharacterCounter text={text} minimal={true} />
+            <div className="flex items-center gap-3">
+              <button
+                onClick={openFile}
+                title="Open a document"
+                className="px-3 py-1.5 text-sm text-text-tertiary hover:text-text-secondary transition-colors rounded-lg hover:bg-gray-50"
+              >
+                Open…
+              </button>
+              <CharacterCounter text={text} minimal={true} />
+            </div>
           </div>
         </div>
       </div>
 
+      {notice && (
+        <div className="animate-fade-in">
+          <div className="bg-gray-100 text-gray-700 px-6 py-4 rounded-2xl text-sm font-medium leading-relaxed">
+            {notice}
+          </div>
+        </div>
+      )}
+
       {/* Error Message - System feedback */}
       {error && (
         <div className="animate-fade-in">
           <div className="bg-error/10 text-error px-6 py-4 rounded-2xl text-sm font-medium leading-relaxed">
             {error}
+            {errorRef && (
+              <button
+                onClick={() => copyErrorDetails().catch(() => {})}
+                className="block mt-2 text-xs underline opacity-80 hover:opacity-100"
+              >
+                Copy details for a bug report
+              </button>
+            )}
           </div>
         </div>
       )}
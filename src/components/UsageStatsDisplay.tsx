@@ -1,6 +1,7 @@
 import { useState, useEffect } from 'react';
 import { invoke } from '@tauri-apps/api/core';
 import { Activity, BarChart3, Clock, Zap } from 'lucide-react';
+import type { ModelLatency } from '../usage';
 
 interface UserInfo {
   subscription_tier: string;
@@ -22,8 +23,11 @@ interface UsageStats {
     character_count: number;
     request_count: number;
   }>;
+  latency_by_model?: ModelLatency[];
 }
 
+const seconds = (ms: number) => `${(ms / 1000).toFixed(1)} s`;
+
 interface UsageStatsDisplayProps {
   isVisible: boolean;
   onClose: () => void;
@@ -225,6 +229,31 @@ export function UsageStatsDisplay({ isVisible, onClose }: UsageStatsDisplayProps
                 </div>
               )}
 
+              {/* Latency */}
+              {usageStats && usageStats.latency_by_model && usageStats.latency_by_model.length > 0 && (
+                <div>
+                  <h3 className="text-lg font-semibold text-gray-800 mb-3 flex items-center">
+                    <Zap size={20} className="mr-2" />
+                    Generation Time
+                  </h3>
+                  <div className="space-y-2">
+                    {usageStats.latency_by_model.map((model) => (
+                      <div key={model.model_id} className="flex items-center justify-between p-3 bg-gray-50 rounded">
+                        <div className="text-sm text-gray-700">
+                          {model.model_id}
+                          <span className="text-xs text-gray-500 ml-2">{model.request_count} requests</span>
+                        </div>
+                        <div className="text-xs text-gray-600 space-x-3">
+                          <span>p50 {seconds(model.p50_ms)}</span>
+                          <span>p90 {seconds(model.p90_ms)}</span>
+                          <span>p99 {seconds(model.p99_ms)}</span>
+                        </div>
+                      </div>
+                    ))}
+                  </div>
+                </div>
+              )}
+
               {/* Refresh Data */}
               <div className="flex justify-center pt-4 border-t">
                 <button
@@ -0,0 +1,27 @@
+import { invoke } from '@tauri-apps/api/core';
+
+/** How a backend command fails: a stable key and parameters to translate, the
+ * message already translated by the backend, and its English original */
+export interface CommandError {
+  key: string;
+  params: Record<string, unknown>;
+  message: string;
+  fallback: string;
+  /** Correlation id of the backend log lines about the failure */
+  reference: string | null;
+}
+
+export function isCommandError(error: unknown): error is CommandError {
+  return typeof error === 'object' && error !== null && 'key' in error && 'fallback' in error;
+}
+
+/** Text for whatever a command rejected with */
+export function errorMessage(error: unknown): string {
+  if (isCommandError(error)) return error.message;
+  return error instanceof Error ? error.message : String(error);
+}
+
+/** Translate backend messages from now on; unknown locales get English.
+ * Resolves to the locale in use. */
+export const setLocale = (locale: string) =>
+  invoke<{ locale: string }>('set_locale', { locale }).then((settings) => settings.locale);
@@ -0,0 +1,418 @@
+import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
+import type { Gaps, GeneratedAudio, GenerationWarning } from './audio';
+
+export interface JobInfo {
+  id: string;
+  sequence: number;
+  state: 'queued' | 'running' | 'parked' | 'done' | 'failed' | 'cancelled' | 'deferred';
+  progress?: number;
+  bytes?: number;
+  error?: string;
+  kind?: string;
+  /** Set when there was no connection and the request waits as a pending job */
+  pendingId?: number;
+  /** While parked after a network error: failures so far and when it tries again; cancel_job still works */
+  attempt?: number;
+  retryAt?: string;
+  voiceId: string;
+  model: string;
+  characters: number;
+  title?: string | null;
+  /** Shared by the jobs of one document split into sections (see `generateSections`) */
+  parentId?: string | null;
+  /** Why `model` isn't the one asked for, when the budget moved it to a cheaper one */
+  downgrade?: Downgrade | null;
+  warnings: GenerationWarning[];
+  gaps: Gaps;
+  /** Set while it runs but is held back by the rate limit or pausing before a retry */
+  waiting?: ThrottleStatus | null;
+  /** The queue is paused, so a queued job won't start until it's resumed */
+  queuePaused: boolean;
+  createdAt: string;
+  startedAt?: string | null;
+  finishedAt?: string | null;
+}
+
+/**
+ * A job as the backend has it. `job:changed` events report each change as
+ * it happens, but a window that reloads mid-job misses them; poll
+ * `getGenerationStatus` then, and whenever an event seems overdue. Once
+ * `result` is set, `take_job_result` hands over the audio; it waits there
+ * until taken or `expiresAt`, whether or not the finishing event was heard.
+ */
+export interface GenerationStatus extends JobInfo {
+  /** Requests done out of those the text is sent as */
+  chunksDone: number;
+  chunks: number;
+  result: ResultHandle | null;
+}
+
+export interface ResultHandle {
+  jobId: string;
+  bytes: number;
+  expiresAt: string;
+}
+
+export const getGenerationStatus = (jobId: string) => invoke<GenerationStatus>('get_generation_status', { jobId });
+
+export interface Downgrade {
+  from: string;
+  to: string;
+  reason: string;
+}
+
+export interface ThrottleStatus {
+  backingOff: boolean;
+  /** "rateLimited" after a 429, "retrying" while pausing before trying a failed request again */
+  reason: 'rateLimited' | 'retrying' | null;
+  /** When requests go out again */
+  resumeAt: string | null;
+}
+
+/** Whether requests are held back; changes arrive as `tts:throttle` events */
+export const getThrottleStatus = () => invoke<ThrottleStatus>('get_throttle_status');
+
+/** Call `onChange` whenever requests start or stop being held back; resolves to the unlisten function */
+export const onThrottleChanged = (onChange: (status: ThrottleStatus) => void) =>
+  listen<ThrottleStatus>('tts:throttle', (event) => onChange(event.payload));
+
+export interface QueueStatus {
+  paused: boolean;
+  /** Waiting to start, parked jobs included */
+  queued: number;
+  running: number;
+  /** Most jobs that run at once, the `max_concurrent_jobs` setting */
+  concurrency: number;
+}
+
+/** Start no new jobs; running ones finish and queued ones keep their place, even across restarts */
+export const pauseQueue = () => invoke<QueueStatus>('pause_queue');
+
+/** Start the queued jobs again, in order */
+export const resumeQueue = () => invoke<QueueStatus>('resume_queue');
+
+export const getQueueStatus = () => invoke<QueueStatus>('get_queue_status');
+
+/** Call `onChange` whenever the queue is paused or resumed; resolves to the unlisten function */
+export const onQueueChanged = (onChange: (status: QueueStatus) => void) =>
+  listen<QueueStatus>('queue:changed', (event) => onChange(event.payload));
+
+export interface RecoverableJob {
+  id: string;
+  title: string | null;
+  /** The start of the text */
+  preview: string;
+  voiceId: string;
+  model: string;
+  characters: number;
+  chunks: number;
+  completedChunks: number;
+  /** completedChunks out of chunks, 0 to 1 */
+  progress: number;
+  createdAt: string;
+}
+
+/** Jobs the app was generating when it last quit or crashed, to offer to finish them */
+export const listRecoverableJobs = () => invoke<RecoverableJob[]>('list_recoverable_jobs');
+
+/** Carry on with an interrupted job from where it stopped; resolves to its id, which `followJob` takes */
+export const recoverJob = (jobId: string) => invoke<string>('recover_job', { jobId });
+
+/** Delete an interrupted job instead of finishing it */
+export const discardRecoverableJob = (jobId: string) => invoke<void>('discard_recoverable_job', { jobId });
+
+export interface ImageText {
+  text: string;
+  /** Tesseract's mean confidence in the words, 0 to 100; below 60 it's worth checking */
+  confidence: number;
+  /** The generation `speak` started; follow it with `followJob` */
+  jobId: string | null;
+}
+
+/** Read the text in an image file, or in the clipboard's image without a path */
+export const readImageText = (path: string | null, speak = false) =>
+  invoke<ImageText>('read_image_text', { path, speak });
+
+export class JobCancelledError extends Error {
+  constructor() {
+    super('Generation was cancelled');
+  }
+}
+
+const isFinished = (job: JobInfo) =>
+  job.state === 'done' || job.state === 'failed' || job.state === 'cancelled' || job.state === 'deferred';
+
+/** How often a followed job is polled, in case its events go missing */
+const POLL_INTERVAL_MS = 5000;
+
+/** Settle with job `jobId` once a poll finds it finished; returns the function that stops polling */
+function pollUntilFinished(jobId: string, settle: (job: JobInfo) => void): () => void {
+  const timer = setInterval(async () => {
+    try {
+      const status = await getGenerationStatus(jobId);
+      if (isFinished(status)) settle(status);
+    } catch {
+      // The events may still come
+    }
+  }, POLL_INTERVAL_MS);
+  return () => clearInterval(timer);
+}
+
+/** Pauses for one generation; what's left out follows the settings */
+export interface Pacing {
+  sentenceGapMs?: number;
+  paragraphGapMs?: number;
+}
+
+/** Done to the finished audio */
+export interface Finishing {
+  /** Cut the dead air at the start and end; how much was cut comes back as a `warning.silence_trimmed` warning */
+  trimSilence?: boolean;
+}
+
+/**
+ * Queue a generation and resolve with its audio once the job finishes.
+ * `onEnqueued` receives the job id as soon as the backend assigns it.
+ */
+export async function generateWithJob(
+  text: string,
+  voiceId: string,
+  onEnqueued?: (jobId: string) => void,
+  pacing: Pacing & Finishing = {},
+): Promise<GeneratedAudio> {
+  let jobId: string | null = null;
+  let stopPolling = () => {};
+  const seen: JobInfo[] = [];
+  let settle: (job: JobInfo) => void = () => {};
+  const finished = new Promise<JobInfo>((resolve) => {
+    settle = resolve;
+  });
+
+  const unlisten = await listen<JobInfo>('job:changed', (event) => {
+    const job = event.payload;
+    if (jobId === null) {
+      seen.push(job); // the job may finish before enqueue returns its id
+    } else if (job.id === jobId && isFinished(job)) {
+      settle(job);
+    }
+  });
+
+  try {
+    jobId = await invoke<string>('enqueue_generation', { options: { text, voiceId, ...pacing } });
+    onEnqueued?.(jobId);
+    const early = seen.find((job) => job.id === jobId && isFinished(job));
+    if (early) settle(early);
+
+    stopPolling = pollUntilFinished(jobId, settle);
+    return await resultOf(await finished);
+  } finally {
+    stopPolling();
+    unlisten();
+  }
+}
+
+/**
+ * Resolve with the audio of a job the backend queued itself, such as the
+ * text given on the command line.
+ */
+export async function followJob(jobId: string): Promise<GeneratedAudio> {
+  let settle: (job: JobInfo) => void = () => {};
+  const finished = new Promise<JobInfo>((resolve) => {
+    settle = resolve;
+  });
+
+  const unlisten = await listen<JobInfo>('job:changed', (event) => {
+    if (event.payload.id === jobId && isFinished(event.payload)) settle(event.payload);
+  });
+
+  const stopPolling = pollUntilFinished(jobId, settle);
+  try {
+    // It may have finished before the listener was registered
+    const current = await getGenerationStatus(jobId);
+    if (isFinished(current)) settle(current);
+    return await resultOf(await finished);
+  } finally {
+    stopPolling();
+    unlisten();
+  }
+}
+
+async function resultOf(job: JobInfo): Promise<GeneratedAudio> {
+  if (job.state === 'cancelled') throw new JobCancelledError();
+  if (job.state === 'deferred') {
+    throw new Error("You're offline. The text was saved and will be read once the connection is back.");
+  }
+  // The job id doubles as its correlation id for get_recent_logs
+  if (job.state === 'failed') throw new Error(`${job.error} (ref: ${job.id})`);
+  return await invoke<GeneratedAudio>('take_job_result', { jobId: job.id });
+}
+
+export interface Snippet {
+  text: string;
+  /** The default voice when left out */
+  voice?: string;
+  presetId?: string;
+}
+
+export interface BatchItem {
+  /** Position in the request */
+  index: number;
+  /** Null when the snippet was refused before it became a job */
+  jobId: string | null;
+  status: 'queued' | 'running' | 'done' | 'failed' | 'cancelled';
+  progress?: number;
+  /** Set once done; play it by `path` */
+  audio?: GeneratedAudio;
+  error?: string;
+}
+
+export interface BatchResult {
+  id: string;
+  items: BatchItem[];
+  succeeded: number;
+  failed: number;
+  finished: boolean;
+}
+
+/**
+ * Queue several snippets at once and resolve with the batch id. Each item's
+ * changes arrive as `batch:item` events, then the whole result as
+ * `batch:finished`; one failing leaves the others playable.
+ */
+export const generateBatch = (items: Snippet[]) => invoke<string>('generate_batch_command', { items });
+
+export const getBatchResult = (batchId: string) => invoke<BatchResult>('get_batch_result', { batchId });
+
+/** Call `onChange` with each item of any batch that changes; resolves to the unlisten function */
+export const onBatchItemChanged = (onChange: (item: BatchItem & { batchId: string }) => void) =>
+  listen<BatchItem & { batchId: string }>('batch:item', (event) => onChange(event.payload));
+
+export type SplitOn = { by: 'headings' } | { by: 'delimiter'; delimiter: string };
+
+export interface SectionRequest {
+  text: string;
+  /** At `#` and `##` headings, reading the text as Markdown, or at lines holding only the delimiter */
+  splitOn: SplitOn;
+  /** Shorter sections join a neighbour; 200 when left out */
+  minSectionChars?: number;
+  /** The defaults when left out */
+  voiceId?: string;
+  model?: string;
+}
+
+export interface ManifestEntry {
+  index: number;
+  title: string;
+  characters: number;
+  jobId: string;
+  /** Name of the file in the destination; null when the section failed */
+  file: string | null;
+  error: string | null;
+}
+
+export interface Manifest {
+  parentId: string;
+  voiceId: string;
+  model: string;
+  createdAt: string;
+  sections: ManifestEntry[];
+}
+
+export interface SectionOutput {
+  files: string[];
+  manifestPath: string;
+  manifest: Manifest;
+}
+
+/**
+ * Generate a document as a numbered file per section in the `destination`
+ * directory, with a manifest.json listing them. Each section's job reports
+ * `job:changed` events with the same `parentId`.
+ */
+export const generateSections = (request: SectionRequest, destination: string) =>
+  invoke<SectionOutput>('generate_sections', { request, destination });
+
+export interface RecentGeneration {
+  id: string;
+  title: string | null;
+  /** The start of the text */
+  preview: string;
+  characters: number;
+  voiceId: string;
+  model: string;
+  format: string;
+  size: number;
+  createdAt: string;
+  /** The audio is still there to replay; otherwise it can only be generated again */
+  available: boolean;
+}
+
+/** The last few finished generations, newest first, as many as the `recent_generations` setting keeps */
+export const getRecentGenerations = (limit?: number) => invoke<RecentGeneration[]>('get_recent_generations', { limit });
+
+export type Replay = { state: 'audio'; audio: GeneratedAudio } | { state: 'regenerating'; jobId: string };
+
+/**
+ * Play a recent generation again without paying for it. If its audio has
+ * gone this fails with `error.replay_missing`; pass `regenerate` once the
+ * user agrees to generate it again, then follow the job with `followJob`.
+ */
+export const replayGeneration = (id: string, regenerate = false, inline = false) =>
+  invoke<Replay>('replay_generation', { id, regenerate, inline });
+
+export interface AudioSegment {
+  jobId: string;
+  index: number;
+  total: number;
+  /** Base64 of this segment's part of the audio */
+  data: string;
+}
+
+export interface SegmentsDone {
+  jobId: string;
+  total: number;
+  size: number;
+  /** Lowercase hex SHA-256 of the whole audio */
+  sha256: string;
+  format: string;
+}
+
+const decodeBase64 = (data: string) => Uint8Array.from(atob(data), (char) => char.charCodeAt(0));
+
+async function sha256Hex(bytes: Uint8Array): Promise<string> {
+  const digest = new Uint8Array(await crypto.subtle.digest('SHA-256', bytes));
+  return Array.from(digest, (byte) => byte.toString(16).padStart(2, '0')).join('');
+}
+
+/**
+ * Receive a finished job's audio as `audio:segment` events rather than one
+ * large reply, for where that is too much for IPC. The segments are put
+ * back in order and checked against the checksum in `audio:segments-done`.
+ * `cancel_job` stops it part way, which rejects; the audio stays to ask for again.
+ */
+export async function receiveJobResult(jobId: string): Promise<{ audio: Uint8Array; format: string }> {
+  const parts = new Map<number, Uint8Array>();
+  const unlisten = await listen<AudioSegment>('audio:segment', (event) => {
+    if (event.payload.jobId === jobId) parts.set(event.payload.index, decodeBase64(event.payload.data));
+  });
+  try {
+    // Resolves once every segment has been sent
+    const done = await invoke<SegmentsDone>('send_job_result', { jobId });
+    if (parts.size !== done.total) throw new Error(`Got ${parts.size} of ${done.total} audio segments`);
+    const audio = new Uint8Array(done.size);
+    let offset = 0;
+    for (let index = 0; index < done.total; index++) {
+      const part = parts.get(index);
+      if (!part || offset + part.length > done.size) throw new Error(`Audio segment ${index} is missing or too long`);
+      audio.set(part, offset);
+      offset += part.length;
+    }
+    if (offset !== done.size || (await sha256Hex(audio)) !== done.sha256) {
+      throw new Error('The audio was damaged on the way; ask for it again');
+    }
+    return { audio, format: done.format };
+  } finally {
+    unlisten();
+  }
+}
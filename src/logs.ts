@@ -0,0 +1,60 @@
+import { invoke } from '@tauri-apps/api/core';
+import { errorMessage, isCommandError } from './errors';
+
+export interface LogLine {
+  timestamp: string;
+  level: string;
+  target: string;
+  message: string;
+  correlationId: string | null;
+}
+
+/** The reference a failed command or job gave its error, if any */
+export function correlationIdOf(error: unknown): string | null {
+  if (isCommandError(error)) return error.reference;
+  return errorMessage(error).match(/\(ref: ([0-9a-f-]+)\)$/)?.[1] ?? null;
+}
+
+/** The latest backend log lines, with the API key blanked out */
+export const getRecentLogs = (correlationId?: string | null) =>
+  invoke<LogLine[]>('get_recent_logs', { correlationId: correlationId ?? null });
+
+/** Plain text for pasting into a bug report */
+export const formatLogs = (lines: LogLine[]) =>
+  lines
+    .map((line) => [line.timestamp, line.level.padEnd(5), line.correlationId ?? '-', line.target, line.message].join(' '))
+    .join('\n');
+
+/** One of the places the app keeps things, for "where is your database?" */
+export interface AppPath {
+  name: 'dataDir' | 'database' | 'configFile' | 'libraryDir' | 'jobChunksDir' | 'recentDir' | 'audioDir' | 'silenceDir';
+  path: string;
+  exists: boolean;
+  /** Bytes, everything inside counted for a directory; null when missing */
+  size: number | null;
+}
+
+export const getAppPaths = () => invoke<AppPath[]>('get_app_paths');
+
+/** Open the data directory, where the database is, in the system file manager */
+export const openAppDataDir = () => invoke<void>('open_app_data_dir');
+
+export type CheckStatus = 'ok' | 'warning' | 'failed';
+
+export interface HealthItem {
+  /** "apiKey", "ffmpeg", "tesseract", "database", "dataDir", "audioDir", "libraryDir" or "connectivity" */
+  name: string;
+  status: CheckStatus;
+  detail: string;
+  /** What to do about it; null when it's fine */
+  hint: string | null;
+}
+
+export interface HealthReport {
+  /** The worst of the items */
+  status: CheckStatus;
+  items: HealthItem[];
+}
+
+/** Checks what generation depends on; takes a few seconds at most */
+export const healthCheck = () => invoke<HealthReport>('health_check');
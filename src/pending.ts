@@ -0,0 +1,37 @@
+import { invoke } from '@tauri-apps/api/core';
+import { listen, type UnlistenFn } from '@tauri-apps/api/event';
+import type { GeneratedAudio } from './audio';
+
+/** A generation saved while offline, run once the connection is back */
+export interface PendingJob {
+  id: number;
+  position: number;
+  text: string;
+  voice_id: string;
+  model_id: string;
+  source: string;
+  created_at: string;
+}
+
+export interface PendingJobFinished {
+  job: PendingJob;
+  audio: GeneratedAudio | null;
+  error: string | null;
+}
+
+export const pendingJobs = {
+  list: () => invoke<PendingJob[]>('list_pending_jobs'),
+  /** `ids` must list every pending job once, first to run first */
+  reorder: (ids: number[]) => invoke<PendingJob[]>('reorder_pending_jobs', { ids }),
+  remove: (id: number) => invoke<boolean>('delete_pending_job', { id }),
+};
+
+/** Called with the whole queue whenever a job is added, removed or reordered */
+export function onPendingJobsChanged(handler: (jobs: PendingJob[]) => void): Promise<UnlistenFn> {
+  return listen<PendingJob[]>('pending:changed', (event) => handler(event.payload));
+}
+
+/** Called as each pending job runs after the connection comes back */
+export function onPendingJobFinished(handler: (finished: PendingJobFinished) => void): Promise<UnlistenFn> {
+  return listen<PendingJobFinished>('pending:finished', (event) => handler(event.payload));
+}
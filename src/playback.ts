@@ -0,0 +1,35 @@
+import { invoke } from '@tauri-apps/api/core';
+import { listen, type UnlistenFn } from '@tauri-apps/api/event';
+
+/** What the backend's player should play */
+export type PlaySource =
+  | { kind: 'job'; id: string }
+  | { kind: 'audio'; id: string }
+  | { kind: 'path'; path: string };
+
+export interface PlaybackStatus {
+  state: 'stopped' | 'playing' | 'paused';
+  positionSecs: number;
+  durationSecs: number | null;
+  volume: number;
+  source: string | null;
+}
+
+/**
+ * The backend's own player, which keeps playing while the window is hidden.
+ * Playing something stops whatever was playing.
+ */
+export const nativePlayer = {
+  play: (source: PlaySource) => invoke<PlaybackStatus>('play_audio', { source }),
+  pause: () => invoke<PlaybackStatus>('pause'),
+  resume: () => invoke<PlaybackStatus>('resume'),
+  stop: () => invoke<PlaybackStatus>('stop'),
+  seek: (seconds: number) => invoke<PlaybackStatus>('seek', { seconds }),
+  setVolume: (volume: number) => invoke<PlaybackStatus>('set_volume', { volume }),
+  status: () => invoke<PlaybackStatus>('get_playback_status'),
+};
+
+/** Called a few times a second while something plays, and once when it stops */
+export function onPlaybackPosition(handler: (status: PlaybackStatus) => void): Promise<UnlistenFn> {
+  return listen<PlaybackStatus>('playback:position', (event) => handler(event.payload));
+}
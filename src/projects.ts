@@ -0,0 +1,79 @@
+import { invoke } from '@tauri-apps/api/core';
+import { listen, type UnlistenFn } from '@tauri-apps/api/event';
+
+/** A reading list generated and exported as one */
+export interface Project {
+  id: number;
+  name: string;
+  created_at: string;
+}
+
+/** One document in a project; a null voice or model uses the settings */
+export interface ProjectItem {
+  id: number;
+  project_id: number;
+  position: number;
+  title: string;
+  text: string;
+  voice_id: string | null;
+  model_id: string | null;
+}
+
+export interface ItemContent {
+  title: string;
+  text: string;
+  voiceId?: string | null;
+  modelId?: string | null;
+}
+
+export type ItemState =
+  | { state: 'generating' }
+  | { state: 'cached' }
+  | { state: 'done' }
+  | { state: 'failed'; error: string };
+
+export type ItemProgress = ItemState & {
+  projectId: number;
+  itemId: number;
+  /** Place in the reading order, from 0 */
+  index: number;
+  total: number;
+  elapsedMs: number;
+  /** Time left for the whole project; null until an item has finished */
+  etaMs: number | null;
+};
+
+export interface ItemResult {
+  itemId: number;
+  title: string;
+  path: string | null;
+  error: string | null;
+  cached: boolean;
+}
+
+/** Numbered files in a directory, or one file with a chapter per item */
+export type ExportMode = 'separate' | 'combined';
+
+export const projects = {
+  create: (name: string) => invoke<number>('create_project', { name }),
+  list: () => invoke<Project[]>('list_projects'),
+  rename: (projectId: number, name: string) => invoke<boolean>('rename_project', { projectId, name }),
+  remove: (projectId: number) => invoke<boolean>('delete_project', { projectId }),
+  items: (projectId: number) => invoke<ProjectItem[]>('get_project_items', { projectId }),
+  addItem: (projectId: number, item: ItemContent) => invoke<number>('add_project_item', { projectId, item }),
+  updateItem: (itemId: number, item: ItemContent) => invoke<boolean>('update_project_item', { itemId, item }),
+  removeItem: (itemId: number) => invoke<boolean>('delete_project_item', { itemId }),
+  /** `ids` must list every item of the project once, first to read first */
+  reorderItems: (projectId: number, ids: number[]) =>
+    invoke<ProjectItem[]>('reorder_project_items', { projectId, ids }),
+  /** Generates what isn't cached yet; failed items are reported, not thrown */
+  generate: (projectId: number) => invoke<ItemResult[]>('generate_project', { projectId }),
+  /** The files written, in reading order */
+  export: (projectId: number, mode: ExportMode, destination: string) =>
+    invoke<string[]>('export_project', { projectId, mode, destination }),
+};
+
+/** Called as each item of a generating project starts and finishes */
+export function onProjectProgress(handler: (progress: ItemProgress) => void): Promise<UnlistenFn> {
+  return listen<ItemProgress>('project:progress', (event) => handler(event.payload));
+}
@@ -0,0 +1,108 @@
+import { invoke } from '@tauri-apps/api/core';
+
+/** One generation in the history */
+export interface UsageRecord {
+  id: number | null;
+  timestamp: string;
+  /** The first 100 characters */
+  text: string;
+  character_count: number;
+  voice_id: string;
+  model_id: string;
+  success: boolean;
+  error_message: string | null;
+  /** Where it was asked for: "app", "cli", "deeplink", ... */
+  source: string;
+  regenerated_from: number | null;
+  downgraded_from: string | null;
+  /** Tells API keys apart without containing any of the key */
+  key_fingerprint: string | null;
+  /** The app version that made it; null for older records */
+  app_version: string | null;
+  /** "default" unless another profile was active */
+  profile: string;
+  /** From the first request to having all the audio; null if not timed */
+  latency_ms?: number | null;
+  /** How many requests the text was split into */
+  chunk_count?: number | null;
+}
+
+/** Leave a field out to match everything */
+export interface UsageFilter {
+  days?: number;
+  voice_id?: string;
+  model_id?: string;
+  success?: boolean;
+  source?: string;
+  key_fingerprint?: string;
+  profile?: string;
+}
+
+export interface UsagePage {
+  records: UsageRecord[];
+  /** How many match in all, for "N results" */
+  total: number;
+}
+
+export const getUsageHistory = (limit: number, filter: UsageFilter = {}) =>
+  invoke<UsagePage>('get_usage_history', { limit, filter });
+
+/** One local hour, oldest first; hours without usage are included */
+export interface HourlyUsage {
+  start: string;
+  /** Successful requests only */
+  characterCount: number;
+  cost: number;
+  /** Failed requests too */
+  requestCount: number;
+}
+
+/** Every profile's usage unless `profile` is given */
+export const getHourlyUsage = (hours: number, profile?: string) =>
+  invoke<HourlyUsage[]>('get_hourly_usage', { hours, profile });
+
+/** The default profile, configured ones and any with usage */
+export const listProfiles = () => invoke<string[]>('list_profiles');
+
+export interface VersionFailures {
+  app_version: string | null;
+  request_count: number;
+  failed_count: number;
+}
+
+export const getFailuresByVersion = (days: number) =>
+  invoke<VersionFailures[]>('get_failures_by_version', { days });
+
+/** CSV header names for each field; `text` is optional */
+export interface CsvMapping {
+  timestamp: string;
+  characters: string;
+  voice: string;
+  model: string;
+  success: string;
+  text?: string;
+}
+
+export interface ImportReport {
+  dry_run: boolean;
+  /** Rows imported, or that would be with dryRun off; 0 while there are errors */
+  imported: number;
+  /** `line` counts the header as line 1 */
+  errors: { line: number; message: string }[];
+}
+
+export const importUsageCsv = (path: string, mapping: CsvMapping, dryRun: boolean) =>
+  invoke<ImportReport>('import_usage_csv', { path, mapping, dryRun });
+
+/** Percentiles of one model's timed, successful generations */
+export interface ModelLatency {
+  model_id: string;
+  request_count: number;
+  p50_ms: number;
+  p90_ms: number;
+  p99_ms: number;
+}
+
+/** The slowest generations of the last 30 days taking at least thresholdMs */
+export const getSlowRequests = (thresholdMs: number, limit: number) =>
+  invoke<UsageRecord[]>('get_slow_requests', { thresholdMs, limit });
@@ -0,0 +1,34 @@
+import { invoke } from '@tauri-apps/api/core';
+
+/** A voice from the backend's table */
+export interface VoiceInfo {
+  id: string;
+  name: string;
+  description: string;
+  gender: 'female' | 'male' | 'neutral';
+  style: string[];
+  /** BCP 47 tags of the accents it sounds most natural in, e.g. "en-GB" */
+  languages: string[];
+  models: string[];
+}
+
+/** The voice to switch to for text in `language`; `voiceId` unless another suits it better */
+export const voiceForLanguage = (language: string, voiceId: string) =>
+  invoke<VoiceInfo>('voice_for_language', { language, voiceId });
+
+/** A voice with how much it was used lately */
+export interface VoiceUsageSummary {
+  voiceId: string;
+  /** Null for a voice in the history that is no longer offered */
+  voice: VoiceInfo | null;
+  legacy: boolean;
+  /** One of the three most used, to float to the top of the picker */
+  frequent: boolean;
+  requestCount: number;
+  characterCount: number;
+  lastUsed: string | null;
+}
+
+/** Every voice with its usage over the last `days` days, the most used first */
+export const getVoiceUsageSummary = (days: number) =>
+  invoke<VoiceUsageSummary[]>('get_voice_usage_summary', { days });